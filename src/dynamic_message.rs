@@ -17,14 +17,20 @@ use pact_models::generators::{
 };
 use pact_models::path_exp::{DocPath, PathToken};
 use pact_models::v4::sync_message::SynchronousMessage;
-use prost::encoding::{encode_key, encode_varint, WireType};
+use prost::encoding::encode_key;
 use prost_types::{DescriptorProto, FileDescriptorSet};
 use serde_json::Value;
 use tonic::codec::{Codec, DecodeBuf, Decoder, EncodeBuf, Encoder};
 use tonic::Status;
-use tracing::{debug, error, instrument, trace, warn};
-
-use crate::message_decoder::{decode_message, ProtobufField, ProtobufFieldData};
+use tracing::{error, instrument, trace, warn};
+
+use crate::message_decoder::{
+  decode_message,
+  encode_field_data,
+  ProtobufField,
+  ProtobufFieldData,
+  repeated_field_encodings
+};
 use crate::message_decoder::generators::{data_value_to_proto_value, GeneratorError};
 
 #[derive(Debug, Clone)]
@@ -73,7 +79,8 @@ impl Codec for PactCodec {
 #[derive(Debug, Clone)]
 pub struct DynamicMessage {
   fields: HashMap<u32, ProtobufField>,
-  descriptors: FileDescriptorSet
+  descriptors: FileDescriptorSet,
+  repeated_field_encodings: HashMap<u32, bool>
 }
 
 impl DynamicMessage {
@@ -99,10 +106,26 @@ impl DynamicMessage {
       .collect();
     DynamicMessage {
       fields,
-      descriptors: descriptors.clone()
+      descriptors: descriptors.clone(),
+      repeated_field_encodings: HashMap::default()
     }
   }
 
+  /// Sets the wire encoding the repeated scalar fields were received with (true if packed), keyed
+  /// by field number
+  pub fn with_repeated_field_encodings(self, encodings: HashMap<u32, bool>) -> DynamicMessage {
+    DynamicMessage {
+      repeated_field_encodings: encodings,
+      .. self
+    }
+  }
+
+  /// Returns true if the repeated scalar field with the given field number was received as a
+  /// packed field, false if it was received unpacked, or None if the encoding is not known.
+  pub fn packed_encoding(&self, field_num: u32) -> Option<bool> {
+    self.repeated_field_encodings.get(&field_num).copied()
+  }
+
   /// Return a vector of the fields
   pub fn proto_fields(&self) -> Vec<ProtobufField> {
     self.fields.values().cloned().collect()
@@ -144,62 +167,7 @@ impl DynamicMessage {
   ) -> anyhow::Result<()> where B: BufMut {
     trace!(%field_num, %field, %data, "Writing field data");
     encode_key(field.field_num, field.wire_type, buffer);
-    match field.wire_type {
-      WireType::Varint => match data {
-        ProtobufFieldData::Boolean(b) => encode_varint(*b as u64, buffer),
-        ProtobufFieldData::UInteger32(n) => encode_varint(*n as u64, buffer),
-        ProtobufFieldData::Integer32(n) => encode_varint(*n as u64, buffer),
-        ProtobufFieldData::UInteger64(n) => encode_varint(*n, buffer),
-        ProtobufFieldData::Integer64(n) => encode_varint(*n as u64, buffer),
-        ProtobufFieldData::Enum(n, _) => encode_varint(*n as u64, buffer),
-        ProtobufFieldData::Unknown(b) => {
-          debug!("Writing unknown field {}", field.data);
-          buffer.put_slice(b.as_slice());
-        },
-        _ => return Err(anyhow!("Expected a varint, but field is {}", field.data))
-      },
-      WireType::SixtyFourBit => match data {
-        ProtobufFieldData::UInteger64(n) => buffer.put_u64_le(*n),
-        ProtobufFieldData::Integer64(n) => buffer.put_i64_le(*n),
-        ProtobufFieldData::Double(n) => buffer.put_f64_le(*n),
-        ProtobufFieldData::Unknown(b) => {
-          debug!("Writing unknown field {}", field.data);
-          buffer.put_slice(b.as_slice());
-        }
-        _ => return Err(anyhow!("Expected a 64 bit value, but field is {}", field.data))
-      }
-      WireType::LengthDelimited => match data {
-        ProtobufFieldData::String(s) => {
-          encode_varint(s.len() as u64, buffer);
-          buffer.put_slice(s.as_bytes());
-        }
-        ProtobufFieldData::Bytes(b) => {
-          encode_varint(b.len() as u64, buffer);
-          buffer.put_slice(b.as_slice());
-        }
-        ProtobufFieldData::Message(m, _) => {
-          encode_varint(m.len() as u64, buffer);
-          buffer.put_slice(m.as_slice());
-        }
-        ProtobufFieldData::Unknown(b) => {
-          debug!("Writing unknown field {}", field.data);
-          buffer.put_slice(b.as_slice());
-        },
-        _ => return Err(anyhow!("Expected a length delimited value, but field is {}", field.data))
-      }
-      WireType::ThirtyTwoBit => match data {
-        ProtobufFieldData::UInteger32(n) => buffer.put_u32_le(*n),
-        ProtobufFieldData::Integer32(n) => buffer.put_i32_le(*n),
-        ProtobufFieldData::Float(n) => buffer.put_f32_le(*n),
-        ProtobufFieldData::Unknown(b) => {
-          debug!("Writing unknown field {}", field.data);
-          buffer.put_slice(b.as_slice());
-        },
-        _ => return Err(anyhow!("Expected a 32 bit value, but field is {}", field.data))
-      }
-      _ => return Err(anyhow!("Groups are not supported"))
-    }
-    Ok(())
+    encode_field_data(field, data, buffer)
   }
 
   /// Retrieve the value for a message field using the given path
@@ -433,8 +401,15 @@ impl Decoder for DynamicMessageDecoder {
   #[instrument(skip_all, fields(bytes = src.remaining()))]
   fn decode(&mut self, src: &mut DecodeBuf<'_>) -> Result<Option<Self::Item>, Self::Error> {
     trace!("Incoming bytes = {:?}", src);
-    match decode_message(src, &self.descriptor, &self.file_descriptor_set) {
-      Ok(fields) => Ok(Some(DynamicMessage::new(fields.as_slice(), &self.file_descriptor_set))),
+    let mut bytes = src.copy_to_bytes(src.remaining());
+    let encodings = repeated_field_encodings(&mut bytes.clone(), &self.descriptor)
+      .unwrap_or_else(|err| {
+        warn!("Failed to read the encoding of the repeated fields - {err}");
+        HashMap::default()
+      });
+    match decode_message(&mut bytes, &self.descriptor, &self.file_descriptor_set) {
+      Ok(fields) => Ok(Some(DynamicMessage::new(fields.as_slice(), &self.file_descriptor_set)
+        .with_repeated_field_encodings(encodings))),
       Err(err) => {
         error!("Failed to decode the message - {err}");
         Err(Status::invalid_argument(format!("Failed to decode the message - {err}")))
@@ -850,6 +825,50 @@ mod tests {
     expect!(result).to(be_equal_to(vec![ field ]));
   }
 
+  #[rstest::rstest]
+  #[case::int32(field_descriptor_proto::Type::Int32, ProtobufFieldData::Integer32(3), &[8, 3])]
+  #[case::sint32(field_descriptor_proto::Type::Sint32, ProtobufFieldData::Integer32(-3), &[8, 5])]
+  #[case::sint64(field_descriptor_proto::Type::Sint64, ProtobufFieldData::Integer64(-300), &[8, 215, 4])]
+  fn dynamic_message_write_to_zigzag_encodes_signed_integer_fields(
+    #[case] field_type: field_descriptor_proto::Type,
+    #[case] data: ProtobufFieldData,
+    #[case] expected: &[u8]
+  ) {
+    let field_descriptor = FieldDescriptorProto {
+      name: Some("one".to_string()),
+      number: Some(1),
+      r#type: Some(field_type as i32),
+      label: None,
+      .. FieldDescriptorProto::default()
+    };
+    let field = ProtobufField {
+      field_num: 1,
+      field_name: "one".to_string(),
+      wire_type: WireType::Varint,
+      data,
+      additional_data: vec![],
+      descriptor: field_descriptor.clone()
+    };
+    let descriptors = FileDescriptorSet {
+      file: vec![]
+    };
+    let descriptor = DescriptorProto {
+      field: vec![
+        field_descriptor.clone()
+      ],
+      .. DescriptorProto::default()
+    };
+    let fields = vec![ field.clone() ];
+    let message = DynamicMessage::new(fields.as_slice(), &descriptors);
+
+    let mut buffer = BytesMut::new();
+    message.write_to(&mut buffer).unwrap();
+    expect!(buffer.as_ref()).to(be_equal_to(expected));
+
+    let result = decode_message(&mut buffer.freeze(), &descriptor, &descriptors).unwrap();
+    expect!(result).to(be_equal_to(vec![ field ]));
+  }
+
   #[test]
   fn dynamic_message_write_to_test_with_multiple_fields() {
     let field_descriptor_1 = FieldDescriptorProto {
@@ -0,0 +1,351 @@
+//! Support for Protobuf files that use editions (i.e. `edition = "2023"`). The features that
+//! control how fields are encoded (field presence and repeated field encoding) can be set on the
+//! file or the field, with the field features taking precedence.
+
+use bytes::Buf;
+use prost::{DecodeError, Message};
+use prost_types::{DescriptorProto, FieldDescriptorProto, FileDescriptorProto, FileDescriptorSet, UninterpretedOption};
+use prost_types::uninterpreted_option::NamePart;
+use tracing::trace;
+
+use crate::utils::{find_file_descriptor_for_message, is_editions_file};
+
+/// Field presence feature (`features.field_presence`)
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum FieldPresence {
+  /// Fields set to the default value are sent on the wire (the editions default)
+  Explicit,
+  /// Fields set to the default value are not sent on the wire (the proto3 behaviour)
+  Implicit,
+  /// Fields are required (the proto2 `required` label)
+  LegacyRequired
+}
+
+impl FieldPresence {
+  fn from_name(name: &str) -> Option<FieldPresence> {
+    match name {
+      "EXPLICIT" => Some(FieldPresence::Explicit),
+      "IMPLICIT" => Some(FieldPresence::Implicit),
+      "LEGACY_REQUIRED" => Some(FieldPresence::LegacyRequired),
+      _ => None
+    }
+  }
+
+  fn name(value: i32) -> Option<&'static str> {
+    match value {
+      1 => Some("EXPLICIT"),
+      2 => Some("IMPLICIT"),
+      3 => Some("LEGACY_REQUIRED"),
+      _ => None
+    }
+  }
+}
+
+/// Repeated field encoding feature (`features.repeated_field_encoding`)
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum RepeatedFieldEncoding {
+  /// Repeated scalar fields are packed (the editions default)
+  Packed,
+  /// Repeated scalar fields are written one value per field
+  Expanded
+}
+
+impl RepeatedFieldEncoding {
+  fn from_name(name: &str) -> Option<RepeatedFieldEncoding> {
+    match name {
+      "PACKED" => Some(RepeatedFieldEncoding::Packed),
+      "EXPANDED" => Some(RepeatedFieldEncoding::Expanded),
+      _ => None
+    }
+  }
+
+  fn name(value: i32) -> Option<&'static str> {
+    match value {
+      1 => Some("PACKED"),
+      2 => Some("EXPANDED"),
+      _ => None
+    }
+  }
+}
+
+const FIELD_PRESENCE: &str = "field_presence";
+const REPEATED_FIELD_ENCODING: &str = "repeated_field_encoding";
+
+/// Features resolved for the fields of a message. These are resolved once for the message from
+/// the file level features, and then any features set on a field override them.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct MessageFeatures {
+  /// If the message is from a file that uses editions. The features only apply to these files.
+  editions: bool,
+  /// Field presence for the fields of the message
+  field_presence: FieldPresence,
+  /// Encoding for the repeated fields of the message
+  repeated_field_encoding: RepeatedFieldEncoding
+}
+
+impl MessageFeatures {
+  /// Resolves the features for the message, looking up the file the message was defined in from
+  /// the descriptors.
+  pub fn resolve(message_descriptor: &DescriptorProto, descriptors: &FileDescriptorSet) -> MessageFeatures {
+    find_file_descriptor_for_message(message_descriptor, descriptors)
+      .map(MessageFeatures::for_file)
+      .unwrap_or_else(MessageFeatures::non_editions)
+  }
+
+  /// Resolves the features for the messages defined in the given file.
+  pub fn for_file(file_descriptor: &FileDescriptorProto) -> MessageFeatures {
+    if !is_editions_file(file_descriptor) {
+      return MessageFeatures::non_editions();
+    }
+
+    let mut features = MessageFeatures {
+      editions: true,
+      field_presence: FieldPresence::Explicit,
+      repeated_field_encoding: RepeatedFieldEncoding::Packed
+    };
+    if let Some(options) = &file_descriptor.options {
+      features.apply(&options.uninterpreted_option);
+    }
+    trace!(?features, "Resolved features for file {}", file_descriptor.name());
+    features
+  }
+
+  fn non_editions() -> MessageFeatures {
+    MessageFeatures {
+      editions: false,
+      field_presence: FieldPresence::Implicit,
+      repeated_field_encoding: RepeatedFieldEncoding::Packed
+    }
+  }
+
+  fn apply(&mut self, options: &[UninterpretedOption]) {
+    if let Some(presence) = feature_value(options, FIELD_PRESENCE).and_then(FieldPresence::from_name) {
+      self.field_presence = presence;
+    }
+    if let Some(encoding) = feature_value(options, REPEATED_FIELD_ENCODING).and_then(RepeatedFieldEncoding::from_name) {
+      self.repeated_field_encoding = encoding;
+    }
+  }
+
+  /// If the message is from a file that uses editions
+  pub fn is_editions(&self) -> bool {
+    self.editions
+  }
+
+  /// Returns the field presence for the field. Fields of messages that are not from a file that
+  /// uses editions will always have implicit presence.
+  pub fn field_presence(&self, field: &FieldDescriptorProto) -> FieldPresence {
+    if self.editions {
+      field_options(field)
+        .and_then(|options| feature_value(options, FIELD_PRESENCE))
+        .and_then(FieldPresence::from_name)
+        .unwrap_or(self.field_presence)
+    } else {
+      FieldPresence::Implicit
+    }
+  }
+
+  /// If a field set to the default value is still sent on the wire
+  pub fn has_explicit_presence(&self, field: &FieldDescriptorProto) -> bool {
+    self.field_presence(field) != FieldPresence::Implicit
+  }
+
+  /// If the values of the repeated field should be packed. This only checks the features, the
+  /// field type must also be a packable scalar type.
+  pub fn is_packed(&self, field: &FieldDescriptorProto) -> bool {
+    if self.editions {
+      field_options(field)
+        .and_then(|options| feature_value(options, REPEATED_FIELD_ENCODING))
+        .and_then(RepeatedFieldEncoding::from_name)
+        .unwrap_or(self.repeated_field_encoding) == RepeatedFieldEncoding::Packed
+    } else {
+      true
+    }
+  }
+}
+
+fn field_options(field: &FieldDescriptorProto) -> Option<&[UninterpretedOption]> {
+  field.options.as_ref().map(|o| o.uninterpreted_option.as_slice())
+}
+
+fn feature_value<'a>(options: &'a [UninterpretedOption], feature: &str) -> Option<&'a str> {
+  options.iter()
+    .find(|option| matches!(option.name.as_slice(),
+      [ features, name ] if features.name_part == "features" && name.name_part == feature))
+    .and_then(|option| option.identifier_value.as_deref())
+}
+
+fn feature_option(feature: &str, value: &str) -> UninterpretedOption {
+  UninterpretedOption {
+    name: vec![
+      NamePart { name_part: "features".to_string(), is_extension: false },
+      NamePart { name_part: feature.to_string(), is_extension: false }
+    ],
+    identifier_value: Some(value.to_string()),
+    .. UninterpretedOption::default()
+  }
+}
+
+// Prost does not support the editions features, so these messages are used to decode just the
+// features from the descriptors. They mirror the fields of the descriptor messages with the same
+// tags.
+
+#[derive(Clone, PartialEq, Message)]
+struct FeatureSetProto {
+  #[prost(int32, optional, tag = "1")]
+  field_presence: Option<i32>,
+  #[prost(int32, optional, tag = "3")]
+  repeated_field_encoding: Option<i32>
+}
+
+#[derive(Clone, PartialEq, Message)]
+struct FileOptionsFeatures {
+  #[prost(message, optional, tag = "50")]
+  features: Option<FeatureSetProto>
+}
+
+#[derive(Clone, PartialEq, Message)]
+struct FieldOptionsFeatures {
+  #[prost(message, optional, tag = "21")]
+  features: Option<FeatureSetProto>
+}
+
+#[derive(Clone, PartialEq, Message)]
+struct FieldFeatures {
+  #[prost(message, optional, tag = "8")]
+  options: Option<FieldOptionsFeatures>
+}
+
+#[derive(Clone, PartialEq, Message)]
+struct MessageFeaturesProto {
+  #[prost(message, repeated, tag = "2")]
+  field: Vec<FieldFeatures>,
+  #[prost(message, repeated, tag = "3")]
+  nested_type: Vec<MessageFeaturesProto>
+}
+
+#[derive(Clone, PartialEq, Message)]
+struct FileFeatures {
+  #[prost(message, repeated, tag = "4")]
+  message_type: Vec<MessageFeaturesProto>,
+  #[prost(message, optional, tag = "8")]
+  options: Option<FileOptionsFeatures>
+}
+
+#[derive(Clone, PartialEq, Message)]
+struct FileDescriptorSetFeatures {
+  #[prost(message, repeated, tag = "1")]
+  file: Vec<FileFeatures>
+}
+
+impl FeatureSetProto {
+  fn to_options(&self) -> Vec<UninterpretedOption> {
+    let presence = self.field_presence.and_then(FieldPresence::name)
+      .map(|value| feature_option(FIELD_PRESENCE, value));
+    let encoding = self.repeated_field_encoding.and_then(RepeatedFieldEncoding::name)
+      .map(|value| feature_option(REPEATED_FIELD_ENCODING, value));
+    presence.into_iter().chain(encoding).collect()
+  }
+}
+
+/// Decodes the Protobuf file descriptor set. Prost does not support the features used by files
+/// with editions, so for those files any features that are set are stored in the descriptors as
+/// uninterpreted options (i.e. `features.field_presence = IMPLICIT`).
+pub fn decode_file_descriptor_set<B>(buffer: B) -> Result<FileDescriptorSet, DecodeError>
+  where B: Buf + Clone {
+  let mut descriptors = FileDescriptorSet::decode(buffer.clone())?;
+  if descriptors.file.iter().any(is_editions_file) {
+    let features = FileDescriptorSetFeatures::decode(buffer)?;
+    for (file, file_features) in descriptors.file.iter_mut().zip(features.file) {
+      if is_editions_file(file) {
+        add_file_features(file, &file_features);
+      }
+    }
+  }
+  Ok(descriptors)
+}
+
+fn add_file_features(file: &mut FileDescriptorProto, features: &FileFeatures) {
+  if let Some(feature_set) = features.options.as_ref().and_then(|o| o.features.as_ref()) {
+    file.options.get_or_insert_with(Default::default).uninterpreted_option
+      .extend(feature_set.to_options());
+  }
+  for (message, message_features) in file.message_type.iter_mut().zip(&features.message_type) {
+    add_message_features(message, message_features);
+  }
+}
+
+fn add_message_features(message: &mut DescriptorProto, features: &MessageFeaturesProto) {
+  for (field, field_features) in message.field.iter_mut().zip(&features.field) {
+    if let Some(feature_set) = field_features.options.as_ref().and_then(|o| o.features.as_ref()) {
+      field.options.get_or_insert_with(Default::default).uninterpreted_option
+        .extend(feature_set.to_options());
+    }
+  }
+  for (nested, nested_features) in message.nested_type.iter_mut().zip(&features.nested_type) {
+    add_message_features(nested, nested_features);
+  }
+}
+
+#[cfg(test)]
+pub(crate) mod tests {
+  use base64::Engine;
+  use base64::engine::general_purpose::STANDARD as BASE64;
+  use expectest::prelude::*;
+
+  use crate::editions::{decode_file_descriptor_set, FieldPresence, MessageFeatures};
+
+  // Descriptors generated by protoc 28.2 from the following file:
+  //
+  // edition = "2023";
+  //
+  // package editions;
+  //
+  // option features.repeated_field_encoding = EXPANDED;
+  //
+  // message Request {
+  //   string name = 1;
+  //   int32 count = 2 [features.field_presence = IMPLICIT];
+  //   repeated int32 values = 3;
+  //   repeated int32 packed_values = 4 [features.repeated_field_encoding = PACKED];
+  //   Nested nested = 5;
+  //
+  //   message Nested {
+  //     string id = 1 [features.field_presence = IMPLICIT];
+  //     int32 total = 2;
+  //   }
+  // }
+  pub(crate) const EDITIONS_DESCRIPTORS: &str = "CpgCCg5lZGl0aW9ucy5wcm90bxIIZWRpdGlvbnMi5wEKB1JlcXVlc3QSEgoEbmFtZRgBIAEoCVIEbmFtZRIbCgVjb3VudBgCIAEoBUIFqgECCAJSBWNvdW50EhYKBnZhbHVlcxgDIAMoBVIGdmFsdWVzEioKDXBhY2tlZF92YWx1ZXMYBCADKAVCBaoBAhgBUgxwYWNrZWRWYWx1ZXMSMAoGbmVzdGVkGAUgASgLMhguZWRpdGlvbnMuUmVxdWVzdC5OZXN0ZWRSBm5lc3RlZBo1CgZOZXN0ZWQSFQoCaWQYASABKAlCBaoBAggCUgJpZBIUCgV0b3RhbBgCIAEoBVIFdG90YWxCBZIDAhgCYghlZGl0aW9uc3DoBw==";
+
+  #[test]
+  fn decode_file_descriptor_set_keeps_the_editions_features() {
+    let bytes = BASE64.decode(EDITIONS_DESCRIPTORS).unwrap();
+    let fds = decode_file_descriptor_set(bytes.as_slice()).unwrap();
+    let request = &fds.file[0].message_type[0];
+    let nested = &request.nested_type[0];
+
+    let features = MessageFeatures::resolve(request, &fds);
+    expect!(features.is_editions()).to(be_true());
+    expect!(features.field_presence(&request.field[0])).to(be_equal_to(FieldPresence::Explicit));
+    expect!(features.field_presence(&request.field[1])).to(be_equal_to(FieldPresence::Implicit));
+    expect!(features.is_packed(&request.field[2])).to(be_false());
+    expect!(features.is_packed(&request.field[3])).to(be_true());
+
+    let features = MessageFeatures::resolve(nested, &fds);
+    expect!(features.field_presence(&nested.field[0])).to(be_equal_to(FieldPresence::Implicit));
+    expect!(features.field_presence(&nested.field[1])).to(be_equal_to(FieldPresence::Explicit));
+  }
+
+  #[test]
+  fn message_features_for_files_without_editions() {
+    let bytes = BASE64.decode(EDITIONS_DESCRIPTORS).unwrap();
+    let mut fds = decode_file_descriptor_set(bytes.as_slice()).unwrap();
+    fds.file[0].syntax = Some("proto3".to_string());
+    let request = &fds.file[0].message_type[0];
+
+    let features = MessageFeatures::resolve(request, &fds);
+    expect!(features.is_editions()).to(be_false());
+    expect!(features.has_explicit_presence(&request.field[0])).to(be_false());
+    expect!(features.is_packed(&request.field[2])).to(be_true());
+  }
+}
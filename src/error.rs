@@ -0,0 +1,69 @@
+//! Error type returned by the public functions of the crate
+
+use std::str::Utf8Error;
+
+use thiserror::Error;
+
+/// Errors that can occur when looking up descriptors or decoding Protobuf messages
+#[derive(Error, Debug)]
+pub enum ProtobufError {
+  /// A message, service, method, enum or file descriptor could not be found
+  #[error("{0}")]
+  DescriptorNotFound(String),
+  /// The encoded message bytes could not be decoded
+  #[error("{0}")]
+  DecodeFailure(String),
+  /// The message contains a type that is not supported
+  #[error("{0}")]
+  UnsupportedType(String),
+  /// An IO error occurred
+  #[error(transparent)]
+  Io(#[from] std::io::Error),
+  /// The Protocol Buffers compiler (protoc) failed or could not be found
+  #[error("{0}")]
+  Protoc(String),
+  /// Any other error
+  #[error(transparent)]
+  Other(anyhow::Error)
+}
+
+impl From<anyhow::Error> for ProtobufError {
+  fn from(err: anyhow::Error) -> Self {
+    // Errors that were converted to anyhow errors internally keep their original variant
+    match err.downcast::<ProtobufError>() {
+      Ok(err) => err,
+      Err(err) => ProtobufError::Other(err)
+    }
+  }
+}
+
+impl From<prost::DecodeError> for ProtobufError {
+  fn from(err: prost::DecodeError) -> Self {
+    ProtobufError::DecodeFailure(err.to_string())
+  }
+}
+
+impl From<Utf8Error> for ProtobufError {
+  fn from(err: Utf8Error) -> Self {
+    ProtobufError::DecodeFailure(err.to_string())
+  }
+}
+
+#[cfg(test)]
+mod tests {
+  use anyhow::anyhow;
+  use expectest::prelude::*;
+
+  use super::ProtobufError;
+
+  #[test]
+  fn from_anyhow_error_keeps_the_original_variant() {
+    let err = anyhow::Error::from(ProtobufError::DescriptorNotFound("not found".to_string()));
+    let result = ProtobufError::from(err);
+    expect!(matches!(result, ProtobufError::DescriptorNotFound(_))).to(be_true());
+
+    let result = ProtobufError::from(anyhow!("some other error"));
+    expect!(matches!(result, ProtobufError::Other(_))).to(be_true());
+    expect!(result.to_string()).to(be_equal_to("some other error"));
+  }
+}
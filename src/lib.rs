@@ -1,8 +1,9 @@
 extern crate core;
 
 pub mod server;
-mod protoc;
-mod protobuf;
+pub mod error;
+pub mod protoc;
+pub mod protobuf;
 mod message_builder;
 pub mod message_decoder;
 pub mod utils;
@@ -10,9 +11,11 @@ pub mod matching;
 pub mod mock_server;
 pub mod tcp;
 pub mod dynamic_message;
+pub mod editions;
 mod mock_service;
-mod verification;
+pub mod verification;
 mod metadata;
+pub mod metrics;
 
 pub mod built_info {
   include!(concat!(env!("OUT_DIR"), "/built.rs"));
@@ -3,20 +3,23 @@
 //! Implements the version 1 of the Pact plugin interface described at `https://github.com/pact-foundation/pact-plugins/blob/main/docs/content-matcher-design.md`.
 
 use std::env;
+use std::future::{Future, pending};
 use std::iter::once;
 use std::net::SocketAddr;
+use std::path::PathBuf;
 use std::str::FromStr;
 use std::sync::Mutex;
 use std::time::{Duration, Instant};
 
 use anyhow::Context;
-use clap::{Arg, ArgAction, Command, command};
+use clap::{Arg, ArgAction, ArgMatches, Command, command};
 use clap::error::ErrorKind;
 use hyper::header;
 use lazy_static::lazy_static;
 use pact_plugin_driver::proto::pact_plugin_server::PactPluginServer;
+use prost_types::FileDescriptorSet;
 use tokio::net::TcpListener;
-use tokio::sync::oneshot::channel;
+use tokio::sync::oneshot::{channel, Receiver};
 use tokio::time;
 use tonic::{Request, Status};
 use tonic::service::Interceptor;
@@ -25,15 +28,20 @@ use tower::ServiceBuilder;
 use tower_http::compression::CompressionLayer;
 use tower_http::sensitive_headers::SetSensitiveHeadersLayer;
 use tower_http::trace::{DefaultMakeSpan, TraceLayer};
-use tracing::info;
+use tracing::{debug, info, warn};
 use tracing_bunyan_formatter::{BunyanFormattingLayer, JsonStorageLayer};
 use tracing_subscriber::fmt::writer::MakeWriterExt;
 use tracing_subscriber::FmtSubscriber;
 use tracing_subscriber::layer::SubscriberExt;
 use uuid::Uuid;
 
+use pact_protobuf_plugin::message_decoder::set_max_field_size;
+use pact_protobuf_plugin::metrics;
+use pact_protobuf_plugin::protobuf::set_max_nesting_depth;
+use pact_protobuf_plugin::protoc::validate_proto_file;
 use pact_protobuf_plugin::server::ProtobufPactPlugin;
 use pact_protobuf_plugin::tcp::TcpIncoming;
+use pact_protobuf_plugin::utils::{list_message_types, list_services};
 
 /// Interceptor to check the server key for the request
 #[derive(Debug, Clone, Default)]
@@ -67,124 +75,202 @@ lazy_static! {
 /// Maximum time to wait when there is no activity to shut the plugin down (10 minutes)
 const MAX_TIME: u64 = 600;
 
+/// Default time to wait for in-flight calls to complete when shutting the plugin down (5 seconds)
+const DRAIN_TIME: u64 = 5;
+
 fn integer_value(v: &str) -> Result<u64, String> {
   v.parse::<u64>().map_err(|e| format!("'{}' is not a valid integer value: {}", v, e) )
 }
 
+/// Environment variable that can be used to set the number of worker threads for the runtime
+const WORKERS_ENV_VAR: &str = "PACT_PROTOBUF_WORKERS";
+
+/// Parses the number of worker threads from the value of the PACT_PROTOBUF_WORKERS environment
+/// variable. Returns None if it is not set, so the runtime default is used.
+fn worker_threads(value: Option<String>) -> Result<Option<usize>, String> {
+  match value {
+    Some(value) if !value.trim().is_empty() => match value.trim().parse::<usize>() {
+      Ok(0) => Err(format!("'{}' is not a valid number of worker threads, it must be greater than zero", value)),
+      Ok(workers) => Ok(Some(workers)),
+      Err(err) => Err(format!("'{}' is not a valid number of worker threads: {}", value, err))
+    }
+    _ => Ok(None)
+  }
+}
+
 /// Main method of the plugin process. This will start a gRPC server using the plugin proto file
 /// (`https://github.com/pact-foundation/pact-plugins/blob/main/proto/plugin.proto`) and then
 /// output the port the server is running on as well as a server key required to access the
 /// gRPC server.
 ///
-/// Log level will be passed in using the `LOG_LEVEL` environment variable.
-#[tokio::main]
-async fn main() -> Result<(), Box<dyn std::error::Error>> {
-    // Setup the logging system based on the LOG_LEVEL environment variable
-    let log_level = env::var("LOG_LEVEL").unwrap_or_else(|_| "INFO".to_string());
-    let file_appender = tracing_appender::rolling::daily("./log", "plugin.log");
-    let (non_blocking, _guard) = tracing_appender::non_blocking(file_appender);
-    let json_appender = tracing_appender::rolling::daily("./log", "plugin.log.json");
-    let (json_non_blocking, _json_guard) = tracing_appender::non_blocking(json_appender);
-
-    // Setup tracing
-    let formatting_layer = BunyanFormattingLayer::new("pact-protobuf-plugin".into(), json_non_blocking);
-    let subscriber = FmtSubscriber::builder()
-      .with_max_level(tracing_core::LevelFilter::from_str(log_level.as_str())
-        .unwrap_or(tracing_core::LevelFilter::INFO))
-      .with_thread_names(true)
-      .with_ansi(false)
-      .with_writer(non_blocking.and(std::io::stdout))
-      .finish()
-      .with(JsonStorageLayer)
-      .with(formatting_layer);
-
-    if let Err(err) = tracing::subscriber::set_global_default(subscriber) {
-      eprintln!("WARN: Failed to initialise global tracing subscriber - {err}");
-    };
+/// Log level will be passed in using the `LOG_LEVEL` environment variable. The number of worker
+/// threads for the runtime can be set with the `PACT_PROTOBUF_WORKERS` environment variable.
+fn main() -> Result<(), Box<dyn std::error::Error>> {
+  let mut builder = tokio::runtime::Builder::new_multi_thread();
+  builder.enable_all();
+  match worker_threads(env::var(WORKERS_ENV_VAR).ok()) {
+    Ok(Some(workers)) => {
+      builder.worker_threads(workers);
+    }
+    Ok(None) => {}
+    Err(err) => eprintln!("WARN: Ignoring the {WORKERS_ENV_VAR} environment variable - {err}")
+  }
+  builder.build()?.block_on(run())
+}
 
-    let app = cli();
-    let matches = match app.try_get_matches() {
-      Ok(matches) => matches,
-      Err(err) => return match err.kind() {
-        ErrorKind::DisplayHelp => {
-          println!("{}", err);
-          Ok(())
-        },
-        ErrorKind::DisplayVersion => {
-          println!("{}", clap::crate_version!());
-          Ok(())
-        },
-        _ => {
-          err.exit();
-        }
+async fn run() -> Result<(), Box<dyn std::error::Error>> {
+  let app = cli();
+  let matches = match app.try_get_matches() {
+    Ok(matches) => matches,
+    Err(err) => return match err.kind() {
+      ErrorKind::DisplayHelp => {
+        println!("{}", err);
+        Ok(())
+      },
+      ErrorKind::DisplayVersion => {
+        println!("{}", clap::crate_version!());
+        Ok(())
+      },
+      _ => {
+        err.exit();
       }
-    };
+    }
+  };
 
-    let plugin = ProtobufPactPlugin::new();
-
-    // Bind to a OS provided port and create a TCP listener
-    let host = plugin.host_to_bind_to()
-      .or_else(|| matches.get_one::<String>("host").cloned())
-      .unwrap_or_else(|| "[::1]".to_string());
-    let addr: SocketAddr = format!("{}:0", host).parse()
-      .with_context(|| format!("Failed to parse the host '{}'", host))?;
-    let listener = TcpListener::bind(addr)
-      .await
-      .with_context(|| format!("Failed to bind to host '{}'", host))?;
-    let address = listener.local_addr()?;
-
-    // Generate a server key and then output the required startup JSON message to standard out
-    let server_key = Uuid::new_v4().to_string();
-    println!("{{\"port\":{}, \"serverKey\":\"{}\"}}", address.port(), server_key);
-
-    // Build our middleware stack
-    let layer = ServiceBuilder::new()
-      // Compress responses
-      .layer(CompressionLayer::new())
-      // Mark the `Authorization` header as sensitive so it doesn't show in logs
-      .layer(SetSensitiveHeadersLayer::new(once(header::AUTHORIZATION)))
-      // Log all requests and responses
-      .layer(
-        TraceLayer::new_for_grpc()
-          .make_span_with(DefaultMakeSpan::new().include_headers(true)),
-      )
-      .into_inner();
-
-    // Create the gRPC server listening on the previously created TCP listener
-    let (snd, rcr) = channel::<()>();
-    update_access_time();
+  if let Some(("validate", args)) = matches.subcommand() {
+    return validate(args).await;
+  }
 
-    let timeout = matches.get_one::<u64>("timeout").copied()
-      .unwrap_or(MAX_TIME);
-    tokio::spawn(async move {
-      let mut interval = time::interval(Duration::from_secs(10));
-      let mut elapsed = false;
-      while !elapsed {
-        interval.tick().await;
-        {
-          let guard = SHUTDOWN_TIMER.lock().unwrap();
-          if let Some(i) = &*guard {
-            if i.elapsed().as_secs() > timeout {
-              info!("No activity for more than {timeout} seconds, sending shutdown signal");
-              elapsed = true;
-            }
+  // Setup the logging system based on the LOG_LEVEL environment variable
+  let log_level = env::var("LOG_LEVEL").unwrap_or_else(|_| "INFO".to_string());
+  let file_appender = tracing_appender::rolling::daily("./log", "plugin.log");
+  let (non_blocking, _guard) = tracing_appender::non_blocking(file_appender);
+  let json_appender = tracing_appender::rolling::daily("./log", "plugin.log.json");
+  let (json_non_blocking, _json_guard) = tracing_appender::non_blocking(json_appender);
+
+  // Setup tracing
+  let formatting_layer = BunyanFormattingLayer::new("pact-protobuf-plugin".into(), json_non_blocking);
+  let subscriber = FmtSubscriber::builder()
+    .with_max_level(tracing_core::LevelFilter::from_str(log_level.as_str())
+      .unwrap_or(tracing_core::LevelFilter::INFO))
+    .with_thread_names(true)
+    .with_ansi(false)
+    .with_writer(non_blocking.and(std::io::stdout))
+    .finish()
+    .with(JsonStorageLayer)
+    .with(formatting_layer);
+
+  if let Err(err) = tracing::subscriber::set_global_default(subscriber) {
+    eprintln!("WARN: Failed to initialise global tracing subscriber - {err}");
+  };
+
+  let plugin = ProtobufPactPlugin::new();
+  if let Some(size) = plugin.max_field_size() {
+    info!("Setting the maximum field size to decode to {} bytes", size);
+    set_max_field_size(size);
+  }
+  if let Some(depth) = plugin.max_nesting_depth() {
+    info!("Setting the maximum nesting depth of embedded messages to {}", depth);
+    set_max_nesting_depth(depth);
+  }
+
+  // Bind to a OS provided port and create a TCP listener
+  let host = plugin.host_to_bind_to()
+    .or_else(|| matches.get_one::<String>("host").cloned())
+    .unwrap_or_else(|| "[::1]".to_string());
+  let addr: SocketAddr = format!("{}:0", host).parse()
+    .with_context(|| format!("Failed to parse the host '{}'", host))?;
+  let listener = TcpListener::bind(addr)
+    .await
+    .with_context(|| format!("Failed to bind to host '{}'", host))?;
+  let address = listener.local_addr()?;
+
+  // Read or generate a server key and then output the required startup JSON message to standard out
+  let server_key = server_key(matches.get_one::<PathBuf>("server-key-file"))?;
+  println!("{{\"port\":{}, \"serverKey\":\"{}\"}}", address.port(), server_key);
+
+  // Build our middleware stack
+  let layer = ServiceBuilder::new()
+    // Compress responses
+    .layer(CompressionLayer::new())
+    // Mark the `Authorization` header as sensitive so it doesn't show in logs
+    .layer(SetSensitiveHeadersLayer::new(once(header::AUTHORIZATION)))
+    // Log all requests and responses
+    .layer(
+      TraceLayer::new_for_grpc()
+        .make_span_with(DefaultMakeSpan::new().include_headers(true)),
+    )
+    .into_inner();
+
+  // Create the gRPC server listening on the previously created TCP listener
+  let (snd, rcr) = channel::<()>();
+  let (drain_snd, drain_rcr) = channel::<()>();
+  update_access_time();
+
+  let timeout = matches.get_one::<u64>("timeout").copied()
+    .unwrap_or(MAX_TIME);
+  let drain_timeout = matches.get_one::<u64>("drain-timeout").copied()
+    .unwrap_or(DRAIN_TIME);
+  tokio::spawn(async move {
+    let mut interval = time::interval(Duration::from_secs(10));
+    let mut elapsed = false;
+    let mut last_metrics = metrics::summary();
+    while !elapsed {
+      interval.tick().await;
+      let current_metrics = metrics::summary();
+      if current_metrics != last_metrics {
+        debug!("Plugin metrics: {}", current_metrics);
+        last_metrics = current_metrics;
+      }
+      {
+        let guard = SHUTDOWN_TIMER.lock().unwrap();
+        if let Some(i) = &*guard {
+          if i.elapsed().as_secs() > timeout {
+            info!("No activity for more than {timeout} seconds, sending shutdown signal");
+            elapsed = true;
           }
         }
       }
-      let _ = snd.send(());
-    });
-    Server::builder()
-      .layer(layer)
-      .add_service(PactPluginServer::with_interceptor(plugin, AuthInterceptor { server_key }))
-      .serve_with_incoming_shutdown(
-        TcpIncoming { inner: listener },
-        async move {
-          let _ = rcr.await;
-          info!("Received shutdown signal, shutting plugin down");
-        }
-      ).await?;
+    }
+    let _ = snd.send(());
+  });
+  let server = Server::builder()
+    .layer(layer)
+    .add_service(PactPluginServer::with_interceptor(plugin, AuthInterceptor { server_key }))
+    .serve_with_incoming_shutdown(
+      TcpIncoming { inner: listener },
+      async move {
+        let _ = rcr.await;
+        info!("Received shutdown signal, shutting plugin down");
+        let _ = drain_snd.send(());
+      }
+    );
+  drain(server, drain_rcr, Duration::from_secs(drain_timeout)).await?;
 
-    Ok(())
+  Ok(())
+}
+
+/// Waits for the server to complete. Once the shutdown signal has been received, the server stops
+/// accepting new connections, and any in-flight calls have until the drain timeout to complete
+/// before the server is stopped.
+async fn drain<E>(
+  server: impl Future<Output = Result<(), E>>,
+  shutdown_started: Receiver<()>,
+  drain_timeout: Duration
+) -> Result<(), E> {
+  let drain_elapsed = async move {
+    match shutdown_started.await {
+      Ok(_) => time::sleep(drain_timeout).await,
+      Err(_) => pending().await
+    }
+  };
+  tokio::select! {
+    result = server => result,
+    _ = drain_elapsed => {
+      warn!("In-flight calls did not complete within {} seconds, stopping the plugin", drain_timeout.as_secs());
+      Ok(())
+    }
+  }
 }
 
 fn cli() -> Command {
@@ -207,12 +293,96 @@ fn cli() -> Command {
       .help("Timeout in seconds to use for inactivity to shutdown the plugin process. Default is 600 seconds (10 minutes)")
       .value_parser(integer_value)
     )
+    .arg(Arg::new("drain-timeout")
+      .long("drain-timeout")
+      .action(ArgAction::Set)
+      .env("PACT_PROTOBUF_DRAIN_TIMEOUT")
+      .help("Time in seconds to wait for in-flight calls to complete when shutting the plugin process down. Default is 5 seconds")
+      .value_parser(integer_value)
+    )
     .arg(Arg::new("host")
       .short('h')
       .long("host")
       .action(ArgAction::Set)
       .help("Host to bind to. Defaults to [::1], which is the IP6 loopback address")
     )
+    .arg(Arg::new("server-key-file")
+      .long("server-key-file")
+      .action(ArgAction::Set)
+      .env("PACT_PROTOBUF_SERVER_KEY_FILE")
+      .value_parser(clap::value_parser!(PathBuf))
+      .help("File to read the server key from. If not provided, a random server key will be generated")
+    )
+    .subcommand(Command::new("validate")
+      .about("Validate a proto file and print a summary of the services, methods and message types in it")
+      .arg(Arg::new("proto")
+        .long("proto")
+        .action(ArgAction::Set)
+        .required(true)
+        .value_parser(clap::value_parser!(PathBuf))
+        .help("Path to the proto file to validate"))
+      .arg(Arg::new("include")
+        .short('I')
+        .long("include")
+        .action(ArgAction::Append)
+        .help("Additional include path to use when parsing the proto file. Can be repeated"))
+    )
+}
+
+/// Returns the server key required to access the gRPC server. If a key file has been provided,
+/// the key will be read from it, otherwise a new random key is generated.
+fn server_key(key_file: Option<&PathBuf>) -> anyhow::Result<String> {
+  match key_file {
+    Some(path) => {
+      let key = std::fs::read_to_string(path)
+        .with_context(|| format!("Failed to read the server key from '{}'", path.display()))?;
+      let key = key.trim();
+      if key.is_empty() {
+        Err(anyhow::anyhow!("The server key file '{}' is empty", path.display()))
+      } else {
+        Ok(key.to_string())
+      }
+    }
+    None => Ok(Uuid::new_v4().to_string())
+  }
+}
+
+/// Parses the proto file passed to the `validate` command, and prints a summary of the services,
+/// methods and message types found. Exits with a non-zero exit code if the file fails to parse.
+async fn validate(args: &ArgMatches) -> Result<(), Box<dyn std::error::Error>> {
+  let proto_file = args.get_one::<PathBuf>("proto").cloned().unwrap_or_default();
+  let includes = args.get_many::<String>("include")
+    .map(|includes| includes.cloned().collect::<Vec<_>>())
+    .unwrap_or_default();
+  match validate_proto_file(&proto_file, &includes).await {
+    Ok(descriptors) => {
+      for line in descriptor_summary(&descriptors) {
+        println!("{}", line);
+      }
+      Ok(())
+    }
+    Err(err) => {
+      eprintln!("ERROR: Failed to parse '{}' - {}", proto_file.display(), err);
+      std::process::exit(1);
+    }
+  }
+}
+
+/// Returns the lines to print for the services, methods and message types in the descriptors
+fn descriptor_summary(descriptors: &FileDescriptorSet) -> Vec<String> {
+  let mut lines = vec![ "Files:".to_string() ];
+  lines.extend(descriptors.file.iter().map(|file| format!("  {}", file.name())));
+
+  lines.push("Services:".to_string());
+  for (service, methods) in list_services(descriptors) {
+    lines.push(format!("  {}", service));
+    lines.extend(methods.iter()
+      .map(|(method, input, output)| format!("    {}({}) returns ({})", method, input, output)));
+  }
+
+  lines.push("Message types:".to_string());
+  lines.extend(list_message_types(descriptors).iter().map(|message| format!("  {}", message)));
+  lines
 }
 
 pub fn update_access_time() {
@@ -222,10 +392,236 @@ pub fn update_access_time() {
 
 #[cfg(test)]
 mod tests {
-  use crate::cli;
+  use expectest::prelude::*;
+
+  use std::path::PathBuf;
+
+  use prost_types::{
+    DescriptorProto,
+    FileDescriptorProto,
+    FileDescriptorSet,
+    MethodDescriptorProto,
+    ServiceDescriptorProto
+  };
+
+  use tonic::Request;
+  use tonic::service::Interceptor;
+
+  use std::net::SocketAddr;
+  use std::sync::Arc;
+  use std::sync::atomic::{AtomicBool, Ordering};
+  use std::time::Duration;
+
+  use pact_plugin_driver::proto::{InitPluginRequest, InitPluginResponse};
+  use pact_plugin_driver::proto::pact_plugin_client::PactPluginClient;
+  use pact_plugin_driver::proto::pact_plugin_server::PactPluginServer;
+  use pact_protobuf_plugin::server::ProtobufPactPlugin;
+  use pact_protobuf_plugin::tcp::TcpIncoming;
+  use tokio::net::TcpListener;
+  use tokio::sync::oneshot::{channel, Sender};
+  use tokio::task::JoinHandle;
+  use tonic::transport::Server;
+  use tower::util::MapFutureLayer;
+
+  use crate::{AuthInterceptor, cli, descriptor_summary, drain, server_key, worker_threads};
 
   #[test]
   fn verify_cli() {
     cli().debug_assert();
   }
+
+  #[test]
+  fn validate_command_test() {
+    let matches = cli().try_get_matches_from(vec![
+      "pact-protobuf-plugin", "validate", "--proto", "tests/simple.proto", "-I", "proto", "--include", "other"
+    ]).unwrap();
+    let (name, args) = matches.subcommand().unwrap();
+    expect!(name).to(be_equal_to("validate"));
+    expect!(args.get_one::<PathBuf>("proto").cloned()).to(be_some().value(PathBuf::from("tests/simple.proto")));
+    let includes = args.get_many::<String>("include").unwrap().cloned().collect::<Vec<_>>();
+    expect!(includes).to(be_equal_to(vec!["proto".to_string(), "other".to_string()]));
+
+    expect!(cli().try_get_matches_from(vec!["pact-protobuf-plugin", "validate"])).to(be_err());
+  }
+
+  #[test]
+  fn descriptor_summary_test() {
+    let descriptors = FileDescriptorSet {
+      file: vec![
+        FileDescriptorProto {
+          name: Some("test.proto".to_string()),
+          package: Some("test".to_string()),
+          message_type: vec![
+            DescriptorProto { name: Some("Request".to_string()), .. DescriptorProto::default() },
+            DescriptorProto { name: Some("Response".to_string()), .. DescriptorProto::default() }
+          ],
+          service: vec![
+            ServiceDescriptorProto {
+              name: Some("Service".to_string()),
+              method: vec![
+                MethodDescriptorProto {
+                  name: Some("Call".to_string()),
+                  input_type: Some(".test.Request".to_string()),
+                  output_type: Some(".test.Response".to_string()),
+                  .. MethodDescriptorProto::default()
+                }
+              ],
+              options: None
+            }
+          ],
+          .. FileDescriptorProto::default()
+        }
+      ]
+    };
+
+    expect!(descriptor_summary(&descriptors)).to(be_equal_to(vec![
+      "Files:".to_string(),
+      "  test.proto".to_string(),
+      "Services:".to_string(),
+      "  .test.Service".to_string(),
+      "    Call(.test.Request) returns (.test.Response)".to_string(),
+      "Message types:".to_string(),
+      "  .test.Request".to_string(),
+      "  .test.Response".to_string()
+    ]));
+  }
+
+  #[test]
+  fn server_key_from_a_file_is_used_by_the_interceptor() {
+    let mut key_file = tempfile::NamedTempFile::new().unwrap();
+    std::io::Write::write_all(&mut key_file, b"provisioned-key\n").unwrap();
+    let key = server_key(Some(&key_file.path().to_path_buf())).unwrap();
+    expect!(key.as_str()).to(be_equal_to("provisioned-key"));
+
+    let mut interceptor = AuthInterceptor { server_key: key };
+    let mut request = Request::new(());
+    request.metadata_mut().insert("authorization", "provisioned-key".parse().unwrap());
+    expect!(interceptor.call(request)).to(be_ok());
+    let mut request = Request::new(());
+    request.metadata_mut().insert("authorization", "some-other-key".parse().unwrap());
+    expect!(interceptor.call(request)).to(be_err());
+  }
+
+  #[test]
+  fn server_key_test() {
+    expect!(server_key(None).unwrap().is_empty()).to(be_false());
+    expect!(server_key(Some(&PathBuf::from("/does/not/exist")))).to(be_err());
+    let key_file = tempfile::NamedTempFile::new().unwrap();
+    expect!(server_key(Some(&key_file.path().to_path_buf()))).to(be_err());
+  }
+
+  #[tokio::test]
+  async fn drain_lets_in_flight_calls_complete() {
+    let (shutdown_snd, shutdown_rcr) = channel::<()>();
+    let completed = Arc::new(AtomicBool::new(false));
+    let call_completed = completed.clone();
+    let server = async move {
+      // Shutdown signal received while a call is still in-flight
+      let _ = shutdown_snd.send(());
+      tokio::time::sleep(Duration::from_millis(100)).await;
+      call_completed.store(true, Ordering::SeqCst);
+      Ok::<(), anyhow::Error>(())
+    };
+
+    expect!(drain(server, shutdown_rcr, Duration::from_secs(5)).await).to(be_ok());
+    expect!(completed.load(Ordering::SeqCst)).to(be_true());
+  }
+
+  #[tokio::test]
+  async fn drain_stops_the_server_once_the_drain_timeout_has_elapsed() {
+    let (shutdown_snd, shutdown_rcr) = channel::<()>();
+    let server = async move {
+      let _ = shutdown_snd.send(());
+      std::future::pending::<anyhow::Result<()>>().await
+    };
+
+    let result = tokio::time::timeout(Duration::from_secs(5),
+      drain(server, shutdown_rcr, Duration::from_millis(100))).await;
+    expect!(result).to(be_ok());
+  }
+
+  /// Starts the plugin gRPC server, where each call takes at least `call_time` to complete. Returns
+  /// the address of the server, the sender for the shutdown signal and the handle for the server task.
+  async fn start_slow_server(
+    call_time: Duration,
+    drain_timeout: Duration
+  ) -> (SocketAddr, Sender<()>, JoinHandle<Result<(), tonic::transport::Error>>) {
+    let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+    let address = listener.local_addr().unwrap();
+    let (shutdown_snd, shutdown_rcr) = channel::<()>();
+    let (drain_snd, drain_rcr) = channel::<()>();
+    let server = Server::builder()
+      .layer(MapFutureLayer::new(move |call| async move {
+        tokio::time::sleep(call_time).await;
+        call.await
+      }))
+      .add_service(PactPluginServer::new(ProtobufPactPlugin::new()))
+      .serve_with_incoming_shutdown(
+        TcpIncoming { inner: listener },
+        async move {
+          let _ = shutdown_rcr.await;
+          let _ = drain_snd.send(());
+        }
+      );
+    (address, shutdown_snd, tokio::spawn(drain(server, drain_rcr, drain_timeout)))
+  }
+
+  /// Starts an InitPlugin call against the server, and waits for it to be in-flight
+  async fn start_call(address: SocketAddr) -> JoinHandle<Result<tonic::Response<InitPluginResponse>, tonic::Status>> {
+    let mut client = PactPluginClient::connect(format!("http://{}", address)).await.unwrap();
+    let call = tokio::spawn(async move {
+      client.init_plugin(InitPluginRequest {
+        implementation: "test".to_string(),
+        version: "0".to_string()
+      }).await
+    });
+    tokio::time::sleep(Duration::from_millis(200)).await;
+    call
+  }
+
+  #[tokio::test]
+  async fn shutting_down_lets_an_in_flight_grpc_call_complete() {
+    let (address, shutdown, server) = start_slow_server(Duration::from_secs(1), Duration::from_secs(5)).await;
+    let call = start_call(address).await;
+
+    shutdown.send(()).unwrap();
+
+    // The server waits for the in-flight call before stopping
+    tokio::time::sleep(Duration::from_millis(200)).await;
+    expect!(server.is_finished()).to(be_false());
+    let response = call.await.unwrap();
+    expect!(response.as_ref()).to(be_ok());
+    expect!(response.unwrap().get_ref().catalogue.len()).to(be_equal_to(3));
+    expect!(tokio::time::timeout(Duration::from_secs(5), server).await.unwrap().unwrap()).to(be_ok());
+  }
+
+  #[tokio::test]
+  async fn shutting_down_does_not_wait_for_an_in_flight_grpc_call_once_the_drain_timeout_has_elapsed() {
+    let (address, shutdown, server) = start_slow_server(Duration::from_secs(30), Duration::from_millis(200)).await;
+    let call = start_call(address).await;
+
+    shutdown.send(()).unwrap();
+
+    // The plugin process exits once the server has stopped, which ends the call
+    expect!(tokio::time::timeout(Duration::from_secs(5), server).await.unwrap().unwrap()).to(be_ok());
+    expect!(call.is_finished()).to(be_false());
+    call.abort();
+  }
+
+  #[test]
+  fn drain_timeout_test() {
+    let matches = cli().try_get_matches_from(vec!["pact-protobuf-plugin", "--drain-timeout", "10"]).unwrap();
+    expect!(matches.get_one::<u64>("drain-timeout").copied()).to(be_some().value(10));
+    expect!(cli().try_get_matches_from(vec!["pact-protobuf-plugin", "--drain-timeout", "soon"])).to(be_err());
+  }
+
+  #[test]
+  fn worker_threads_test() {
+    expect!(worker_threads(None)).to(be_ok().value(None));
+    expect!(worker_threads(Some("".to_string()))).to(be_ok().value(None));
+    expect!(worker_threads(Some("4".to_string()))).to(be_ok().value(Some(4)));
+    expect!(worker_threads(Some(" 16 ".to_string()))).to(be_ok().value(Some(16)));
+    expect!(worker_threads(Some("0".to_string()))).to(be_err());
+    expect!(worker_threads(Some("lots".to_string()))).to(be_err());
+  }
 }
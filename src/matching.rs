@@ -1,10 +1,11 @@
 //! Functions for matching Protobuf messages
 
-use std::collections::BTreeMap;
+use std::collections::{BTreeMap, BTreeSet, HashMap};
 use std::fmt::{Debug, Display, Formatter};
 
 use anyhow::anyhow;
 use bytes::{Bytes, BytesMut};
+use chrono::{DateTime, Utc};
 use itertools::{Either, Itertools};
 use maplit::hashmap;
 use pact_matching::{BodyMatchResult, CommonMismatch, CoreMatchingContext, DiffConfig, MatchingContext, Mismatch};
@@ -14,24 +15,40 @@ use pact_matching::matchingrules::{compare_lists_with_matchingrule, compare_maps
 use pact_matching::Mismatch::BodyMismatch;
 use pact_models::content_types::ContentType;
 use pact_models::matchingrules::{Category, MatchingRule, RuleList, RuleLogic};
-use pact_models::path_exp::DocPath;
+use pact_models::path_exp::{DocPath, PathToken};
 use pact_models::prelude::MatchingRuleCategory;
+use pact_plugin_driver::plugin_models::PluginInteractionConfig;
 use prost_types::{DescriptorProto, FieldDescriptorProto, FileDescriptorSet};
 use prost_types::field_descriptor_proto::Type;
+use serde_json::{json, Value};
 use tracing::{debug, instrument, trace, warn};
 
-use crate::message_decoder::{decode_message, ProtobufField, ProtobufFieldData};
+use crate::editions::MessageFeatures;
+use crate::message_decoder::{
+  decode_message,
+  decode_message_with_defaults,
+  populate_default_values,
+  ProtobufField,
+  ProtobufFieldData,
+  should_use_default
+};
+use crate::metrics::{self, Counter};
 use crate::utils::{
   display_bytes,
   enum_name,
+  field_number,
   struct_field_data_to_json,
   find_message_descriptor_for_type,
   find_message_field_by_name,
   find_method_descriptor_for_service,
   find_service_descriptor_for_type,
+  format_iso8601_duration,
   is_map_field,
+  is_oneof_field,
   is_repeated_field,
+  is_valid_duration,
   last_name,
+  response_message_type,
   split_service_and_method
 };
 
@@ -46,16 +63,50 @@ use crate::utils::{
 /// - `actual_message_bytes` - The actual message as bytes.
 /// - `matching_rules` - Matching rules to use when comparing the messages.
 /// - `allow_unexpected_keys` - If true, allow unexpected keys in the actual message.
+/// - `fail_fast` - If true, stop comparing at the first mismatch found and only return that one.
+///   Otherwise all mismatches are collected.
+/// - `interaction_config` - Plugin configuration for the interaction. If `strictEnums` is set, any
+///   enum value in the actual message that is not defined in the enum descriptor is treated as a
+///   mismatch. `repeatedFieldKeys` maps the paths of repeated message fields to the field used to
+///   pair the actual and expected elements. Any fields with paths matching one of the `ignoreFields`
+///   paths are not compared. If `closedMessage` is set, unexpected fields in the actual message are
+///   treated as mismatches, regardless of `allow_unexpected_keys`. `timestampTolerance` maps the
+///   paths of timestamp fields to the number of seconds the actual value may differ from the expected one.
+///   `numberRanges` maps the paths of numeric fields to the bounds their actual values must be within.
 /// 
 /// # Returns
 /// A BodyMatchResult indicating if the messages match or not.
+#[allow(clippy::too_many_arguments)]
 pub fn match_message(
   message_name: &str,
   descriptors: &FileDescriptorSet,
   expected_message_bytes: &mut Bytes,
   actual_message_bytes: &mut Bytes,
   matching_rules: &MatchingRuleCategory,
-  allow_unexpected_keys: bool
+  allow_unexpected_keys: bool,
+  fail_fast: bool,
+  interaction_config: &HashMap<String, Value>
+) -> anyhow::Result<BodyMatchResult> {
+  metrics::increment(Counter::Comparisons);
+  let result = compare_messages(message_name, descriptors, expected_message_bytes, actual_message_bytes,
+    matching_rules, allow_unexpected_keys, fail_fast, interaction_config);
+  if !matches!(result, Ok(BodyMatchResult::Ok)) {
+    metrics::increment(Counter::ComparisonFailures);
+  }
+  result
+}
+
+/// Decodes and compares the expected and actual messages (see `match_message`)
+#[allow(clippy::too_many_arguments)]
+fn compare_messages(
+  message_name: &str,
+  descriptors: &FileDescriptorSet,
+  expected_message_bytes: &mut Bytes,
+  actual_message_bytes: &mut Bytes,
+  matching_rules: &MatchingRuleCategory,
+  allow_unexpected_keys: bool,
+  fail_fast: bool,
+  interaction_config: &HashMap<String, Value>
 ) -> anyhow::Result<BodyMatchResult> {
   // message_name can be a fully-qualified name (if created with a recent version of the plugin),
   // or not (if created with an older version of the plugin). find_message_descriptor_for_type can handle both.
@@ -67,16 +118,87 @@ pub fn match_message(
   let actual_message = decode_message(actual_message_bytes, &message_descriptor, descriptors)?;
   debug!("actual message = {:?}", actual_message);
 
-  let plugin_config = hashmap!{};
-  let diff_config = if allow_unexpected_keys {
+  let plugin_config = hashmap!{
+    "protobuf".to_string() => PluginInteractionConfig {
+      pact_configuration: hashmap!{},
+      interaction_configuration: interaction_config.clone()
+    }
+  };
+  let closed_message = interaction_config.get("closedMessage")
+    .and_then(|value| value.as_bool())
+    .unwrap_or_default();
+  let diff_config = if allow_unexpected_keys && !closed_message {
     DiffConfig::AllowUnexpectedKeys
   } else {
     DiffConfig::NoUnexpectedKeys
   };
   let context = CoreMatchingContext::new(diff_config, matching_rules, &plugin_config);
 
-  compare(&message_descriptor, &expected_message, &actual_message, &context,
-          expected_message_bytes, descriptors)
+  let result = compare(&message_descriptor, &expected_message, &actual_message, &context,
+    expected_message_bytes, descriptors, fail_fast)?;
+
+  let strict_enums = interaction_config.get("strictEnums")
+    .and_then(|value| value.as_bool())
+    .unwrap_or_default();
+  if strict_enums {
+    let enum_mismatches = undefined_enum_values(&DocPath::root(), &actual_message, descriptors)?;
+    Ok(merge_mismatches(result, enum_mismatches))
+  } else {
+    Ok(result)
+  }
+}
+
+/// Returns a mismatch for every enum value in the message fields that is not defined in the
+/// enum descriptor. Embedded messages are checked recursively.
+fn undefined_enum_values(
+  path: &DocPath,
+  fields: &[ProtobufField],
+  descriptors: &FileDescriptorSet
+) -> anyhow::Result<Vec<Mismatch>> {
+  let mut mismatches = vec![];
+  for field in fields {
+    let field_path = path.join(field.field_name.as_str());
+    match &field.data {
+      ProtobufFieldData::Enum(value, enum_descriptor)
+        if !enum_descriptor.value.iter().any(|v| v.number() == *value) => {
+        mismatches.push(BodyMismatch {
+          path: field_path.to_string(),
+          expected: None,
+          actual: Some(Bytes::from(value.to_string())),
+          mismatch: format!("Enum value {} for field '{}' is not defined in enum {}",
+            value, field.field_name, enum_descriptor.name())
+        });
+      }
+      ProtobufFieldData::Message(bytes, message_descriptor) => {
+        let mut bytes = Bytes::copy_from_slice(bytes);
+        let message_fields = decode_message(&mut bytes, message_descriptor, descriptors)?;
+        mismatches.extend(undefined_enum_values(&field_path, &message_fields, descriptors)?);
+      }
+      _ => {}
+    }
+  }
+  Ok(mismatches)
+}
+
+/// Adds the additional mismatches to the match result
+fn merge_mismatches(result: BodyMatchResult, additional: Vec<Mismatch>) -> BodyMatchResult {
+  if additional.is_empty() {
+    return result;
+  }
+
+  let mut mismatches = match result {
+    BodyMatchResult::Ok => hashmap!{},
+    BodyMatchResult::BodyMismatches(mismatches) => mismatches,
+    BodyMatchResult::BodyTypeMismatch { .. } => return result
+  };
+  for mismatch in additional {
+    let path = match &mismatch {
+      BodyMismatch { path, .. } => path.clone(),
+      _ => "$".to_string()
+    };
+    mismatches.entry(path).or_default().push(mismatch);
+  }
+  BodyMatchResult::BodyMismatches(mismatches)
 }
 
 /// Match a Protobuf service call, which has an input and output message.
@@ -91,6 +213,9 @@ pub fn match_message(
 /// If it's not there but `request_part` is set to `request`, we compare request message.
 ///   - request part is a part of the method name, e.g. `GetFeature:request`.
 /// In any other case we compare the response message.
+///
+/// If `fail_fast` is true, the comparison will stop at the first mismatch found.
+#[allow(clippy::too_many_arguments)]
 pub fn match_service(
   service: &str,
   descriptors: &FileDescriptorSet,
@@ -98,9 +223,11 @@ pub fn match_service(
   actual_request: &mut Bytes,
   rules: &MatchingRuleCategory,
   allow_unexpected_keys: bool,
-  content_type: &ContentType
+  fail_fast: bool,
+  content_type: &ContentType,
+  interaction_config: &HashMap<String, Value>
 ) -> anyhow::Result<BodyMatchResult> {
-  trace!(service, ?descriptors, allow_unexpected_keys, ?rules, ?content_type, ">> match_service");
+  trace!(service, ?descriptors, allow_unexpected_keys, fail_fast, ?rules, ?content_type, ">> match_service");
   
   let (service_name, method_name) = split_service_and_method(service)?;
   // service_name can be a fully-qualified name (if created with a recent version of the plugin),
@@ -117,20 +244,20 @@ pub fn match_service(
   let expected_message_type = content_type.attributes.get("message");
   
   // TODO: what if both the request and response have the same type but different matching rules?
-  let message_type = if let Some(message_type) = expected_message_type {
+  let (message_type, part) = if let Some(message_type) = expected_message_type {
     // It's not necessary to look at the package from the content type here, as we're going to be using
     // the package from the input or output type anyway, and those do contain package in their name
     let message_type = last_name(message_type.as_str());
     let input_type = method_descriptor.input_type();
     if last_name(input_type) == message_type {
-      input_type
+      (input_type, "request")
     } else {
-      method_descriptor.output_type()
+      (response_message_type(&method_descriptor, interaction_config), "response")
     }
   } else if service_part == "request" {
-    method_descriptor.input_type()
+    (method_descriptor.input_type(), "request")
   } else {
-    method_descriptor.output_type()
+    (response_message_type(&method_descriptor, interaction_config), "response")
   };
 
   trace!("Message type = {}", message_type);
@@ -138,10 +265,11 @@ pub fn match_service(
   // that includes both the package and the type. match_message expects this kind of input.
   match_message(message_type, descriptors,
                 expected_request, actual_request,
-                rules, allow_unexpected_keys)
+                rules, allow_unexpected_keys, fail_fast, &interaction_config_for_part(interaction_config, part))
 }
 
-/// Compare the expected message to the actual one
+/// Compare the expected message to the actual one. If `fail_fast` is true, the comparison will
+/// return as soon as the first mismatch is found.
 #[tracing::instrument(ret, skip_all, fields(expected_fields = expected_message.len(), actual_fields = actual_message.len()) )]
 pub(crate) fn compare(
   message_descriptor: &DescriptorProto,
@@ -149,7 +277,8 @@ pub(crate) fn compare(
   actual_message: &[ProtobufField],
   matching_context: &(dyn MatchingContext + Send + Sync),
   expected_message_bytes: &Bytes,
-  descriptors: &FileDescriptorSet
+  descriptors: &FileDescriptorSet,
+  fail_fast: bool
 ) -> anyhow::Result<BodyMatchResult> {
   let actual_fields = populate_default_values(actual_message, message_descriptor, descriptors);
   if expected_message.is_empty() {
@@ -164,45 +293,47 @@ pub(crate) fn compare(
       }]
     }))
   } else {
-    compare_message(DocPath::root(), expected_message, actual_fields.as_slice(), matching_context, message_descriptor, descriptors)
+    compare_message(DocPath::root(), expected_message, actual_fields.as_slice(), matching_context,
+      message_descriptor, descriptors, fail_fast)
   }
 }
 
-fn populate_default_values(
-  fields: &[ProtobufField],
+/// Checks that for each oneof in the message, if the expected message has a member set then the
+/// actual message does not have a different member set. Returns a mismatch keyed by the index of
+/// the oneof for each oneof where a different member was set.
+fn compare_oneof_cases(
+  path: &DocPath,
   message_descriptor: &DescriptorProto,
-  fds: &FileDescriptorSet
-) -> Vec<ProtobufField> {
-  let mut field_vec = Vec::from(fields);
-  for field in &message_descriptor.field {
-    if let Some(field_num) = field.number {
-      let entry = field_vec.iter()
-        .find(|i| i.field_num == field_num as u32);
-      if entry.is_none() && should_use_default(field) {
-        if let Some(def) = ProtobufField::default_field(field, message_descriptor, fds) {
-          field_vec.push(def)
-        }
+  expected_message_fields: &[ProtobufField],
+  actual_message_fields: &[ProtobufField]
+) -> HashMap<i32, Mismatch> {
+  let mut result = hashmap!{};
+
+  for (index, oneof) in message_descriptor.oneof_decl.iter().enumerate() {
+    let index = index as i32;
+    let set_member = |fields: &[ProtobufField]| message_descriptor.field.iter()
+      .filter(|field| field.oneof_index == Some(index) && is_oneof_field(field))
+      .find(|field| fields.iter().any(|value| field_number(field) == Some(value.field_num)));
+    if let (Some(expected), Some(actual)) = (set_member(expected_message_fields), set_member(actual_message_fields)) {
+      if expected.number != actual.number {
+        let oneof_name = oneof.name.clone().unwrap_or_else(|| index.to_string());
+        trace!(%oneof_name, expected = ?expected.name, actual = ?actual.name, "oneof has a different member set");
+        result.insert(index, BodyMismatch {
+          path: path.join(&oneof_name).to_string(),
+          expected: Some(expected.name().to_string().into()),
+          actual: Some(actual.name().to_string().into()),
+          mismatch: format!("Expected oneof '{}' to have field '{}' set, but field '{}' was set",
+            oneof_name, expected.name(), actual.name())
+        });
       }
     }
   }
-  field_vec
-}
 
-fn should_use_default(descriptor: &FieldDescriptorProto) -> bool {
-  //     For strings, the default value is the empty string.
-  //     For bytes, the default value is empty bytes.
-  //     For bools, the default value is false.
-  //     For numeric types, the default value is zero. For float and double types, -0.0 and 0.0 are treated as equivalent, and will round-trip.
-  //     For enums, the default value is the first defined enum value, which must be 0.
-  match descriptor.r#type() {
-    Type::Double | Type::Float | Type::Int64 | Type::Uint64 | Type::Int32 | Type::Fixed64 |
-    Type::Fixed32 | Type::Bool | Type::String | Type::Bytes | Type::Uint32 | Type::Enum |
-    Type::Sfixed32 | Type::Sfixed64 | Type::Sint32 | Type::Sint64 => !is_repeated_field(descriptor),
-    _ => false
-  }
+  result
 }
 
-/// Compare the fields of the expected and actual messages
+/// Compare the fields of the expected and actual messages. If `fail_fast` is true, only the first
+/// mismatch found will be returned.
 #[tracing::instrument(ret,
   skip_all,
   fields(%path, expected_fields = expected_message_fields.len(), actual_fields=actual_message_fields.len())
@@ -214,17 +345,31 @@ pub fn compare_message(
   matching_context: &(dyn MatchingContext + Send + Sync),
   message_descriptor: &DescriptorProto,
   descriptors: &FileDescriptorSet,
+  fail_fast: bool
 ) -> anyhow::Result<BodyMatchResult> {
   let mut results = hashmap!{};
+  let features = MessageFeatures::resolve(message_descriptor, descriptors);
+  let ignored_paths = ignored_field_paths(matching_context);
+
+  let oneof_mismatches = compare_oneof_cases(&path, message_descriptor, expected_message_fields,
+    actual_message_fields);
+  for mismatch in oneof_mismatches.values() {
+    if let BodyMismatch { path, .. } = mismatch {
+      results.insert(path.clone(), vec![ mismatch.clone() ]);
+    }
+    if fail_fast {
+      return Ok(BodyMatchResult::BodyMismatches(results));
+    }
+  }
 
   let fields = message_descriptor.field.iter()
     .filter_map(|field| {
-      field.number.map(|no| {
-        let expected_field_values = expected_message_fields.iter().filter(|value| value.field_num == no as u32)
+      field_number(field).map(|no| {
+        let expected_field_values = expected_message_fields.iter().filter(|value| value.field_num == no)
           .collect_vec();
-        let actual_field_values = actual_message_fields.iter().filter(|value| value.field_num == no as u32)
+        let actual_field_values = actual_message_fields.iter().filter(|value| value.field_num == no)
           .collect_vec();
-        (no as u32, (field, expected_field_values, actual_field_values))
+        (no, (field, expected_field_values, actual_field_values))
       })
     });
 
@@ -238,9 +383,34 @@ pub fn compare_message(
     let field_path = path.join(&field_name);
     trace!(%field_name, field_no, "Comparing message field {:?} => {:?}", expected, actual);
 
-    if is_map_field(message_descriptor, field_descriptor) {
+    if ignored_field(&field_path, &ignored_paths) {
+      trace!(%field_name, field_no, "field is configured to be ignored, skipping");
+      continue;
+    }
+
+    if let Some(oneof_index) = field_descriptor.oneof_index {
+      if oneof_mismatches.contains_key(&oneof_index) {
+        trace!(%field_name, field_no, "field is a member of a oneof that has a different member set, skipping");
+        continue;
+      }
+    }
+
+    if expected.is_empty() && field_expected_to_be_absent(&field_path, matching_context) {
+      trace!(%field_name, field_no, "field is expected to be absent");
+      if let Some(actual_value) = actual.iter().find(|value| !value.is_default_value()) {
+        results.insert(field_path.to_string(), vec![
+          BodyMismatch {
+            path: field_path.to_string(),
+            expected: None,
+            actual: Some(Bytes::from(actual_value.data.as_bytes())),
+            mismatch: format!("Expected field '{}' to be absent, but received a value for it", field_name)
+          }
+        ]);
+      }
+    } else if is_map_field(message_descriptor, field_descriptor) {
       trace!(%field_name, field_no, "field is a map field");
-      let map_comparison = compare_map_field(&field_path, field_descriptor, expected, actual, matching_context, descriptors);
+      let map_comparison = compare_map_field(&field_path, field_descriptor, expected, actual, matching_context,
+        descriptors, fail_fast);
       if !map_comparison.is_empty() {
         results.insert(field_path.to_string(), map_comparison);
       }
@@ -248,10 +418,21 @@ pub fn compare_message(
       trace!(%field_name, field_no, "field is a repeated field");
       let e = expected.iter().map(|f| (*f).clone()).collect_vec();
       let a = actual.iter().map(|f| (*f).clone()).collect_vec();
-      let repeated_comparison = compare_repeated_field(&field_path, field_descriptor, &e, &a, matching_context, descriptors);
+      let repeated_comparison = compare_repeated_field(&field_path, field_descriptor, &e, &a, matching_context,
+        descriptors, fail_fast);
       if !repeated_comparison.is_empty() {
         results.insert(field_path.to_string(), repeated_comparison);
       }
+    } else if features.has_explicit_presence(field_descriptor) && actual.is_empty() && !expected.is_empty() && should_use_default(field_descriptor) {
+      trace!(%field_name, field_no, "field has explicit presence and was not set");
+      results.insert(field_path.to_string(), vec![
+        BodyMismatch {
+          path: field_path.to_string(),
+          expected: expected.first().map(|field_data| Bytes::from(field_data.data.as_bytes())),
+          actual: None,
+          mismatch: format!("Expected field '{}' to be set, but it was missing", field_name)
+        }
+      ]);
     } else if let Some(expected_value) = expected.first() {
       let actual_value = actual.first().map(|v| (*v).clone()).unwrap_or_else(|| {
         // Need to compare against the default values, as gRPC lib may have skipped sending the field if it was a default
@@ -302,12 +483,14 @@ pub fn compare_message(
         } else {
           matching_context.clone_with(matching_context.matchers())
         };
-        let comparison = compare_field(&field_path, expected_value, field_descriptor, &actual_value, context.as_ref(), descriptors);
+        let comparison = compare_field(&field_path, expected_value, field_descriptor, &actual_value, context.as_ref(),
+          descriptors, fail_fast);
         if !comparison.is_empty() {
           results.insert(field_path.to_string(), comparison);
         }
       } else {
-        let comparison = compare_field(&field_path, expected_value, field_descriptor, &actual_value, matching_context, descriptors);
+        let comparison = compare_field(&field_path, expected_value, field_descriptor, &actual_value, matching_context,
+          descriptors, fail_fast);
         if !comparison.is_empty() {
           results.insert(field_path.to_string(), comparison);
         }
@@ -325,6 +508,14 @@ pub fn compare_message(
         ]);
       }
     }
+
+    if fail_fast && !results.is_empty() {
+      debug!("Fail fast is enabled, stopping at the first mismatch for field '{}'", field_name);
+      for mismatches in results.values_mut() {
+        mismatches.truncate(1);
+      }
+      break;
+    }
   }
 
   if results.is_empty() {
@@ -334,6 +525,16 @@ pub fn compare_message(
   }
 }
 
+/// If the field has been configured with `absent()`, it is recorded as a null matcher against the
+/// field path
+fn field_expected_to_be_absent(
+  path: &DocPath,
+  matching_context: &(dyn MatchingContext + Send + Sync)
+) -> bool {
+  let rules = matching_context.select_best_matcher(path);
+  !rules.cascaded && rules.rules.contains(&MatchingRule::Null)
+}
+
 /// Compare a simple field (non-map and non-repeated)
 #[tracing::instrument(ret,
   skip_all,
@@ -345,14 +546,36 @@ fn compare_field(
   descriptor: &FieldDescriptorProto,
   actual: &ProtobufField,
   matching_context: &(dyn MatchingContext + Send + Sync),
-  descriptors: &FileDescriptorSet
+  descriptors: &FileDescriptorSet,
+  fail_fast: bool
 ) -> Vec<Mismatch> {
+  if let Some(tolerance) = timestamp_tolerance(path, matching_context) {
+    if let Some(expected) = timestamp_value(field, descriptors) {
+      trace!("Comparing timestamp values with a tolerance of {:?}", tolerance);
+      return compare_timestamps(path, field, actual, expected, tolerance, descriptors);
+    }
+  }
+
+  if let Some(range) = number_range(path, matching_context) {
+    let mismatches = compare_number_range(path, actual, &range);
+    if !mismatches.is_empty() {
+      return mismatches;
+    }
+  }
+
   match (&field.data, &actual.data) {
     (ProtobufFieldData::String(s1), ProtobufFieldData::String(s2)) => {
       trace!("Comparing string values");
-      let s1 = s1.clone();
-      let s2 = s2.clone();
-      compare_value(path, field, &s1, &s2, s1.as_str(), s2.as_str(), matching_context)
+      if matching_context.matcher_is_defined(path) &&
+        is_equals_ignore_case_rule(&matching_context.select_best_matcher(path), s1) {
+        compare_ignoring_case(path, s1, s2)
+      } else if let Some(values) = any_of_values(path, matching_context) {
+        compare_any_of(path, s2, &values)
+      } else {
+        let s1 = s1.clone();
+        let s2 = s2.clone();
+        compare_value(path, field, &s1, &s2, s1.as_str(), s2.as_str(), matching_context)
+      }
     },
     (ProtobufFieldData::Boolean(b1), ProtobufFieldData::Boolean(b2)) => {
       trace!("Comparing boolean values");
@@ -376,23 +599,35 @@ fn compare_field(
     },
     (ProtobufFieldData::Float(n1), ProtobufFieldData::Float(n2)) => {
       trace!("Comparing Float values");
-      compare_value(path, field, *n1 as f64, *n2 as f64, n1.to_string().as_str(), n2.to_string().as_str(), matching_context)
+      compare_floating_point_value(path, field, *n1 as f64, *n2 as f64, n1.to_string().as_str(), n2.to_string().as_str(), matching_context)
     },
     (ProtobufFieldData::Double(n1), ProtobufFieldData::Double(n2)) => {
       trace!("Comparing Double values");
-      compare_value(path, field, *n1, *n2, n1.to_string().as_str(), n2.to_string().as_str(), matching_context)
+      compare_floating_point_value(path, field, *n1, *n2, n1.to_string().as_str(), n2.to_string().as_str(), matching_context)
     },
     (ProtobufFieldData::Bytes(b1), ProtobufFieldData::Bytes(b2)) => {
       trace!("Comparing byte arrays");
-      let b1_str = display_bytes(b1);
-      let b2_str = display_bytes(b2);
-      compare_value(path, field, b1.as_slice(), b2.as_slice(), b1_str.as_str(), b2_str.as_str(), matching_context)
+      if let Some(content_type) = content_type_matcher(path, matching_context) {
+        compare_bytes_with_content_type(path, b1, b2, &content_type, matching_context, descriptors, fail_fast)
+      } else {
+        let b1_str = display_bytes(b1);
+        let b2_str = display_bytes(b2);
+        compare_value(path, field, b1.as_slice(), b2.as_slice(), b1_str.as_str(), b2_str.as_str(), matching_context)
+      }
     },
     (ProtobufFieldData::Enum(b1, descriptor), ProtobufFieldData::Enum(b2, _)) => {
       trace!("Comparing Enum values");
       let enum_1 = enum_name(*b1, descriptor);
       let enum_2 = enum_name(*b2, descriptor);
-      compare_value(path, field, &enum_1, &enum_2, enum_1.as_str(), enum_2.as_str(), matching_context)
+      if let Some(values) = any_of_values(path, matching_context) {
+        compare_any_of(path, &enum_2, &values)
+      } else {
+        compare_value(path, field, &enum_1, &enum_2, enum_1.as_str(), enum_2.as_str(), matching_context)
+      }
+    },
+    (ProtobufFieldData::Duration(seconds, nanos), _) => {
+      trace!("Comparing duration values");
+      compare_durations(path, field, actual, *seconds, *nanos, matching_context)
     },
     (ProtobufFieldData::Message(b1, message_descriptor), ProtobufFieldData::Message(b2, _)) => {
       trace!("Comparing embedded messages");
@@ -442,6 +677,23 @@ fn compare_field(
             let b2_str = display_bytes(&b2);
             compare_value(path, field, b1, b2, b1_str.as_str(), b2_str.as_str(), matching_context)
           }
+          ".google.protobuf.FieldMask" => {
+            debug!("Field is a Protobuf FieldMask, will compare the paths as a set");
+            let expected_paths = field_mask_paths(&expected_message);
+            let actual_paths = field_mask_paths(&actual_message);
+            if expected_paths == actual_paths {
+              vec![]
+            } else {
+              vec![
+                BodyMismatch {
+                  path: path.to_string(),
+                  expected: Some(format!("{:?}", expected_paths).into()),
+                  actual: Some(format!("{:?}", actual_paths).into()),
+                  mismatch: format!("Expected field mask paths {:?} but received {:?}", expected_paths, actual_paths)
+                }
+              ]
+            }
+          }
           ".google.protobuf.Struct" => {
             debug!("Field is a Protobuf Struct, will compare it as JSON");
             trace!("Parsing expected message");
@@ -473,6 +725,8 @@ fn compare_field(
               }
             };
 
+            let expected_json = whole_numbers_to_integers(path, &expected_json, matching_context);
+            let actual_json = whole_numbers_to_integers(path, &actual_json, matching_context);
             trace!(%expected_json, %actual_json, "Comparing JSON");
             match compare_json(path, &expected_json, &actual_json, matching_context) {
               Ok(_) => vec![],
@@ -481,7 +735,8 @@ fn compare_field(
           }
           _ => {
             debug!("Field is a normal message");
-            match compare_message(path.clone(), &expected_message, &actual_message, matching_context, message_descriptor, descriptors) {
+            match compare_message(path.clone(), &expected_message, &actual_message, matching_context,
+              message_descriptor, descriptors, fail_fast) {
               Ok(result) => match result {
                 BodyMatchResult::Ok => vec![],
                 BodyMatchResult::BodyTypeMismatch { message, .. } => vec![
@@ -527,6 +782,200 @@ fn compare_field(
   }
 }
 
+/// Returns the content type if there is a content type matcher defined for the path
+fn content_type_matcher(
+  path: &DocPath,
+  matching_context: &(dyn MatchingContext + Send + Sync)
+) -> Option<ContentType> {
+  if matching_context.matcher_is_defined(path) {
+    matching_context.select_best_matcher(path).rules.iter()
+      .find_map(|rule| match rule {
+        MatchingRule::ContentType(content_type) => ContentType::parse(content_type.as_str()).ok(),
+        _ => None
+      })
+  } else {
+    None
+  }
+}
+
+/// If the content type is for an encoded Protobuf message (i.e. `application/protobuf;message=.pkg.Type`),
+/// returns the type of the message
+pub(crate) fn protobuf_message_type(content_type: &ContentType) -> Option<&String> {
+  if content_type.main_type == "application" && matches!(content_type.sub_type.as_str(), "protobuf" | "x-protobuf") {
+    content_type.attributes.get("message")
+  } else {
+    None
+  }
+}
+
+/// Checks the `message` parameter of the actual content type against the message type of the
+/// expected content type. If `strict` is set, the actual content type must declare the same
+/// message type, otherwise a different message type is only logged as a warning. Older versions of the plugin did not use fully-qualified names, so only the last part of the
+/// names is compared if either name is not fully-qualified.
+pub fn match_content_type_message(
+  expected: &ContentType,
+  actual: Option<&ContentType>,
+  strict: bool
+) -> BodyMatchResult {
+  let expected_message = match protobuf_message_type(expected) {
+    Some(message) => message,
+    None => return BodyMatchResult::Ok
+  };
+  let actual_message = actual.and_then(protobuf_message_type);
+  let mismatch = |message: String| BodyMatchResult::BodyTypeMismatch {
+    expected_type: expected.to_string(),
+    actual_type: actual.map(|ct| ct.to_string()).unwrap_or_default(),
+    message,
+    expected: None,
+    actual: None
+  };
+
+  match actual_message {
+    Some(actual_message) => {
+      let matches = if expected_message.starts_with('.') && actual_message.starts_with('.') {
+        expected_message == actual_message
+      } else {
+        last_name(expected_message) == last_name(actual_message)
+      };
+      if matches {
+        BodyMatchResult::Ok
+      } else if strict {
+        mismatch(format!("Expected a message of type '{}' but the content type declared '{}'",
+          expected_message, actual_message))
+      } else {
+        warn!("Expected a message of type '{}' but the content type declared '{}', ignoring as strictMessageType is not set",
+          expected_message, actual_message);
+        BodyMatchResult::Ok
+      }
+    }
+    None if strict => mismatch(format!("Expected a message of type '{}' but the content type '{}' did not declare a message type",
+      expected_message, actual.map(|ct| ct.to_string()).unwrap_or_default())),
+    None => BodyMatchResult::Ok
+  }
+}
+
+/// Compares the contents of bytes fields using the content type from a content type matcher.
+/// JSON contents will be compared as JSON, and Protobuf messages will be decoded and compared as
+/// messages. Otherwise the bytes are compared for equality.
+#[allow(clippy::too_many_arguments)]
+fn compare_bytes_with_content_type(
+  path: &DocPath,
+  expected: &[u8],
+  actual: &[u8],
+  content_type: &ContentType,
+  matching_context: &(dyn MatchingContext + Send + Sync),
+  descriptors: &FileDescriptorSet,
+  fail_fast: bool
+) -> Vec<Mismatch> {
+  if content_type.is_json() {
+    debug!("Bytes field has a JSON content type, will compare it as JSON");
+    let expected_json = match serde_json::from_slice(expected) {
+      Ok(json) => json,
+      Err(err) => return vec![
+        BodyMismatch {
+          path: path.to_string(),
+          expected: Some(Bytes::copy_from_slice(expected)),
+          actual: Some(Bytes::copy_from_slice(actual)),
+          mismatch: format!("Could not parse expected bytes as {} - {}", content_type, err)
+        }
+      ]
+    };
+    let actual_json = match serde_json::from_slice(actual) {
+      Ok(json) => json,
+      Err(err) => return vec![
+        BodyMismatch {
+          path: path.to_string(),
+          expected: Some(Bytes::copy_from_slice(expected)),
+          actual: Some(Bytes::copy_from_slice(actual)),
+          mismatch: format!("Expected bytes with content type {} but could not parse them - {}", content_type, err)
+        }
+      ]
+    };
+
+    // The content type matcher applies to the bytes, not to the JSON values
+    let context = without_content_type_matchers(matching_context);
+
+    trace!(%expected_json, %actual_json, "Comparing JSON");
+    match compare_json(path, &expected_json, &actual_json, context.as_ref()) {
+      Ok(_) => vec![],
+      Err(err) => err.iter().map(CommonMismatch::to_body_mismatch).collect()
+    }
+  } else if let Some(message_type) = protobuf_message_type(content_type) {
+    debug!("Bytes field has a Protobuf content type, will compare it as a {} message", message_type);
+    compare_bytes_as_message(path, expected, actual, content_type, message_type, matching_context,
+      descriptors, fail_fast)
+  } else if expected == actual {
+    vec![]
+  } else {
+    debug!("No comparison is supported for content type {}, comparing the bytes", content_type);
+    vec![
+      BodyMismatch {
+        path: path.to_string(),
+        expected: Some(Bytes::copy_from_slice(expected)),
+        actual: Some(Bytes::copy_from_slice(actual)),
+        mismatch: format!("Expected bytes with content type {} to be equal to {} but got {}",
+          content_type, display_bytes(expected), display_bytes(actual))
+      }
+    ]
+  }
+}
+
+/// Decodes the contents of bytes fields as the given Protobuf message type, and compares the
+/// decoded messages
+#[allow(clippy::too_many_arguments)]
+fn compare_bytes_as_message(
+  path: &DocPath,
+  expected: &[u8],
+  actual: &[u8],
+  content_type: &ContentType,
+  message_type: &str,
+  matching_context: &(dyn MatchingContext + Send + Sync),
+  descriptors: &FileDescriptorSet,
+  fail_fast: bool
+) -> Vec<Mismatch> {
+  let mismatch = |mismatch: String| vec![
+    BodyMismatch {
+      path: path.to_string(),
+      expected: Some(Bytes::copy_from_slice(expected)),
+      actual: Some(Bytes::copy_from_slice(actual)),
+      mismatch
+    }
+  ];
+
+  let message_descriptor = match find_message_descriptor_for_type(message_type, descriptors) {
+    Ok((message_descriptor, _)) => message_descriptor,
+    Err(err) => return mismatch(format!("Could not find the message type for content type {} - {}", content_type, err))
+  };
+  let expected_fields = match decode_message(&mut Bytes::copy_from_slice(expected), &message_descriptor, descriptors) {
+    Ok(fields) => fields,
+    Err(err) => return mismatch(format!("Could not decode expected bytes as {} - {}", content_type, err))
+  };
+  let actual_fields = match decode_message(&mut Bytes::copy_from_slice(actual), &message_descriptor, descriptors) {
+    Ok(fields) => populate_default_values(&fields, &message_descriptor, descriptors),
+    Err(err) => return mismatch(format!("Expected bytes with content type {} but could not decode them - {}", content_type, err))
+  };
+
+  // The content type matcher applies to the bytes, not to the fields of the message
+  let context = without_content_type_matchers(matching_context);
+  match compare_message(path.clone(), &expected_fields, &actual_fields, context.as_ref(),
+    &message_descriptor, descriptors, fail_fast) {
+    Ok(result) => result.mismatches(),
+    Err(err) => mismatch(format!("Failed to compare the bytes with content type {} - {}", content_type, err))
+  }
+}
+
+/// Returns a copy of the matching context with any content type matchers removed
+fn without_content_type_matchers(
+  matching_context: &(dyn MatchingContext + Send + Sync)
+) -> Box<dyn MatchingContext + Send + Sync> {
+  let mut rules = matching_context.matchers().clone();
+  for rule_list in rules.rules.values_mut() {
+    rule_list.rules.retain(|rule| !matches!(rule, MatchingRule::ContentType(_)));
+  }
+  rules.rules.retain(|_, rule_list| !rule_list.is_empty());
+  matching_context.clone_with(&rules)
+}
+
 /// Compares the actual value to the expected one.
 fn compare_value<T>(
   path: &DocPath,
@@ -541,7 +990,16 @@ fn compare_value<T>(
 
   if matching_context.matcher_is_defined(path) {
     debug!("compare_value: Matcher defined for path '{}' and values {:?} -> {:?}", path, expected, actual);
-    match match_values(path, &matching_context.select_best_matcher(path), expected, actual) {
+    let rules = matching_context.select_best_matcher(path);
+    if let Some(mismatch) = numeric_matcher_type_mismatch(&rules, field, actual_str) {
+      return vec![BodyMismatch {
+        path: path.to_string(),
+        expected: Some(expected_str.as_bytes().to_vec().into()),
+        actual: Some(actual_str.as_bytes().to_vec().into()),
+        mismatch
+      }];
+    }
+    match match_values(path, &rules, expected, actual) {
       Ok(_) => vec![],
       Err(mismatches) => mismatches.iter().map(|m| BodyMismatch {
         path: path.to_string(),
@@ -564,6 +1022,301 @@ fn compare_value<T>(
   }
 }
 
+/// Compares Float and Double values. NaN is never equal to itself, so an expected NaN value will
+/// match an actual NaN value when the values are compared for equality.
+fn compare_floating_point_value(
+  path: &DocPath,
+  field: &ProtobufField,
+  expected: f64,
+  actual: f64,
+  expected_str: &str,
+  actual_str: &str,
+  matching_context: &dyn MatchingContext
+) -> Vec<Mismatch> {
+  if expected.is_nan() && actual.is_nan() {
+    let equality = !matching_context.matcher_is_defined(path) || matching_context.select_best_matcher(path)
+      .rules.iter().all(|rule| *rule == MatchingRule::Equality);
+    if equality {
+      trace!("compare_floating_point_value: expected and actual values are both NaN");
+      return vec![];
+    }
+  }
+  compare_value(path, field, expected, actual, expected_str, actual_str, matching_context)
+}
+
+/// Checks any integer or decimal matchers against the type of the Protobuf field, as an integer
+/// field can never match a decimal matcher, and a floating point field can never match an
+/// integer matcher. Returns the mismatch message if the field type is not compatible.
+fn numeric_matcher_type_mismatch(rules: &RuleList, field: &ProtobufField, actual: &str) -> Option<String> {
+  if rules.rule_logic == RuleLogic::Or && rules.rules.len() > 1 {
+    return None;
+  }
+
+  let field_type = field.descriptor.r#type();
+  let is_integer_field = matches!(field_type, Type::Int32 | Type::Int64 | Type::Uint32 | Type::Uint64 |
+    Type::Sint32 | Type::Sint64 | Type::Fixed32 | Type::Fixed64 | Type::Sfixed32 | Type::Sfixed64);
+  let is_decimal_field = matches!(field_type, Type::Float | Type::Double);
+  rules.rules.iter().find_map(|rule| match rule {
+    MatchingRule::Decimal if is_integer_field => Some(format!(
+      "Expected {} to match a decimal number, but field '{}' is an integer field ({:?})",
+      actual, field.field_name, field_type)),
+    MatchingRule::Integer if is_decimal_field => Some(format!(
+      "Expected {} to match an integer number, but field '{}' is a floating point field ({:?})",
+      actual, field.field_name, field_type)),
+    _ => None
+  })
+}
+
+/// Creates the matching rule used to match a string value ignoring case. This is recorded as a
+/// case-insensitive regex for the value, so other Pact implementations can also verify it.
+pub(crate) fn equals_ignore_case_rule(value: &str) -> MatchingRule {
+  MatchingRule::Regex(equals_ignore_case_regex(value))
+}
+
+/// Case-insensitive regex that only matches the value
+fn equals_ignore_case_regex(value: &str) -> String {
+  format!("(?i)^{}$", regex::escape(value))
+}
+
+/// Creates the matching rule used to match a value against a set of allowed values. This is
+/// recorded as a regex that only matches the values, so other Pact implementations can also verify it.
+pub(crate) fn any_of_rule(values: &[String]) -> MatchingRule {
+  MatchingRule::Regex(format!("^(?:{})$", values.iter().map(|value| regex::escape(value)).join("|")))
+}
+
+/// Returns the allowed values if the matching rules for the path are for a set of allowed values
+/// (created with `any_of_rule`)
+fn any_of_values(path: &DocPath, matching_context: &(dyn MatchingContext + Send + Sync)) -> Option<Vec<String>> {
+  if !matching_context.matcher_is_defined(path) {
+    return None;
+  }
+  let rules = matching_context.select_best_matcher(path);
+  match rules.rules.as_slice() {
+    [MatchingRule::Regex(regex)] => {
+      let alternatives = regex.strip_prefix("^(?:")?.strip_suffix(")$")?;
+      let mut values = vec![];
+      let mut value = String::new();
+      let mut chars = alternatives.chars();
+      while let Some(ch) = chars.next() {
+        match ch {
+          '\\' => value.push(chars.next()?),
+          '|' => values.push(std::mem::take(&mut value)),
+          _ => value.push(ch)
+        }
+      }
+      values.push(value);
+      // Only treat the regex as a set of values if it is exactly what would have been generated
+      // for them, otherwise it is a normal regex matcher
+      if any_of_rule(&values) == MatchingRule::Regex(regex.clone()) {
+        Some(values)
+      } else {
+        None
+      }
+    }
+    _ => None
+  }
+}
+
+/// Compares the actual value to the set of allowed values
+fn compare_any_of(path: &DocPath, actual: &str, values: &[String]) -> Vec<Mismatch> {
+  debug!("compare_any_of: Allowed values matcher defined for path '{}'", path);
+  if values.iter().any(|value| value == actual) {
+    vec![]
+  } else {
+    vec![BodyMismatch {
+      path: path.to_string(),
+      expected: Some(values.join(", ").into()),
+      actual: Some(actual.as_bytes().to_vec().into()),
+      mismatch: format!("Expected '{}' to be one of {}", actual,
+        values.iter().map(|value| format!("'{}'", value)).join(", "))
+    }]
+  }
+}
+
+/// Bounds of a number range matcher (i.e. `range(1, 100)`). Either bound can be missing, in which
+/// case that side of the range is unbounded.
+#[derive(Clone, Debug, Default, PartialEq)]
+pub(crate) struct NumberRange {
+  /// Lower bound of the range
+  pub min: Option<f64>,
+  /// If the lower bound is excluded from the range
+  pub min_exclusive: bool,
+  /// Upper bound of the range
+  pub max: Option<f64>,
+  /// If the upper bound is excluded from the range
+  pub max_exclusive: bool
+}
+
+impl NumberRange {
+  /// If the value is within the bounds of the range
+  pub fn contains(&self, value: f64) -> bool {
+    let above_min = match self.min {
+      Some(min) if self.min_exclusive => value > min,
+      Some(min) => value >= min,
+      None => true
+    };
+    let below_max = match self.max {
+      Some(max) if self.max_exclusive => value < max,
+      Some(max) => value <= max,
+      None => true
+    };
+    above_min && below_max
+  }
+
+  /// If the float value is within the bounds of the range. The bounds are converted to floats
+  /// first, so a bound like 0.1 includes the float value 0.1.
+  pub fn contains_f32(&self, value: f32) -> bool {
+    let range = NumberRange {
+      min: self.min.map(|min| min as f32 as f64),
+      max: self.max.map(|max| max as f32 as f64),
+      .. self.clone()
+    };
+    range.contains(value as f64)
+  }
+
+  /// Returns the JSON form of the range that is stored in the interaction configuration
+  pub fn to_json(&self) -> Value {
+    json!({
+      "min": self.min,
+      "minExclusive": self.min_exclusive,
+      "max": self.max,
+      "maxExclusive": self.max_exclusive
+    })
+  }
+
+  /// Creates the range from the JSON form stored in the interaction configuration
+  pub fn from_json(json: &Value) -> Option<NumberRange> {
+    let bound = |name: &str| json.get(name).and_then(|value| match value {
+      Value::Number(n) => n.as_f64(),
+      Value::String(s) => s.parse().ok(),
+      _ => None
+    });
+    let exclusive = |name: &str| json.get(name).and_then(|value| value.as_bool()).unwrap_or_default();
+    json.as_object().map(|_| NumberRange {
+      min: bound("min"),
+      min_exclusive: exclusive("minExclusive"),
+      max: bound("max"),
+      max_exclusive: exclusive("maxExclusive")
+    })
+  }
+}
+
+impl Display for NumberRange {
+  fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+    let min = self.min.map(|min| if self.min_exclusive {
+      format!("greater than {}", min)
+    } else {
+      format!("greater than or equal to {}", min)
+    });
+    let max = self.max.map(|max| if self.max_exclusive {
+      format!("less than {}", max)
+    } else {
+      format!("less than or equal to {}", max)
+    });
+    match (min, max) {
+      (Some(min), Some(max)) => write!(f, "{} and {}", min, max),
+      (Some(bound), None) | (None, Some(bound)) => write!(f, "{}", bound),
+      (None, None) => write!(f, "any number")
+    }
+  }
+}
+
+/// Returns the numeric value of the field data as a floating point number, if it is a numeric field
+fn numeric_value(data: &ProtobufFieldData) -> Option<f64> {
+  match data {
+    ProtobufFieldData::Integer32(n) => Some(*n as f64),
+    ProtobufFieldData::UInteger32(n) => Some(*n as f64),
+    ProtobufFieldData::Integer64(n) => Some(*n as f64),
+    ProtobufFieldData::UInteger64(n) => Some(*n as f64),
+    ProtobufFieldData::Float(n) => Some(*n as f64),
+    ProtobufFieldData::Double(n) => Some(*n),
+    _ => None
+  }
+}
+
+/// Checks that the actual numeric value is within the bounds of the range
+fn compare_number_range(path: &DocPath, actual: &ProtobufField, range: &NumberRange) -> Vec<Mismatch> {
+  debug!("compare_number_range: Number range matcher defined for path '{}'", path);
+  let in_range = match &actual.data {
+    ProtobufFieldData::Float(n) => Some(range.contains_f32(*n)),
+    data => numeric_value(data).map(|value| range.contains(value))
+  };
+  match in_range {
+    Some(false) => vec![BodyMismatch {
+      path: path.to_string(),
+      expected: Some(range.to_string().into()),
+      actual: Some(actual.data.to_string().into()),
+      mismatch: format!("Expected {} to be {}", actual.data, range)
+    }],
+    _ => vec![]
+  }
+}
+
+/// Largest integer that can be exactly represented by a double (2^53 - 1)
+const MAX_SAFE_INTEGER: f64 = 9_007_199_254_740_991.0;
+
+/// Struct number values are always doubles, so whole numbers at paths with an integer matcher are
+/// converted to JSON integers to allow them to match. Other numbers are left as doubles, so they
+/// can still be matched with decimal matchers.
+fn whole_numbers_to_integers(
+  path: &DocPath,
+  json: &Value,
+  matching_context: &(dyn MatchingContext + Send + Sync)
+) -> Value {
+  match json {
+    Value::Object(map) => Value::Object(map.iter()
+      .map(|(key, value)| (key.clone(), whole_numbers_to_integers(&path.join(key), value, matching_context)))
+      .collect()),
+    Value::Array(values) => Value::Array(values.iter().enumerate()
+      .map(|(index, value)| whole_numbers_to_integers(&path.join_index(index), value, matching_context))
+      .collect()),
+    Value::Number(n) => match n.as_f64() {
+      Some(f) if n.is_f64() && f.fract() == 0.0 && f.abs() <= MAX_SAFE_INTEGER &&
+        matching_context.matcher_is_defined(path) &&
+        matching_context.select_best_matcher(path).rules.contains(&MatchingRule::Integer) => json!(f as i64),
+      _ => json.clone()
+    },
+    _ => json.clone()
+  }
+}
+
+/// Returns the paths from a decoded google.protobuf.FieldMask message as a set
+fn field_mask_paths(fields: &[ProtobufField]) -> BTreeSet<String> {
+  fields.iter()
+    .filter(|field| field.field_name == "paths")
+    .filter_map(|field| match &field.data {
+      ProtobufFieldData::String(s) => Some(s.clone()),
+      _ => None
+    })
+    .collect()
+}
+
+/// If the rules are for a case-insensitive equality match against the expected value
+fn is_equals_ignore_case_rule(rules: &RuleList, expected: &str) -> bool {
+  let rule = equals_ignore_case_rule(expected);
+  rules.rules.len() == 1 && rules.rules.contains(&rule)
+}
+
+/// Compares the actual string value to the expected one, ignoring case. This uses the same regex
+/// that is recorded in the Pact file, so the values are compared the same way by other Pact
+/// implementations.
+fn compare_ignoring_case(path: &DocPath, expected: &str, actual: &str) -> Vec<Mismatch> {
+  debug!("compare_ignoring_case: Case-insensitive matcher defined for path '{}'", path);
+  let matches = regex::Regex::new(equals_ignore_case_regex(expected).as_str())
+    .map(|regex| regex.is_match(actual))
+    .unwrap_or_default();
+  if matches {
+    vec![]
+  } else {
+    vec![BodyMismatch {
+      path: path.to_string(),
+      expected: Some(expected.as_bytes().to_vec().into()),
+      actual: Some(actual.as_bytes().to_vec().into()),
+      mismatch: format!("Expected '{}' to be equal to '{}' ignoring case", actual, expected)
+    }]
+  }
+}
+
 /// Compare a repeated field
 #[tracing::instrument(ret, skip_all, fields(%path, expected = expected_fields.len(), actual = actual_fields.len()))]
 fn compare_repeated_field(
@@ -572,7 +1325,8 @@ fn compare_repeated_field(
   expected_fields: &[ProtobufField],
   actual_fields: &[ProtobufField],
   matching_context: &(dyn MatchingContext + Send + Sync),
-  descriptors: &FileDescriptorSet
+  descriptors: &FileDescriptorSet,
+  fail_fast: bool
 ) -> Vec<Mismatch> {
   trace!(">>> compare_repeated_field({}, {:?}, {:?})", path, expected_fields, actual_fields);
 
@@ -581,10 +1335,13 @@ fn compare_repeated_field(
   if matching_context.matcher_is_defined(path) {
     debug!("compare_repeated_field: Matcher defined for path '{}'", path);
     let rules = matching_context.select_best_matcher(path);
+    result.extend(compare_repeated_field_length(path, descriptor, &rules, actual_fields.len()));
     for matcher in &rules.rules {
+      // The length of the field has already been checked, so we only need to compare the values
+      let matcher = if is_length_matcher(matcher) { &MatchingRule::Type } else { matcher };
       if let Err(comparison) = compare_lists_with_matchingrule(matcher, path,
         expected_fields, actual_fields, matching_context, rules.cascaded, &mut |field_path, expected, actual, context| {
-          let comparison = compare_field(field_path, expected, descriptor, actual, context, descriptors);
+          let comparison = compare_field(field_path, expected, descriptor, actual, context, descriptors, fail_fast);
           if comparison.is_empty() {
             Ok(())
           } else {
@@ -594,6 +1351,31 @@ fn compare_repeated_field(
         result.extend(comparison.iter().map(CommonMismatch::to_body_mismatch));
       }
     }
+  } else if let Some(key_field) = repeated_field_key(path, descriptor, matching_context) {
+    debug!("compare_repeated_field: Pairing the values of '{}' using the key field '{}'", path, key_field);
+    result.extend(compare_keyed_repeated_field(path, descriptor, key_field.as_str(), expected_fields,
+      actual_fields, matching_context, descriptors, fail_fast));
+  } else if let Some(mode) = repeated_field_matching(path, matching_context) {
+    debug!("compare_repeated_field: Comparing the values of '{}' using {} matching", path, mode);
+    if mode == "subset" {
+      result.extend(compare_repeated_field_subset(path, descriptor, expected_fields, actual_fields,
+        matching_context, descriptors, fail_fast));
+    } else {
+      // Only the expected values are compared, any additional actual values are ignored
+      result.extend(compare_list_content(path, descriptor, expected_fields, actual_fields, matching_context,
+        descriptors, fail_fast));
+    }
+  } else if !expected_fields.is_empty() && matching_context.matcher_is_defined(&path.join("*")) {
+    debug!("compare_repeated_field: Matcher defined for all the values of '{}'", path);
+    // The matcher applies to every value, so compare each actual value against the expected example
+    let expected = &expected_fields[0];
+    for (index, actual) in actual_fields.iter().enumerate() {
+      if fail_fast && !result.is_empty() {
+        break;
+      }
+      result.extend(compare_field(&path.join(index.to_string()), expected, descriptor, actual,
+        matching_context, descriptors, fail_fast));
+    }
   } else if expected_fields.is_empty() && !actual_fields.is_empty() {
     debug!("Expected an empty list, but actual has {} field(s)", actual_fields.len());
     result.push(Mismatch::BodyMismatch {
@@ -607,7 +1389,8 @@ fn compare_repeated_field(
     })
   } else {
     trace!("Comparing repeated fields as a list");
-    result.extend(compare_list_content(path, descriptor, expected_fields, actual_fields, matching_context, descriptors));
+    result.extend(compare_list_content(path, descriptor, expected_fields, actual_fields, matching_context,
+      descriptors, fail_fast));
     if expected_fields.len() != actual_fields.len() {
       result.push(Mismatch::BodyMismatch {
         path: path.to_string(),
@@ -627,15 +1410,498 @@ fn compare_repeated_field(
   result
 }
 
-/// Compare a map field
-#[instrument(ret, skip_all, fields(%path))]
-fn compare_map_field(
-  path: &DocPath,
-  descriptor: &FieldDescriptorProto,
-  expected_fields: Vec<&ProtobufField>,
-  actual_fields: Vec<&ProtobufField>,
-  matching_context: &(dyn MatchingContext + Send + Sync),
-  descriptors: &FileDescriptorSet
+/// Returns the paths of the fields that have been configured to be ignored with the `ignoreFields`
+/// option. The configured paths can contain wildcards (i.e. `$.*.created_at` or `$.items[*].created_at`).
+fn ignored_field_paths(matching_context: &(dyn MatchingContext + Send + Sync)) -> Vec<DocPath> {
+  matching_context.plugin_configuration().get("protobuf")
+    .and_then(|config| config.interaction_configuration.get("ignoreFields"))
+    .and_then(|paths| paths.as_array())
+    .map(|paths| paths.iter()
+      .filter_map(|ignored_path| ignored_path.as_str())
+      .filter_map(|ignored_path| match DocPath::new(ignored_path) {
+        Ok(path) => Some(path),
+        Err(err) => {
+          warn!("Ignoring invalid path '{}' in the ignoreFields configuration - {}", ignored_path, err);
+          None
+        }
+      })
+      .collect())
+    .unwrap_or_default()
+}
+
+/// If the field at the given path matches one of the ignored field paths
+fn ignored_field(path: &DocPath, ignored_paths: &[DocPath]) -> bool {
+  if ignored_paths.is_empty() {
+    return false;
+  }
+  let path_tokens = path.to_vec();
+  let path_tokens = path_tokens.iter().map(|token| token.as_str()).collect_vec();
+  ignored_paths.iter().any(|ignored_path| ignored_path.matches_path_exactly(&path_tokens))
+}
+
+/// Returns the tolerance to use when comparing timestamp fields, if one has been configured for
+/// the path with the `timestampTolerance` option. The tolerance is a number of seconds. The paths
+/// can contain wildcards (i.e. `$.events[*].created` or `$.events.*`), and the values of a repeated
+/// field use the tolerance for the field if there is not one for their index.
+fn timestamp_tolerance(
+  path: &DocPath,
+  matching_context: &(dyn MatchingContext + Send + Sync)
+) -> Option<chrono::Duration> {
+  interaction_config_for_field_value("timestampTolerance", path, matching_context)
+    .and_then(|tolerance| match tolerance {
+      Value::Number(n) => n.as_f64(),
+      Value::String(s) => s.parse().ok(),
+      _ => None
+    })
+    .filter(|seconds| *seconds >= 0.0)
+    .map(|seconds| chrono::Duration::milliseconds((seconds * 1000.0) as i64))
+}
+
+/// Returns the bounds of the number range matcher configured for the path (with `range(min, max)`),
+/// which are stored in the interaction configuration as `numberRanges`. For service interactions,
+/// the ranges for the part being compared are selected with `interaction_config_for_part`. The
+/// values of a repeated field use the range for their index, or the range for the field if there
+/// is not one.
+fn number_range(
+  path: &DocPath,
+  matching_context: &(dyn MatchingContext + Send + Sync)
+) -> Option<NumberRange> {
+  interaction_config_for_field_value("numberRanges", path, matching_context)
+    .and_then(|range| NumberRange::from_json(&range))
+}
+
+/// Returns the interaction configuration to use when comparing a part (`request` or `response`) of
+/// a service interaction. Options that are stored separately for each part (like `numberRanges`)
+/// are replaced with the values for that part.
+pub(crate) fn interaction_config_for_part(
+  interaction_config: &HashMap<String, Value>,
+  part: &str
+) -> HashMap<String, Value> {
+  let mut config = interaction_config.clone();
+  if let Some(ranges) = interaction_config.get("numberRanges") {
+    match ranges.get(part) {
+      Some(part_ranges) => config.insert("numberRanges".to_string(), part_ranges.clone()),
+      None => config.remove("numberRanges")
+    };
+  }
+  config
+}
+
+/// Returns true if the interaction has been configured with the `failFast` option, in which case the
+/// comparison stops at the first mismatch found.
+pub(crate) fn fail_fast(interaction_config: &HashMap<String, Value>) -> bool {
+  interaction_config.get("failFast")
+    .and_then(|value| value.as_bool())
+    .unwrap_or_default()
+}
+
+/// Returns the value of a `google.protobuf.Timestamp` or RFC3339 formatted string field as a date-time
+fn timestamp_value(
+  field: &ProtobufField,
+  descriptors: &FileDescriptorSet
+) -> Option<DateTime<Utc>> {
+  match &field.data {
+    ProtobufFieldData::String(s) => DateTime::parse_from_rfc3339(s).ok()
+      .map(|dt| dt.with_timezone(&Utc)),
+    ProtobufFieldData::Message(bytes, descriptor) if field.descriptor.type_name() == ".google.protobuf.Timestamp" => {
+      let fields = decode_message(&mut Bytes::copy_from_slice(bytes), descriptor, descriptors).ok()?;
+      let seconds = match find_message_field_by_name(descriptor, fields.clone(), "seconds").map(|f| f.data) {
+        Some(ProtobufFieldData::Integer64(seconds)) => seconds,
+        _ => 0
+      };
+      let nanos = match find_message_field_by_name(descriptor, fields, "nanos").map(|f| f.data) {
+        Some(ProtobufFieldData::Integer32(nanos)) => nanos as u32,
+        _ => 0
+      };
+      DateTime::from_timestamp(seconds, nanos)
+    }
+    _ => None
+  }
+}
+
+/// Compare a duration field. The actual value must be a valid duration, and the durations are
+/// compared using their ISO-8601 form (i.e. `PT1.5S`), which any matching rules are applied to.
+fn compare_durations(
+  path: &DocPath,
+  field: &ProtobufField,
+  actual: &ProtobufField,
+  expected_seconds: i64,
+  expected_nanos: i32,
+  matching_context: &(dyn MatchingContext + Send + Sync)
+) -> Vec<Mismatch> {
+  let expected = format_iso8601_duration(expected_seconds, expected_nanos);
+  match actual.data {
+    ProtobufFieldData::Duration(seconds, nanos) if is_valid_duration(seconds, nanos) => {
+      let actual = format_iso8601_duration(seconds, nanos);
+      compare_value(path, field, &expected, &actual, expected.as_str(), actual.as_str(), matching_context)
+    }
+    ProtobufFieldData::Duration(seconds, nanos) => vec![
+      BodyMismatch {
+        path: path.to_string(),
+        expected: Some(expected.clone().into()),
+        actual: Some(actual.data.to_string().into()),
+        mismatch: format!("Expected a valid duration, but got {} second(s) and {} nanosecond(s)", seconds, nanos)
+      }
+    ],
+    _ => vec![
+      BodyMismatch {
+        path: path.to_string(),
+        expected: Some(expected.clone().into()),
+        actual: Some(actual.data.to_string().into()),
+        mismatch: format!("Expected a duration value like {}, but got {}", expected, actual.data)
+      }
+    ]
+  }
+}
+
+/// Compare a timestamp field, allowing the actual value to be within the tolerance of the expected value
+fn compare_timestamps(
+  path: &DocPath,
+  field: &ProtobufField,
+  actual: &ProtobufField,
+  expected: DateTime<Utc>,
+  tolerance: chrono::Duration,
+  descriptors: &FileDescriptorSet
+) -> Vec<Mismatch> {
+  match timestamp_value(actual, descriptors) {
+    Some(actual_value) => if (actual_value - expected).abs() <= tolerance {
+      vec![]
+    } else {
+      vec![
+        BodyMismatch {
+          path: path.to_string(),
+          expected: Some(expected.to_rfc3339().into()),
+          actual: Some(actual_value.to_rfc3339().into()),
+          mismatch: format!("Expected timestamp {} to be within {} second(s) of {}",
+            actual_value.to_rfc3339(), tolerance.num_milliseconds() as f64 / 1000.0, expected.to_rfc3339())
+        }
+      ]
+    }
+    None => vec![
+      BodyMismatch {
+        path: path.to_string(),
+        expected: Some(field.data.to_string().into()),
+        actual: Some(actual.data.to_string().into()),
+        mismatch: format!("Expected a timestamp value within {} second(s) of {}, but got {}",
+          tolerance.num_milliseconds() as f64 / 1000.0, expected.to_rfc3339(), actual.data)
+      }
+    ]
+  }
+}
+
+/// Returns the name of the field used to pair the values of a repeated message field, if one has
+/// been configured for the path with the `repeatedFieldKeys` option
+fn repeated_field_key(
+  path: &DocPath,
+  descriptor: &FieldDescriptorProto,
+  matching_context: &(dyn MatchingContext + Send + Sync)
+) -> Option<String> {
+  if descriptor.r#type() != Type::Message {
+    return None;
+  }
+  interaction_config_for_path("repeatedFieldKeys", path, matching_context)
+    .and_then(|key| key.as_str().map(|key| key.to_string()))
+}
+
+/// Compare the values of a repeated message field by pairing each expected value with the actual
+/// value that has the same key field value, regardless of the order. Values that do not have the
+/// key field set, or that have the same key as another value, are reported as mismatches.
+#[allow(clippy::too_many_arguments)]
+fn compare_keyed_repeated_field(
+  path: &DocPath,
+  descriptor: &FieldDescriptorProto,
+  key_field: &str,
+  expected_fields: &[ProtobufField],
+  actual_fields: &[ProtobufField],
+  matching_context: &(dyn MatchingContext + Send + Sync),
+  descriptors: &FileDescriptorSet,
+  fail_fast: bool
+) -> Vec<Mismatch> {
+  let expected_values = keyed_values(expected_fields, key_field, descriptors);
+  let actual_values = keyed_values(actual_fields, key_field, descriptors);
+  let mut result = vec![];
+
+  for (index, (key, expected)) in expected_values.iter().enumerate() {
+    if fail_fast && !result.is_empty() {
+      break;
+    }
+    let item_path = path.join(index.to_string());
+    let Some(key) = key else {
+      result.push(BodyMismatch {
+        path: item_path.to_string(),
+        expected: None,
+        actual: None,
+        mismatch: format!("Expected value {} of repeated field '{}' does not have the key field '{}' set",
+          index, descriptor.name(), key_field)
+      });
+      continue;
+    };
+    if expected_values[..index].iter().any(|(other_key, _)| other_key.as_ref() == Some(key)) {
+      result.push(BodyMismatch {
+        path: item_path.to_string(),
+        expected: Some(Bytes::from(key.clone())),
+        actual: None,
+        mismatch: format!("Expected repeated field '{}' has more than one value with {} '{}'",
+          descriptor.name(), key_field, key)
+      });
+      continue;
+    }
+    match actual_values.iter().find(|(actual_key, _)| actual_key.as_ref() == Some(key)) {
+      Some((_, actual)) => result.extend(compare_field(&item_path, expected, descriptor,
+        actual, matching_context, descriptors, fail_fast)),
+      None => result.push(BodyMismatch {
+        path: path.to_string(),
+        expected: Some(Bytes::from(key.clone())),
+        actual: None,
+        mismatch: format!("Expected repeated field '{}' to have a value with {} '{}' but it was not received",
+          descriptor.name(), key_field, key)
+      })
+    }
+  }
+
+  for (index, (key, _)) in actual_values.iter().enumerate() {
+    if fail_fast && !result.is_empty() {
+      break;
+    }
+    match key {
+      None => result.push(BodyMismatch {
+        path: path.join(index.to_string()).to_string(),
+        expected: None,
+        actual: None,
+        mismatch: format!("Received a value for repeated field '{}' that does not have the key field '{}' set",
+          descriptor.name(), key_field)
+      }),
+      Some(key) if actual_values[..index].iter().any(|(other_key, _)| other_key.as_ref() == Some(key)) => {
+        result.push(BodyMismatch {
+          path: path.join(index.to_string()).to_string(),
+          expected: None,
+          actual: Some(Bytes::from(key.clone())),
+          mismatch: format!("Received more than one value with {} '{}' for repeated field '{}'",
+            key_field, key, descriptor.name())
+        });
+      }
+      Some(key) if !expected_values.iter().any(|(expected_key, _)| expected_key.as_ref() == Some(key)) => {
+        result.push(BodyMismatch {
+          path: path.to_string(),
+          expected: None,
+          actual: Some(Bytes::from(key.clone())),
+          mismatch: format!("Received an unexpected value with {} '{}' for repeated field '{}'",
+            key_field, key, descriptor.name())
+        });
+      }
+      _ => {}
+    }
+  }
+
+  result
+}
+
+/// Returns how the values of the repeated field should be matched (either `prefix` or `subset`), if
+/// it has been configured for the path with the `repeatedFieldMatching` option
+fn repeated_field_matching(
+  path: &DocPath,
+  matching_context: &(dyn MatchingContext + Send + Sync)
+) -> Option<String> {
+  interaction_config_for_path("repeatedFieldMatching", path, matching_context)
+    .and_then(|mode| mode.as_str().map(|mode| mode.to_string()))
+    .filter(|mode| mode == "prefix" || mode == "subset")
+}
+
+/// Returns the value configured for the field path in the option from the interaction configuration.
+/// The option is a map of field paths to values, and the paths can contain wildcards (i.e.
+/// `$.items[*].values` or `$.items.*.values`). A value configured for the exact path is preferred.
+fn interaction_config_for_path(
+  option: &str,
+  path: &DocPath,
+  matching_context: &(dyn MatchingContext + Send + Sync)
+) -> Option<Value> {
+  let values = matching_context.plugin_configuration().get("protobuf")
+    .and_then(|config| config.interaction_configuration.get(option))
+    .and_then(|values| values.as_object())?;
+  values.get(&path.to_string())
+    .or_else(|| {
+      let path_tokens = path.to_vec();
+      let path_tokens = path_tokens.iter().map(|token| token.as_str()).collect_vec();
+      values.iter()
+        .filter_map(|(config_path, value)| DocPath::new(config_path).ok().map(|config_path| (config_path, value)))
+        .filter(|(config_path, _)| config_path.matches_path_exactly(&path_tokens))
+        .max_by_key(|(config_path, _)| config_path.path_weight(&path_tokens).0)
+        .map(|(_, value)| value)
+    })
+    .cloned()
+}
+
+/// Returns the value configured for the field path in the option, the same as
+/// `interaction_config_for_path`, except that the values of a repeated field will use the value
+/// configured for the field if there is not one for their index.
+fn interaction_config_for_field_value(
+  option: &str,
+  path: &DocPath,
+  matching_context: &(dyn MatchingContext + Send + Sync)
+) -> Option<Value> {
+  interaction_config_for_path(option, path, matching_context)
+    .or_else(|| match path.tokens().last() {
+      Some(PathToken::Index(_)) => path.parent()
+        .and_then(|parent| interaction_config_for_path(option, &parent, matching_context)),
+      _ => None
+    })
+}
+
+/// Compare the values of a repeated field, where each expected value must match a different actual
+/// value, regardless of the order. Any additional actual values are ignored. As an actual value can
+/// match more than one expected value, the values are paired using a maximum bipartite matching.
+#[allow(clippy::too_many_arguments)]
+fn compare_repeated_field_subset(
+  path: &DocPath,
+  descriptor: &FieldDescriptorProto,
+  expected_fields: &[ProtobufField],
+  actual_fields: &[ProtobufField],
+  matching_context: &(dyn MatchingContext + Send + Sync),
+  descriptors: &FileDescriptorSet,
+  fail_fast: bool
+) -> Vec<Mismatch> {
+  let candidates = expected_fields.iter().enumerate()
+    .map(|(index, expected)| {
+      let item_path = path.join(index.to_string());
+      actual_fields.iter().enumerate()
+        .filter(|(_, actual)| compare_field(&item_path, expected, descriptor, actual, matching_context,
+          descriptors, true).is_empty())
+        .map(|(actual_index, _)| actual_index)
+        .collect_vec()
+    })
+    .collect_vec();
+
+  // Index of the expected value each actual value is paired with
+  let mut pairs = vec![None; actual_fields.len()];
+  let mut result = vec![];
+  for (index, expected) in expected_fields.iter().enumerate() {
+    if fail_fast && !result.is_empty() {
+      break;
+    }
+    if !pair_repeated_value(index, &candidates, &mut vec![false; actual_fields.len()], &mut pairs) {
+      result.push(BodyMismatch {
+        path: path.join(index.to_string()).to_string(),
+        expected: Some(Bytes::from(expected.data.to_string())),
+        actual: None,
+        mismatch: format!("Expected repeated field '{}' to have a value matching {} but none of the received values matched",
+          descriptor.name(), expected.data)
+      });
+    }
+  }
+
+  result
+}
+
+/// Tries to pair the expected value with one of its candidate actual values, moving any expected
+/// value already paired with a candidate to one of its other candidates (an augmenting path).
+fn pair_repeated_value(
+  expected: usize,
+  candidates: &[Vec<usize>],
+  visited: &mut [bool],
+  pairs: &mut [Option<usize>]
+) -> bool {
+  for &actual in &candidates[expected] {
+    if !visited[actual] {
+      visited[actual] = true;
+      if pairs[actual].is_none_or(|other| pair_repeated_value(other, candidates, visited, pairs)) {
+        pairs[actual] = Some(expected);
+        return true;
+      }
+    }
+  }
+  false
+}
+
+/// If there is a matcher defined for the values of the map (i.e. `$.map.*`), or for the fields of
+/// the values if they are messages
+fn map_value_matcher_is_defined(
+  path: &DocPath,
+  matching_context: &(dyn MatchingContext + Send + Sync)
+) -> bool {
+  let values_path = path.join("*");
+  matching_context.matcher_is_defined(&values_path) || matching_context.matchers().rules.keys()
+    .any(|rule_path| rule_path.tokens().starts_with(values_path.tokens()))
+}
+
+/// Returns the values of the repeated message field along with the value of their key field. The
+/// key is the default value of the field if it is not set and the field does not have explicit
+/// presence, and is `None` if the value does not have the key field.
+fn keyed_values<'a>(
+  fields: &'a [ProtobufField],
+  key_field: &str,
+  descriptors: &FileDescriptorSet
+) -> Vec<(Option<String>, &'a ProtobufField)> {
+  fields.iter()
+    .map(|field| {
+      let key = match &field.data {
+        ProtobufFieldData::Message(bytes, message_descriptor) => {
+          let mut bytes = Bytes::copy_from_slice(bytes);
+          decode_message_with_defaults(&mut bytes, message_descriptor, descriptors).ok()
+            .and_then(|message_fields| message_fields.iter()
+              .find(|message_field| message_field.field_name == key_field)
+              .map(|message_field| match &message_field.data {
+                ProtobufFieldData::String(s) => s.clone(),
+                data => data.to_string()
+              }))
+        }
+        _ => None
+      };
+      (key, field)
+    })
+    .collect()
+}
+
+/// If the matching rule is a min or max length matcher
+fn is_length_matcher(rule: &MatchingRule) -> bool {
+  matches!(rule, MatchingRule::MinType(_) | MatchingRule::MaxType(_) | MatchingRule::MinMaxType(_, _))
+}
+
+/// Checks the number of values of a repeated field against any min or max length matchers
+fn compare_repeated_field_length(
+  path: &DocPath,
+  descriptor: &FieldDescriptorProto,
+  rules: &RuleList,
+  actual_len: usize
+) -> Vec<Mismatch> {
+  let field_name = descriptor.name.clone().unwrap_or_else(|| descriptor.number.unwrap_or_default().to_string());
+  rules.rules.iter()
+    .filter_map(|rule| {
+      let (min, max) = match rule {
+        MatchingRule::MinType(min) => (Some(*min), None),
+        MatchingRule::MaxType(max) => (None, Some(*max)),
+        MatchingRule::MinMaxType(min, max) => (Some(*min), Some(*max)),
+        _ => (None, None)
+      };
+      if min.map(|min| actual_len < min).unwrap_or(false) {
+        Some(format!("Expected repeated field '{}' to have at least {} value(s) but received {}",
+          field_name, min.unwrap_or_default(), actual_len))
+      } else if max.map(|max| actual_len > max).unwrap_or(false) {
+        Some(format!("Expected repeated field '{}' to have at most {} value(s) but received {}",
+          field_name, max.unwrap_or_default(), actual_len))
+      } else {
+        None
+      }
+    })
+    .map(|mismatch| Mismatch::BodyMismatch {
+      path: path.to_string(),
+      expected: None,
+      actual: None,
+      mismatch
+    })
+    .collect()
+}
+
+/// Compare a map field. Map entries are paired by key, so the order they are received in does
+/// not matter. If a key is repeated, the last entry for the key is used.
+#[instrument(ret, skip_all, fields(%path))]
+fn compare_map_field(
+  path: &DocPath,
+  descriptor: &FieldDescriptorProto,
+  expected_fields: Vec<&ProtobufField>,
+  actual_fields: Vec<&ProtobufField>,
+  matching_context: &(dyn MatchingContext + Send + Sync),
+  descriptors: &FileDescriptorSet,
+  fail_fast: bool
 ) -> Vec<Mismatch> {
   trace!(">> compare_map_field('{}', {:?}, {:?})", path, expected_fields, actual_fields);
 
@@ -665,7 +1931,8 @@ fn compare_map_field(
       trace!("compare_map_field: matcher = {:?}", matcher);
       if let Err(comparison) = compare_maps_with_matchingrule(matcher, rules.cascaded, path,
         &expected_map, &actual_map, matching_context, &mut |field_path, expected, actual, context| {
-          let field_result = compare_field(field_path, &expected.value, &expected.field_descriptor, &actual.value, context, descriptors);
+          let field_result = compare_field(field_path, &expected.value, &expected.field_descriptor, &actual.value,
+            context, descriptors, fail_fast);
           if field_result.is_empty() {
             Ok(())
           } else {
@@ -675,6 +1942,23 @@ fn compare_map_field(
         result.extend(comparison.iter().map(CommonMismatch::to_body_mismatch));
       }
     }
+
+    // With an eachKey matcher, the keys can be different to the expected ones, so any entries
+    // without an expected key are compared against the first expected value. This is only done
+    // if there are matchers for the values, otherwise the values would have to equal the example.
+    if rules.rules.iter().any(|rule| matches!(rule, MatchingRule::EachKey(_)))
+      && !rules.rules.iter().any(|rule| rule.is_values_matcher())
+      && map_value_matcher_is_defined(path, matching_context) {
+      if let Some(expected) = expected_map.values().next() {
+        for (key, actual) in actual_map.iter().filter(|(key, _)| !expected_map.contains_key(*key)) {
+          if fail_fast && !result.is_empty() {
+            break;
+          }
+          result.extend(compare_field(&path.join(key), &expected.value, &expected.field_descriptor,
+            &actual.value, matching_context, descriptors, fail_fast));
+        }
+      }
+    }
   } else {
     debug!("compare_map_field: no matcher defined for path '{}'", path);
     debug!("                   expected keys {:?}", expected_map.keys());
@@ -696,9 +1980,13 @@ fn compare_map_field(
         result.extend(mismatches.iter().map(CommonMismatch::to_body_mismatch));
       }
       for (key, value) in &expected_map {
+        if fail_fast && !result.is_empty() {
+          break;
+        }
         let entry_path = path.join(key);
         if let Some(actual) = actual_map.get(key.as_str()) {
-          result.extend(compare_field(&entry_path, &value.value, &value.field_descriptor, &actual.value, matching_context, descriptors));
+          result.extend(compare_field(&entry_path, &value.value, &value.field_descriptor, &actual.value,
+            matching_context, descriptors, fail_fast));
         } else {
           result.push(BodyMismatch {
             path: path.to_string(),
@@ -758,15 +2046,20 @@ fn compare_list_content(
   expected: &[ProtobufField],
   actual: &[ProtobufField],
   matching_context: &(dyn MatchingContext + Send + Sync),
-  descriptors: &FileDescriptorSet
+  descriptors: &FileDescriptorSet,
+  fail_fast: bool
 ) -> Vec<Mismatch> {
   let mut result = vec![];
   for (index, value) in expected.iter().enumerate() {
+    if fail_fast && !result.is_empty() {
+      break;
+    }
     let ps = index.to_string();
     debug!("Comparing list item {} with value '{:?}' to '{:?}'", index, actual.get(index), value);
     let p = path.join(ps);
     if index < actual.len() {
-      result.extend(compare_field(&p, value, descriptor, actual.get(index).unwrap(), matching_context, descriptors));
+      result.extend(compare_field(&p, value, descriptor, actual.get(index).unwrap(), matching_context,
+        descriptors, fail_fast));
     } else if !matching_context.matcher_is_defined(&p) {
       result.push(Mismatch::BodyMismatch {
         path: path.to_string(),
@@ -782,7 +2075,7 @@ fn compare_list_content(
 /// Find the field descriptor in the message descriptor for the given field value
 fn find_field_descriptor(field: &ProtobufField, descriptor: &DescriptorProto) -> Option<FieldDescriptorProto> {
   descriptor.field.iter()
-    .find(|field_desc| field_desc.number.unwrap_or_default() == field.field_num as i32)
+    .find(|field_desc| field_number(field_desc) == Some(field.field_num))
     .cloned()
 }
 
@@ -795,11 +2088,14 @@ mod tests {
   use pact_models::{matchingrules, matchingrules_list};
   use prost::encoding::WireType;
   use prost::Message;
-  use prost_types::{DescriptorProto, EnumDescriptorProto, EnumValueDescriptorProto, FieldDescriptorProto, FileDescriptorSet, MessageOptions};
+  use prost_types::{DescriptorProto, EnumDescriptorProto, EnumValueDescriptorProto, FieldDescriptorProto, FileDescriptorProto, FileDescriptorSet, MessageOptions};
   use prost_types::field_descriptor_proto::Label;
   use prost_types::field_descriptor_proto::Label::{Optional, Repeated};
   use prost_types::field_descriptor_proto::Type::{Enum, String};
+  use serde_json::json;
 
+  use crate::editions::decode_file_descriptor_set;
+  use crate::editions::tests::EDITIONS_DESCRIPTORS;
   use crate::message_decoder::ProtobufField;
   use crate::utils::find_enum_by_name;
 
@@ -1071,6 +2367,7 @@ mod tests {
       &context,
       &message_descriptor,
       &fds,
+      false
     ).unwrap();
 
     expect!(result).to(be_equal_to(BodyMatchResult::Ok));
@@ -1134,6 +2431,7 @@ mod tests {
       &context,
       &message_descriptor,
       &fds,
+      false
     ).unwrap();
 
     expect!(result).to(be_equal_to(BodyMatchResult::Ok));
@@ -1210,6 +2508,7 @@ mod tests {
       &context,
       &message_descriptor,
       &fds,
+      false
     ).unwrap();
 
     expect!(result).to(be_equal_to(BodyMatchResult::Ok));
@@ -1289,7 +2588,8 @@ mod tests {
       &[],
       &context,
       &expected_bytes,
-      &fds
+      &fds,
+      false
     ).unwrap();
     expect!(result).to(be_equal_to(BodyMatchResult::Ok));
 
@@ -1309,7 +2609,8 @@ mod tests {
       actual,
       &context,
       &expected_bytes,
-      &fds
+      &fds,
+      false
     ).unwrap();
     expect!(result).to(be_equal_to(BodyMatchResult::Ok));
   }
@@ -1397,7 +2698,8 @@ mod tests {
       actual,
       &context,
       &expected_bytes,
-      &fds
+      &fds,
+      false
     ).unwrap();
     expect!(result).to(be_equal_to(BodyMatchResult::Ok));
 
@@ -1425,7 +2727,8 @@ mod tests {
       actual,
       &context,
       &expected_bytes,
-      &fds
+      &fds,
+      false
     ).unwrap();
     expect!(result).to_not(be_equal_to(BodyMatchResult::Ok));
   }
@@ -1483,4 +2786,2283 @@ mod tests {
     };
     expect!(should_use_default(&field)).to(be_false());
   }
+
+  fn string_field(field_num: u32, name: &str, value: &str, descriptor: &FieldDescriptorProto) -> ProtobufField {
+    ProtobufField {
+      field_num,
+      field_name: name.to_string(),
+      wire_type: WireType::LengthDelimited,
+      data: ProtobufFieldData::String(value.to_string()),
+      additional_data: vec![],
+      descriptor: descriptor.clone()
+    }
+  }
+
+  #[test_log::test]
+  fn compare_message_in_fail_fast_mode_only_returns_the_first_mismatch() {
+    let fields = ["a", "b", "c"].iter().enumerate()
+      .map(|(index, name)| FieldDescriptorProto {
+        name: Some(name.to_string()),
+        number: Some(index as i32 + 1),
+        label: Some(Optional as i32),
+        r#type: Some(String as i32),
+        .. FieldDescriptorProto::default()
+      })
+      .collect_vec();
+    let message_descriptor = DescriptorProto {
+      name: Some("Test".to_string()),
+      field: fields.clone(),
+      .. DescriptorProto::default()
+    };
+    let fds = FileDescriptorSet { file: vec![] };
+    let context = CoreMatchingContext::new(DiffConfig::NoUnexpectedKeys,
+      &MatchingRuleCategory::empty("body"), &hashmap!{});
+
+    let expected = vec![
+      string_field(1, "a", "one", &fields[0]),
+      string_field(2, "b", "two", &fields[1]),
+      string_field(3, "c", "three", &fields[2])
+    ];
+    let actual = vec![
+      string_field(1, "a", "uno", &fields[0]),
+      string_field(2, "b", "dos", &fields[1]),
+      string_field(3, "c", "tres", &fields[2])
+    ];
+
+    let result = compare_message(DocPath::root(), &expected, &actual, &context,
+      &message_descriptor, &fds, false).unwrap();
+    expect!(result.mismatches().len()).to(be_equal_to(3));
+
+    let result = compare_message(DocPath::root(), &expected, &actual, &context,
+      &message_descriptor, &fds, true).unwrap();
+    let mismatches = result.mismatches();
+    expect!(mismatches.len()).to(be_equal_to(1));
+    expect!(mismatches[0].description()).to(be_equal_to("$.a -> Expected 'uno' to be equal to 'one'"));
+  }
+
+  #[test_log::test]
+  fn match_message_with_a_different_oneof_member_set() {
+    let fds = FileDescriptorSet::decode(crate::protobuf::tests::DESCRIPTOR_WITH_ENUM_BYTES.as_slice()).unwrap();
+
+    // ShapeMessage { square: Square { edge_length: 3.0 } }
+    let mut expected = Bytes::from_static(&[10, 5, 13, 0, 0, 64, 64]);
+    // ShapeMessage { rectangle: Rectangle { length: 3.0, width: 4.0 } }
+    let mut actual = Bytes::from_static(&[18, 10, 13, 0, 0, 64, 64, 21, 0, 0, 128, 64]);
+
+    let result = match_message(".area_calculator.ShapeMessage", &fds, &mut expected, &mut actual,
+      &MatchingRuleCategory::empty("body"), true, false, &hashmap!{}).unwrap();
+    expect!(result.mismatches()).to(be_equal_to(vec![
+      BodyMismatch {
+        path: "$.shape".to_string(),
+        expected: Some("square".into()),
+        actual: Some("rectangle".into()),
+        mismatch: "Expected oneof 'shape' to have field 'square' set, but field 'rectangle' was set".to_string()
+      }
+    ]));
+
+    let mut expected = Bytes::from_static(&[10, 5, 13, 0, 0, 64, 64]);
+    let mut actual = Bytes::from_static(&[10, 5, 13, 0, 0, 64, 64]);
+    let result = match_message(".area_calculator.ShapeMessage", &fds, &mut expected, &mut actual,
+      &MatchingRuleCategory::empty("body"), true, false, &hashmap!{}).unwrap();
+    expect!(result).to(be_equal_to(BodyMatchResult::Ok));
+  }
+
+  #[test_log::test]
+  fn match_message_with_strict_enums_fails_for_undefined_enum_values() {
+    let fds = FileDescriptorSet {
+      file: vec![
+        FileDescriptorProto {
+          name: Some("test.proto".to_string()),
+          message_type: vec![
+            DescriptorProto {
+              name: Some("Test".to_string()),
+              field: vec![
+                FieldDescriptorProto {
+                  name: Some("status".to_string()),
+                  number: Some(1),
+                  label: Some(Optional as i32),
+                  r#type: Some(Enum as i32),
+                  type_name: Some(".Status".to_string()),
+                  .. FieldDescriptorProto::default()
+                }
+              ],
+              .. DescriptorProto::default()
+            }
+          ],
+          enum_type: vec![
+            EnumDescriptorProto {
+              name: Some("Status".to_string()),
+              value: vec![
+                EnumValueDescriptorProto { name: Some("UNKNOWN".to_string()), number: Some(0), options: None },
+                EnumValueDescriptorProto { name: Some("OK".to_string()), number: Some(1), options: None }
+              ],
+              .. EnumDescriptorProto::default()
+            }
+          ],
+          .. FileDescriptorProto::default()
+        }
+      ]
+    };
+    let matching_rules = matchingrules! {
+      "body" => { "$.status" => [ MatchingRule::Type ] }
+    }.rules_for_category("body").unwrap();
+
+    // Test { status: OK }
+    let mut expected = Bytes::from_static(&[8, 1]);
+    // Test { status: 5 }, which is not defined in the enum
+    let mut actual = Bytes::from_static(&[8, 5]);
+    let result = match_message(".Test", &fds, &mut expected.clone(), &mut actual.clone(),
+      &matching_rules, true, false, &hashmap!{}).unwrap();
+    expect!(result).to(be_equal_to(BodyMatchResult::Ok));
+
+    let result = match_message(".Test", &fds, &mut expected, &mut actual,
+      &matching_rules, true, false, &hashmap!{ "strictEnums".to_string() => json!(true) }).unwrap();
+    expect!(result.mismatches()).to(be_equal_to(vec![
+      BodyMismatch {
+        path: "$.status".to_string(),
+        expected: None,
+        actual: Some("5".into()),
+        mismatch: "Enum value 5 for field 'status' is not defined in enum Status".to_string()
+      }
+    ]));
+  }
+
+  #[rstest::rstest]
+  #[case::open_message(false, vec![])]
+  #[case::closed_message(true, vec!["Expected field 'note' to be missing, but received a value for it"])]
+  fn match_message_with_fields_that_were_not_expected(
+    #[case] closed: bool,
+    #[case] expected_mismatches: Vec<&str>
+  ) {
+    let string_field = |name: &str, number: i32| FieldDescriptorProto {
+      name: Some(name.to_string()),
+      number: Some(number),
+      label: Some(Optional as i32),
+      r#type: Some(String as i32),
+      .. FieldDescriptorProto::default()
+    };
+    let fds = FileDescriptorSet {
+      file: vec![
+        FileDescriptorProto {
+          name: Some("test.proto".to_string()),
+          message_type: vec![
+            DescriptorProto {
+              name: Some("Test".to_string()),
+              field: vec![ string_field("id", 1), string_field("note", 2) ],
+              .. DescriptorProto::default()
+            }
+          ],
+          .. FileDescriptorProto::default()
+        }
+      ]
+    };
+    let matching_rules = MatchingRuleCategory::empty("body");
+    let interaction_config = hashmap!{ "closedMessage".to_string() => json!(closed) };
+
+    // Test { id: "1" }
+    let mut expected = Bytes::from_static(&[10, 1, b'1']);
+    // Test { id: "1", note: "x" }
+    let mut actual = Bytes::from_static(&[10, 1, b'1', 18, 1, b'x']);
+    let result = match_message(".Test", &fds, &mut expected.clone(), &mut actual,
+      &matching_rules, true, false, &interaction_config).unwrap();
+    let mismatches = result.mismatches().iter()
+      .map(mismatch_message)
+      .collect::<Vec<_>>();
+    expect!(mismatches).to(be_equal_to(expected_mismatches.iter().map(|m| m.to_string()).collect::<Vec<_>>()));
+
+    // Test { id: "1", note: "" }, where the note has the default value
+    let mut actual = Bytes::from_static(&[10, 1, b'1', 18, 0]);
+    let result = match_message(".Test", &fds, &mut expected, &mut actual,
+      &matching_rules, true, false, &interaction_config).unwrap();
+    expect!(result).to(be_equal_to(BodyMatchResult::Ok));
+  }
+
+  #[test_log::test]
+  fn match_message_with_an_embedded_message_from_a_different_proto_file() {
+    let fds = FileDescriptorSet {
+      file: vec![
+        FileDescriptorProto {
+          name: Some("a.proto".to_string()),
+          package: Some("a".to_string()),
+          dependency: vec![ "b.proto".to_string() ],
+          message_type: vec![
+            DescriptorProto {
+              name: Some("Request".to_string()),
+              field: vec![
+                FieldDescriptorProto {
+                  name: Some("item".to_string()),
+                  number: Some(1),
+                  label: Some(Optional as i32),
+                  r#type: Some(Type::Message as i32),
+                  type_name: Some(".b.Item".to_string()),
+                  .. FieldDescriptorProto::default()
+                }
+              ],
+              .. DescriptorProto::default()
+            }
+          ],
+          .. FileDescriptorProto::default()
+        },
+        FileDescriptorProto {
+          name: Some("b.proto".to_string()),
+          package: Some("b".to_string()),
+          message_type: vec![
+            DescriptorProto {
+              name: Some("Item".to_string()),
+              field: vec![
+                FieldDescriptorProto {
+                  name: Some("name".to_string()),
+                  number: Some(1),
+                  label: Some(Optional as i32),
+                  r#type: Some(Type::String as i32),
+                  .. FieldDescriptorProto::default()
+                },
+                FieldDescriptorProto {
+                  name: Some("count".to_string()),
+                  number: Some(2),
+                  label: Some(Optional as i32),
+                  r#type: Some(Type::Int32 as i32),
+                  .. FieldDescriptorProto::default()
+                }
+              ],
+              .. DescriptorProto::default()
+            }
+          ],
+          .. FileDescriptorProto::default()
+        }
+      ]
+    };
+    let matching_rules = matchingrules! {
+      "body" => { "$.item.name" => [ MatchingRule::Type ] }
+    }.rules_for_category("body").unwrap();
+
+    // Request { item: { name: "x", count: 1 } }
+    let mut expected = Bytes::from_static(&[10, 5, 10, 1, 120, 16, 1]);
+    // Request { item: { name: "yz", count: 1 } }
+    let mut actual = Bytes::from_static(&[10, 6, 10, 2, 121, 122, 16, 1]);
+    let result = match_message(".a.Request", &fds, &mut expected.clone(), &mut actual,
+      &matching_rules, true, false, &hashmap!{}).unwrap();
+    expect!(result).to(be_equal_to(BodyMatchResult::Ok));
+
+    // Request { item: { name: "yz", count: 2 } }
+    let mut actual = Bytes::from_static(&[10, 6, 10, 2, 121, 122, 16, 2]);
+    let result = match_message(".a.Request", &fds, &mut expected, &mut actual,
+      &matching_rules, true, false, &hashmap!{}).unwrap();
+    expect!(result.mismatches()).to(be_equal_to(vec![
+      BodyMismatch {
+        path: "$.item.count".to_string(),
+        expected: Some("1".into()),
+        actual: Some("2".into()),
+        mismatch: "Expected 1 (Integer) to be equal to 2 (Integer)".to_string()
+      }
+    ]));
+  }
+
+  #[test_log::test]
+  fn compare_message_with_an_allowed_values_matcher() {
+    let string_descriptor = FieldDescriptorProto {
+      name: Some("status".to_string()),
+      number: Some(1),
+      label: Some(Optional as i32),
+      r#type: Some(String as i32),
+      .. FieldDescriptorProto::default()
+    };
+    let enum_descriptor = EnumDescriptorProto {
+      name: Some("Colour".to_string()),
+      value: ["RED", "GREEN", "BLUE"].iter().enumerate().map(|(i, name)| EnumValueDescriptorProto {
+        name: Some(name.to_string()),
+        number: Some(i as i32),
+        options: None
+      }).collect(),
+      .. EnumDescriptorProto::default()
+    };
+    let enum_field_descriptor = FieldDescriptorProto {
+      name: Some("colour".to_string()),
+      number: Some(2),
+      label: Some(Optional as i32),
+      r#type: Some(Type::Enum as i32),
+      type_name: Some(".Colour".to_string()),
+      .. FieldDescriptorProto::default()
+    };
+    let message_descriptor = DescriptorProto {
+      name: Some("Test".to_string()),
+      field: vec![string_descriptor.clone(), enum_field_descriptor.clone()],
+      .. DescriptorProto::default()
+    };
+    let fds = FileDescriptorSet { file: vec![] };
+    let rules = matchingrules_list! {
+      "body";
+      "$.status" => [ any_of_rule(&["PENDING".to_string(), "ACTIVE".to_string()]) ],
+      "$.colour" => [ any_of_rule(&["RED".to_string(), "GREEN".to_string()]) ]
+    };
+    let context = CoreMatchingContext::new(DiffConfig::NoUnexpectedKeys, &rules, &hashmap!{});
+    let message = |status: &str, colour: i32| vec![
+      string_field(1, "status", status, &string_descriptor),
+      ProtobufField {
+        field_num: 2,
+        field_name: "colour".to_string(),
+        wire_type: WireType::Varint,
+        data: ProtobufFieldData::Enum(colour, enum_descriptor.clone()),
+        additional_data: vec![],
+        descriptor: enum_field_descriptor.clone()
+      }
+    ];
+
+    let expected = message("PENDING", 0);
+    let actual = message("ACTIVE", 1);
+    let result = compare_message(DocPath::root(), &expected, &actual, &context,
+      &message_descriptor, &fds, false).unwrap();
+    expect!(result).to(be_equal_to(BodyMatchResult::Ok));
+
+    let actual = message("CANCELLED", 2);
+    let result = compare_message(DocPath::root(), &expected, &actual, &context,
+      &message_descriptor, &fds, false).unwrap();
+    let mismatches = result.mismatches().iter()
+      .map(mismatch_message)
+      .sorted()
+      .collect::<Vec<_>>();
+    expect!(mismatches).to(be_equal_to(vec![
+      "Expected 'BLUE' to be one of 'RED', 'GREEN'".to_string(),
+      "Expected 'CANCELLED' to be one of 'PENDING', 'ACTIVE'".to_string()
+    ]));
+  }
+
+  #[test_log::test]
+  fn compare_message_with_a_regex_matcher_on_an_enum_field() {
+    let enum_descriptor = EnumDescriptorProto {
+      name: Some("BreakType".to_string()),
+      value: ["UNKNOWN", "AUDIO_AD_BREAK", "VIDEO_AD_BREAK", "AUDIO_PROGRAM"].iter().enumerate()
+        .map(|(i, name)| EnumValueDescriptorProto {
+          name: Some(name.to_string()),
+          number: Some(i as i32),
+          options: None
+        })
+        .collect(),
+      .. EnumDescriptorProto::default()
+    };
+    let enum_field_descriptor = FieldDescriptorProto {
+      name: Some("type".to_string()),
+      number: Some(1),
+      label: Some(Optional as i32),
+      r#type: Some(Type::Enum as i32),
+      type_name: Some(".BreakType".to_string()),
+      .. FieldDescriptorProto::default()
+    };
+    let message_descriptor = DescriptorProto {
+      name: Some("Break".to_string()),
+      field: vec![enum_field_descriptor.clone()],
+      .. DescriptorProto::default()
+    };
+    let fds = FileDescriptorSet { file: vec![] };
+    let rules = matchingrules_list! {
+      "body";
+      "$.type" => [ MatchingRule::Regex(".*_AD_BREAK".to_string()) ]
+    };
+    let context = CoreMatchingContext::new(DiffConfig::NoUnexpectedKeys, &rules, &hashmap!{});
+    let message = |value: i32| vec![
+      ProtobufField {
+        field_num: 1,
+        field_name: "type".to_string(),
+        wire_type: WireType::Varint,
+        data: ProtobufFieldData::Enum(value, enum_descriptor.clone()),
+        additional_data: vec![],
+        descriptor: enum_field_descriptor.clone()
+      }
+    ];
+
+    let expected = message(1);
+    let result = compare_message(DocPath::root(), &expected, &message(2), &context,
+      &message_descriptor, &fds, false).unwrap();
+    expect!(result).to(be_equal_to(BodyMatchResult::Ok));
+
+    let result = compare_message(DocPath::root(), &expected, &message(3), &context,
+      &message_descriptor, &fds, false).unwrap();
+    let mismatches = result.mismatches().iter()
+      .map(mismatch_message)
+      .collect::<Vec<_>>();
+    expect!(mismatches).to(be_equal_to(vec![
+      "Expected 'AUDIO_PROGRAM' to match '.*_AD_BREAK'".to_string()
+    ]));
+  }
+
+  #[rstest::rstest]
+  #[case::same_order(vec![vec![10, 6, 10, 1, 97, 18, 1, 49], vec![10, 6, 10, 1, 98, 18, 1, 50]], true)]
+  #[case::different_order(vec![vec![10, 6, 10, 1, 98, 18, 1, 50], vec![10, 6, 10, 1, 97, 18, 1, 49]], true)]
+  #[case::duplicate_key_last_one_wins(vec![vec![10, 6, 10, 1, 97, 18, 1, 57], vec![10, 6, 10, 1, 98, 18, 1, 50], vec![10, 6, 10, 1, 97, 18, 1, 49]], true)]
+  #[case::different_value(vec![vec![10, 6, 10, 1, 98, 18, 1, 51], vec![10, 6, 10, 1, 97, 18, 1, 49]], false)]
+  #[case::missing_entry(vec![vec![10, 6, 10, 1, 98, 18, 1, 50]], false)]
+  fn match_message_compares_map_entries_by_key(#[case] entries: Vec<Vec<u8>>, #[case] matches: bool) {
+    let fds = FileDescriptorSet {
+      file: vec![
+        FileDescriptorProto {
+          name: Some("test.proto".to_string()),
+          message_type: vec![
+            DescriptorProto {
+              name: Some("Test".to_string()),
+              field: vec![
+                FieldDescriptorProto {
+                  name: Some("labels".to_string()),
+                  number: Some(1),
+                  label: Some(Repeated as i32),
+                  r#type: Some(Type::Message as i32),
+                  type_name: Some(".Test.LabelsEntry".to_string()),
+                  .. FieldDescriptorProto::default()
+                }
+              ],
+              nested_type: vec![
+                DescriptorProto {
+                  name: Some("LabelsEntry".to_string()),
+                  field: vec![
+                    FieldDescriptorProto {
+                      name: Some("key".to_string()),
+                      number: Some(1),
+                      label: Some(Optional as i32),
+                      r#type: Some(Type::String as i32),
+                      .. FieldDescriptorProto::default()
+                    },
+                    FieldDescriptorProto {
+                      name: Some("value".to_string()),
+                      number: Some(2),
+                      label: Some(Optional as i32),
+                      r#type: Some(Type::String as i32),
+                      .. FieldDescriptorProto::default()
+                    }
+                  ],
+                  options: Some(MessageOptions { map_entry: Some(true), .. MessageOptions::default() }),
+                  .. DescriptorProto::default()
+                }
+              ],
+              .. DescriptorProto::default()
+            }
+          ],
+          syntax: Some("proto3".to_string()),
+          .. FileDescriptorProto::default()
+        }
+      ]
+    };
+    let matching_rules = MatchingRuleCategory::empty("body");
+
+    // Test { labels: { a: "1", b: "2" } }
+    let mut expected = Bytes::from_static(&[10, 6, 10, 1, 97, 18, 1, 49, 10, 6, 10, 1, 98, 18, 1, 50]);
+    let mut actual = Bytes::from(entries.concat());
+    let result = match_message(".Test", &fds, &mut expected, &mut actual,
+      &matching_rules, false, false, &hashmap!{}).unwrap();
+    expect!(result == BodyMatchResult::Ok).to(be_equal_to(matches));
+  }
+
+  #[rstest::rstest]
+  #[case::same_key(&[("a", "1234")], true, vec![])]
+  #[case::different_key(&[("b", "5678")], true, vec![])]
+  #[case::multiple_keys(&[("b", "5678"), ("c", "90")], true, vec![])]
+  #[case::invalid_key(&[("B", "5678")], true, vec!["Expected 'B' to match '[a-z]+'"])]
+  #[case::invalid_value(&[("b", "five")], true, vec!["Expected 'five' to match '\\d+'"])]
+  #[case::no_value_matcher(&[("b", "five")], false, vec![])]
+  #[case::no_value_matcher_and_invalid_key(&[("B", "five")], false, vec!["Expected 'B' to match '[a-z]+'"])]
+  fn match_message_with_a_regex_keyed_map_of_messages(
+    #[case] entries: &[(&str, &str)],
+    #[case] value_matcher: bool,
+    #[case] expected_mismatches: Vec<&str>
+  ) {
+    let string_field = |name: &str, number: i32| FieldDescriptorProto {
+      name: Some(name.to_string()),
+      number: Some(number),
+      label: Some(Optional as i32),
+      r#type: Some(String as i32),
+      .. FieldDescriptorProto::default()
+    };
+    let fds = FileDescriptorSet {
+      file: vec![
+        FileDescriptorProto {
+          name: Some("test.proto".to_string()),
+          message_type: vec![
+            DescriptorProto {
+              name: Some("Test".to_string()),
+              field: vec![
+                FieldDescriptorProto {
+                  name: Some("areas".to_string()),
+                  number: Some(1),
+                  label: Some(Repeated as i32),
+                  r#type: Some(Type::Message as i32),
+                  type_name: Some(".Test.AreasEntry".to_string()),
+                  .. FieldDescriptorProto::default()
+                }
+              ],
+              nested_type: vec![
+                DescriptorProto {
+                  name: Some("AreasEntry".to_string()),
+                  field: vec![
+                    string_field("key", 1),
+                    FieldDescriptorProto {
+                      name: Some("value".to_string()),
+                      number: Some(2),
+                      label: Some(Optional as i32),
+                      r#type: Some(Type::Message as i32),
+                      type_name: Some(".Area".to_string()),
+                      .. FieldDescriptorProto::default()
+                    }
+                  ],
+                  options: Some(MessageOptions { map_entry: Some(true), .. MessageOptions::default() }),
+                  .. DescriptorProto::default()
+                }
+              ],
+              .. DescriptorProto::default()
+            },
+            DescriptorProto {
+              name: Some("Area".to_string()),
+              field: vec![ string_field("id", 1) ],
+              .. DescriptorProto::default()
+            }
+          ],
+          syntax: Some("proto3".to_string()),
+          .. FileDescriptorProto::default()
+        }
+      ]
+    };
+    let mut matching_rules = matchingrules! {
+      "body" => {
+        "$.areas" => [ MatchingRule::EachKey(MatchingRuleDefinition::new("a".to_string(), ValueType::String,
+          MatchingRule::Regex("[a-z]+".to_string()), None)) ]
+      }
+    }.rules_for_category("body").unwrap();
+    if value_matcher {
+      matching_rules.add_rule(DocPath::new_unwrap("$.areas.*.id"), MatchingRule::Regex("\\d+".to_string()), RuleLogic::And);
+    }
+    let message = |entries: &[(&str, &str)]| {
+      let mut buffer = BytesMut::new();
+      for (key, id) in entries {
+        let mut area = BytesMut::new();
+        prost::encoding::string::encode(1, &id.to_string(), &mut area);
+        let mut entry = BytesMut::new();
+        prost::encoding::string::encode(1, &key.to_string(), &mut entry);
+        prost::encoding::bytes::encode(2, &area.freeze(), &mut entry);
+        prost::encoding::bytes::encode(1, &entry.freeze(), &mut buffer);
+      }
+      buffer.freeze()
+    };
+
+    // Test { areas: { a: { id: "1234" } } }
+    let mut expected = message(&[("a", "1234")]);
+    let mut actual = message(entries);
+    let result = match_message(".Test", &fds, &mut expected, &mut actual,
+      &matching_rules, true, false, &hashmap!{}).unwrap();
+    let mismatches = result.mismatches().iter()
+      .map(mismatch_message)
+      .collect::<Vec<_>>();
+    expect!(mismatches).to(be_equal_to(expected_mismatches.iter().map(|m| m.to_string()).collect::<Vec<_>>()));
+  }
+
+  #[test_log::test]
+  fn match_message_updates_the_comparison_counters() {
+    let fds = FileDescriptorSet {
+      file: vec![
+        FileDescriptorProto {
+          name: Some("test.proto".to_string()),
+          message_type: vec![
+            DescriptorProto {
+              name: Some("Test".to_string()),
+              field: vec![
+                FieldDescriptorProto {
+                  name: Some("count".to_string()),
+                  number: Some(1),
+                  label: Some(Optional as i32),
+                  r#type: Some(Type::Int32 as i32),
+                  .. FieldDescriptorProto::default()
+                }
+              ],
+              .. DescriptorProto::default()
+            }
+          ],
+          .. FileDescriptorProto::default()
+        }
+      ]
+    };
+    let matching_rules = MatchingRuleCategory::empty("body");
+    let comparisons = metrics::value(Counter::Comparisons);
+    let failures = metrics::value(Counter::ComparisonFailures);
+
+    // Test { count: 1 }
+    let expected = Bytes::from_static(&[8, 1]);
+    let result = match_message(".Test", &fds, &mut expected.clone(), &mut expected.clone(),
+      &matching_rules, true, false, &hashmap!{}).unwrap();
+    expect!(result).to(be_equal_to(BodyMatchResult::Ok));
+    expect!(metrics::value(Counter::Comparisons)).to(be_greater_or_equal_to(comparisons + 1));
+
+    // Test { count: 2 }
+    let mut actual = Bytes::from_static(&[8, 2]);
+    let result = match_message(".Test", &fds, &mut expected.clone(), &mut actual,
+      &matching_rules, true, false, &hashmap!{}).unwrap();
+    expect!(result).to_not(be_equal_to(BodyMatchResult::Ok));
+    expect!(metrics::value(Counter::Comparisons)).to(be_greater_or_equal_to(comparisons + 2));
+    expect!(metrics::value(Counter::ComparisonFailures)).to(be_greater_or_equal_to(failures + 1));
+  }
+
+  #[test_log::test]
+  fn compare_message_with_a_type_matcher_on_the_whole_message() {
+    let field_descriptor = FieldDescriptorProto {
+      name: Some("implementation".to_string()),
+      number: Some(1),
+      r#type: Some(Type::String as i32),
+      .. FieldDescriptorProto::default()
+    };
+    let message_descriptor = DescriptorProto {
+      name: Some("Plugin".to_string()),
+      field: vec![field_descriptor.clone()],
+      .. DescriptorProto::default()
+    };
+    let fds = FileDescriptorSet { file: vec![] };
+    let rules = matchingrules_list! {
+      "body";
+      "$" => [ MatchingRule::Type ]
+    };
+    let context = CoreMatchingContext::new(DiffConfig::NoUnexpectedKeys, &rules, &hashmap!{});
+    let message = |data: ProtobufFieldData| vec![
+      ProtobufField {
+        field_num: 1,
+        field_name: "implementation".to_string(),
+        wire_type: WireType::LengthDelimited,
+        data,
+        additional_data: vec![],
+        descriptor: field_descriptor.clone()
+      }
+    ];
+
+    let expected = message(ProtobufFieldData::String("plugin-driver-rust".to_string()));
+    let result = compare_message(DocPath::root(), &expected,
+      &message(ProtobufFieldData::String("plugin-driver-jvm".to_string())), &context,
+      &message_descriptor, &fds, false).unwrap();
+    expect!(result).to(be_equal_to(BodyMatchResult::Ok));
+
+    let result = compare_message(DocPath::root(), &expected,
+      &message(ProtobufFieldData::Integer32(100)), &context,
+      &message_descriptor, &fds, false).unwrap();
+    expect!(result).to_not(be_equal_to(BodyMatchResult::Ok));
+  }
+
+  #[test_log::test]
+  fn compare_message_with_a_case_insensitive_string_matcher() {
+    let field_descriptor = FieldDescriptorProto {
+      name: Some("status".to_string()),
+      number: Some(1),
+      label: Some(Optional as i32),
+      r#type: Some(String as i32),
+      .. FieldDescriptorProto::default()
+    };
+    let message_descriptor = DescriptorProto {
+      name: Some("Test".to_string()),
+      field: vec![field_descriptor.clone()],
+      .. DescriptorProto::default()
+    };
+    let fds = FileDescriptorSet { file: vec![] };
+    let rules = matchingrules_list! {
+      "body"; "$.status" => [ equals_ignore_case_rule("Active") ]
+    };
+    let context = CoreMatchingContext::new(DiffConfig::NoUnexpectedKeys, &rules, &hashmap!{});
+
+    let expected = vec![ string_field(1, "status", "Active", &field_descriptor) ];
+    for value in ["Active", "ACTIVE", "active"] {
+      let actual = vec![ string_field(1, "status", value, &field_descriptor) ];
+      let result = compare_message(DocPath::root(), &expected, &actual, &context,
+        &message_descriptor, &fds, false).unwrap();
+      expect!(result).to(be_equal_to(BodyMatchResult::Ok));
+    }
+
+    let actual = vec![ string_field(1, "status", "Inactive", &field_descriptor) ];
+    let result = compare_message(DocPath::root(), &expected, &actual, &context,
+      &message_descriptor, &fds, false).unwrap();
+    expect!(result.mismatches()).to(be_equal_to(vec![
+      BodyMismatch {
+        path: "$.status".to_string(),
+        expected: Some("Active".into()),
+        actual: Some("Inactive".into()),
+        mismatch: "Expected 'Inactive' to be equal to 'Active' ignoring case".to_string()
+      }
+    ]));
+  }
+
+  #[rstest::rstest]
+  #[case::non_ascii("Äpfel", "äPFEL", true)]
+  #[case::no_full_case_folding("Straße", "STRASSE", false)]
+  #[case::escaped("a.b", "A.B", true)]
+  #[case::escaped_mismatch("a.b", "axb", false)]
+  fn compare_ignoring_case_uses_the_same_regex_as_the_matching_rule(
+    #[case] expected: &str,
+    #[case] actual: &str,
+    #[case] matches: bool
+  ) {
+    let regex = match equals_ignore_case_rule(expected) {
+      MatchingRule::Regex(regex) => regex::Regex::new(regex.as_str()).unwrap(),
+      rule => panic!("Expected a regex rule, got {:?}", rule)
+    };
+    expect!(regex.is_match(actual)).to(be_equal_to(matches));
+    expect!(compare_ignoring_case(&DocPath::root(), expected, actual).is_empty()).to(be_equal_to(matches));
+  }
+
+  #[test_log::test]
+  fn compare_message_with_a_field_expected_to_be_absent() {
+    let field_descriptor = FieldDescriptorProto {
+      name: Some("reason".to_string()),
+      number: Some(1),
+      label: Some(Optional as i32),
+      r#type: Some(String as i32),
+      .. FieldDescriptorProto::default()
+    };
+    let message_descriptor = DescriptorProto {
+      name: Some("Test".to_string()),
+      field: vec![field_descriptor.clone()],
+      .. DescriptorProto::default()
+    };
+    let fds = FileDescriptorSet { file: vec![] };
+    let rules = matchingrules_list! {
+      "body"; "$.reason" => [ MatchingRule::Null ]
+    };
+    let context = CoreMatchingContext::new(DiffConfig::AllowUnexpectedKeys, &rules, &hashmap!{});
+
+    let result = compare_message(DocPath::root(), &[], &[], &context,
+      &message_descriptor, &fds, false).unwrap();
+    expect!(result).to(be_equal_to(BodyMatchResult::Ok));
+
+    let actual = vec![ string_field(1, "reason", "", &field_descriptor) ];
+    let result = compare_message(DocPath::root(), &[], &actual, &context,
+      &message_descriptor, &fds, false).unwrap();
+    expect!(result).to(be_equal_to(BodyMatchResult::Ok));
+
+    let actual = vec![ string_field(1, "reason", "timeout", &field_descriptor) ];
+    let result = compare_message(DocPath::root(), &[], &actual, &context,
+      &message_descriptor, &fds, false).unwrap();
+    expect!(result.mismatches()).to(be_equal_to(vec![
+      BodyMismatch {
+        path: "$.reason".to_string(),
+        expected: None,
+        actual: Some("timeout".into()),
+        mismatch: "Expected field 'reason' to be absent, but received a value for it".to_string()
+      }
+    ]));
+  }
+
+  #[test_log::test]
+  fn compare_with_an_editions_message_requires_fields_with_explicit_presence_to_be_present() {
+    let bytes = BASE64.decode(EDITIONS_DESCRIPTORS).unwrap();
+    let mut fds = decode_file_descriptor_set(bytes.as_slice()).unwrap();
+    let message_descriptor = fds.file[0].message_type[0].clone();
+    let context = CoreMatchingContext::new(DiffConfig::AllowUnexpectedKeys,
+      &MatchingRuleCategory::empty("body"), &hashmap!{});
+    let name = string_field(1, "name", "", &message_descriptor.field[0]);
+    let count = ProtobufField {
+      field_num: 2,
+      field_name: "count".to_string(),
+      wire_type: WireType::Varint,
+      data: ProtobufFieldData::Integer32(0),
+      additional_data: vec![],
+      descriptor: message_descriptor.field[1].clone()
+    };
+    let expected = vec![ name.clone(), count.clone() ];
+
+    let result = compare(&message_descriptor, &expected, &[ name.clone(), count ], &context,
+      &Bytes::new(), &fds, false).unwrap();
+    expect!(result).to(be_equal_to(BodyMatchResult::Ok));
+
+    // count has implicit presence, so it is not sent when set to the default value
+    let result = compare(&message_descriptor, &expected, &[ name ], &context,
+      &Bytes::new(), &fds, false).unwrap();
+    expect!(result).to(be_equal_to(BodyMatchResult::Ok));
+
+    let result = compare(&message_descriptor, &expected, &[], &context,
+      &Bytes::new(), &fds, false).unwrap();
+    expect!(result.mismatches()).to(be_equal_to(vec![
+      BodyMismatch {
+        path: "$.name".to_string(),
+        expected: Some(Bytes::new()),
+        actual: None,
+        mismatch: "Expected field 'name' to be set, but it was missing".to_string()
+      }
+    ]));
+
+    fds.file[0].syntax = Some("proto3".to_string());
+    let result = compare(&message_descriptor, &expected, &[], &context,
+      &Bytes::new(), &fds, false).unwrap();
+    expect!(result).to(be_equal_to(BodyMatchResult::Ok));
+  }
+
+  #[test_log::test]
+  fn compare_message_with_a_content_type_matcher_on_a_bytes_field() {
+    let field_descriptor = FieldDescriptorProto {
+      name: Some("payload".to_string()),
+      number: Some(1),
+      label: Some(Optional as i32),
+      r#type: Some(Type::Bytes as i32),
+      .. FieldDescriptorProto::default()
+    };
+    let message_descriptor = DescriptorProto {
+      name: Some("Test".to_string()),
+      field: vec![field_descriptor.clone()],
+      .. DescriptorProto::default()
+    };
+    let fds = FileDescriptorSet { file: vec![] };
+    let rules = matchingrules_list! {
+      "body";
+      "$.payload" => [ MatchingRule::ContentType("application/json".to_string()) ],
+      "$.payload.id" => [ MatchingRule::Integer ]
+    };
+    let context = CoreMatchingContext::new(DiffConfig::NoUnexpectedKeys, &rules, &hashmap!{});
+    let bytes_field = |value: &str| ProtobufField {
+      field_num: 1,
+      field_name: "payload".to_string(),
+      wire_type: WireType::LengthDelimited,
+      data: ProtobufFieldData::Bytes(value.as_bytes().to_vec()),
+      additional_data: vec![],
+      descriptor: field_descriptor.clone()
+    };
+
+    let expected = vec![ bytes_field(r#"{"id": 100, "name": "test"}"#) ];
+    let actual = vec![ bytes_field(r#"{"name":"test","id":200}"#) ];
+    let result = compare_message(DocPath::root(), &expected, &actual, &context,
+      &message_descriptor, &fds, false).unwrap();
+    expect!(result).to(be_equal_to(BodyMatchResult::Ok));
+
+    let actual = vec![ bytes_field(r#"{"name":"other","id":200}"#) ];
+    let result = compare_message(DocPath::root(), &expected, &actual, &context,
+      &message_descriptor, &fds, false).unwrap();
+    let mismatches = result.mismatches();
+    expect!(mismatches.len()).to(be_equal_to(1));
+    match &mismatches[0] {
+      BodyMismatch { path, .. } => {
+        expect!(path.as_str()).to(be_equal_to("$.payload.name"));
+      }
+      mismatch => panic!("Expected a body mismatch, got {:?}", mismatch)
+    }
+
+    let actual = vec![ bytes_field("not JSON") ];
+    let result = compare_message(DocPath::root(), &expected, &actual, &context,
+      &message_descriptor, &fds, false).unwrap();
+    expect!(result.mismatches().len()).to(be_equal_to(1));
+  }
+
+  #[rstest::rstest]
+  #[case::within_the_length(vec![1, 2, 3], vec![])]
+  #[case::minimum_length(vec![1, 2], vec![])]
+  #[case::maximum_length(vec![1, 2, 3, 4], vec![])]
+  #[case::too_short(vec![1], vec!["Expected [1] (size 1) to have minimum size of 2"])]
+  #[case::too_long(vec![1, 2, 3, 4, 5], vec!["Expected [1, 2, 3, 4, 5] (size 5) to have maximum size of 4"])]
+  #[test_log::test]
+  fn compare_message_with_a_length_matcher_on_a_bytes_field(
+    #[case] actual_bytes: Vec<u8>,
+    #[case] expected_mismatches: Vec<&str>
+  ) {
+    let field_descriptor = FieldDescriptorProto {
+      name: Some("data".to_string()),
+      number: Some(1),
+      label: Some(Optional as i32),
+      r#type: Some(Type::Bytes as i32),
+      .. FieldDescriptorProto::default()
+    };
+    let message_descriptor = DescriptorProto {
+      name: Some("Test".to_string()),
+      field: vec![field_descriptor.clone()],
+      .. DescriptorProto::default()
+    };
+    let fds = FileDescriptorSet { file: vec![] };
+    let rules = matchingrules_list! {
+      "body";
+      "$.data" => [ MatchingRule::MinType(2), MatchingRule::MaxType(4), MatchingRule::Type ]
+    };
+    let context = CoreMatchingContext::new(DiffConfig::NoUnexpectedKeys, &rules, &hashmap!{});
+    let bytes_field = |value: Vec<u8>| ProtobufField {
+      field_num: 1,
+      field_name: "data".to_string(),
+      wire_type: WireType::LengthDelimited,
+      data: ProtobufFieldData::Bytes(value),
+      additional_data: vec![],
+      descriptor: field_descriptor.clone()
+    };
+
+    let expected = vec![ bytes_field(vec![9, 9, 9]) ];
+    let actual = vec![ bytes_field(actual_bytes) ];
+    let result = compare_message(DocPath::root(), &expected, &actual, &context,
+      &message_descriptor, &fds, false).unwrap();
+    let mismatches = result.mismatches().iter()
+      .map(|mismatch| mismatch.description())
+      .collect::<Vec<_>>();
+    expect!(mismatches).to(be_equal_to(expected_mismatches.iter()
+      .map(|mismatch| format!("$.data -> {}", mismatch))
+      .collect::<Vec<_>>()));
+  }
+
+  #[test_log::test]
+  fn compare_message_with_an_exact_length_matcher_on_a_bytes_field() {
+    let field_descriptor = FieldDescriptorProto {
+      name: Some("data".to_string()),
+      number: Some(1),
+      label: Some(Optional as i32),
+      r#type: Some(Type::Bytes as i32),
+      .. FieldDescriptorProto::default()
+    };
+    let message_descriptor = DescriptorProto {
+      name: Some("Test".to_string()),
+      field: vec![field_descriptor.clone()],
+      .. DescriptorProto::default()
+    };
+    let fds = FileDescriptorSet { file: vec![] };
+    let rules = matchingrules_list! {
+      "body";
+      "$.data" => [ MatchingRule::MinMaxType(3, 3) ]
+    };
+    let context = CoreMatchingContext::new(DiffConfig::NoUnexpectedKeys, &rules, &hashmap!{});
+    let bytes_field = |value: Vec<u8>| ProtobufField {
+      field_num: 1,
+      field_name: "data".to_string(),
+      wire_type: WireType::LengthDelimited,
+      data: ProtobufFieldData::Bytes(value),
+      additional_data: vec![],
+      descriptor: field_descriptor.clone()
+    };
+
+    let expected = vec![ bytes_field(vec![9, 9, 9]) ];
+    let actual = vec![ bytes_field(vec![1, 2, 3]) ];
+    let result = compare_message(DocPath::root(), &expected, &actual, &context,
+      &message_descriptor, &fds, false).unwrap();
+    expect!(result).to(be_equal_to(BodyMatchResult::Ok));
+
+    let actual = vec![ bytes_field(vec![1, 2]) ];
+    let result = compare_message(DocPath::root(), &expected, &actual, &context,
+      &message_descriptor, &fds, false).unwrap();
+    let mismatches = result.mismatches();
+    expect!(mismatches.len()).to(be_equal_to(1));
+    expect!(mismatches[0].description()).to(be_equal_to(
+      "$.data -> Expected [1, 2] (size 2) to have minimum size of 3"));
+  }
+
+  #[test_log::test]
+  fn compare_message_with_a_protobuf_content_type_matcher_on_a_bytes_field() {
+    let field_descriptor = FieldDescriptorProto {
+      name: Some("payload".to_string()),
+      number: Some(1),
+      label: Some(Optional as i32),
+      r#type: Some(Type::Bytes as i32),
+      .. FieldDescriptorProto::default()
+    };
+    let message_descriptor = DescriptorProto {
+      name: Some("Test".to_string()),
+      field: vec![field_descriptor.clone()],
+      .. DescriptorProto::default()
+    };
+    let inner_descriptor = DescriptorProto {
+      name: Some("Inner".to_string()),
+      field: vec![
+        FieldDescriptorProto {
+          name: Some("name".to_string()),
+          number: Some(1),
+          label: Some(Optional as i32),
+          r#type: Some(Type::String as i32),
+          .. FieldDescriptorProto::default()
+        },
+        FieldDescriptorProto {
+          name: Some("id".to_string()),
+          number: Some(2),
+          label: Some(Optional as i32),
+          r#type: Some(Type::Int32 as i32),
+          .. FieldDescriptorProto::default()
+        }
+      ],
+      .. DescriptorProto::default()
+    };
+    let fds = FileDescriptorSet {
+      file: vec![
+        FileDescriptorProto {
+          name: Some("test.proto".to_string()),
+          package: Some("pkg".to_string()),
+          message_type: vec![ message_descriptor.clone(), inner_descriptor ],
+          syntax: Some("proto3".to_string()),
+          .. FileDescriptorProto::default()
+        }
+      ]
+    };
+    let rules = matchingrules_list! {
+      "body";
+      "$.payload" => [ MatchingRule::ContentType("application/protobuf;message=.pkg.Inner".to_string()) ],
+      "$.payload.id" => [ MatchingRule::Integer ]
+    };
+    let context = CoreMatchingContext::new(DiffConfig::NoUnexpectedKeys, &rules, &hashmap!{});
+    let bytes_field = |value: &[u8]| ProtobufField {
+      field_num: 1,
+      field_name: "payload".to_string(),
+      wire_type: WireType::LengthDelimited,
+      data: ProtobufFieldData::Bytes(value.to_vec()),
+      additional_data: vec![],
+      descriptor: field_descriptor.clone()
+    };
+
+    // Inner { name: "test", id: 100 }
+    let expected = vec![ bytes_field(&[10, 4, 116, 101, 115, 116, 16, 100]) ];
+    // Inner { name: "test", id: 200 }
+    let actual = vec![ bytes_field(&[10, 4, 116, 101, 115, 116, 16, 200, 1]) ];
+    let result = compare_message(DocPath::root(), &expected, &actual, &context,
+      &message_descriptor, &fds, false).unwrap();
+    expect!(result).to(be_equal_to(BodyMatchResult::Ok));
+
+    // Inner { name: "tent", id: 200 }
+    let actual = vec![ bytes_field(&[10, 4, 116, 101, 110, 116, 16, 200, 1]) ];
+    let result = compare_message(DocPath::root(), &expected, &actual, &context,
+      &message_descriptor, &fds, false).unwrap();
+    let mismatches = result.mismatches();
+    expect!(mismatches.len()).to(be_equal_to(1));
+    match &mismatches[0] {
+      BodyMismatch { path, .. } => {
+        expect!(path.as_str()).to(be_equal_to("$.payload.name"));
+      }
+      mismatch => panic!("Expected a body mismatch, got {:?}", mismatch)
+    }
+
+    let actual = vec![ bytes_field(&[10, 50]) ];
+    let result = compare_message(DocPath::root(), &expected, &actual, &context,
+      &message_descriptor, &fds, false).unwrap();
+    expect!(result.mismatches().len()).to(be_equal_to(1));
+  }
+
+  #[test_log::test]
+  fn compare_message_with_an_unsupported_content_type_matcher_on_a_bytes_field_compares_the_bytes() {
+    let field_descriptor = FieldDescriptorProto {
+      name: Some("payload".to_string()),
+      number: Some(1),
+      label: Some(Optional as i32),
+      r#type: Some(Type::Bytes as i32),
+      .. FieldDescriptorProto::default()
+    };
+    let message_descriptor = DescriptorProto {
+      name: Some("Test".to_string()),
+      field: vec![field_descriptor.clone()],
+      .. DescriptorProto::default()
+    };
+    let fds = FileDescriptorSet { file: vec![] };
+    let rules = matchingrules_list! {
+      "body"; "$.payload" => [ MatchingRule::ContentType("image/png".to_string()) ]
+    };
+    let context = CoreMatchingContext::new(DiffConfig::NoUnexpectedKeys, &rules, &hashmap!{});
+    let bytes_field = |value: &[u8]| ProtobufField {
+      field_num: 1,
+      field_name: "payload".to_string(),
+      wire_type: WireType::LengthDelimited,
+      data: ProtobufFieldData::Bytes(value.to_vec()),
+      additional_data: vec![],
+      descriptor: field_descriptor.clone()
+    };
+
+    let expected = vec![ bytes_field(&[1, 2, 3]) ];
+    let result = compare_message(DocPath::root(), &expected, &[ bytes_field(&[1, 2, 3]) ], &context,
+      &message_descriptor, &fds, false).unwrap();
+    expect!(result).to(be_equal_to(BodyMatchResult::Ok));
+
+    let result = compare_message(DocPath::root(), &expected, &[ bytes_field(&[1, 2, 4]) ], &context,
+      &message_descriptor, &fds, false).unwrap();
+    expect!(result.mismatches().len()).to(be_equal_to(1));
+  }
+
+  #[test_log::test]
+  fn compare_message_with_a_field_mask_field_compares_the_paths_as_a_set() {
+    let field_mask_descriptor = DescriptorProto {
+      name: Some("FieldMask".to_string()),
+      field: vec![
+        FieldDescriptorProto {
+          name: Some("paths".to_string()),
+          number: Some(1),
+          label: Some(Repeated as i32),
+          r#type: Some(String as i32),
+          .. FieldDescriptorProto::default()
+        }
+      ],
+      .. DescriptorProto::default()
+    };
+    let field_descriptor = FieldDescriptorProto {
+      name: Some("update_mask".to_string()),
+      number: Some(1),
+      label: Some(Optional as i32),
+      r#type: Some(Type::Message as i32),
+      type_name: Some(".google.protobuf.FieldMask".to_string()),
+      .. FieldDescriptorProto::default()
+    };
+    let message_descriptor = DescriptorProto {
+      name: Some("Test".to_string()),
+      field: vec![field_descriptor.clone()],
+      .. DescriptorProto::default()
+    };
+    let fds = FileDescriptorSet { file: vec![] };
+    let context = CoreMatchingContext::new(DiffConfig::NoUnexpectedKeys,
+      &MatchingRuleCategory::empty("body"), &hashmap!{});
+    let field_mask = |paths: &[&str]| ProtobufField {
+      field_num: 1,
+      field_name: "update_mask".to_string(),
+      wire_type: WireType::LengthDelimited,
+      data: ProtobufFieldData::Message(prost_types::FieldMask {
+        paths: paths.iter().map(|p| p.to_string()).collect()
+      }.encode_to_vec(), field_mask_descriptor.clone()),
+      additional_data: vec![],
+      descriptor: field_descriptor.clone()
+    };
+
+    let expected = vec![ field_mask(&["name", "address.city"]) ];
+    let actual = vec![ field_mask(&["address.city", "name"]) ];
+    let result = compare_message(DocPath::root(), &expected, &actual, &context,
+      &message_descriptor, &fds, false).unwrap();
+    expect!(result).to(be_equal_to(BodyMatchResult::Ok));
+
+    let actual = vec![ field_mask(&["name"]) ];
+    let result = compare_message(DocPath::root(), &expected, &actual, &context,
+      &message_descriptor, &fds, false).unwrap();
+    let mismatches = result.mismatches();
+    expect!(mismatches.len()).to(be_equal_to(1));
+    match &mismatches[0] {
+      BodyMismatch { path, mismatch, .. } => {
+        expect!(path.as_str()).to(be_equal_to("$.update_mask"));
+        expect!(mismatch.as_str()).to(be_equal_to(
+          "Expected field mask paths {\"address.city\", \"name\"} but received {\"name\"}"));
+      }
+      mismatch => panic!("Expected a body mismatch, got {:?}", mismatch)
+    }
+  }
+
+  #[rstest::rstest]
+  #[case::int32_with_decimal(Type::Int32, ProtobufFieldData::Integer32(100), MatchingRule::Decimal,
+    "Expected 100 to match a decimal number, but field 'value' is an integer field (Int32)")]
+  #[case::uint64_with_decimal(Type::Uint64, ProtobufFieldData::UInteger64(100), MatchingRule::Decimal,
+    "Expected 100 to match a decimal number, but field 'value' is an integer field (Uint64)")]
+  #[case::float_with_integer(Type::Float, ProtobufFieldData::Float(100.0), MatchingRule::Integer,
+    "Expected 100 to match an integer number, but field 'value' is a floating point field (Float)")]
+  #[case::double_with_integer(Type::Double, ProtobufFieldData::Double(100.5), MatchingRule::Integer,
+    "Expected 100.5 to match an integer number, but field 'value' is a floating point field (Double)")]
+  fn compare_message_with_a_numeric_matcher_for_a_different_field_type(
+    #[case] field_type: Type,
+    #[case] value: ProtobufFieldData,
+    #[case] rule: MatchingRule,
+    #[case] mismatch: &str
+  ) {
+    let field_descriptor = FieldDescriptorProto {
+      name: Some("value".to_string()),
+      number: Some(1),
+      label: Some(Optional as i32),
+      r#type: Some(field_type as i32),
+      .. FieldDescriptorProto::default()
+    };
+    let message_descriptor = DescriptorProto {
+      name: Some("Test".to_string()),
+      field: vec![field_descriptor.clone()],
+      .. DescriptorProto::default()
+    };
+    let fds = FileDescriptorSet { file: vec![] };
+    let numeric_field = |data: ProtobufFieldData| ProtobufField {
+      field_num: 1,
+      field_name: "value".to_string(),
+      wire_type: WireType::Varint,
+      data,
+      additional_data: vec![],
+      descriptor: field_descriptor.clone()
+    };
+
+    let rules = matchingrules_list! { "body"; "$.value" => [ rule ] };
+    let context = CoreMatchingContext::new(DiffConfig::NoUnexpectedKeys, &rules, &hashmap!{});
+    let expected = vec![ numeric_field(value.clone()) ];
+    let actual = vec![ numeric_field(value) ];
+    let result = compare_message(DocPath::root(), &expected, &actual, &context,
+      &message_descriptor, &fds, false).unwrap();
+    let mismatches = result.mismatches();
+    expect!(mismatches.len()).to(be_equal_to(1));
+    match &mismatches[0] {
+      BodyMismatch { path, mismatch: message, .. } => {
+        expect!(path.as_str()).to(be_equal_to("$.value"));
+        expect!(message.as_str()).to(be_equal_to(mismatch));
+      }
+      other => panic!("Expected a body mismatch, got {:?}", other)
+    }
+
+    let rules = matchingrules_list! { "body"; "$.value" => [ MatchingRule::Number ] };
+    let context = CoreMatchingContext::new(DiffConfig::NoUnexpectedKeys, &rules, &hashmap!{});
+    let result = compare_message(DocPath::root(), &expected, &expected, &context,
+      &message_descriptor, &fds, false).unwrap();
+    expect!(result).to(be_equal_to(BodyMatchResult::Ok));
+  }
+
+  #[rstest::rstest]
+  #[case::double_nan(Type::Double, ProtobufFieldData::Double(f64::NAN), ProtobufFieldData::Double(f64::NAN), true)]
+  #[case::float_nan(Type::Float, ProtobufFieldData::Float(f32::NAN), ProtobufFieldData::Float(f32::NAN), true)]
+  #[case::double_nan_with_number(Type::Double, ProtobufFieldData::Double(f64::NAN), ProtobufFieldData::Double(1.0), false)]
+  #[case::double_infinity(Type::Double, ProtobufFieldData::Double(f64::INFINITY), ProtobufFieldData::Double(f64::INFINITY), true)]
+  #[case::double_negative_infinity(Type::Double, ProtobufFieldData::Double(f64::NEG_INFINITY), ProtobufFieldData::Double(f64::NEG_INFINITY), true)]
+  #[case::double_infinities(Type::Double, ProtobufFieldData::Double(f64::INFINITY), ProtobufFieldData::Double(f64::NEG_INFINITY), false)]
+  #[case::float_infinity(Type::Float, ProtobufFieldData::Float(f32::INFINITY), ProtobufFieldData::Float(f32::INFINITY), true)]
+  #[case::float_infinities(Type::Float, ProtobufFieldData::Float(f32::NEG_INFINITY), ProtobufFieldData::Float(f32::INFINITY), false)]
+  #[case::float_infinity_with_max(Type::Float, ProtobufFieldData::Float(f32::INFINITY), ProtobufFieldData::Float(f32::MAX), false)]
+  fn compare_message_with_nan_and_infinite_floating_point_values(
+    #[case] field_type: Type,
+    #[case] expected_value: ProtobufFieldData,
+    #[case] actual_value: ProtobufFieldData,
+    #[case] matches: bool
+  ) {
+    let field_descriptor = FieldDescriptorProto {
+      name: Some("value".to_string()),
+      number: Some(1),
+      label: Some(Optional as i32),
+      r#type: Some(field_type as i32),
+      .. FieldDescriptorProto::default()
+    };
+    let message_descriptor = DescriptorProto {
+      name: Some("Test".to_string()),
+      field: vec![field_descriptor.clone()],
+      .. DescriptorProto::default()
+    };
+    let fds = FileDescriptorSet { file: vec![] };
+    let field = |data: ProtobufFieldData| ProtobufField {
+      field_num: 1,
+      field_name: "value".to_string(),
+      wire_type: WireType::SixtyFourBit,
+      data,
+      additional_data: vec![],
+      descriptor: field_descriptor.clone()
+    };
+
+    let rules = MatchingRuleCategory::empty("body");
+    let context = CoreMatchingContext::new(DiffConfig::NoUnexpectedKeys, &rules, &hashmap!{});
+    let expected = vec![ field(expected_value) ];
+    let actual = vec![ field(actual_value) ];
+    let result = compare_message(DocPath::root(), &expected, &actual, &context,
+      &message_descriptor, &fds, false).unwrap();
+    expect!(result.mismatches().is_empty()).to(be_equal_to(matches));
+  }
+
+  fn mismatch_message(mismatch: &Mismatch) -> std::string::String {
+    match mismatch {
+      BodyMismatch { mismatch, .. } => mismatch.clone(),
+      _ => mismatch.description()
+    }
+  }
+
+  #[rstest::rstest]
+  #[case::at_least(vec![ MatchingRule::MinType(2) ], 2, vec![])]
+  #[case::at_least_with_more(vec![ MatchingRule::MinType(2) ], 3, vec![])]
+  #[case::at_least_with_less(vec![ MatchingRule::MinType(2) ], 1,
+    vec!["Expected repeated field 'values' to have at least 2 value(s) but received 1"])]
+  #[case::at_most(vec![ MatchingRule::MaxType(3) ], 3, vec![])]
+  #[case::at_most_with_more(vec![ MatchingRule::MaxType(3) ], 4,
+    vec!["Expected repeated field 'values' to have at most 3 value(s) but received 4"])]
+  #[case::exact(vec![ MatchingRule::MinType(2), MatchingRule::MaxType(2) ], 2, vec![])]
+  #[case::exact_with_less(vec![ MatchingRule::MinType(2), MatchingRule::MaxType(2) ], 1,
+    vec!["Expected repeated field 'values' to have at least 2 value(s) but received 1"])]
+  #[case::exact_with_more(vec![ MatchingRule::MinType(2), MatchingRule::MaxType(2) ], 3,
+    vec!["Expected repeated field 'values' to have at most 2 value(s) but received 3"])]
+  fn compare_message_with_repeated_field_and_length_matchers(
+    #[case] rules: Vec<MatchingRule>,
+    #[case] actual_count: usize,
+    #[case] expected_mismatches: Vec<&str>
+  ) {
+    let field_descriptor = FieldDescriptorProto {
+      name: Some("values".to_string()),
+      number: Some(1),
+      label: Some(Repeated as i32),
+      r#type: Some(String as i32),
+      .. FieldDescriptorProto::default()
+    };
+    let message_descriptor = DescriptorProto {
+      name: Some("Test".to_string()),
+      field: vec![field_descriptor.clone()],
+      .. DescriptorProto::default()
+    };
+    let fds = FileDescriptorSet { file: vec![] };
+    let mut matching_rules = MatchingRuleCategory::empty("body");
+    for rule in rules {
+      matching_rules.add_rule(DocPath::new_unwrap("$.values"), rule, RuleLogic::And);
+    }
+    let context = CoreMatchingContext::new(DiffConfig::NoUnexpectedKeys, &matching_rules, &hashmap!{});
+
+    let expected = vec![ string_field(1, "values", "a", &field_descriptor) ];
+    let actual = (0..actual_count)
+      .map(|index| string_field(1, "values", index.to_string().as_str(), &field_descriptor))
+      .collect::<Vec<_>>();
+    let result = compare_message(DocPath::root(), &expected, &actual, &context,
+      &message_descriptor, &fds, false).unwrap();
+    let mismatches = result.mismatches().iter()
+      .map(mismatch_message)
+      .collect::<Vec<_>>();
+    expect!(mismatches).to(be_equal_to(expected_mismatches.iter().map(|m| m.to_string()).collect::<Vec<_>>()));
+  }
+
+  #[test_log::test]
+  fn compare_message_with_repeated_field_and_length_matchers_with_no_expected_values() {
+    let field_descriptor = FieldDescriptorProto {
+      name: Some("values".to_string()),
+      number: Some(1),
+      label: Some(Repeated as i32),
+      r#type: Some(String as i32),
+      .. FieldDescriptorProto::default()
+    };
+    let message_descriptor = DescriptorProto {
+      name: Some("Test".to_string()),
+      field: vec![field_descriptor.clone()],
+      .. DescriptorProto::default()
+    };
+    let fds = FileDescriptorSet { file: vec![] };
+    let rules = matchingrules_list! {
+      "body"; "$.values" => [ MatchingRule::MinType(1), MatchingRule::MaxType(2) ]
+    };
+    let context = CoreMatchingContext::new(DiffConfig::NoUnexpectedKeys, &rules, &hashmap!{});
+
+    let result = compare_message(DocPath::root(), &[], &[], &context,
+      &message_descriptor, &fds, false).unwrap();
+    expect!(result.mismatches().iter().map(mismatch_message).collect::<Vec<_>>()).to(be_equal_to(vec![
+      "Expected repeated field 'values' to have at least 1 value(s) but received 0".to_string()
+    ]));
+
+    let actual = (0..3)
+      .map(|index| string_field(1, "values", index.to_string().as_str(), &field_descriptor))
+      .collect::<Vec<_>>();
+    let result = compare_message(DocPath::root(), &[], &actual, &context,
+      &message_descriptor, &fds, false).unwrap();
+    expect!(result.mismatches().iter().map(mismatch_message).collect::<Vec<_>>()).to(be_equal_to(vec![
+      "Expected repeated field 'values' to have at most 2 value(s) but received 3".to_string()
+    ]));
+  }
+
+  #[test_log::test]
+  fn compare_message_with_a_matcher_for_all_the_values_of_a_repeated_field() {
+    let field_descriptor = FieldDescriptorProto {
+      name: Some("values".to_string()),
+      number: Some(1),
+      label: Some(Repeated as i32),
+      r#type: Some(Type::Int32 as i32),
+      .. FieldDescriptorProto::default()
+    };
+    let message_descriptor = DescriptorProto {
+      name: Some("Test".to_string()),
+      field: vec![field_descriptor.clone()],
+      .. DescriptorProto::default()
+    };
+    let fds = FileDescriptorSet { file: vec![] };
+    let int_field = |value: i32| ProtobufField {
+      field_num: 1,
+      field_name: "values".to_string(),
+      wire_type: WireType::Varint,
+      data: ProtobufFieldData::Integer32(value),
+      additional_data: vec![],
+      descriptor: field_descriptor.clone()
+    };
+    let expected = vec![ int_field(1) ];
+    let actual = vec![ int_field(2), int_field(3), int_field(4) ];
+
+    let rules = matchingrules_list! { "body"; "$.values.*" => [ MatchingRule::Number ] };
+    let context = CoreMatchingContext::new(DiffConfig::NoUnexpectedKeys, &rules, &hashmap!{});
+    let result = compare_message(DocPath::root(), &expected, &actual, &context,
+      &message_descriptor, &fds, false).unwrap();
+    expect!(result).to(be_equal_to(BodyMatchResult::Ok));
+
+    let rules = matchingrules_list! { "body"; "$.values.*" => [ MatchingRule::Decimal ] };
+    let context = CoreMatchingContext::new(DiffConfig::NoUnexpectedKeys, &rules, &hashmap!{});
+    let result = compare_message(DocPath::root(), &expected, &actual, &context,
+      &message_descriptor, &fds, false).unwrap();
+    let paths = result.mismatches().iter()
+      .map(|mismatch| match mismatch {
+        BodyMismatch { path, .. } => path.clone(),
+        _ => mismatch.description()
+      })
+      .collect::<Vec<_>>();
+    expect!(paths).to(be_equal_to(vec!["$.values[0]", "$.values[1]", "$.values[2]"]));
+  }
+
+  fn area_descriptors() -> (DescriptorProto, FieldDescriptorProto) {
+    let area_descriptor = DescriptorProto {
+      name: Some("Area".to_string()),
+      field: vec![
+        FieldDescriptorProto {
+          name: Some("id".to_string()),
+          number: Some(1),
+          label: Some(Optional as i32),
+          r#type: Some(String as i32),
+          .. FieldDescriptorProto::default()
+        },
+        FieldDescriptorProto {
+          name: Some("shape".to_string()),
+          number: Some(2),
+          label: Some(Optional as i32),
+          r#type: Some(String as i32),
+          .. FieldDescriptorProto::default()
+        }
+      ],
+      .. DescriptorProto::default()
+    };
+    let field_descriptor = FieldDescriptorProto {
+      name: Some("areas".to_string()),
+      number: Some(1),
+      label: Some(Repeated as i32),
+      r#type: Some(Type::Message as i32),
+      type_name: Some(".Area".to_string()),
+      .. FieldDescriptorProto::default()
+    };
+    (area_descriptor, field_descriptor)
+  }
+
+  fn area_bytes(id: &str, shape: &str) -> Vec<u8> {
+    let mut bytes = vec![10, id.len() as u8];
+    bytes.extend_from_slice(id.as_bytes());
+    bytes.extend_from_slice(&[18, shape.len() as u8]);
+    bytes.extend_from_slice(shape.as_bytes());
+    bytes
+  }
+
+  #[test_log::test]
+  fn compare_message_with_repeated_message_field_paired_by_a_key_field() {
+    let (area_descriptor, field_descriptor) = area_descriptors();
+    let message_descriptor = DescriptorProto {
+      name: Some("AreaList".to_string()),
+      field: vec![field_descriptor.clone()],
+      .. DescriptorProto::default()
+    };
+    let fds = FileDescriptorSet { file: vec![] };
+    let area_field = |id: &str, shape: &str| ProtobufField {
+      field_num: 1,
+      field_name: "areas".to_string(),
+      wire_type: WireType::LengthDelimited,
+      data: ProtobufFieldData::Message(area_bytes(id, shape), area_descriptor.clone()),
+      additional_data: vec![],
+      descriptor: field_descriptor.clone()
+    };
+    let plugin_config = hashmap!{
+      "protobuf".to_string() => PluginInteractionConfig {
+        pact_configuration: hashmap!{},
+        interaction_configuration: hashmap!{
+          "repeatedFieldKeys".to_string() => json!({ "$.areas": "id" })
+        }
+      }
+    };
+    let rules = MatchingRuleCategory::empty("body");
+    let context = CoreMatchingContext::new(DiffConfig::NoUnexpectedKeys, &rules, &plugin_config);
+
+    let expected = vec![ area_field("1", "square"), area_field("2", "circle") ];
+    let actual = vec![ area_field("2", "circle"), area_field("1", "square") ];
+    let result = compare_message(DocPath::root(), &expected, &actual, &context,
+      &message_descriptor, &fds, false).unwrap();
+    expect!(result).to(be_equal_to(BodyMatchResult::Ok));
+
+    let actual = vec![ area_field("3", "triangle"), area_field("1", "rectangle") ];
+    let result = compare_message(DocPath::root(), &expected, &actual, &context,
+      &message_descriptor, &fds, false).unwrap();
+    let mismatches = result.mismatches().iter()
+      .map(mismatch_message)
+      .collect::<Vec<_>>();
+    expect!(mismatches).to(be_equal_to(vec![
+      "Expected 'rectangle' to be equal to 'square'".to_string(),
+      "Expected repeated field 'areas' to have a value with id '2' but it was not received".to_string(),
+      "Received an unexpected value with id '3' for repeated field 'areas'".to_string()
+    ]));
+
+    let context = CoreMatchingContext::new(DiffConfig::NoUnexpectedKeys, &rules, &hashmap!{});
+    let actual = vec![ area_field("2", "circle"), area_field("1", "square") ];
+    let result = compare_message(DocPath::root(), &expected, &actual, &context,
+      &message_descriptor, &fds, false).unwrap();
+    expect!(result).to_not(be_equal_to(BodyMatchResult::Ok));
+  }
+
+  #[rstest::rstest]
+  #[case::duplicate_actual_keys("id", vec![("1", "square"), ("2", "circle")],
+    vec![("1", "square"), ("1", "square"), ("2", "circle")],
+    vec!["Received more than one value with id '1' for repeated field 'areas'"])]
+  #[case::duplicate_expected_keys("id", vec![("1", "square"), ("1", "circle")],
+    vec![("1", "square")],
+    vec!["Expected repeated field 'areas' has more than one value with id '1'"])]
+  #[case::missing_keys("code", vec![("1", "square")], vec![("1", "square")], vec![
+    "Expected value 0 of repeated field 'areas' does not have the key field 'code' set",
+    "Received a value for repeated field 'areas' that does not have the key field 'code' set"
+  ])]
+  #[test_log::test]
+  fn compare_message_with_repeated_message_field_paired_by_a_key_field_with_duplicate_or_missing_keys(
+    #[case] key_field: &str,
+    #[case] expected_values: Vec<(&str, &str)>,
+    #[case] actual_values: Vec<(&str, &str)>,
+    #[case] expected_mismatches: Vec<&str>
+  ) {
+    let (area_descriptor, field_descriptor) = area_descriptors();
+    let message_descriptor = DescriptorProto {
+      name: Some("AreaList".to_string()),
+      field: vec![field_descriptor.clone()],
+      .. DescriptorProto::default()
+    };
+    let fds = FileDescriptorSet { file: vec![] };
+    let area_fields = |values: &[(&str, &str)]| values.iter()
+      .map(|(id, shape)| ProtobufField {
+        field_num: 1,
+        field_name: "areas".to_string(),
+        wire_type: WireType::LengthDelimited,
+        data: ProtobufFieldData::Message(area_bytes(id, shape), area_descriptor.clone()),
+        additional_data: vec![],
+        descriptor: field_descriptor.clone()
+      })
+      .collect::<Vec<_>>();
+    let plugin_config = hashmap!{
+      "protobuf".to_string() => PluginInteractionConfig {
+        pact_configuration: hashmap!{},
+        interaction_configuration: hashmap!{
+          "repeatedFieldKeys".to_string() => json!({ "$.areas": key_field })
+        }
+      }
+    };
+    let rules = MatchingRuleCategory::empty("body");
+    let context = CoreMatchingContext::new(DiffConfig::NoUnexpectedKeys, &rules, &plugin_config);
+
+    let result = compare_message(DocPath::root(), &area_fields(&expected_values), &area_fields(&actual_values),
+      &context, &message_descriptor, &fds, false).unwrap();
+    let mismatches = result.mismatches().iter()
+      .map(mismatch_message)
+      .collect::<Vec<_>>();
+    expect!(mismatches).to(be_equal_to(expected_mismatches));
+  }
+
+  #[test_log::test]
+  fn compare_message_with_a_nested_repeated_message_field_paired_by_a_key_field() {
+    let (area_descriptor, areas_descriptor) = area_descriptors();
+    let group_descriptor = DescriptorProto {
+      name: Some("Group".to_string()),
+      field: vec![areas_descriptor.clone()],
+      .. DescriptorProto::default()
+    };
+    let groups_descriptor = FieldDescriptorProto {
+      name: Some("groups".to_string()),
+      number: Some(1),
+      label: Some(Repeated as i32),
+      r#type: Some(Type::Message as i32),
+      type_name: Some(".Group".to_string()),
+      .. FieldDescriptorProto::default()
+    };
+    let message_descriptor = DescriptorProto {
+      name: Some("GroupList".to_string()),
+      field: vec![groups_descriptor.clone()],
+      .. DescriptorProto::default()
+    };
+    let fds = FileDescriptorSet {
+      file: vec![
+        FileDescriptorProto {
+          name: Some("groups.proto".to_string()),
+          message_type: vec![area_descriptor.clone(), group_descriptor.clone(), message_descriptor.clone()],
+          syntax: Some("proto3".to_string()),
+          .. FileDescriptorProto::default()
+        }
+      ]
+    };
+    let group_field = |areas: &[(&str, &str)]| {
+      let mut bytes = vec![];
+      for (id, shape) in areas {
+        let area = area_bytes(id, shape);
+        bytes.extend_from_slice(&[10, area.len() as u8]);
+        bytes.extend_from_slice(&area);
+      }
+      ProtobufField {
+        field_num: 1,
+        field_name: "groups".to_string(),
+        wire_type: WireType::LengthDelimited,
+        data: ProtobufFieldData::Message(bytes, group_descriptor.clone()),
+        additional_data: vec![],
+        descriptor: groups_descriptor.clone()
+      }
+    };
+    let plugin_config = hashmap!{
+      "protobuf".to_string() => PluginInteractionConfig {
+        pact_configuration: hashmap!{},
+        interaction_configuration: hashmap!{
+          "repeatedFieldKeys".to_string() => json!({ "$.groups[*].areas": "id" })
+        }
+      }
+    };
+    let rules = MatchingRuleCategory::empty("body");
+    let context = CoreMatchingContext::new(DiffConfig::NoUnexpectedKeys, &rules, &plugin_config);
+
+    let expected = vec![ group_field(&[("1", "square"), ("2", "circle")]) ];
+    let actual = vec![ group_field(&[("2", "circle"), ("1", "square")]) ];
+    let result = compare_message(DocPath::root(), &expected, &actual, &context,
+      &message_descriptor, &fds, false).unwrap();
+    expect!(result).to(be_equal_to(BodyMatchResult::Ok));
+  }
+
+  #[rstest::rstest]
+  #[case::prefix_with_additional_values("prefix", vec!["a", "b", "c"], vec![])]
+  #[case::prefix_out_of_order("prefix", vec!["b", "a"], vec![
+    "Expected 'b' to be equal to 'a'", "Expected 'a' to be equal to 'b'"
+  ])]
+  #[case::prefix_missing_value("prefix", vec!["a"], vec!["Expected field values(1)=\"b\" but was missing"])]
+  #[case::subset_with_additional_values("subset", vec!["c", "b", "a"], vec![])]
+  #[case::subset_missing_value("subset", vec!["c", "a"], vec![
+    "Expected repeated field 'values' to have a value matching \"b\" but none of the received values matched"
+  ])]
+  #[case::subset_repeated_value("subset", vec!["a", "a", "c"], vec![
+    "Expected repeated field 'values' to have a value matching \"b\" but none of the received values matched"
+  ])]
+  #[test_log::test]
+  fn compare_message_with_additional_repeated_values(
+    #[case] mode: &str,
+    #[case] actual_values: Vec<&str>,
+    #[case] expected_mismatches: Vec<&str>
+  ) {
+    let field_descriptor = FieldDescriptorProto {
+      name: Some("values".to_string()),
+      number: Some(1),
+      label: Some(Repeated as i32),
+      r#type: Some(String as i32),
+      .. FieldDescriptorProto::default()
+    };
+    let message_descriptor = DescriptorProto {
+      name: Some("Values".to_string()),
+      field: vec![field_descriptor.clone()],
+      .. DescriptorProto::default()
+    };
+    let fds = FileDescriptorSet { file: vec![] };
+    let string_field = |value: &str| ProtobufField {
+      field_num: 1,
+      field_name: "values".to_string(),
+      wire_type: WireType::LengthDelimited,
+      data: ProtobufFieldData::String(value.to_string()),
+      additional_data: vec![],
+      descriptor: field_descriptor.clone()
+    };
+    let plugin_config = hashmap!{
+      "protobuf".to_string() => PluginInteractionConfig {
+        pact_configuration: hashmap!{},
+        interaction_configuration: hashmap!{
+          "repeatedFieldMatching".to_string() => json!({ "$.values": mode })
+        }
+      }
+    };
+    let rules = MatchingRuleCategory::empty("body");
+    let context = CoreMatchingContext::new(DiffConfig::NoUnexpectedKeys, &rules, &plugin_config);
+
+    let expected = vec![ string_field("a"), string_field("b") ];
+    let actual = actual_values.iter().map(|value| string_field(value)).collect::<Vec<_>>();
+    let result = compare_message(DocPath::root(), &expected, &actual, &context,
+      &message_descriptor, &fds, false).unwrap();
+    let mismatches = result.mismatches().iter()
+      .map(mismatch_message)
+      .collect::<Vec<_>>();
+    expect!(mismatches).to(be_equal_to(expected_mismatches.iter()
+      .map(|mismatch| mismatch.to_string())
+      .collect::<Vec<_>>()));
+  }
+
+  #[rstest::rstest]
+  #[case::values_in_order(vec!["a", "b"], vec![])]
+  #[case::values_out_of_order(vec!["b", "a"], vec![])]
+  #[case::missing_value(vec!["a", "a"], vec![
+    "Expected repeated field 'values' to have a value matching \"b\" but none of the received values matched"
+  ])]
+  fn compare_message_with_subset_repeated_values_pairs_the_values(
+    #[case] actual_values: Vec<&str>,
+    #[case] expected_mismatches: Vec<&str>
+  ) {
+    let field_descriptor = FieldDescriptorProto {
+      name: Some("values".to_string()),
+      number: Some(1),
+      label: Some(Repeated as i32),
+      r#type: Some(String as i32),
+      .. FieldDescriptorProto::default()
+    };
+    let message_descriptor = DescriptorProto {
+      name: Some("Values".to_string()),
+      field: vec![field_descriptor.clone()],
+      .. DescriptorProto::default()
+    };
+    let string_field = |value: &str| ProtobufField {
+      field_num: 1,
+      field_name: "values".to_string(),
+      wire_type: WireType::LengthDelimited,
+      data: ProtobufFieldData::String(value.to_string()),
+      additional_data: vec![],
+      descriptor: field_descriptor.clone()
+    };
+    let plugin_config = hashmap!{
+      "protobuf".to_string() => PluginInteractionConfig {
+        pact_configuration: hashmap!{},
+        interaction_configuration: hashmap!{
+          "repeatedFieldMatching".to_string() => json!({ "$.values": "subset" })
+        }
+      }
+    };
+    // The first expected value matches either actual value, so taking the first match for it
+    // would leave no value for the second one
+    let rules = matchingrules! {
+      "body" => { "$.values[0]" => [ MatchingRule::Regex("[ab]".to_string()) ] }
+    }.rules_for_category("body").unwrap();
+    let context = CoreMatchingContext::new(DiffConfig::NoUnexpectedKeys, &rules, &plugin_config);
+
+    let expected = vec![ string_field("a"), string_field("b") ];
+    let actual = actual_values.iter().map(|value| string_field(value)).collect::<Vec<_>>();
+    let result = compare_message(DocPath::root(), &expected, &actual, &context,
+      &message_descriptor, &FileDescriptorSet::default(), false).unwrap();
+    let mismatches = result.mismatches().iter()
+      .map(mismatch_message)
+      .collect::<Vec<_>>();
+    expect!(mismatches).to(be_equal_to(expected_mismatches.iter()
+      .map(|mismatch| mismatch.to_string())
+      .collect::<Vec<_>>()));
+  }
+
+  #[rstest::rstest]
+  #[case::exact_path("$.items[0].values", Some("subset"))]
+  #[case::star_index("$.items[*].values", Some("subset"))]
+  #[case::star("$.items.*.values", Some("subset"))]
+  #[case::other_field("$.items[*].names", None)]
+  #[case::parent_field("$.items", None)]
+  fn interaction_config_for_path_matches_wildcard_paths(#[case] config_path: &str, #[case] expected: Option<&str>) {
+    let plugin_config = hashmap!{
+      "protobuf".to_string() => PluginInteractionConfig {
+        pact_configuration: hashmap!{},
+        interaction_configuration: hashmap!{
+          "repeatedFieldMatching".to_string() => json!({ config_path: "subset" })
+        }
+      }
+    };
+    let rules = MatchingRuleCategory::empty("body");
+    let context = CoreMatchingContext::new(DiffConfig::NoUnexpectedKeys, &rules, &plugin_config);
+
+    let path = DocPath::root().join("items").join("0").join("values");
+    expect!(interaction_config_for_path("repeatedFieldMatching", &path, &context))
+      .to(be_equal_to(expected.map(|value| json!(value))));
+    expect!(interaction_config_for_path("repeatedFieldKeys", &path, &context)).to(be_none());
+  }
+
+  #[rstest::rstest]
+  #[case::same_value("2024-01-01T00:00:00Z", 1704067200, 0, true)]
+  #[case::at_the_tolerance("2024-01-01T00:00:05Z", 1704067195, 0, true)]
+  #[case::beyond_the_tolerance("2024-01-01T00:00:05.001Z", 1704067195, 0, false)]
+  #[case::timestamp_beyond_the_tolerance("2024-01-01T00:00:00Z", 1704067194, 999_999_999, false)]
+  fn compare_message_with_a_timestamp_tolerance(
+    #[case] actual_created: &str,
+    #[case] actual_seconds: i64,
+    #[case] actual_nanos: i32,
+    #[case] matches: bool
+  ) {
+    let timestamp_descriptor = DescriptorProto {
+      name: Some("Timestamp".to_string()),
+      field: vec![
+        FieldDescriptorProto {
+          name: Some("seconds".to_string()),
+          number: Some(1),
+          r#type: Some(Type::Int64 as i32),
+          .. FieldDescriptorProto::default()
+        },
+        FieldDescriptorProto {
+          name: Some("nanos".to_string()),
+          number: Some(2),
+          r#type: Some(Type::Int32 as i32),
+          .. FieldDescriptorProto::default()
+        }
+      ],
+      .. DescriptorProto::default()
+    };
+    let created_descriptor = FieldDescriptorProto {
+      name: Some("created".to_string()),
+      number: Some(1),
+      label: Some(Optional as i32),
+      r#type: Some(String as i32),
+      .. FieldDescriptorProto::default()
+    };
+    let updated_descriptor = FieldDescriptorProto {
+      name: Some("updated".to_string()),
+      number: Some(2),
+      label: Some(Optional as i32),
+      r#type: Some(Type::Message as i32),
+      type_name: Some(".google.protobuf.Timestamp".to_string()),
+      .. FieldDescriptorProto::default()
+    };
+    let message_descriptor = DescriptorProto {
+      name: Some("Event".to_string()),
+      field: vec![ created_descriptor.clone(), updated_descriptor.clone() ],
+      .. DescriptorProto::default()
+    };
+    let fds = FileDescriptorSet {
+      file: vec![
+        FileDescriptorProto {
+          name: Some("google/protobuf/timestamp.proto".to_string()),
+          package: Some("google.protobuf".to_string()),
+          message_type: vec![ timestamp_descriptor.clone() ],
+          .. FileDescriptorProto::default()
+        }
+      ]
+    };
+    let event = |created: &str, seconds: i64, nanos: i32| {
+      let mut timestamp = BytesMut::new();
+      prost::encoding::int64::encode(1, &seconds, &mut timestamp);
+      prost::encoding::int32::encode(2, &nanos, &mut timestamp);
+      vec![
+        ProtobufField {
+          field_num: 1,
+          field_name: "created".to_string(),
+          wire_type: WireType::LengthDelimited,
+          data: ProtobufFieldData::String(created.to_string()),
+          additional_data: vec![],
+          descriptor: created_descriptor.clone()
+        },
+        ProtobufField {
+          field_num: 2,
+          field_name: "updated".to_string(),
+          wire_type: WireType::LengthDelimited,
+          data: ProtobufFieldData::Message(timestamp.to_vec(), timestamp_descriptor.clone()),
+          additional_data: vec![],
+          descriptor: updated_descriptor.clone()
+        }
+      ]
+    };
+    let rules = MatchingRuleCategory::empty("body");
+    let plugin_config = hashmap!{
+      "protobuf".to_string() => PluginInteractionConfig {
+        pact_configuration: hashmap!{},
+        interaction_configuration: hashmap!{
+          "timestampTolerance".to_string() => json!({ "$.created": 5, "$.updated": "5" })
+        }
+      }
+    };
+    let context = CoreMatchingContext::new(DiffConfig::NoUnexpectedKeys, &rules, &plugin_config);
+
+    let expected = event("2024-01-01T00:00:00Z", 1704067200, 0);
+    let actual = event(actual_created, actual_seconds, actual_nanos);
+    let result = compare_message(DocPath::root(), &expected, &actual, &context,
+      &message_descriptor, &fds, false).unwrap();
+    if matches {
+      expect!(result).to(be_equal_to(BodyMatchResult::Ok));
+    } else {
+      let mismatches = result.mismatches();
+      expect!(mismatches.len()).to(be_equal_to(1));
+      expect!(mismatch_message(&mismatches[0]).contains("to be within 5 second(s) of 2024-01-01T00:00:00+00:00"))
+        .to(be_true());
+    }
+  }
+
+  #[rstest::rstest]
+  #[case::within_the_tolerance(3, true)]
+  #[case::beyond_the_tolerance(10, false)]
+  #[test_log::test]
+  fn match_message_with_a_timestamp_tolerance_for_repeated_and_map_fields(#[case] offset: i64, #[case] matches: bool) {
+    let fds = FileDescriptorSet {
+      file: vec![
+        FileDescriptorProto {
+          name: Some("test.proto".to_string()),
+          message_type: vec![
+            DescriptorProto {
+              name: Some("Events".to_string()),
+              field: vec![
+                FieldDescriptorProto {
+                  name: Some("times".to_string()),
+                  number: Some(1),
+                  label: Some(Repeated as i32),
+                  r#type: Some(Type::String as i32),
+                  .. FieldDescriptorProto::default()
+                },
+                FieldDescriptorProto {
+                  name: Some("by_name".to_string()),
+                  number: Some(2),
+                  label: Some(Repeated as i32),
+                  r#type: Some(Type::Message as i32),
+                  type_name: Some(".Events.ByNameEntry".to_string()),
+                  .. FieldDescriptorProto::default()
+                }
+              ],
+              nested_type: vec![
+                DescriptorProto {
+                  name: Some("ByNameEntry".to_string()),
+                  field: vec![
+                    FieldDescriptorProto {
+                      name: Some("key".to_string()),
+                      number: Some(1),
+                      label: Some(Optional as i32),
+                      r#type: Some(Type::String as i32),
+                      .. FieldDescriptorProto::default()
+                    },
+                    FieldDescriptorProto {
+                      name: Some("value".to_string()),
+                      number: Some(2),
+                      label: Some(Optional as i32),
+                      r#type: Some(Type::String as i32),
+                      .. FieldDescriptorProto::default()
+                    }
+                  ],
+                  options: Some(MessageOptions { map_entry: Some(true), .. MessageOptions::default() }),
+                  .. DescriptorProto::default()
+                }
+              ],
+              .. DescriptorProto::default()
+            }
+          ],
+          syntax: Some("proto3".to_string()),
+          .. FileDescriptorProto::default()
+        }
+      ]
+    };
+    let events = |offset: i64| {
+      let timestamp = |hour: i64| (DateTime::from_timestamp(1704067200 + hour * 3600 + offset, 0).unwrap())
+        .to_rfc3339_opts(chrono::SecondsFormat::Secs, true);
+      let mut buffer = BytesMut::new();
+      prost::encoding::string::encode(1, &timestamp(0), &mut buffer);
+      prost::encoding::string::encode(1, &timestamp(1), &mut buffer);
+      let mut entry = BytesMut::new();
+      prost::encoding::string::encode(1, &"a".to_string(), &mut entry);
+      prost::encoding::string::encode(2, &timestamp(2), &mut entry);
+      prost::encoding::bytes::encode(2, &entry.to_vec(), &mut buffer);
+      buffer.freeze()
+    };
+    let interaction_config = hashmap!{
+      "timestampTolerance".to_string() => json!({ "$.times": 5, "$.by_name.*": 5 })
+    };
+
+    let result = match_message(".Events", &fds, &mut events(0), &mut events(offset),
+      &MatchingRuleCategory::empty("body"), false, false, &interaction_config).unwrap();
+    let mismatches = result.mismatches().iter()
+      .map(|mismatch| match mismatch {
+        BodyMismatch { path, .. } => path.clone(),
+        _ => mismatch.description()
+      })
+      .sorted()
+      .collect::<Vec<_>>();
+    if matches {
+      expect!(mismatches).to(be_equal_to(Vec::<std::string::String>::new()));
+    } else {
+      expect!(mismatches).to(be_equal_to(vec!["$.by_name.a", "$.times[0]", "$.times[1]"]));
+    }
+  }
+
+  #[rstest::rstest]
+  #[case::int_at_the_inclusive_min(1, 0.5, true)]
+  #[case::int_below_the_inclusive_min(0, 0.5, false)]
+  #[case::int_below_the_exclusive_max(9, 0.5, true)]
+  #[case::int_at_the_exclusive_max(10, 0.5, false)]
+  #[case::double_at_the_inclusive_max(5, 1.0, true)]
+  #[case::double_above_the_inclusive_max(5, 1.001, false)]
+  #[case::double_above_the_exclusive_min(5, 0.001, true)]
+  #[case::double_at_the_exclusive_min(5, 0.0, false)]
+  fn compare_message_with_number_ranges(#[case] actual_count: i32, #[case] actual_ratio: f64, #[case] matches: bool) {
+    let count_descriptor = FieldDescriptorProto {
+      name: Some("count".to_string()),
+      number: Some(1),
+      label: Some(Optional as i32),
+      r#type: Some(Type::Int32 as i32),
+      .. FieldDescriptorProto::default()
+    };
+    let ratio_descriptor = FieldDescriptorProto {
+      name: Some("ratio".to_string()),
+      number: Some(2),
+      label: Some(Optional as i32),
+      r#type: Some(Type::Double as i32),
+      .. FieldDescriptorProto::default()
+    };
+    let message_descriptor = DescriptorProto {
+      name: Some("Stats".to_string()),
+      field: vec![ count_descriptor.clone(), ratio_descriptor.clone() ],
+      .. DescriptorProto::default()
+    };
+    let stats = |count: i32, ratio: f64| vec![
+      ProtobufField {
+        field_num: 1,
+        field_name: "count".to_string(),
+        wire_type: WireType::Varint,
+        data: ProtobufFieldData::Integer32(count),
+        additional_data: vec![],
+        descriptor: count_descriptor.clone()
+      },
+      ProtobufField {
+        field_num: 2,
+        field_name: "ratio".to_string(),
+        wire_type: WireType::SixtyFourBit,
+        data: ProtobufFieldData::Double(ratio),
+        additional_data: vec![],
+        descriptor: ratio_descriptor.clone()
+      }
+    ];
+    let rules = matchingrules! {
+      "body" => {
+        "$.count" => [ MatchingRule::Number ],
+        "$.ratio" => [ MatchingRule::Number ]
+      }
+    }.rules_for_category("body").unwrap();
+    let plugin_config = hashmap!{
+      "protobuf".to_string() => PluginInteractionConfig {
+        pact_configuration: hashmap!{},
+        interaction_configuration: hashmap!{
+          "numberRanges".to_string() => json!({
+            "$.count": { "min": 1, "minExclusive": false, "max": 10, "maxExclusive": true },
+            "$.ratio": { "min": 0, "minExclusive": true, "max": 1, "maxExclusive": false }
+          })
+        }
+      }
+    };
+    let context = CoreMatchingContext::new(DiffConfig::NoUnexpectedKeys, &rules, &plugin_config);
+
+    let expected = stats(5, 0.5);
+    let actual = stats(actual_count, actual_ratio);
+    let result = compare_message(DocPath::root(), &expected, &actual, &context,
+      &message_descriptor, &FileDescriptorSet::default(), false).unwrap();
+    if matches {
+      expect!(result).to(be_equal_to(BodyMatchResult::Ok));
+    } else {
+      let mismatches = result.mismatches();
+      expect!(mismatches.len()).to(be_equal_to(1));
+      let expected_message = if (1..10).contains(&actual_count) {
+        format!("Expected {} to be greater than 0 and less than or equal to 1", actual_ratio)
+      } else {
+        format!("Expected {} to be greater than or equal to 1 and less than 10", actual_count)
+      };
+      expect!(mismatch_message(&mismatches[0])).to(be_equal_to(expected_message));
+    }
+  }
+
+  #[rstest::rstest]
+  #[case::field_range(json!({ "$.values": { "min": 1, "max": 10 } }), vec![1, 10, 11, 0], vec!["$.values[2]", "$.values[3]"])]
+  #[case::index_range(json!({ "$.values[0]": { "min": 1, "max": 10 }, "$.values": { "min": 5, "max": 20 } }), vec![1, 4, 11, 0],
+    vec!["$.values[1]", "$.values[3]"])]
+  fn compare_message_with_a_number_range_for_a_repeated_field(
+    #[case] ranges: Value,
+    #[case] actual_values: Vec<i32>,
+    #[case] mismatched_paths: Vec<&str>
+  ) {
+    let values_descriptor = FieldDescriptorProto {
+      name: Some("values".to_string()),
+      number: Some(1),
+      label: Some(Repeated as i32),
+      r#type: Some(Type::Int32 as i32),
+      .. FieldDescriptorProto::default()
+    };
+    let message_descriptor = DescriptorProto {
+      name: Some("Values".to_string()),
+      field: vec![ values_descriptor.clone() ],
+      .. DescriptorProto::default()
+    };
+    let values = |values: &[i32]| values.iter()
+      .map(|value| ProtobufField {
+        field_num: 1,
+        field_name: "values".to_string(),
+        wire_type: WireType::Varint,
+        data: ProtobufFieldData::Integer32(*value),
+        additional_data: vec![],
+        descriptor: values_descriptor.clone()
+      })
+      .collect::<Vec<_>>();
+    let rules = matchingrules! {
+      "body" => { "$.values.*" => [ MatchingRule::Number ] }
+    }.rules_for_category("body").unwrap();
+    let plugin_config = hashmap!{
+      "protobuf".to_string() => PluginInteractionConfig {
+        pact_configuration: hashmap!{},
+        interaction_configuration: hashmap!{ "numberRanges".to_string() => ranges }
+      }
+    };
+    let context = CoreMatchingContext::new(DiffConfig::NoUnexpectedKeys, &rules, &plugin_config);
+
+    let result = compare_message(DocPath::root(), &values(&[5]), &values(&actual_values), &context,
+      &message_descriptor, &FileDescriptorSet::default(), false).unwrap();
+    let paths = result.mismatches().iter()
+      .map(|mismatch| match mismatch {
+        BodyMismatch { path, .. } => path.clone(),
+        _ => panic!("Expected a body mismatch, got {:?}", mismatch)
+      })
+      .sorted()
+      .collect::<Vec<_>>();
+    expect!(paths).to(be_equal_to(mismatched_paths.iter().map(|path| path.to_string()).collect::<Vec<_>>()));
+  }
+
+  #[rstest::rstest]
+  #[case::at_the_inclusive_max(0.1, true)]
+  #[case::at_the_inclusive_min(0.0, true)]
+  #[case::above_the_max(0.100001, false)]
+  #[case::below_the_min(-0.1, false)]
+  fn compare_number_range_compares_float_fields_as_floats(#[case] value: f32, #[case] matches: bool) {
+    let field = ProtobufField {
+      field_num: 1,
+      field_name: "ratio".to_string(),
+      wire_type: WireType::ThirtyTwoBit,
+      data: ProtobufFieldData::Float(value),
+      additional_data: vec![],
+      descriptor: FieldDescriptorProto::default()
+    };
+    let range = NumberRange { min: Some(0.0), max: Some(0.1), .. NumberRange::default() };
+    let mismatches = compare_number_range(&DocPath::new_unwrap("$.ratio"), &field, &range);
+    expect!(mismatches.is_empty()).to(be_equal_to(matches));
+  }
+
+  #[test]
+  fn interaction_config_for_part_selects_the_number_ranges_for_the_part() {
+    let config = hashmap!{
+      "service".to_string() => json!(".area_calculator.Calculator/calculateOne"),
+      "numberRanges".to_string() => json!({
+        "request": { "$.count": { "min": 1, "max": 10 } }
+      })
+    };
+    expect!(interaction_config_for_part(&config, "request")).to(be_equal_to(hashmap!{
+      "service".to_string() => json!(".area_calculator.Calculator/calculateOne"),
+      "numberRanges".to_string() => json!({ "$.count": { "min": 1, "max": 10 } })
+    }));
+    expect!(interaction_config_for_part(&config, "response")).to(be_equal_to(hashmap!{
+      "service".to_string() => json!(".area_calculator.Calculator/calculateOne")
+    }));
+  }
+
+  #[rstest::rstest]
+  #[case::nested_path(json!(["$.audit.created_at"]))]
+  #[case::wildcard_path(json!(["$.*.created_at"]))]
+  fn compare_message_ignoring_configured_fields(#[case] ignore_fields: Value) {
+    let string_field = |name: &str, number: i32| FieldDescriptorProto {
+      name: Some(name.to_string()),
+      number: Some(number),
+      label: Some(Optional as i32),
+      r#type: Some(String as i32),
+      .. FieldDescriptorProto::default()
+    };
+    let audit_descriptor = DescriptorProto {
+      name: Some("Audit".to_string()),
+      field: vec![ string_field("created_at", 1), string_field("user", 2) ],
+      .. DescriptorProto::default()
+    };
+    let audit_field_descriptor = FieldDescriptorProto {
+      name: Some("audit".to_string()),
+      number: Some(2),
+      label: Some(Optional as i32),
+      r#type: Some(Type::Message as i32),
+      type_name: Some(".Audit".to_string()),
+      .. FieldDescriptorProto::default()
+    };
+    let message_descriptor = DescriptorProto {
+      name: Some("Order".to_string()),
+      field: vec![ string_field("id", 1), audit_field_descriptor.clone() ],
+      .. DescriptorProto::default()
+    };
+    let fds = FileDescriptorSet { file: vec![] };
+    let order = |id: &str, created_at: &str| {
+      let mut bytes = vec![10, created_at.len() as u8];
+      bytes.extend_from_slice(created_at.as_bytes());
+      bytes.extend_from_slice(&[18, 4]);
+      bytes.extend_from_slice("fred".as_bytes());
+      vec![
+        ProtobufField {
+          field_num: 1,
+          field_name: "id".to_string(),
+          wire_type: WireType::LengthDelimited,
+          data: ProtobufFieldData::String(id.to_string()),
+          additional_data: vec![],
+          descriptor: string_field("id", 1)
+        },
+        ProtobufField {
+          field_num: 2,
+          field_name: "audit".to_string(),
+          wire_type: WireType::LengthDelimited,
+          data: ProtobufFieldData::Message(bytes, audit_descriptor.clone()),
+          additional_data: vec![],
+          descriptor: audit_field_descriptor.clone()
+        }
+      ]
+    };
+    let rules = MatchingRuleCategory::empty("body");
+    let plugin_config = hashmap!{
+      "protobuf".to_string() => PluginInteractionConfig {
+        pact_configuration: hashmap!{},
+        interaction_configuration: hashmap!{ "ignoreFields".to_string() => ignore_fields }
+      }
+    };
+    let context = CoreMatchingContext::new(DiffConfig::NoUnexpectedKeys, &rules, &plugin_config);
+    let default_context = CoreMatchingContext::new(DiffConfig::NoUnexpectedKeys, &rules, &hashmap!{});
+
+    let expected = order("100", "2024-01-01T00:00:00Z");
+    let actual = order("100", "2024-06-30T12:34:56Z");
+    let result = compare_message(DocPath::root(), &expected, &actual, &context,
+      &message_descriptor, &fds, false).unwrap();
+    expect!(result).to(be_equal_to(BodyMatchResult::Ok));
+
+    let result = compare_message(DocPath::root(), &expected, &actual, &default_context,
+      &message_descriptor, &fds, false).unwrap();
+    expect!(result.mismatches().len()).to(be_equal_to(1));
+
+    let actual = order("200", "2024-06-30T12:34:56Z");
+    let result = compare_message(DocPath::root(), &expected, &actual, &context,
+      &message_descriptor, &fds, false).unwrap();
+    let mismatches = result.mismatches().iter()
+      .map(mismatch_message)
+      .collect::<Vec<_>>();
+    expect!(mismatches).to(be_equal_to(vec!["Expected '200' to be equal to '100'".to_string()]));
+  }
+
+  #[test_log::test]
+  fn match_message_applies_matching_rules_to_the_members_of_a_struct_field() {
+    let bytes = BASE64.decode(DESCRIPTORS).unwrap();
+    let fds = FileDescriptorSet::decode(bytes.as_slice()).unwrap();
+    let config = |count: f64, price: f64| {
+      let config = pact_plugin_driver::proto::PluginConfiguration {
+        interaction_configuration: Some(prost_types::Struct {
+          fields: maplit::btreemap!{
+            "count".to_string() => prost_types::Value { kind: Some(prost_types::value::Kind::NumberValue(count)) },
+            "price".to_string() => prost_types::Value { kind: Some(prost_types::value::Kind::NumberValue(price)) }
+          }
+        }),
+        pact_configuration: None
+      };
+      Bytes::from(config.encode_to_vec())
+    };
+    let matching_rules = matchingrules_list! {
+      "body";
+      "$.interactionConfiguration.count" => [ MatchingRule::Integer ],
+      "$.interactionConfiguration.price" => [ MatchingRule::Number ]
+    };
+
+    let expected = config(1.0, 1.5);
+    let result = match_message(".io.pact.plugin.PluginConfiguration", &fds, &mut expected.clone(),
+      &mut config(200.0, 3.0), &matching_rules, false, false, &hashmap!{}).unwrap();
+    expect!(result).to(be_equal_to(BodyMatchResult::Ok));
+
+    let result = match_message(".io.pact.plugin.PluginConfiguration", &fds, &mut expected.clone(),
+      &mut config(2.5, 3.0), &matching_rules, false, false, &hashmap!{}).unwrap();
+    let mismatches = result.mismatches().iter()
+      .map(|mismatch| match mismatch {
+        pact_matching::Mismatch::BodyMismatch { path, .. } => path.clone(),
+        _ => mismatch.description()
+      })
+      .collect::<Vec<_>>();
+    expect!(mismatches).to(be_equal_to(vec![ "$.interactionConfiguration.count".to_string() ]));
+    let matching_rules = matchingrules_list! {
+      "body";
+      "$.interactionConfiguration.count" => [ MatchingRule::Integer ],
+      "$.interactionConfiguration.price" => [ MatchingRule::Decimal ]
+    };
+    let result = match_message(".io.pact.plugin.PluginConfiguration", &fds, &mut expected.clone(),
+      &mut config(2.0, 2.0), &matching_rules, false, false, &hashmap!{}).unwrap();
+    expect!(result).to(be_equal_to(BodyMatchResult::Ok));
+  }
+
+  #[rstest::rstest]
+  #[case("application/protobuf;message=.test.Test", false, true)]
+  #[case("application/protobuf;message=Test", false, true)]
+  #[case("application/x-protobuf;message=.test.Test", true, true)]
+  #[case("application/protobuf;message=.test.Other", false, true)]
+  #[case("application/protobuf;message=.other.Test", false, true)]
+  #[case("application/protobuf;message=Other", false, true)]
+  #[case("application/protobuf;message=.test.Other", true, false)]
+  #[case("application/protobuf;message=.other.Test", true, false)]
+  #[case("application/protobuf;message=Other", true, false)]
+  #[case("application/protobuf", false, true)]
+  #[case("application/protobuf", true, false)]
+  #[case("", false, true)]
+  #[case("", true, false)]
+  fn match_content_type_message_test(#[case] actual: &str, #[case] strict: bool, #[case] matches: bool) {
+    let expected = ContentType::parse("application/protobuf;message=.test.Test").unwrap();
+    let actual = ContentType::parse(actual).ok();
+    let result = match_content_type_message(&expected, actual.as_ref(), strict);
+    expect!(result == BodyMatchResult::Ok).to(be_equal_to(matches));
+  }
+
+  #[test]
+  fn match_content_type_message_reports_the_message_types() {
+    let expected = ContentType::parse("application/protobuf;message=.test.Test").unwrap();
+    let actual = ContentType::parse("application/protobuf;message=.test.Other").unwrap();
+    let result = match_content_type_message(&expected, Some(&actual), true);
+    expect!(result).to(be_equal_to(BodyMatchResult::BodyTypeMismatch {
+      expected_type: "application/protobuf;message=.test.Test".to_string(),
+      actual_type: "application/protobuf;message=.test.Other".to_string(),
+      message: "Expected a message of type '.test.Test' but the content type declared '.test.Other'".to_string(),
+      expected: None,
+      actual: None
+    }));
+
+    let actual = ContentType::parse("application/protobuf").unwrap();
+    let result = match_content_type_message(&expected, Some(&actual), true);
+    expect!(result).to(be_equal_to(BodyMatchResult::BodyTypeMismatch {
+      expected_type: "application/protobuf;message=.test.Test".to_string(),
+      actual_type: "application/protobuf".to_string(),
+      message: "Expected a message of type '.test.Test' but the content type 'application/protobuf' did not declare a message type".to_string(),
+      expected: None,
+      actual: None
+    }));
+  }
 }
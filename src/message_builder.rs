@@ -1,7 +1,8 @@
 //! Builder for creating protobuf messages based on a descriptor
 
+use std::cmp::Ordering;
 use std::collections::btree_map::Entry;
-use std::collections::BTreeMap;
+use std::collections::{BTreeMap, HashMap};
 
 use anyhow::anyhow;
 use bytes::{BufMut, Bytes, BytesMut};
@@ -13,6 +14,7 @@ use prost_types::{DescriptorProto, EnumDescriptorProto, FieldDescriptorProto, Fi
 use prost_types::field_descriptor_proto::Type;
 use tracing::{trace, warn};
 
+use crate::editions::MessageFeatures;
 use crate::utils::{last_name, should_be_packed_type, display_bytes};
 
 /// Enum to set what type of field the value is for
@@ -49,6 +51,8 @@ pub struct MessageBuilder {
   /// Message name
   pub message_name: String,
   pub(crate) fields: BTreeMap<String, FieldValueInner>,
+  /// If the message is configured using the canonical Protobuf JSON form
+  pub(crate) canonical_json: bool
 }
 
 impl MessageBuilder {
@@ -58,7 +62,8 @@ impl MessageBuilder {
       file_descriptor: file_descriptor.clone(),
       descriptor: descriptor.clone(),
       message_name: message_name.to_string(),
-      fields: btreemap!{}
+      fields: btreemap!{},
+      canonical_json: false
     }
   }
 
@@ -250,7 +255,9 @@ impl MessageBuilder {
       let value_proto = entry_proto.field.iter().find(|f| f.name.clone().unwrap_or_default() == "value")
         .ok_or_else(|| anyhow!("Did not find the field descriptor for the value for the map field {} in the Protobuf descriptor", entry_type_name))?;
 
+      // Map entries are sorted by key, so the same map is always encoded to the same bytes
       let entries = field_value.values.iter().tuples::<(_, _)>()
+        .sorted_by(|(k1, _), (k2, _)| compare_map_keys(&k1.rtype, &k2.rtype))
         .map(|(k, v)| {
           MessageFieldValue {
             name: entry_name.to_string(),
@@ -272,7 +279,8 @@ impl MessageBuilder {
                   field_type: MessageFieldValueType::Normal,
                   proto_type: value_proto.r#type()
                 }
-              }
+              },
+              canonical_json: self.canonical_json
             }))
           }
         }).collect();
@@ -291,7 +299,8 @@ impl MessageBuilder {
   fn encode_repeated_field(&self, buffer: &mut BytesMut, field_value: &FieldValueInner) -> anyhow::Result<()> {
     trace!(">> encode_repeated_field({:?})", field_value);
     if !field_value.values.is_empty() {
-      if should_be_packed_type(field_value.proto_type) {
+      let features = MessageFeatures::for_file(&self.file_descriptor);
+      if should_be_packed_type(field_value.proto_type) && features.is_packed(&field_value.descriptor) {
         self.encode_packed_field(buffer, field_value)?;
       } else {
         for value in &field_value.values {
@@ -302,15 +311,24 @@ impl MessageBuilder {
     Ok(())
   }
 
-  /// Generate a markdown representation of the message
+  /// Generate a markdown representation of the message. If the file descriptor has source code
+  /// info (i.e. protoc was invoked with `--include_source_info`), any leading comments for the
+  /// fields are included.
   pub fn generate_markup(&self, indent: &str) -> anyhow::Result<String> {
     let mut buffer = String::new();
+    let comments = field_comments(&self.file_descriptor, &self.descriptor);
 
     buffer.push_str(format!("```protobuf\n{}message {} {{\n", indent, self.message_name).as_str());
 
     for (name, inner) in self.fields.iter()
       .sorted_by(|(_, a), (_, b)| Ord::cmp(&a.descriptor.number, &b.descriptor.number)) {
       if let Some(field_num) = inner.descriptor.number {
+        if let Some(comment) = comments.get(&field_num) {
+          for line in comment.lines() {
+            buffer.push_str(format!("{}    // {}", indent, line.trim()).trim_end());
+            buffer.push('\n');
+          }
+        }
         match inner.field_type {
           MessageFieldValueType::Normal => buffer.push_str(format!("{}    {} {} = {};\n", indent, field_type_name(inner)?, name, field_num).as_str()),
           MessageFieldValueType::Map => buffer.push_str(format!("{}    map<{}> {} = {};\n", indent, field_type_name(inner)?, name, field_num).as_str()),
@@ -435,7 +453,12 @@ impl MessageBuilder {
 }
 
 fn field_type_name(field: &FieldValueInner) -> anyhow::Result<String> {
-  Ok(match field.proto_type {
+  proto_type_name(field.proto_type, &field.descriptor)
+}
+
+/// Name of the Protobuf type to use in the interaction markup
+pub(crate) fn proto_type_name(proto_type: Type, descriptor: &FieldDescriptorProto) -> anyhow::Result<String> {
+  Ok(match proto_type {
     Type::Double => "double".to_string(),
     Type::Float => "float".to_string(),
     Type::Int64 => "int64".to_string(),
@@ -447,14 +470,14 @@ fn field_type_name(field: &FieldValueInner) -> anyhow::Result<String> {
     Type::String => "string".to_string(),
     Type::Group => "group".to_string(),
     Type::Message => {
-      let message = field.descriptor.type_name.as_ref()
+      let message = descriptor.type_name.as_ref()
         .ok_or_else(|| anyhow!("Type name is missing from the descriptor for message field"))?;
       format!("message {}", message)
     },
     Type::Bytes => "bytes".to_string(),
     Type::Uint32 => "uint32".to_string(),
     Type::Enum => {
-      let enum_type_name = field.descriptor.type_name.as_ref()
+      let enum_type_name = descriptor.type_name.as_ref()
         .ok_or_else(|| anyhow!("Type name is missing from the descriptor for enum field"))?;
       format!("enum {}", enum_type_name)
     }
@@ -494,6 +517,20 @@ pub enum RType {
   Struct(prost_types::Struct)
 }
 
+/// Orders the keys of map entries. Integer and boolean keys are ordered by value, and string keys
+/// lexicographically. Any other keys are treated as equal, which keeps their order.
+fn compare_map_keys(a: &RType, b: &RType) -> Ordering {
+  match (a, b) {
+    (RType::String(a), RType::String(b)) => a.cmp(b),
+    (RType::Boolean(a), RType::Boolean(b)) => a.cmp(b),
+    (RType::UInteger32(a), RType::UInteger32(b)) => a.cmp(b),
+    (RType::Integer32(a), RType::Integer32(b)) => a.cmp(b),
+    (RType::UInteger64(a), RType::UInteger64(b)) => a.cmp(b),
+    (RType::Integer64(a), RType::Integer64(b)) => a.cmp(b),
+    _ => Ordering::Equal
+  }
+}
+
 impl RType {
   /// Convert this value to a double
   pub fn as_f64(&self) -> anyhow::Result<f64> {
@@ -718,6 +755,51 @@ impl MessageFieldValue {
   }
 }
 
+/// Returns the leading comments for the fields of the message, keyed by field number, from the
+/// source code info of the file descriptor. Returns an empty map if there is no source code info.
+fn field_comments(file_descriptor: &FileDescriptorProto, descriptor: &DescriptorProto) -> HashMap<i32, String> {
+  let source_code_info = match &file_descriptor.source_code_info {
+    Some(info) => info,
+    None => return HashMap::default()
+  };
+  // Field 4 of the file descriptor is the message types
+  let message_path = file_descriptor.message_type.iter().enumerate()
+    .find_map(|(index, message)| message_path(message, descriptor, vec![4, index as i32]));
+  let message_path = match message_path {
+    Some(path) => path,
+    None => return HashMap::default()
+  };
+
+  source_code_info.location.iter()
+    .filter_map(|location| {
+      // Field 2 of a message descriptor is the fields
+      let (field_index, prefix) = location.path.split_last()?;
+      if prefix.len() == message_path.len() + 1 && prefix.starts_with(&message_path) && prefix.last() == Some(&2) {
+        let comment = location.leading_comments.as_deref()?.trim();
+        let field = descriptor.field.get(*field_index as usize)?;
+        (!comment.is_empty()).then(|| (field.number(), comment.to_string()))
+      } else {
+        None
+      }
+    })
+    .collect()
+}
+
+/// Returns the source code info path to the target message descriptor, if it is the message or
+/// one of its nested types (which are field 3 of a message descriptor)
+fn message_path(message: &DescriptorProto, target: &DescriptorProto, path: Vec<i32>) -> Option<Vec<i32>> {
+  if message == target {
+    Some(path)
+  } else {
+    message.nested_type.iter().enumerate()
+      .find_map(|(index, nested)| {
+        let mut nested_path = path.clone();
+        nested_path.extend([3, index as i32]);
+        message_path(nested, target, nested_path)
+      })
+  }
+}
+
 #[cfg(test)]
 pub(crate) mod tests {
   use base64::Engine;
@@ -744,12 +826,16 @@ pub(crate) mod tests {
     FileDescriptorProto,
     FileDescriptorSet,
     MessageOptions,
-    OneofDescriptorProto
+    OneofDescriptorProto,
+    SourceCodeInfo
   };
   use prost_types::field_descriptor_proto::Label::Optional;
+  use prost_types::source_code_info::Location;
   use prost_types::value::Kind;
   use trim_margin::MarginTrimmable;
 
+  use crate::editions::decode_file_descriptor_set;
+  use crate::editions::tests::EDITIONS_DESCRIPTORS;
   use crate::message_builder::{MessageBuilder, MessageFieldValue, RType};
   use crate::message_builder::MessageFieldValueType::Repeated;
   use crate::message_decoder::{decode_message, ProtobufFieldData};
@@ -1241,6 +1327,72 @@ pub(crate) mod tests {
     fds.file.iter().find(|fd| fd.name.clone().unwrap_or_default() == file_name).cloned()
   }
 
+  #[test]
+  fn encode_message_is_deterministic() {
+    let map_field = FieldDescriptorProto {
+      name: Some("labels".to_string()),
+      number: Some(3),
+      label: Some(field_descriptor_proto::Label::Repeated as i32),
+      r#type: Some(field_descriptor_proto::Type::Message as i32),
+      type_name: Some(".LabelsEntry".to_string()),
+      .. FieldDescriptorProto::default()
+    };
+    let name_field = string_field_descriptor!("name", 1);
+    let count_field = i32_field_descriptor!("count", 2);
+    let descriptor = DescriptorProto {
+      name: Some("Labelled".to_string()),
+      field: vec![ name_field.clone(), count_field.clone(), map_field.clone() ],
+      nested_type: vec![
+        DescriptorProto {
+          name: Some("LabelsEntry".to_string()),
+          field: vec![
+            i32_field_descriptor!("key", 1),
+            string_field_descriptor!("value", 2)
+          ],
+          options: Some(MessageOptions {
+            map_entry: Some(true),
+            .. MessageOptions::default()
+          }),
+          .. DescriptorProto::default()
+        }
+      ],
+      .. DescriptorProto::default()
+    };
+    let file_descriptor = FileDescriptorProto::default();
+    let value = |rtype: RType| MessageFieldValue { name: "".to_string(), raw_value: None, rtype };
+    let build = |keys: &[i32], fields_reversed: bool| {
+      let mut builder = MessageBuilder::new(&descriptor, "Labelled", &file_descriptor);
+      for key in keys {
+        builder.add_map_field_value(&map_field, "labels", value(RType::Integer32(*key)),
+          value(RType::String(format!("label {}", key))));
+      }
+      if fields_reversed {
+        builder.set_field_value(&count_field, "count", value(RType::Integer32(2)));
+        builder.set_field_value(&name_field, "name", value(RType::String("test".to_string())));
+      } else {
+        builder.set_field_value(&name_field, "name", value(RType::String("test".to_string())));
+        builder.set_field_value(&count_field, "count", value(RType::Integer32(2)));
+      }
+      builder.encode_message().unwrap()
+    };
+
+    let bytes = build(&[10, 2, -1], false);
+    expect!(build(&[10, 2, -1], false)).to(be_equal_to(bytes.clone()));
+    expect!(build(&[2, -1, 10], true)).to(be_equal_to(bytes.clone()));
+
+    // fields are in field number order, and the map entries are in key order
+    let mut buffer = bytes.clone();
+    let fields = decode_message(&mut buffer, &descriptor, &FileDescriptorSet { file: vec![] }).unwrap();
+    expect!(fields.iter().map(|field| field.field_num).collect::<Vec<_>>()).to(be_equal_to(vec![1, 2, 3, 3, 3]));
+    let keys = fields.iter()
+      .filter_map(|field| match &field.data {
+        ProtobufFieldData::Message(entry, _) => Some(entry[1] as i8),
+        _ => None
+      })
+      .collect::<Vec<_>>();
+    expect!(keys).to(be_equal_to(vec![-1, 2, 10]));
+  }
+
   #[test_log::test]
   fn encode_message_with_map_field_test() {
     // message CompareContentsRequest {
@@ -1789,6 +1941,32 @@ pub(crate) mod tests {
     expect!(result.to_vec()).to(be_equal_to(expected));
   }
 
+  #[test_log::test]
+  fn repeated_fields_use_the_editions_repeated_field_encoding() {
+    let bytes = BASE64.decode(EDITIONS_DESCRIPTORS).unwrap();
+    let fds = decode_file_descriptor_set(bytes.as_slice()).unwrap();
+    let file_descriptor = &fds.file[0];
+    let request_descriptor = &file_descriptor.message_type[0];
+    let mut builder = MessageBuilder::new(request_descriptor, "Request", file_descriptor);
+    for field_name in ["values", "packed_values"] {
+      let field_descriptor = builder.field_by_name(field_name).unwrap();
+      for value in [1, 2] {
+        builder.add_repeated_field_value(&field_descriptor, field_name, MessageFieldValue {
+          name: field_name.to_string(),
+          raw_value: Some(value.to_string()),
+          rtype: RType::Integer32(value)
+        });
+      }
+    }
+
+    let expected = vec![
+      24, 1, 24, 2, // Field 3 (values) is expanded by the file features
+      34, 2, 1, 2   // Field 4 (packed_values) is packed by the field features
+    ];
+    let result = builder.encode_message().unwrap();
+    expect!(result.to_vec()).to(be_equal_to(expected));
+  }
+
   #[test_log::test]
   fn test_field_with_global_enum() {
     let bytes: &[u8] = &DESCRIPTOR_WITH_ENUM_BYTES;
@@ -1819,4 +1997,71 @@ pub(crate) mod tests {
     let result = message_builder.encode_message().unwrap();
     expect!(result.to_vec()).to(be_equal_to(expected));
   }
+
+  #[test]
+  fn generate_markup_includes_the_field_comments_from_the_source_code_info() {
+    let field1 = FieldDescriptorProto {
+      name: Some("id".to_string()),
+      number: Some(1),
+      r#type: Some(field_descriptor_proto::Type::String as i32),
+      .. FieldDescriptorProto::default()
+    };
+    let field2 = FieldDescriptorProto {
+      name: Some("name".to_string()),
+      number: Some(3),
+      r#type: Some(field_descriptor_proto::Type::String as i32),
+      .. FieldDescriptorProto::default()
+    };
+    let nested = DescriptorProto {
+      name: Some("Person".to_string()),
+      field: vec![ field1.clone(), field2.clone() ],
+      .. DescriptorProto::default()
+    };
+    let descriptor = DescriptorProto {
+      name: Some("Request".to_string()),
+      nested_type: vec![ nested.clone() ],
+      .. DescriptorProto::default()
+    };
+    let location = |path: Vec<i32>, comment: &str| Location {
+      path,
+      leading_comments: Some(comment.to_string()),
+      .. Location::default()
+    };
+    let file_descriptor = FileDescriptorProto {
+      name: Some("test.proto".to_string()),
+      message_type: vec![ DescriptorProto { name: Some("Other".to_string()), .. DescriptorProto::default() }, descriptor ],
+      source_code_info: Some(SourceCodeInfo {
+        location: vec![
+          location(vec![4, 0, 2, 0], " Not for this message\n"),
+          location(vec![4, 1, 3, 0, 2, 0], " ID of the person\n Must be numeric\n"),
+          location(vec![4, 1, 3, 0, 2, 1], "\n")
+        ]
+      }),
+      .. FileDescriptorProto::default()
+    };
+
+    let mut message = MessageBuilder::new(&nested, "Person", &file_descriptor);
+    message.set_field_value(&field1, "id", MessageFieldValue {
+      name: "id".to_string(),
+      raw_value: Some("100".to_string()),
+      rtype: RType::String("100".to_string())
+    });
+    message.set_field_value(&field2, "name", MessageFieldValue {
+      name: "name".to_string(),
+      raw_value: Some("Fred".to_string()),
+      rtype: RType::String("Fred".to_string())
+    });
+
+    expect!(message.generate_markup("")).to(be_ok().value(
+      "|```protobuf
+         |message Person {
+         |    // ID of the person
+         |    // Must be numeric
+         |    string id = 1;
+         |    string name = 3;
+         |}
+         |```
+         |
+         ".trim_margin().unwrap()));
+  }
 }
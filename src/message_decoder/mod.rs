@@ -1,23 +1,60 @@
 //! Decoder for encoded Protobuf messages using the descriptors
 
+use std::collections::HashMap;
 use std::fmt::{Debug, Display, Formatter};
 use std::mem;
 use std::str::from_utf8;
+use std::sync::atomic::{AtomicUsize, Ordering};
 
 use anyhow::anyhow;
-use bytes::{Buf, Bytes, BytesMut};
+use bytes::{Buf, BufMut, Bytes, BytesMut};
 use itertools::Itertools;
-use prost::encoding::{decode_key, decode_varint, encode_varint, WireType};
+use prost::Message;
+use prost::encoding::{decode_key, decode_varint, encode_key, encode_varint, skip_field, DecodeContext, WireType};
 use prost_types::{DescriptorProto, EnumDescriptorProto, FieldDescriptorProto, FileDescriptorSet};
 use prost_types::field_descriptor_proto::Type;
 use tracing::{debug, error, trace, warn};
 
+use crate::editions::MessageFeatures;
+use crate::error::ProtobufError;
 use crate::utils::{
-  as_hex, find_enum_by_name, find_enum_by_name_in_message, find_message_descriptor_for_type, is_repeated_field, last_name, should_be_packed_type
+  as_hex, field_number, find_enum_by_name, find_enum_by_name_in_message, find_message_descriptor_for_type,
+  format_iso8601_duration, is_oneof_field, is_repeated_field, last_name, should_be_packed_type
 };
 
 pub mod generators;
 
+/// Default maximum size (64 MiB) of a length-delimited field that will be decoded
+pub const DEFAULT_MAX_FIELD_SIZE: usize = 64 * 1024 * 1024;
+
+static MAX_FIELD_SIZE: AtomicUsize = AtomicUsize::new(DEFAULT_MAX_FIELD_SIZE);
+
+/// Sets the maximum size of a length-delimited field (strings, bytes, embedded messages or
+/// packed fields) that will be decoded. Any field with a larger length will result in an error.
+pub fn set_max_field_size(size: usize) {
+  MAX_FIELD_SIZE.store(size, Ordering::Relaxed);
+}
+
+/// Returns the maximum size of a length-delimited field that will be decoded
+pub fn max_field_size() -> usize {
+  MAX_FIELD_SIZE.load(Ordering::Relaxed)
+}
+
+/// Maximum nesting depth of group fields that will be decoded (the same as the default recursion
+/// limit of the Protobuf libraries)
+const MAX_GROUP_DEPTH: usize = 100;
+
+/// Checks the length read from the wire for a length-delimited field against the maximum size
+fn check_field_length(field_num: u32, data_length: u64) -> Result<(), ProtobufError> {
+  let max_size = max_field_size();
+  if data_length > max_size as u64 {
+    Err(ProtobufError::DecodeFailure(format!("Field {} has a length of {} bytes, which exceeds the maximum allowed size of {} bytes",
+      field_num, data_length, max_size)))
+  } else {
+    Ok(())
+  }
+}
+
 /// Decoded Protobuf field
 #[derive(Clone, Debug, PartialEq)]
 pub struct ProtobufField {
@@ -56,7 +93,7 @@ impl ProtobufField {
   ) -> Option<ProtobufField> {
     default_field_data(field_descriptor, descriptor, fds).map(|data|
       ProtobufField {
-        field_num: field_descriptor.number.unwrap_or_default() as u32,
+        field_num: field_number(field_descriptor).unwrap_or_default(),
         field_name: field_descriptor.name.clone().unwrap_or_default(),
         wire_type: wire_type_for_field(field_descriptor),
         data,
@@ -103,8 +140,8 @@ fn default_field_data(
         Type::Int64 => Some(ProtobufFieldData::Integer64(s.parse().unwrap_or_default())),
         Type::Uint64 => Some(ProtobufFieldData::UInteger64(s.parse().unwrap_or_default())),
         Type::Int32 => Some(ProtobufFieldData::Integer32(s.parse().unwrap_or_default())),
-        Type::Fixed64 => Some(ProtobufFieldData::Integer64(s.parse().unwrap_or_default())),
-        Type::Fixed32 => Some(ProtobufFieldData::Integer32(s.parse().unwrap_or_default())),
+        Type::Fixed64 => Some(ProtobufFieldData::UInteger64(s.parse().unwrap_or_default())),
+        Type::Fixed32 => Some(ProtobufFieldData::UInteger32(s.parse().unwrap_or_default())),
         Type::Bool => Some(ProtobufFieldData::Boolean(s == "true")),
         Type::String => Some(ProtobufFieldData::String(s.clone())),
         Type::Bytes => Some(ProtobufFieldData::Bytes(s.as_bytes().to_vec())),
@@ -126,7 +163,7 @@ fn default_field_data(
       // For strings, the default value is the empty string.
       // For bytes, the default value is empty bytes.
       // For bools, the default value is false.
-      // For numeric types, the default value is zero.
+      // For numeric types, the default value is zero. NaN and infinite values are never the default.
       // For enums, the default value is the first defined enum value, which must be 0.
       // For message fields, the field is not set. Its exact value is language-dependent.
       match field_descriptor.r#type() {
@@ -135,8 +172,8 @@ fn default_field_data(
         Type::Int64 => Some(ProtobufFieldData::Integer64(0)),
         Type::Uint64 => Some(ProtobufFieldData::UInteger64(0)),
         Type::Int32 => Some(ProtobufFieldData::Integer32(0)),
-        Type::Fixed64 => Some(ProtobufFieldData::Integer64(0)),
-        Type::Fixed32 => Some(ProtobufFieldData::Integer32(0)),
+        Type::Fixed64 => Some(ProtobufFieldData::UInteger64(0)),
+        Type::Fixed32 => Some(ProtobufFieldData::UInteger32(0)),
         Type::Bool => Some(ProtobufFieldData::Boolean(false)),
         Type::String => Some(ProtobufFieldData::String(String::default())),
         Type::Bytes => Some(ProtobufFieldData::Bytes(vec![])),
@@ -198,6 +235,8 @@ pub enum ProtobufFieldData {
   Enum(i32, EnumDescriptorProto),
   /// Embedded message
   Message(Vec<u8>, DescriptorProto),
+  /// google.protobuf.Duration value (seconds and nanos)
+  Duration(i64, i32),
   /// For field data that does not match the descriptor
   Unknown(Vec<u8>)
 }
@@ -217,6 +256,7 @@ impl ProtobufFieldData {
       ProtobufFieldData::Bytes(_) => "Bytes",
       ProtobufFieldData::Enum(_, _) => "Enum",
       ProtobufFieldData::Message(_, _) => "Message",
+      ProtobufFieldData::Duration(_, _) => "Duration",
       ProtobufFieldData::Unknown(_) => "Unknown"
     }
   }
@@ -235,6 +275,7 @@ impl ProtobufFieldData {
       ProtobufFieldData::Bytes(b) => b.clone(),
       ProtobufFieldData::Enum(_, _) => self.to_string().as_bytes().to_vec(),
       ProtobufFieldData::Message(b, _) => b.clone(),
+      ProtobufFieldData::Duration(seconds, nanos) => encode_duration(*seconds, *nanos),
       ProtobufFieldData::Unknown(data) => data.clone()
     }
   }
@@ -259,6 +300,7 @@ impl ProtobufFieldData {
           ProtobufFieldData::Bytes(_) => ProtobufFieldData::Bytes(s.as_bytes().to_vec()),
           ProtobufFieldData::Enum(_, descriptor) => ProtobufFieldData::Enum(s.parse().unwrap_or_default(), descriptor.clone()),
           ProtobufFieldData::Message(_, descriptor) => ProtobufFieldData::Message(Default::default(), descriptor.clone()),
+          ProtobufFieldData::Duration(_, _) => ProtobufFieldData::Duration(0, 0),
           ProtobufFieldData::Unknown(_) => ProtobufFieldData::Unknown(Default::default())
         }
       }
@@ -281,6 +323,7 @@ impl ProtobufFieldData {
           ProtobufFieldData::Bytes(_) => ProtobufFieldData::Bytes(Default::default()),
           ProtobufFieldData::Enum(_, descriptor) => ProtobufFieldData::Enum(0, descriptor.clone()),
           ProtobufFieldData::Message(_, descriptor) => ProtobufFieldData::Message(Default::default(), descriptor.clone()),
+          ProtobufFieldData::Duration(_, _) => ProtobufFieldData::Duration(0, 0),
           ProtobufFieldData::Unknown(_) => ProtobufFieldData::Unknown(Default::default())
         }
       }
@@ -307,6 +350,7 @@ impl ProtobufFieldData {
       ProtobufFieldData::Bytes(v) => v.is_empty(),
       ProtobufFieldData::Enum(v, _) => *v == 0,
       ProtobufFieldData::Message(v, _) => v.is_empty(),
+      ProtobufFieldData::Duration(seconds, nanos) => *seconds == 0 && *nanos == 0,
       ProtobufFieldData::Unknown(v) => v.is_empty()
     }
   }
@@ -386,6 +430,7 @@ impl Display for ProtobufFieldData {
       ProtobufFieldData::Message(_, descriptor) => {
         write!(f, "{}", descriptor.name.clone().unwrap_or_else(|| "unknown".to_string()))
       }
+      ProtobufFieldData::Duration(seconds, nanos) => write!(f, "{}", format_iso8601_duration(*seconds, *nanos)),
       ProtobufFieldData::Unknown(b) => if b.len() <= 16 {
         write!(f, "{}", as_hex(b.as_slice()))
       } else {
@@ -395,14 +440,45 @@ impl Display for ProtobufFieldData {
   }
 }
 
+/// Renders the decoded message fields as a tree, with one line for each field value. The fields of
+/// embedded messages are rendered indented under the field for the message, and fields are rendered
+/// in field number order. Any embedded messages that can not be decoded are rendered with just the
+/// message type.
+pub fn render_message(fields: &[ProtobufField], descriptors: &FileDescriptorSet) -> Vec<String> {
+  let mut lines = vec![];
+  render_message_fields(fields, descriptors, 0, &mut lines);
+  lines
+}
+
+fn render_message_fields(
+  fields: &[ProtobufField],
+  descriptors: &FileDescriptorSet,
+  indent: usize,
+  lines: &mut Vec<String>
+) {
+  for field in fields.iter().sorted_by_key(|field| field.field_num) {
+    lines.push(format!("{:indent$}{}: {}", "", field.field_name, field.data, indent = indent));
+    if let ProtobufFieldData::Message(bytes, descriptor) = &field.data {
+      let mut bytes = Bytes::copy_from_slice(bytes);
+      match decode_message(&mut bytes, descriptor, descriptors) {
+        Ok(message_fields) => render_message_fields(&message_fields, descriptors, indent + 2, lines),
+        Err(err) => warn!("Failed to decode embedded message for field '{}' - {}", field.field_name, err)
+      }
+    }
+  }
+}
+
 /// Decodes the Protobuf message using the descriptors and returns an array of ProtobufField values.
 /// This will return a value for each field value in the incoming bytes in the same order, and will
 /// not consolidate repeated fields.
+///
+/// Returns a `ProtobufError::DecodeFailure` if the bytes are not a valid encoded message, or a
+/// `ProtobufError::DescriptorNotFound` if a descriptor for an embedded message or enum can not be found.
 pub fn decode_message<B>(
   buffer: &mut B,
   descriptor: &DescriptorProto,
   descriptors: &FileDescriptorSet
-) -> anyhow::Result<Vec<ProtobufField>>
+) -> Result<Vec<ProtobufField>, ProtobufError>
   where B: Buf {
   trace!("Decoding message using descriptor {:?}", descriptor);
   trace!("all descriptors available for decoding the message: {:?}", descriptors);
@@ -413,7 +489,7 @@ pub fn decode_message<B>(
     let (field_num, wire_type) = decode_key(buffer)?;
     trace!(field_num, ?wire_type, "read field header, bytes remaining = {}", buffer.remaining());
 
-    match &find_field_descriptor(field_num as i32, descriptor) {
+    match &find_field_descriptor(field_num, descriptor) {
       Ok(field_descriptor) => {
         let field_name = field_descriptor.name();
         trace!("field_name = {}", field_name);
@@ -456,30 +532,34 @@ pub fn decode_message<B>(
           }
           WireType::LengthDelimited => {
             let data_length = decode_varint(buffer)?;
+            check_field_length(field_num, data_length)?;
             let mut data_buffer = if buffer.remaining() >= data_length as usize {
               buffer.copy_to_bytes(data_length as usize)
             } else {
-              return Err(anyhow!("Insufficient data remaining ({} bytes) to read {} bytes for field {}", buffer.remaining(), data_length, field_num));
+              return Err(ProtobufError::DecodeFailure(format!("Insufficient data remaining ({} bytes) to read {} bytes for field {}",
+                buffer.remaining(), data_length, field_num)));
             };
             let t: Type = field_descriptor.r#type();
             trace!(field_type = ?t, data_buffer = ?data_buffer);
             match t {
               Type::String => vec![ (ProtobufFieldData::String(from_utf8(&data_buffer)?.to_string()), wire_type) ],
+              Type::Message if field_descriptor.type_name() == ".google.protobuf.Duration" => {
+                // Durations are decoded to their seconds and nanos, so they can be compared as a value
+                let duration = prost_types::Duration::decode(data_buffer.clone())?;
+                vec![ (ProtobufFieldData::Duration(duration.seconds, duration.nanos), wire_type) ]
+              }
               Type::Message => {
                 let full_type_name = field_descriptor.type_name();
                 trace!(%full_type_name, "Embedded message");
-                // TODO: replace with proper support for nested fields
-                // this code checks fully qualified name first, if it can find it, this means the type name was a 
-                // valid fully-qualified reference;
-                // if it's not found, it's a nested type, so we look for it in the nested types of the current message
-                // This misses the case when the type name refers to a fully-qualified nested type in another message
-                // or package. This also doesn't deal with relative paths, but I don't think descriptors actually
-                // contain those.
+                // this code checks fully qualified name first (which includes types nested in other messages),
+                // if it can find it, this means the type name was a valid fully-qualified reference;
+                // if it's not found, we look for it in the nested types of the current message. This doesn't deal
+                // with relative paths, but I don't think descriptors actually contain those.
                 let message_proto = find_message_descriptor_for_type(full_type_name, descriptors).map(|(d,_)|d)
                 .or_else(|_| {
                   descriptor.nested_type.iter().find(
                     |message_descriptor| message_descriptor.name.as_deref() == Some(last_name(full_type_name))
-                  ).cloned().ok_or_else(|| anyhow!("Did not find the message {:?} for the field {} in the Protobuf descriptor", field_descriptor.type_name, field_num))
+                  ).cloned().ok_or_else(|| ProtobufError::DescriptorNotFound(format!("Did not find the message {:?} for the field {} in the Protobuf descriptor", field_descriptor.type_name, field_num)))
                 })?;
                 vec![ (ProtobufFieldData::Message(data_buffer.to_vec(), message_proto), wire_type) ]
               }
@@ -509,7 +589,11 @@ pub fn decode_message<B>(
               }
             }
           }
-          _ => return Err(anyhow!("Messages with {:?} wire type fields are not supported", wire_type))
+          WireType::StartGroup => {
+            warn!("Field {} is a group field, which is deprecated and not supported. It will be treated as an unknown field", field_num);
+            vec![ (ProtobufFieldData::Unknown(decode_group(field_num, buffer, 1)?), wire_type) ]
+          }
+          _ => return Err(ProtobufError::UnsupportedType(format!("Messages with {:?} wire type fields are not supported", wire_type)))
         };
 
         trace!(field_num, ?wire_type, ?data, "read field, bytes remaining = {}", buffer.remaining());
@@ -538,13 +622,19 @@ pub fn decode_message<B>(
           WireType::SixtyFourBit => buffer.get_u64().to_le_bytes().to_vec(),
           WireType::LengthDelimited => {
             let data_length = decode_varint(buffer)?;
+            check_field_length(field_num, data_length)?;
+            if buffer.remaining() < data_length as usize {
+              return Err(ProtobufError::DecodeFailure(format!("Insufficient data remaining ({} bytes) to read {} bytes for field {}",
+                buffer.remaining(), data_length, field_num)));
+            }
             let mut buf = BytesMut::with_capacity((data_length + 8) as usize);
             encode_varint(data_length, &mut buf);
             buf.extend_from_slice(&buffer.copy_to_bytes(data_length as usize));
             buf.freeze().to_vec()
           }
           WireType::ThirtyTwoBit => buffer.get_u32().to_le_bytes().to_vec(),
-          _ => return Err(anyhow!("Messages with {:?} wire type fields are not supported", wire_type))
+          WireType::StartGroup => decode_group(field_num, buffer, 1)?,
+          _ => return Err(ProtobufError::UnsupportedType(format!("Messages with {:?} wire type fields are not supported", wire_type)))
         };
         fields.push(ProtobufField {
           field_num,
@@ -567,16 +657,243 @@ pub fn decode_message<B>(
   Ok(result)
 }
 
+/// Reads the contents of a group field up to and including the matching end group tag, and returns
+/// the raw bytes so the field can be stored as an unknown field. Groups are a deprecated proto2
+/// feature, so their contents are not decoded. Groups nested deeper than `MAX_GROUP_DEPTH` will
+/// result in an error.
+fn decode_group<B>(field_num: u32, buffer: &mut B, depth: usize) -> Result<Vec<u8>, ProtobufError> where B: Buf {
+  if depth > MAX_GROUP_DEPTH {
+    return Err(ProtobufError::DecodeFailure(format!("Group field {} exceeds the maximum nesting depth of {}",
+      field_num, MAX_GROUP_DEPTH)));
+  }
+  let mut data = BytesMut::new();
+  loop {
+    if !buffer.has_remaining() {
+      return Err(ProtobufError::DecodeFailure(format!("Did not find the end of the group for field {}", field_num)));
+    }
+    let (inner_field_num, wire_type) = decode_key(buffer)?;
+    encode_key(inner_field_num, wire_type, &mut data);
+    match wire_type {
+      WireType::Varint => encode_varint(decode_varint(buffer)?, &mut data),
+      WireType::SixtyFourBit => {
+        if buffer.remaining() < 8 {
+          return Err(ProtobufError::DecodeFailure(format!("Insufficient data remaining ({} bytes) to read 8 bytes for field {}",
+            buffer.remaining(), inner_field_num)));
+        }
+        data.put_u64_le(buffer.get_u64_le());
+      }
+      WireType::LengthDelimited => {
+        let data_length = decode_varint(buffer)?;
+        check_field_length(inner_field_num, data_length)?;
+        if buffer.remaining() < data_length as usize {
+          return Err(ProtobufError::DecodeFailure(format!("Insufficient data remaining ({} bytes) to read {} bytes for field {}",
+            buffer.remaining(), data_length, inner_field_num)));
+        }
+        encode_varint(data_length, &mut data);
+        data.extend_from_slice(&buffer.copy_to_bytes(data_length as usize));
+      }
+      WireType::StartGroup => data.extend_from_slice(&decode_group(inner_field_num, buffer, depth + 1)?),
+      WireType::EndGroup => if inner_field_num == field_num {
+        return Ok(data.to_vec());
+      } else {
+        return Err(ProtobufError::DecodeFailure(format!("Expected the end of the group for field {}, but got the end of group {}",
+          field_num, inner_field_num)));
+      }
+      WireType::ThirtyTwoBit => {
+        if buffer.remaining() < 4 {
+          return Err(ProtobufError::DecodeFailure(format!("Insufficient data remaining ({} bytes) to read 4 bytes for field {}",
+            buffer.remaining(), inner_field_num)));
+        }
+        data.put_u32_le(buffer.get_u32_le());
+      }
+    }
+  }
+}
+
+/// Decodes the Protobuf message using the descriptors, the same as `decode_message`, but also adds
+/// the default values for any fields not present in the incoming bytes. A zero-length message will
+/// decode to the default values for all the fields.
+pub fn decode_message_with_defaults<B>(
+  buffer: &mut B,
+  descriptor: &DescriptorProto,
+  descriptors: &FileDescriptorSet
+) -> Result<Vec<ProtobufField>, ProtobufError>
+  where B: Buf {
+  decode_message(buffer, descriptor, descriptors)
+    .map(|fields| populate_default_values(&fields, descriptor, descriptors))
+}
+
+/// Adds the default values for any fields of the message that are not set. Default values are only
+/// used for singular scalar fields of messages that do not have explicit field presence.
+pub(crate) fn populate_default_values(
+  fields: &[ProtobufField],
+  message_descriptor: &DescriptorProto,
+  fds: &FileDescriptorSet
+) -> Vec<ProtobufField> {
+  let mut field_vec = Vec::from(fields);
+  let features = MessageFeatures::resolve(message_descriptor, fds);
+  for field in &message_descriptor.field {
+    if let Some(field_num) = field_number(field) {
+      let entry = field_vec.iter()
+        .find(|i| i.field_num == field_num);
+      if entry.is_none() && !features.has_explicit_presence(field) && should_use_default(field) {
+        if let Some(def) = ProtobufField::default_field(field, message_descriptor, fds) {
+          field_vec.push(def)
+        }
+      }
+    }
+  }
+  field_vec
+}
+
+/// If the default value should be used for the field when it is not set
+pub(crate) fn should_use_default(descriptor: &FieldDescriptorProto) -> bool {
+  //     For strings, the default value is the empty string.
+  //     For bytes, the default value is empty bytes.
+  //     For bools, the default value is false.
+  //     For numeric types, the default value is zero. For float and double types, -0.0 and 0.0 are treated as equivalent, and will round-trip.
+  //     For enums, the default value is the first defined enum value, which must be 0.
+  match descriptor.r#type() {
+    Type::Double | Type::Float | Type::Int64 | Type::Uint64 | Type::Int32 | Type::Fixed64 |
+    Type::Fixed32 | Type::Bool | Type::String | Type::Bytes | Type::Uint32 | Type::Enum |
+    Type::Sfixed32 | Type::Sfixed64 | Type::Sint32 | Type::Sint64 => !is_repeated_field(descriptor)
+      && !is_oneof_field(descriptor),
+    _ => false
+  }
+}
+
+/// Encodes the fields back into a Protobuf message. The fields are written in the order given, and
+/// consecutive values of a repeated scalar field are written as a packed field, unless packing has
+/// been disabled for the field. Embedded messages are written using their encoded bytes.
+pub fn encode_fields(fields: &[ProtobufField]) -> anyhow::Result<Vec<u8>> {
+  let mut buffer = BytesMut::new();
+  for (field_num, values) in &fields.iter().chunk_by(|field| field.field_num) {
+    let values = values.collect_vec();
+    if values.iter().all(|field| is_packed_field(field)) {
+      let mut packed = BytesMut::new();
+      for field in &values {
+        encode_field_data(field, &field.data, &mut packed)?;
+      }
+      encode_key(field_num, WireType::LengthDelimited, &mut buffer);
+      encode_varint(packed.len() as u64, &mut buffer);
+      buffer.put_slice(&packed);
+    } else {
+      for field in values {
+        encode_key(field_num, field.wire_type, &mut buffer);
+        encode_field_data(field, &field.data, &mut buffer)?;
+      }
+    }
+  }
+  Ok(buffer.to_vec())
+}
+
+/// If the field value should be written as part of a packed repeated field. Repeated scalar fields
+/// are packed by default in proto3.
+fn is_packed_field(field: &ProtobufField) -> bool {
+  is_repeated_field(&field.descriptor)
+    && should_be_packed_type(field.descriptor.r#type())
+    && field.wire_type != WireType::LengthDelimited
+    && !matches!(field.data, ProtobufFieldData::Unknown(_))
+    && field.descriptor.options.as_ref().and_then(|options| options.packed).unwrap_or(true)
+}
+
+/// Writes the field data (without the field key) to the buffer using the wire type of the field
+pub(crate) fn encode_field_data<B>(
+  field: &ProtobufField,
+  data: &ProtobufFieldData,
+  buffer: &mut B
+) -> anyhow::Result<()> where B: BufMut {
+  match field.wire_type {
+    WireType::Varint => match data {
+      ProtobufFieldData::Boolean(b) => encode_varint(*b as u64, buffer),
+      ProtobufFieldData::UInteger32(n) => encode_varint(*n as u64, buffer),
+      ProtobufFieldData::Integer32(n) => if field.descriptor.r#type() == Type::Sint32 {
+        encode_varint(((*n << 1) ^ (*n >> 31)) as u32 as u64, buffer)
+      } else {
+        encode_varint(*n as u64, buffer)
+      },
+      ProtobufFieldData::UInteger64(n) => encode_varint(*n, buffer),
+      ProtobufFieldData::Integer64(n) => if field.descriptor.r#type() == Type::Sint64 {
+        encode_varint(((*n << 1) ^ (*n >> 63)) as u64, buffer)
+      } else {
+        encode_varint(*n as u64, buffer)
+      },
+      ProtobufFieldData::Enum(n, _) => encode_varint(*n as u64, buffer),
+      ProtobufFieldData::Unknown(b) => {
+        debug!("Writing unknown field {}", field.data);
+        buffer.put_slice(b.as_slice());
+      },
+      _ => return Err(anyhow!("Expected a varint, but field is {}", field.data))
+    },
+    WireType::SixtyFourBit => match data {
+      ProtobufFieldData::UInteger64(n) => buffer.put_u64_le(*n),
+      ProtobufFieldData::Integer64(n) => buffer.put_i64_le(*n),
+      ProtobufFieldData::Double(n) => buffer.put_f64_le(*n),
+      ProtobufFieldData::Unknown(b) => {
+        debug!("Writing unknown field {}", field.data);
+        buffer.put_slice(b.as_slice());
+      }
+      _ => return Err(anyhow!("Expected a 64 bit value, but field is {}", field.data))
+    }
+    WireType::LengthDelimited => match data {
+      ProtobufFieldData::String(s) => {
+        encode_varint(s.len() as u64, buffer);
+        buffer.put_slice(s.as_bytes());
+      }
+      ProtobufFieldData::Bytes(b) => {
+        encode_varint(b.len() as u64, buffer);
+        buffer.put_slice(b.as_slice());
+      }
+      ProtobufFieldData::Message(m, _) => {
+        encode_varint(m.len() as u64, buffer);
+        buffer.put_slice(m.as_slice());
+      }
+      ProtobufFieldData::Duration(seconds, nanos) => {
+        let m = encode_duration(*seconds, *nanos);
+        encode_varint(m.len() as u64, buffer);
+        buffer.put_slice(m.as_slice());
+      }
+      ProtobufFieldData::Unknown(b) => {
+        debug!("Writing unknown field {}", field.data);
+        buffer.put_slice(b.as_slice());
+      },
+      _ => return Err(anyhow!("Expected a length delimited value, but field is {}", field.data))
+    }
+    WireType::ThirtyTwoBit => match data {
+      ProtobufFieldData::UInteger32(n) => buffer.put_u32_le(*n),
+      ProtobufFieldData::Integer32(n) => buffer.put_i32_le(*n),
+      ProtobufFieldData::Float(n) => buffer.put_f32_le(*n),
+      ProtobufFieldData::Unknown(b) => {
+        debug!("Writing unknown field {}", field.data);
+        buffer.put_slice(b.as_slice());
+      },
+      _ => return Err(anyhow!("Expected a 32 bit value, but field is {}", field.data))
+    }
+    // Group fields are stored with the raw bytes of the group, including the end group tag
+    WireType::StartGroup => match data {
+      ProtobufFieldData::Unknown(b) => buffer.put_slice(b.as_slice()),
+      _ => return Err(anyhow!("Groups are not supported"))
+    }
+    _ => return Err(anyhow!("Groups are not supported"))
+  }
+  Ok(())
+}
+
+/// Encodes the seconds and nanos as a google.protobuf.Duration message
+fn encode_duration(seconds: i64, nanos: i32) -> Vec<u8> {
+  prost_types::Duration { seconds, nanos }.encode_to_vec()
+}
+
 fn decode_enum(
   descriptor: &DescriptorProto,
   descriptors: &FileDescriptorSet,
   field_descriptor: &FieldDescriptorProto,
   varint: u64
-) -> anyhow::Result<ProtobufFieldData> {
+) -> Result<ProtobufFieldData, ProtobufError> {
   let enum_type_name = field_descriptor.type_name.clone().unwrap_or_default();
   let enum_proto = find_enum_by_name_in_message(&descriptor.enum_type, enum_type_name.as_str())
     .or_else(|| find_enum_by_name(descriptors, enum_type_name.as_str()))
-    .ok_or_else(|| anyhow!("Did not find the enum {} for the field in the Protobuf descriptor", enum_type_name))?;
+    .ok_or_else(|| ProtobufError::DescriptorNotFound(format!("Did not find the enum {} for the field in the Protobuf descriptor", enum_type_name)))?;
   Ok(ProtobufFieldData::Enum(varint as i32, enum_proto.clone()))
 }
 
@@ -585,7 +902,7 @@ fn decode_packed_field(
   descriptor: &DescriptorProto,
   descriptors: &FileDescriptorSet,
   data: &mut Bytes
-) -> anyhow::Result<Vec<(ProtobufFieldData, WireType)>> {
+) -> Result<Vec<(ProtobufFieldData, WireType)>, ProtobufError> {
   let mut values = vec![];
   let t: Type = field.r#type();
   match t {
@@ -663,24 +980,43 @@ fn decode_packed_field(
         values.push((ProtobufFieldData::Integer64(((varint >> 1) as i64) ^ (-((varint & 1) as i64))), WireType::Varint));
       }
     }
-    _ => return Err(anyhow!("Field type {:?} can not be packed", t))
+    _ => return Err(ProtobufError::UnsupportedType(format!("Field type {:?} can not be packed", t)))
   };
 
   if data.is_empty() {
     Ok(values)
   } else {
-    Err(anyhow!("Failed to decode packed repeated field, there was still {} bytes in the buffer", data.remaining()))
+    Err(ProtobufError::DecodeFailure(format!("Failed to decode packed repeated field, there was still {} bytes in the buffer", data.remaining())))
   }
 }
 
-fn find_field_descriptor(field_num: i32, descriptor: &DescriptorProto) -> anyhow::Result<FieldDescriptorProto> {
-  descriptor.field.iter().find(|field| {
-    if let Some(num)  = field.number {
-      num == field_num
-    } else {
-      false
+/// Scans the encoded message and returns the wire encoding used for each repeated scalar field
+/// found in it, keyed by field number. The value will be true if all the values for the field were
+/// written as packed fields, and false if any were written unpacked. The field values are not decoded.
+pub fn repeated_field_encodings<B>(
+  buffer: &mut B,
+  descriptor: &DescriptorProto
+) -> Result<HashMap<u32, bool>, ProtobufError> where B: Buf {
+  let mut encodings = HashMap::new();
+
+  while buffer.has_remaining() {
+    let (field_num, wire_type) = decode_key(buffer)?;
+    if let Ok(field_descriptor) = find_field_descriptor(field_num, descriptor) {
+      if is_repeated_field(&field_descriptor) && should_be_packed_type(field_descriptor.r#type()) {
+        let packed = wire_type == WireType::LengthDelimited;
+        encodings.entry(field_num)
+          .and_modify(|all_packed| *all_packed = *all_packed && packed)
+          .or_insert(packed);
+      }
     }
-  })
+    skip_field(wire_type, field_num, buffer, DecodeContext::default())?;
+  }
+
+  Ok(encodings)
+}
+
+fn find_field_descriptor(field_num: u32, descriptor: &DescriptorProto) -> anyhow::Result<FieldDescriptorProto> {
+  descriptor.field.iter().find(|field| field_number(field) == Some(field_num))
     .cloned()
     .ok_or_else(|| anyhow!("Did not find a field with number {} in the descriptor", field_num))
 }
@@ -691,10 +1027,11 @@ mod tests {
   use base64::engine::general_purpose::STANDARD as BASE64;
   use bytes::{BufMut, Bytes, BytesMut};
   use expectest::prelude::*;
+  use maplit::hashmap;
   use pact_plugin_driver::proto::InitPluginRequest;
-  use prost::encoding::WireType;
+  use prost::encoding::{encode_key, encode_varint, WireType};
   use prost::Message;
-  use prost_types::{DescriptorProto, EnumDescriptorProto, EnumValueDescriptorProto, FileDescriptorSet};
+  use prost_types::{DescriptorProto, EnumDescriptorProto, EnumValueDescriptorProto, FileDescriptorProto, FileDescriptorSet};
 
   use crate::{
     bool_field_descriptor,
@@ -709,7 +1046,19 @@ mod tests {
     u32_field_descriptor,
     u64_field_descriptor
   };
-  use crate::message_decoder::{decode_message, ProtobufFieldData};
+  use crate::editions::decode_file_descriptor_set;
+  use crate::editions::tests::EDITIONS_DESCRIPTORS;
+  use crate::error::ProtobufError;
+  use crate::message_decoder::{
+    decode_message,
+    decode_message_with_defaults,
+    DEFAULT_MAX_FIELD_SIZE,
+    encode_fields,
+    MAX_GROUP_DEPTH,
+    ProtobufFieldData,
+    render_message,
+    repeated_field_encodings
+  };
   use crate::protobuf::tests::DESCRIPTOR_WITH_ENUM_BYTES;
   use crate::message_builder::tests::REPEATED_ENUM_DESCRIPTORS;
 
@@ -1065,6 +1414,63 @@ mod tests {
     expect!(&field_result.data).to(be_equal_to(&ProtobufFieldData::Message(encoded, message_descriptor)));
   }
 
+  #[test]
+  fn decode_message_with_defaults_for_an_empty_message() {
+    let repeated_field = prost_types::FieldDescriptorProto {
+      label: Some(prost_types::field_descriptor_proto::Label::Repeated as i32),
+      .. string_field_descriptor!("tags", 4)
+    };
+    let oneof_field = prost_types::FieldDescriptorProto {
+      oneof_index: Some(0),
+      .. i32_field_descriptor!("choice", 5)
+    };
+    let descriptor = DescriptorProto {
+      name: Some("TestMessage".to_string()),
+      field: vec![
+        string_field_descriptor!("name", 1),
+        i32_field_descriptor!("count", 2),
+        bool_field_descriptor!("active", 3),
+        repeated_field,
+        oneof_field
+      ],
+      oneof_decl: vec![
+        prost_types::OneofDescriptorProto { name: Some("options".to_string()), options: None }
+      ],
+      .. DescriptorProto::default()
+    };
+    let fds = FileDescriptorSet { file: vec![] };
+
+    let result = decode_message(&mut Bytes::new(), &descriptor, &fds).unwrap();
+    expect!(result.iter()).to(be_empty());
+
+    let result = decode_message_with_defaults(&mut Bytes::new(), &descriptor, &fds).unwrap();
+    expect!(result.len()).to(be_equal_to(3));
+    expect!(result[0].field_name.as_str()).to(be_equal_to("name"));
+    expect!(&result[0].data).to(be_equal_to(&ProtobufFieldData::String(String::default())));
+    expect!(result[1].field_name.as_str()).to(be_equal_to("count"));
+    expect!(&result[1].data).to(be_equal_to(&ProtobufFieldData::Integer32(0)));
+    expect!(result[2].field_name.as_str()).to(be_equal_to("active"));
+    expect!(&result[2].data).to(be_equal_to(&ProtobufFieldData::Boolean(false)));
+  }
+
+  #[test]
+  fn decode_message_with_defaults_only_populates_editions_fields_with_implicit_presence() {
+    let bytes = BASE64.decode(EDITIONS_DESCRIPTORS).unwrap();
+    let fds = decode_file_descriptor_set(bytes.as_slice()).unwrap();
+    let request_descriptor = &fds.file[0].message_type[0];
+    let nested_descriptor = &request_descriptor.nested_type[0];
+
+    let result = decode_message_with_defaults(&mut Bytes::new(), request_descriptor, &fds).unwrap();
+    expect!(result.len()).to(be_equal_to(1));
+    expect!(result[0].field_name.as_str()).to(be_equal_to("count"));
+    expect!(&result[0].data).to(be_equal_to(&ProtobufFieldData::Integer32(0)));
+
+    let result = decode_message_with_defaults(&mut Bytes::new(), nested_descriptor, &fds).unwrap();
+    expect!(result.len()).to(be_equal_to(1));
+    expect!(result[0].field_name.as_str()).to(be_equal_to("id"));
+    expect!(&result[0].data).to(be_equal_to(&ProtobufFieldData::String(String::default())));
+  }
+
   #[test]
   fn decode_message_with_unknown_field() {
     let message = InitPluginRequest {
@@ -1103,6 +1509,289 @@ mod tests {
     expect!(field_result.data.type_name()).to(be_equal_to("Unknown"));
   }
 
+  #[test]
+  fn decode_message_with_a_group_field() {
+    let message_descriptor = DescriptorProto {
+      name: Some("LegacyMessage".to_string()),
+      field: vec![
+        prost_types::FieldDescriptorProto {
+          name: Some("legacy".to_string()),
+          number: Some(1),
+          r#type: Some(prost_types::field_descriptor_proto::Type::Group as i32),
+          .. prost_types::FieldDescriptorProto::default()
+        },
+        string_field_descriptor!("name", 3)
+      ],
+      .. DescriptorProto::default()
+    };
+    let fds = FileDescriptorSet { file: vec![] };
+
+    // group field 1 { field 2: 150, group field 4 { field 5: 1 } }, field 3: "test", unknown group field 6 { }
+    let bytes: &[u8] = &[11, 16, 150, 1, 35, 40, 1, 36, 12, 26, 4, 116, 101, 115, 116, 51, 52];
+    let mut buffer = Bytes::from_static(bytes);
+    let result = decode_message(&mut buffer, &message_descriptor, &fds).unwrap();
+    expect!(result.len()).to(be_equal_to(3));
+
+    let field_result = result.first().unwrap();
+    expect!(field_result.field_num).to(be_equal_to(1));
+    expect!(field_result.wire_type).to(be_equal_to(WireType::StartGroup));
+    expect!(&field_result.data).to(be_equal_to(&ProtobufFieldData::Unknown(vec![16, 150, 1, 35, 40, 1, 36, 12])));
+
+    let field_result = result.get(1).unwrap();
+    expect!(field_result.field_num).to(be_equal_to(3));
+    expect!(&field_result.data).to(be_equal_to(&ProtobufFieldData::String("test".to_string())));
+
+    let field_result = result.get(2).unwrap();
+    expect!(field_result.field_num).to(be_equal_to(6));
+    expect!(field_result.wire_type).to(be_equal_to(WireType::StartGroup));
+    expect!(&field_result.data).to(be_equal_to(&ProtobufFieldData::Unknown(vec![52])));
+
+    expect!(encode_fields(&result).unwrap()).to(be_equal_to(bytes.to_vec()));
+
+    let mut buffer = Bytes::from_static(&[11, 16, 150, 1]);
+    expect!(decode_message(&mut buffer, &message_descriptor, &fds).unwrap_err().to_string())
+      .to(be_equal_to("Did not find the end of the group for field 1"));
+  }
+
+  #[test]
+  fn decode_message_with_deeply_nested_group_fields() {
+    let message_descriptor = DescriptorProto {
+      name: Some("LegacyMessage".to_string()),
+      .. DescriptorProto::default()
+    };
+    let fds = FileDescriptorSet { file: vec![] };
+
+    let mut buffer = BytesMut::new();
+    for _ in 0..MAX_GROUP_DEPTH {
+      encode_key(1, WireType::StartGroup, &mut buffer);
+    }
+    for _ in 0..MAX_GROUP_DEPTH {
+      encode_key(1, WireType::EndGroup, &mut buffer);
+    }
+    let result = decode_message(&mut buffer.clone().freeze(), &message_descriptor, &fds).unwrap();
+    expect!(result.len()).to(be_equal_to(1));
+
+    let mut buffer = BytesMut::new();
+    for _ in 0..100_000 {
+      encode_key(1, WireType::StartGroup, &mut buffer);
+    }
+    expect!(decode_message(&mut buffer.freeze(), &message_descriptor, &fds).unwrap_err().to_string())
+      .to(be_equal_to("Group field 1 exceeds the maximum nesting depth of 100"));
+  }
+
+  #[test]
+  fn decode_message_with_a_duration_field() {
+    let message_descriptor = DescriptorProto {
+      name: Some("Timeout".to_string()),
+      field: vec![ message_field_descriptor!("timeout", 1, ".google.protobuf.Duration") ],
+      .. DescriptorProto::default()
+    };
+    let fds = FileDescriptorSet { file: vec![] };
+    let bytes: &[u8] = &[10, 8, 8, 90, 16, 128, 202, 181, 238, 1];
+
+    let result = decode_message(&mut Bytes::from(bytes), &message_descriptor, &fds).unwrap();
+    expect!(result.len()).to(be_equal_to(1));
+    expect!(&result[0].data).to(be_equal_to(&ProtobufFieldData::Duration(90, 500_000_000)));
+    expect!(result[0].data.to_string()).to(be_equal_to("PT1M30.5S"));
+    expect!(encode_fields(&result).unwrap()).to(be_equal_to(bytes.to_vec()));
+  }
+
+  #[test]
+  fn decode_message_with_the_maximum_field_number() {
+    let max_field_number = 536_870_911;
+    let message_descriptor = DescriptorProto {
+      name: Some("HighFieldNumbers".to_string()),
+      field: vec![
+        string_field_descriptor!("name", max_field_number as i32),
+        string_field_descriptor!("other", 1)
+      ],
+      .. DescriptorProto::default()
+    };
+
+    let mut buffer = BytesMut::new();
+    encode_key(max_field_number, WireType::LengthDelimited, &mut buffer);
+    buffer.put_u8(4);
+    buffer.put_slice("test".as_bytes());
+    encode_key(max_field_number - 1, WireType::Varint, &mut buffer);
+    buffer.put_u8(1);
+    let bytes = buffer.freeze();
+
+    let result = decode_message(&mut bytes.clone(), &message_descriptor, &FileDescriptorSet { file: vec![] }).unwrap();
+    expect!(result.len()).to(be_equal_to(2));
+
+    let field_result = result.last().unwrap();
+    expect!(field_result.field_num).to(be_equal_to(max_field_number));
+    expect!(field_result.field_name.as_str()).to(be_equal_to("name"));
+    expect!(&field_result.data).to(be_equal_to(&ProtobufFieldData::String("test".to_string())));
+
+    let field_result = result.first().unwrap();
+    expect!(field_result.field_num).to(be_equal_to(max_field_number - 1));
+    expect!(field_result.data.type_name()).to(be_equal_to("Unknown"));
+
+    let mut encoded = Bytes::from(encode_fields(&result).unwrap());
+    let result = decode_message(&mut encoded, &message_descriptor, &FileDescriptorSet { file: vec![] }).unwrap();
+    expect!(result.last().unwrap().field_num).to(be_equal_to(max_field_number));
+  }
+
+  #[test]
+  fn decode_message_with_a_field_referencing_a_type_nested_in_another_message() {
+    let inner_descriptor = DescriptorProto {
+      name: Some("Inner".to_string()),
+      field: vec![ string_field_descriptor!("value", 1) ],
+      .. DescriptorProto::default()
+    };
+    let outer_descriptor = DescriptorProto {
+      name: Some("Outer".to_string()),
+      nested_type: vec![ inner_descriptor.clone() ],
+      .. DescriptorProto::default()
+    };
+    let message_descriptor = DescriptorProto {
+      name: Some("Request".to_string()),
+      field: vec![ message_field_descriptor!("inner", 1, ".pkg.Outer.Inner") ],
+      .. DescriptorProto::default()
+    };
+    let descriptors = FileDescriptorSet {
+      file: vec![
+        FileDescriptorProto {
+          name: Some("outer.proto".to_string()),
+          package: Some("pkg".to_string()),
+          message_type: vec![ outer_descriptor ],
+          .. FileDescriptorProto::default()
+        },
+        FileDescriptorProto {
+          name: Some("request.proto".to_string()),
+          package: Some("other".to_string()),
+          dependency: vec![ "outer.proto".to_string() ],
+          message_type: vec![ message_descriptor.clone() ],
+          .. FileDescriptorProto::default()
+        }
+      ]
+    };
+
+    let mut bytes = Bytes::from_static(&[10, 6, 10, 4, b't', b'e', b's', b't']);
+    let result = decode_message(&mut bytes, &message_descriptor, &descriptors).unwrap();
+    expect!(result.len()).to(be_equal_to(1));
+    let field_result = result.first().unwrap();
+    expect!(field_result.field_name.as_str()).to(be_equal_to("inner"));
+    expect!(&field_result.data).to(be_equal_to(&ProtobufFieldData::Message(vec![10, 4, b't', b'e', b's', b't'],
+      inner_descriptor)));
+
+    expect!(render_message(&result, &descriptors)).to(be_equal_to(vec![
+      "inner: Inner".to_string(),
+      "  value: \"test\"".to_string()
+    ]));
+  }
+
+  #[test]
+  fn decode_message_with_a_field_length_exceeding_the_maximum_size() {
+    let field1 = string_field_descriptor!("implementation", 1);
+    let message_descriptor = DescriptorProto {
+      name: Some("InitPluginRequest".to_string()),
+      field: vec![
+        field1.clone()
+      ],
+      .. DescriptorProto::default()
+    };
+
+    let length = DEFAULT_MAX_FIELD_SIZE as u64 + 1;
+    let mut buffer = BytesMut::new();
+    buffer.put_u8(10);
+    encode_varint(length, &mut buffer);
+    buffer.put_slice("test".as_bytes());
+    let result = decode_message(&mut buffer, &message_descriptor, &FileDescriptorSet{ file: vec![] });
+    expect!(result.unwrap_err().to_string()).to(be_equal_to(
+      format!("Field 1 has a length of {} bytes, which exceeds the maximum allowed size of {} bytes", length, DEFAULT_MAX_FIELD_SIZE)));
+
+    // Unknown fields are also checked
+    let mut buffer = BytesMut::new();
+    buffer.put_u8(18);
+    encode_varint(length, &mut buffer);
+    buffer.put_slice("test".as_bytes());
+    let result = decode_message(&mut buffer, &message_descriptor, &FileDescriptorSet{ file: vec![] });
+    expect!(result.unwrap_err().to_string()).to(be_equal_to(
+      format!("Field 2 has a length of {} bytes, which exceeds the maximum allowed size of {} bytes", length, DEFAULT_MAX_FIELD_SIZE)));
+  }
+
+  #[test]
+  fn decode_message_returns_the_error_variant_for_the_failure() {
+    let message_descriptor = DescriptorProto {
+      name: Some("TestMessage".to_string()),
+      field: vec![
+        string_field_descriptor!("name", 1),
+        message_field_descriptor!("child", 2, ".Missing")
+      ],
+      .. DescriptorProto::default()
+    };
+    let descriptors = FileDescriptorSet { file: vec![] };
+
+    // field 1 has a length of 10 bytes, but only 4 bytes are available
+    let mut buffer = Bytes::from_static(&[10, 10, 116, 101, 115, 116]);
+    let result = decode_message(&mut buffer, &message_descriptor, &descriptors);
+    expect!(matches!(result, Err(ProtobufError::DecodeFailure(_)))).to(be_true());
+
+    // field 1 with an end group wire type with no matching start group
+    let mut buffer = Bytes::from_static(&[12]);
+    let result = decode_message(&mut buffer, &message_descriptor, &descriptors);
+    expect!(matches!(result, Err(ProtobufError::UnsupportedType(_)))).to(be_true());
+
+    // field 2 is an embedded message which has no descriptor
+    let mut buffer = Bytes::from_static(&[18, 0]);
+    let result = decode_message(&mut buffer, &message_descriptor, &descriptors);
+    expect!(matches!(result, Err(ProtobufError::DescriptorNotFound(_)))).to(be_true());
+  }
+
+  #[test]
+  fn encode_fields_round_trip() {
+    let child_descriptor = DescriptorProto {
+      name: Some("Child".to_string()),
+      field: vec![ i64_field_descriptor!("id", 1) ],
+      .. DescriptorProto::default()
+    };
+    let descriptor = DescriptorProto {
+      name: Some("Complex".to_string()),
+      field: vec![
+        string_field_descriptor!("name", 1),
+        prost_types::FieldDescriptorProto {
+          r#type: Some(prost_types::field_descriptor_proto::Type::Sint32 as i32),
+          .. i32_field_descriptor!("delta", 2)
+        },
+        prost_types::FieldDescriptorProto {
+          label: Some(prost_types::field_descriptor_proto::Label::Repeated as i32),
+          .. i32_field_descriptor!("values", 3)
+        },
+        message_field_descriptor!("child", 4, "Child"),
+        prost_types::FieldDescriptorProto {
+          label: Some(prost_types::field_descriptor_proto::Label::Repeated as i32),
+          .. string_field_descriptor!("tags", 5)
+        },
+        f64_field_descriptor!("score", 6)
+      ],
+      nested_type: vec![ child_descriptor ],
+      .. DescriptorProto::default()
+    };
+
+    let mut buffer = BytesMut::new();
+    buffer.put_slice(&[10, 4]);
+    buffer.put_slice("test".as_bytes());
+    buffer.put_slice(&[16, 5]); // -3 as a zigzag encoded sint32
+    buffer.put_slice(&[26, 4, 1, 2, 172, 2]); // packed [1, 2, 300]
+    buffer.put_slice(&[34, 2, 8, 7]); // Child { id: 7 }
+    buffer.put_slice(&[42, 1, 97, 42, 1, 98]);
+    buffer.put_u8(49);
+    buffer.put_f64_le(1.5);
+    let bytes = buffer.freeze();
+
+    let fields = decode_message(&mut bytes.clone(), &descriptor, &FileDescriptorSet { file: vec![] }).unwrap();
+    expect!(fields.len()).to(be_equal_to(9));
+    expect!(fields[1].data.clone()).to(be_equal_to(ProtobufFieldData::Integer32(-3)));
+
+    let encoded = encode_fields(&fields).unwrap();
+    expect!(encoded.as_slice()).to(be_equal_to(bytes.as_ref()));
+
+    let decoded = decode_message(&mut Bytes::from(encoded), &descriptor, &FileDescriptorSet { file: vec![] }).unwrap();
+    expect!(decoded).to(be_equal_to(fields));
+  }
+
   #[test]
   fn default_field_value_test_boolean() {
     let descriptor = bool_field_descriptor!("bool_field", 1);
@@ -1205,6 +1894,60 @@ mod tests {
     expect!(ProtobufFieldData::Integer64(123).default_field_value(&descriptor)).to(be_equal_to(ProtobufFieldData::Integer64(0)));
   }
 
+  #[test]
+  fn default_field_value_test_fixed32() {
+    let descriptor = prost_types::FieldDescriptorProto {
+      r#type: Some(prost_types::field_descriptor_proto::Type::Fixed32 as i32),
+      .. u32_field_descriptor!("field", 1)
+    };
+    expect!(ProtobufFieldData::UInteger32(123).default_field_value(&descriptor)).to(be_equal_to(ProtobufFieldData::UInteger32(0)));
+
+    let descriptor = prost_types::FieldDescriptorProto {
+      default_value: Some("4000000000".to_string()),
+      .. descriptor
+    };
+    expect!(ProtobufFieldData::UInteger32(123).default_field_value(&descriptor)).to(be_equal_to(ProtobufFieldData::UInteger32(4000000000)));
+
+    let descriptor = prost_types::FieldDescriptorProto {
+      default_value: Some("sdsd".to_string()),
+      .. descriptor
+    };
+    expect!(ProtobufFieldData::UInteger32(123).default_field_value(&descriptor)).to(be_equal_to(ProtobufFieldData::UInteger32(0)));
+  }
+
+  #[test]
+  fn default_field_value_test_fixed64() {
+    let descriptor = prost_types::FieldDescriptorProto {
+      r#type: Some(prost_types::field_descriptor_proto::Type::Fixed64 as i32),
+      .. u64_field_descriptor!("field", 1)
+    };
+    expect!(ProtobufFieldData::UInteger64(123).default_field_value(&descriptor)).to(be_equal_to(ProtobufFieldData::UInteger64(0)));
+
+    let descriptor = prost_types::FieldDescriptorProto {
+      default_value: Some("18000000000000000000".to_string()),
+      .. descriptor
+    };
+    expect!(ProtobufFieldData::UInteger64(123).default_field_value(&descriptor)).to(be_equal_to(ProtobufFieldData::UInteger64(18000000000000000000)));
+
+    let descriptor = prost_types::FieldDescriptorProto {
+      default_value: Some("sdsd".to_string()),
+      .. descriptor
+    };
+    expect!(ProtobufFieldData::UInteger64(123).default_field_value(&descriptor)).to(be_equal_to(ProtobufFieldData::UInteger64(0)));
+  }
+
+  #[test]
+  fn is_default_field_value_for_floating_point_values() {
+    expect!(ProtobufFieldData::Double(0.0).is_default_field_value()).to(be_true());
+    expect!(ProtobufFieldData::Double(f64::NAN).is_default_field_value()).to(be_false());
+    expect!(ProtobufFieldData::Double(f64::INFINITY).is_default_field_value()).to(be_false());
+    expect!(ProtobufFieldData::Double(f64::NEG_INFINITY).is_default_field_value()).to(be_false());
+    expect!(ProtobufFieldData::Float(0.0).is_default_field_value()).to(be_true());
+    expect!(ProtobufFieldData::Float(f32::NAN).is_default_field_value()).to(be_false());
+    expect!(ProtobufFieldData::Float(f32::INFINITY).is_default_field_value()).to(be_false());
+    expect!(ProtobufFieldData::Float(f32::NEG_INFINITY).is_default_field_value()).to(be_false());
+  }
+
   #[test]
   fn default_field_value_test_f32() {
     let descriptor = f32_field_descriptor!("field", 1);
@@ -1382,6 +2125,46 @@ mod tests {
     expect!(&field_result.data).to(be_equal_to(&ProtobufFieldData::Float(12.0)));
   }
 
+  #[test]
+  fn repeated_field_encodings_test() {
+    let descriptor = DescriptorProto {
+      name: Some("RepeatedFieldMessage".to_string()),
+      field: vec![
+        prost_types::FieldDescriptorProto {
+          name: Some("values".to_string()),
+          number: Some(1),
+          label: Some(prost_types::field_descriptor_proto::Label::Repeated as i32),
+          r#type: Some(prost_types::field_descriptor_proto::Type::Int32 as i32),
+          .. prost_types::FieldDescriptorProto::default()
+        },
+        prost_types::FieldDescriptorProto {
+          name: Some("name".to_string()),
+          number: Some(2),
+          r#type: Some(prost_types::field_descriptor_proto::Type::String as i32),
+          .. prost_types::FieldDescriptorProto::default()
+        }
+      ],
+      .. DescriptorProto::default()
+    };
+
+    let mut packed = BytesMut::new();
+    packed.put_slice(&[10, 3, 1, 2, 3]); // values: packed [1, 2, 3]
+    packed.put_slice(&[18, 1, 97]); // name: "a"
+    expect!(repeated_field_encodings(&mut packed, &descriptor).unwrap())
+      .to(be_equal_to(hashmap!{ 1 => true }));
+
+    let mut unpacked = BytesMut::new();
+    unpacked.put_slice(&[8, 1, 8, 2, 8, 3]); // values: unpacked [1, 2, 3]
+    unpacked.put_slice(&[18, 1, 97]); // name: "a"
+    expect!(repeated_field_encodings(&mut unpacked, &descriptor).unwrap())
+      .to(be_equal_to(hashmap!{ 1 => false }));
+
+    let mut mixed = BytesMut::new();
+    mixed.put_slice(&[10, 2, 1, 2, 8, 3]); // values: packed [1, 2] then unpacked [3]
+    expect!(repeated_field_encodings(&mut mixed, &descriptor).unwrap())
+      .to(be_equal_to(hashmap!{ 1 => false }));
+  }
+
   #[test_log::test]
   fn decode_message_with_global_enum_field() {
     let bytes: &[u8] = &DESCRIPTOR_WITH_ENUM_BYTES;
@@ -5,6 +5,9 @@ use std::collections::HashMap;
 use ansi_term::Colour::{Green, Red};
 use ansi_term::Style;
 use anyhow::anyhow;
+use base64::Engine;
+use base64::engine::general_purpose::STANDARD as BASE64;
+use bytes::Bytes;
 use itertools::{Either, Itertools};
 use maplit::hashmap;
 use pact_matching::{CoreMatchingContext, matchers, MatchingContext, Mismatch};
@@ -16,13 +19,15 @@ use pact_models::matchingrules::expressions::{is_matcher_def, MatchingRuleDefini
 use pact_models::path_exp::DocPath;
 use pact_models::v4::message_parts::MessageContents;
 use pact_plugin_driver::utils::proto_value_to_string;
-use prost_types::Value;
+use prost::Message;
+use prost_types::{FileDescriptorSet, Value};
 use tonic::{Code, Status};
 use tonic::metadata::{Ascii, MetadataMap, MetadataValue};
-use tracing::instrument;
+use tracing::{instrument, warn};
 use tracing::log::trace;
 
-use crate::utils::proto_value_to_map;
+use crate::matching::match_message;
+use crate::utils::{find_message_descriptor_for_type, proto_value_to_map};
 
 #[derive(Clone, Debug, PartialEq, PartialOrd, Default)]
 /// Wrapper for a message metadata value. Currently only string values are supported.
@@ -39,11 +44,66 @@ impl MessageMetadataValue {
   }
 }
 
+/// Key in the message metadata that the expected gRPC trailers (trailing metadata) are stored under
+pub const TRAILERS_KEY: &str = "pact:trailers";
+
+/// Key in the message metadata that the expected gRPC status details are stored under. The value is
+/// the Base64 encoded `google.rpc.Status` message, which is what is sent in the gRPC trailers.
+pub const STATUS_DETAILS_KEY: &str = "grpc-status-details-bin";
+
+/// The `google.rpc.Status` message, which is used by the gRPC richer error model to return
+/// additional error details with a status
+#[derive(Clone, PartialEq, prost::Message)]
+pub struct RpcStatus {
+  /// The status code, which should be an enum value of `google.rpc.Code`
+  #[prost(int32, tag = "1")]
+  pub code: i32,
+  /// Developer-facing error message
+  #[prost(string, tag = "2")]
+  pub message: String,
+  /// List of messages that carry the error details
+  #[prost(message, repeated, tag = "3")]
+  pub details: Vec<prost_types::Any>
+}
+
 #[derive(Clone, Debug)]
 pub struct MessageMetadata {
   pub matching_rules: MatchingRuleCategory,
   pub generators: HashMap<String, Generator>,
   pub values: HashMap<String, MessageMetadataValue>,
+  /// Expected trailing metadata values
+  pub trailers: HashMap<String, MessageMetadataValue>
+}
+
+impl MessageMetadata {
+  /// Adds the trailing metadata to this metadata. Any matching rules and generators for the
+  /// trailers are stored under the trailers key.
+  pub fn with_trailers(&self, trailers: &MessageMetadata) -> MessageMetadata {
+    let mut metadata = self.clone();
+    let trailers_path = DocPath::root().join(TRAILERS_KEY);
+    for (path, rules) in &trailers.matching_rules.rules {
+      let key = path.last_field().unwrap_or_default();
+      for rule in &rules.rules {
+        metadata.matching_rules.add_rule(trailers_path.join(key), rule.clone(), rules.rule_logic);
+      }
+    }
+    for (key, generator) in &trailers.generators {
+      metadata.generators.insert(trailers_path.join(key).to_string(), generator.clone());
+    }
+    metadata.trailers.extend(trailers.values.clone());
+    metadata
+  }
+}
+
+impl Default for MessageMetadata {
+  fn default() -> Self {
+    MessageMetadata {
+      matching_rules: MatchingRuleCategory::empty("metadata"),
+      generators: hashmap!{},
+      values: hashmap!{},
+      trailers: hashmap!{}
+    }
+  }
 }
 
 #[instrument(ret, level = "trace")]
@@ -60,7 +120,10 @@ pub fn process_metadata(metadata_config: Option<&Value>) -> anyhow::Result<Optio
 
       for (key, value) in &metadata_map {
         let str_value = proto_value_to_string(value).unwrap_or_default();
-        if is_matcher_def(str_value.as_str()) {
+        if let Some(example) = present_matcher_value(str_value.as_str()) {
+          matching_rules.add_rule(DocPath::new(key)?, MatchingRule::NotEmpty, RuleLogic::And);
+          values.insert(key.clone(), MessageMetadataValue::new(example));
+        } else if is_matcher_def(str_value.as_str()) {
           let mrd = parse_matcher_def(str_value.as_str())?;
           if !mrd.rules.is_empty() {
             for rule in &mrd.rules {
@@ -82,12 +145,30 @@ pub fn process_metadata(metadata_config: Option<&Value>) -> anyhow::Result<Optio
       Ok(Some(MessageMetadata {
         matching_rules,
         generators,
-        values
+        values,
+        trailers: hashmap!{}
       }))
     }
   }
 }
 
+/// Default example value used for metadata keys configured with `present()`
+const PRESENT_EXAMPLE_VALUE: &str = "present";
+
+/// If the metadata value is configured with `present()` (or `present('example')`), which only
+/// requires the key to be set with a non-empty value. Returns the example value to use.
+fn present_matcher_value(value: &str) -> Option<&str> {
+  let args = value.trim()
+    .strip_prefix("present(")
+    .and_then(|value| value.strip_suffix(')'))?
+    .trim();
+  if args.is_empty() {
+    Some(PRESENT_EXAMPLE_VALUE)
+  } else {
+    args.strip_prefix('\'').and_then(|args| args.strip_suffix('\''))
+  }
+}
+
 #[derive(Clone, Debug)]
 pub struct MetadataMatchResult {
   /// Overall result of the comparison
@@ -124,6 +205,38 @@ pub fn compare_metadata(
   expected_metadata: &HashMap<String, serde_json::Value>,
   actual_metadata: &MetadataMap,
   context: &CoreMatchingContext
+) -> anyhow::Result<(MetadataMatchResult, Vec<String>)> {
+  compare_metadata_values(&DocPath::root(), expected_metadata, actual_metadata, context)
+}
+
+/// Compares the expected trailing metadata (stored under the trailers key in the expected metadata)
+/// to the actual trailing metadata
+#[instrument(ret)]
+pub fn compare_trailers(
+  expected_metadata: &HashMap<String, serde_json::Value>,
+  actual_trailers: &MetadataMap,
+  context: &CoreMatchingContext
+) -> anyhow::Result<(MetadataMatchResult, Vec<String>)> {
+  let expected_trailers = expected_trailers(expected_metadata);
+  compare_metadata_values(&DocPath::root().join(TRAILERS_KEY), &expected_trailers,
+    actual_trailers, context)
+}
+
+/// Returns the expected trailing metadata values from the expected metadata
+pub fn expected_trailers(expected_metadata: &HashMap<String, serde_json::Value>) -> HashMap<String, serde_json::Value> {
+  expected_metadata.get(TRAILERS_KEY)
+    .and_then(|trailers| trailers.as_object())
+    .map(|trailers| trailers.iter()
+      .map(|(k, v)| (k.clone(), v.clone()))
+      .collect())
+    .unwrap_or_default()
+}
+
+fn compare_metadata_values(
+  path: &DocPath,
+  expected_metadata: &HashMap<String, serde_json::Value>,
+  actual_metadata: &MetadataMap,
+  context: &CoreMatchingContext
 ) -> anyhow::Result<(MetadataMatchResult, Vec<String>)> {
   if expected_metadata.is_empty() {
     Ok((MetadataMatchResult::ok(), vec![]))
@@ -150,7 +263,7 @@ pub fn compare_metadata(
 
     for (key, expected_value) in expected_metadata {
       if let Some(actual_value) = actual_metadata.get(key) {
-        let out = match_metadata_value(&mut mismatches, key, expected_value, actual_value, context);
+        let out = match_metadata_value(&mut mismatches, &path.join(key), key, expected_value, actual_value, context);
         output.push(out);
       } else if !is_special_metadata_key(key.as_str()) {
         output.push(format!("          key '{}' ({})", bold.paint(key), Red.paint("FAILED")));
@@ -172,24 +285,24 @@ pub fn compare_metadata(
 
 fn is_special_metadata_key(key: &str) -> bool {
   let key = key.to_lowercase();
-  key == "content-type" || key == "contenttype"
+  key == "content-type" || key == "contenttype" || key == TRAILERS_KEY || key == STATUS_DETAILS_KEY
 }
 
 fn match_metadata_value(
   mismatches: &mut Vec<Mismatch>,
+  path: &DocPath,
   key: &String,
   expected: &serde_json::Value,
   actual: &MetadataValue<Ascii>,
   context: &CoreMatchingContext
 ) -> String {
-  let path = DocPath::root().join(key);
   let expected = json_to_string(expected);
   let bold = Style::new().bold();
   match actual.to_str() {
     Ok(actual) => {
-      if context.matcher_is_defined(&path) {
-        let matchers = context.select_best_matcher(&path);
-        let result = if let Err(errors) = matchers::match_values(&path, &matchers, &expected, &actual.to_string()) {
+      if context.matcher_is_defined(path) {
+        let matchers = context.select_best_matcher(path);
+        let result = if let Err(errors) = matchers::match_values(path, &matchers, &expected, &actual.to_string()) {
           for mismatch in errors {
             mismatches.push(Mismatch::MetadataMismatch {
               key: key.clone(),
@@ -288,11 +401,118 @@ pub fn grpc_status(response_contents: &MessageContents) -> Option<Status> {
       .map(json_to_string)
       .unwrap_or("No message set".to_string());
     string_to_code(status.as_str(), message.as_str())
+      .map(|status| match expected_status_details(&response_contents.metadata) {
+        Some(Ok(details)) => Status::with_details(status.code(), status.message(),
+          Bytes::from(details.encode_to_vec())),
+        Some(Err(err)) => {
+          warn!("Ignoring the gRPC status details as they could not be decoded - {}", err);
+          status
+        }
+        None => status
+      })
   } else {
     None
   }
 }
 
+/// Returns the expected gRPC status details (`google.rpc.Status`) from the expected metadata, if
+/// there are any
+pub fn expected_status_details(
+  expected_metadata: &HashMap<String, serde_json::Value>
+) -> Option<anyhow::Result<RpcStatus>> {
+  expected_metadata.get(STATUS_DETAILS_KEY)
+    .map(|value| {
+      let bytes = BASE64.decode(json_to_string(value))
+        .map_err(|err| anyhow!("The gRPC status details are not valid Base64 - {}", err))?;
+      RpcStatus::decode(bytes.as_slice())
+        .map_err(|err| anyhow!("The gRPC status details are not a valid google.rpc.Status message - {}", err))
+    })
+}
+
+/// Compares the expected gRPC status details (stored under the `grpc-status-details-bin` key in
+/// the expected metadata) to the details of the status received. The code and message must be
+/// equal, and each of the expected detail messages must be matched by the received detail message
+/// at the same position, which must have the same type. Detail messages with types that are in the
+/// descriptors are compared as Protobuf messages, otherwise the bytes are compared.
+pub fn compare_status_details(
+  expected_metadata: &HashMap<String, serde_json::Value>,
+  actual_status: &Status,
+  descriptors: &FileDescriptorSet
+) -> anyhow::Result<(MetadataMatchResult, Vec<String>)> {
+  let expected = match expected_status_details(expected_metadata) {
+    Some(expected) => expected?,
+    None => return Ok((MetadataMatchResult::ok(), vec![]))
+  };
+
+  let bold = Style::new().bold();
+  let key = STATUS_DETAILS_KEY.to_string();
+  let mismatch = |mismatch: String| Mismatch::MetadataMismatch {
+    key: key.clone(),
+    expected: BASE64.encode(expected.encode_to_vec()),
+    actual: BASE64.encode(actual_status.details()),
+    mismatch
+  };
+
+  if actual_status.details().is_empty() {
+    return Ok((
+      MetadataMatchResult::mismatches(vec![
+        mismatch("Expected the gRPC status to have details, but none were received".to_string())
+      ]),
+      vec![ format!("        status details ({})", Red.paint("FAILED")) ]
+    ));
+  }
+
+  let actual = RpcStatus::decode(actual_status.details())
+    .map_err(|err| anyhow!("The received gRPC status details are not a valid google.rpc.Status message - {}", err))?;
+  let mut mismatches = vec![];
+  if expected.code != actual.code {
+    mismatches.push(mismatch(format!("Expected status details code {} but received {}",
+      expected.code, actual.code)));
+  }
+  if expected.message != actual.message {
+    mismatches.push(mismatch(format!("Expected status details message '{}' but received '{}'",
+      expected.message, actual.message)));
+  }
+
+  for (index, expected_detail) in expected.details.iter().enumerate() {
+    match actual.details.get(index) {
+      Some(actual_detail) if actual_detail.type_url != expected_detail.type_url => {
+        mismatches.push(mismatch(format!("Expected status detail {} to have type '{}' but received '{}'",
+          index, expected_detail.type_url, actual_detail.type_url)));
+      }
+      Some(actual_detail) => {
+        let message_name = expected_detail.type_url.rsplit('/').next().unwrap_or_default();
+        if find_message_descriptor_for_type(format!(".{}", message_name).as_str(), descriptors).is_ok() {
+          let result = match_message(format!(".{}", message_name).as_str(), descriptors,
+            &mut Bytes::from(expected_detail.value.clone()), &mut Bytes::from(actual_detail.value.clone()),
+            &MatchingRuleCategory::empty("body"), true, false, &hashmap!{})?;
+          for body_mismatch in result.mismatches() {
+            if let Mismatch::BodyMismatch { path, mismatch: description, .. } = body_mismatch {
+              mismatches.push(mismatch(format!("Status detail {} ({}) {}: {}", index, message_name,
+                path, description)));
+            }
+          }
+        } else if expected_detail.value != actual_detail.value {
+          mismatches.push(mismatch(format!("Expected status detail {} ({}) to be equal to the received detail",
+            index, message_name)));
+        }
+      }
+      None => {
+        mismatches.push(mismatch(format!("Expected status detail {} with type '{}' but it was not received",
+          index, expected_detail.type_url)));
+      }
+    }
+  }
+
+  let output = format!("        status details with {} detail message(s) [{}]", bold.paint(expected.details.len().to_string()),
+    if mismatches.is_empty() { Green.paint("OK") } else { Red.paint("FAILED") });
+  if mismatches.is_empty() {
+    Ok((MetadataMatchResult::ok(), vec![ output ]))
+  } else {
+    Ok((MetadataMatchResult::mismatches(mismatches), vec![ output ]))
+  }
+}
+
 pub fn string_to_code(status: &str, message: &str) -> Option<Status> {
   match status {
     // Taken from https://grpc.github.io/grpc/core/md_doc_statuscodes.html
@@ -352,15 +572,31 @@ mod tests {
   use maplit::{btreemap, hashmap};
   use pact_matching::{CoreMatchingContext, DiffConfig, Mismatch};
   use pact_models::matchingrules;
-  use pact_models::matchingrules::MatchingRule;
+  use pact_models::matchingrules::{MatchingRule, MatchingRuleCategory, RuleLogic};
+  use pact_models::generators::Generator;
   use pact_models::path_exp::DocPath;
+  use pact_models::expression_parser::DataType;
   use pact_models::v4::message_parts::MessageContents;
   use prost_types::{Struct, Value, value};
   use serde_json::json;
-  use tonic::Code;
+  use base64::Engine;
+  use base64::engine::general_purpose::STANDARD as BASE64;
+  use prost::Message;
+  use prost_types::FileDescriptorSet;
+  use tonic::{Code, Status};
   use tonic::metadata::MetadataMap;
 
-  use crate::metadata::{compare_metadata, grpc_status, MessageMetadataValue, process_metadata};
+  use crate::metadata::{
+    compare_metadata,
+    compare_status_details,
+    compare_trailers,
+    grpc_status,
+    MessageMetadataValue,
+    process_metadata,
+    RpcStatus,
+    STATUS_DETAILS_KEY,
+    TRAILERS_KEY
+  };
   use crate::utils::prost_string;
 
   #[test]
@@ -421,6 +657,24 @@ mod tests {
     ]));
   }
 
+  #[test]
+  fn process_metadata_records_any_generators_in_the_values() {
+    let config = Value {
+      kind: Some(value::Kind::StructValue(Struct {
+        fields: btreemap!{
+          "authorization".to_string() => prost_string("matching(equalTo, fromProviderState('Bearer ${token}', 'Bearer 1234'))")
+        }
+      }))
+    };
+    let result = process_metadata(Some(&config)).unwrap().unwrap();
+    expect!(result.values).to(be_equal_to(hashmap!{
+      "authorization".to_string() => MessageMetadataValue::new("Bearer 1234")
+    }));
+    expect!(result.generators).to(be_equal_to(hashmap!{
+      "authorization".to_string() => Generator::ProviderStateGenerator("Bearer ${token}".to_string(), Some(DataType::STRING))
+    }));
+  }
+
   #[test]
   fn compare_metadata_returns_ok_if_there_is_no_expected_metadata() {
     let expected = hashmap!{};
@@ -509,6 +763,90 @@ mod tests {
     }).collect::<Vec<String>>()).to(be_equal_to(vec!["x-b".to_string()]));
   }
 
+  #[test]
+  fn compare_metadata_with_keys_that_only_need_to_be_present() {
+    let config = Value {
+      kind: Some(value::Kind::StructValue(Struct {
+        fields: btreemap!{
+          "x-request-id".to_string() => prost_string("present()"),
+          "x-trace-id".to_string() => prost_string("present('abc123')"),
+          "x-session".to_string() => prost_string("notEmpty('s1')")
+        }
+      }))
+    };
+    let metadata = process_metadata(Some(&config)).unwrap().unwrap();
+    expect!(metadata.values.clone()).to(be_equal_to(hashmap!{
+      "x-request-id".to_string() => MessageMetadataValue::new("present"),
+      "x-trace-id".to_string() => MessageMetadataValue::new("abc123"),
+      "x-session".to_string() => MessageMetadataValue::new("s1")
+    }));
+    let expected = metadata.values.iter()
+      .map(|(k, v)| (k.clone(), json!(v.value)))
+      .collect();
+    let context = CoreMatchingContext::new(DiffConfig::NoUnexpectedKeys, &metadata.matching_rules, &hashmap!{});
+
+    let mut actual = MetadataMap::new();
+    actual.insert("x-request-id", "5f0c7e".parse().unwrap());
+    actual.insert("x-trace-id", "any value".parse().unwrap());
+    actual.insert("x-session", "1234".parse().unwrap());
+    let (result, _) = compare_metadata(&expected, &actual, &context).unwrap();
+    expect!(result.mismatches.iter()).to(be_empty());
+
+    let mut actual = MetadataMap::new();
+    actual.insert("x-trace-id", "".parse().unwrap());
+    actual.insert("x-session", "1234".parse().unwrap());
+    let (result, _) = compare_metadata(&expected, &actual, &context).unwrap();
+    let mut keys = result.mismatches.iter().map(|m| match m {
+      Mismatch::MetadataMismatch { key, .. } => key.clone(),
+      _ => m.description()
+    }).collect::<Vec<String>>();
+    keys.sort();
+    expect!(keys).to(be_equal_to(vec!["x-request-id".to_string(), "x-trace-id".to_string()]));
+  }
+
+  #[test]
+  fn compare_metadata_with_matchers_from_the_metadata_configuration() {
+    let config = Value {
+      kind: Some(value::Kind::StructValue(Struct {
+        fields: btreemap!{
+          "x-request-id".to_string() => prost_string("matching(regex, '^[a-f0-9]{6}$', 'a1b2c3')"),
+          "x-timestamp".to_string() => prost_string("matching(datetime, 'yyyy-MM-dd HH:mm:ss', '2024-01-02 10:11:12')"),
+          "x-count".to_string() => prost_string("matching(number, 100)"),
+          "x-info".to_string() => prost_string("matching(include, 'ok')")
+        }
+      }))
+    };
+    let metadata = process_metadata(Some(&config)).unwrap().unwrap();
+    expect!(metadata.matching_rules.rules.len()).to(be_equal_to(4));
+    let expected = metadata.values.iter()
+      .map(|(k, v)| (k.clone(), json!(v.value)))
+      .collect();
+    let context = CoreMatchingContext::new(DiffConfig::NoUnexpectedKeys, &metadata.matching_rules, &hashmap!{});
+
+    let mut actual = MetadataMap::new();
+    actual.insert("x-request-id", "ffee00".parse().unwrap());
+    actual.insert("x-timestamp", "2025-12-31 23:59:59".parse().unwrap());
+    actual.insert("x-count", "12.5".parse().unwrap());
+    actual.insert("x-info", "all ok here".parse().unwrap());
+    let (result, _) = compare_metadata(&expected, &actual, &context).unwrap();
+    expect!(result.mismatches.iter()).to(be_empty());
+
+    let mut actual = MetadataMap::new();
+    actual.insert("x-request-id", "XYZ".parse().unwrap());
+    actual.insert("x-timestamp", "31/12/2025".parse().unwrap());
+    actual.insert("x-count", "lots".parse().unwrap());
+    actual.insert("x-info", "failed".parse().unwrap());
+    let (result, _) = compare_metadata(&expected, &actual, &context).unwrap();
+    let mut keys = result.mismatches.iter().map(|m| match m {
+      Mismatch::MetadataMismatch { key, .. } => key.clone(),
+      _ => m.description()
+    }).collect::<Vec<String>>();
+    keys.sort();
+    expect!(keys).to(be_equal_to(vec![
+      "x-count".to_string(), "x-info".to_string(), "x-request-id".to_string(), "x-timestamp".to_string()
+    ]));
+  }
+
   #[test]
   fn compare_metadata_when_checking_missing_keys_ignores_pact_special_values() {
     let expected = hashmap!{
@@ -524,6 +862,86 @@ mod tests {
     expect!(result.mismatches.len()).to(be_equal_to(0));
   }
 
+  #[test]
+  fn with_trailers_stores_the_trailer_values_and_rules_under_the_trailers_key() {
+    let headers = process_metadata(Some(&Value {
+      kind: Some(value::Kind::StructValue(Struct {
+        fields: btreemap!{
+          "x-status".to_string() => prost_string("matching(regex, '^[a-z]+$', 'ok')")
+        }
+      }))
+    })).unwrap().unwrap();
+    let trailers = process_metadata(Some(&Value {
+      kind: Some(value::Kind::StructValue(Struct {
+        fields: btreemap!{
+          "x-status".to_string() => prost_string("matching(integer, 100)")
+        }
+      }))
+    })).unwrap().unwrap();
+
+    let result = headers.with_trailers(&trailers);
+    expect!(result.values).to(be_equal_to(hashmap!{
+      "x-status".to_string() => MessageMetadataValue::new("ok")
+    }));
+    expect!(result.trailers).to(be_equal_to(hashmap!{
+      "x-status".to_string() => MessageMetadataValue::new("100")
+    }));
+    let rules = result.matching_rules.rules.get(&DocPath::root().join(TRAILERS_KEY).join("x-status"));
+    expect!(&rules.unwrap().rules).to(be_equal_to(&vec![ MatchingRule::Integer ]));
+  }
+
+  #[test]
+  fn compare_trailers_matches_the_trailer_values_separately_from_the_headers() {
+    let expected = hashmap!{
+      "x-status".to_string() => json!("header"),
+      TRAILERS_KEY.to_string() => json!({
+        "x-status": "trailer"
+      })
+    };
+    let mut headers = MetadataMap::new();
+    headers.insert("x-status", "header".parse().expect("Expected a value"));
+    let mut trailers = MetadataMap::new();
+    trailers.insert("x-status", "trailer".parse().expect("Expected a value"));
+    let context = CoreMatchingContext::default();
+
+    let (result, _) = compare_metadata(&expected, &headers, &context).unwrap();
+    expect!(result.result).to(be_true());
+    let (result, _) = compare_trailers(&expected, &trailers, &context).unwrap();
+    expect!(result.result).to(be_true());
+
+    let (result, _) = compare_trailers(&expected, &headers, &context).unwrap();
+    expect!(result.result).to(be_false());
+    expect!(result.mismatches.len()).to(be_equal_to(1));
+  }
+
+  #[test]
+  fn compare_trailers_applies_the_trailer_matching_rules() {
+    let expected = hashmap!{
+      "x-status".to_string() => json!("ok"),
+      TRAILERS_KEY.to_string() => json!({
+        "x-status": "100"
+      })
+    };
+    let mut headers = MetadataMap::new();
+    headers.insert("x-status", "ok".parse().expect("Expected a value"));
+    let mut trailers = MetadataMap::new();
+    trailers.insert("x-status", "200".parse().expect("Expected a value"));
+    let mut rules = MatchingRuleCategory::empty("metadata");
+    rules.add_rule(DocPath::new_unwrap("x-status"), MatchingRule::Regex("^[a-z]+$".to_string()), RuleLogic::And);
+    rules.add_rule(DocPath::root().join(TRAILERS_KEY).join("x-status"), MatchingRule::Integer, RuleLogic::And);
+    let context = CoreMatchingContext::new(DiffConfig::AllowUnexpectedKeys, &rules, &hashmap!{});
+
+    let (result, _) = compare_metadata(&expected, &headers, &context).unwrap();
+    expect!(result.result).to(be_true());
+    let (result, _) = compare_trailers(&expected, &trailers, &context).unwrap();
+    expect!(result.result).to(be_true());
+
+    let mut trailers = MetadataMap::new();
+    trailers.insert("x-status", "ok".parse().expect("Expected a value"));
+    let (result, _) = compare_trailers(&expected, &trailers, &context).unwrap();
+    expect!(result.result).to(be_false());
+  }
+
   #[test]
   fn grpc_status_test_no_status_set() {
     let message = MessageContents {
@@ -576,4 +994,51 @@ mod tests {
     let message = setup_message("33", None);
     expect!(grpc_status(&message).unwrap().code()).to(be_equal_to(Code::Unknown));
   }
+
+  #[test]
+  fn compare_status_details_test() {
+    let status = |code: Code, details: Vec<(&str, &[u8])>| RpcStatus {
+      code: code as i32,
+      message: "Not found".to_string(),
+      details: details.iter().map(|(type_url, value)| prost_types::Any {
+        type_url: type_url.to_string(),
+        value: value.to_vec()
+      }).collect()
+    };
+    let expected_metadata = hashmap!{
+      "grpc-status".to_string() => json!("NOT_FOUND"),
+      STATUS_DETAILS_KEY.to_string() => json!(BASE64.encode(
+        status(Code::NotFound, vec![ ("type.googleapis.com/ErrorInfo", &[1, 2, 3]) ]).encode_to_vec()))
+    };
+    let fds = FileDescriptorSet { file: vec![] };
+    let compare = |actual: RpcStatus| {
+      let actual_status = Status::with_details(Code::NotFound, "Not found", actual.encode_to_vec().into());
+      compare_status_details(&expected_metadata, &actual_status, &fds).unwrap().0.mismatches.iter()
+        .map(|mismatch| mismatch.description())
+        .collect::<Vec<_>>()
+    };
+
+    expect!(compare(status(Code::NotFound, vec![ ("type.googleapis.com/ErrorInfo", &[1, 2, 3]) ])).iter()).to(be_empty());
+    expect!(compare(status(Code::NotFound, vec![
+      ("type.googleapis.com/ErrorInfo", &[1, 2, 3]), ("type.googleapis.com/Help", &[4])
+    ])).iter()).to(be_empty());
+    expect!(compare(status(Code::Internal, vec![ ("type.googleapis.com/ErrorInfo", &[1, 2, 4]) ]))).to(be_equal_to(vec![
+      "Expected status details code 5 but received 13".to_string(),
+      "Expected status detail 0 (ErrorInfo) to be equal to the received detail".to_string()
+    ]));
+    expect!(compare(status(Code::NotFound, vec![ ("type.googleapis.com/Help", &[1, 2, 3]) ]))).to(be_equal_to(vec![
+      "Expected status detail 0 to have type 'type.googleapis.com/ErrorInfo' but received 'type.googleapis.com/Help'".to_string()
+    ]));
+    expect!(compare(status(Code::NotFound, vec![]))).to(be_equal_to(vec![
+      "Expected status detail 0 with type 'type.googleapis.com/ErrorInfo' but it was not received".to_string()
+    ]));
+
+    let result = compare_status_details(&expected_metadata, &Status::not_found("Not found"), &fds).unwrap();
+    expect!(result.0.mismatches.iter().map(|mismatch| mismatch.description()).collect::<Vec<_>>()).to(be_equal_to(vec![
+      "Expected the gRPC status to have details, but none were received".to_string()
+    ]));
+
+    let result = compare_status_details(&hashmap!{}, &Status::not_found("Not found"), &fds).unwrap();
+    expect!(result.0.all_matched()).to(be_true());
+  }
 }
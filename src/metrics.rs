@@ -0,0 +1,85 @@
+//! Counters for the operations performed by the plugin. The counters are updated with relaxed
+//! atomic operations, so they have negligible overhead, and are periodically logged by the
+//! plugin process.
+
+use std::sync::atomic::{AtomicU64, Ordering};
+
+use itertools::Itertools;
+
+/// Operations that are counted
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Counter {
+  /// Protobuf messages built from the test configuration
+  MessagesBuilt,
+  /// Comparisons of expected and actual messages
+  Comparisons,
+  /// Comparisons that resulted in mismatches or failed with an error
+  ComparisonFailures,
+  /// Invocations of the protoc compiler
+  ProtocInvocations,
+  /// Invocations of the protoc compiler that failed
+  ProtocFailures
+}
+
+impl Counter {
+  /// All the counters, in the order they are reported
+  pub const ALL: [Counter; 5] = [
+    Counter::MessagesBuilt,
+    Counter::Comparisons,
+    Counter::ComparisonFailures,
+    Counter::ProtocInvocations,
+    Counter::ProtocFailures
+  ];
+
+  /// Name of the counter used when reporting it
+  pub fn name(&self) -> &'static str {
+    match self {
+      Counter::MessagesBuilt => "messagesBuilt",
+      Counter::Comparisons => "comparisons",
+      Counter::ComparisonFailures => "comparisonFailures",
+      Counter::ProtocInvocations => "protocInvocations",
+      Counter::ProtocFailures => "protocFailures"
+    }
+  }
+}
+
+static COUNTERS: [AtomicU64; 5] = [const { AtomicU64::new(0) }; 5];
+
+/// Increments the given counter
+pub fn increment(counter: Counter) {
+  COUNTERS[counter as usize].fetch_add(1, Ordering::Relaxed);
+}
+
+/// Returns the current value of the given counter
+pub fn value(counter: Counter) -> u64 {
+  COUNTERS[counter as usize].load(Ordering::Relaxed)
+}
+
+/// Returns a summary of all the counters, in the form `name=value`
+pub fn summary() -> String {
+  Counter::ALL.iter()
+    .map(|counter| format!("{}={}", counter.name(), value(*counter)))
+    .join(", ")
+}
+
+#[cfg(test)]
+mod tests {
+  use expectest::prelude::*;
+
+  use super::*;
+
+  #[test]
+  fn increment_updates_the_counter() {
+    let before = value(Counter::ProtocFailures);
+    increment(Counter::ProtocFailures);
+    expect!(value(Counter::ProtocFailures)).to(be_greater_or_equal_to(before + 1));
+  }
+
+  #[test]
+  fn summary_includes_all_the_counters() {
+    let summary = summary();
+    for counter in Counter::ALL {
+      expect!(summary.contains(format!("{}=", counter.name()).as_str())).to(be_true());
+    }
+  }
+}
@@ -2,16 +2,20 @@
 
 use std::collections::HashMap;
 use std::future::Future;
-use std::net::SocketAddr;
+use std::io;
+use std::net::{IpAddr, Ipv6Addr, SocketAddr};
 use std::pin::Pin;
 use std::sync::Mutex;
 use std::task::{Context, Poll};
+use std::time::Duration;
 
 use anyhow::anyhow;
 use base64::Engine;
 use base64::engine::general_purpose::STANDARD as BASE64;
 use bytes::Bytes;
 use http::{Method, Request, Response};
+use http_body::Frame;
+use http_body_util::BodyExt;
 use hyper::body::Incoming;
 use hyper::server::conn::http2::Builder;
 use hyper_util::rt::{TokioExecutor, TokioIo};
@@ -32,6 +36,7 @@ use tokio::net::TcpListener;
 use tokio::select;
 use tokio::sync::oneshot::{channel, Sender};
 use tonic::body::{BoxBody, empty_body};
+use tonic::codec::CompressionEncoding;
 use tonic::metadata::MetadataMap;
 use tower::ServiceBuilder;
 use tower_http::ServiceBuilderExt;
@@ -40,9 +45,14 @@ use tracing::{debug, error, Instrument, instrument, trace, trace_span};
 
 use crate::dynamic_message::PactCodec;
 use crate::metadata::MetadataMatchResult;
-use crate::mock_service::MockService;
+use crate::mock_service::{MockService, ResponseTrailers};
 use crate::utils::{build_grpc_route, find_message_descriptor_for_type, lookup_service_descriptors_for_interaction, parse_grpc_route, to_fully_qualified_name};
 
+/// Number of attempts made to bind the mock server port before giving up
+const BIND_ATTEMPTS: u32 = 3;
+/// Delay before the first retry of a failed bind. This is doubled for each subsequent attempt.
+const BIND_RETRY_DELAY: Duration = Duration::from_millis(100);
+
 lazy_static! {
   pub static ref MOCK_SERVER_STATE: Mutex<HashMap<String, (Sender<()>, HashMap<String, (usize, Vec<(BodyMatchResult, MetadataMatchResult)>)>)>> = Mutex::new(hashmap!{});
 }
@@ -53,7 +63,7 @@ pub struct GrpcMockServer {
   pact: V4Pact,
   plugin_config: PluginData,
   descriptors: HashMap<String, FileDescriptorSet>,
-  routes: HashMap<String, (FileDescriptorSet, FileDescriptorProto, MethodDescriptorProto, SynchronousMessage)>,
+  routes: HashMap<String, (FileDescriptorSet, FileDescriptorProto, MethodDescriptorProto, Vec<SynchronousMessage>)>,
   /// Server key for this mock server
   pub server_key: String,
   /// test context pass in from the test framework
@@ -96,8 +106,11 @@ impl GrpcMockServer
       return Err(anyhow!("Pact file does not contain any Protobuf descriptors"));
     }
 
-    // Build a map of routes using the interactions in the Pact file
-    self.routes = self.pact.interactions.iter()
+    // Build a map of routes using the interactions in the Pact file. There can be more than one
+    // interaction for a route, in which case the interactions are kept in the order they are
+    // defined in the Pact file
+    let mut routes: HashMap<String, (FileDescriptorSet, FileDescriptorProto, MethodDescriptorProto, Vec<SynchronousMessage>)> = HashMap::new();
+    let interactions = self.pact.interactions.iter()
     .filter_map(|i| i.as_v4_sync_message())
     .filter_map(|i| match lookup_service_descriptors_for_interaction(&i, &self.pact) {
       Ok((file_set, service, method, file)) => Some((file_set, service, method, file, i.clone())),
@@ -106,34 +119,36 @@ impl GrpcMockServer
       match to_fully_qualified_name(service.name(), file.package()) {
         Ok(service_full_name) => {
           match build_grpc_route(service_full_name.as_str(), method.name()) {
-            Ok(route) => Some((route, (file_set.clone(), file.clone(), method.clone(), i.clone()))),
+            Ok(route) => Some((route, file_set.clone(), file.clone(), method.clone(), i.clone())),
             Err(_) => None
           }
         },
         Err(_) => None
       }
-    }).collect();
+    });
+    for (route, file_set, file, method, interaction) in interactions {
+      routes.entry(route)
+        .or_insert_with(|| (file_set, file, method, vec![]))
+        .3.push(interaction);
+    }
+    self.routes = routes;
 
     // Bind to a OS provided port and create a TCP listener
-    let interface = if host_interface.is_empty() {
-      "[::1]"
-    } else {
-      host_interface
-    };
-    let addr: SocketAddr = format!("{interface}:{port}").parse()?;
+    let addr = mock_server_address(host_interface, port)?;
     trace!("setting up mock server {addr}");
 
     let (shutdown_snd, mut shutdown_recv) = channel::<()>();
     {
       let mut guard = MOCK_SERVER_STATE.lock().unwrap();
-      // Initialise all the routes with an initial state of not received
-      let initial_state = self.routes.keys()
-        .map(|k| (k.clone(), (0, vec![])))
+      // Initialise all the interactions with an initial state of not received
+      let initial_state = self.routes.iter()
+        .flat_map(|(route, (_, _, _, messages))| messages.iter()
+          .map(|message| (result_key(route, message, messages.len()), (0, vec![]))))
         .collect();
       guard.insert(self.server_key.clone(), (shutdown_snd, initial_state));
     }
 
-    let listener = TcpListener::bind(addr).await?;
+    let listener = bind_with_retry(addr, BIND_ATTEMPTS, BIND_RETRY_DELAY, TcpListener::bind).await?;
     let address = listener.local_addr()?;
 
     self.update_mock_server_address(&address);
@@ -241,8 +256,11 @@ impl Service<Request<Incoming>> for GrpcMockServer  {
             let request_path = req.uri().path();
             debug!(?request_path, "gRPC request received");
             if let Some((service_full_name, method)) = parse_grpc_route(request_path) {
-              if let Some((file, _file_descriptor, method_descriptor, message)) = routes.get(request_path) {
-                trace!(message = message.description.as_str(), "Found route for service call");
+              if let Some((file, _file_descriptor, method_descriptor, messages)) = routes.get(request_path) {
+                // Routes are only created with at least one interaction. The interaction to respond
+                // with is selected by the mock service once the request has been received.
+                let message = &messages[0];
+                trace!(interactions = messages.len(), "Found route for service call");
                 
                 let service_and_method = format!("{service_full_name}/{method}");  // just for logging
                 let input_name = method_descriptor.input_type.as_ref().expect(format!(
@@ -254,11 +272,18 @@ impl Service<Request<Incoming>> for GrpcMockServer  {
                   if let Ok((output_message, _)) = find_message_descriptor_for_type(output_name, &file) {
                     let codec = PactCodec::new(file, &input_message, &output_message, message);
                     let mock_service = MockService::new(file, service_full_name.as_str(),
-                      method_descriptor, &input_message, &output_message, message, server_key.as_str(),
+                      method_descriptor, &input_message, &output_message, messages, server_key.as_str(),
                       pact
                     );
-                    let mut grpc = tonic::server::Grpc::new(codec);
-                    let response = grpc.unary(mock_service, req).await;
+                    let mut grpc = tonic::server::Grpc::new(codec)
+                      .accept_compressed(CompressionEncoding::Gzip);
+                    if messages.iter().any(compress_responses) {
+                      // Responses will only be compressed if the client has indicated that it accepts
+                      // gzip with the grpc-accept-encoding header, and the mock service will disable
+                      // compression for the responses of any interactions without the option set
+                      grpc = grpc.send_compressed(CompressionEncoding::Gzip);
+                    }
+                    let response = with_response_trailers(grpc.unary(mock_service, req).await);
                     trace!(?response, ">> sending response");
                     Ok(response)
                   } else {
@@ -290,6 +315,72 @@ impl Service<Request<Incoming>> for GrpcMockServer  {
   }
 }
 
+/// If the `compressResponses` option was set for the interaction, response messages should be gzip
+/// compressed for clients that accept it.
+pub(crate) fn compress_responses(message: &SynchronousMessage) -> bool {
+  message.plugin_config.get("protobuf")
+    .and_then(|config| config.get("compressResponses"))
+    .and_then(|value| value.as_bool())
+    .unwrap_or_default()
+}
+
+/// Returns the key used to store the results for an interaction in the mock server state. This is
+/// the route, unless there is more than one interaction for the route, in which case the description
+/// of the interaction is added so that each interaction is reported separately.
+pub(crate) fn result_key(route: &str, interaction: &SynchronousMessage, interactions: usize) -> String {
+  if interactions > 1 {
+    format!("{} ({})", route, interaction.description)
+  } else {
+    route.to_string()
+  }
+}
+
+/// Returns the address to bind the mock server to. The host interface must be an IP address (IPv6
+/// addresses may be enclosed in square brackets, e.g. `[::]`), and defaults to the IPv6 loopback
+/// interface if not provided. Use `0.0.0.0` or `[::]` to make the mock server reachable from other
+/// hosts or containers.
+fn mock_server_address(host_interface: &str, port: u32) -> anyhow::Result<SocketAddr> {
+  let host = host_interface.trim();
+  let ip = if host.is_empty() {
+    IpAddr::V6(Ipv6Addr::LOCALHOST)
+  } else {
+    let address = host.strip_prefix('[')
+      .and_then(|h| h.strip_suffix(']'))
+      .unwrap_or(host);
+    address.parse::<IpAddr>()
+      .map_err(|err| anyhow!("Host interface '{}' for the mock server is not a valid IP address - {}", host_interface, err))?
+  };
+  let port = u16::try_from(port)
+    .map_err(|_| anyhow!("Port {} for the mock server is not a valid port number", port))?;
+  Ok(SocketAddr::new(ip, port))
+}
+
+/// Binds the listener for the mock server, retrying with a backoff if the address is in use. Under
+/// heavy parallel test runs, binding the port can transiently fail with `EADDRINUSE`.
+async fn bind_with_retry<T, F, Fut>(
+  addr: SocketAddr,
+  attempts: u32,
+  retry_delay: Duration,
+  bind: F
+) -> io::Result<T>
+  where F: Fn(SocketAddr) -> Fut,
+        Fut: Future<Output = io::Result<T>> {
+  let mut delay = retry_delay;
+  let mut attempt = 1;
+  loop {
+    match bind(addr).await {
+      Err(err) if err.kind() == io::ErrorKind::AddrInUse && attempt < attempts => {
+        debug!("Failed to bind mock server to {} (attempt {} of {}), will retry in {:?} - {}",
+          addr, attempt, attempts, delay, err);
+        tokio::time::sleep(delay).await;
+        delay *= 2;
+        attempt += 1;
+      }
+      result => return result
+    }
+  }
+}
+
 fn invalid_media() -> Response<BoxBody> {
   http::Response::builder()
     .status(415)
@@ -313,6 +404,26 @@ fn invalid_path() -> Response<BoxBody> {
     .unwrap()
 }
 
+/// Adds any trailing metadata set by the mock service to the trailers sent after the response
+/// message, along with the gRPC status
+fn with_response_trailers(mut response: Response<BoxBody>) -> Response<BoxBody> {
+  match response.extensions_mut().remove::<ResponseTrailers>() {
+    Some(ResponseTrailers(trailers)) => {
+      let trailers = trailers.into_headers();
+      response.map(|body| body
+        .map_frame(move |frame| match frame.into_trailers() {
+          Ok(mut headers) => {
+            headers.extend(trailers.clone());
+            Frame::trailers(headers)
+          }
+          Err(frame) => frame
+        })
+        .boxed_unsync())
+    }
+    None => response
+  }
+}
+
 fn failed_precondition() -> Response<BoxBody> {
   http::Response::builder()
     .status(200)
@@ -321,3 +432,96 @@ fn failed_precondition() -> Response<BoxBody> {
     .body(empty_body())
     .unwrap()
 }
+
+#[cfg(test)]
+mod tests {
+  use std::io;
+  use std::net::SocketAddr;
+  use std::sync::atomic::{AtomicU32, Ordering};
+  use std::time::Duration;
+
+  use expectest::prelude::*;
+  use maplit::hashmap;
+  use pact_models::plugins::PluginData;
+  use pact_models::prelude::v4::V4Pact;
+  use serde_json::json;
+  use tokio::net::{TcpListener, TcpStream};
+
+  use super::{bind_with_retry, GrpcMockServer, mock_server_address, MOCK_SERVER_STATE};
+
+  #[test]
+  fn mock_server_address_test() {
+    expect!(mock_server_address("", 0).unwrap()).to(be_equal_to("[::1]:0".parse::<SocketAddr>().unwrap()));
+    expect!(mock_server_address("0.0.0.0", 1234).unwrap()).to(be_equal_to("0.0.0.0:1234".parse::<SocketAddr>().unwrap()));
+    expect!(mock_server_address("127.0.0.1", 0).unwrap()).to(be_equal_to("127.0.0.1:0".parse::<SocketAddr>().unwrap()));
+    expect!(mock_server_address("::", 0).unwrap()).to(be_equal_to("[::]:0".parse::<SocketAddr>().unwrap()));
+    expect!(mock_server_address("[::]", 0).unwrap()).to(be_equal_to("[::]:0".parse::<SocketAddr>().unwrap()));
+
+    expect!(mock_server_address("not-an-address", 0).unwrap_err().to_string()).to(
+      be_equal_to("Host interface 'not-an-address' for the mock server is not a valid IP address - invalid IP address syntax"));
+    expect!(mock_server_address("127.0.0.1", 100000).unwrap_err().to_string()).to(
+      be_equal_to("Port 100000 for the mock server is not a valid port number"));
+  }
+
+  #[test_log::test(tokio::test)]
+  async fn start_server_binds_to_the_provided_host_interface() {
+    let plugin_config = PluginData {
+      name: "protobuf".to_string(),
+      version: "0.0.0".to_string(),
+      configuration: hashmap!{
+        "key".to_string() => json!({ "protoDescriptors": crate::protobuf::tests::DESCRIPTOR_BYTES })
+      }
+    };
+    let server = GrpcMockServer::new(V4Pact::default(), &plugin_config, hashmap!{});
+    let server_key = server.server_key.clone();
+
+    let address = server.start_server("127.0.0.1", 0, false).await.unwrap();
+    expect!(address.ip().to_string()).to(be_equal_to("127.0.0.1"));
+    expect!(TcpStream::connect(address).await).to(be_ok());
+
+    let (shutdown, _) = MOCK_SERVER_STATE.lock().unwrap().remove(&server_key).unwrap();
+    let _ = shutdown.send(());
+  }
+
+  #[test_log::test(tokio::test)]
+  async fn bind_with_retry_retries_if_the_address_is_in_use() {
+    let addr: SocketAddr = "127.0.0.1:0".parse().unwrap();
+    let calls = AtomicU32::new(0);
+    let result = bind_with_retry(addr, 3, Duration::from_millis(1), |addr| {
+      let call = calls.fetch_add(1, Ordering::SeqCst);
+      async move {
+        if call == 0 {
+          Err(io::Error::from(io::ErrorKind::AddrInUse))
+        } else {
+          TcpListener::bind(addr).await
+        }
+      }
+    }).await;
+    expect!(result).to(be_ok());
+    expect!(calls.load(Ordering::SeqCst)).to(be_equal_to(2));
+  }
+
+  #[test_log::test(tokio::test)]
+  async fn bind_with_retry_gives_up_after_the_number_of_attempts() {
+    let addr: SocketAddr = "127.0.0.1:0".parse().unwrap();
+    let calls = AtomicU32::new(0);
+    let result = bind_with_retry(addr, 3, Duration::from_millis(1), |_| {
+      calls.fetch_add(1, Ordering::SeqCst);
+      async { Err::<TcpListener, _>(io::Error::from(io::ErrorKind::AddrInUse)) }
+    }).await;
+    expect!(result.unwrap_err().kind()).to(be_equal_to(io::ErrorKind::AddrInUse));
+    expect!(calls.load(Ordering::SeqCst)).to(be_equal_to(3));
+  }
+
+  #[test_log::test(tokio::test)]
+  async fn bind_with_retry_does_not_retry_other_errors() {
+    let addr: SocketAddr = "127.0.0.1:0".parse().unwrap();
+    let calls = AtomicU32::new(0);
+    let result = bind_with_retry(addr, 3, Duration::from_millis(1), |_| {
+      calls.fetch_add(1, Ordering::SeqCst);
+      async { Err::<TcpListener, _>(io::Error::from(io::ErrorKind::PermissionDenied)) }
+    }).await;
+    expect!(result.unwrap_err().kind()).to(be_equal_to(io::ErrorKind::PermissionDenied));
+    expect!(calls.load(Ordering::SeqCst)).to(be_equal_to(1));
+  }
+}
@@ -3,9 +3,11 @@
 use std::future::Future;
 use std::pin::Pin;
 use std::task::{Context, Poll};
+use std::time::Duration;
 
+use anyhow::anyhow;
 use maplit::hashmap;
-use pact_matching::{CoreMatchingContext, DiffConfig};
+use pact_matching::{BodyMatchResult, CoreMatchingContext, DiffConfig};
 use pact_models::generators::{GeneratorCategory, GeneratorTestMode};
 use pact_models::json_utils::json_to_string;
 use pact_models::pact::Pact;
@@ -14,23 +16,33 @@ use pact_models::v4::message_parts::MessageContents;
 use pact_models::v4::sync_message::SynchronousMessage;
 use pact_plugin_driver::plugin_models::PluginInteractionConfig;
 use prost_types::{DescriptorProto, FileDescriptorSet, MethodDescriptorProto};
+use serde_json::Value;
 use tonic::{Request, Response, Status};
 use tonic::metadata::{Entry, MetadataMap};
 use tower_service::Service;
 use tracing::{debug, error, info, trace, warn};
 
 use crate::dynamic_message::DynamicMessage;
-use crate::matching::compare;
+use crate::matching::{compare, interaction_config_for_part};
 use crate::message_decoder::decode_message;
-use crate::metadata::{compare_metadata, grpc_status};
-use crate::mock_server::MOCK_SERVER_STATE;
-use crate::utils::build_grpc_route;
+use crate::metadata::{compare_metadata, expected_trailers, grpc_status, MetadataMatchResult, TRAILERS_KEY};
+use crate::mock_server::{compress_responses, MOCK_SERVER_STATE, result_key};
+use crate::utils::{build_grpc_route, find_message_descriptor_for_type, response_message_type};
+
+/// Result of comparing the incoming request to the request of an interaction
+type RequestComparison = (anyhow::Result<BodyMatchResult>, anyhow::Result<(MetadataMatchResult, Vec<String>)>);
+
+/// Trailing metadata to send with the response. This is stored in the extensions of the response,
+/// and the mock server adds it to the trailers once the response message has been sent.
+#[derive(Debug, Clone)]
+pub(crate) struct ResponseTrailers(pub(crate) MetadataMap);
 
 #[derive(Debug, Clone)]
 pub(crate) struct MockService {
   file_descriptor_set: FileDescriptorSet,
   service_name: String,
   message: SynchronousMessage,
+  interactions: Vec<SynchronousMessage>,
   method_descriptor: MethodDescriptorProto,
   input_message: DescriptorProto,
   output_message: DescriptorProto,
@@ -62,37 +74,9 @@ impl MockService {
     request_metadata: MetadataMap
   ) -> Result<Response<DynamicMessage>, Status> {
     trace!(?request, "Handling request message");
-    // 1. Compare the incoming message to the request message from the interaction
-    let mut expected_message_bytes = self.message.request.contents.value().unwrap_or_default();
-    let expected_message = decode_message(&mut expected_message_bytes, &message_descriptor, &self.file_descriptor_set)
+    let (mismatches, md_mismatches) = self.compare_request(&self.message, &request,
+      &message_descriptor, &request_metadata)
       .map_err(|err| Status::invalid_argument(err.to_string()))?;
-    trace!("Expected message has {} fields", expected_message.len());
-    let plugin_config = self.pact.plugin_data().iter()
-      .map(|pd| {
-        (pd.name.clone(), PluginInteractionConfig {
-          pact_configuration: pd.configuration.clone(),
-          interaction_configuration: self.message.plugin_config.get(pd.name.as_str()).cloned().unwrap_or_default()
-        })
-      }).collect();
-
-    let context = CoreMatchingContext::new(DiffConfig::NoUnexpectedKeys,
-      &self.message.request.matching_rules.rules_for_category("body").unwrap_or_default(),
-      &plugin_config);
-    let mismatches = compare(
-      &message_descriptor,
-      &expected_message,
-      request.flatten_fields().as_slice(),
-      &context,
-      &expected_message_bytes,
-      &self.file_descriptor_set
-    );
-
-    // 2. Compare any metadata from the incoming message
-    let md_context = CoreMatchingContext::new(DiffConfig::NoUnexpectedKeys,
-      &self.message.request.matching_rules.rules_for_category("metadata").unwrap_or_default(),
-      &plugin_config);
-    let md_mismatches = compare_metadata(&self.message.request.metadata, &request_metadata,
-      &md_context);
 
     trace!("Comparison result = {:?}", mismatches);
     match (mismatches, md_mismatches) {
@@ -102,7 +86,7 @@ impl MockService {
           let mut guard = MOCK_SERVER_STATE.lock().unwrap();
           let method_name = self.method_descriptor.name.clone().unwrap_or_else(|| "unknown method".into());
           let key = match build_grpc_route(self.service_name.as_str(), method_name.as_str()) {
-            Ok(k) => k,
+            Ok(route) => result_key(route.as_str(), &self.message, self.interactions.len()),
             Err(err) => Err(Status::internal(err.to_string()))?
           };
           if let Some((_, results)) = guard.get_mut(self.server_key.as_str()) {
@@ -117,11 +101,42 @@ impl MockService {
 
         if result.all_matched() && md_result.all_matched() {
           debug!("Request matched OK");
+          if let Some(delay) = self.response_delay() {
+            match grpc_timeout(&request_metadata) {
+              Some(timeout) if timeout < delay => {
+                info!("Response delay of {:?} exceeds the request deadline of {:?}", delay, timeout);
+                return Err(Status::deadline_exceeded(format!("Deadline of {:?} exceeded", timeout)));
+              }
+              _ => {
+                debug!("Delaying the response by {:?}", delay);
+                tokio::time::sleep(delay).await;
+              }
+            }
+          }
           let response_contents = self.message.response.first().cloned().unwrap_or_default();
           // check for a gRPC status on the response metadata
           if let Some(status) = grpc_status(&response_contents) {
             info!("a gRPC status {} is set for the response, returning that", status);
+            // tonic sends the metadata of an error status as the trailers
+            let mut status = status;
+            Self::insert_metadata(expected_trailers(&response_contents.metadata).iter(), status.metadata_mut());
             Err(status)
+          } else if self.echo_request() {
+            if message_descriptor != response_descriptor {
+              error!("Can not echo the request, as the request and response message types are different");
+              return Err(Status::failed_precondition(format!(
+                "Can not echo the request, as the request message type '{}' is not the same as the response message type '{}'",
+                message_descriptor.name(), response_descriptor.name())));
+            }
+            debug!("Returning the request message as the response");
+            let mut response = Response::new(request);
+            if !response_contents.metadata.is_empty() {
+              Self::set_response_metadata(&response_contents, &mut response);
+            }
+            if !compress_responses(&self.message) {
+              response.disable_compression();
+            }
+            Ok(response)
           } else {
             debug!("Returning response");
             let mut response_bytes = response_contents.contents.value()
@@ -140,13 +155,22 @@ impl MockService {
             trace!("Sending message {message:?}");
             let mut response = Response::new(message);
             if !response_contents.metadata.is_empty() {
-              Self::set_response_metadata(response_contents, &mut response);
+              Self::set_response_metadata(&response_contents, &mut response);
+            }
+            if !compress_responses(&self.message) {
+              response.disable_compression();
             }
             Ok(response)
           }
-        } else {
+        } else if !result.all_matched() {
           error!("Failed to match the request message - {result:?}");
           Err(Status::failed_precondition(format!("Failed to match the request message - {result:?}")))
+        } else {
+          error!("Failed to match the request metadata - {md_result:?}");
+          let mismatches = md_result.mismatches.iter()
+            .map(|mismatch| mismatch.description())
+            .collect::<Vec<_>>();
+          Err(Status::invalid_argument(format!("Failed to match the request metadata - {}", mismatches.join(", "))))
         }
       }
       (Err(err), _) => {
@@ -160,33 +184,118 @@ impl MockService {
     }
   }
 
-  fn set_response_metadata(response_contents: MessageContents, response: &mut Response<DynamicMessage>) {
-    let md = response.metadata_mut();
-    for (key, value) in &response_contents.metadata {
+  /// Compares the incoming message and metadata to the request from the given interaction.
+  fn compare_request(
+    &self,
+    message: &SynchronousMessage,
+    request: &DynamicMessage,
+    message_descriptor: &DescriptorProto,
+    request_metadata: &MetadataMap
+  ) -> anyhow::Result<RequestComparison> {
+    // 1. Compare the incoming message to the request message from the interaction
+    let mut expected_message_bytes = message.request.contents.value().unwrap_or_default();
+    let expected_message = decode_message(&mut expected_message_bytes, message_descriptor, &self.file_descriptor_set)?;
+    trace!("Expected message has {} fields", expected_message.len());
+    let plugin_config = self.pact.plugin_data().iter()
+      .map(|pd| {
+        (pd.name.clone(), PluginInteractionConfig {
+          pact_configuration: pd.configuration.clone(),
+          interaction_configuration: message.plugin_config.get(pd.name.as_str())
+            .map(|config| interaction_config_for_part(config, "request"))
+            .unwrap_or_default()
+        })
+      }).collect();
+
+    let context = CoreMatchingContext::new(DiffConfig::NoUnexpectedKeys,
+      &message.request.matching_rules.rules_for_category("body").unwrap_or_default(),
+      &plugin_config);
+    let mismatches = compare(
+      message_descriptor,
+      &expected_message,
+      request.flatten_fields().as_slice(),
+      &context,
+      &expected_message_bytes,
+      &self.file_descriptor_set,
+      false
+    );
+
+    // 2. Compare any metadata from the incoming message
+    let md_context = CoreMatchingContext::new(DiffConfig::NoUnexpectedKeys,
+      &message.request.matching_rules.rules_for_category("metadata").unwrap_or_default(),
+      &plugin_config);
+    let md_mismatches = compare_metadata(&message.request.metadata, request_metadata,
+      &md_context);
+
+    Ok((mismatches, md_mismatches))
+  }
+
+  /// Selects the interaction to respond with when there is more than one interaction for the
+  /// service method. The request of each interaction is compared to the incoming message, and the
+  /// interaction with the least mismatches is used. If more than one interaction matches equally
+  /// well, the first one defined is used.
+  fn select_interaction(
+    &self,
+    request: &DynamicMessage,
+    message_descriptor: &DescriptorProto,
+    request_metadata: &MetadataMap
+  ) -> SynchronousMessage {
+    if self.interactions.len() < 2 {
+      return self.message.clone();
+    }
+
+    self.interactions.iter()
+      .min_by_key(|interaction| {
+        let mismatches = match self.compare_request(interaction, request, message_descriptor, request_metadata) {
+          Ok((Ok(result), Ok((md_result, _)))) => result.mismatches().len() + md_result.mismatches.len(),
+          _ => usize::MAX
+        };
+        trace!(interaction = interaction.description.as_str(), mismatches, "Compared request to interaction");
+        mismatches
+      })
+      .cloned()
+      .unwrap_or_else(|| self.message.clone())
+  }
+
+  fn set_response_metadata(response_contents: &MessageContents, response: &mut Response<DynamicMessage>) {
+    let values = response_contents.metadata.iter()
+      .filter(|(key, _)| {
+        let key = key.to_lowercase();
+        // exclude the content type, because that is a special value added by the Pact framework
+        // also exclude the gRPC status, because that is handled separately, and the expected trailers
+        key != "content-type" && key != "contenttype" && key != "grpc-status" && key != TRAILERS_KEY
+      });
+    Self::insert_metadata(values, response.metadata_mut());
+
+    let trailers = expected_trailers(&response_contents.metadata);
+    if !trailers.is_empty() {
+      let mut trailer_metadata = MetadataMap::new();
+      Self::insert_metadata(trailers.iter(), &mut trailer_metadata);
+      response.extensions_mut().insert(ResponseTrailers(trailer_metadata));
+    }
+  }
+
+  fn insert_metadata<'a>(values: impl Iterator<Item = (&'a String, &'a Value)>, md: &mut MetadataMap) {
+    for (key, value) in values {
       let key = key.to_lowercase();
-      // exclude the content type, because that is a special value added by the Pact framework
-      // also exclude the gRPC status, because that is handled separately
-      if key != "content-type" && key != "contenttype" && key != "grpc-status" {
-        match json_to_string(value).parse() {
-          Ok(parsed_val) => {
-            match md.entry(key.as_str()) {
-              Ok(entry) => match entry {
-                Entry::Occupied(mut o) => {
-                  warn!("Replacing existing gRPC metadata key '{}'", key);
-                  o.insert(parsed_val);
-                },
-                Entry::Vacant(v) => {
-                  v.insert(parsed_val);
-                }
-              }
-              Err(err) => {
-                error!("'{}' is not a valid gRPC metadata key, ignoring it - {}", key, err);
+      match json_to_string(value).parse() {
+        Ok(parsed_val) => {
+          match md.entry(key.as_str()) {
+            Ok(entry) => match entry {
+              Entry::Occupied(mut o) => {
+                warn!("Replacing existing gRPC metadata key '{}'", key);
+                o.insert(parsed_val);
+              },
+              Entry::Vacant(v) => {
+                v.insert(parsed_val);
               }
             }
+            Err(err) => {
+              error!("'{}' is not a valid gRPC metadata key, ignoring it - {}", key, err);
+            }
           }
-          Err(err) => {
-            error!("'{}' is not a valid gRPC metadata value, ignoring it - {}", value, err);
-          }
+        }
+        Err(err) => {
+          error!("'{}' is not a valid gRPC metadata value, ignoring it - {}", value, err);
         }
       }
     }
@@ -201,7 +310,7 @@ impl MockService {
     method_descriptor: &MethodDescriptorProto,
     input_message: &DescriptorProto,
     output_message: &DescriptorProto,
-    message: &SynchronousMessage,
+    interactions: &[SynchronousMessage],
     server_key: &str,
     pact: V4Pact
   ) -> Self {
@@ -211,12 +320,48 @@ impl MockService {
       method_descriptor: method_descriptor.clone(),
       input_message: input_message.clone(),
       output_message: output_message.clone(),
-      message: message.clone(),
+      message: interactions.first().cloned().unwrap_or_default(),
+      interactions: interactions.to_vec(),
       server_key: server_key.to_string(),
       pact
     }
   }
 
+  /// If the `responseDelay` option was set for the interaction, the mock service will wait for
+  /// the given number of milliseconds before responding.
+  fn response_delay(&self) -> Option<Duration> {
+    self.message.plugin_config.get("protobuf")
+      .and_then(|config| config.get("responseDelay"))
+      // the plugin configuration is passed around as a Protobuf Struct, so numbers may be floats
+      .and_then(|value| value.as_u64().or_else(|| value.as_f64()
+        .filter(|delay| *delay >= 0.0)
+        .map(|delay| delay as u64)))
+      .map(Duration::from_millis)
+  }
+
+  /// Returns the descriptor for the response message of the selected interaction. This is the output
+  /// type of the method, unless the interaction overrides it with the `responseMessageType` option.
+  fn response_descriptor(&self) -> anyhow::Result<DescriptorProto> {
+    let interaction_config = self.message.plugin_config.get("protobuf").cloned().unwrap_or_default();
+    let output_name = response_message_type(&self.method_descriptor, &interaction_config);
+    if output_name == self.method_descriptor.output_type() {
+      Ok(self.output_message.clone())
+    } else {
+      find_message_descriptor_for_type(output_name, &self.file_descriptor_set)
+        .map(|(descriptor, _)| descriptor)
+        .map_err(|err| anyhow!(err))
+    }
+  }
+
+  /// If the `echoRequest` option was set for the interaction, the mock service will return the
+  /// request message as the response.
+  fn echo_request(&self) -> bool {
+    self.message.plugin_config.get("protobuf")
+      .and_then(|config| config.get("echoRequest"))
+      .and_then(|value| value.as_bool())
+      .unwrap_or_default()
+  }
+
   fn apply_generators(&self, message: &mut DynamicMessage, contents: &MessageContents) -> anyhow::Result<()> {
     let context = hashmap!{}; // TODO: This needs to be passed in via the start mock server call
 
@@ -228,6 +373,30 @@ impl MockService {
   }
 }
 
+/// Parses the deadline sent by the client in the `grpc-timeout` header. The value is a positive
+/// integer followed by a unit: `H` (hours), `M` (minutes), `S` (seconds), `m` (milliseconds),
+/// `u` (microseconds) or `n` (nanoseconds).
+fn grpc_timeout(metadata: &MetadataMap) -> Option<Duration> {
+  let value = metadata.get("grpc-timeout")?.to_str().ok()?;
+  if value.is_empty() {
+    return None;
+  }
+  let (amount, unit) = value.split_at(value.len() - 1);
+  let amount: u64 = amount.parse().ok()?;
+  match unit {
+    "H" => Some(Duration::from_secs(amount.saturating_mul(60 * 60))),
+    "M" => Some(Duration::from_secs(amount.saturating_mul(60))),
+    "S" => Some(Duration::from_secs(amount)),
+    "m" => Some(Duration::from_millis(amount)),
+    "u" => Some(Duration::from_micros(amount)),
+    "n" => Some(Duration::from_nanos(amount)),
+    _ => {
+      warn!("Ignoring invalid grpc-timeout value '{}'", value);
+      None
+    }
+  }
+}
+
 impl Service<Request<DynamicMessage>> for MockService {
   type Response = Response<DynamicMessage>;
   type Error = Status;
@@ -241,9 +410,13 @@ impl Service<Request<DynamicMessage>> for MockService {
     let (request_metadata, _, request) = req.into_parts();
     trace!(?request, "Incoming message received");
     let message_descriptor = self.input_message.clone();
-    let response_descriptor = self.output_message.clone();
-    let service = self.clone();
+    let mut service = self.clone();
     Box::pin(async move {
+      service.message = service.select_interaction(&request, &message_descriptor, &request_metadata);
+      let response_descriptor = service.response_descriptor().map_err(|err| {
+        error!("Did not find the descriptor for the response message - {}", err);
+        Status::failed_precondition(err.to_string())
+      })?;
       service.handle_message(request, message_descriptor, response_descriptor, request_metadata).await
     })
   }
@@ -257,14 +430,66 @@ mod tests {
   use expectest::prelude::*;
   use pact_models::v4::pact::V4Pact;
   use prost::Message;
-  use prost_types::FileDescriptorSet;
+  use prost_types::{DescriptorProto, FileDescriptorProto, FileDescriptorSet, MethodDescriptorProto};
   use serde_json::json;
+  use std::time::Duration;
+  use tonic::Code;
   use tonic::metadata::{MetadataMap, MetadataKey, MetadataValue};
 
   use crate::dynamic_message::DynamicMessage;
   use crate::message_decoder::decode_message;
-  use crate::mock_service::MockService;
+  use crate::mock_server::{MOCK_SERVER_STATE, result_key};
+  use crate::mock_service::{grpc_timeout, MockService};
   use crate::protobuf::tests::DESCRIPTOR_BYTES;
+  use crate::utils::last_name;
+
+  // taken from https://github.com/pact-foundation/pact-plugins/tree/main/examples/gRPC/area_calculator
+  const AREA_CALCULATOR_DESCRIPTOR: &str = "CsoHChVhcmVhX2NhbGN1bGF0b3IucHJvdG8SD2FyZWFfY2FsY3VsYXRvciK6Ago\
+    MU2hhcGVNZXNzYWdlEjEKBnNxdWFyZRgBIAEoCzIXLmFyZWFfY2FsY3VsYXRvci5TcXVhcmVIAFIGc3F1YXJlEjoKC\
+    XJlY3RhbmdsZRgCIAEoCzIaLmFyZWFfY2FsY3VsYXRvci5SZWN0YW5nbGVIAFIJcmVjdGFuZ2xlEjEKBmNpcmNsZRg\
+    DIAEoCzIXLmFyZWFfY2FsY3VsYXRvci5DaXJjbGVIAFIGY2lyY2xlEjcKCHRyaWFuZ2xlGAQgASgLMhkuYXJlYV9jY\
+    WxjdWxhdG9yLlRyaWFuZ2xlSABSCHRyaWFuZ2xlEkYKDXBhcmFsbGVsb2dyYW0YBSABKAsyHi5hcmVhX2NhbGN1bGF\
+    0b3IuUGFyYWxsZWxvZ3JhbUgAUg1wYXJhbGxlbG9ncmFtQgcKBXNoYXBlIikKBlNxdWFyZRIfCgtlZGdlX2xlbmd0a\
+    BgBIAEoAlIKZWRnZUxlbmd0aCI5CglSZWN0YW5nbGUSFgoGbGVuZ3RoGAEgASgCUgZsZW5ndGgSFAoFd2lkdGgYAiA\
+    BKAJSBXdpZHRoIiAKBkNpcmNsZRIWCgZyYWRpdXMYASABKAJSBnJhZGl1cyJPCghUcmlhbmdsZRIVCgZlZGdlX2EYAS\
+    ABKAJSBWVkZ2VBEhUKBmVkZ2VfYhgCIAEoAlIFZWRnZUISFQoGZWRnZV9jGAMgASgCUgVlZGdlQyJICg1QYXJhbGxlbG\
+    9ncmFtEh8KC2Jhc2VfbGVuZ3RoGAEgASgCUgpiYXNlTGVuZ3RoEhYKBmhlaWdodBgCIAEoAlIGaGVpZ2h0IkQKC0FyZW\
+    FSZXF1ZXN0EjUKBnNoYXBlcxgBIAMoCzIdLmFyZWFfY2FsY3VsYXRvci5TaGFwZU1lc3NhZ2VSBnNoYXBlcyIkCgxBcm\
+    VhUmVzcG9uc2USFAoFdmFsdWUYASADKAJSBXZhbHVlMq0BCgpDYWxjdWxhdG9yEk4KDGNhbGN1bGF0ZU9uZRIdLmFyZW\
+    FfY2FsY3VsYXRvci5TaGFwZU1lc3NhZ2UaHS5hcmVhX2NhbGN1bGF0b3IuQXJlYVJlc3BvbnNlIgASTwoOY2FsY3VsY\
+    XRlTXVsdGkSHC5hcmVhX2NhbGN1bGF0b3IuQXJlYVJlcXVlc3QaHS5hcmVhX2NhbGN1bGF0b3IuQXJlYVJlc3BvbnNl\
+    IgBCHFoXaW8ucGFjdC9hcmVhX2NhbGN1bGF0b3LQAgFiBnByb3RvMw==";
+
+  /// Decodes the area calculator descriptors, returning the file descriptor set and the descriptor
+  /// for area_calculator.proto
+  fn area_calculator_descriptors() -> (FileDescriptorSet, FileDescriptorProto) {
+    let bytes = BASE64.decode(AREA_CALCULATOR_DESCRIPTOR).unwrap();
+    let file_descriptor_set = FileDescriptorSet::decode(bytes.as_slice()).unwrap();
+    let ac_desc = file_descriptor_set.file.iter()
+      .find(|ds| ds.name.clone().unwrap_or_default() == "area_calculator.proto")
+      .cloned()
+      .unwrap();
+    (file_descriptor_set, ac_desc)
+  }
+
+  /// Looks up the method of the Calculator service, along with its input and output messages
+  fn calculator_method(
+    ac_desc: &FileDescriptorProto,
+    method_name: &str
+  ) -> (MethodDescriptorProto, DescriptorProto, DescriptorProto) {
+    let method = ac_desc.service.iter()
+      .find(|sd| sd.name.clone().unwrap_or_default() == "Calculator")
+      .and_then(|sd| sd.method.iter().find(|md| md.name.clone().unwrap_or_default() == method_name))
+      .cloned()
+      .unwrap();
+    let message = |type_name: &str| ac_desc.message_type.iter()
+      .find(|md| md.name.clone().unwrap_or_default() == last_name(type_name))
+      .cloned()
+      .unwrap();
+    let input_message = message(method.input_type());
+    let output_message = message(method.output_type());
+    (method, input_message, output_message)
+  }
 
   #[test_log::test(tokio::test)]
   async fn handle_message_applies_any_generators() {
@@ -379,6 +604,7 @@ mod tests {
       file_descriptor_set: file_descriptor_set.clone(),
       service_name: "Calculator".to_string(),
       message: message.as_v4_sync_message().unwrap(),
+      interactions: vec![],
       method_descriptor: method.clone(),
       input_message: input_message.clone(),
       output_message: output_message.clone(),
@@ -397,42 +623,8 @@ mod tests {
 
   #[test_log::test(tokio::test)]
   async fn handle_message_handles_multiple_field_values() {
-    // taken from https://github.com/pact-foundation/pact-plugins/tree/main/examples/gRPC/area_calculator
-    let descriptor_encoded = "CsoHChVhcmVhX2NhbGN1bGF0b3IucHJvdG8SD2FyZWFfY2FsY3VsYXRvciK6Ago\
-    MU2hhcGVNZXNzYWdlEjEKBnNxdWFyZRgBIAEoCzIXLmFyZWFfY2FsY3VsYXRvci5TcXVhcmVIAFIGc3F1YXJlEjoKC\
-    XJlY3RhbmdsZRgCIAEoCzIaLmFyZWFfY2FsY3VsYXRvci5SZWN0YW5nbGVIAFIJcmVjdGFuZ2xlEjEKBmNpcmNsZRg\
-    DIAEoCzIXLmFyZWFfY2FsY3VsYXRvci5DaXJjbGVIAFIGY2lyY2xlEjcKCHRyaWFuZ2xlGAQgASgLMhkuYXJlYV9jY\
-    WxjdWxhdG9yLlRyaWFuZ2xlSABSCHRyaWFuZ2xlEkYKDXBhcmFsbGVsb2dyYW0YBSABKAsyHi5hcmVhX2NhbGN1bGF\
-    0b3IuUGFyYWxsZWxvZ3JhbUgAUg1wYXJhbGxlbG9ncmFtQgcKBXNoYXBlIikKBlNxdWFyZRIfCgtlZGdlX2xlbmd0a\
-    BgBIAEoAlIKZWRnZUxlbmd0aCI5CglSZWN0YW5nbGUSFgoGbGVuZ3RoGAEgASgCUgZsZW5ndGgSFAoFd2lkdGgYAiA\
-    BKAJSBXdpZHRoIiAKBkNpcmNsZRIWCgZyYWRpdXMYASABKAJSBnJhZGl1cyJPCghUcmlhbmdsZRIVCgZlZGdlX2EYAS\
-    ABKAJSBWVkZ2VBEhUKBmVkZ2VfYhgCIAEoAlIFZWRnZUISFQoGZWRnZV9jGAMgASgCUgVlZGdlQyJICg1QYXJhbGxlbG\
-    9ncmFtEh8KC2Jhc2VfbGVuZ3RoGAEgASgCUgpiYXNlTGVuZ3RoEhYKBmhlaWdodBgCIAEoAlIGaGVpZ2h0IkQKC0FyZW\
-    FSZXF1ZXN0EjUKBnNoYXBlcxgBIAMoCzIdLmFyZWFfY2FsY3VsYXRvci5TaGFwZU1lc3NhZ2VSBnNoYXBlcyIkCgxBcm\
-    VhUmVzcG9uc2USFAoFdmFsdWUYASADKAJSBXZhbHVlMq0BCgpDYWxjdWxhdG9yEk4KDGNhbGN1bGF0ZU9uZRIdLmFyZW\
-    FfY2FsY3VsYXRvci5TaGFwZU1lc3NhZ2UaHS5hcmVhX2NhbGN1bGF0b3IuQXJlYVJlc3BvbnNlIgASTwoOY2FsY3VsY\
-    XRlTXVsdGkSHC5hcmVhX2NhbGN1bGF0b3IuQXJlYVJlcXVlc3QaHS5hcmVhX2NhbGN1bGF0b3IuQXJlYVJlc3BvbnNl\
-    IgBCHFoXaW8ucGFjdC9hcmVhX2NhbGN1bGF0b3LQAgFiBnByb3RvMw==";
-    let bytes = BASE64.decode(descriptor_encoded).unwrap();
-    let bytes1 = Bytes::copy_from_slice(bytes.as_slice());
-    let file_descriptor_set = FileDescriptorSet::decode(bytes1).unwrap();
-
-    let ac_desc = file_descriptor_set.file.iter()
-      .find(|ds| ds.name.clone().unwrap_or_default() == "area_calculator.proto")
-      .cloned()
-      .unwrap();
-    let service_desc = ac_desc.service.iter()
-      .find(|sd| sd.name.clone().unwrap_or_default() == "Calculator")
-      .unwrap();
-    let method = service_desc.method.iter()
-      .find(|md| md.name.clone().unwrap_or_default() == "calculateMulti")
-      .unwrap();
-    let input_message = ac_desc.message_type.iter()
-      .find(|md| md.name.clone().unwrap_or_default() == "AreaRequest")
-      .unwrap();
-    let output_message = ac_desc.message_type.iter()
-      .find(|md| md.name.clone().unwrap_or_default() == "AreaResponse")
-      .unwrap();
+    let (file_descriptor_set, ac_desc) = area_calculator_descriptors();
+    let (method, input_message, output_message) = calculator_method(&ac_desc, "calculateMulti");
 
     let request_bytes: &[u8] = [10, 12, 18, 10, 13, 0, 0, 64, 64, 21, 0, 0, 128, 64, 10, 7, 10, 5, 13, 0, 0, 64, 64].as_slice();
     let pact_json = json!({
@@ -510,13 +702,14 @@ mod tests {
     let message = pact.interactions.first().unwrap();
 
     let mut bytes2 = Bytes::from(b"\n\x0c\x12\n\r\0\0@@\x15\0\0\x80@\n\x07\n\x05\r\0\0@@".as_slice());
-    let fields = decode_message(&mut bytes2, input_message, &file_descriptor_set).unwrap();
+    let fields = decode_message(&mut bytes2, &input_message, &file_descriptor_set).unwrap();
     let request = DynamicMessage::new(fields.as_slice(), &file_descriptor_set);
 
     let mock_service = MockService {
       file_descriptor_set: file_descriptor_set.clone(),
       service_name: "Calculator".to_string(),
       message: message.as_v4_sync_message().unwrap(),
+      interactions: vec![],
       method_descriptor: method.clone(),
       input_message: input_message.clone(),
       output_message: output_message.clone(),
@@ -530,4 +723,249 @@ mod tests {
       md).await;
     expect!(response).to(be_ok());
   }
+
+  #[test_log::test(tokio::test)]
+  async fn handle_message_returns_invalid_argument_if_the_request_metadata_does_not_match() {
+    let (file_descriptor_set, ac_desc) = area_calculator_descriptors();
+    let (method, input_message, output_message) = calculator_method(&ac_desc, "calculateMulti");
+
+    let request_bytes: &[u8] = [10, 12, 18, 10, 13, 0, 0, 64, 64, 21, 0, 0, 128, 64, 10, 7, 10, 5, 13, 0, 0, 64, 64].as_slice();
+    let pact_json = json!({
+      "interactions": [
+        {
+          "type": "Synchronous/Messages",
+          "description": "calculate rectangle area request",
+          "request": {
+            "contents": {
+              "content": BASE64.encode(request_bytes),
+              "contentType": "application/protobuf; message=AreaRequest",
+              "contentTypeHint": "BINARY",
+              "encoded": "base64"
+            },
+            "metadata": {
+              "contentType": "application/protobuf;message=.area_calculator.AreaRequest",
+              "x-api-key": "key-100"
+            },
+            "matchingRules": {
+              "metadata": {
+                "x-api-key": {
+                  "combine": "AND",
+                  "matchers": [
+                    {
+                      "match": "regex",
+                      "regex": "^key-\\d+$"
+                    }
+                  ]
+                }
+              },
+              "body": {
+                "$.shapes[0].rectangle.length": {
+                  "combine": "AND",
+                  "matchers": [
+                    {
+                      "match": "number"
+                    }
+                  ]
+                },
+                "$.shapes[0].rectangle.width": {
+                  "combine": "AND",
+                  "matchers": [
+                    {
+                      "match": "number"
+                    }
+                  ]
+                },
+                "$.shapes[1].square.edge_length": {
+                  "combine": "AND",
+                  "matchers": [
+                    {
+                      "match": "number"
+                    }
+                  ]
+                }
+              }
+            }
+          },
+          "response": [
+            {
+              "contents": {
+                "content": "CgQAAEBB",
+                "contentType": "application/protobuf; message=.area_calculator.AreaResponse",
+                "contentTypeHint": "BINARY",
+                "encoded": "base64"
+              },
+              "metadata": {
+                "contentType": "application/protobuf;message=.area_calculator.AreaResponse"
+              }
+            }
+          ],
+          "pluginConfiguration": {
+            "protobuf": {
+              "descriptorKey": "d58838959e37498cddf51805bedf4dca",
+              "service": ".area_calculator.Calculator/calculateMulti"
+            }
+          },
+          "transport": "grpc"
+        }
+      ],
+      "metadata": {
+        "pactSpecification": { "version": "4.0" }
+      }
+    });
+
+    let pact = V4Pact::pact_from_json(&pact_json, "<>").unwrap();
+    let message = pact.interactions.first().unwrap();
+
+    let mut bytes2 = Bytes::from(b"\n\x0c\x12\n\r\0\0@@\x15\0\0\x80@\n\x07\n\x05\r\0\0@@".as_slice());
+    let fields = decode_message(&mut bytes2, &input_message, &file_descriptor_set).unwrap();
+    let request = DynamicMessage::new(fields.as_slice(), &file_descriptor_set);
+
+    let mock_service = MockService {
+      file_descriptor_set: file_descriptor_set.clone(),
+      service_name: "Calculator".to_string(),
+      message: message.as_v4_sync_message().unwrap(),
+      interactions: vec![],
+      method_descriptor: method.clone(),
+      input_message: input_message.clone(),
+      output_message: output_message.clone(),
+      server_key: "9876789".to_string(),
+      pact
+    };
+
+    let mut md = MetadataMap::new();
+    md.insert(MetadataKey::from_static("contenttype"), MetadataValue::from_static("application/protobuf;message=.area_calculator.AreaRequest"));
+    md.insert(MetadataKey::from_static("x-api-key"), MetadataValue::from_static("key-200"));
+    let response = mock_service.handle_message(request.clone(), input_message.clone(), output_message.clone(),
+      md).await;
+    expect!(response).to(be_ok());
+
+    let mut md = MetadataMap::new();
+    md.insert(MetadataKey::from_static("contenttype"), MetadataValue::from_static("application/protobuf;message=.area_calculator.AreaRequest"));
+    md.insert(MetadataKey::from_static("x-api-key"), MetadataValue::from_static("wrong-key"));
+    let status = mock_service.handle_message(request.clone(), input_message.clone(), output_message.clone(),
+      md).await.unwrap_err();
+    expect!(status.code()).to(be_equal_to(Code::InvalidArgument));
+    expect!(status.message()).to(be_equal_to(
+      "Failed to match the request metadata - Comparison of metadata key 'x-api-key' failed: Expected 'wrong-key' to match '^key-\\d+$'"));
+
+    let mut md = MetadataMap::new();
+    md.insert(MetadataKey::from_static("contenttype"), MetadataValue::from_static("application/protobuf;message=.area_calculator.AreaRequest"));
+    let status = mock_service.handle_message(request, input_message.clone(), output_message.clone(),
+      md).await.unwrap_err();
+    expect!(status.code()).to(be_equal_to(Code::InvalidArgument));
+  }
+
+  #[test_log::test(tokio::test)]
+  async fn select_interaction_uses_the_interaction_that_best_matches_the_request() {
+    let (file_descriptor_set, ac_desc) = area_calculator_descriptors();
+    let (method, input_message, output_message) = calculator_method(&ac_desc, "calculateOne");
+
+    // Square messages with an edge length of 3 and 4
+    let square_3: &[u8] = [10, 5, 13, 0, 0, 64, 64].as_slice();
+    let square_4: &[u8] = [10, 5, 13, 0, 0, 128, 64].as_slice();
+    let interaction = |description: &str, request: &[u8]| json!({
+      "type": "Synchronous/Messages",
+      "description": description,
+      "request": {
+        "contents": {
+          "content": BASE64.encode(request),
+          "contentType": "application/protobuf; message=.area_calculator.ShapeMessage",
+          "contentTypeHint": "BINARY",
+          "encoded": "base64"
+        }
+      },
+      "response": [
+        {
+          "contents": {
+            "content": "CgQAAEBB",
+            "contentType": "application/protobuf; message=.area_calculator.AreaResponse",
+            "contentTypeHint": "BINARY",
+            "encoded": "base64"
+          }
+        }
+      ],
+      "pluginConfiguration": {
+        "protobuf": {
+          "descriptorKey": "d58838959e37498cddf51805bedf4dca",
+          "service": ".area_calculator.Calculator/calculateOne"
+        }
+      },
+      "transport": "grpc"
+    });
+    let pact_json = json!({
+      "interactions": [
+        interaction("square with an edge length of 3", square_3),
+        interaction("square with an edge length of 4", square_4)
+      ],
+      "metadata": {
+        "pactSpecification": { "version": "4.0" }
+      }
+    });
+    let pact = V4Pact::pact_from_json(&pact_json, "<>").unwrap();
+    let interactions = pact.interactions.iter()
+      .map(|i| i.as_v4_sync_message().unwrap())
+      .collect::<Vec<_>>();
+
+    let mock_service = MockService::new(&file_descriptor_set, "Calculator", &method, &input_message,
+      &output_message, &interactions, "9876789", pact.clone());
+
+    for (request_bytes, expected) in [(square_4, "square with an edge length of 4"), (square_3, "square with an edge length of 3")] {
+      let fields = decode_message(&mut Bytes::copy_from_slice(request_bytes), &input_message, &file_descriptor_set).unwrap();
+      let request = DynamicMessage::new(fields.as_slice(), &file_descriptor_set);
+      let selected = mock_service.select_interaction(&request, &input_message, &MetadataMap::new());
+      expect!(selected.description.as_str()).to(be_equal_to(expected));
+    }
+
+    // The results are recorded against the interaction that was selected
+    let route = "/area_calculator.Calculator/calculateOne";
+    let (shutdown, _) = tokio::sync::oneshot::channel();
+    let initial_state = interactions.iter()
+      .map(|interaction| (result_key(route, interaction, interactions.len()), (0, vec![])))
+      .collect();
+    MOCK_SERVER_STATE.lock().unwrap().insert("9876789".to_string(), (shutdown, initial_state));
+    let fields = decode_message(&mut Bytes::copy_from_slice(square_3), &input_message, &file_descriptor_set).unwrap();
+    let request = DynamicMessage::new(fields.as_slice(), &file_descriptor_set);
+    let mut service = MockService::new(&file_descriptor_set, ".area_calculator.Calculator", &method, &input_message,
+      &output_message, &interactions, "9876789", pact.clone());
+    service.message = service.select_interaction(&request, &input_message, &MetadataMap::new());
+    let response = service.handle_message(request, input_message.clone(), output_message.clone(), MetadataMap::new()).await;
+    expect!(response).to(be_ok());
+
+    let (_, results) = MOCK_SERVER_STATE.lock().unwrap().remove("9876789").unwrap();
+    let counts = results.iter()
+      .map(|(key, (count, _))| (key.clone(), *count))
+      .collect::<std::collections::HashMap<_, _>>();
+    expect!(counts).to(be_equal_to(maplit::hashmap!{
+      format!("{} (square with an edge length of 3)", route) => 1,
+      format!("{} (square with an edge length of 4)", route) => 0
+    }));
+    // The response message type is taken from the selected interaction
+    let mut interaction = interactions[1].clone();
+    interaction.plugin_config.get_mut("protobuf").unwrap()
+      .insert("responseMessageType".to_string(), json!(".area_calculator.ShapeMessage"));
+    service.message = interaction;
+    expect!(service.response_descriptor().unwrap().name.unwrap()).to(be_equal_to("ShapeMessage"));
+    service.message = interactions[0].clone();
+    expect!(service.response_descriptor().unwrap().name.unwrap()).to(be_equal_to("AreaResponse"));
+  }
+
+  #[test]
+  fn grpc_timeout_test() {
+    let metadata = |value: &str| {
+      let mut metadata = MetadataMap::new();
+      metadata.insert("grpc-timeout", value.parse().unwrap());
+      metadata
+    };
+    expect!(grpc_timeout(&MetadataMap::new())).to(be_none());
+    expect!(grpc_timeout(&metadata("1H"))).to(be_some().value(Duration::from_secs(3600)));
+    expect!(grpc_timeout(&metadata("2M"))).to(be_some().value(Duration::from_secs(120)));
+    expect!(grpc_timeout(&metadata("10S"))).to(be_some().value(Duration::from_secs(10)));
+    expect!(grpc_timeout(&metadata("100m"))).to(be_some().value(Duration::from_millis(100)));
+    expect!(grpc_timeout(&metadata("100u"))).to(be_some().value(Duration::from_micros(100)));
+    expect!(grpc_timeout(&metadata("100n"))).to(be_some().value(Duration::from_nanos(100)));
+    expect!(grpc_timeout(&metadata("18446744073709551615H"))).to(be_some().value(Duration::from_secs(u64::MAX)));
+    expect!(grpc_timeout(&metadata("18446744073709551615M"))).to(be_some().value(Duration::from_secs(u64::MAX)));
+    expect!(grpc_timeout(&metadata("100"))).to(be_none());
+    expect!(grpc_timeout(&metadata("abcS"))).to(be_none());
+  }
 }
@@ -1,20 +1,25 @@
 //! Module for processing and comparing protobuf messages
 
 use std::collections::{BTreeMap, HashMap, HashSet};
-use std::path::Path;
+use std::env;
+use std::path::{Path, PathBuf};
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::time::Duration;
 
 use anyhow::anyhow;
+use bytes::Bytes;
 use base64::Engine;
 use base64::engine::general_purpose::STANDARD as BASE64;
 use itertools::{Either, Itertools};
 use maplit::{btreemap, hashmap};
 use num::ToPrimitive;
+use pact_models::content_types::ContentType;
 use pact_models::generators::Generator;
 use pact_models::json_utils::json_to_string;
 use pact_models::matchingrules;
 use pact_models::matchingrules::expressions::{is_matcher_def, MatchingRuleDefinition, parse_matcher_def, ValueType};
 use pact_models::matchingrules::MatchingRuleCategory;
-use pact_models::path_exp::DocPath;
+use pact_models::path_exp::{DocPath, PathToken};
 use pact_models::prelude::RuleLogic;
 use pact_plugin_driver::proto::{
   Body,
@@ -26,20 +31,35 @@ use pact_plugin_driver::proto::{
 use pact_plugin_driver::proto::body::ContentTypeHint;
 use pact_plugin_driver::proto::interaction_response::MarkupType;
 use pact_plugin_driver::utils::{proto_value_to_json, proto_value_to_string, to_proto_struct};
-use prost_types::{DescriptorProto, FieldDescriptorProto, FileDescriptorProto, ServiceDescriptorProto, Struct};
+use prost::Message;
+use prost_types::{DescriptorProto, FieldDescriptorProto, FileDescriptorProto, FileDescriptorSet, ServiceDescriptorProto, Struct};
 use prost_types::field_descriptor_proto::Type;
 use prost_types::value::Kind;
+use reqwest::Url;
 use serde_json::{json, Value};
+use tempfile::TempDir;
 use tokio::fs::File;
 use tokio::io::AsyncReadExt;
 use tracing::{debug, error, instrument, trace, warn};
 use tracing_core::LevelFilter;
-
-use crate::message_builder::{MessageBuilder, MessageFieldValue, MessageFieldValueType, RType};
-use crate::metadata::{MessageMetadata, process_metadata};
-use crate::protoc::Protoc;
+use tonic::Code;
+
+use crate::built_info;
+use crate::matching::{any_of_rule, equals_ignore_case_rule, NumberRange, protobuf_message_type};
+use crate::message_builder::{MessageBuilder, MessageFieldValue, MessageFieldValueType, proto_type_name, RType};
+use crate::message_decoder::{decode_message, ProtobufField};
+use crate::metadata::{
+  MessageMetadata,
+  process_metadata,
+  RpcStatus,
+  STATUS_DETAILS_KEY,
+  string_to_code,
+  TRAILERS_KEY
+};
+use crate::metrics::{self, Counter};
+use crate::protoc::{FileSystemProtoSource, Protoc};
 use crate::utils::{
-  to_fully_qualified_name, find_enum_value_by_name, find_enum_value_by_name_in_message, find_message_descriptor_for_type_in_map, find_nested_type, is_map_field, is_repeated_field, last_name, prost_string, split_service_and_method
+  to_fully_qualified_name, enum_name, fds_map_to_vec, fds_to_map, find_enum_value_by_name, find_enum_value_by_name_in_message, find_enum_value_by_number, find_message_descriptor_for_type, find_message_descriptor_for_type_in_map, find_nested_type, is_map_field, is_repeated_field, last_name, parse_iso8601_duration, parse_name, prost_string, response_message_type, split_service_and_method
 };
 
 /// Converts user-provided configuration and .proto files into a pact interaction.
@@ -72,16 +92,28 @@ use crate::utils::{
 /// A tuple of values to construct the pact file:
 /// - Vector of interactions - single for a message interaction, or a request/response pair for a grpc interaction
 /// - Plugin configuration, which can be used to store the protobuf file and descriptors
+///
+/// If the proto file is a HTTP or HTTPS URL, it will be downloaded to a temporary directory first
+/// (using the `download_timeout`, or 30 seconds if not set).
 pub(crate) async fn process_proto(
   proto_file: String,
   protoc: &Protoc,
-  config: &BTreeMap<String, prost_types::Value>
+  config: &BTreeMap<String, prost_types::Value>,
+  download_timeout: Option<Duration>
 ) -> anyhow::Result<(Vec<InteractionResponse>, PluginConfiguration)> {
   debug!("Parsing proto file '{}'", proto_file);
   trace!(">> process_proto({proto_file}, {config:?})");
 
-  let proto_file = Path::new(proto_file.as_str());
-  let (descriptors, digest, descriptor_bytes) = protoc.parse_proto_file(proto_file).await?;
+  let downloaded = if is_remote_url(proto_file.as_str()) {
+    Some(download_proto_file(proto_file.as_str(), download_timeout.unwrap_or(DEFAULT_DOWNLOAD_TIMEOUT)).await?)
+  } else {
+    None
+  };
+  let proto_file = match &downloaded {
+    Some((_, path)) => path.as_path(),
+    None => Path::new(proto_file.as_str())
+  };
+  let (descriptors, descriptor_hash, descriptor_bytes) = protoc.parse_proto_file(proto_file, &FileSystemProtoSource).await?;
   debug!("Parsed proto file OK, file descriptors = {:?}", descriptors.file.iter().map(|file| file.name.as_ref()).collect_vec());
   trace!("Descriptor bytes {:?}", descriptor_bytes.as_slice());
 
@@ -101,7 +133,7 @@ pub(crate) async fn process_proto(
     }
   }
   let descriptor_encoded = BASE64.encode(&descriptor_bytes);
-  let descriptor_hash = format!("{:x}", md5::compute(&descriptor_bytes));
+  dump_descriptors(env::var(DUMP_DESCRIPTORS_ENV_VAR).ok(), descriptor_hash.as_str(), &descriptor_bytes).await;
   let mut interactions = vec![];
 
   if let Some(message_type) = config.get("pact:message-type") {
@@ -127,13 +159,13 @@ pub(crate) async fn process_proto(
   let mut file_contents = String::new();
   f.read_to_string(&mut file_contents).await?;
 
-  let digest_str = format!("{:x}", digest);
   let plugin_config = PluginConfiguration {
     interaction_configuration: None,
     pact_configuration: Some(to_proto_struct(&hashmap!{
-      digest_str => json!({
+      descriptor_hash => json!({
         "protoFile": file_contents,
-        "protoDescriptors": descriptor_encoded
+        "protoDescriptors": descriptor_encoded,
+        "pluginVersion": built_info::PKG_VERSION
       })
     }))
   };
@@ -141,6 +173,61 @@ pub(crate) async fn process_proto(
   Ok((interactions, plugin_config))
 }
 
+/// Environment variable that can be set to a directory to write the encoded file descriptor sets to
+const DUMP_DESCRIPTORS_ENV_VAR: &str = "PACT_PROTOBUF_DUMP_DESCRIPTORS";
+
+/// Writes the encoded file descriptor set to `<hash>.pb` in the dump directory, if one is set. This
+/// is only for debugging, so any failure is logged and ignored.
+async fn dump_descriptors(dump_dir: Option<String>, descriptor_hash: &str, descriptor_bytes: &[u8]) {
+  if let Some(dir) = dump_dir.filter(|dir| !dir.is_empty()) {
+    let path = Path::new(dir.as_str()).join(format!("{}.pb", descriptor_hash));
+    match tokio::fs::write(&path, descriptor_bytes).await {
+      Ok(_) => debug!("Wrote the file descriptor set to '{}'", path.display()),
+      Err(err) => warn!("Failed to write the file descriptor set to '{}' - {}", path.display(), err)
+    }
+  }
+}
+
+/// Default timeout to use when downloading proto files
+const DEFAULT_DOWNLOAD_TIMEOUT: Duration = Duration::from_secs(30);
+
+/// If the proto file has been provided as a HTTP or HTTPS URL
+fn is_remote_url(proto_file: &str) -> bool {
+  let proto_file = proto_file.trim().to_lowercase();
+  proto_file.starts_with("http://") || proto_file.starts_with("https://")
+}
+
+/// Downloads the proto file from the URL into a temporary directory. The file will keep the same
+/// name as the last segment of the URL path. Returns the temporary directory (which will be deleted
+/// when it is dropped) and the path to the downloaded file.
+async fn download_proto_file(url: &str, timeout: Duration) -> anyhow::Result<(TempDir, PathBuf)> {
+  debug!("Downloading proto file from '{}'", url);
+  let parsed_url = Url::parse(url.trim())
+    .map_err(|err| anyhow!("'{}' is not a valid URL - {}", url, err))?;
+  let file_name = parsed_url.path_segments()
+    .and_then(|mut segments| segments.next_back())
+    .filter(|name| !name.is_empty())
+    .map(|name| name.to_string())
+    .ok_or_else(|| anyhow!("Could not determine the proto file name from the URL '{}'", url))?;
+
+  let client = reqwest::Client::builder()
+    .timeout(timeout)
+    .build()?;
+  let response = client.get(parsed_url).send().await
+    .map_err(|err| anyhow!("Failed to download the proto file from '{}' - {}", url, err))?;
+  if !response.status().is_success() {
+    return Err(anyhow!("Failed to download the proto file from '{}' - {}", url, response.status()));
+  }
+  let contents = response.bytes().await
+    .map_err(|err| anyhow!("Failed to download the proto file from '{}' - {}", url, err))?;
+
+  let dir = tempfile::tempdir()?;
+  let path = dir.path().join(file_name);
+  tokio::fs::write(&path, &contents).await?;
+  debug!("Downloaded {} bytes to '{}'", contents.len(), path.display());
+  Ok((dir, path))
+}
+
 /// Configure the interaction for a gRPC service method, which has an input and output message.
 /// Main work is done in `construct_protobuf_interaction_for_service`;
 /// this function does two things:
@@ -149,6 +236,20 @@ pub(crate) async fn process_proto(
 ///   - service: the fully qualified service name; allows to locate this service when verifying this interaction
 ///   - descriptorKey: a hash of the protobuf file descriptor, which allows to locate the file descriptor 
 /// in the plugin configuration when verifying this interaction
+///   - strictEnums: only set if enabled, undefined enum values will be treated as mismatches
+///   - compressResponses: only set if enabled, the mock server will compress the response messages
+///   - echoRequest: only set if enabled, the mock server will return the request message as the response
+///   - responseDelay: only set if configured, the number of milliseconds the mock server will wait before responding
+///   - repeatedFieldKeys: only set if configured, the key fields used to pair the elements of repeated message fields
+///   - repeatedFieldMatching: only set if configured, the repeated fields that may have more values than were expected
+///   - timestampTolerance: only set if configured, the number of seconds timestamp fields may differ from the expected values
+///   - numberRanges: only set if configured, the bounds of any number range matchers (`range(min, max)`)  for the request and response
+///   - ignoreFields: only set if configured, the paths of the fields that will not be compared
+///   - closedMessage: only set if enabled, fields in the actual messages that were not expected will be treated as mismatches
+///   - failFast: only set if enabled, verification will stop comparing the messages at the first mismatch
+///   - strictMessageType: only set if enabled, the content type of the actual messages must declare the expected message type
+///   - responseMessageType: only set if configured, the message type to use for the response instead of the method output type
+///   - packedEncoding: only set if configured, the wire encoding (packed or unpacked) expected for repeated scalar fields
 fn configure_protobuf_service(
   service_with_method: &str,
   config: &BTreeMap<String, prost_types::Value>,
@@ -160,21 +261,91 @@ fn configure_protobuf_service(
 
   debug!("Looking for service and method with name '{}'", service_with_method);
   let (service, method_name) = split_service_and_method(service_with_method)?;
-  // Lookup service inside the descriptor, but don't search all file descriptors to avoid similarly named services
-  let service_descriptor = descriptor.service
-    .iter().find(|p| p.name() == service)
-    .ok_or_else(|| anyhow!("Did not find a descriptor for service '{}'", service_with_method))?;
+  let (service_name, package) = parse_name(service);
+  let (service_descriptor, package) = match package {
+    // Fully-qualified service name, so lookup the service in the file descriptors for the package
+    Some(package) => {
+      let service_descriptor = all_descriptors.values()
+        .filter(|fd| fd.package() == package)
+        .sorted_by_key(|fd| fd.name() != descriptor.name())
+        .find_map(|fd| fd.service.iter().find(|p| p.name() == service_name))
+        .ok_or_else(|| anyhow!("Did not find a descriptor for service '{}' in package '{}'", service_with_method, package))?;
+      (service_descriptor, package)
+    }
+    // Lookup service inside the descriptor, but don't search all file descriptors to avoid similarly named services
+    None => {
+      let service_descriptor = descriptor.service
+        .iter().find(|p| p.name() == service_name)
+        .ok_or_else(|| anyhow!("Did not find a descriptor for service '{}'", service_with_method))?;
+      (service_descriptor, descriptor.package())
+    }
+  };
   trace!("service_descriptor = {:?}", service_descriptor);
-  
-  let service_with_method = service_with_method.split_once(':').map(|(s, _)| s).unwrap_or(service_with_method);
-  let service_full_name = to_fully_qualified_name(service_with_method, descriptor.package())?;
+
+  let method = method_name.split_once(':').map(|(m, _)| m).unwrap_or(method_name);
+  let service_full_name = to_fully_qualified_name(format!("{}/{}", service_name, method).as_str(), package)?;
+  let response_delay = response_delay(config)?;
+  let packed_encoding = packed_encoding(config)?;
+  let output_type_override = service_descriptor.method.iter()
+    .find(|m| m.name() == method)
+    .map(|m| (m.output_type(), response_message_type(m, &protobuf_config(config)).to_string()))
+    .and_then(|(output_type, message_type)| (message_type != output_type).then_some(message_type));
+  let repeated_field_matching = repeated_field_matching(config)?;
   construct_protobuf_interaction_for_service(service_descriptor, config, method_name, all_descriptors)
     .map(|(request, response)| {
+      let mut interaction_configuration = hashmap! {
+        "service".to_string() => Value::String(service_full_name),
+        "descriptorKey".to_string() => Value::String(descriptor_hash.to_string())
+      };
+      if protobuf_config_flag(config, "strictEnums") {
+        interaction_configuration.insert("strictEnums".to_string(), Value::Bool(true));
+      }
+      if let Some(keys) = repeated_field_keys(config) {
+        interaction_configuration.insert("repeatedFieldKeys".to_string(), keys);
+      }
+      if let Some(modes) = repeated_field_matching.clone() {
+        interaction_configuration.insert("repeatedFieldMatching".to_string(), modes);
+      }
+      if let Some(tolerances) = timestamp_tolerance(config) {
+        interaction_configuration.insert("timestampTolerance".to_string(), tolerances);
+      }
+      let ranges = ["request", "response"].iter()
+        .filter_map(|part| config.get(*part)
+          .and_then(|part_config| number_ranges(&proto_value_to_json(part_config)))
+          .map(|ranges| (part.to_string(), ranges)))
+        .collect::<serde_json::Map<_, _>>();
+      if !ranges.is_empty() {
+        interaction_configuration.insert("numberRanges".to_string(), Value::Object(ranges));
+      }
+      if let Some(paths) = ignore_fields(config) {
+        interaction_configuration.insert("ignoreFields".to_string(), paths);
+      }
+      if protobuf_config_flag(config, "closedMessage") {
+        interaction_configuration.insert("closedMessage".to_string(), Value::Bool(true));
+      }
+      if protobuf_config_flag(config, "failFast") {
+        interaction_configuration.insert("failFast".to_string(), Value::Bool(true));
+      }
+      if protobuf_config_flag(config, "strictMessageType") {
+        interaction_configuration.insert("strictMessageType".to_string(), Value::Bool(true));
+      }
+      if protobuf_config_flag(config, "echoRequest") {
+        interaction_configuration.insert("echoRequest".to_string(), Value::Bool(true));
+      }
+      if protobuf_config_flag(config, "compressResponses") {
+        interaction_configuration.insert("compressResponses".to_string(), Value::Bool(true));
+      }
+      if let Some(delay) = response_delay {
+        interaction_configuration.insert("responseDelay".to_string(), json!(delay));
+      }
+      if let Some(message_type) = output_type_override.clone() {
+        interaction_configuration.insert("responseMessageType".to_string(), Value::String(message_type));
+      }
+      if let Some(encoding) = packed_encoding {
+        interaction_configuration.insert("packedEncoding".to_string(), Value::String(encoding));
+      }
       let plugin_configuration = Some(PluginConfiguration {
-        interaction_configuration: Some(to_proto_struct(&hashmap! {
-            "service".to_string() => Value::String(service_full_name),
-            "descriptorKey".to_string() => Value::String(descriptor_hash.to_string())
-        })),
+        interaction_configuration: Some(to_proto_struct(&interaction_configuration)),
         pact_configuration: None
       });
       trace!("request = {request:?}");
@@ -212,39 +383,228 @@ fn construct_protobuf_interaction_for_service(
 
   let input_name = method_descriptor.input_type.as_ref()
     .ok_or_else(|| anyhow!("Input message name is empty for service {}/{}", service_name, method_name))?;
-  let output_name = method_descriptor.output_type.as_ref()
-    .ok_or_else(|| anyhow!("Output message name is empty for service {}/{}", service_name, method_name))?;
+  let output_name = response_message_type(method_descriptor, &protobuf_config(config)).to_string();
+  if output_name.is_empty() {
+    return Err(anyhow!("Output message name is empty for service {}/{}", service_name, method_name));
+  }
   
+  if protobuf_config_flag(config, "echoRequest") && input_name.as_str() != output_name.as_str() {
+    return Err(anyhow!("'echoRequest' can only be used with methods that have the same input and output message types, \
+      but {}/{} has an input type of '{}' and an output type of '{}'", service_name, method_name, input_name, output_name));
+  }
+
   let (request_descriptor, request_file_descriptor) = 
     find_message_descriptor_for_type_in_map(input_name, all_descriptors)?;
   let (response_descriptor, response_file_descriptor) = 
-    find_message_descriptor_for_type_in_map(output_name, all_descriptors)?;
+    find_message_descriptor_for_type_in_map(output_name.as_str(), all_descriptors)
+      .map_err(|err| if output_name.as_str() == method_descriptor.output_type() {
+        anyhow!(err)
+      } else {
+        anyhow!("Did not find the message type '{}' configured with 'responseMessageType' - {}", output_name, err)
+      })?;
   
   trace!(%input_name, ?request_descriptor, ?request_file_descriptor, "Input message descriptor");
   trace!(%output_name, ?response_descriptor, ?response_file_descriptor, "Output message descriptor");
   
+  let canonical_json = protobuf_config_flag(config, "canonicalJson");
   let request_part_config = request_part(config, service_part)?;
+  let request_examples = example_overrides(config, Some("request"), &request_part_config)?;
+  let map_request_names = |config: BTreeMap<String, prost_types::Value>| if canonical_json {
+    canonical_json_config(&config, &request_descriptor, all_descriptors)
+  } else {
+    config
+  };
+  let request_part_config = map_request_names(request_part_config);
   trace!(config = ?request_part_config, service_part, "Processing request part config");
   let request_metadata = process_metadata(config.get("requestMetadata"))?;
+  let request_generators = external_generators(config, Some("request"))?;
 
   let interaction = construct_protobuf_interaction_for_message(&request_descriptor,
     &request_part_config, "", &request_file_descriptor, all_descriptors, request_metadata.as_ref())?;
+  let request_examples = request_examples.map(map_request_names)
+    .map(|examples| construct_protobuf_interaction_for_message(&request_descriptor,
+      &examples, "", &request_file_descriptor, all_descriptors, request_metadata.as_ref()))
+    .transpose()?;
   let request_part = Some(InteractionResponse {
     part_name: "request".into(),
-    .. interaction
+    .. add_matching_rules(with_example_contents(interaction, request_examples),
+      &external_matching_rules(config, Some("request"))?)
   });
+  let request_part = request_part.map(|interaction| add_generators(interaction, &request_generators));
 
   let response_part_config = response_part(config, service_part)?;
   trace!(config = ?response_part_config, service_part, "Processing response part config");
+  let response_trailers = process_metadata(config.get("responseTrailers"))?;
+  let grpc_status = config.get("pact:grpc-status");
+  let response_rules = external_matching_rules(config, Some("response"))?;
+  let response_generators = external_generators(config, Some("response"))?;
+  // Error responses can be configured with only the response metadata, in which case there is
+  // no response message and only the gRPC status is returned and verified
+  let no_response_message = service_part != "response" && !config.contains_key("response");
   let mut response_part = vec![];
-  for (config, md_config) in response_part_config {
-    let response_metadata = process_metadata(md_config)?;
+  let map_response_names = |config: &BTreeMap<String, prost_types::Value>| if canonical_json {
+    canonical_json_config(config, &response_descriptor, all_descriptors)
+  } else {
+    config.clone()
+  };
+  for (index, (part_config, md_config)) in response_part_config.iter().enumerate() {
+    let response_examples = example_overrides(config, Some("response"), part_config)?
+      .map(|examples| map_response_names(&examples));
+    let config = &map_response_names(part_config);
+    let md_config = grpc_status_config(*md_config, grpc_status)?;
+    let md_config = status_details_config(md_config.as_ref(), all_descriptors)?;
+    let status_only = no_response_message && is_error_status(md_config.as_ref());
+    let mut response_metadata = process_metadata(md_config.as_ref())?;
+    if let Some(trailers) = &response_trailers {
+      // gRPC trailers are only applied to the first response message
+      if index == 0 {
+        response_metadata = Some(response_metadata.unwrap_or_default().with_trailers(trailers));
+      }
+    }
     let interaction = construct_protobuf_interaction_for_message(
-      &response_descriptor, &config, "", &response_file_descriptor, all_descriptors, response_metadata.as_ref())?;
-    response_part.push(InteractionResponse { part_name: "response".into(), .. interaction });
+      &response_descriptor, config, "", &response_file_descriptor, all_descriptors, response_metadata.as_ref())?;
+    let response_examples = response_examples
+      .map(|examples| construct_protobuf_interaction_for_message(&response_descriptor, &examples, "",
+        &response_file_descriptor, all_descriptors, response_metadata.as_ref()))
+      .transpose()?;
+    let interaction = add_matching_rules(with_example_contents(interaction, response_examples), &response_rules);
+    let interaction = add_generators(interaction, &response_generators);
+    response_part.push(InteractionResponse {
+      part_name: "response".into(),
+      contents: interaction.contents.filter(|_| !status_only),
+      .. interaction
+    });
+  }
+
+  let hint = content_type_hint(config)?;
+  let with_hint = |interaction: InteractionResponse| InteractionResponse {
+    contents: interaction.contents.map(|body| Body { content_type_hint: hint as i32, .. body }),
+    .. interaction
+  };
+  Ok((request_part.map(with_hint), response_part.into_iter().map(with_hint).collect()))
+}
+
+/// If the `pact:grpc-status` value is configured, adds the gRPC status to the response metadata
+/// (as the `grpc-status` and `grpc-message` values). It can be configured with just the status, or
+/// with the status and message, i.e.
+///
+/// ```json
+/// "pact:grpc-status": { "code": "UNAUTHENTICATED", "message": "Invalid token" }
+/// ```
+fn grpc_status_config(
+  metadata_config: Option<&prost_types::Value>,
+  grpc_status: Option<&prost_types::Value>
+) -> anyhow::Result<Option<prost_types::Value>> {
+  let grpc_status = match grpc_status {
+    Some(grpc_status) => grpc_status,
+    None => return Ok(metadata_config.cloned())
+  };
+  let (code, message) = match &grpc_status.kind {
+    Some(Kind::StructValue(s)) => (
+      s.fields.get("code").and_then(proto_value_to_string)
+        .ok_or_else(|| anyhow!("'pact:grpc-status' must have a 'code' value"))?,
+      s.fields.get("message").and_then(proto_value_to_string)
+    ),
+    Some(Kind::StringValue(_)) | Some(Kind::NumberValue(_)) => (proto_value_to_string(grpc_status).unwrap_or_default(), None),
+    _ => return Err(anyhow!("'pact:grpc-status' must be either a status or a map with the status code and message"))
+  };
+
+  let mut fields = match metadata_config.and_then(|config| config.kind.as_ref()) {
+    Some(Kind::StructValue(s)) => s.fields.clone(),
+    None => btreemap!{},
+    Some(_) => return Ok(metadata_config.cloned())
+  };
+  if fields.contains_key("grpc-status") {
+    return Err(anyhow!("'pact:grpc-status' can not be used with a 'grpc-status' value in the response metadata"));
+  }
+  fields.insert("grpc-status".to_string(), prost_string(code));
+  if let Some(message) = message {
+    fields.insert("grpc-message".to_string(), prost_string(message));
+  }
+  Ok(Some(prost_types::Value { kind: Some(Kind::StructValue(Struct { fields })) }))
+}
+
+/// If the response metadata configures a gRPC status that is not `OK`
+fn is_error_status(metadata_config: Option<&prost_types::Value>) -> bool {
+  match metadata_config.and_then(|config| config.kind.as_ref()) {
+    Some(Kind::StructValue(s)) => s.fields.get("grpc-status")
+      .and_then(proto_value_to_string)
+      .and_then(|status| string_to_code(status.as_str(), ""))
+      .is_some_and(|status| status.code() != Code::Ok),
+    _ => false
+  }
+}
+
+/// If the response metadata configures the gRPC status details (`grpc-status-details-bin`) with
+/// the detail messages, builds the `google.rpc.Status` message and replaces the configuration with
+/// the Base64 encoded message. The code and message of the status are taken from the `grpc-status`
+/// and `grpc-message` values. Each detail message is configured with the message type in the
+/// `@type` attribute and the fields of the message, i.e.
+///
+/// ```json
+/// "grpc-status-details-bin": {
+///   "details": [
+///     { "@type": ".area_calculator.ErrorInfo", "reason": "INVALID_SHAPE" }
+///   ]
+/// }
+/// ```
+fn status_details_config(
+  metadata_config: Option<&prost_types::Value>,
+  all_descriptors: &HashMap<String, &FileDescriptorProto>
+) -> anyhow::Result<Option<prost_types::Value>> {
+  let fields = match metadata_config.and_then(|config| config.kind.as_ref()) {
+    Some(Kind::StructValue(s)) => &s.fields,
+    _ => return Ok(metadata_config.cloned())
+  };
+  let details_config = match fields.get(STATUS_DETAILS_KEY).and_then(|value| value.kind.as_ref()) {
+    Some(Kind::StructValue(s)) => s,
+    _ => return Ok(metadata_config.cloned())
+  };
+
+  let code = fields.get("grpc-status")
+    .map(|value| proto_value_to_string(value).unwrap_or_default())
+    .and_then(|status| string_to_code(status.as_str(), ""))
+    .map(|status| status.code() as i32)
+    .ok_or_else(|| anyhow!("'{}' can only be used with an error 'grpc-status'", STATUS_DETAILS_KEY))?;
+  let message = fields.get("grpc-message")
+    .and_then(proto_value_to_string)
+    .unwrap_or_default();
+
+  let mut details = vec![];
+  let detail_configs = match details_config.fields.get("details").and_then(|value| value.kind.as_ref()) {
+    Some(Kind::ListValue(list)) => list.values.clone(),
+    None => vec![],
+    Some(_) => return Err(anyhow!("The 'details' for '{}' must be a list of messages", STATUS_DETAILS_KEY))
+  };
+  for detail_config in &detail_configs {
+    let mut detail_fields = match &detail_config.kind {
+      Some(Kind::StructValue(s)) => s.fields.clone(),
+      _ => return Err(anyhow!("Each of the 'details' for '{}' must be a message", STATUS_DETAILS_KEY))
+    };
+    let message_type = detail_fields.remove("@type")
+      .and_then(|value| proto_value_to_string(&value))
+      .ok_or_else(|| anyhow!("Each of the 'details' for '{}' must have the message type set with '@type'",
+        STATUS_DETAILS_KEY))?;
+    let (descriptor, file_descriptor) = find_message_descriptor_for_type_in_map(message_type.as_str(), all_descriptors)?;
+    let detail = construct_protobuf_interaction_for_message(&descriptor, &detail_fields, "",
+      &file_descriptor, all_descriptors, None)?;
+    let full_name = if file_descriptor.package().is_empty() {
+      descriptor.name().to_string()
+    } else {
+      format!("{}.{}", file_descriptor.package(), descriptor.name())
+    };
+    details.push(prost_types::Any {
+      type_url: format!("type.googleapis.com/{}", full_name),
+      value: detail.contents.and_then(|body| body.content).unwrap_or_default()
+    });
   }
 
-  Ok((request_part, response_part))
+  let status = RpcStatus { code, message, details };
+  let mut fields = fields.clone();
+  fields.insert(STATUS_DETAILS_KEY.to_string(), prost_types::Value {
+    kind: Some(Kind::StringValue(BASE64.encode(status.encode_to_vec())))
+  });
+  Ok(Some(prost_types::Value { kind: Some(Kind::StructValue(Struct { fields })) }))
 }
 
 fn response_part<'a>(
@@ -274,8 +634,9 @@ fn response_part<'a>(
           _ => vec![]
         }
       }).unwrap_or_default())
-  } else if let Some(response_md_config) = config.get("responseMetadata") {
-    Ok(vec![(btreemap!{}, Some(response_md_config))])
+  } else if config.contains_key("responseMetadata") || config.contains_key("responseTrailers")
+    || config.contains_key("pact:grpc-status") {
+    Ok(vec![(btreemap!{}, config.get("responseMetadata"))])
   } else {
     Ok(vec![])
   }
@@ -316,19 +677,79 @@ fn configure_protobuf_message(
   all_descriptors: &HashMap<String, &FileDescriptorProto>
 ) -> anyhow::Result<InteractionResponse> {
   trace!(">> configure_protobuf_message({}, {:?})", message_name, descriptor_hash);
+  let repeated_field_matching = repeated_field_matching(config)?;
   debug!("Looking for message '{}' in '{}'", message_name, descriptor.name());
   let message_descriptor = descriptor.message_type
     .iter().find(|p| p.name() == message_name)
     .ok_or_else(|| anyhow!("Did not find a descriptor for message '{}' in '{}'", message_name, descriptor.name()))?;
   let message_full_name = to_fully_qualified_name(message_name, descriptor.package())?;
-  construct_protobuf_interaction_for_message(message_descriptor, config, "", descriptor, all_descriptors, None)
+  // Only use the external matching rules if the message does not have a field with the same name
+  let external_rules = if message_descriptor.field.iter().any(|field| field.name() == MATCHING_RULES_KEY) {
+    MatchingRuleCategory::empty("body")
+  } else {
+    external_matching_rules(config, None)?
+  };
+  let external_generators = if message_descriptor.field.iter().any(|field| field.name() == GENERATORS_KEY) {
+    HashMap::new()
+  } else {
+    external_generators(config, None)?
+  };
+  let examples = if message_descriptor.field.iter().any(|field| field.name() == EXAMPLES_KEY) {
+    None
+  } else {
+    example_overrides(config, None, config)?
+  };
+  let canonical_json = protobuf_config_flag(config, "canonicalJson");
+  let map_names = |message_config: &BTreeMap<String, prost_types::Value>| if canonical_json {
+    canonical_json_config(message_config, message_descriptor, all_descriptors)
+  } else {
+    message_config.clone()
+  };
+  let examples = examples
+    .map(|examples| construct_protobuf_interaction_for_message(message_descriptor, &map_names(&examples), "",
+      descriptor, all_descriptors, None))
+    .transpose()?;
+  construct_protobuf_interaction_for_message(message_descriptor, &map_names(config), "", descriptor, all_descriptors, None)
+    .map(|interaction| add_matching_rules(with_example_contents(interaction, examples), &external_rules))
+    .map(|interaction| add_generators(interaction, &external_generators))
     .map(|interaction| {
+      let mut interaction_configuration = hashmap!{
+        "message".to_string() => Value::String(message_full_name),
+        "descriptorKey".to_string() => Value::String(descriptor_hash.to_string())
+      };
+      if protobuf_config_flag(config, "strictEnums") {
+        interaction_configuration.insert("strictEnums".to_string(), Value::Bool(true));
+      }
+      if let Some(keys) = repeated_field_keys(config) {
+        interaction_configuration.insert("repeatedFieldKeys".to_string(), keys);
+      }
+      if let Some(modes) = repeated_field_matching.clone() {
+        interaction_configuration.insert("repeatedFieldMatching".to_string(), modes);
+      }
+      if let Some(tolerances) = timestamp_tolerance(config) {
+        interaction_configuration.insert("timestampTolerance".to_string(), tolerances);
+      }
+      if let Some(paths) = ignore_fields(config) {
+        interaction_configuration.insert("ignoreFields".to_string(), paths);
+      }
+      let message_config = Value::Object(config.iter()
+        .map(|(key, value)| (key.clone(), proto_value_to_json(value)))
+        .collect());
+      if let Some(ranges) = number_ranges(&message_config) {
+        interaction_configuration.insert("numberRanges".to_string(), ranges);
+      }
+      if protobuf_config_flag(config, "closedMessage") {
+        interaction_configuration.insert("closedMessage".to_string(), Value::Bool(true));
+      }
+      if protobuf_config_flag(config, "failFast") {
+        interaction_configuration.insert("failFast".to_string(), Value::Bool(true));
+      }
+      if protobuf_config_flag(config, "strictMessageType") {
+        interaction_configuration.insert("strictMessageType".to_string(), Value::Bool(true));
+      }
       InteractionResponse {
         plugin_configuration: Some(PluginConfiguration {
-          interaction_configuration: Some(to_proto_struct(&hashmap!{
-            "message".to_string() => Value::String(message_full_name),
-            "descriptorKey".to_string() => Value::String(descriptor_hash.to_string())
-          })),
+          interaction_configuration: Some(to_proto_struct(&interaction_configuration)),
           pact_configuration: None
         }),
         .. interaction
@@ -367,27 +788,52 @@ fn construct_protobuf_interaction_for_message(
   trace!("message_descriptor = {:?}", message_descriptor);
   
   let message_name = message_descriptor.name.as_ref().expect("Message descriptor name cannot be empty");
-  let mut message_builder = MessageBuilder::new(message_descriptor, message_name, file_descriptor);
   let mut matching_rules = MatchingRuleCategory::empty("body");
   let mut generators = hashmap!{};
 
-  debug!("Building message {} from Protobuf descriptor", message_name);
   let mut path = DocPath::root();
   if !message_part.is_empty() {
     path.push_field(message_part);
   }
 
-  for (key, value) in config {
-    if !key.starts_with("pact:") {
-      let field_path = path.join(key);
-      debug!(?field_path, "Building field for key '{}'", key);
-      construct_message_field(&mut message_builder, &mut matching_rules, &mut generators,
-        key, &proto_value_to_json(value), &field_path, all_descriptors)?;
+  let (message_bytes, markup) = if let Some(encoded) = encoded_message_config(message_descriptor, config) {
+    debug!("Building message {} from the Base64 encoded message", message_name);
+    let bytes = Bytes::from(BASE64.decode(encoded.trim())
+      .map_err(|err| anyhow!("Failed to decode the Base64 encoded message for {} - {}", message_name, err))?);
+    let fds = FileDescriptorSet { file: fds_map_to_vec(all_descriptors) };
+    let fields = decode_message(&mut bytes.clone(), message_descriptor, &fds)
+      .map_err(|err| anyhow!("Failed to decode the encoded message as a {} - {}", message_name, err))?;
+    // Repeated fields are decoded as a field per value, so only add the rule once per field
+    for field_name in fields.iter().map(|field| &field.field_name).unique() {
+      matching_rules.add_rule(path.join(field_name), matchingrules::MatchingRule::Type, RuleLogic::And);
     }
-  }
+    (bytes, markup_for_decoded_message(message_descriptor, &fields)?)
+  } else {
+    let mut message_builder = MessageBuilder::new(message_descriptor, message_name, file_descriptor);
+    message_builder.canonical_json = protobuf_config_flag(config, "canonicalJson");
+    debug!("Building message {} from Protobuf descriptor", message_name);
+    let config = if let Some(example) = referenced_message_config(config, &path, &mut matching_rules)? {
+      debug!("Building message {} from the referenced example", message_name);
+      example
+    } else {
+      config.clone()
+    };
+    for (key, value) in &config {
+      if (key == MATCHING_RULES_KEY || key == EXAMPLES_KEY || key == GENERATORS_KEY) && message_builder.field_by_name(key).is_none() {
+        trace!("Skipping the '{}' value, as the message has no field with that name", key);
+      } else if !key.starts_with("pact:") {
+        let field_path = path.join(key);
+        debug!(?field_path, "Building field for key '{}'", key);
+        construct_message_field(&mut message_builder, &mut matching_rules, &mut generators,
+          key, &proto_value_to_json(value), &field_path, all_descriptors)?;
+      }
+    }
+    trace!("Final message builder: {:?}", message_builder);
+    (message_builder.encode_message()?, message_builder.generate_markup("")?)
+  };
 
   debug!("Constructing response to return");
-  trace!("Final message builder: {:?}", message_builder);
+  metrics::increment(Counter::MessagesBuilt);
   trace!("matching rules: {:?}", matching_rules);
   trace!("generators: {:?}", generators);
 
@@ -407,20 +853,28 @@ fn construct_protobuf_interaction_for_message(
     for (k, v) in &metadata.values {
       metadata_fields.insert(k.clone(), prost_string(&v.value));
     }
+    if !metadata.trailers.is_empty() {
+      let trailers = metadata.trailers.iter()
+        .map(|(k, v)| (k.clone(), prost_string(&v.value)))
+        .collect();
+      metadata_fields.insert(TRAILERS_KEY.to_string(), prost_types::Value {
+        kind: Some(Kind::StructValue(Struct { fields: trailers }))
+      });
+    }
   }
 
   Ok(InteractionResponse {
     contents: Some(Body {
       content_type: content_type.clone(),
-      content: Some(message_builder.encode_message()?.to_vec()),
-      content_type_hint: ContentTypeHint::Binary as i32,
+      content: Some(message_bytes.to_vec()),
+      content_type_hint: content_type_hint(config)? as i32,
     }),
     message_metadata: Some(Struct {
       fields: metadata_fields
     }),
     rules,
     generators,
-    interaction_markup: message_builder.generate_markup("")?,
+    interaction_markup: markup,
     interaction_markup_type: MarkupType::CommonMark as i32,
     part_name: message_part.to_string(),
     metadata_rules: metadata.map(|md| extract_rules(&md.matching_rules)).unwrap_or_default(),
@@ -429,6 +883,427 @@ fn construct_protobuf_interaction_for_message(
   })
 }
 
+/// Maps a message configuration written in the canonical Protobuf JSON form to the Protobuf field
+/// names. The mapped configuration is flagged with `canonicalJson`, so the values of bytes fields
+/// are Base64 decoded when the message is built.
+fn canonical_json_config(
+  config: &BTreeMap<String, prost_types::Value>,
+  descriptor: &DescriptorProto,
+  all_descriptors: &HashMap<String, &FileDescriptorProto>
+) -> BTreeMap<String, prost_types::Value> {
+  let mut mapped = map_json_names(config, descriptor, all_descriptors);
+  if !protobuf_config_flag(&mapped, "canonicalJson") {
+    mapped.insert("pact:protobuf-config".to_string(), prost_types::Value {
+      kind: Some(Kind::StructValue(Struct {
+        fields: btreemap!{
+          "canonicalJson".to_string() => prost_types::Value { kind: Some(Kind::BoolValue(true)) }
+        }
+      }))
+    });
+  }
+  mapped
+}
+
+/// Maps the keys of a message configuration written in the canonical Protobuf JSON form (i.e.
+/// `createdAt`) to the Protobuf field names (i.e. `created_at`) using the `json_name` of the fields.
+/// The configuration of embedded message fields is mapped recursively, and any keys that do not
+/// match the JSON name of a field are left as is.
+fn map_json_names(
+  config: &BTreeMap<String, prost_types::Value>,
+  descriptor: &DescriptorProto,
+  all_descriptors: &HashMap<String, &FileDescriptorProto>
+) -> BTreeMap<String, prost_types::Value> {
+  config.iter()
+    .map(|(key, value)| {
+      match descriptor.field.iter().find(|field| json_name(field) == *key) {
+        Some(field) => {
+          let embedded_type = if field.r#type() == Type::Message && !is_map_field(descriptor, field)
+            && !field.type_name().starts_with(".google.protobuf.") {
+            find_nested_type(descriptor, field)
+              .or_else(|| find_message_descriptor_for_type_in_map(field.type_name(), all_descriptors).ok().map(|(m, _)| m))
+          } else {
+            None
+          };
+          let value = match embedded_type {
+            Some(embedded_type) => map_json_names_in_value(value, &embedded_type, all_descriptors),
+            None => value.clone()
+          };
+          (field.name().to_string(), value)
+        }
+        None => (key.clone(), value.clone())
+      }
+    })
+    .collect()
+}
+
+/// Maps the keys of an embedded message configuration (or list of configurations) written in the
+/// canonical Protobuf JSON form to the Protobuf field names
+fn map_json_names_in_value(
+  value: &prost_types::Value,
+  descriptor: &DescriptorProto,
+  all_descriptors: &HashMap<String, &FileDescriptorProto>
+) -> prost_types::Value {
+  match &value.kind {
+    Some(Kind::StructValue(s)) => prost_types::Value {
+      kind: Some(Kind::StructValue(Struct { fields: map_json_names(&s.fields, descriptor, all_descriptors) }))
+    },
+    Some(Kind::ListValue(list)) => prost_types::Value {
+      kind: Some(Kind::ListValue(prost_types::ListValue {
+        values: list.values.iter()
+          .map(|value| map_json_names_in_value(value, descriptor, all_descriptors))
+          .collect()
+      }))
+    },
+    _ => value.clone()
+  }
+}
+
+/// Returns the JSON name of the field. If the descriptor does not have one, it is derived from the
+/// field name by converting it to lower camel case, as protoc does.
+fn json_name(field: &FieldDescriptorProto) -> String {
+  match &field.json_name {
+    Some(name) if !name.is_empty() => name.clone(),
+    _ => {
+      let mut json_name = String::new();
+      let mut capitalise_next = false;
+      for ch in field.name().chars() {
+        if ch == '_' {
+          capitalise_next = true;
+        } else if capitalise_next {
+          json_name.extend(ch.to_uppercase());
+          capitalise_next = false;
+        } else {
+          json_name.push(ch);
+        }
+      }
+      json_name
+    }
+  }
+}
+
+/// If the message has been configured with the already encoded message as a Base64 string,
+/// returns the encoded value. This is either the `pact:message` attribute, or the request or
+/// response has been configured with a string value (which is stored as `value`) and the message
+/// does not have a `value` field.
+fn encoded_message_config(
+  message_descriptor: &DescriptorProto,
+  config: &BTreeMap<String, prost_types::Value>
+) -> Option<String> {
+  if let Some(value) = config.get("pact:message") {
+    proto_value_to_string(value)
+  } else if config.len() == 1 && !message_descriptor.field.iter().any(|field| field.name() == "value") {
+    config.get("value").and_then(|value| match &value.kind {
+      Some(Kind::StringValue(s)) => Some(s.clone()),
+      _ => None
+    })
+  } else {
+    None
+  }
+}
+
+/// If the message has been configured with a top level `pact:match` that refers to a named example
+/// (i.e. `matching($'example')`), returns the configuration of the referenced example and adds the
+/// matching rules for the whole message. The message is matched by type against the example.
+fn referenced_message_config(
+  config: &BTreeMap<String, prost_types::Value>,
+  path: &DocPath,
+  matching_rules: &mut MatchingRuleCategory
+) -> anyhow::Result<Option<BTreeMap<String, prost_types::Value>>> {
+  if let Some(definition) = config.get("pact:match").and_then(proto_value_to_string) {
+    let mrd = parse_matcher_def(definition.as_str())?;
+    let (rules, references): (Vec<_>, Vec<_>) = mrd.rules.iter().cloned().partition_map(|rule| rule);
+    if let Some(reference) = references.first() {
+      match config.get(reference.name.as_str()).map(|value| &value.kind) {
+        Some(Some(Kind::StructValue(s))) => {
+          if rules.is_empty() {
+            matching_rules.add_rule(path.clone(), matchingrules::MatchingRule::Type, RuleLogic::And);
+          }
+          for rule in rules {
+            matching_rules.add_rule(path.clone(), rule, RuleLogic::And);
+          }
+          Ok(Some(s.fields.clone()))
+        }
+        Some(_) => Err(anyhow!("Expression '{}' must refer to an example object, but '{}' is not an object",
+          definition, reference.name)),
+        None => Err(anyhow!("Expression '{}' refers to non-existent item '{}'", definition, reference.name))
+      }
+    } else {
+      Ok(None)
+    }
+  } else {
+    Ok(None)
+  }
+}
+
+/// Returns the content type hint to use for the message body. This defaults to binary, but can be
+/// overridden with the `contentTypeHint` value in the `pact:protobuf-config` configuration.
+fn content_type_hint(config: &BTreeMap<String, prost_types::Value>) -> anyhow::Result<ContentTypeHint> {
+  let hint = config.get("pact:protobuf-config")
+    .and_then(|config| match &config.kind {
+      Some(Kind::StructValue(s)) => s.fields.get("contentTypeHint"),
+      _ => None
+    })
+    .and_then(proto_value_to_string);
+  match hint {
+    Some(hint) => match hint.to_lowercase().as_str() {
+      "binary" => Ok(ContentTypeHint::Binary),
+      "text" => Ok(ContentTypeHint::Text),
+      _ => Err(anyhow!("'{}' is not a valid content type hint, it must be either 'binary' or 'text'", hint))
+    }
+    None => Ok(ContentTypeHint::Binary)
+  }
+}
+
+/// Returns true if the boolean option is set in the `pact:protobuf-config` configuration. Supported
+/// options are:
+/// - `strictEnums`: enum values that are not defined in the enum descriptor will be treated as mismatches
+/// - `compressResponses`: the mock server will gzip compress responses if the client accepts it
+/// - `echoRequest`: the mock server will return the request message as the response message
+/// - `closedMessage`: fields set in the actual message that were not expected will be treated as mismatches
+/// - `canonicalJson`: the message configuration uses the JSON names of the fields (canonical Protobuf JSON form)
+/// - `strictMessageType`: the content type of the actual message must declare the expected message type, otherwise
+///   a different message type is only logged as a warning
+/// - `failFast`: when verifying, the comparison of the messages will stop at the first mismatch
+fn protobuf_config_flag(config: &BTreeMap<String, prost_types::Value>, name: &str) -> bool {
+  config.get("pact:protobuf-config")
+    .and_then(|config| match &config.kind {
+      Some(Kind::StructValue(s)) => s.fields.get(name),
+      _ => None
+    })
+    .map(|value| match &value.kind {
+      Some(Kind::BoolValue(b)) => *b,
+      Some(Kind::StringValue(s)) => s.to_lowercase() == "true",
+      _ => false
+    })
+    .unwrap_or_default()
+}
+
+/// Returns the `repeatedFieldKeys` value from the `pact:protobuf-config` configuration. This maps the
+/// path of a repeated message field (i.e. `$.areas`) to the name of the field in the message that
+/// identifies each element, so the elements can be matched by key instead of by position.
+fn repeated_field_keys(config: &BTreeMap<String, prost_types::Value>) -> Option<Value> {
+  config.get("pact:protobuf-config")
+    .and_then(|config| match &config.kind {
+      Some(Kind::StructValue(s)) => s.fields.get("repeatedFieldKeys"),
+      _ => None
+    })
+    .map(proto_value_to_json)
+    .filter(|value| value.is_object())
+}
+
+/// Returns the `repeatedFieldMatching` value from the `pact:protobuf-config` configuration. This maps
+/// the path of a repeated field (i.e. `$.items`) to how the values are matched when more values are
+/// received than were expected, and must be either `prefix` (the expected values must be the first
+/// values received, in order) or `subset` (each expected value must match a received value, in any order).
+fn repeated_field_matching(config: &BTreeMap<String, prost_types::Value>) -> anyhow::Result<Option<Value>> {
+  let modes = config.get("pact:protobuf-config")
+    .and_then(|config| match &config.kind {
+      Some(Kind::StructValue(s)) => s.fields.get("repeatedFieldMatching"),
+      _ => None
+    })
+    .map(proto_value_to_json);
+  match modes {
+    None | Some(Value::Null) => Ok(None),
+    Some(Value::Object(map)) => {
+      if let Some((path, mode)) = map.iter().find(|(_, mode)| !matches!(mode.as_str(), Some("prefix" | "subset"))) {
+        Err(anyhow!("'repeatedFieldMatching' for '{}' must be either 'prefix' or 'subset', got {}", path, mode))
+      } else {
+        Ok(Some(Value::Object(map)))
+      }
+    }
+    Some(value) => Err(anyhow!("'repeatedFieldMatching' must be a map of field paths to either 'prefix' or 'subset', got {}", value))
+  }
+}
+
+/// Returns the `timestampTolerance` value from the `pact:protobuf-config` configuration. This maps
+/// the path of a `google.protobuf.Timestamp` or RFC3339 string field (i.e. `$.created`) to the number
+/// of seconds the actual value may differ from the expected value.
+fn timestamp_tolerance(config: &BTreeMap<String, prost_types::Value>) -> Option<Value> {
+  config.get("pact:protobuf-config")
+    .and_then(|config| match &config.kind {
+      Some(Kind::StructValue(s)) => s.fields.get("timestampTolerance"),
+      _ => None
+    })
+    .map(proto_value_to_json)
+    .filter(|value| value.is_object())
+}
+
+/// Returns the bounds of any number range matchers (i.e. `range(1, 100)`) in the message
+/// configuration, keyed by the path of the field. There is no matching rule in the Pact
+/// specification for a range, so these are stored in the interaction configuration.
+fn number_ranges(message_config: &Value) -> Option<Value> {
+  let mut ranges = serde_json::Map::new();
+  collect_number_ranges(&DocPath::root(), message_config, &mut ranges);
+  if ranges.is_empty() {
+    None
+  } else {
+    Some(Value::Object(ranges))
+  }
+}
+
+fn collect_number_ranges(path: &DocPath, config: &Value, ranges: &mut serde_json::Map<String, Value>) {
+  match config {
+    Value::Object(fields) => for (key, value) in fields {
+      if key == "pact:match" {
+        // A matching expression applies to the field the object configures
+        if !path.is_root() {
+          collect_number_ranges(path, value, ranges);
+        }
+      } else if !key.starts_with("pact:") {
+        collect_number_ranges(&path.join(key), value, ranges);
+      }
+    }
+    // Multiple response messages use the same path for each message, while the values of a repeated
+    // field are stored at the path of their index. A single value for a repeated field applies to
+    // all its values, and is stored at the path of the field.
+    Value::Array(values) => for (index, value) in values.iter().enumerate() {
+      if path.is_root() {
+        collect_number_ranges(path, value, ranges);
+      } else {
+        collect_number_ranges(&path.join_index(index), value, ranges);
+      }
+    }
+    Value::String(s) => if let Some(Ok((range, _))) = number_range_value(s) {
+      ranges.insert(path.to_string(), range.to_json());
+    }
+    _ => {}
+  }
+}
+
+/// Returns the `ignoreFields` value from the `pact:protobuf-config` configuration. This is a list
+/// of field paths (i.e. `$.audit.created_at`, which can contain wildcards) that will be skipped when
+/// comparing messages.
+fn ignore_fields(config: &BTreeMap<String, prost_types::Value>) -> Option<Value> {
+  config.get("pact:protobuf-config")
+    .and_then(|config| match &config.kind {
+      Some(Kind::StructValue(s)) => s.fields.get("ignoreFields"),
+      _ => None
+    })
+    .map(proto_value_to_json)
+    .filter(|value| value.is_array())
+}
+
+/// Returns the `responseDelay` value from the `pact:protobuf-config` configuration. This is the
+/// number of milliseconds the mock server will wait before returning the response, and can be
+/// a number or a numeric string.
+fn response_delay(config: &BTreeMap<String, prost_types::Value>) -> anyhow::Result<Option<u64>> {
+  let delay = config.get("pact:protobuf-config")
+    .and_then(|config| match &config.kind {
+      Some(Kind::StructValue(s)) => s.fields.get("responseDelay"),
+      _ => None
+    });
+  match delay.and_then(|value| value.kind.as_ref()) {
+    None | Some(Kind::NullValue(_)) => Ok(None),
+    Some(Kind::NumberValue(n)) if *n >= 0.0 && n.fract() == 0.0 => Ok(Some(*n as u64)),
+    Some(Kind::StringValue(s)) if s.parse::<u64>().is_ok() => Ok(s.parse().ok()),
+    Some(_) => Err(anyhow!("'responseDelay' must be a positive number of milliseconds, got {:?}",
+      delay.map(proto_value_to_json).unwrap_or_default()))
+  }
+}
+
+/// Returns the `packedEncoding` value from the `pact:protobuf-config` configuration. This is the
+/// wire encoding the repeated scalar fields of the response message are expected to be received
+/// with when verifying the provider, and must be either `packed` or `unpacked`.
+fn packed_encoding(config: &BTreeMap<String, prost_types::Value>) -> anyhow::Result<Option<String>> {
+  let encoding = config.get("pact:protobuf-config")
+    .and_then(|config| match &config.kind {
+      Some(Kind::StructValue(s)) => s.fields.get("packedEncoding"),
+      _ => None
+    });
+  match encoding.and_then(|value| value.kind.as_ref()) {
+    None | Some(Kind::NullValue(_)) => Ok(None),
+    Some(Kind::StringValue(s)) if s == "packed" || s == "unpacked" => Ok(Some(s.clone())),
+    Some(_) => Err(anyhow!("'packedEncoding' must be either 'packed' or 'unpacked', got {:?}",
+      encoding.map(proto_value_to_json).unwrap_or_default()))
+  }
+}
+
+/// Returns the options from the `pact:protobuf-config` configuration as JSON, which is the same
+/// form they are stored in the interaction configuration
+fn protobuf_config(config: &BTreeMap<String, prost_types::Value>) -> HashMap<String, Value> {
+  match config.get("pact:protobuf-config").map(proto_value_to_json) {
+    Some(Value::Object(options)) => options.into_iter().collect(),
+    _ => HashMap::default()
+  }
+}
+
+/// Encodes a Protobuf message from a JSON test configuration. The configuration has the same form
+/// as the fields of a `pact:message-type` interaction configuration, and the message type must be
+/// defined in the file descriptors. Any matching rules and generators in the configuration are
+/// ignored, and the example values are used.
+pub fn encode_message_from_json(
+  message_type: &str,
+  config: &Value,
+  descriptors: &FileDescriptorSet
+) -> anyhow::Result<Bytes> {
+  let config = match config {
+    Value::Object(map) => to_proto_struct(&map.iter().map(|(k, v)| (k.clone(), v.clone())).collect()),
+    _ => return Err(anyhow!("Message configuration must be a JSON object, got {}", config))
+  };
+  let (message_descriptor, file_descriptor) = find_message_descriptor_for_type(message_type, descriptors)?;
+  let all_descriptors = fds_to_map(descriptors);
+  let interaction = construct_protobuf_interaction_for_message(&message_descriptor,
+    &config.fields, "", &file_descriptor, &all_descriptors, None)?;
+  interaction.contents
+    .and_then(|body| body.content)
+    .map(Bytes::from)
+    .ok_or_else(|| anyhow!("No message was built for message type {}", message_type))
+}
+
+/// Encodes a Protobuf message from a YAML test configuration. The YAML is converted to JSON, and
+/// the message built with `encode_message_from_json`.
+pub fn encode_message_from_yaml(
+  message_type: &str,
+  config: &str,
+  descriptors: &FileDescriptorSet
+) -> anyhow::Result<Bytes> {
+  let json: Value = serde_yaml_ng::from_str(config)
+    .map_err(|err| anyhow!("Failed to parse the YAML message configuration - {}", err))?;
+  encode_message_from_json(message_type, &json, descriptors)
+}
+
+/// Generates the interaction markup for a message that was decoded from the encoded bytes. The
+/// fields are rendered in field number order, regardless of the order they were encoded in.
+/// Message and enum fields include the name of their type, and map fields the key and value types.
+fn markup_for_decoded_message(
+  message_descriptor: &DescriptorProto,
+  fields: &[ProtobufField]
+) -> anyhow::Result<String> {
+  let mut buffer = String::new();
+  buffer.push_str(format!("```protobuf\nmessage {} {{\n", message_descriptor.name()).as_str());
+  for field in fields.iter().unique_by(|field| field.field_num).sorted_by_key(|field| field.field_num) {
+    let descriptor = &field.descriptor;
+    if is_map_field(message_descriptor, descriptor) {
+      let type_name = map_entry_type_names(message_descriptor, descriptor)?;
+      buffer.push_str(format!("    map<{}> {} = {};\n", type_name, field.field_name, field.field_num).as_str());
+    } else if is_repeated_field(descriptor) {
+      let type_name = proto_type_name(descriptor.r#type(), descriptor)?;
+      buffer.push_str(format!("    repeated {} {} = {};\n", type_name, field.field_name, field.field_num).as_str());
+    } else {
+      let type_name = proto_type_name(descriptor.r#type(), descriptor)?;
+      buffer.push_str(format!("    {} {} = {};\n", type_name, field.field_name, field.field_num).as_str());
+    }
+  }
+  buffer.push_str("}\n```\n");
+  Ok(buffer)
+}
+
+/// Returns the key and value types of a map field (i.e. `string, message .package.Value`), from the
+/// fields of the map entry descriptor
+fn map_entry_type_names(
+  message_descriptor: &DescriptorProto,
+  descriptor: &FieldDescriptorProto
+) -> anyhow::Result<String> {
+  let entry = find_nested_type(message_descriptor, descriptor)
+    .ok_or_else(|| anyhow!("Did not find the map entry descriptor for field '{}'", descriptor.name()))?;
+  let entry_field = |name: &str| entry.field.iter()
+    .find(|field| field.name() == name)
+    .ok_or_else(|| anyhow!("Map entry descriptor for field '{}' does not have a '{}' field", descriptor.name(), name));
+  let key = entry_field("key")?;
+  let value = entry_field("value")?;
+  Ok(format!("{}, {}", proto_type_name(key.r#type(), key)?, proto_type_name(value.r#type(), value)?))
+}
+
 fn extract_generators(generators: &HashMap<String, Generator>) -> HashMap<String, pact_plugin_driver::proto::Generator> {
   generators.iter().filter_map(|(path, generator)| {
     let gen_values = generator.values();
@@ -447,32 +1322,266 @@ fn extract_generators(generators: &HashMap<String, Generator>) -> HashMap<String
   }).collect()
 }
 
-fn extract_rules(matching_rules: &MatchingRuleCategory) -> HashMap<String, MatchingRules> {
-  matching_rules.rules.iter().map(|(path, rule_list)| {
-    (path.to_string(), MatchingRules {
-      rule: rule_list.rules.iter().map(|rule| {
-        let rule_values = rule.values();
-        let values = if rule_values.is_empty() {
-          None
-        } else {
-          Some(to_proto_struct(&rule_values.iter().map(|(k, v)| (k.to_string(), v.clone())).collect()))
-        };
-        MatchingRule {
-          r#type: rule.name(),
-          values
-        }
-      }).collect()
-    })
-  }).collect()
-}
+/// Key in the test configuration for matching rules defined using JSON pointers
+const MATCHING_RULES_KEY: &str = "matchingRules";
 
-/// Construct a single field for a message from the provided config
-#[tracing::instrument(ret,
-  skip_all,
-  fields(%path, %field_name, %value)
-)]
-fn construct_message_field(
-  message_builder: &mut MessageBuilder,
+/// Returns the matching rules configured with the `matchingRules` value in the test configuration.
+/// This is a map of JSON pointers (i.e. `/response/entity/details/name`) to matcher definitions.
+/// For gRPC interactions, the first part of the pointer must be either `request` or `response`,
+/// and only the rules for the given part will be returned.
+fn external_matching_rules(
+  config: &BTreeMap<String, prost_types::Value>,
+  part: Option<&str>
+) -> anyhow::Result<MatchingRuleCategory> {
+  let mut matching_rules = MatchingRuleCategory::empty("body");
+  let rules_config = match config.get(MATCHING_RULES_KEY).and_then(|value| value.kind.as_ref()) {
+    Some(Kind::StructValue(s)) => s,
+    Some(_) => return Err(anyhow!("'{}' must be a map of JSON pointers to matcher definitions", MATCHING_RULES_KEY)),
+    None => return Ok(matching_rules)
+  };
+
+  for (pointer, definition) in &rules_config.fields {
+    let segments = match pointer_segments(pointer, part, "Matching rule")? {
+      Some(segments) => segments,
+      None => continue
+    };
+    let path = segments.iter().fold(DocPath::root(), |path, segment| path.join(segment));
+
+    let definition = proto_value_to_string(definition)
+      .ok_or_else(|| anyhow!("The matcher definition for '{}' must be a string", pointer))?;
+    let mrd = parse_matcher_def(definition.as_str())
+      .map_err(|err| anyhow!("'{}' is not a valid matcher definition for '{}' - {}", definition, pointer, err))?;
+    for rule in &mrd.rules {
+      match rule {
+        Either::Left(rule) => matching_rules.add_rule(path.clone(), rule.clone(), RuleLogic::And),
+        Either::Right(reference) => return Err(anyhow!("Matcher definition for '{}' can not refer to a matching reference '{}'",
+          pointer, reference.name))
+      }
+    }
+  }
+
+  Ok(matching_rules)
+}
+
+/// Returns the segments of the JSON pointer. For gRPC interactions (where `part` is given), the first
+/// segment must be either `request` or `response`, and `None` is returned if it is not for the
+/// given part.
+fn pointer_segments(pointer: &str, part: Option<&str>, description: &str) -> anyhow::Result<Option<Vec<String>>> {
+  let mut segments = pointer.strip_prefix('/')
+    .ok_or_else(|| anyhow!("'{}' is not a valid JSON pointer, it must start with a '/'", pointer))?
+    .split('/')
+    .filter(|segment| !segment.is_empty())
+    .map(|segment| segment.replace("~1", "/").replace("~0", "~"));
+  if let Some(part) = part {
+    match segments.next() {
+      Some(segment) if segment == part => {}
+      Some(segment) if segment == "request" || segment == "response" => return Ok(None),
+      _ => return Err(anyhow!("{} path '{}' must start with either '/request' or '/response'", description, pointer))
+    }
+  }
+  Ok(Some(segments.collect()))
+}
+
+/// Key in the test configuration for generators defined using JSON pointers
+const GENERATORS_KEY: &str = "generators";
+
+/// Returns the generators configured with the `generators` value in the test configuration. This
+/// is a map of JSON pointers (i.e. `/response/id`) to generator definitions in the Pact JSON form
+/// (i.e. `{ "type": "RandomInt", "min": 1, "max": 100 }`). For gRPC interactions, only the
+/// generators for the given part will be returned.
+fn external_generators(
+  config: &BTreeMap<String, prost_types::Value>,
+  part: Option<&str>
+) -> anyhow::Result<HashMap<String, Generator>> {
+  let generators_config = match config.get(GENERATORS_KEY).and_then(|value| value.kind.as_ref()) {
+    Some(Kind::StructValue(s)) => s,
+    Some(_) => return Err(anyhow!("'{}' must be a map of JSON pointers to generator definitions", GENERATORS_KEY)),
+    None => return Ok(HashMap::new())
+  };
+
+  let mut generators = HashMap::new();
+  for (pointer, definition) in &generators_config.fields {
+    let segments = match pointer_segments(pointer, part, "Generator")? {
+      Some(segments) => segments,
+      None => continue
+    };
+    let path = segments.iter().fold(DocPath::root(), |path, segment| path.join(segment));
+
+    let definition = proto_value_to_json(definition);
+    let generator_type = definition.get("type")
+      .and_then(|value| value.as_str())
+      .ok_or_else(|| anyhow!("The generator definition for '{}' must be a map with a 'type' value", pointer))?;
+    let generator = Generator::create(generator_type, &definition)
+      .map_err(|err| anyhow!("'{}' is not a valid generator definition for '{}' - {}", definition, pointer, err))?;
+    generators.insert(path.to_string(), generator);
+  }
+
+  Ok(generators)
+}
+
+/// Adds the generators to the interaction. These replace any generators from the matcher
+/// definitions for the same fields, while the matching rules for the fields are kept, so the
+/// generated values are still verified with the matching rules.
+fn add_generators(interaction: InteractionResponse, generators: &HashMap<String, Generator>) -> InteractionResponse {
+  let mut interaction_generators = interaction.generators;
+  for (path, generator) in extract_generators(generators) {
+    if interaction_generators.insert(path.clone(), generator).is_some() {
+      debug!("Generator for '{}' replaces the generator from the matcher definition", path);
+    }
+  }
+  InteractionResponse { generators: interaction_generators, .. interaction }
+}
+
+/// Key in the test configuration for example values defined using JSON pointers
+const EXAMPLES_KEY: &str = "examples";
+
+/// Returns the message configuration with the example values configured with the `examples` value
+/// in the test configuration applied. This is a map of JSON pointers (i.e. `/response/total`) to
+/// the example values to encode in the message, which replace the configured field values.
+/// Returns `None` if there are no example values for the message.
+fn example_overrides(
+  config: &BTreeMap<String, prost_types::Value>,
+  part: Option<&str>,
+  message_config: &BTreeMap<String, prost_types::Value>
+) -> anyhow::Result<Option<BTreeMap<String, prost_types::Value>>> {
+  let examples_config = match config.get(EXAMPLES_KEY).and_then(|value| value.kind.as_ref()) {
+    Some(Kind::StructValue(s)) => s,
+    Some(_) => return Err(anyhow!("'{}' must be a map of JSON pointers to example values", EXAMPLES_KEY)),
+    None => return Ok(None)
+  };
+
+  let mut message = prost_types::Value {
+    kind: Some(Kind::StructValue(Struct { fields: message_config.clone() }))
+  };
+  let mut updated = false;
+  for (pointer, example) in &examples_config.fields {
+    if let Some(segments) = pointer_segments(pointer, part, "Example")? {
+      if segments.is_empty() || !set_config_value(&mut message, &segments, example) {
+        return Err(anyhow!("Example path '{}' does not refer to a field in the message configuration", pointer));
+      }
+      updated = true;
+    }
+  }
+
+  match message.kind {
+    Some(Kind::StructValue(s)) if updated => Ok(Some(s.fields)),
+    _ => Ok(None)
+  }
+}
+
+/// Sets the value in the configuration at the path given by the segments. Values can only be added
+/// to existing embedded message configurations.
+fn set_config_value(config: &mut prost_types::Value, segments: &[String], value: &prost_types::Value) -> bool {
+  match segments.split_first() {
+    None => {
+      *config = value.clone();
+      true
+    }
+    Some((segment, rest)) => match config.kind.as_mut() {
+      Some(Kind::StructValue(s)) => match s.fields.get_mut(segment) {
+        Some(field_config) => set_config_value(field_config, rest, value),
+        None if rest.is_empty() => {
+          s.fields.insert(segment.clone(), value.clone());
+          true
+        }
+        None => false
+      }
+      Some(Kind::ListValue(l)) => segment.parse::<usize>().ok()
+        .and_then(|index| l.values.get_mut(index))
+        .map(|item| set_config_value(item, rest, value))
+        .unwrap_or(false),
+      _ => false
+    }
+  }
+}
+
+/// Uses the message contents built with the example values for the interaction, keeping the matching
+/// rules and generators from the configured field values
+fn with_example_contents(interaction: InteractionResponse, examples: Option<InteractionResponse>) -> InteractionResponse {
+  match examples {
+    Some(examples) => InteractionResponse {
+      contents: examples.contents,
+      interaction_markup: examples.interaction_markup,
+      .. interaction
+    },
+    None => interaction
+  }
+}
+
+/// Adds the matching rules to any already set for the interaction
+fn add_matching_rules(interaction: InteractionResponse, matching_rules: &MatchingRuleCategory) -> InteractionResponse {
+  let mut rules = interaction.rules;
+  for (path, path_rules) in extract_rules(matching_rules) {
+    rules.entry(path).or_default().rule.extend(path_rules.rule);
+  }
+  InteractionResponse { rules, .. interaction }
+}
+
+fn extract_rules(matching_rules: &MatchingRuleCategory) -> HashMap<String, MatchingRules> {
+  matching_rules.rules.iter().map(|(path, rule_list)| {
+    (path.to_string(), MatchingRules {
+      rule: rule_list.rules.iter().map(|rule| {
+        let rule_values = rule.values();
+        let values = if rule_values.is_empty() {
+          None
+        } else {
+          Some(to_proto_struct(&rule_values.iter().map(|(k, v)| (k.to_string(), v.clone())).collect()))
+        };
+        MatchingRule {
+          r#type: rule.name(),
+          values
+        }
+      }).collect()
+    })
+  }).collect()
+}
+
+/// Returns the types of the matching rules (i.e. `type` or `regex`) configured for each field path
+/// of the built interaction, as they will be recorded in the Pact file. This can be used to enforce
+/// policies on how fields are matched (i.e. that sensitive fields are only matched by type).
+pub fn matching_rule_types(interaction: &InteractionResponse) -> BTreeMap<String, Vec<String>> {
+  interaction.rules.iter()
+    .map(|(path, rules)| (path.clone(), rules.rule.iter().map(|rule| rule.r#type.clone()).collect()))
+    .collect()
+}
+
+/// Default maximum depth that embedded message fields can be nested when building a message
+pub const DEFAULT_MAX_NESTING_DEPTH: usize = 32;
+
+static MAX_NESTING_DEPTH: AtomicUsize = AtomicUsize::new(DEFAULT_MAX_NESTING_DEPTH);
+
+/// Sets the maximum depth that embedded message fields can be nested when building a message
+pub fn set_max_nesting_depth(depth: usize) {
+  MAX_NESTING_DEPTH.store(depth, Ordering::Relaxed);
+}
+
+/// Returns the maximum depth that embedded message fields can be nested when building a message
+pub fn max_nesting_depth() -> usize {
+  MAX_NESTING_DEPTH.load(Ordering::Relaxed)
+}
+
+/// Checks the nesting depth of the embedded message field (the number of fields in the path)
+/// against the maximum nesting depth
+fn check_nesting_depth(path: &DocPath) -> anyhow::Result<()> {
+  let depth = path.tokens().iter()
+    .filter(|token| matches!(token, PathToken::Field(_)))
+    .count();
+  let max_depth = max_nesting_depth();
+  if depth > max_depth {
+    Err(anyhow!("Message field '{}' is nested {} levels deep, which exceeds the maximum nesting depth of {}",
+      path, depth, max_depth))
+  } else {
+    Ok(())
+  }
+}
+
+/// Construct a single field for a message from the provided config
+#[tracing::instrument(ret,
+  skip_all,
+  fields(%path, %field_name, %value)
+)]
+fn construct_message_field(
+  message_builder: &mut MessageBuilder,
   matching_rules: &mut MatchingRuleCategory,
   generators: &mut HashMap<String, Generator>,
   field_name: &str,
@@ -483,6 +1592,11 @@ fn construct_message_field(
   if !field_name.starts_with("pact:") {
     if let Some(field) = message_builder.field_by_name(field_name)  {
       trace!(?field_name, descriptor = ?field, "Found a descriptor for field");
+      if is_absent_value(value) {
+        trace!(?field_name, "Field is expected to be absent");
+        matching_rules.add_rule(path.clone(), matchingrules::MatchingRule::Null, RuleLogic::And);
+        return Ok(());
+      }
       match field.r#type {
         Some(r#type) => if r#type == Type::Message as i32 {
           // Embedded message
@@ -678,17 +1792,31 @@ fn build_single_embedded_field_value(
       debug!("Field is a Protobuf Struct");
       build_struct_field(path, message_builder, field_type, field_descriptor, field, value, matching_rules, generators)
     }
+    ".google.protobuf.FieldMask" if !value.is_object() => {
+      debug!("Field is a Protobuf FieldMask");
+      build_field_mask_field(message_builder, field_type, field_descriptor, field, value, all_descriptors)
+    }
+    ".google.protobuf.Duration" if value.is_string() => {
+      debug!("Field is a Protobuf Duration");
+      build_duration_field(path, message_builder, field_type, field_descriptor, field, value, matching_rules,
+        generators, all_descriptors)
+    }
+    wrapper_type if is_wrapper_type(wrapper_type) && !value.is_object() => {
+      debug!("Field is a Protobuf wrapper type, configuring the value field of the wrapper");
+      build_single_embedded_field_value(path, message_builder, field_type, field_descriptor, field,
+        &json!({ "value": value }), matching_rules, generators, all_descriptors)
+    }
     _ => if is_map_field(&message_builder.descriptor, field_descriptor) {
       debug!("Message field '{}' is a Map field", field);
       build_map_field(path, message_builder, field_descriptor, field, value, matching_rules, generators, all_descriptors)?;
       Ok(None)
     } else if let Value::Object(config) = value {
       debug!("Configuring the message from config {:?}", config);
-      let embedded_type = find_nested_type(&message_builder.descriptor, field_descriptor)
-        .or_else(|| find_message_descriptor_for_type_in_map(type_name.as_str(), all_descriptors).ok().map(|(m, _)| m))
-        .ok_or_else(|| anyhow!("Did not find message '{}' in the current message or in the file descriptors", type_name))?;
+      check_nesting_depth(path)?;
+      let embedded_type = embedded_message_type(message_builder, field_descriptor, all_descriptors)?;
       let mut embedded_builder = MessageBuilder::new(
         &embedded_type, last_name(type_name.as_str()), &message_builder.file_descriptor);
+      embedded_builder.canonical_json = message_builder.canonical_json;
 
       let field_value = if let Some(definition) = config.get("pact:match") {
         let mut field_value = None;
@@ -737,6 +1865,20 @@ fn build_single_embedded_field_value(
         }
       };
 
+      message_builder.set_field_value(field_descriptor, field, field_value.clone());
+      Ok(Some(field_value))
+    } else if let Some(rules) = embedded_message_placeholder(value)? {
+      debug!("Configuring message field '{}' from a placeholder, the message contents will not be constrained", field);
+      for rule in rules {
+        matching_rules.add_rule(path.clone(), rule, RuleLogic::And);
+      }
+      let embedded_type = embedded_message_type(message_builder, field_descriptor, all_descriptors)?;
+      let field_value = MessageFieldValue {
+        name: field.to_string(),
+        raw_value: None,
+        rtype: RType::Message(Box::new(MessageBuilder::new(
+          &embedded_type, last_name(type_name.as_str()), &message_builder.file_descriptor)))
+      };
       message_builder.set_field_value(field_descriptor, field, field_value.clone());
       Ok(Some(field_value))
     } else {
@@ -745,6 +1887,139 @@ fn build_single_embedded_field_value(
   }
 }
 
+/// If the type is one of the well-known wrapper types (i.e. `google.protobuf.StringValue`), which
+/// are messages with a single `value` field. `google.protobuf.BytesValue` is not included, as it
+/// is configured as a bytes field.
+fn is_wrapper_type(type_name: &str) -> bool {
+  matches!(type_name, ".google.protobuf.DoubleValue" | ".google.protobuf.FloatValue" |
+    ".google.protobuf.Int64Value" | ".google.protobuf.UInt64Value" | ".google.protobuf.Int32Value" |
+    ".google.protobuf.UInt32Value" | ".google.protobuf.BoolValue" | ".google.protobuf.StringValue")
+}
+
+/// Looks up the descriptor for the message type of an embedded message field, first in the nested
+/// types of the current message and then in all the file descriptors
+fn embedded_message_type(
+  message_builder: &MessageBuilder,
+  field_descriptor: &FieldDescriptorProto,
+  all_descriptors: &HashMap<String, &FileDescriptorProto>
+) -> anyhow::Result<DescriptorProto> {
+  let type_name = field_descriptor.type_name();
+  find_nested_type(&message_builder.descriptor, field_descriptor)
+    .or_else(|| find_message_descriptor_for_type_in_map(type_name, all_descriptors).ok().map(|(m, _)| m))
+    .ok_or_else(|| anyhow!("Did not find message '{}' in the current message or in the file descriptors", type_name))
+}
+
+/// If the value for an embedded message field is a matcher definition placeholder (i.e.
+/// `matching(type, '')` or `notEmpty('')`), returns the matching rules to apply to the field. The
+/// value in the definition is ignored, and the contents of the message are not constrained.
+fn embedded_message_placeholder(value: &Value) -> anyhow::Result<Option<Vec<matchingrules::MatchingRule>>> {
+  match value {
+    Value::String(s) if is_matcher_def(s) => {
+      let mrd = parse_matcher_def(s)?;
+      mrd.rules.iter()
+        .map(|rule| match rule {
+          Either::Left(rule @ (matchingrules::MatchingRule::Type | matchingrules::MatchingRule::NotEmpty)) => Ok(rule.clone()),
+          _ => Err(anyhow!("Only type or notEmpty matchers can be used as a placeholder for a message field, got '{}'", s))
+        })
+        .collect::<anyhow::Result<Vec<_>>>()
+        .map(Some)
+    }
+    _ => Ok(None)
+  }
+}
+
+/// Create a field value of type google.protobuf.Duration from an ISO-8601 duration string (i.e.
+/// `PT1.5S`), which can also be a matcher definition (i.e. `matching(type, 'PT1.5S')`). Any matching
+/// rules are applied to the ISO-8601 form of the actual duration when verifying.
+#[allow(clippy::too_many_arguments)]
+fn build_duration_field(
+  path: &DocPath,
+  message_builder: &mut MessageBuilder,
+  field_type: MessageFieldValueType,
+  field_descriptor: &FieldDescriptorProto,
+  field: &str,
+  value: &Value,
+  matching_rules: &mut MatchingRuleCategory,
+  generators: &mut HashMap<String, Generator>,
+  all_descriptors: &HashMap<String, &FileDescriptorProto>
+) -> anyhow::Result<Option<MessageFieldValue>> {
+  let value = json_to_string(value);
+  let example = if is_matcher_def(value.as_str()) {
+    let mrd = parse_matcher_def(value.as_str())?;
+    for rule in &mrd.rules {
+      match rule {
+        Either::Left(rule) => matching_rules.add_rule(path.clone(), rule.clone(), RuleLogic::And),
+        Either::Right(mr) => return Err(anyhow!("Was expecting a value for '{}', but got a matching reference {:?}", path, mr))
+      }
+    }
+    if mrd.generator.is_some() {
+      warn!("Generators are not supported for google.protobuf.Duration fields, ignoring the generator for '{}'", path);
+    }
+    mrd.value
+  } else {
+    value
+  };
+
+  let (seconds, nanos) = if example.is_empty() {
+    (0, 0)
+  } else {
+    parse_iso8601_duration(example.as_str())
+      .map_err(|err| anyhow!("Field '{}' must be configured with an ISO-8601 duration - {}", field, err))?
+  };
+  let mut config = serde_json::Map::new();
+  if seconds != 0 {
+    config.insert("seconds".to_string(), json!(seconds));
+  }
+  if nanos != 0 {
+    config.insert("nanos".to_string(), json!(nanos));
+  }
+  build_single_embedded_field_value(path, message_builder, field_type, field_descriptor, field,
+    &Value::Object(config), matching_rules, generators, all_descriptors)
+}
+
+/// Create a field value of type google.protobuf.FieldMask from either a comma-separated string
+/// or a list of paths
+fn build_field_mask_field(
+  message_builder: &mut MessageBuilder,
+  field_type: MessageFieldValueType,
+  field_descriptor: &FieldDescriptorProto,
+  field_name: &str,
+  field_value: &Value,
+  all_descriptors: &HashMap<String, &FileDescriptorProto>
+) -> anyhow::Result<Option<MessageFieldValue>> {
+  let paths = match field_value {
+    Value::String(s) => s.split(',')
+      .map(|path| path.trim())
+      .filter(|path| !path.is_empty())
+      .map(|path| path.to_string())
+      .collect::<Vec<_>>(),
+    Value::Array(list) => list.iter()
+      .map(|path| match path {
+        Value::String(s) => Ok(s.trim().to_string()),
+        _ => Err(anyhow!("google.protobuf.FieldMask paths must be strings, got {:?}", path))
+      })
+      .collect::<anyhow::Result<Vec<_>>>()?,
+    _ => return Err(anyhow!("google.protobuf.FieldMask fields need to be configured with a string or a list of paths, got {:?}", field_value))
+  };
+
+  let (field_mask_descriptor, _) = find_message_descriptor_for_type_in_map(".google.protobuf.FieldMask", all_descriptors)?;
+  let paths_descriptor = field_mask_descriptor.field.iter()
+    .find(|field| field.name() == "paths")
+    .ok_or_else(|| anyhow!("Did not find the paths field in the google.protobuf.FieldMask descriptor"))?;
+  let mut embedded_builder = MessageBuilder::new(&field_mask_descriptor, "FieldMask", &message_builder.file_descriptor);
+  for path in paths {
+    embedded_builder.add_repeated_field_value(paths_descriptor, "paths", MessageFieldValue::string("paths", path.as_str()));
+  }
+
+  let constructed_value = MessageFieldValue {
+    name: field_name.to_string(),
+    raw_value: None,
+    rtype: RType::Message(Box::new(embedded_builder))
+  };
+  update_message_builder(message_builder, field_type, field_descriptor, field_name, &constructed_value);
+  Ok(Some(constructed_value))
+}
+
 /// Create a field value of type google.protobuf.Struct
 fn build_struct_field(
   path: &DocPath,
@@ -878,6 +2153,8 @@ fn build_map_field(
   trace!("build_map_field: field_type = {}", field_type);
 
   if let Value::Object(config) = value {
+    let mut each_value = false;
+    let mut each_key = false;
     if let Some(definition) = config.get("pact:match") {
       debug!("Parsing matching rule definition {:?}", definition);
       let definition = json_to_string(definition);
@@ -887,6 +2164,11 @@ fn build_map_field(
         for rule in &mrd.rules {
           match rule {
             Either::Left(rule) => {
+              match rule {
+                matchingrules::MatchingRule::EachValue(_) => each_value = true,
+                matchingrules::MatchingRule::EachKey(_) => each_key = true,
+                _ => {}
+              }
               matching_rules.add_rule(path.clone(), rule.clone(), RuleLogic::And)
             },
             Either::Right(mr) => {
@@ -912,6 +2194,7 @@ fn build_map_field(
       trace!("Map field value descriptor = {:?}", value_descriptor);
 
       let mut embedded_builder = MessageBuilder::new(&map_type, message_name.as_str(), &message_builder.file_descriptor);
+      embedded_builder.canonical_json = message_builder.canonical_json;
       for (inner_field, value) in config {
         if inner_field != "pact:match" {
           let entry_path = path.join(inner_field);
@@ -922,7 +2205,17 @@ fn build_map_field(
           )?
             .ok_or_else(|| anyhow!("Was not able to construct map key value {:?}", key_descriptor.type_name))?;
 
-          let value_value = if value_descriptor.r#type() == Type::Message {
+          let value_value = if value_descriptor.r#type() == Type::Message && (each_value || each_key) {
+            // Embedded message. With an eachValue or eachKey matcher, the matchers for the fields
+            // of the message apply to all the values of the map, so are only added once
+            trace!("Value is an embedded message type");
+            let mut value_rules = MatchingRuleCategory::empty(matching_rules.name.clone());
+            let value_value = build_single_embedded_field_value(&path.join("*"), &mut embedded_builder,
+              MessageFieldValueType::Normal, value_descriptor, "value", value, &mut value_rules, generators,
+              all_descriptors)?;
+            add_new_rules(matching_rules, value_rules);
+            value_value
+          } else if value_descriptor.r#type() == Type::Message {
             // Embedded message
             trace!("Value is an embedded message type");
             build_single_embedded_field_value(&entry_path, &mut embedded_builder, MessageFieldValueType::Normal,
@@ -947,6 +2240,20 @@ fn build_map_field(
   }
 }
 
+/// Adds the rules to the matching rules, skipping any rule that has already been added for the path
+fn add_new_rules(matching_rules: &mut MatchingRuleCategory, rules: MatchingRuleCategory) {
+  for (path, rule_list) in rules.rules {
+    for rule in rule_list.rules {
+      let exists = matching_rules.rules.get(&path)
+        .map(|existing| existing.rules.contains(&rule))
+        .unwrap_or(false);
+      if !exists {
+        matching_rules.add_rule(path.clone(), rule, rule_list.rule_logic);
+      }
+    }
+  }
+}
+
 /// Constructs a simple message field (non-repeated or map) from the configuration value and
 /// updates the matching rules and generators for it.
 #[tracing::instrument(ret,
@@ -970,8 +2277,17 @@ fn build_field_value(
     Value::Null => Ok(None),
     Value::String(s) => {
       let constructed_value = match field_type {
-        MessageFieldValueType::Repeated => {
-          let path = path.join("*");
+        MessageFieldValueType::Repeated => if let Some(sequence) = sequence_values(s) {
+          return construct_sequence_values(path, message_builder, descriptor, field_name,
+            matching_rules, sequence?, all_descriptors);
+        } else {
+          // Values from a list are already at their index path, otherwise the matcher applies to
+          // all the values of the repeated field
+          let path = if matches!(path.tokens().last(), Some(PathToken::Index(_))) {
+            path.clone()
+          } else {
+            path.join("*")
+          };
           let constructed_value = construct_value_from_string(&path, message_builder,
             descriptor, field_name, matching_rules, generators, s, all_descriptors)?;
           debug!("Setting field {:?}:repeated to value {:?}", field_name, constructed_value);
@@ -988,6 +2304,17 @@ fn build_field_value(
       };
       Ok(Some(constructed_value))
     }
+    Value::Array(list) if descriptor.r#type() == Type::Bytes && is_repeated_field(descriptor) => {
+      // Each value of a repeated bytes field is a Base64 encoded string
+      let mut constructed_value = None;
+      for item in list {
+        let value = repeated_bytes_value(field_name, item)?;
+        message_builder.add_repeated_field_value(descriptor, field_name, value.clone());
+        constructed_value.get_or_insert(value);
+      }
+      trace!(?message_builder, "Constructed repeated bytes field from array");
+      Ok(constructed_value)
+    }
     Value::Array(list) => {
       if let Some((first, rest)) = list.split_first() {
         let index_path = path.join("0");
@@ -1022,25 +2349,53 @@ fn build_field_value(
     }
     Value::Number(n) => if n.is_u64() {
       let f = n.as_u64().unwrap_or_default();
-      construct_numeric_value(message_builder, field_type, descriptor, field_name, value, f)
+      construct_numeric_value(message_builder, field_type, descriptor, field_name, value, f, all_descriptors)
     } else if n.is_i64() {
       let f = n.as_i64().unwrap_or_default();
-      construct_numeric_value(message_builder, field_type, descriptor, field_name, value, f)
+      construct_numeric_value(message_builder, field_type, descriptor, field_name, value, f, all_descriptors)
     } else {
       let f = n.as_f64().unwrap_or_default();
-      construct_numeric_value(message_builder, field_type, descriptor, field_name, value, f)
+      construct_numeric_value(message_builder, field_type, descriptor, field_name, value, f, all_descriptors)
+    }
+    Value::Object(config) if config.contains_key("pact:match") => {
+      // A scalar field can also be configured with a matching expression, i.e. {"pact:match": "range(1, 10)"}
+      if let Some(extra) = config.keys().find(|key| *key != "pact:match") {
+        return Err(anyhow!("Field {} can only be configured with a 'pact:match' expression, got '{}'", field_name, extra));
+      }
+      match config.get("pact:match") {
+        Some(definition @ Value::String(_)) => build_field_value(path, message_builder, field_type,
+          descriptor, field_name, definition, matching_rules, generators, all_descriptors),
+        _ => Err(anyhow!("The 'pact:match' expression for field {} must be a string, got {:?}", field_name, value))
+      }
     }
     _ => Err(anyhow!("Field values must be configured with a string value, got {:?}", value))
   }
 }
 
+/// Constructs a value for an element of a repeated bytes field from a Base64 encoded string
+fn repeated_bytes_value(field_name: &str, value: &Value) -> anyhow::Result<MessageFieldValue> {
+  match value {
+    Value::String(s) => BASE64.decode(s.trim())
+      .map(|bytes| MessageFieldValue {
+        name: field_name.to_string(),
+        raw_value: Some(s.clone()),
+        rtype: RType::Bytes(bytes)
+      })
+      .map_err(|err| anyhow!("Values for repeated bytes field '{}' must be Base64 encoded strings, '{}' is not valid - {}",
+        field_name, s, err)),
+    _ => Err(anyhow!("Values for repeated bytes field '{}' must be Base64 encoded strings, got {:?}",
+      field_name, value))
+  }
+}
+
 fn construct_numeric_value<N: ToPrimitive>(
   message_builder: &mut MessageBuilder,
   field_type: MessageFieldValueType,
   descriptor: &FieldDescriptorProto,
   field_name: &str,
   value: &Value,
-  f: N
+  f: N,
+  all_descriptors: &HashMap<String, &FileDescriptorProto>
 ) -> anyhow::Result<Option<MessageFieldValue>> {
   match descriptor.r#type() {
     Type::Double => if let Some(f) = f.to_f64() {
@@ -1115,6 +2470,15 @@ fn construct_numeric_value<N: ToPrimitive>(
       Err(anyhow!("Can not construct an unsigned integer value from the given value, field {} type is {:?} but value is {:?}",
         field_name, descriptor.r#type(), value))
     },
+    Type::Enum => if let Some(i) = f.to_i32() {
+      let constructed_value = enum_value_for_number(field_name, i, descriptor, &message_builder.descriptor,
+        all_descriptors)?;
+      update_message_builder(message_builder, field_type, descriptor, field_name, &constructed_value);
+      Ok(Some(constructed_value))
+    } else {
+      Err(anyhow!("Can not construct an enum value from the given value, field {} type is {:?} but value is {:?}",
+        field_name, descriptor.r#type(), value))
+    },
     _ => {
       Err(anyhow!("Only numeric field values can be configured with a numeric value, field {} type is {:?} but value is {:?}",
         field_name, descriptor.r#type(), value))
@@ -1141,6 +2505,31 @@ fn update_message_builder(
   }
 }
 
+/// Constructs the values for a repeated field from the values of a sequence expression
+/// (i.e. `sequence(3, 0, 1)`). Each element will be matched by type.
+fn construct_sequence_values(
+  path: &DocPath,
+  message_builder: &mut MessageBuilder,
+  descriptor: &FieldDescriptorProto,
+  field_name: &str,
+  matching_rules: &mut MatchingRuleCategory,
+  sequence: Vec<i64>,
+  all_descriptors: &HashMap<String, &FileDescriptorProto>
+) -> anyhow::Result<Option<MessageFieldValue>> {
+  trace!(?field_name, ?sequence, "Building repeated field values from a sequence");
+  let mut first_value = None;
+  for value in sequence {
+    let constructed_value = value_for_type(field_name, value.to_string().as_str(), descriptor,
+      &message_builder.descriptor, all_descriptors)?;
+    message_builder.add_repeated_field_value(descriptor, field_name, constructed_value.clone());
+    if first_value.is_none() {
+      first_value = Some(constructed_value);
+    }
+  }
+  matching_rules.add_rule(path.join("*"), matchingrules::MatchingRule::Type, RuleLogic::And);
+  Ok(first_value)
+}
+
 fn construct_value_from_string(
   path: &DocPath,
   message_builder: &mut MessageBuilder,
@@ -1152,7 +2541,42 @@ fn construct_value_from_string(
   all_descriptors: &HashMap<String, &FileDescriptorProto>
 ) -> anyhow::Result<MessageFieldValue> {
   trace!(?field_name, string = ?s, "Building value from string");
-  if is_matcher_def(s) {
+  if let Some(value) = equals_ignore_case_value(s) {
+    trace!("String value is a case-insensitive equality matcher");
+    if descriptor.r#type() == Type::String {
+      matching_rules.add_rule(path.clone(), equals_ignore_case_rule(value), RuleLogic::And);
+      value_for_type(field_name, value, descriptor, &message_builder.descriptor, all_descriptors)
+    } else {
+      Err(anyhow!("equalsIgnoreCase can only be used with string fields, field {} type is {:?}",
+        field_name, descriptor.r#type()))
+    }
+  } else if let Some(values) = any_of_values(s) {
+    trace!(?values, "String value is an allowed values matcher");
+    let values = values?;
+    if matches!(descriptor.r#type(), Type::String | Type::Enum) {
+      if descriptor.r#type() == Type::Enum {
+        // The allowed values must be a subset of the values defined in the enum
+        for value in values.iter().skip(1) {
+          value_for_type(field_name, value, descriptor, &message_builder.descriptor, all_descriptors)?;
+        }
+      }
+      matching_rules.add_rule(path.clone(), any_of_rule(&values), RuleLogic::And);
+      value_for_type(field_name, values[0].as_str(), descriptor, &message_builder.descriptor, all_descriptors)
+    } else {
+      Err(anyhow!("anyOf can only be used with string or enum fields, field {} type is {:?}",
+        field_name, descriptor.r#type()))
+    }
+  } else if let Some(range) = number_range_value(s) {
+    trace!(?range, "String value is a number range matcher");
+    let (_, example) = range?;
+    if is_numeric_field(descriptor) {
+      matching_rules.add_rule(path.clone(), matchingrules::MatchingRule::Number, RuleLogic::And);
+      value_for_type(field_name, example.as_str(), descriptor, &message_builder.descriptor, all_descriptors)
+    } else {
+      Err(anyhow!("range can only be used with numeric fields, field {} type is {:?}",
+        field_name, descriptor.r#type()))
+    }
+  } else if is_matcher_def(s) || is_length_matcher_def(s) {
     trace!("String value is a matcher definition");
     let mrd = parse_matcher_def(s)?;
     trace!("matcher definition = {:?}", mrd);
@@ -1160,7 +2584,7 @@ fn construct_value_from_string(
       for rule in &mrd.rules {
         match rule {
           Either::Left(rule) => {
-            let path = if rule.is_values_matcher() && path.is_wildcard() {
+            let path = if (rule.is_values_matcher() || is_length_matcher(rule)) && path.is_wildcard() {
               // TODO: replace this with "path.parent().unwrap_or(DocPath::root())" when pact_models
               // 1.1.6 is released
               parent(path).unwrap_or(DocPath::root())
@@ -1177,73 +2601,304 @@ fn construct_value_from_string(
     if let Some(generator) = &mrd.generator {
       generators.insert(path.to_string(), generator.clone());
     }
-    value_for_type(field_name, &value_for_field(&mrd), descriptor, &message_builder.descriptor,
-                   all_descriptors)
+    match protobuf_content_type(&mrd) {
+      Some(message_type) if descriptor.r#type() == Type::Bytes =>
+        encoded_message_bytes(field_name, &value_for_field(&mrd), message_type.as_str(), all_descriptors),
+      _ => field_value_from_string(field_name, &value_for_field(&mrd), descriptor, message_builder,
+                                   all_descriptors)
+    }
   } else {
-    value_for_type(field_name, s, descriptor, &message_builder.descriptor,
-      all_descriptors)
+    field_value_from_string(field_name, s, descriptor, message_builder, all_descriptors)
   }
 }
 
-fn parent(path: &DocPath) -> Option<DocPath> {
-  let tokens = path.tokens().clone();
-  if path.is_root() || tokens.len() <= 1 {
-    None
+/// Constructs the value for the field from the string. If the message is configured using the
+/// canonical Protobuf JSON form, the values of bytes fields are Base64 encoded.
+fn field_value_from_string(
+  field_name: &str,
+  s: &str,
+  descriptor: &FieldDescriptorProto,
+  message_builder: &MessageBuilder,
+  all_descriptors: &HashMap<String, &FileDescriptorProto>
+) -> anyhow::Result<MessageFieldValue> {
+  if message_builder.canonical_json && descriptor.r#type() == Type::Bytes {
+    BASE64.decode(s.trim())
+      .map(|bytes| MessageFieldValue {
+        name: field_name.to_string(),
+        raw_value: Some(s.to_string()),
+        rtype: RType::Bytes(bytes)
+      })
+      .map_err(|err| anyhow!("The value for bytes field '{}' must be Base64 encoded when using canonical JSON, '{}' is not valid - {}",
+        field_name, s, err))
   } else {
-    let mut path = DocPath::root();
-    let tokens = tokens.split_last().unwrap().1;
-    for part in tokens.iter().skip(1) {
-      path = path.join(part.to_string());
-    }
-    Some(path)
+    value_for_type(field_name, s, descriptor, &message_builder.descriptor, all_descriptors)
   }
 }
 
-fn value_for_field(mrd: &MatchingRuleDefinition) -> String {
-  if mrd.value.is_empty() {
-    if let Some(value_matcher) = mrd.rules.iter().find_map(|m| {
-      match m {
-        Either::Left(mr) => match mr {
-          matchingrules::MatchingRule::EachValue(def) => Some(def.value.clone()),
-          _ => None
-        }
-        Either::Right(_) => None
-      }
-    }) {
-      value_matcher
-    } else {
-      String::default()
-    }
-  } else {
-    mrd.value.clone()
-  }
+/// If the matcher definition has a content type matcher for an encoded Protobuf message (i.e.
+/// `matching(contentType, 'application/protobuf;message=.pkg.Type', '<base64>')`), returns the
+/// type of the message
+fn protobuf_content_type(mrd: &MatchingRuleDefinition) -> Option<String> {
+  mrd.rules.iter().find_map(|rule| match rule {
+    Either::Left(matchingrules::MatchingRule::ContentType(content_type)) => ContentType::parse(content_type.as_str()).ok()
+      .and_then(|content_type| protobuf_message_type(&content_type).cloned()),
+    _ => None
+  })
 }
 
-fn value_for_type(
+/// Constructs the value for a bytes field that contains an encoded Protobuf message. The value must
+/// be the Base64 encoded message, and it must be able to be decoded as the given message type.
+fn encoded_message_bytes(
   field_name: &str,
-  field_value: &str,
-  descriptor: &FieldDescriptorProto,
-  message_descriptor: &DescriptorProto,
+  value: &str,
+  message_type: &str,
   all_descriptors: &HashMap<String, &FileDescriptorProto>
 ) -> anyhow::Result<MessageFieldValue> {
-  trace!("value_for_type({}, {}, _)", field_name, field_value);
+  let bytes = BASE64.decode(value.trim())
+    .map_err(|err| anyhow!("The example value for bytes field '{}' must be a Base64 encoded {} message - {}",
+      field_name, message_type, err))?;
+  let (message_descriptor, _) = find_message_descriptor_for_type_in_map(message_type, all_descriptors)?;
+  let fds = FileDescriptorSet { file: fds_map_to_vec(all_descriptors) };
+  decode_message(&mut Bytes::from(bytes.clone()), &message_descriptor, &fds)
+    .map_err(|err| anyhow!("The example value for bytes field '{}' is not a valid {} message - {}",
+      field_name, message_type, err))?;
+  Ok(MessageFieldValue {
+    name: field_name.to_string(),
+    raw_value: Some(value.to_string()),
+    rtype: RType::Bytes(bytes)
+  })
+}
 
-  let type_name = descriptor.type_name.clone().unwrap_or_default();
-  debug!("Creating value for type {:?} from '{}'", type_name, field_value);
+/// If the string is a matcher definition that starts with a length matcher (i.e.
+/// `atLeast(1), eachValue(...)`), which is not detected by `is_matcher_def`
+fn is_length_matcher_def(s: &str) -> bool {
+  let s = s.trim_start();
+  s.starts_with("atLeast(") || s.starts_with("atMost(")
+}
 
-  let t = descriptor.r#type();
-  match t {
-    Type::Double => MessageFieldValue::double(field_name, field_value),
-    Type::Float => MessageFieldValue::float(field_name, field_value),
-    Type::Int64 | Type::Sfixed64 | Type::Sint64 => MessageFieldValue::integer_64(field_name, field_value),
-    Type::Uint64 | Type::Fixed64 => MessageFieldValue::uinteger_64(field_name, field_value),
-    Type::Int32 | Type::Sfixed32 | Type::Sint32 => MessageFieldValue::integer_32(field_name, field_value),
-    Type::Uint32 | Type::Fixed32 => MessageFieldValue::uinteger_32(field_name, field_value),
-    Type::Bool => MessageFieldValue::boolean(field_name, field_value),
-    Type::String => Ok(MessageFieldValue::string(field_name, field_value)),
-    Type::Message => {
-      if type_name == ".google.protobuf.BytesValue" {
-        Ok(MessageFieldValue::bytes(field_name, field_value))
+/// If the matching rule is for the length of a repeated field (i.e. `atLeast(2)` or `atMost(5)`),
+/// which needs to be applied to the field and not the values
+fn is_length_matcher(rule: &matchingrules::MatchingRule) -> bool {
+  matches!(rule, matchingrules::MatchingRule::MinType(_) | matchingrules::MatchingRule::MaxType(_) |
+    matchingrules::MatchingRule::MinMaxType(_, _))
+}
+
+/// If the field configuration is `absent()`, which is used to indicate that the field must not
+/// be set in the message
+fn is_absent_value(value: &Value) -> bool {
+  match value {
+    Value::String(s) => s.trim() == "absent()",
+    _ => false
+  }
+}
+
+/// Returns the example value if the string is a case-insensitive equality matcher definition
+/// (i.e. `equalsIgnoreCase('value')`)
+fn equals_ignore_case_value(s: &str) -> Option<&str> {
+  s.trim()
+    .strip_prefix("equalsIgnoreCase(")
+    .and_then(|s| s.strip_suffix(')'))
+    .map(|s| s.trim())
+    .and_then(|s| s.strip_prefix('\'').and_then(|s| s.strip_suffix('\'')))
+}
+
+/// Returns the allowed values if the string is an allowed values matcher definition (i.e.
+/// `anyOf('PENDING', 'ACTIVE')` or `oneOf('PENDING', 'ACTIVE')`). The values must be quoted with
+/// single quotes, and at least one value is required.
+fn any_of_values(s: &str) -> Option<anyhow::Result<Vec<String>>> {
+  let s = s.trim();
+  let args = s.strip_prefix("anyOf(")
+    .or_else(|| s.strip_prefix("oneOf("))
+    .and_then(|s| s.strip_suffix(')'))?;
+
+  let mut values = vec![];
+  let mut chars = args.trim().chars().peekable();
+  while chars.peek().is_some() {
+    if chars.next() != Some('\'') {
+      return Some(Err(anyhow!("Values for '{}' must be quoted with single quotes", s)));
+    }
+    let mut value = String::new();
+    loop {
+      match chars.next() {
+        Some('\\') => if let Some(ch) = chars.next() { value.push(ch) },
+        Some('\'') => break,
+        Some(ch) => value.push(ch),
+        None => return Some(Err(anyhow!("Missing closing quote for value '{}' in '{}'", value, s)))
+      }
+    }
+    values.push(value);
+    while chars.next_if(|ch| ch.is_whitespace()).is_some() {}
+    if chars.next_if_eq(&',').is_some() {
+      while chars.next_if(|ch| ch.is_whitespace()).is_some() {}
+    } else if chars.peek().is_some() {
+      return Some(Err(anyhow!("Values for '{}' must be separated with commas", s)));
+    }
+  }
+
+  if values.is_empty() {
+    Some(Err(anyhow!("'{}' requires at least one value", s)))
+  } else {
+    Some(Ok(values))
+  }
+}
+
+/// Returns the values if the string is a sequence expression (i.e. `sequence(count, start, step)`).
+/// The start value defaults to 0 and the step to 1, so `sequence(3)` will return 0, 1, 2.
+fn sequence_values(s: &str) -> Option<anyhow::Result<Vec<i64>>> {
+  let s = s.trim();
+  let args = s.strip_prefix("sequence(").and_then(|s| s.strip_suffix(')'))?;
+  let args = match args.split(',')
+    .map(|arg| arg.trim())
+    .filter(|arg| !arg.is_empty())
+    .map(|arg| arg.parse::<i64>().map_err(|err| anyhow!("'{}' is not a valid sequence value - {}", arg, err)))
+    .collect::<anyhow::Result<Vec<_>>>() {
+    Ok(args) => args,
+    Err(err) => return Some(Err(err))
+  };
+  let (count, start, step) = match args.as_slice() {
+    [count] => (*count, 0, 1),
+    [count, start] => (*count, *start, 1),
+    [count, start, step] => (*count, *start, *step),
+    _ => return Some(Err(anyhow!("A sequence expression requires a count, and an optional start and step value, got '{}'", s)))
+  };
+  let count = match u32::try_from(count) {
+    Ok(count) => count,
+    Err(_) => return Some(Err(anyhow!("Sequence count must not be negative, got {}", count)))
+  };
+  Some(Ok((0..count as i64)
+    .map(|index| start.saturating_add(step.saturating_mul(index)))
+    .collect()))
+}
+
+/// Returns the bounds and example value if the string is a number range matcher definition (i.e.
+/// `range(1, 100)` or `range(>0, <1.5, 0.75)`). Bounds are inclusive unless prefixed with `>` (for
+/// the minimum) or `<` (for the maximum), and `*` is used for an unbounded side. If no example value
+/// is given, the inclusive minimum (or maximum) is used.
+fn number_range_value(s: &str) -> Option<anyhow::Result<(NumberRange, String)>> {
+  let s = s.trim();
+  let args = s.strip_prefix("range(").and_then(|s| s.strip_suffix(')'))?;
+  Some(parse_number_range(s, args))
+}
+
+fn parse_number_range(s: &str, args: &str) -> anyhow::Result<(NumberRange, String)> {
+  let args = args.split(',').map(|arg| arg.trim()).collect_vec();
+  let (min, max, example) = match args.as_slice() {
+    [min, max] => (*min, *max, None),
+    [min, max, example] => (*min, *max, Some(*example)),
+    _ => return Err(anyhow!("'{}' requires a minimum and maximum value, and an optional example value", s))
+  };
+  let (min, min_exclusive) = number_range_bound(s, min, '>')?;
+  let (max, max_exclusive) = number_range_bound(s, max, '<')?;
+  let range = NumberRange {
+    min: min.map(|(value, _)| value),
+    min_exclusive,
+    max: max.map(|(value, _)| value),
+    max_exclusive
+  };
+  if let (Some(min), Some(max)) = (range.min, range.max) {
+    if min > max || (min == max && (min_exclusive || max_exclusive)) {
+      return Err(anyhow!("'{}' does not allow any values", s));
+    }
+  }
+
+  let example = match (example, min, max) {
+    (Some(example), _, _) => example,
+    (None, Some((_, min)), _) if !min_exclusive => min,
+    (None, _, Some((_, max))) if !max_exclusive => max,
+    _ => return Err(anyhow!("'{}' requires an example value, as it has no inclusive bound", s))
+  };
+  match example.parse::<f64>() {
+    Ok(value) if range.contains(value) => Ok((range, example.to_string())),
+    Ok(_) => Err(anyhow!("Example value {} is not within the bounds of '{}'", example, s)),
+    Err(_) => Err(anyhow!("'{}' is not a valid example value for '{}'", example, s))
+  }
+}
+
+/// Parses one of the bounds of a number range, returning the value (and the text of the value)
+/// and if it is exclusive. Bounds are exclusive if they start with the prefix character (`>` or `<`)
+/// not followed by `=`.
+fn number_range_bound<'a>(s: &str, bound: &'a str, prefix: char) -> anyhow::Result<(Option<(f64, &'a str)>, bool)> {
+  if bound == "*" {
+    return Ok((None, false));
+  }
+  let (bound, exclusive) = match bound.strip_prefix(prefix) {
+    Some(bound) => match bound.strip_prefix('=') {
+      Some(bound) => (bound.trim(), false),
+      None => (bound.trim(), true)
+    },
+    None => (bound, false)
+  };
+  match bound.parse::<f64>() {
+    Ok(value) if !value.is_nan() => Ok((Some((value, bound)), exclusive)),
+    _ => Err(anyhow!("'{}' is not a valid bound for '{}'", bound, s))
+  }
+}
+
+/// If the field is one of the numeric scalar types
+fn is_numeric_field(descriptor: &FieldDescriptorProto) -> bool {
+  matches!(descriptor.r#type(), Type::Double | Type::Float | Type::Int64 | Type::Sfixed64 | Type::Sint64 |
+    Type::Uint64 | Type::Fixed64 | Type::Int32 | Type::Sfixed32 | Type::Sint32 | Type::Uint32 | Type::Fixed32)
+}
+
+fn parent(path: &DocPath) -> Option<DocPath> {
+  let tokens = path.tokens().clone();
+  if path.is_root() || tokens.len() <= 1 {
+    None
+  } else {
+    let mut path = DocPath::root();
+    let tokens = tokens.split_last().unwrap().1;
+    for part in tokens.iter().skip(1) {
+      path = path.join(part.to_string());
+    }
+    Some(path)
+  }
+}
+
+fn value_for_field(mrd: &MatchingRuleDefinition) -> String {
+  if mrd.value.is_empty() {
+    if let Some(value_matcher) = mrd.rules.iter().find_map(|m| {
+      match m {
+        Either::Left(mr) => match mr {
+          matchingrules::MatchingRule::EachValue(def) => Some(def.value.clone()),
+          _ => None
+        }
+        Either::Right(_) => None
+      }
+    }) {
+      value_matcher
+    } else {
+      String::default()
+    }
+  } else {
+    mrd.value.clone()
+  }
+}
+
+fn value_for_type(
+  field_name: &str,
+  field_value: &str,
+  descriptor: &FieldDescriptorProto,
+  message_descriptor: &DescriptorProto,
+  all_descriptors: &HashMap<String, &FileDescriptorProto>
+) -> anyhow::Result<MessageFieldValue> {
+  trace!("value_for_type({}, {}, _)", field_name, field_value);
+
+  let type_name = descriptor.type_name.clone().unwrap_or_default();
+  debug!("Creating value for type {:?} from '{}'", type_name, field_value);
+
+  let t = descriptor.r#type();
+  match t {
+    Type::Double => MessageFieldValue::double(field_name, field_value),
+    Type::Float => MessageFieldValue::float(field_name, field_value),
+    Type::Int64 | Type::Sfixed64 | Type::Sint64 => MessageFieldValue::integer_64(field_name, field_value),
+    Type::Uint64 | Type::Fixed64 => MessageFieldValue::uinteger_64(field_name, field_value),
+    Type::Int32 | Type::Sfixed32 | Type::Sint32 => MessageFieldValue::integer_32(field_name, field_value),
+    Type::Uint32 | Type::Fixed32 => MessageFieldValue::uinteger_32(field_name, field_value),
+    Type::Bool => MessageFieldValue::boolean(field_name, field_value),
+    Type::String => Ok(MessageFieldValue::string(field_name, field_value)),
+    Type::Message => {
+      if type_name == ".google.protobuf.BytesValue" {
+        Ok(MessageFieldValue::bytes(field_name, field_value))
       } else {
         Err(anyhow!("value_for_type: Protobuf field {} has an unsupported type {:?} {}", field_name, t, type_name))
       }
@@ -1258,6 +2913,9 @@ fn value_for_type(
           raw_value: Some(field_value.to_string()),
           rtype: RType::Enum(n, desc)
         })
+      } else if let Ok(number) = field_value.parse::<i32>() {
+        // Enum values can also be configured with the numeric value
+        enum_value_for_number(field_name, number, descriptor, message_descriptor, all_descriptors)
       } else {
         Err(anyhow!("Protobuf enum value {} has no value {}", type_name, field_value))
       }
@@ -1266,13 +2924,34 @@ fn value_for_type(
   }
 }
 
+/// Constructs an enum field value from the numeric value of the enum. Will return an error if the
+/// enum does not declare a value with that number.
+fn enum_value_for_number(
+  field_name: &str,
+  number: i32,
+  descriptor: &FieldDescriptorProto,
+  message_descriptor: &DescriptorProto,
+  all_descriptors: &HashMap<String, &FileDescriptorProto>
+) -> anyhow::Result<MessageFieldValue> {
+  let type_name = descriptor.type_name.clone().unwrap_or_default();
+  if let Some((n, desc)) = find_enum_value_by_number(message_descriptor, all_descriptors, type_name.as_str(), number) {
+    Ok(MessageFieldValue {
+      name: field_name.to_string(),
+      raw_value: Some(enum_name(n, &desc)),
+      rtype: RType::Enum(n, desc)
+    })
+  } else {
+    Err(anyhow!("Protobuf enum {} has no value with number {}", type_name, number))
+  }
+}
+
 #[cfg(test)]
 pub(crate) mod tests {
-  use std::collections::HashMap;
+  use std::collections::{BTreeMap, HashMap};
 
   use base64::Engine;
   use base64::engine::general_purpose::STANDARD as BASE64;
-  use bytes::Bytes;
+  use bytes::{Bytes, BytesMut};
   use expectest::prelude::*;
   use lazy_static::lazy_static;
   use maplit::{btreemap, hashmap};
@@ -1280,16 +2959,24 @@ pub(crate) mod tests {
   use pact_models::matchingrules::expressions::{MatchingRuleDefinition, ValueType};
   use pact_models::path_exp::DocPath;
   use pact_models::prelude::MatchingRuleCategory;
-  use pact_plugin_driver::proto::{MatchingRule, MatchingRules};
+  use pact_models::v4::message_parts::MessageContents;
+  use pact_matching::BodyMatchResult;
+  use pact_plugin_driver::proto::{InteractionResponse, MatchingRule, MatchingRules};
+  use pact_plugin_driver::proto::body::ContentTypeHint;
   use pact_plugin_driver::proto::interaction_response::MarkupType;
+  use pact_plugin_driver::utils::{proto_value_to_json, proto_value_to_string};
   use pretty_assertions::assert_eq;
+  use prost::encoding::WireType;
   use prost::Message;
   use prost_types::{
     DescriptorProto,
+    EnumDescriptorProto,
+    EnumValueDescriptorProto,
     field_descriptor_proto,
     FieldDescriptorProto,
     FileDescriptorProto,
     FileDescriptorSet,
+    MessageOptions,
     MethodDescriptorProto,
     MethodOptions,
     OneofDescriptorProto,
@@ -1299,21 +2986,41 @@ pub(crate) mod tests {
   use prost_types::field_descriptor_proto::{Label, Type};
   use prost_types::value::Kind::{ListValue, NullValue, NumberValue, StringValue, StructValue};
   use serde_json::{json, Value};
+  use tonic::{Code, Status};
   use trim_margin::MarginTrimmable;
 
+  use crate::matching::match_message;
   use crate::message_builder::{MessageBuilder, MessageFieldValue, MessageFieldValueType, RType};
+  use crate::message_decoder::{decode_message, ProtobufFieldData};
+  use crate::metadata::{compare_status_details, grpc_status, RpcStatus};
   use crate::protobuf::{
+    any_of_values,
     build_embedded_message_field_value,
     build_field_value,
+    build_map_field,
     build_single_embedded_field_value,
     construct_message_field,
     construct_protobuf_interaction_for_message,
     construct_protobuf_interaction_for_service,
+    configure_protobuf_message,
+    configure_protobuf_service,
+    construct_value_from_string,
+    DEFAULT_MAX_NESTING_DEPTH,
+    dump_descriptors,
+    encode_message_from_yaml,
+    matching_rule_types,
+    number_range_value,
+    number_ranges,
+    packed_encoding,
+    process_proto,
+    repeated_field_matching,
     request_part,
+    response_delay,
     response_part,
+    sequence_values,
     value_for_type
   };
-  use crate::utils::find_message_descriptor_for_type;
+  use crate::utils::{fds_to_map, find_message_descriptor_for_type, hash_descriptors, prost_string};
 
   #[test]
   fn value_for_type_test() {
@@ -1367,132 +3074,1429 @@ pub(crate) mod tests {
   }
 
   #[test]
-  fn construct_protobuf_interaction_for_message_test() {
-    // construct_protobuf_interaction_for_message doesn't actually verify 
-    // that the message descriptor is part of a file descriptor
-    // so it doesn't have to be here as well
-    // It will still assume that the message came from this file descriptor and will use the package field from
-    // the file descriptor as the message package too.
-    let file_descriptor = FileDescriptorProto {
-      name: Some("test_file.proto".to_string()),
-      package: Some("test_package".to_string()),
-      dependency: vec![],
-      public_dependency: vec![],
-      weak_dependency: vec![],
-      message_type: vec![],
-      enum_type: vec![],
-      service: vec![],
-      extension: vec![],
-      options: None,
-      source_code_info: None,
-      syntax: None
-    };
+  fn construct_value_from_string_with_equals_ignore_case() {
     let message_descriptor = DescriptorProto {
-      name: Some("test_message".to_string()),
+      name: Some("Test".to_string()),
+      .. DescriptorProto::default()
+    };
+    let file_descriptor = FileDescriptorProto::default();
+    let mut message_builder = MessageBuilder::new(&message_descriptor, "Test", &file_descriptor);
+    let descriptor = FieldDescriptorProto {
+      name: Some("status".to_string()),
+      number: Some(1),
+      r#type: Some(Type::String as i32),
+      .. FieldDescriptorProto::default()
+    };
+    let path = DocPath::new_unwrap("$.status");
+    let mut matching_rules = MatchingRuleCategory::empty("body");
+    let mut generators = hashmap!{};
+
+    let result = construct_value_from_string(&path, &mut message_builder, &descriptor, "status",
+      &mut matching_rules, &mut generators, "equalsIgnoreCase('Active')", &hashmap!{}).unwrap();
+    expect!(result.rtype).to(be_equal_to(RType::String("Active".to_string())));
+    expect!(matching_rules.clone()).to(be_equal_to(matchingrules_list! {
+      "body"; "$.status" => [ pact_models::matchingrules::MatchingRule::Regex("(?i)^Active$".to_string()) ]
+    }));
+
+    let descriptor = FieldDescriptorProto {
+      name: Some("count".to_string()),
+      number: Some(2),
+      r#type: Some(Type::Int32 as i32),
+      .. FieldDescriptorProto::default()
+    };
+    let result = construct_value_from_string(&path, &mut message_builder, &descriptor, "count",
+      &mut matching_rules, &mut generators, "equalsIgnoreCase('100')", &hashmap!{});
+    expect!(result).to(be_err());
+  }
+
+  #[test]
+  fn construct_value_from_string_with_a_protobuf_content_type() {
+    let inner_descriptor = DescriptorProto {
+      name: Some("Inner".to_string()),
       field: vec![
         FieldDescriptorProto {
-          name: Some("implementation".to_string()),
+          name: Some("name".to_string()),
           number: Some(1),
-          label: None,
-          r#type: Some(field_descriptor_proto::Type::String as i32),
-          type_name: Some("string".to_string()),
-          extendee: None,
-          default_value: None,
-          oneof_index: None,
-          json_name: None,
-          options: None,
-          proto3_optional: None
+          r#type: Some(Type::String as i32),
+          .. FieldDescriptorProto::default()
         },
         FieldDescriptorProto {
-          name: Some("version".to_string()),
+          name: Some("id".to_string()),
           number: Some(2),
-          label: None,
-          r#type: Some(field_descriptor_proto::Type::String as i32),
-          type_name: Some("string".to_string()),
-          extendee: None,
-          default_value: None,
-          oneof_index: None,
-          json_name: None,
-          options: None,
-          proto3_optional: None
-        },
-        FieldDescriptorProto {
-          name: Some("length".to_string()),
-          number: Some(3),
-          label: None,
-          r#type: Some(field_descriptor_proto::Type::Int64 as i32),
-          type_name: Some("int64".to_string()),
-          extendee: None,
-          default_value: None,
-          oneof_index: None,
-          json_name: None,
-          options: None,
-          proto3_optional: None
-        },
-        FieldDescriptorProto {
-          name: Some("hash".to_string()),
-          number: Some(4),
-          label: None,
-          r#type: Some(field_descriptor_proto::Type::Uint64 as i32),
-          type_name: Some("uint64".to_string()),
-          extendee: None,
-          default_value: None,
-          oneof_index: None,
-          json_name: None,
-          options: None,
-          proto3_optional: None
+          r#type: Some(Type::Int32 as i32),
+          .. FieldDescriptorProto::default()
         }
       ],
-      extension: vec![],
-      nested_type: vec![],
-      enum_type: vec![],
-      extension_range: vec![],
-      oneof_decl: vec![],
-      options: None,
-      reserved_range: vec![],
-      reserved_name: vec![]
+      .. DescriptorProto::default()
     };
-    let config = btreemap! {
-      "implementation".to_string() => prost_types::Value { kind: Some(prost_types::value::Kind::StringValue("notEmpty('plugin-driver-rust')".to_string())) },
-      "version".to_string() => prost_types::Value { kind: Some(prost_types::value::Kind::StringValue("matching(semver, '0.0.0')".to_string())) },
-      "hash".to_string() => prost_types::Value { kind: Some(prost_types::value::Kind::StringValue("matching(integer, 1234)".to_string())) }
+    let message_descriptor = DescriptorProto {
+      name: Some("Test".to_string()),
+      .. DescriptorProto::default()
     };
+    let file_descriptor = FileDescriptorProto {
+      name: Some("test.proto".to_string()),
+      package: Some("pkg".to_string()),
+      message_type: vec![ message_descriptor.clone(), inner_descriptor ],
+      .. FileDescriptorProto::default()
+    };
+    let file_descriptors = hashmap!{ "test.proto".to_string() => &file_descriptor };
+    let mut message_builder = MessageBuilder::new(&message_descriptor, "Test", &file_descriptor);
+    let descriptor = FieldDescriptorProto {
+      name: Some("payload".to_string()),
+      number: Some(1),
+      r#type: Some(Type::Bytes as i32),
+      .. FieldDescriptorProto::default()
+    };
+    let path = DocPath::new_unwrap("$.payload");
+    let mut matching_rules = MatchingRuleCategory::empty("body");
+    let mut generators = hashmap!{};
 
-    let result = construct_protobuf_interaction_for_message(&message_descriptor, &config,
-      "", &file_descriptor, &hashmap!{}, None).unwrap();
-
-    let body = result.contents.as_ref().unwrap();
-    expect!(body.content_type.as_str()).to(be_equal_to("application/protobuf;message=.test_package.test_message"));
-    expect!(body.content_type_hint).to(be_equal_to(2));
-    expect!(body.content.as_ref()).to(be_some().value(&vec![
-      10, // field 1 length encoded (1 << 3 + 2 == 10)
-      18, // 18 bytes
-      112, 108, 117, 103, 105, 110, 45, 100, 114, 105, 118, 101, 114, 45, 114, 117, 115, 116,
-      18, // field 2 length encoded (2 << 3 + 2 == 18)
-      5, // 5 bytes
-      48, 46, 48, 46, 48,
-      32, // field 4 varint encoded (4 << 3 + 0 == 32)
-      210, 9 // 9 << 7 + 210 == 1234
-    ]));
-
-    expect!(result.rules).to(be_equal_to(hashmap! {
-      "$.implementation".to_string() => MatchingRules { rule: vec![ MatchingRule { r#type: "not-empty".to_string(), .. MatchingRule::default() } ] },
-      "$.version".to_string() => MatchingRules { rule: vec![ MatchingRule { r#type: "semver".to_string(), .. MatchingRule::default() } ] },
-      "$.hash".to_string() => MatchingRules { rule: vec![ MatchingRule { r#type: "integer".to_string(), .. MatchingRule::default() } ] }
+    let result = construct_value_from_string(&path, &mut message_builder, &descriptor, "payload",
+      &mut matching_rules, &mut generators,
+      "matching(contentType, 'application/protobuf;message=.pkg.Inner', 'CgR0ZXN0EGQ=')", &file_descriptors).unwrap();
+    expect!(result.rtype).to(be_equal_to(RType::Bytes(vec![10, 4, 116, 101, 115, 116, 16, 100])));
+    expect!(matching_rules.clone()).to(be_equal_to(matchingrules_list! {
+      "body"; "$.payload" => [
+        pact_models::matchingrules::MatchingRule::ContentType("application/protobuf;message=.pkg.Inner".to_string())
+      ]
     }));
 
-    expect!(result.generators).to(be_equal_to(hashmap! {}));
+    let result = construct_value_from_string(&path, &mut message_builder, &descriptor, "payload",
+      &mut matching_rules, &mut generators,
+      "matching(contentType, 'application/protobuf;message=.pkg.Inner', 'not base64!')", &file_descriptors);
+    expect!(result).to(be_err());
 
-    expect!(result.interaction_markup_type).to(be_equal_to(MarkupType::CommonMark as i32));
-    expect!(result.interaction_markup).to(be_equal_to(
-     "|```protobuf
-      |message test_message {
-      |    string implementation = 1;
-      |    string version = 2;
-      |    uint64 hash = 4;
-      |}
-      |```
-      |".trim_margin().unwrap()));
+    let result = construct_value_from_string(&path, &mut message_builder, &descriptor, "payload",
+      &mut matching_rules, &mut generators,
+      "matching(contentType, 'application/protobuf;message=.pkg.Missing', 'CgR0ZXN0EGQ=')", &file_descriptors);
+    expect!(result).to(be_err());
+  }
+
+  #[test]
+  fn construct_value_from_string_with_any_of() {
+    let message_descriptor = DescriptorProto {
+      name: Some("Test".to_string()),
+      .. DescriptorProto::default()
+    };
+    let file_descriptor = FileDescriptorProto::default();
+    let mut message_builder = MessageBuilder::new(&message_descriptor, "Test", &file_descriptor);
+    let descriptor = FieldDescriptorProto {
+      name: Some("status".to_string()),
+      number: Some(1),
+      r#type: Some(Type::String as i32),
+      .. FieldDescriptorProto::default()
+    };
+    let path = DocPath::new_unwrap("$.status");
+    let mut matching_rules = MatchingRuleCategory::empty("body");
+    let mut generators = hashmap!{};
+
+    let result = construct_value_from_string(&path, &mut message_builder, &descriptor, "status",
+      &mut matching_rules, &mut generators, "anyOf('PENDING', 'ACTIVE', 'ON.HOLD')", &hashmap!{}).unwrap();
+    expect!(result.rtype).to(be_equal_to(RType::String("PENDING".to_string())));
+    expect!(matching_rules.clone()).to(be_equal_to(matchingrules_list! {
+      "body"; "$.status" => [ pact_models::matchingrules::MatchingRule::Regex("^(?:PENDING|ACTIVE|ON\\.HOLD)$".to_string()) ]
+    }));
+
+    let result = construct_value_from_string(&path, &mut message_builder, &descriptor, "status",
+      &mut matching_rules, &mut generators, "anyOf()", &hashmap!{});
+    expect!(result.unwrap_err().to_string()).to(be_equal_to("'anyOf()' requires at least one value"));
+
+    let descriptor = FieldDescriptorProto {
+      name: Some("count".to_string()),
+      number: Some(2),
+      r#type: Some(Type::Int32 as i32),
+      .. FieldDescriptorProto::default()
+    };
+    let result = construct_value_from_string(&path, &mut message_builder, &descriptor, "count",
+      &mut matching_rules, &mut generators, "oneOf('1', '2')", &hashmap!{});
+    expect!(result).to(be_err());
+  }
+
+  #[test_log::test]
+  fn construct_value_from_string_with_any_of_for_an_enum_field() {
+    let enum_descriptor = EnumDescriptorProto {
+      name: Some("Colour".to_string()),
+      value: ["RED", "GREEN", "BLUE"].iter().enumerate().map(|(i, name)| EnumValueDescriptorProto {
+        name: Some(name.to_string()),
+        number: Some(i as i32),
+        options: None
+      }).collect(),
+      .. EnumDescriptorProto::default()
+    };
+    let descriptor = FieldDescriptorProto {
+      name: Some("colour".to_string()),
+      number: Some(1),
+      r#type: Some(Type::Enum as i32),
+      type_name: Some(".Test.Colour".to_string()),
+      .. FieldDescriptorProto::default()
+    };
+    let message_descriptor = DescriptorProto {
+      name: Some("Test".to_string()),
+      field: vec![ descriptor.clone() ],
+      enum_type: vec![ enum_descriptor.clone() ],
+      .. DescriptorProto::default()
+    };
+    let file_descriptor = FileDescriptorProto {
+      name: Some("test.proto".to_string()),
+      message_type: vec![ message_descriptor.clone() ],
+      syntax: Some("proto3".to_string()),
+      .. FileDescriptorProto::default()
+    };
+    let mut message_builder = MessageBuilder::new(&message_descriptor, "Test", &file_descriptor);
+    let path = DocPath::new_unwrap("$.colour");
+    let mut matching_rules = MatchingRuleCategory::empty("body");
+
+    let result = construct_value_from_string(&path, &mut message_builder, &descriptor, "colour",
+      &mut matching_rules, &mut hashmap!{}, "anyOf('RED', 'GREEN')", &hashmap!{}).unwrap();
+    expect!(result.rtype.clone()).to(be_equal_to(RType::Enum(0, enum_descriptor.clone())));
+    expect!(matching_rules.clone()).to(be_equal_to(matchingrules_list! {
+      "body"; "$.colour" => [ pact_models::matchingrules::MatchingRule::Regex("^(?:RED|GREEN)$".to_string()) ]
+    }));
+
+    message_builder.set_field_value(&descriptor, "colour", result);
+    let fds = FileDescriptorSet { file: vec![ file_descriptor.clone() ] };
+    let expected = message_builder.encode_message().unwrap();
+    let result = match_message(".Test", &fds, &mut expected.clone(), &mut Bytes::from_static(&[8, 1]),
+      &matching_rules, false, false, &hashmap!{}).unwrap();
+    expect!(result).to(be_equal_to(BodyMatchResult::Ok));
+    let result = match_message(".Test", &fds, &mut expected.clone(), &mut Bytes::from_static(&[8, 2]),
+      &matching_rules, false, false, &hashmap!{}).unwrap();
+    expect!(result.mismatches().iter().map(|m| m.description()).collect::<Vec<_>>()).to(be_equal_to(vec![
+      "$.colour -> Expected 'BLUE' to be one of 'RED', 'GREEN'".to_string()
+    ]));
+
+    let result = construct_value_from_string(&path, &mut message_builder, &descriptor, "colour",
+      &mut MatchingRuleCategory::empty("body"), &mut hashmap!{}, "anyOf('RED', 'PURPLE')", &hashmap!{});
+    expect!(result.unwrap_err().to_string()).to(be_equal_to("Protobuf enum value .Test.Colour has no value PURPLE"));
+  }
+
+  #[test]
+  fn construct_value_from_string_with_a_length_matcher_for_a_bytes_field() {
+    let descriptor = FieldDescriptorProto {
+      name: Some("data".to_string()),
+      number: Some(1),
+      r#type: Some(Type::Bytes as i32),
+      .. FieldDescriptorProto::default()
+    };
+    let message_descriptor = DescriptorProto {
+      name: Some("Test".to_string()),
+      field: vec![ descriptor.clone() ],
+      .. DescriptorProto::default()
+    };
+    let file_descriptor = FileDescriptorProto {
+      name: Some("test.proto".to_string()),
+      message_type: vec![ message_descriptor.clone() ],
+      syntax: Some("proto3".to_string()),
+      .. FileDescriptorProto::default()
+    };
+    let mut message_builder = MessageBuilder::new(&message_descriptor, "Test", &file_descriptor);
+    let path = DocPath::new_unwrap("$.data");
+    let mut matching_rules = MatchingRuleCategory::empty("body");
+
+    let result = construct_value_from_string(&path, &mut message_builder, &descriptor, "data",
+      &mut matching_rules, &mut hashmap!{}, "atLeast(3), atMost(8), matching(type, 'hello')", &hashmap!{}).unwrap();
+    expect!(result.rtype.clone()).to(be_equal_to(RType::Bytes("hello".as_bytes().to_vec())));
+    expect!(matching_rules.clone()).to(be_equal_to(matchingrules_list! {
+      "body"; "$.data" => [
+        pact_models::matchingrules::MatchingRule::MinType(3),
+        pact_models::matchingrules::MatchingRule::MaxType(8),
+        pact_models::matchingrules::MatchingRule::Type
+      ]
+    }));
+
+    message_builder.set_field_value(&descriptor, "data", result);
+    let fds = FileDescriptorSet { file: vec![ file_descriptor.clone() ] };
+    let expected = message_builder.encode_message().unwrap();
+    let result = match_message(".Test", &fds, &mut expected.clone(), &mut Bytes::from_static(b"\x0a\x04abcd"),
+      &matching_rules, false, false, &hashmap!{}).unwrap();
+    expect!(result).to(be_equal_to(BodyMatchResult::Ok));
+
+    let result = match_message(".Test", &fds, &mut expected.clone(), &mut Bytes::from_static(b"\x0a\x02ab"),
+      &matching_rules, false, false, &hashmap!{}).unwrap();
+    expect!(result.mismatches().len()).to(be_equal_to(1));
+
+    let result = match_message(".Test", &fds, &mut expected.clone(), &mut Bytes::from_static(b"\x0a\x09abcdefghi"),
+      &matching_rules, false, false, &hashmap!{}).unwrap();
+    expect!(result.mismatches().len()).to(be_equal_to(1));
+  }
+
+  #[rstest::rstest]
+  #[case("anyOf('A')", Some(vec!["A"]))]
+  #[case("oneOf('A', 'B')", Some(vec!["A", "B"]))]
+  #[case("  anyOf( 'A' ,'B, C','it\\'s' )  ", Some(vec!["A", "B, C", "it's"]))]
+  #[case("anyOf(A, B)", None)]
+  #[case("anyOf('A' 'B')", None)]
+  #[case("anyOf('A)", None)]
+  fn any_of_values_test(#[case] definition: &str, #[case] expected: Option<Vec<&str>>) {
+    let result = any_of_values(definition).unwrap();
+    match expected {
+      Some(values) => {
+        expect!(result.unwrap()).to(be_equal_to(values.iter().map(|v| v.to_string()).collect::<Vec<_>>()));
+      }
+      None => {
+        expect!(result).to(be_err());
+      }
+    }
+  }
+
+  #[rstest::rstest]
+  #[case("sequence(3)", Some(vec![0, 1, 2]))]
+  #[case(" sequence( 3, 10, 5 ) ", Some(vec![10, 15, 20]))]
+  #[case("sequence(2, -1)", Some(vec![-1, 0]))]
+  #[case("sequence(0)", Some(vec![]))]
+  #[case("sequence()", None)]
+  #[case("sequence(-1)", None)]
+  #[case("sequence(a, b)", None)]
+  #[case("sequence(1, 2, 3, 4)", None)]
+  fn sequence_values_test(#[case] definition: &str, #[case] expected: Option<Vec<i64>>) {
+    let result = sequence_values(definition).unwrap();
+    match expected {
+      Some(values) => {
+        expect!(result.unwrap()).to(be_equal_to(values));
+      }
+      None => {
+        expect!(result).to(be_err());
+      }
+    }
+    expect!(sequence_values("matching(type, 1)")).to(be_none());
+  }
+
+  #[rstest::rstest]
+  #[case("range(1, 10)", Some(("greater than or equal to 1 and less than or equal to 10", "1")))]
+  #[case("range(>=1, <=10, 5)", Some(("greater than or equal to 1 and less than or equal to 10", "5")))]
+  #[case(" range( >0 , <1.5, 0.75 ) ", Some(("greater than 0 and less than 1.5", "0.75")))]
+  #[case("range(>0, 100)", Some(("greater than 0 and less than or equal to 100", "100")))]
+  #[case("range(-5, *)", Some(("greater than or equal to -5", "-5")))]
+  #[case("range(*, <0, -1)", Some(("less than 0", "-1")))]
+  #[case("range(1)", None)]
+  #[case("range(>0, <1)", None)]
+  #[case("range(10, 1)", None)]
+  #[case("range(>1, 1, 1)", None)]
+  #[case("range(1, 10, 11)", None)]
+  #[case("range(a, 10)", None)]
+  fn number_range_value_test(#[case] definition: &str, #[case] expected: Option<(&str, &str)>) {
+    let result = number_range_value(definition).unwrap();
+    match expected {
+      Some((range, example)) => {
+        let (actual_range, value) = result.unwrap();
+        expect!(actual_range.to_string()).to(be_equal_to(range.to_string()));
+        expect!(value).to(be_equal_to(example.to_string()));
+      }
+      None => {
+        expect!(result).to(be_err());
+      }
+    }
+  }
+
+  #[test]
+  fn number_ranges_collects_the_ranges_by_field_path() {
+    let request = json!({
+      "pact:match": "range(0, 1)",
+      "count": "range(1, <10)",
+      "stats": { "ratio": "range(>0, 1)", "name": "notEmpty('test')" },
+      "total": { "pact:match": "range(5, 50)" }
+    });
+    expect!(number_ranges(&request)).to(be_some().value(json!({
+      "$.count": { "min": 1.0, "minExclusive": false, "max": 10.0, "maxExclusive": true },
+      "$.total": { "min": 5.0, "minExclusive": false, "max": 50.0, "maxExclusive": false },
+      "$.stats.ratio": { "min": 0.0, "minExclusive": true, "max": 1.0, "maxExclusive": false }
+    })));
+    let responses = json!([ { "total": "range(0, *)", "values": ["range(1, 2)", "range(3, 4)"] }, { "total": "range(0, *)" } ]);
+    expect!(number_ranges(&responses)).to(be_some().value(json!({
+      "$.total": { "min": 0.0, "minExclusive": false, "max": null, "maxExclusive": false },
+      "$.values[0]": { "min": 1.0, "minExclusive": false, "max": 2.0, "maxExclusive": false },
+      "$.values[1]": { "min": 3.0, "minExclusive": false, "max": 4.0, "maxExclusive": false }
+    })));
+    expect!(number_ranges(&json!({ "name": "notEmpty('test')" }))).to(be_none());
+  }
+
+  #[test_log::test]
+  fn construct_value_from_string_with_a_number_range() {
+    let descriptor = FieldDescriptorProto {
+      name: Some("ratio".to_string()),
+      number: Some(1),
+      r#type: Some(Type::Double as i32),
+      .. FieldDescriptorProto::default()
+    };
+    let message_descriptor = DescriptorProto {
+      name: Some("Test".to_string()),
+      field: vec![ descriptor.clone() ],
+      .. DescriptorProto::default()
+    };
+    let file_descriptor = FileDescriptorProto {
+      name: Some("test.proto".to_string()),
+      message_type: vec![ message_descriptor.clone() ],
+      syntax: Some("proto3".to_string()),
+      .. FileDescriptorProto::default()
+    };
+    let mut message_builder = MessageBuilder::new(&message_descriptor, "Test", &file_descriptor);
+    let path = DocPath::new_unwrap("$.ratio");
+    let mut matching_rules = MatchingRuleCategory::empty("body");
+
+    let result = construct_value_from_string(&path, &mut message_builder, &descriptor, "ratio",
+      &mut matching_rules, &mut hashmap!{}, "range(>0, 1, 0.5)", &hashmap!{}).unwrap();
+    expect!(result.rtype.clone()).to(be_equal_to(RType::Double(0.5)));
+    expect!(matching_rules.clone()).to(be_equal_to(matchingrules_list! {
+      "body"; "$.ratio" => [ pact_models::matchingrules::MatchingRule::Number ]
+    }));
+
+    message_builder.set_field_value(&descriptor, "ratio", result);
+    let fds = FileDescriptorSet { file: vec![ file_descriptor.clone() ] };
+    let expected = message_builder.encode_message().unwrap();
+    let interaction_config = hashmap!{
+      "numberRanges".to_string() => number_ranges(&json!({ "ratio": "range(>0, 1, 0.5)" })).unwrap()
+    };
+    let double_message = |value: f64| {
+      let mut buffer = vec![9];
+      buffer.extend_from_slice(&value.to_le_bytes());
+      Bytes::from(buffer)
+    };
+    let result = match_message(".Test", &fds, &mut expected.clone(), &mut double_message(1.0),
+      &matching_rules, false, false, &interaction_config).unwrap();
+    expect!(result).to(be_equal_to(BodyMatchResult::Ok));
+    let result = match_message(".Test", &fds, &mut expected.clone(), &mut double_message(1.25),
+      &matching_rules, false, false, &interaction_config).unwrap();
+    expect!(result.mismatches().iter().map(|m| m.description()).collect::<Vec<_>>()).to(be_equal_to(vec![
+      "$.ratio -> Expected 1.25 to be greater than 0 and less than or equal to 1".to_string()
+    ]));
+
+    let descriptor = FieldDescriptorProto {
+      name: Some("name".to_string()),
+      number: Some(2),
+      r#type: Some(Type::String as i32),
+      .. FieldDescriptorProto::default()
+    };
+    let result = construct_value_from_string(&path, &mut message_builder, &descriptor, "name",
+      &mut MatchingRuleCategory::empty("body"), &mut hashmap!{}, "range(1, 10)", &hashmap!{});
+    expect!(result.unwrap_err().to_string()).to(be_equal_to("range can only be used with numeric fields, field name type is String"));
+  }
+
+  #[test_log::test]
+  fn build_field_value_with_a_pact_match_expression() {
+    let descriptor = FieldDescriptorProto {
+      name: Some("count".to_string()),
+      number: Some(1),
+      r#type: Some(Type::Int32 as i32),
+      .. FieldDescriptorProto::default()
+    };
+    let message_descriptor = DescriptorProto {
+      name: Some("Test".to_string()),
+      field: vec![ descriptor.clone() ],
+      .. DescriptorProto::default()
+    };
+    let file_descriptor = FileDescriptorProto::default();
+    let mut message_builder = MessageBuilder::new(&message_descriptor, "Test", &file_descriptor);
+    let path = DocPath::new_unwrap("$.count");
+    let mut matching_rules = MatchingRuleCategory::empty("body");
+
+    let result = build_field_value(&path, &mut message_builder, MessageFieldValueType::Normal,
+      &descriptor, "count", &json!({ "pact:match": "range(1, 10, 5)" }), &mut matching_rules,
+      &mut hashmap!{}, &hashmap!{}).unwrap();
+    expect!(result.unwrap().rtype).to(be_equal_to(RType::Integer32(5)));
+    expect!(matching_rules).to(be_equal_to(matchingrules_list! {
+      "body"; "$.count" => [ pact_models::matchingrules::MatchingRule::Number ]
+    }));
+
+    let result = build_field_value(&path, &mut message_builder, MessageFieldValueType::Normal,
+      &descriptor, "count", &json!({ "pact:match": "range(1, 10, 5)", "other": 1 }),
+      &mut MatchingRuleCategory::empty("body"), &mut hashmap!{}, &hashmap!{});
+    expect!(result.unwrap_err().to_string()).to(be_equal_to(
+      "Field count can only be configured with a 'pact:match' expression, got 'other'"));
+  }
+
+  #[test]
+  fn construct_message_field_with_a_field_expected_to_be_absent() {
+    let descriptor = FieldDescriptorProto {
+      name: Some("reason".to_string()),
+      number: Some(1),
+      r#type: Some(Type::String as i32),
+      .. FieldDescriptorProto::default()
+    };
+    let message_descriptor = DescriptorProto {
+      name: Some("Test".to_string()),
+      field: vec![ descriptor ],
+      .. DescriptorProto::default()
+    };
+    let file_descriptor = FileDescriptorProto::default();
+    let mut message_builder = MessageBuilder::new(&message_descriptor, "Test", &file_descriptor);
+    let path = DocPath::new_unwrap("$.reason");
+    let mut matching_rules = MatchingRuleCategory::empty("body");
+    let mut generators = hashmap!{};
+
+    let result = construct_message_field(&mut message_builder, &mut matching_rules,
+      &mut generators, "reason", &json!("absent()"), &path, &hashmap!{});
+    expect!(result).to(be_ok());
+    expect!(message_builder.fields.is_empty()).to(be_true());
+    expect!(matching_rules).to(be_equal_to(matchingrules_list! {
+      "body"; "$.reason" => [ pact_models::matchingrules::MatchingRule::Null ]
+    }));
+  }
+
+  #[test]
+  fn construct_message_field_with_a_sequence_of_values() {
+    let descriptor = FieldDescriptorProto {
+      name: Some("ids".to_string()),
+      number: Some(1),
+      label: Some(Label::Repeated as i32),
+      r#type: Some(Type::Int32 as i32),
+      .. FieldDescriptorProto::default()
+    };
+    let message_descriptor = DescriptorProto {
+      name: Some("Test".to_string()),
+      field: vec![ descriptor ],
+      .. DescriptorProto::default()
+    };
+    let file_descriptor = FileDescriptorProto::default();
+    let mut message_builder = MessageBuilder::new(&message_descriptor, "Test", &file_descriptor);
+    let path = DocPath::new_unwrap("$.ids");
+    let mut matching_rules = MatchingRuleCategory::empty("body");
+    let mut generators = hashmap!{};
+
+    let result = construct_message_field(&mut message_builder, &mut matching_rules,
+      &mut generators, "ids", &json!("sequence(3)"), &path, &hashmap!{});
+    expect!(result).to(be_ok());
+    let values = message_builder.fields.get("ids").unwrap().values.iter()
+      .map(|v| v.rtype.clone())
+      .collect::<Vec<_>>();
+    expect!(values).to(be_equal_to(vec![ RType::Integer32(0), RType::Integer32(1), RType::Integer32(2) ]));
+    expect!(matching_rules.clone()).to(be_equal_to(matchingrules_list! {
+      "body"; "$.ids.*" => [ pact_models::matchingrules::MatchingRule::Type ]
+    }));
+
+    let mut message_builder = MessageBuilder::new(&message_descriptor, "Test", &file_descriptor);
+    let result = construct_message_field(&mut message_builder, &mut matching_rules,
+      &mut generators, "ids", &json!("sequence(3, 100, 10)"), &path, &hashmap!{});
+    expect!(result).to(be_ok());
+    let values = message_builder.fields.get("ids").unwrap().values.iter()
+      .map(|v| v.rtype.clone())
+      .collect::<Vec<_>>();
+    expect!(values).to(be_equal_to(vec![ RType::Integer32(100), RType::Integer32(110), RType::Integer32(120) ]));
+  }
+
+  #[test]
+  fn construct_message_field_with_a_repeated_bytes_field() {
+    let descriptor = FieldDescriptorProto {
+      name: Some("chunks".to_string()),
+      number: Some(1),
+      label: Some(Label::Repeated as i32),
+      r#type: Some(Type::Bytes as i32),
+      .. FieldDescriptorProto::default()
+    };
+    let message_descriptor = DescriptorProto {
+      name: Some("Test".to_string()),
+      field: vec![ descriptor ],
+      .. DescriptorProto::default()
+    };
+    let file_descriptor = FileDescriptorProto::default();
+    let mut message_builder = MessageBuilder::new(&message_descriptor, "Test", &file_descriptor);
+    let path = DocPath::new_unwrap("$.chunks");
+    let mut matching_rules = MatchingRuleCategory::empty("body");
+    let mut generators = hashmap!{};
+
+    let result = construct_message_field(&mut message_builder, &mut matching_rules,
+      &mut generators, "chunks", &json!(["AAEC", "aGVsbG8="]), &path, &hashmap!{});
+    expect!(result).to(be_ok());
+    let values = message_builder.fields.get("chunks").unwrap().values.iter()
+      .map(|v| v.rtype.clone())
+      .collect::<Vec<_>>();
+    expect!(values).to(be_equal_to(vec![ RType::Bytes(vec![0, 1, 2]), RType::Bytes("hello".as_bytes().to_vec()) ]));
+
+    let mut message_builder = MessageBuilder::new(&message_descriptor, "Test", &file_descriptor);
+    let result = construct_message_field(&mut message_builder, &mut matching_rules,
+      &mut generators, "chunks", &json!(["AAEC", "not base64!"]), &path, &hashmap!{});
+    expect!(result.unwrap_err().to_string()).to(be_equal_to(
+      "Values for repeated bytes field 'chunks' must be Base64 encoded strings, 'not base64!' is not valid - Invalid symbol 32, offset 3."));
+  }
+
+  #[test]
+  fn construct_message_field_with_a_config_nested_beyond_the_maximum_depth() {
+    let message_descriptor = DescriptorProto {
+      name: Some("Node".to_string()),
+      field: vec![
+        FieldDescriptorProto {
+          name: Some("name".to_string()),
+          number: Some(1),
+          r#type: Some(Type::String as i32),
+          .. FieldDescriptorProto::default()
+        },
+        FieldDescriptorProto {
+          name: Some("child".to_string()),
+          number: Some(2),
+          r#type: Some(Type::Message as i32),
+          type_name: Some(".Node".to_string()),
+          .. FieldDescriptorProto::default()
+        }
+      ],
+      .. DescriptorProto::default()
+    };
+    let file_descriptor = FileDescriptorProto {
+      name: Some("node.proto".to_string()),
+      message_type: vec![ message_descriptor.clone() ],
+      .. FileDescriptorProto::default()
+    };
+    let all_descriptors = hashmap!{ "node.proto".to_string() => &file_descriptor };
+    let nested_config = |depth: usize| (0..depth)
+      .fold(json!({ "name": "leaf" }), |config, _| json!({ "name": "node", "child": config }));
+    let path = DocPath::new_unwrap("$.child");
+
+    let mut message_builder = MessageBuilder::new(&message_descriptor, "Node", &file_descriptor);
+    let result = construct_message_field(&mut message_builder, &mut MatchingRuleCategory::empty("body"),
+      &mut hashmap!{}, "child", &nested_config(DEFAULT_MAX_NESTING_DEPTH - 1), &path, &all_descriptors);
+    expect!(result).to(be_ok());
+
+    let mut message_builder = MessageBuilder::new(&message_descriptor, "Node", &file_descriptor);
+    let result = construct_message_field(&mut message_builder, &mut MatchingRuleCategory::empty("body"),
+      &mut hashmap!{}, "child", &nested_config(DEFAULT_MAX_NESTING_DEPTH), &path, &all_descriptors);
+    expect!(result.unwrap_err().to_string().ends_with(
+      "is nested 33 levels deep, which exceeds the maximum nesting depth of 32")).to(be_true());
+  }
+
+  #[rstest::rstest]
+  #[case::type_matcher("matching(type, '')", Some(pact_models::matchingrules::MatchingRule::Type))]
+  #[case::not_empty_matcher("notEmpty('address')", Some(pact_models::matchingrules::MatchingRule::NotEmpty))]
+  #[case::other_matcher("matching(regex, '\\w+', 'address')", None)]
+  fn construct_message_field_with_an_embedded_message_placeholder(
+    #[case] placeholder: &str,
+    #[case] expected_rule: Option<pact_models::matchingrules::MatchingRule>
+  ) {
+    let address_descriptor = DescriptorProto {
+      name: Some("Address".to_string()),
+      field: vec![
+        FieldDescriptorProto {
+          name: Some("street".to_string()),
+          number: Some(1),
+          r#type: Some(Type::String as i32),
+          .. FieldDescriptorProto::default()
+        }
+      ],
+      .. DescriptorProto::default()
+    };
+    let message_descriptor = DescriptorProto {
+      name: Some("Order".to_string()),
+      field: vec![
+        FieldDescriptorProto {
+          name: Some("address".to_string()),
+          number: Some(1),
+          r#type: Some(Type::Message as i32),
+          type_name: Some(".Address".to_string()),
+          .. FieldDescriptorProto::default()
+        }
+      ],
+      .. DescriptorProto::default()
+    };
+    let file_descriptor = FileDescriptorProto {
+      name: Some("order.proto".to_string()),
+      message_type: vec![ message_descriptor.clone(), address_descriptor.clone() ],
+      .. FileDescriptorProto::default()
+    };
+    let all_descriptors = hashmap!{ "order.proto".to_string() => &file_descriptor };
+    let mut message_builder = MessageBuilder::new(&message_descriptor, "Order", &file_descriptor);
+    let path = DocPath::new_unwrap("$.address");
+    let mut matching_rules = MatchingRuleCategory::empty("body");
+
+    let result = construct_message_field(&mut message_builder, &mut matching_rules,
+      &mut hashmap!{}, "address", &json!(placeholder), &path, &all_descriptors);
+    match expected_rule {
+      Some(rule) => {
+        expect!(result).to(be_ok());
+        expect!(matching_rules).to(be_equal_to(matchingrules_list! { "body"; "$.address" => [ rule ] }));
+        let values = message_builder.fields.get("address").unwrap().values.iter()
+          .map(|v| v.rtype.clone())
+          .collect::<Vec<_>>();
+        expect!(values).to(be_equal_to(vec![
+          RType::Message(Box::new(MessageBuilder::new(&address_descriptor, "Address", &file_descriptor)))
+        ]));
+        // An empty embedded message is encoded with a length of zero
+        expect!(message_builder.encode_message().unwrap().to_vec()).to(be_equal_to(vec![10, 0]));
+      }
+      None => {
+        expect!(result.unwrap_err().to_string()).to(be_equal_to(format!(
+          "Only type or notEmpty matchers can be used as a placeholder for a message field, got '{}'", placeholder)));
+      }
+    }
+  }
+
+  #[test]
+  fn construct_message_field_with_a_matcher_for_a_repeated_field() {
+    let descriptor = FieldDescriptorProto {
+      name: Some("numbers".to_string()),
+      number: Some(1),
+      label: Some(Label::Repeated as i32),
+      r#type: Some(Type::Int32 as i32),
+      .. FieldDescriptorProto::default()
+    };
+    let message_descriptor = DescriptorProto {
+      name: Some("Test".to_string()),
+      field: vec![ descriptor ],
+      .. DescriptorProto::default()
+    };
+    let file_descriptor = FileDescriptorProto::default();
+    let mut message_builder = MessageBuilder::new(&message_descriptor, "Test", &file_descriptor);
+    let path = DocPath::new_unwrap("$.numbers");
+    let mut matching_rules = MatchingRuleCategory::empty("body");
+    let mut generators = hashmap!{};
+
+    let result = construct_message_field(&mut message_builder, &mut matching_rules,
+      &mut generators, "numbers", &json!("matching(number, 1)"), &path, &hashmap!{});
+    expect!(result).to(be_ok());
+    expect!(matching_rules).to(be_equal_to(matchingrules_list! {
+      "body"; "$.numbers.*" => [ pact_models::matchingrules::MatchingRule::Number ]
+    }));
+  }
+
+  #[test_log::test]
+  fn construct_message_field_with_a_matcher_for_a_repeated_enum_field() {
+    let enum_descriptor = EnumDescriptorProto {
+      name: Some("Value".to_string()),
+      value: ["VALUE0", "VALUE1", "VALUE2", "VALUE3"].iter().enumerate().map(|(i, name)| EnumValueDescriptorProto {
+        name: Some(name.to_string()),
+        number: Some(i as i32),
+        options: None
+      }).collect(),
+      .. EnumDescriptorProto::default()
+    };
+    let descriptor = FieldDescriptorProto {
+      name: Some("values".to_string()),
+      number: Some(1),
+      label: Some(Label::Repeated as i32),
+      r#type: Some(Type::Enum as i32),
+      type_name: Some(".Test.Value".to_string()),
+      .. FieldDescriptorProto::default()
+    };
+    let message_descriptor = DescriptorProto {
+      name: Some("Test".to_string()),
+      field: vec![ descriptor.clone() ],
+      enum_type: vec![ enum_descriptor.clone() ],
+      .. DescriptorProto::default()
+    };
+    let file_descriptor = FileDescriptorProto {
+      name: Some("test.proto".to_string()),
+      message_type: vec![ message_descriptor.clone() ],
+      syntax: Some("proto3".to_string()),
+      .. FileDescriptorProto::default()
+    };
+    let file_descriptors = hashmap!{ "test.proto".to_string() => &file_descriptor };
+    let mut message_builder = MessageBuilder::new(&message_descriptor, "Test", &file_descriptor);
+    let mut matching_rules = MatchingRuleCategory::empty("body");
+
+    construct_message_field(&mut message_builder, &mut matching_rules, &mut hashmap!{}, "values",
+      &json!("matching(regex, 'VALUE1|VALUE2', 'VALUE1')"), &DocPath::new_unwrap("$.values"),
+      &file_descriptors).unwrap();
+    expect!(matching_rules.clone()).to(be_equal_to(matchingrules_list! {
+      "body"; "$.values.*" => [ pact_models::matchingrules::MatchingRule::Regex("VALUE1|VALUE2".to_string()) ]
+    }));
+
+    // Matchers for the values in a list apply to that value only
+    let mut list_builder = MessageBuilder::new(&message_descriptor, "Test", &file_descriptor);
+    let mut list_matching_rules = MatchingRuleCategory::empty("body");
+    construct_message_field(&mut list_builder, &mut list_matching_rules, &mut hashmap!{}, "values",
+      &json!(["matching(regex, 'VALUE1|VALUE2', 'VALUE1')", "VALUE3"]), &DocPath::new_unwrap("$.values"),
+      &file_descriptors).unwrap();
+    expect!(list_matching_rules).to(be_equal_to(matchingrules_list! {
+      "body"; "$.values[0]" => [ pact_models::matchingrules::MatchingRule::Regex("VALUE1|VALUE2".to_string()) ]
+    }));
+
+    let fds = FileDescriptorSet { file: vec![ file_descriptor.clone() ] };
+    let expected = message_builder.encode_message().unwrap();
+    // Test { values: [VALUE2, VALUE1, VALUE2] }
+    let result = match_message(".Test", &fds, &mut expected.clone(), &mut Bytes::from_static(&[10, 3, 2, 1, 2]),
+      &matching_rules, false, false, &hashmap!{}).unwrap();
+    expect!(result).to(be_equal_to(BodyMatchResult::Ok));
+
+    // Test { values: [VALUE1, VALUE3] }
+    let result = match_message(".Test", &fds, &mut expected.clone(), &mut Bytes::from_static(&[10, 2, 1, 3]),
+      &matching_rules, false, false, &hashmap!{}).unwrap();
+    expect!(result.mismatches().iter().map(|m| m.description()).collect::<Vec<_>>()).to(be_equal_to(vec![
+      "$.values[1] -> Expected 'VALUE3' to match 'VALUE1|VALUE2'".to_string()
+    ]));
+  }
+
+  #[test]
+  fn construct_message_field_with_length_matchers_for_a_repeated_field() {
+    let descriptor = FieldDescriptorProto {
+      name: Some("numbers".to_string()),
+      number: Some(1),
+      label: Some(Label::Repeated as i32),
+      r#type: Some(Type::String as i32),
+      .. FieldDescriptorProto::default()
+    };
+    let message_descriptor = DescriptorProto {
+      name: Some("Test".to_string()),
+      field: vec![ descriptor ],
+      .. DescriptorProto::default()
+    };
+    let file_descriptor = FileDescriptorProto::default();
+    let mut message_builder = MessageBuilder::new(&message_descriptor, "Test", &file_descriptor);
+    let path = DocPath::new_unwrap("$.numbers");
+    let mut matching_rules = MatchingRuleCategory::empty("body");
+    let mut generators = hashmap!{};
+
+    let result = construct_message_field(&mut message_builder, &mut matching_rules,
+      &mut generators, "numbers", &json!("atLeast(2), atMost(5), eachValue(matching(type, '100'))"),
+      &path, &hashmap!{});
+    expect!(result).to(be_ok());
+    expect!(matching_rules.rules.get(&path).map(|rules| rules.rules.clone())).to(be_some().value(vec![
+      pact_models::matchingrules::MatchingRule::MinType(2),
+      pact_models::matchingrules::MatchingRule::MaxType(5),
+      pact_models::matchingrules::MatchingRule::EachValue(MatchingRuleDefinition::new("100".to_string(),
+        ValueType::Unknown, pact_models::matchingrules::MatchingRule::Type, None))
+    ]));
+  }
+
+  #[rstest::rstest]
+  #[case::string(json!("name, address.city"))]
+  #[case::list(json!(["name", "address.city"]))]
+  fn construct_message_field_with_a_field_mask(#[case] value: Value) {
+    let field_mask_descriptor = DescriptorProto {
+      name: Some("FieldMask".to_string()),
+      field: vec![
+        FieldDescriptorProto {
+          name: Some("paths".to_string()),
+          number: Some(1),
+          label: Some(Label::Repeated as i32),
+          r#type: Some(Type::String as i32),
+          .. FieldDescriptorProto::default()
+        }
+      ],
+      .. DescriptorProto::default()
+    };
+    let field_mask_file = FileDescriptorProto {
+      name: Some("google/protobuf/field_mask.proto".to_string()),
+      package: Some("google.protobuf".to_string()),
+      message_type: vec![ field_mask_descriptor ],
+      .. FileDescriptorProto::default()
+    };
+    let message_descriptor = DescriptorProto {
+      name: Some("Test".to_string()),
+      field: vec![
+        FieldDescriptorProto {
+          name: Some("update_mask".to_string()),
+          number: Some(1),
+          r#type: Some(Type::Message as i32),
+          type_name: Some(".google.protobuf.FieldMask".to_string()),
+          .. FieldDescriptorProto::default()
+        }
+      ],
+      .. DescriptorProto::default()
+    };
+    let file_descriptor = FileDescriptorProto::default();
+    let all_descriptors = hashmap!{ "google/protobuf/field_mask.proto".to_string() => &field_mask_file };
+    let mut message_builder = MessageBuilder::new(&message_descriptor, "Test", &file_descriptor);
+    let path = DocPath::new_unwrap("$.update_mask");
+    let mut matching_rules = MatchingRuleCategory::empty("body");
+    let mut generators = hashmap!{};
+
+    let result = construct_message_field(&mut message_builder, &mut matching_rules,
+      &mut generators, "update_mask", &value, &path, &all_descriptors);
+    expect!(result).to(be_ok());
+    let field = message_builder.fields.get("update_mask").unwrap();
+    if let RType::Message(builder) = &field.values[0].rtype {
+      let paths = builder.fields.get("paths").unwrap().values.iter()
+        .map(|v| v.rtype.clone())
+        .collect::<Vec<_>>();
+      expect!(paths).to(be_equal_to(vec![
+        RType::String("name".to_string()),
+        RType::String("address.city".to_string())
+      ]));
+    } else {
+      panic!("Expected a message value, got {:?}", field.values[0].rtype);
+    }
+  }
+
+  #[test]
+  fn construct_protobuf_interaction_for_message_test() {
+    // construct_protobuf_interaction_for_message doesn't actually verify 
+    // that the message descriptor is part of a file descriptor
+    // so it doesn't have to be here as well
+    // It will still assume that the message came from this file descriptor and will use the package field from
+    // the file descriptor as the message package too.
+    let file_descriptor = FileDescriptorProto {
+      name: Some("test_file.proto".to_string()),
+      package: Some("test_package".to_string()),
+      dependency: vec![],
+      public_dependency: vec![],
+      weak_dependency: vec![],
+      message_type: vec![],
+      enum_type: vec![],
+      service: vec![],
+      extension: vec![],
+      options: None,
+      source_code_info: None,
+      syntax: None
+    };
+    let message_descriptor = DescriptorProto {
+      name: Some("test_message".to_string()),
+      field: vec![
+        FieldDescriptorProto {
+          name: Some("implementation".to_string()),
+          number: Some(1),
+          label: None,
+          r#type: Some(field_descriptor_proto::Type::String as i32),
+          type_name: Some("string".to_string()),
+          extendee: None,
+          default_value: None,
+          oneof_index: None,
+          json_name: None,
+          options: None,
+          proto3_optional: None
+        },
+        FieldDescriptorProto {
+          name: Some("version".to_string()),
+          number: Some(2),
+          label: None,
+          r#type: Some(field_descriptor_proto::Type::String as i32),
+          type_name: Some("string".to_string()),
+          extendee: None,
+          default_value: None,
+          oneof_index: None,
+          json_name: None,
+          options: None,
+          proto3_optional: None
+        },
+        FieldDescriptorProto {
+          name: Some("length".to_string()),
+          number: Some(3),
+          label: None,
+          r#type: Some(field_descriptor_proto::Type::Int64 as i32),
+          type_name: Some("int64".to_string()),
+          extendee: None,
+          default_value: None,
+          oneof_index: None,
+          json_name: None,
+          options: None,
+          proto3_optional: None
+        },
+        FieldDescriptorProto {
+          name: Some("hash".to_string()),
+          number: Some(4),
+          label: None,
+          r#type: Some(field_descriptor_proto::Type::Uint64 as i32),
+          type_name: Some("uint64".to_string()),
+          extendee: None,
+          default_value: None,
+          oneof_index: None,
+          json_name: None,
+          options: None,
+          proto3_optional: None
+        }
+      ],
+      extension: vec![],
+      nested_type: vec![],
+      enum_type: vec![],
+      extension_range: vec![],
+      oneof_decl: vec![],
+      options: None,
+      reserved_range: vec![],
+      reserved_name: vec![]
+    };
+    let config = btreemap! {
+      "implementation".to_string() => prost_types::Value { kind: Some(prost_types::value::Kind::StringValue("notEmpty('plugin-driver-rust')".to_string())) },
+      "version".to_string() => prost_types::Value { kind: Some(prost_types::value::Kind::StringValue("matching(semver, '0.0.0')".to_string())) },
+      "hash".to_string() => prost_types::Value { kind: Some(prost_types::value::Kind::StringValue("matching(integer, 1234)".to_string())) }
+    };
+
+    let result = construct_protobuf_interaction_for_message(&message_descriptor, &config,
+      "", &file_descriptor, &hashmap!{}, None).unwrap();
+
+    let body = result.contents.as_ref().unwrap();
+    expect!(body.content_type.as_str()).to(be_equal_to("application/protobuf;message=.test_package.test_message"));
+    expect!(body.content_type_hint).to(be_equal_to(2));
+    expect!(body.content.as_ref()).to(be_some().value(&vec![
+      10, // field 1 length encoded (1 << 3 + 2 == 10)
+      18, // 18 bytes
+      112, 108, 117, 103, 105, 110, 45, 100, 114, 105, 118, 101, 114, 45, 114, 117, 115, 116,
+      18, // field 2 length encoded (2 << 3 + 2 == 18)
+      5, // 5 bytes
+      48, 46, 48, 46, 48,
+      32, // field 4 varint encoded (4 << 3 + 0 == 32)
+      210, 9 // 9 << 7 + 210 == 1234
+    ]));
+
+    expect!(result.rules).to(be_equal_to(hashmap! {
+      "$.implementation".to_string() => MatchingRules { rule: vec![ MatchingRule { r#type: "not-empty".to_string(), .. MatchingRule::default() } ] },
+      "$.version".to_string() => MatchingRules { rule: vec![ MatchingRule { r#type: "semver".to_string(), .. MatchingRule::default() } ] },
+      "$.hash".to_string() => MatchingRules { rule: vec![ MatchingRule { r#type: "integer".to_string(), .. MatchingRule::default() } ] }
+    }));
+
+    expect!(result.generators).to(be_equal_to(hashmap! {}));
+
+    expect!(result.interaction_markup_type).to(be_equal_to(MarkupType::CommonMark as i32));
+    expect!(result.interaction_markup).to(be_equal_to(
+     "|```protobuf
+      |message test_message {
+      |    string implementation = 1;
+      |    string version = 2;
+      |    uint64 hash = 4;
+      |}
+      |```
+      |".trim_margin().unwrap()));
+  }
+
+  #[test]
+  fn construct_protobuf_interaction_for_message_with_a_base64_encoded_message() {
+    let file_descriptor = FileDescriptorProto {
+      name: Some("test_file.proto".to_string()),
+      package: Some("test_package".to_string()),
+      .. FileDescriptorProto::default()
+    };
+    let string_field = |name: &str, number: i32| FieldDescriptorProto {
+      name: Some(name.to_string()),
+      number: Some(number),
+      r#type: Some(field_descriptor_proto::Type::String as i32),
+      .. FieldDescriptorProto::default()
+    };
+    let message_descriptor = DescriptorProto {
+      name: Some("test_message".to_string()),
+      field: vec![
+        string_field("implementation", 1),
+        string_field("version", 2),
+        FieldDescriptorProto {
+          name: Some("hash".to_string()),
+          number: Some(4),
+          r#type: Some(field_descriptor_proto::Type::Uint64 as i32),
+          .. FieldDescriptorProto::default()
+        }
+      ],
+      .. DescriptorProto::default()
+    };
+    let config = btreemap! {
+      "value".to_string() => prost_string("ChJwbHVnaW4tZHJpdmVyLXJ1c3QSBTAuMC4wINIJ")
+    };
+
+    let result = construct_protobuf_interaction_for_message(&message_descriptor, &config,
+      "", &file_descriptor, &hashmap!{}, None).unwrap();
+
+    let body = result.contents.as_ref().unwrap();
+    expect!(body.content_type.as_str()).to(be_equal_to("application/protobuf;message=.test_package.test_message"));
+    expect!(body.content.as_ref()).to(be_some().value(&BASE64.decode("ChJwbHVnaW4tZHJpdmVyLXJ1c3QSBTAuMC4wINIJ").unwrap()));
+    expect!(result.rules).to(be_equal_to(hashmap! {
+      "$.implementation".to_string() => MatchingRules { rule: vec![ MatchingRule { r#type: "type".to_string(), .. MatchingRule::default() } ] },
+      "$.version".to_string() => MatchingRules { rule: vec![ MatchingRule { r#type: "type".to_string(), .. MatchingRule::default() } ] },
+      "$.hash".to_string() => MatchingRules { rule: vec![ MatchingRule { r#type: "type".to_string(), .. MatchingRule::default() } ] }
+    }));
+    expect!(result.interaction_markup).to(be_equal_to(
+     "|```protobuf
+      |message test_message {
+      |    string implementation = 1;
+      |    string version = 2;
+      |    uint64 hash = 4;
+      |}
+      |```
+      |".trim_margin().unwrap()));
+
+    let config = btreemap! {
+      "value".to_string() => prost_string("not base64!")
+    };
+    let result = construct_protobuf_interaction_for_message(&message_descriptor, &config,
+      "", &file_descriptor, &hashmap!{}, None);
+    expect!(result).to(be_err());
+  }
+
+  #[test]
+  fn construct_protobuf_interaction_for_message_with_a_base64_encoded_message_with_a_repeated_field() {
+    let file_descriptor = FileDescriptorProto {
+      name: Some("test_file.proto".to_string()),
+      package: Some("test_package".to_string()),
+      .. FileDescriptorProto::default()
+    };
+    let message_descriptor = DescriptorProto {
+      name: Some("test_message".to_string()),
+      field: vec![
+        FieldDescriptorProto {
+          name: Some("tags".to_string()),
+          number: Some(1),
+          label: Some(Label::Repeated as i32),
+          r#type: Some(field_descriptor_proto::Type::String as i32),
+          .. FieldDescriptorProto::default()
+        }
+      ],
+      .. DescriptorProto::default()
+    };
+    // tags: ["a", "b"]
+    let config = btreemap! {
+      "value".to_string() => prost_string("CgFhCgFi")
+    };
+
+    let result = construct_protobuf_interaction_for_message(&message_descriptor, &config,
+      "", &file_descriptor, &hashmap!{}, None).unwrap();
+
+    expect!(result.rules).to(be_equal_to(hashmap! {
+      "$.tags".to_string() => MatchingRules { rule: vec![ MatchingRule { r#type: "type".to_string(), .. MatchingRule::default() } ] }
+    }));
+  }
+
+  #[test]
+  fn construct_protobuf_interaction_for_message_with_a_base64_encoded_message_renders_the_fields_in_order_with_their_types() {
+    let field = |name: &str, number: i32, field_type: field_descriptor_proto::Type, type_name: Option<&str>| FieldDescriptorProto {
+      name: Some(name.to_string()),
+      number: Some(number),
+      r#type: Some(field_type as i32),
+      type_name: type_name.map(|name| name.to_string()),
+      .. FieldDescriptorProto::default()
+    };
+    let message_descriptor = DescriptorProto {
+      name: Some("test_message".to_string()),
+      field: vec![
+        field("implementation", 1, field_descriptor_proto::Type::String, None),
+        field("status", 2, field_descriptor_proto::Type::Enum, Some(".test_package.Status")),
+        field("child", 3, field_descriptor_proto::Type::Message, Some(".test_package.Child")),
+        FieldDescriptorProto {
+          label: Some(Label::Repeated as i32),
+          .. field("counts", 4, field_descriptor_proto::Type::Message, Some(".test_package.test_message.CountsEntry"))
+        }
+      ],
+      nested_type: vec![
+        DescriptorProto {
+          name: Some("CountsEntry".to_string()),
+          field: vec![
+            field("key", 1, field_descriptor_proto::Type::String, None),
+            field("value", 2, field_descriptor_proto::Type::Int32, None)
+          ],
+          options: Some(MessageOptions {
+            map_entry: Some(true),
+            .. MessageOptions::default()
+          }),
+          .. DescriptorProto::default()
+        }
+      ],
+      .. DescriptorProto::default()
+    };
+    let file_descriptor = FileDescriptorProto {
+      name: Some("test_file.proto".to_string()),
+      package: Some("test_package".to_string()),
+      message_type: vec![
+        message_descriptor.clone(),
+        DescriptorProto {
+          name: Some("Child".to_string()),
+          field: vec![ field("id", 1, field_descriptor_proto::Type::Int32, None) ],
+          .. DescriptorProto::default()
+        }
+      ],
+      enum_type: vec![
+        EnumDescriptorProto {
+          name: Some("Status".to_string()),
+          value: ["UNKNOWN", "ACTIVE"].iter().enumerate().map(|(i, name)| EnumValueDescriptorProto {
+            name: Some(name.to_string()),
+            number: Some(i as i32),
+            .. EnumValueDescriptorProto::default()
+          }).collect(),
+          .. EnumDescriptorProto::default()
+        }
+      ],
+      .. FileDescriptorProto::default()
+    };
+    // The fields are encoded in reverse order (counts, child, status, implementation)
+    let config = btreemap! {
+      "value".to_string() => prost_string("IgUKAWEQAhoCCAcQAQoDYWJj")
+    };
+
+    let result = construct_protobuf_interaction_for_message(&message_descriptor, &config,
+      "", &file_descriptor, &hashmap!{ "test_file.proto".to_string() => &file_descriptor }, None).unwrap();
+
+    expect!(result.interaction_markup).to(be_equal_to(
+     "|```protobuf
+      |message test_message {
+      |    string implementation = 1;
+      |    enum .test_package.Status status = 2;
+      |    message .test_package.Child child = 3;
+      |    map<string, int32> counts = 4;
+      |}
+      |```
+      |".trim_margin().unwrap()));
+  }
+
+  #[test_log::test]
+  fn construct_message_field_with_different_expectations_for_each_repeated_message() {
+    let item_descriptor = DescriptorProto {
+      name: Some("Item".to_string()),
+      field: vec![
+        FieldDescriptorProto {
+          name: Some("name".to_string()),
+          number: Some(1),
+          r#type: Some(Type::String as i32),
+          .. FieldDescriptorProto::default()
+        }
+      ],
+      .. DescriptorProto::default()
+    };
+    let message_descriptor = DescriptorProto {
+      name: Some("Items".to_string()),
+      field: vec![
+        FieldDescriptorProto {
+          name: Some("items".to_string()),
+          number: Some(1),
+          label: Some(Label::Repeated as i32),
+          r#type: Some(Type::Message as i32),
+          type_name: Some(".Item".to_string()),
+          .. FieldDescriptorProto::default()
+        }
+      ],
+      .. DescriptorProto::default()
+    };
+    let file_descriptor = FileDescriptorProto {
+      name: Some("items.proto".to_string()),
+      message_type: vec![ message_descriptor.clone(), item_descriptor.clone() ],
+      syntax: Some("proto3".to_string()),
+      .. FileDescriptorProto::default()
+    };
+    let file_descriptors = hashmap!{ "items.proto".to_string() => &file_descriptor };
+    let mut message_builder = MessageBuilder::new(&message_descriptor, "Items", &file_descriptor);
+    let mut matching_rules = MatchingRuleCategory::empty("body");
+    let mut generators = hashmap!{};
+    let config = json!([
+      { "name": "matching(regex, '^a.*', 'apple')" },
+      { "name": "matching(regex, '^b.*', 'banana')" }
+    ]);
+
+    construct_message_field(&mut message_builder, &mut matching_rules, &mut generators,
+      "items", &config, &DocPath::new_unwrap("$.items"), &file_descriptors).unwrap();
+    expect!(matching_rules.clone()).to(be_equal_to(matchingrules_list! {
+      "body";
+      "$.items[0].name" => [ pact_models::matchingrules::MatchingRule::Regex("^a.*".to_string()) ],
+      "$.items[1].name" => [ pact_models::matchingrules::MatchingRule::Regex("^b.*".to_string()) ]
+    }));
+
+    let fds = FileDescriptorSet { file: vec![ file_descriptor.clone() ] };
+    let expected = message_builder.encode_message().unwrap();
+    let actual = |names: [&str; 2]| {
+      let mut builder = MessageBuilder::new(&message_descriptor, "Items", &file_descriptor);
+      construct_message_field(&mut builder, &mut MatchingRuleCategory::empty("body"), &mut hashmap!{},
+        "items", &json!([ { "name": names[0] }, { "name": names[1] } ]),
+        &DocPath::new_unwrap("$.items"), &file_descriptors).unwrap();
+      builder.encode_message().unwrap()
+    };
+
+    let result = match_message(".Items", &fds, &mut expected.clone(), &mut actual(["avocado", "blueberry"]),
+      &matching_rules, false, false, &hashmap!{}).unwrap();
+    expect!(result).to(be_equal_to(BodyMatchResult::Ok));
+
+    let result = match_message(".Items", &fds, &mut expected.clone(), &mut actual(["blueberry", "avocado"]),
+      &matching_rules, false, false, &hashmap!{}).unwrap();
+    let mismatches = result.mismatches().iter()
+      .map(|mismatch| match mismatch {
+        pact_matching::Mismatch::BodyMismatch { path, .. } => path.clone(),
+        _ => mismatch.description()
+      })
+      .collect::<Vec<_>>();
+    expect!(mismatches).to(be_equal_to(vec![ "$.items[0].name".to_string(), "$.items[1].name".to_string() ]));
+  }
+
+  #[test]
+  fn matching_rule_types_test() {
+    let string_field = |name: &str, number: i32| FieldDescriptorProto {
+      name: Some(name.to_string()),
+      number: Some(number),
+      r#type: Some(Type::String as i32),
+      .. FieldDescriptorProto::default()
+    };
+    let message_descriptor = DescriptorProto {
+      name: Some("Person".to_string()),
+      field: vec![
+        string_field("id", 1),
+        string_field("name", 2),
+        string_field("ssn", 3),
+        FieldDescriptorProto {
+          name: Some("tags".to_string()),
+          number: Some(4),
+          label: Some(Label::Repeated as i32),
+          r#type: Some(Type::String as i32),
+          .. FieldDescriptorProto::default()
+        }
+      ],
+      .. DescriptorProto::default()
+    };
+    let file_descriptor = FileDescriptorProto {
+      name: Some("test.proto".to_string()),
+      package: Some("test".to_string()),
+      message_type: vec![ message_descriptor.clone() ],
+      .. FileDescriptorProto::default()
+    };
+    let config = btreemap!{
+      "id".to_string() => prost_string("matching(regex, '\\d+', '100')"),
+      "name".to_string() => prost_string("matching(type, 'Fred')"),
+      "ssn".to_string() => prost_string("123-45-6789"),
+      "tags".to_string() => prost_string("atLeast(1), eachValue(matching(type, 'tag'))")
+    };
+    let all_descriptors = hashmap!{ "test.proto".to_string() => &file_descriptor };
+
+    let interaction = construct_protobuf_interaction_for_message(&message_descriptor, &config, "",
+      &file_descriptor, &all_descriptors, None).unwrap();
+    expect!(matching_rule_types(&interaction)).to(be_equal_to(btreemap!{
+      "$.id".to_string() => vec!["regex".to_string()],
+      "$.name".to_string() => vec!["type".to_string()],
+      "$.tags".to_string() => vec!["min-type".to_string(), "each-value".to_string()]
+    }));
+  }
+
+  #[test]
+  fn encode_message_from_yaml_test() {
+    let message_descriptor = DescriptorProto {
+      name: Some("Test".to_string()),
+      field: vec![
+        FieldDescriptorProto {
+          name: Some("name".to_string()),
+          number: Some(1),
+          r#type: Some(Type::String as i32),
+          .. FieldDescriptorProto::default()
+        },
+        FieldDescriptorProto {
+          name: Some("count".to_string()),
+          number: Some(2),
+          r#type: Some(Type::Int32 as i32),
+          .. FieldDescriptorProto::default()
+        },
+        FieldDescriptorProto {
+          name: Some("tags".to_string()),
+          number: Some(3),
+          label: Some(Label::Repeated as i32),
+          r#type: Some(Type::String as i32),
+          .. FieldDescriptorProto::default()
+        }
+      ],
+      .. DescriptorProto::default()
+    };
+    let fds = FileDescriptorSet {
+      file: vec![
+        FileDescriptorProto {
+          name: Some("test.proto".to_string()),
+          package: Some("test".to_string()),
+          message_type: vec![ message_descriptor.clone() ],
+          syntax: Some("proto3".to_string()),
+          .. FileDescriptorProto::default()
+        }
+      ]
+    };
+    let config = "
+      |name: \"matching(type, 'test')\"
+      |count: 10
+      |tags:
+      |  - a
+      |  - b
+      |".trim_margin().unwrap();
+
+    let mut bytes = encode_message_from_yaml(".test.Test", config.as_str(), &fds).unwrap();
+    let fields = decode_message(&mut bytes, &message_descriptor, &fds).unwrap();
+    let values = fields.iter()
+      .map(|field| format!("{} = {}", field.field_name, field.data))
+      .collect::<Vec<_>>();
+    expect!(values).to(be_equal_to(vec![
+      "name = \"test\"".to_string(),
+      "count = 10".to_string(),
+      "tags = \"a\"".to_string(),
+      "tags = \"b\"".to_string()
+    ]));
+
+    expect!(encode_message_from_yaml(".test.Test", "- not a map", &fds)).to(be_err());
+    expect!(encode_message_from_yaml(".test.Test", "name: [", &fds)).to(be_err());
+  }
+
+  #[test]
+  fn construct_protobuf_interaction_for_message_from_a_top_level_reference() {
+    let file_descriptor = FileDescriptorProto {
+      name: Some("test_file.proto".to_string()),
+      package: Some("test_package".to_string()),
+      .. FileDescriptorProto::default()
+    };
+    let string_field = |name: &str, number: i32| FieldDescriptorProto {
+      name: Some(name.to_string()),
+      number: Some(number),
+      r#type: Some(field_descriptor_proto::Type::String as i32),
+      .. FieldDescriptorProto::default()
+    };
+    let message_descriptor = DescriptorProto {
+      name: Some("test_message".to_string()),
+      field: vec![
+        string_field("implementation", 1),
+        string_field("version", 2),
+        FieldDescriptorProto {
+          name: Some("hash".to_string()),
+          number: Some(4),
+          r#type: Some(field_descriptor_proto::Type::Uint64 as i32),
+          .. FieldDescriptorProto::default()
+        }
+      ],
+      .. DescriptorProto::default()
+    };
+    let example = prost_types::Value {
+      kind: Some(StructValue(Struct {
+        fields: btreemap! {
+          "implementation".to_string() => prost_string("plugin-driver-rust"),
+          "version".to_string() => prost_string("matching(semver, '0.0.0')"),
+          "hash".to_string() => prost_types::Value { kind: Some(NumberValue(1234.0)) }
+        }
+      }))
+    };
+    let config = btreemap! {
+      "pact:match".to_string() => prost_string("matching($'example')"),
+      "example".to_string() => example.clone()
+    };
+
+    let result = construct_protobuf_interaction_for_message(&message_descriptor, &config,
+      "", &file_descriptor, &hashmap!{}, None).unwrap();
+
+    let body = result.contents.as_ref().unwrap();
+    expect!(body.content.as_ref()).to(be_some().value(&BASE64.decode("ChJwbHVnaW4tZHJpdmVyLXJ1c3QSBTAuMC4wINIJ").unwrap()));
+    expect!(result.rules).to(be_equal_to(hashmap! {
+      "$".to_string() => MatchingRules { rule: vec![ MatchingRule { r#type: "type".to_string(), .. MatchingRule::default() } ] },
+      "$.version".to_string() => MatchingRules { rule: vec![ MatchingRule { r#type: "semver".to_string(), .. MatchingRule::default() } ] }
+    }));
+
+    let config = btreemap! {
+      "pact:match".to_string() => prost_string("matching($'missing')"),
+      "example".to_string() => example
+    };
+    let result = construct_protobuf_interaction_for_message(&message_descriptor, &config,
+      "", &file_descriptor, &hashmap!{}, None);
+    expect!(result.unwrap_err().to_string()).to(
+      be_equal_to("Expression 'matching($'missing')' refers to non-existent item 'missing'"));
+  }
+
+  #[test]
+  fn construct_protobuf_interaction_for_message_with_a_content_type_hint() {
+    let file_descriptor = FileDescriptorProto {
+      name: Some("test_file.proto".to_string()),
+      package: Some("test_package".to_string()),
+      .. FileDescriptorProto::default()
+    };
+    let message_descriptor = DescriptorProto {
+      name: Some("test_message".to_string()),
+      field: vec![
+        FieldDescriptorProto {
+          name: Some("implementation".to_string()),
+          number: Some(1),
+          r#type: Some(field_descriptor_proto::Type::String as i32),
+          .. FieldDescriptorProto::default()
+        }
+      ],
+      .. DescriptorProto::default()
+    };
+    let config_with_hint = |hint: &str| btreemap! {
+      "implementation".to_string() => prost_string("plugin-driver-rust"),
+      "pact:protobuf-config".to_string() => prost_types::Value {
+        kind: Some(StructValue(Struct {
+          fields: btreemap! { "contentTypeHint".to_string() => prost_string(hint) }
+        }))
+      }
+    };
+
+    let config = btreemap! {
+      "implementation".to_string() => prost_string("plugin-driver-rust")
+    };
+    let result = construct_protobuf_interaction_for_message(&message_descriptor, &config,
+      "", &file_descriptor, &hashmap!{}, None).unwrap();
+    expect!(result.contents.unwrap().content_type_hint).to(be_equal_to(ContentTypeHint::Binary as i32));
+
+    let result = construct_protobuf_interaction_for_message(&message_descriptor, &config_with_hint("text"),
+      "", &file_descriptor, &hashmap!{}, None).unwrap();
+    expect!(result.contents.unwrap().content_type_hint).to(be_equal_to(ContentTypeHint::Text as i32));
+
+    let result = construct_protobuf_interaction_for_message(&message_descriptor, &config_with_hint("binary"),
+      "", &file_descriptor, &hashmap!{}, None).unwrap();
+    expect!(result.contents.unwrap().content_type_hint).to(be_equal_to(ContentTypeHint::Binary as i32));
+
+    let result = construct_protobuf_interaction_for_message(&message_descriptor, &config_with_hint("other"),
+      "", &file_descriptor, &hashmap!{}, None);
+    expect!(result).to(be_err());
   }
 
   const DESCRIPTORS_FOR_EACH_VALUE_TEST: [u8; 267] = [
@@ -1601,31 +4605,126 @@ pub(crate) mod tests {
       .iter().map(|des| (des.name.clone().unwrap_or_default(), des))
       .collect();
 
-    let result = build_field_value(
-      &path, &mut message_builder, MessageFieldValueType::Repeated, field_descriptor,
-      "value", &Value::String("eachValue(matching(type, '00000000000000000000000000000000'))".to_string()),
-      &mut matching_rules, &mut generators, &file_descriptors
-    ).unwrap();
+    let result = build_field_value(
+      &path, &mut message_builder, MessageFieldValueType::Repeated, field_descriptor,
+      "value", &Value::String("eachValue(matching(type, '00000000000000000000000000000000'))".to_string()),
+      &mut matching_rules, &mut generators, &file_descriptors
+    ).unwrap();
+
+    expect!(result.as_ref()).to(be_some());
+    let message_field_value = result.unwrap();
+    expect!(message_field_value).to(be_equal_to(MessageFieldValue {
+      name: "value".to_string(),
+      raw_value: Some("00000000000000000000000000000000".to_string()),
+      rtype: RType::String("00000000000000000000000000000000".to_string())
+    }));
+    let field_value = message_builder.fields.get("value").unwrap();
+    expect!(field_value.values.as_ref()).to(be_equal_to(vec![
+      MessageFieldValue {
+        name: "value".to_string(),
+        raw_value: Some("00000000000000000000000000000000".to_string()),
+        rtype: RType::String("00000000000000000000000000000000".to_string())
+      }
+    ]));
+  }
+
+  #[test]
+  fn construct_protobuf_interaction_for_service_returns_error_on_invalid_request_type() {
+    let string_descriptor = DescriptorProto {
+      name: Some("StringValue".to_string()),
+      field: vec![
+        FieldDescriptorProto {
+          name: Some("value".to_string()),
+          number: Some(1),
+          label: None,
+          r#type: Some(field_descriptor_proto::Type::String as i32),
+          type_name: Some("string".to_string()),
+          extendee: None,
+          default_value: None,
+          oneof_index: None,
+          json_name: None,
+          options: None,
+          proto3_optional: None
+        }
+      ],
+      extension: vec![],
+      nested_type: vec![],
+      enum_type: vec![],
+      extension_range: vec![],
+      oneof_decl: vec![],
+      options: None,
+      reserved_range: vec![],
+      reserved_name: vec![]
+    };
+    let message_descriptor = DescriptorProto {
+      name: Some("test_message".to_string()),
+      field: vec![
+        FieldDescriptorProto {
+          name: Some("value".to_string()),
+          number: Some(1),
+          label: None,
+          r#type: Some(field_descriptor_proto::Type::String as i32),
+          type_name: Some("string".to_string()),
+          extendee: None,
+          default_value: None,
+          oneof_index: None,
+          json_name: None,
+          options: None,
+          proto3_optional: None
+        }
+      ],
+      extension: vec![],
+      nested_type: vec![],
+      enum_type: vec![],
+      extension_range: vec![],
+      oneof_decl: vec![],
+      options: None,
+      reserved_range: vec![],
+      reserved_name: vec![]
+    };
+    let file_descriptor: FileDescriptorProto = FileDescriptorProto {
+      name: Some("test_file.proto".to_string()),
+      package: Some("test_package".to_string()),
+      dependency: vec![],
+      public_dependency: vec![],
+      weak_dependency: vec![],
+      message_type: vec![ string_descriptor, message_descriptor ],
+      enum_type: vec![],
+      service: vec![],
+      extension: vec![],
+      options: None,
+      source_code_info: None,
+      syntax: None
+    };
+    let service_descriptor = ServiceDescriptorProto {
+      name: Some("test_service".to_string()),
+      method: vec![
+        MethodDescriptorProto {
+          name: Some("call".to_string()),
+          input_type: Some(".test_package.StringValue".to_string()),
+          output_type: Some(".test_package.test_message".to_string()),
+          options: None,
+          client_streaming: None,
+          server_streaming: None
+        }
+      ],
+      options: None
+    };
+
+    let config = btreemap! {
+      "request".to_string() => prost_types::Value { kind: Some(prost_types::value::Kind::BoolValue(true)) }
+    };
 
-    expect!(result.as_ref()).to(be_some());
-    let message_field_value = result.unwrap();
-    expect!(message_field_value).to(be_equal_to(MessageFieldValue {
-      name: "value".to_string(),
-      raw_value: Some("00000000000000000000000000000000".to_string()),
-      rtype: RType::String("00000000000000000000000000000000".to_string())
-    }));
-    let field_value = message_builder.fields.get("value").unwrap();
-    expect!(field_value.values.as_ref()).to(be_equal_to(vec![
-      MessageFieldValue {
-        name: "value".to_string(),
-        raw_value: Some("00000000000000000000000000000000".to_string()),
-        rtype: RType::String("00000000000000000000000000000000".to_string())
-      }
-    ]));
+    let result = construct_protobuf_interaction_for_service(
+      &service_descriptor, &config, "call", &hashmap!{ "file".to_string() => &file_descriptor });
+    expect!(result.as_ref()).to(be_err());
+    expect!(result.unwrap_err().to_string()).to(
+      be_equal_to("Request contents is of an un-processable type: BoolValue(true), it should be either a Struct or a StringValue")
+    );
   }
 
-  #[test]
-  fn construct_protobuf_interaction_for_service_returns_error_on_invalid_request_type() {
+  #[test_log::test]
+  fn construct_protobuf_interaction_for_service_supports_string_value_type() {
     let string_descriptor = DescriptorProto {
       name: Some("StringValue".to_string()),
       field: vec![
@@ -1678,7 +4777,7 @@ pub(crate) mod tests {
       reserved_range: vec![],
       reserved_name: vec![]
     };
-    let file_descriptor: FileDescriptorProto = FileDescriptorProto {
+    let file_descriptor = FileDescriptorProto {
       name: Some("test_file.proto".to_string()),
       package: Some("test_package".to_string()),
       dependency: vec![],
@@ -1697,65 +4796,643 @@ pub(crate) mod tests {
       method: vec![
         MethodDescriptorProto {
           name: Some("call".to_string()),
-          input_type: Some(".test_package.StringValue".to_string()),
-          output_type: Some(".test_package.test_message".to_string()),
-          options: None,
-          client_streaming: None,
-          server_streaming: None
+          input_type: Some(".test_package.StringValue".to_string()),
+          output_type: Some(".test_package.test_message".to_string()),
+          options: None,
+          client_streaming: None,
+          server_streaming: None
+        }
+      ],
+      options: None
+    };
+
+    let config = btreemap! {
+      "request".to_string() => prost_types::Value { kind: Some(prost_types::value::Kind::StringValue("true".to_string())) }
+    };
+
+    let result = construct_protobuf_interaction_for_service(
+      &service_descriptor, &config, "call", &hashmap!{ "file".to_string() => &file_descriptor });
+    expect!(result).to(be_ok());
+  }
+
+  #[test_log::test]
+  fn construct_protobuf_interaction_for_service_with_canonical_json_config() {
+    let field = |name: &str, number: i32, json_name: Option<&str>, r#type: Type, type_name: Option<&str>| FieldDescriptorProto {
+      name: Some(name.to_string()),
+      number: Some(number),
+      r#type: Some(r#type as i32),
+      type_name: type_name.map(|n| n.to_string()),
+      json_name: json_name.map(|n| n.to_string()),
+      .. FieldDescriptorProto::default()
+    };
+    let address_descriptor = DescriptorProto {
+      name: Some("Address".to_string()),
+      field: vec![ field("street_name", 1, Some("streetName"), Type::String, None) ],
+      .. DescriptorProto::default()
+    };
+    let request_descriptor = DescriptorProto {
+      name: Some("Request".to_string()),
+      field: vec![
+        field("order_id", 1, Some("orderId"), Type::String, None),
+        // No json_name in the descriptor, so it is derived from the field name
+        field("shipping_address", 2, None, Type::Message, Some(".test_package.Address")),
+        field("payload_data", 3, Some("payloadData"), Type::Bytes, None)
+      ],
+      .. DescriptorProto::default()
+    };
+    let file_descriptor = FileDescriptorProto {
+      name: Some("test_file.proto".to_string()),
+      package: Some("test_package".to_string()),
+      message_type: vec![ request_descriptor.clone(), address_descriptor.clone() ],
+      .. FileDescriptorProto::default()
+    };
+    let service_descriptor = ServiceDescriptorProto {
+      name: Some("test_service".to_string()),
+      method: vec![
+        MethodDescriptorProto {
+          name: Some("call".to_string()),
+          input_type: Some(".test_package.Request".to_string()),
+          output_type: Some(".test_package.Request".to_string()),
+          .. MethodDescriptorProto::default()
+        }
+      ],
+      options: None
+    };
+    let all_descriptors = hashmap!{ "test_file.proto".to_string() => &file_descriptor };
+    let config = |canonical_json: bool| serde_json::from_value::<BTreeMap<std::string::String, Value>>(json!({
+      "request": { "orderId": "100", "shippingAddress": { "streetName": "Main St" }, "payloadData": "AQID" },
+      "pact:protobuf-config": { "canonicalJson": canonical_json }
+    })).unwrap().iter()
+      .map(|(k, v)| (k.clone(), pact_plugin_driver::utils::to_proto_value(v)))
+      .collect::<BTreeMap<_, _>>();
+
+    let (request, _) = construct_protobuf_interaction_for_service(&service_descriptor, &config(true),
+      "call", &all_descriptors).unwrap();
+    let request = request.unwrap();
+    let mut expected = vec![10, 3];
+    expected.extend_from_slice("100".as_bytes());
+    expected.extend_from_slice(&[18, 9, 10, 7]);
+    expected.extend_from_slice("Main St".as_bytes());
+    // Bytes values are Base64 encoded in the canonical JSON form
+    expected.extend_from_slice(&[26, 3, 1, 2, 3]);
+    expect!(request.contents.unwrap().content.unwrap()).to(be_equal_to(expected));
+
+    let result = construct_protobuf_interaction_for_service(&service_descriptor, &config(false),
+      "call", &all_descriptors);
+    expect!(result.unwrap_err().to_string().starts_with("Message Request has no field 'orderId'")).to(be_true());
+  }
+
+  #[test_log::test]
+  fn construct_protobuf_interaction_for_service_with_echo_request() {
+    let message_descriptor = |name: &str| DescriptorProto {
+      name: Some(name.to_string()),
+      field: vec![
+        FieldDescriptorProto {
+          name: Some("id".to_string()),
+          number: Some(1),
+          r#type: Some(field_descriptor_proto::Type::String as i32),
+          .. FieldDescriptorProto::default()
+        }
+      ],
+      .. DescriptorProto::default()
+    };
+    let method_descriptor = |name: &str, output_type: &str| MethodDescriptorProto {
+      name: Some(name.to_string()),
+      input_type: Some(".test_package.Request".to_string()),
+      output_type: Some(output_type.to_string()),
+      .. MethodDescriptorProto::default()
+    };
+    let file_descriptor = FileDescriptorProto {
+      name: Some("test_file.proto".to_string()),
+      package: Some("test_package".to_string()),
+      message_type: vec![ message_descriptor("Request"), message_descriptor("Response") ],
+      .. FileDescriptorProto::default()
+    };
+    let service_descriptor = ServiceDescriptorProto {
+      name: Some("test_service".to_string()),
+      method: vec![
+        method_descriptor("echo", ".test_package.Request"),
+        method_descriptor("call", ".test_package.Response")
+      ],
+      options: None
+    };
+    let all_descriptors = hashmap!{ "test_file.proto".to_string() => &file_descriptor };
+    let config = serde_json::from_value::<BTreeMap<std::string::String, Value>>(json!({
+      "request": { "id": "100" },
+      "pact:protobuf-config": { "echoRequest": true }
+    })).unwrap().iter()
+      .map(|(k, v)| (k.clone(), pact_plugin_driver::utils::to_proto_value(v)))
+      .collect();
+
+    let result = construct_protobuf_interaction_for_service(&service_descriptor, &config,
+      "echo", &all_descriptors);
+    expect!(result).to(be_ok());
+
+    let result = construct_protobuf_interaction_for_service(&service_descriptor, &config,
+      "call", &all_descriptors);
+    expect!(result.unwrap_err().to_string()).to(be_equal_to(
+      "'echoRequest' can only be used with methods that have the same input and output message types, \
+      but test_service/call has an input type of '.test_package.Request' and an output type of '.test_package.Response'"));
+  }
+
+  #[test_log::test]
+  fn construct_protobuf_interaction_for_service_with_external_matching_rules() {
+    let string_field = |name: &str, number: i32| FieldDescriptorProto {
+      name: Some(name.to_string()),
+      number: Some(number),
+      r#type: Some(field_descriptor_proto::Type::String as i32),
+      .. FieldDescriptorProto::default()
+    };
+    let message_field = |name: &str, number: i32, type_name: &str| FieldDescriptorProto {
+      name: Some(name.to_string()),
+      number: Some(number),
+      r#type: Some(field_descriptor_proto::Type::Message as i32),
+      type_name: Some(type_name.to_string()),
+      .. FieldDescriptorProto::default()
+    };
+    let message_descriptor = |name: &str, fields: Vec<FieldDescriptorProto>| DescriptorProto {
+      name: Some(name.to_string()),
+      field: fields,
+      .. DescriptorProto::default()
+    };
+    let file_descriptor = FileDescriptorProto {
+      name: Some("test_file.proto".to_string()),
+      package: Some("test_package".to_string()),
+      message_type: vec![
+        message_descriptor("Request", vec![ string_field("id", 1) ]),
+        message_descriptor("Response", vec![ message_field("entity", 1, ".test_package.Entity") ]),
+        message_descriptor("Entity", vec![ message_field("details", 1, ".test_package.Details") ]),
+        message_descriptor("Details", vec![ message_field("name", 1, ".test_package.Name") ]),
+        message_descriptor("Name", vec![ string_field("first", 1), string_field("last", 2) ])
+      ],
+      .. FileDescriptorProto::default()
+    };
+    let service_descriptor = ServiceDescriptorProto {
+      name: Some("test_service".to_string()),
+      method: vec![
+        MethodDescriptorProto {
+          name: Some("call".to_string()),
+          input_type: Some(".test_package.Request".to_string()),
+          output_type: Some(".test_package.Response".to_string()),
+          .. MethodDescriptorProto::default()
+        }
+      ],
+      options: None
+    };
+    let all_descriptors = hashmap!{ "test_file.proto".to_string() => &file_descriptor };
+    let to_config = |config: Value| serde_json::from_value::<BTreeMap<std::string::String, Value>>(config)
+      .unwrap().iter()
+      .map(|(k, v)| (k.clone(), pact_plugin_driver::utils::to_proto_value(v)))
+      .collect::<BTreeMap<_, _>>();
+
+    let config = to_config(json!({
+      "request": { "id": "100" },
+      "response": {
+        "entity": { "details": { "name": { "first": "Fred", "last": "matching(type, 'Smith')" } } }
+      },
+      "matchingRules": {
+        "/request/id": "matching(regex, '\\d+', '100')",
+        "/response/entity/details/name/first": "matching(type, 'Fred')",
+        "/response/entity/details/name/last": "notEmpty('Smith')"
+      }
+    }));
+    let (request, response) = construct_protobuf_interaction_for_service(&service_descriptor, &config,
+      "call", &all_descriptors).unwrap();
+
+    let request = request.unwrap();
+    expect!(request.rules.keys().cloned().collect::<Vec<_>>()).to(be_equal_to(vec!["$.id".to_string()]));
+    expect!(request.rules["$.id"].rule[0].r#type.as_str()).to(be_equal_to("regex"));
+
+    let response = response.first().unwrap();
+    let rules = response.rules.iter()
+      .map(|(path, rules)| (path.clone(), rules.rule.iter().map(|rule| rule.r#type.clone()).collect::<Vec<_>>()))
+      .collect::<BTreeMap<_, _>>();
+    expect!(rules).to(be_equal_to(btreemap!{
+      "$.entity.details.name.first".to_string() => vec!["type".to_string()],
+      "$.entity.details.name.last".to_string() => vec!["type".to_string(), "not-empty".to_string()]
+    }));
+
+    let config = to_config(json!({
+      "request": { "id": "100" },
+      "matchingRules": {
+        "/entity/details/name/first": "matching(type, 'Fred')"
+      }
+    }));
+    let result = construct_protobuf_interaction_for_service(&service_descriptor, &config,
+      "call", &all_descriptors);
+    expect!(result.unwrap_err().to_string()).to(be_equal_to(
+      "Matching rule path '/entity/details/name/first' must start with either '/request' or '/response'"));
+  }
+
+  #[test]
+  fn construct_protobuf_interaction_for_service_with_example_overrides() {
+    let string_field = |name: &str, number: i32| FieldDescriptorProto {
+      name: Some(name.to_string()),
+      number: Some(number),
+      r#type: Some(field_descriptor_proto::Type::String as i32),
+      .. FieldDescriptorProto::default()
+    };
+    let message_field = |name: &str, number: i32, type_name: &str| FieldDescriptorProto {
+      name: Some(name.to_string()),
+      number: Some(number),
+      r#type: Some(field_descriptor_proto::Type::Message as i32),
+      type_name: Some(type_name.to_string()),
+      .. FieldDescriptorProto::default()
+    };
+    let message_descriptor = |name: &str, fields: Vec<FieldDescriptorProto>| DescriptorProto {
+      name: Some(name.to_string()),
+      field: fields,
+      .. DescriptorProto::default()
+    };
+    let file_descriptor = FileDescriptorProto {
+      name: Some("test_file.proto".to_string()),
+      package: Some("test_package".to_string()),
+      message_type: vec![
+        message_descriptor("Request", vec![ string_field("id", 1) ]),
+        message_descriptor("Response", vec![ message_field("name", 1, ".test_package.Name") ]),
+        message_descriptor("Name", vec![ string_field("first", 1), string_field("last", 2) ])
+      ],
+      .. FileDescriptorProto::default()
+    };
+    let service_descriptor = ServiceDescriptorProto {
+      name: Some("test_service".to_string()),
+      method: vec![
+        MethodDescriptorProto {
+          name: Some("call".to_string()),
+          input_type: Some(".test_package.Request".to_string()),
+          output_type: Some(".test_package.Response".to_string()),
+          .. MethodDescriptorProto::default()
+        }
+      ],
+      options: None
+    };
+    let all_descriptors = hashmap!{ "test_file.proto".to_string() => &file_descriptor };
+    let to_config = |config: Value| serde_json::from_value::<BTreeMap<std::string::String, Value>>(config)
+      .unwrap().iter()
+      .map(|(k, v)| (k.clone(), pact_plugin_driver::utils::to_proto_value(v)))
+      .collect::<BTreeMap<_, _>>();
+
+    let config = to_config(json!({
+      "request": { "id": "matching(regex, '\\d+', '100')" },
+      "response": {
+        "name": { "first": "matching(type, 'Fred')", "last": "Smith" }
+      },
+      "examples": {
+        "/request/id": "42",
+        "/response/name/first": "Mary"
+      }
+    }));
+    let (request, response) = construct_protobuf_interaction_for_service(&service_descriptor, &config,
+      "call", &all_descriptors).unwrap();
+
+    let request = request.unwrap();
+    expect!(request.contents.unwrap().content).to(be_some().value(vec![10, 2, b'4', b'2']));
+    expect!(request.rules["$.id"].rule[0].r#type.as_str()).to(be_equal_to("regex"));
+
+    let response = response.first().unwrap();
+    let mut expected = vec![10, 13, 10, 4];
+    expected.extend_from_slice(b"Mary");
+    expected.extend_from_slice(&[18, 5]);
+    expected.extend_from_slice(b"Smith");
+    expect!(response.contents.as_ref().unwrap().content.clone()).to(be_some().value(expected));
+    expect!(response.rules.keys().cloned().collect::<Vec<_>>()).to(be_equal_to(vec!["$.name.first".to_string()]));
+    expect!(response.rules["$.name.first"].rule[0].r#type.as_str()).to(be_equal_to("type"));
+
+    let config = to_config(json!({
+      "request": { "id": "100" },
+      "examples": {
+        "/request/name/first": "Mary"
+      }
+    }));
+    let result = construct_protobuf_interaction_for_service(&service_descriptor, &config,
+      "call", &all_descriptors);
+    expect!(result.unwrap_err().to_string()).to(be_equal_to(
+      "Example path '/request/name/first' does not refer to a field in the message configuration"));
+  }
+
+  #[test_log::test]
+  fn construct_protobuf_interaction_for_service_with_a_matcher_and_generator_on_the_same_field() {
+    let string_field = |name: &str, number: i32| FieldDescriptorProto {
+      name: Some(name.to_string()),
+      number: Some(number),
+      r#type: Some(field_descriptor_proto::Type::String as i32),
+      .. FieldDescriptorProto::default()
+    };
+    let message_descriptor = |name: &str, fields: Vec<FieldDescriptorProto>| DescriptorProto {
+      name: Some(name.to_string()),
+      field: fields,
+      .. DescriptorProto::default()
+    };
+    let file_descriptor = FileDescriptorProto {
+      name: Some("test_file.proto".to_string()),
+      package: Some("test_package".to_string()),
+      message_type: vec![
+        message_descriptor("Request", vec![ string_field("name", 1) ]),
+        message_descriptor("Response", vec![ string_field("id", 1) ])
+      ],
+      .. FileDescriptorProto::default()
+    };
+    let service_descriptor = ServiceDescriptorProto {
+      name: Some("test_service".to_string()),
+      method: vec![
+        MethodDescriptorProto {
+          name: Some("call".to_string()),
+          input_type: Some(".test_package.Request".to_string()),
+          output_type: Some(".test_package.Response".to_string()),
+          .. MethodDescriptorProto::default()
+        }
+      ],
+      options: None
+    };
+    let all_descriptors = hashmap!{ "test_file.proto".to_string() => &file_descriptor };
+    let config = serde_json::from_value::<BTreeMap<std::string::String, Value>>(json!({
+      "request": { "name": "Fred" },
+      "response": { "id": "matching(regex, '\\d+', '1234')" },
+      "generators": {
+        "/response/id": { "type": "RandomInt", "min": 1, "max": 1000 }
+      }
+    })).unwrap().iter()
+      .map(|(k, v)| (k.clone(), pact_plugin_driver::utils::to_proto_value(v)))
+      .collect::<BTreeMap<_, _>>();
+    let (request, response) = construct_protobuf_interaction_for_service(&service_descriptor, &config,
+      "call", &all_descriptors).unwrap();
+
+    expect!(request.unwrap().generators.is_empty()).to(be_true());
+    let response = response.first().unwrap();
+    expect!(response.rules["$.id"].rule[0].r#type.as_str()).to(be_equal_to("regex"));
+    let generator = &response.generators["$.id"];
+    expect!(generator.r#type.as_str()).to(be_equal_to("RandomInt"));
+
+    // The generated value must still satisfy the regex matcher
+    let fds = FileDescriptorSet { file: vec![ file_descriptor.clone() ] };
+    let mut contents = Bytes::from(response.contents.as_ref().unwrap().content.clone().unwrap());
+    let fields = crate::message_decoder::decode_message(&mut contents, &file_descriptor.message_type[1], &fds).unwrap();
+    let mut message = crate::dynamic_message::DynamicMessage::new(&fields, &fds);
+    let generators = hashmap!{
+      DocPath::new("$.id").unwrap() => pact_models::generators::Generator::create(generator.r#type.as_str(),
+        &generator.values.as_ref().map(pact_plugin_driver::utils::proto_struct_to_json).unwrap_or_default()).unwrap()
+    };
+    message.apply_generators(Some(&generators), &pact_models::generators::GeneratorTestMode::Consumer, &hashmap!{}).unwrap();
+    let generated = message.fetch_field_value(&DocPath::new("$.id").unwrap()).unwrap();
+    let generated = match generated.data {
+      ProtobufFieldData::String(value) => value,
+      data => panic!("Expected a string value, got {:?}", data)
+    };
+    expect!(regex::Regex::new("^\\d+$").unwrap().is_match(generated.as_str())).to(be_true());
+    expect!(generated.parse::<u64>().unwrap()).to(be_less_or_equal_to(1000));
+
+    let config = serde_json::from_value::<BTreeMap<std::string::String, Value>>(json!({
+      "request": { "name": "Fred" },
+      "generators": { "/response/id": { "min": 1 } }
+    })).unwrap().iter()
+      .map(|(k, v)| (k.clone(), pact_plugin_driver::utils::to_proto_value(v)))
+      .collect::<BTreeMap<_, _>>();
+    let result = construct_protobuf_interaction_for_service(&service_descriptor, &config,
+      "call", &all_descriptors);
+    expect!(result.unwrap_err().to_string()).to(be_equal_to(
+      "The generator definition for '/response/id' must be a map with a 'type' value"));
+  }
+
+  #[rstest::rstest]
+  #[case::status(json!("UNAUTHENTICATED"), Code::Unauthenticated, "No message set")]
+  #[case::status_number(json!(16), Code::Unauthenticated, "No message set")]
+  #[case::status_and_message(json!({ "code": "PERMISSION_DENIED", "message": "Not allowed" }),
+    Code::PermissionDenied, "Not allowed")]
+  fn construct_protobuf_interaction_for_service_with_a_grpc_status(
+    #[case] grpc_status_config: Value,
+    #[case] code: Code,
+    #[case] message: &str
+  ) {
+    let message_descriptor = |name: &str| DescriptorProto {
+      name: Some(name.to_string()),
+      field: vec![
+        FieldDescriptorProto {
+          name: Some("id".to_string()),
+          number: Some(1),
+          r#type: Some(field_descriptor_proto::Type::String as i32),
+          .. FieldDescriptorProto::default()
+        }
+      ],
+      .. DescriptorProto::default()
+    };
+    let file_descriptor = FileDescriptorProto {
+      name: Some("test_file.proto".to_string()),
+      package: Some("test_package".to_string()),
+      message_type: vec![ message_descriptor("Request"), message_descriptor("Response") ],
+      .. FileDescriptorProto::default()
+    };
+    let service_descriptor = ServiceDescriptorProto {
+      name: Some("test_service".to_string()),
+      method: vec![
+        MethodDescriptorProto {
+          name: Some("call".to_string()),
+          input_type: Some(".test_package.Request".to_string()),
+          output_type: Some(".test_package.Response".to_string()),
+          .. MethodDescriptorProto::default()
+        }
+      ],
+      options: None
+    };
+    let all_descriptors = hashmap!{ "test_file.proto".to_string() => &file_descriptor };
+    let to_config = |config: Value| serde_json::from_value::<BTreeMap<std::string::String, Value>>(config)
+      .unwrap().iter()
+      .map(|(k, v)| (k.clone(), pact_plugin_driver::utils::to_proto_value(v)))
+      .collect::<BTreeMap<_, _>>();
+
+    let config = to_config(json!({
+      "request": { "id": "100" },
+      "pact:grpc-status": grpc_status_config.clone()
+    }));
+    let (_, response) = construct_protobuf_interaction_for_service(&service_descriptor, &config,
+      "call", &all_descriptors).unwrap();
+    let metadata = response.first().unwrap().message_metadata.as_ref().unwrap().fields.iter()
+      .map(|(k, v)| (k.clone(), proto_value_to_json(v)))
+      .collect::<HashMap<_, _>>();
+    let response_contents = MessageContents { metadata, .. MessageContents::default() };
+    let status = grpc_status(&response_contents).unwrap();
+    expect!(status.code()).to(be_equal_to(code));
+    expect!(status.message()).to(be_equal_to(message));
+
+    let config = to_config(json!({
+      "request": { "id": "100" },
+      "responseMetadata": { "grpc-status": "NOT_FOUND" },
+      "pact:grpc-status": grpc_status_config
+    }));
+    let result = construct_protobuf_interaction_for_service(&service_descriptor, &config,
+      "call", &all_descriptors);
+    expect!(result.unwrap_err().to_string()).to(be_equal_to(
+      "'pact:grpc-status' can not be used with a 'grpc-status' value in the response metadata"));
+  }
+
+  #[rstest::rstest]
+  #[case::error_status(json!({ "grpc-status": "NOT_FOUND", "grpc-message": "No such entity" }), false)]
+  #[case::ok_status(json!({ "grpc-status": "OK" }), true)]
+  #[case::no_status(json!({ "x-request-id": "1234" }), true)]
+  fn construct_protobuf_interaction_for_service_with_only_the_response_metadata(
+    #[case] response_metadata: Value,
+    #[case] has_response_message: bool
+  ) {
+    let message_descriptor = |name: &str| DescriptorProto {
+      name: Some(name.to_string()),
+      field: vec![
+        FieldDescriptorProto {
+          name: Some("id".to_string()),
+          number: Some(1),
+          r#type: Some(field_descriptor_proto::Type::String as i32),
+          .. FieldDescriptorProto::default()
+        }
+      ],
+      .. DescriptorProto::default()
+    };
+    let file_descriptor = FileDescriptorProto {
+      name: Some("test_file.proto".to_string()),
+      package: Some("test_package".to_string()),
+      message_type: vec![ message_descriptor("Request"), message_descriptor("Response") ],
+      .. FileDescriptorProto::default()
+    };
+    let service_descriptor = ServiceDescriptorProto {
+      name: Some("test_service".to_string()),
+      method: vec![
+        MethodDescriptorProto {
+          name: Some("call".to_string()),
+          input_type: Some(".test_package.Request".to_string()),
+          output_type: Some(".test_package.Response".to_string()),
+          .. MethodDescriptorProto::default()
+        }
+      ],
+      options: None
+    };
+    let all_descriptors = hashmap!{ "test_file.proto".to_string() => &file_descriptor };
+    let config = serde_json::from_value::<BTreeMap<std::string::String, Value>>(json!({
+      "request": { "id": "100" },
+      "responseMetadata": response_metadata
+    })).unwrap().iter()
+      .map(|(k, v)| (k.clone(), pact_plugin_driver::utils::to_proto_value(v)))
+      .collect();
+
+    let (request, response) = construct_protobuf_interaction_for_service(&service_descriptor, &config,
+      "call", &all_descriptors).unwrap();
+    expect!(request.unwrap().contents).to(be_some());
+    expect!(response.len()).to(be_equal_to(1));
+    let response = response.first().unwrap();
+    expect!(response.contents.is_some()).to(be_equal_to(has_response_message));
+    if !has_response_message {
+      let metadata = response.message_metadata.as_ref().unwrap().fields.iter()
+        .map(|(k, v)| (k.clone(), proto_value_to_json(v)))
+        .collect::<HashMap<_, _>>();
+      let response_contents = MessageContents { metadata, .. MessageContents::default() };
+      let status = grpc_status(&response_contents).unwrap();
+      expect!(status.code()).to(be_equal_to(Code::NotFound));
+      expect!(status.message()).to(be_equal_to("No such entity"));
+    }
+  }
+
+  #[test_log::test]
+  fn construct_protobuf_interaction_for_service_with_grpc_status_details() {
+    let string_field = |name: &str, number: i32| FieldDescriptorProto {
+      name: Some(name.to_string()),
+      number: Some(number),
+      r#type: Some(field_descriptor_proto::Type::String as i32),
+      .. FieldDescriptorProto::default()
+    };
+    let message_descriptor = |name: &str, fields: Vec<FieldDescriptorProto>| DescriptorProto {
+      name: Some(name.to_string()),
+      field: fields,
+      .. DescriptorProto::default()
+    };
+    let file_descriptor = FileDescriptorProto {
+      name: Some("test_file.proto".to_string()),
+      package: Some("test_package".to_string()),
+      message_type: vec![
+        message_descriptor("Request", vec![ string_field("id", 1) ]),
+        message_descriptor("Response", vec![ string_field("name", 1) ]),
+        message_descriptor("ErrorInfo", vec![ string_field("reason", 1), string_field("domain", 2) ])
+      ],
+      .. FileDescriptorProto::default()
+    };
+    let service_descriptor = ServiceDescriptorProto {
+      name: Some("test_service".to_string()),
+      method: vec![
+        MethodDescriptorProto {
+          name: Some("call".to_string()),
+          input_type: Some(".test_package.Request".to_string()),
+          output_type: Some(".test_package.Response".to_string()),
+          .. MethodDescriptorProto::default()
         }
       ],
       options: None
     };
+    let all_descriptors = hashmap!{ "test_file.proto".to_string() => &file_descriptor };
+    let config = serde_json::from_value::<BTreeMap<std::string::String, Value>>(json!({
+      "request": { "id": "100" },
+      "responseMetadata": {
+        "grpc-status": "NOT_FOUND",
+        "grpc-message": "Not found",
+        "grpc-status-details-bin": {
+          "details": [
+            { "@type": ".test_package.ErrorInfo", "reason": "NO_SUCH_ID", "domain": "example.com" }
+          ]
+        }
+      }
+    })).unwrap().iter()
+      .map(|(k, v)| (k.clone(), pact_plugin_driver::utils::to_proto_value(v)))
+      .collect();
 
-    let config = btreemap! {
-      "request".to_string() => prost_types::Value { kind: Some(prost_types::value::Kind::BoolValue(true)) }
-    };
+    let (_, response) = construct_protobuf_interaction_for_service(&service_descriptor, &config,
+      "call", &all_descriptors).unwrap();
+    let metadata = response.first().unwrap().message_metadata.as_ref().unwrap().fields.iter()
+      .map(|(k, v)| (k.clone(), proto_value_to_json(v)))
+      .collect::<HashMap<_, _>>();
+    let response_contents = MessageContents { metadata, .. MessageContents::default() };
+
+    let status = grpc_status(&response_contents).unwrap();
+    expect!(status.code()).to(be_equal_to(Code::NotFound));
+    expect!(status.message()).to(be_equal_to("Not found"));
+    let details = RpcStatus::decode(status.details()).unwrap();
+    expect!(details.code).to(be_equal_to(Code::NotFound as i32));
+    expect!(details.message.as_str()).to(be_equal_to("Not found"));
+    expect!(details.details.len()).to(be_equal_to(1));
+    expect!(details.details[0].type_url.as_str()).to(be_equal_to("type.googleapis.com/test_package.ErrorInfo"));
+
+    let fds = FileDescriptorSet { file: vec![ file_descriptor.clone() ] };
+    let (result, _) = compare_status_details(&response_contents.metadata, &status, &fds).unwrap();
+    expect!(result.mismatches.iter()).to(be_empty());
+
+    let mut error_info = BytesMut::new();
+    prost::encoding::string::encode(1, &"EXPIRED_ID".to_string(), &mut error_info);
+    prost::encoding::string::encode(2, &"example.com".to_string(), &mut error_info);
+    let actual_status = Status::with_details(Code::NotFound, "Not found", RpcStatus {
+      code: Code::NotFound as i32,
+      message: "Not found".to_string(),
+      details: vec![ prost_types::Any {
+        type_url: "type.googleapis.com/test_package.ErrorInfo".to_string(),
+        value: error_info.to_vec()
+      } ]
+    }.encode_to_vec().into());
+    let (result, _) = compare_status_details(&response_contents.metadata, &actual_status, &fds).unwrap();
+    let mismatches = result.mismatches.iter()
+      .map(|mismatch| mismatch.description())
+      .collect::<Vec<_>>();
+    expect!(mismatches).to(be_equal_to(vec![
+      "Status detail 0 (test_package.ErrorInfo) $.reason: Expected 'EXPIRED_ID' to be equal to 'NO_SUCH_ID'".to_string()
+    ]));
 
-    let result = construct_protobuf_interaction_for_service(
-      &service_descriptor, &config, "call", &hashmap!{ "file".to_string() => &file_descriptor });
-    expect!(result.as_ref()).to(be_err());
-    expect!(result.unwrap_err().to_string()).to(
-      be_equal_to("Request contents is of an un-processable type: BoolValue(true), it should be either a Struct or a StringValue")
-    );
+    let (result, _) = compare_status_details(&response_contents.metadata,
+      &Status::not_found("Not found"), &fds).unwrap();
+    expect!(result.mismatches.len()).to(be_equal_to(1));
   }
 
-  #[test_log::test]
-  fn construct_protobuf_interaction_for_service_supports_string_value_type() {
-    let string_descriptor = DescriptorProto {
-      name: Some("StringValue".to_string()),
-      field: vec![
-        FieldDescriptorProto {
-          name: Some("value".to_string()),
-          number: Some(1),
-          label: None,
-          r#type: Some(field_descriptor_proto::Type::String as i32),
-          type_name: Some("string".to_string()),
-          extendee: None,
-          default_value: None,
-          oneof_index: None,
-          json_name: None,
-          options: None,
-          proto3_optional: None
-        }
-      ],
-      extension: vec![],
-      nested_type: vec![],
-      enum_type: vec![],
-      extension_range: vec![],
-      oneof_decl: vec![],
-      options: None,
-      reserved_range: vec![],
-      reserved_name: vec![]
-    };
-    let message_descriptor = DescriptorProto {
-      name: Some("test_message".to_string()),
+  #[test]
+  fn construct_protobuf_interaction_for_service_supports_overriding_the_response_message_type() {
+    let message_descriptor = |name: &str, field_name: &str| DescriptorProto {
+      name: Some(name.to_string()),
       field: vec![
         FieldDescriptorProto {
-          name: Some("value".to_string()),
+          name: Some(field_name.to_string()),
           number: Some(1),
           label: None,
           r#type: Some(field_descriptor_proto::Type::String as i32),
-          type_name: Some("string".to_string()),
+          type_name: None,
           extendee: None,
           default_value: None,
           oneof_index: None,
@@ -1764,36 +5441,25 @@ pub(crate) mod tests {
           proto3_optional: None
         }
       ],
-      extension: vec![],
-      nested_type: vec![],
-      enum_type: vec![],
-      extension_range: vec![],
-      oneof_decl: vec![],
-      options: None,
-      reserved_range: vec![],
-      reserved_name: vec![]
+      .. DescriptorProto::default()
     };
     let file_descriptor = FileDescriptorProto {
       name: Some("test_file.proto".to_string()),
       package: Some("test_package".to_string()),
-      dependency: vec![],
-      public_dependency: vec![],
-      weak_dependency: vec![],
-      message_type: vec![ string_descriptor, message_descriptor ],
-      enum_type: vec![],
-      service: vec![],
-      extension: vec![],
-      options: None,
-      source_code_info: None,
-      syntax: None
+      message_type: vec![
+        message_descriptor("Request", "value"),
+        message_descriptor("Response", "value"),
+        message_descriptor("ErrorResponse", "error")
+      ],
+      .. FileDescriptorProto::default()
     };
     let service_descriptor = ServiceDescriptorProto {
       name: Some("test_service".to_string()),
       method: vec![
         MethodDescriptorProto {
           name: Some("call".to_string()),
-          input_type: Some(".test_package.StringValue".to_string()),
-          output_type: Some(".test_package.test_message".to_string()),
+          input_type: Some(".test_package.Request".to_string()),
+          output_type: Some(".test_package.Response".to_string()),
           options: None,
           client_streaming: None,
           server_streaming: None
@@ -1801,14 +5467,28 @@ pub(crate) mod tests {
       ],
       options: None
     };
-
-    let config = btreemap! {
-      "request".to_string() => prost_types::Value { kind: Some(prost_types::value::Kind::StringValue("true".to_string())) }
+    let all_descriptors = hashmap!{ "test_file.proto".to_string() => &file_descriptor };
+    let struct_value = |fields: HashMap<String, Value>| prost_types::Value {
+      kind: Some(StructValue(pact_plugin_driver::utils::to_proto_struct(&fields)))
+    };
+    let config = |message_type: &str| btreemap! {
+      "request".to_string() => struct_value(hashmap! { "value".to_string() => json!("notEmpty('a')") }),
+      "response".to_string() => struct_value(hashmap! { "error".to_string() => json!("notEmpty('not found')") }),
+      "pact:protobuf-config".to_string() => struct_value(hashmap! {
+        "responseMessageType".to_string() => json!(message_type)
+      })
     };
 
-    let result = construct_protobuf_interaction_for_service(
-      &service_descriptor, &config, "call", &hashmap!{ "file".to_string() => &file_descriptor });
-    expect!(result).to(be_ok());
+    let (_, response) = construct_protobuf_interaction_for_service(&service_descriptor,
+      &config(".test_package.ErrorResponse"), "call", &all_descriptors).unwrap();
+    let body = response.first().unwrap().contents.as_ref().unwrap();
+    expect!(body.content_type.as_str()).to(be_equal_to("application/protobuf;message=.test_package.ErrorResponse"));
+
+    let result = construct_protobuf_interaction_for_service(&service_descriptor,
+      &config(".test_package.DoesNotExist"), "call", &all_descriptors);
+    expect!(result.unwrap_err().to_string()).to(be_equal_to(
+      "Did not find the message type '.test_package.DoesNotExist' configured with 'responseMessageType' - \
+      Did not find a message type 'DoesNotExist' in any of the file descriptors '[\"test_file.proto\"]'"));
   }
 
   lazy_static! {
@@ -2262,6 +5942,184 @@ pub(crate) mod tests {
     expect!(result).to(be_ok());
   }
 
+  #[test_log::test]
+  fn build_embedded_message_field_value_with_repeated_wrapper_field() {
+    let wrappers_descriptor = FileDescriptorProto {
+      name: Some("google/protobuf/wrappers.proto".to_string()),
+      package: Some("google.protobuf".to_string()),
+      message_type: vec![
+        DescriptorProto {
+          name: Some("StringValue".to_string()),
+          field: vec![
+            FieldDescriptorProto {
+              name: Some("value".to_string()),
+              number: Some(1),
+              r#type: Some(Type::String as i32),
+              .. FieldDescriptorProto::default()
+            }
+          ],
+          .. DescriptorProto::default()
+        }
+      ],
+      syntax: Some("proto3".to_string()),
+      .. FileDescriptorProto::default()
+    };
+    let field_descriptor = FieldDescriptorProto {
+      name: Some("names".to_string()),
+      number: Some(1),
+      label: Some(field_descriptor_proto::Label::Repeated as i32),
+      r#type: Some(Type::Message as i32),
+      type_name: Some(".google.protobuf.StringValue".to_string()),
+      .. FieldDescriptorProto::default()
+    };
+    let message_descriptor = DescriptorProto {
+      name: Some("Test".to_string()),
+      field: vec![ field_descriptor.clone() ],
+      .. DescriptorProto::default()
+    };
+    let file_descriptor = FileDescriptorProto {
+      name: Some("test.proto".to_string()),
+      dependency: vec![ "google/protobuf/wrappers.proto".to_string() ],
+      message_type: vec![ message_descriptor.clone() ],
+      syntax: Some("proto3".to_string()),
+      .. FileDescriptorProto::default()
+    };
+    let file_descriptors = hashmap!{
+      "google/protobuf/wrappers.proto".to_string() => &wrappers_descriptor,
+      "test.proto".to_string() => &file_descriptor
+    };
+    let mut message_builder = MessageBuilder::new(&message_descriptor, "Test", &file_descriptor);
+    let path = DocPath::new_unwrap("$.names");
+    let mut matching_rules = MatchingRuleCategory::empty("body");
+    let mut generators = hashmap!{};
+
+    build_embedded_message_field_value(&mut message_builder, &path, &field_descriptor, "names",
+      &json!(["a", "matching(type, 'b')"]), &mut matching_rules, &mut generators, &file_descriptors
+    ).unwrap();
+
+    let expected = message_builder.encode_message().unwrap();
+    expect!(expected.to_vec()).to(be_equal_to(vec![10, 3, 10, 1, 97, 10, 3, 10, 1, 98]));
+    expect!(matching_rules.clone()).to(be_equal_to(matchingrules_list! {
+      "body"; "$.names[1].value" => [ pact_models::matchingrules::MatchingRule::Type ]
+    }));
+
+    let fds = FileDescriptorSet { file: vec![ wrappers_descriptor.clone(), file_descriptor.clone() ] };
+    let result = match_message(".Test", &fds, &mut expected.clone(),
+      &mut Bytes::from_static(&[10, 3, 10, 1, 97, 10, 3, 10, 1, 99]), &matching_rules, false, false, &hashmap!{}).unwrap();
+    expect!(result).to(be_equal_to(BodyMatchResult::Ok));
+    let result = match_message(".Test", &fds, &mut expected.clone(),
+      &mut Bytes::from_static(&[10, 3, 10, 1, 99, 10, 3, 10, 1, 98]), &matching_rules, false, false, &hashmap!{}).unwrap();
+    expect!(result.mismatches().len()).to(be_equal_to(1));
+  }
+
+  #[rstest::rstest]
+  #[case::same_duration("matching(type, 'PT1.5S')", vec![8, 1, 16, 128, 202, 181, 238, 1], true)]
+  #[case::other_duration("matching(type, 'PT1.5S')", vec![8, 120], true)]
+  #[case::negative_duration("matching(type, 'PT1.5S')", vec![8, 255, 255, 255, 255, 255, 255, 255, 255, 255, 1], true)]
+  #[case::invalid_duration("matching(type, 'PT1.5S')", vec![8, 1, 16, 255, 255, 255, 255, 255, 255, 255, 255, 255, 1], false)]
+  #[case::matching_regex(r"matching(regex, '^PT\d+M$', 'PT2M')", vec![8, 120], true)]
+  #[case::not_matching_regex(r"matching(regex, '^PT\d+M$', 'PT2M')", vec![8, 1], false)]
+  #[case::equal_duration("PT2M", vec![8, 120], true)]
+  #[case::not_equal_duration("PT2M", vec![8, 121], false)]
+  fn build_embedded_message_field_value_with_a_duration_field(
+    #[case] config: &str,
+    #[case] actual_duration: Vec<u8>,
+    #[case] matches: bool
+  ) {
+    let duration_descriptor = FileDescriptorProto {
+      name: Some("google/protobuf/duration.proto".to_string()),
+      package: Some("google.protobuf".to_string()),
+      message_type: vec![
+        DescriptorProto {
+          name: Some("Duration".to_string()),
+          field: vec![
+            FieldDescriptorProto {
+              name: Some("seconds".to_string()),
+              number: Some(1),
+              r#type: Some(Type::Int64 as i32),
+              .. FieldDescriptorProto::default()
+            },
+            FieldDescriptorProto {
+              name: Some("nanos".to_string()),
+              number: Some(2),
+              r#type: Some(Type::Int32 as i32),
+              .. FieldDescriptorProto::default()
+            }
+          ],
+          .. DescriptorProto::default()
+        }
+      ],
+      syntax: Some("proto3".to_string()),
+      .. FileDescriptorProto::default()
+    };
+    let field_descriptor = FieldDescriptorProto {
+      name: Some("timeout".to_string()),
+      number: Some(1),
+      r#type: Some(Type::Message as i32),
+      type_name: Some(".google.protobuf.Duration".to_string()),
+      .. FieldDescriptorProto::default()
+    };
+    let message_descriptor = DescriptorProto {
+      name: Some("Test".to_string()),
+      field: vec![ field_descriptor.clone() ],
+      .. DescriptorProto::default()
+    };
+    let file_descriptor = FileDescriptorProto {
+      name: Some("test.proto".to_string()),
+      dependency: vec![ "google/protobuf/duration.proto".to_string() ],
+      message_type: vec![ message_descriptor.clone() ],
+      syntax: Some("proto3".to_string()),
+      .. FileDescriptorProto::default()
+    };
+    let file_descriptors = hashmap!{
+      "google/protobuf/duration.proto".to_string() => &duration_descriptor,
+      "test.proto".to_string() => &file_descriptor
+    };
+    let mut message_builder = MessageBuilder::new(&message_descriptor, "Test", &file_descriptor);
+    let path = DocPath::new_unwrap("$.timeout");
+    let mut matching_rules = MatchingRuleCategory::empty("body");
+
+    build_embedded_message_field_value(&mut message_builder, &path, &field_descriptor, "timeout",
+      &json!(config), &mut matching_rules, &mut hashmap!{}, &file_descriptors
+    ).unwrap();
+
+    let expected = message_builder.encode_message().unwrap();
+    let fds = FileDescriptorSet { file: vec![ duration_descriptor.clone(), file_descriptor.clone() ] };
+    let mut actual = vec![10, actual_duration.len() as u8];
+    actual.extend(actual_duration);
+    let result = match_message(".Test", &fds, &mut expected.clone(), &mut Bytes::from(actual),
+      &matching_rules, false, false, &hashmap!{}).unwrap();
+    expect!(result == BodyMatchResult::Ok).to(be_equal_to(matches));
+  }
+
+  #[test]
+  fn build_embedded_message_field_value_with_an_invalid_duration() {
+    let field_descriptor = FieldDescriptorProto {
+      name: Some("timeout".to_string()),
+      number: Some(1),
+      r#type: Some(Type::Message as i32),
+      type_name: Some(".google.protobuf.Duration".to_string()),
+      .. FieldDescriptorProto::default()
+    };
+    let message_descriptor = DescriptorProto {
+      name: Some("Test".to_string()),
+      field: vec![ field_descriptor.clone() ],
+      .. DescriptorProto::default()
+    };
+    let file_descriptor = FileDescriptorProto {
+      name: Some("test.proto".to_string()),
+      message_type: vec![ message_descriptor.clone() ],
+      .. FileDescriptorProto::default()
+    };
+    let mut message_builder = MessageBuilder::new(&message_descriptor, "Test", &file_descriptor);
+
+    let result = build_embedded_message_field_value(&mut message_builder, &DocPath::new_unwrap("$.timeout"),
+      &field_descriptor, "timeout", &json!("1.5 seconds"), &mut MatchingRuleCategory::empty("body"),
+      &mut hashmap!{}, &hashmap!{});
+    expect!(result.unwrap_err().to_string()).to(be_equal_to(
+      "Field 'timeout' must be configured with an ISO-8601 duration - '1.5 seconds' is not a valid ISO-8601 duration"));
+  }
+
   #[test_log::test]
   fn build_single_embedded_field_value_with_field_from_different_proto_file() {
     let bytes = BASE64.decode(DESCRIPTOR_BYTES).unwrap();
@@ -2479,16 +6337,176 @@ pub(crate) mod tests {
     let field_config = json!("matching(type, 'VALUE_ONE')");
     let mut matching_rules = MatchingRuleCategory::empty("body");
     let mut generators = hashmap!{};
-    let file_descriptors: HashMap<String, &FileDescriptorProto> = fds.file
-      .iter().map(|des| (des.name.clone().unwrap_or_default(), des))
-      .collect();
-
+    let file_descriptors: HashMap<String, &FileDescriptorProto> = fds.file
+      .iter().map(|des| (des.name.clone().unwrap_or_default(), des))
+      .collect();
+
+
+    let result = build_field_value(&path, &mut message_builder,
+      MessageFieldValueType::Normal, field_descriptor, "result", &field_config,
+      &mut matching_rules, &mut generators, &file_descriptors
+    );
+    expect!(result).to(be_ok());
+  }
+
+  #[rstest::rstest]
+  #[case::name(json!("VALUE_ONE"))]
+  #[case::numeric_string(json!("1"))]
+  #[case::number(json!(1))]
+  #[case::matcher_with_number(json!("matching(type, '1')"))]
+  #[case::regex_matcher(json!("matching(regex, 'VALUE_.*', 'VALUE_ONE')"))]
+  fn build_field_value_with_enum_name_or_number(#[case] field_config: Value) {
+    let bytes: &[u8] = &DESCRIPTORS_ROUTE_GUIDE_WITH_ENUM_BASIC;
+    let fds: FileDescriptorSet = FileDescriptorSet::decode(Bytes::from(bytes)).unwrap();
+
+    let main_descriptor = fds.file.iter()
+      .find(|fd| fd.name.clone().unwrap_or_default() == "test_enum.proto")
+      .unwrap();
+    let message_descriptor = main_descriptor.message_type.iter()
+      .find(|md| md.name.clone().unwrap_or_default() == "Feature").unwrap();
+    let mut message_builder = MessageBuilder::new(message_descriptor, "Feature", main_descriptor);
+    let path = DocPath::new("$.result").unwrap();
+    let field_descriptor = message_descriptor.field.iter()
+      .find(|fd| fd.name.clone().unwrap_or_default() == "result")
+      .unwrap();
+    let mut matching_rules = MatchingRuleCategory::empty("body");
+    let mut generators = hashmap!{};
+    let file_descriptors = fds_to_map(&fds);
+
+    let result = build_field_value(&path, &mut message_builder,
+      MessageFieldValueType::Normal, field_descriptor, "result", &field_config,
+      &mut matching_rules, &mut generators, &file_descriptors
+    ).unwrap().unwrap();
+    let enum_descriptor = main_descriptor.enum_type.first().unwrap().clone();
+    expect!(result.rtype).to(be_equal_to(RType::Enum(1, enum_descriptor)));
+  }
+
+  #[rstest::rstest]
+  #[case::numeric_string(json!("5"))]
+  #[case::number(json!(5))]
+  fn build_field_value_with_an_undeclared_enum_number(#[case] field_config: Value) {
+    let bytes: &[u8] = &DESCRIPTORS_ROUTE_GUIDE_WITH_ENUM_BASIC;
+    let fds: FileDescriptorSet = FileDescriptorSet::decode(Bytes::from(bytes)).unwrap();
+
+    let main_descriptor = fds.file.iter()
+      .find(|fd| fd.name.clone().unwrap_or_default() == "test_enum.proto")
+      .unwrap();
+    let message_descriptor = main_descriptor.message_type.iter()
+      .find(|md| md.name.clone().unwrap_or_default() == "Feature").unwrap();
+    let mut message_builder = MessageBuilder::new(message_descriptor, "Feature", main_descriptor);
+    let path = DocPath::new("$.result").unwrap();
+    let field_descriptor = message_descriptor.field.iter()
+      .find(|fd| fd.name.clone().unwrap_or_default() == "result")
+      .unwrap();
+    let mut matching_rules = MatchingRuleCategory::empty("body");
+    let mut generators = hashmap!{};
+    let file_descriptors = fds_to_map(&fds);
+
+    let result = build_field_value(&path, &mut message_builder,
+      MessageFieldValueType::Normal, field_descriptor, "result", &field_config,
+      &mut matching_rules, &mut generators, &file_descriptors
+    );
+    expect!(result.unwrap_err().to_string()).to(
+      be_equal_to("Protobuf enum .routeguide.v2.TestEnum has no value with number 5"));
+  }
+
+  #[rstest::rstest]
+  #[case::matcher(json!("matching(boolean, false)"))]
+  #[case::boolean(json!(false))]
+  fn build_field_value_encodes_an_optional_bool_set_to_false(#[case] field_config: Value) {
+    let field_descriptor = FieldDescriptorProto {
+      name: Some("enabled".to_string()),
+      number: Some(1),
+      label: Some(Label::Optional as i32),
+      r#type: Some(Type::Bool as i32),
+      oneof_index: Some(0),
+      proto3_optional: Some(true),
+      .. FieldDescriptorProto::default()
+    };
+    let message_descriptor = DescriptorProto {
+      name: Some("Flags".to_string()),
+      field: vec![ field_descriptor.clone() ],
+      oneof_decl: vec![
+        OneofDescriptorProto { name: Some("_enabled".to_string()), options: None }
+      ],
+      .. DescriptorProto::default()
+    };
+    let file_descriptor = FileDescriptorProto {
+      name: Some("flags.proto".to_string()),
+      message_type: vec![ message_descriptor.clone() ],
+      syntax: Some("proto3".to_string()),
+      .. FileDescriptorProto::default()
+    };
+    let mut message_builder = MessageBuilder::new(&message_descriptor, "Flags", &file_descriptor);
+    let path = DocPath::new("$.enabled").unwrap();
+    let mut matching_rules = MatchingRuleCategory::empty("body");
+    let mut generators = hashmap!{};
+    let file_descriptors = hashmap!{ "flags.proto".to_string() => &file_descriptor };
+
+    let result = build_field_value(&path, &mut message_builder,
+      MessageFieldValueType::Normal, &field_descriptor, "enabled", &field_config,
+      &mut matching_rules, &mut generators, &file_descriptors
+    );
+    expect!(result).to(be_ok());
+
+    let mut bytes = message_builder.encode_message().unwrap();
+    let fds = FileDescriptorSet { file: vec![ file_descriptor.clone() ] };
+    let fields = decode_message(&mut bytes, &message_descriptor, &fds).unwrap();
+    expect!(fields.len()).to(be_equal_to(1));
+    expect!(fields[0].field_num).to(be_equal_to(1));
+    expect!(&fields[0].data).to(be_equal_to(&ProtobufFieldData::Boolean(false)));
+  }
+
+  #[rstest::rstest]
+  #[case::fixed32(Type::Fixed32, json!(4000000000_u32), ProtobufFieldData::UInteger32(4000000000), WireType::ThirtyTwoBit)]
+  #[case::fixed64(Type::Fixed64, json!(18000000000000000000_u64), ProtobufFieldData::UInteger64(18000000000000000000), WireType::SixtyFourBit)]
+  #[case::sfixed32(Type::Sfixed32, json!(123456), ProtobufFieldData::Integer32(123456), WireType::ThirtyTwoBit)]
+  #[case::negative_sfixed32(Type::Sfixed32, json!(-123456), ProtobufFieldData::Integer32(-123456), WireType::ThirtyTwoBit)]
+  #[case::sfixed64(Type::Sfixed64, json!(9000000000_i64), ProtobufFieldData::Integer64(9000000000), WireType::SixtyFourBit)]
+  #[case::negative_sfixed64(Type::Sfixed64, json!(-9000000000_i64), ProtobufFieldData::Integer64(-9000000000), WireType::SixtyFourBit)]
+  #[case::negative_sfixed32_matcher(Type::Sfixed32, json!("matching(integer, -100)"), ProtobufFieldData::Integer32(-100), WireType::ThirtyTwoBit)]
+  fn build_field_value_round_trips_fixed_width_numbers(
+    #[case] field_type: Type,
+    #[case] field_config: Value,
+    #[case] expected: ProtobufFieldData,
+    #[case] wire_type: WireType
+  ) {
+    let field_descriptor = FieldDescriptorProto {
+      name: Some("value".to_string()),
+      number: Some(1),
+      label: Some(Label::Optional as i32),
+      r#type: Some(field_type as i32),
+      .. FieldDescriptorProto::default()
+    };
+    let message_descriptor = DescriptorProto {
+      name: Some("Fixed".to_string()),
+      field: vec![ field_descriptor.clone() ],
+      .. DescriptorProto::default()
+    };
+    let file_descriptor = FileDescriptorProto {
+      name: Some("fixed.proto".to_string()),
+      message_type: vec![ message_descriptor.clone() ],
+      syntax: Some("proto3".to_string()),
+      .. FileDescriptorProto::default()
+    };
+    let mut message_builder = MessageBuilder::new(&message_descriptor, "Fixed", &file_descriptor);
+    let path = DocPath::new("$.value").unwrap();
+    let mut matching_rules = MatchingRuleCategory::empty("body");
+    let mut generators = hashmap!{};
+    let file_descriptors = hashmap!{ "fixed.proto".to_string() => &file_descriptor };
 
     let result = build_field_value(&path, &mut message_builder,
-      MessageFieldValueType::Normal, field_descriptor, "result", &field_config,
+      MessageFieldValueType::Normal, &field_descriptor, "value", &field_config,
       &mut matching_rules, &mut generators, &file_descriptors
     );
     expect!(result).to(be_ok());
+
+    let mut bytes = message_builder.encode_message().unwrap();
+    let fds = FileDescriptorSet { file: vec![ file_descriptor.clone() ] };
+    let fields = decode_message(&mut bytes, &message_descriptor, &fds).unwrap();
+    expect!(fields.len()).to(be_equal_to(1));
+    expect!(fields[0].wire_type).to(be_equal_to(wire_type));
+    expect!(&fields[0].data).to(be_equal_to(&expected));
   }
 
   #[test]
@@ -2817,4 +6835,563 @@ pub(crate) mod tests {
       }
     });
   }
+
+  /// Starts a simple HTTP server that returns the given status and body for every request, and
+  /// returns the base URL for the server
+  async fn start_http_server(status: &'static str, body: &'static str) -> String {
+    let listener = tokio::net::TcpListener::bind("127.0.0.1:0").await.unwrap();
+    let address = listener.local_addr().unwrap();
+    tokio::spawn(async move {
+      while let Ok((mut stream, _)) = listener.accept().await {
+        let mut buffer = [0_u8; 4096];
+        let _ = tokio::io::AsyncReadExt::read(&mut stream, &mut buffer).await;
+        let response = format!("HTTP/1.1 {}\r\nContent-Length: {}\r\nConnection: close\r\n\r\n{}",
+          status, body.len(), body);
+        let _ = tokio::io::AsyncWriteExt::write_all(&mut stream, response.as_bytes()).await;
+      }
+    });
+    format!("http://{}", address)
+  }
+
+  #[test_log::test(tokio::test)]
+  async fn process_proto_with_a_remote_proto_file() {
+    let base_url = start_http_server("200 OK", r#"syntax = "proto3";
+
+      package remote;
+
+      message Greeting {
+        string message = 1;
+      }
+    "#).await;
+    let protoc = crate::protoc::setup_protoc(&hashmap!{}, &vec![]).await.unwrap();
+    let config = btreemap! {
+      "pact:message-type".to_string() => prost_string("Greeting"),
+      "message".to_string() => prost_string("hello")
+    };
+
+    let (interactions, _) = process_proto(format!("{}/protos/greeting.proto", base_url), &protoc,
+      &config, None).await.unwrap();
+    expect!(interactions.len()).to(be_equal_to(1));
+    expect!(interactions[0].contents.as_ref().unwrap().content_type.as_str())
+      .to(be_equal_to("application/protobuf;message=.remote.Greeting"));
+  }
+
+  #[test_log::test(tokio::test)]
+  async fn process_proto_with_a_remote_proto_file_that_fails_to_download() {
+    let base_url = start_http_server("404 Not Found", "").await;
+    let protoc = crate::protoc::setup_protoc(&hashmap!{}, &vec![]).await.unwrap();
+    let config = btreemap! {
+      "pact:message-type".to_string() => prost_string("Greeting")
+    };
+
+    let url = format!("{}/protos/greeting.proto", base_url);
+    let result = process_proto(url.clone(), &protoc, &config, None).await;
+    expect!(result.unwrap_err().to_string()).to(be_equal_to(
+      format!("Failed to download the proto file from '{}' - 404 Not Found", url)));
+  }
+
+  #[test_log::test(tokio::test)]
+  async fn dump_descriptors_writes_the_descriptor_bytes_when_a_dump_directory_is_set() {
+    let dir = tempfile::tempdir().unwrap();
+    let descriptor_bytes = BASE64.decode(DESCRIPTOR_BYTES).unwrap();
+    let hash = hash_descriptors(&descriptor_bytes);
+
+    dump_descriptors(None, hash.as_str(), &descriptor_bytes).await;
+    expect!(dir.path().join(format!("{}.pb", hash)).exists()).to(be_false());
+
+    dump_descriptors(Some(dir.path().to_string_lossy().to_string()), hash.as_str(), &descriptor_bytes).await;
+    let written = std::fs::read(dir.path().join(format!("{}.pb", hash))).unwrap();
+    expect!(written).to(be_equal_to(descriptor_bytes));
+  }
+
+  #[rstest::rstest]
+  #[case::not_set(json!({}), Some(None))]
+  #[case::number(json!({ "responseDelay": 500 }), Some(Some(500)))]
+  #[case::string(json!({ "responseDelay": "500" }), Some(Some(500)))]
+  #[case::negative_number(json!({ "responseDelay": -1 }), None)]
+  #[case::invalid_string(json!({ "responseDelay": "soon" }), None)]
+  fn response_delay_test(#[case] protobuf_config: Value, #[case] expected: Option<Option<u64>>) {
+    let config = btreemap!{
+      "pact:protobuf-config".to_string() => prost_types::Value {
+        kind: Some(StructValue(pact_plugin_driver::utils::to_proto_struct(&protobuf_config.as_object().unwrap().clone().into_iter().collect())))
+      }
+    };
+    let result = response_delay(&config);
+    match expected {
+      Some(delay) => { expect!(result.unwrap()).to(be_equal_to(delay)); },
+      None => { expect!(result).to(be_err()); }
+    }
+  }
+
+  #[rstest::rstest]
+  #[case::not_set(json!({}), Some(None))]
+  #[case::packed(json!({ "packedEncoding": "packed" }), Some(Some("packed")))]
+  #[case::unpacked(json!({ "packedEncoding": "unpacked" }), Some(Some("unpacked")))]
+  #[case::invalid_string(json!({ "packedEncoding": "compressed" }), None)]
+  #[case::boolean(json!({ "packedEncoding": true }), None)]
+  fn packed_encoding_test(#[case] protobuf_config: Value, #[case] expected: Option<Option<&str>>) {
+    let config = btreemap!{
+      "pact:protobuf-config".to_string() => prost_types::Value {
+        kind: Some(StructValue(pact_plugin_driver::utils::to_proto_struct(&protobuf_config.as_object().unwrap().clone().into_iter().collect())))
+      }
+    };
+    let result = packed_encoding(&config);
+    match expected {
+      Some(encoding) => { expect!(result.unwrap()).to(be_equal_to(encoding.map(|e| e.to_string()))); },
+      None => { expect!(result).to(be_err()); }
+    }
+  }
+
+  #[rstest::rstest]
+  #[case::not_set(json!({}), Some(None))]
+  #[case::prefix(json!({ "repeatedFieldMatching": { "$.items": "prefix" } }), Some(Some(json!({ "$.items": "prefix" }))))]
+  #[case::subset(json!({ "repeatedFieldMatching": { "$.items": "subset", "$.tags": "prefix" } }),
+    Some(Some(json!({ "$.items": "subset", "$.tags": "prefix" }))))]
+  #[case::invalid_mode(json!({ "repeatedFieldMatching": { "$.items": "any" } }), None)]
+  #[case::not_a_map(json!({ "repeatedFieldMatching": "subset" }), None)]
+  fn repeated_field_matching_test(#[case] protobuf_config: Value, #[case] expected: Option<Option<Value>>) {
+    let config = btreemap!{
+      "pact:protobuf-config".to_string() => prost_types::Value {
+        kind: Some(StructValue(pact_plugin_driver::utils::to_proto_struct(&protobuf_config.as_object().unwrap().clone().into_iter().collect())))
+      }
+    };
+    let result = repeated_field_matching(&config);
+    match expected {
+      Some(modes) => { expect!(result.unwrap()).to(be_equal_to(modes)); },
+      None => { expect!(result).to(be_err()); }
+    }
+  }
+
+  #[test]
+  fn configure_protobuf_service_with_a_fully_qualified_service_name() {
+    let file_descriptor = |file_name: &str, package: &str| {
+      let message = |name: &str| DescriptorProto {
+        name: Some(name.to_string()),
+        field: vec![
+          FieldDescriptorProto {
+            name: Some("name".to_string()),
+            number: Some(1),
+            r#type: Some(field_descriptor_proto::Type::String as i32),
+            .. FieldDescriptorProto::default()
+          }
+        ],
+        .. DescriptorProto::default()
+      };
+      FileDescriptorProto {
+        name: Some(file_name.to_string()),
+        package: Some(package.to_string()),
+        message_type: vec![ message("Request"), message("Response") ],
+        service: vec![
+          ServiceDescriptorProto {
+            name: Some("Calculator".to_string()),
+            method: vec![
+              MethodDescriptorProto {
+                name: Some("calculateOne".to_string()),
+                input_type: Some(format!(".{}.Request", package)),
+                output_type: Some(format!(".{}.Response", package)),
+                .. MethodDescriptorProto::default()
+              }
+            ],
+            .. ServiceDescriptorProto::default()
+          }
+        ],
+        .. FileDescriptorProto::default()
+      }
+    };
+    let calculator = file_descriptor("calculator.proto", "area_calculator");
+    let other = file_descriptor("other.proto", "other_calculator");
+    let all_descriptors = hashmap! {
+      "calculator.proto".to_string() => &calculator,
+      "other.proto".to_string() => &other
+    };
+    let name_struct = prost_types::Value {
+      kind: Some(prost_types::value::Kind::StructValue(prost_types::Struct {
+        fields: btreemap! { "name".to_string() => prost_string("test") }
+      }))
+    };
+    let config = btreemap! {
+      "request".to_string() => name_struct.clone(),
+      "response".to_string() => name_struct
+    };
+    let service_name = |response: &InteractionResponse| {
+      response.plugin_configuration.as_ref()
+        .and_then(|config| config.interaction_configuration.as_ref())
+        .and_then(|config| config.fields.get("service"))
+        .and_then(proto_value_to_string)
+    };
+
+    let (request, response) = configure_protobuf_service("Calculator/calculateOne", &config,
+      &calculator, &all_descriptors, "1234").unwrap();
+    let request = request.unwrap();
+    expect!(service_name(&request)).to(be_some().value(".area_calculator.Calculator/calculateOne"));
+    expect!(request.contents.unwrap().content_type).to(be_equal_to("application/protobuf;message=.area_calculator.Request"));
+    expect!(service_name(&response[0])).to(be_some().value(".area_calculator.Calculator/calculateOne"));
+
+    let (request, response) = configure_protobuf_service(".other_calculator.Calculator/calculateOne", &config,
+      &calculator, &all_descriptors, "1234").unwrap();
+    let request = request.unwrap();
+    expect!(service_name(&request)).to(be_some().value(".other_calculator.Calculator/calculateOne"));
+    expect!(request.contents.unwrap().content_type).to(be_equal_to("application/protobuf;message=.other_calculator.Request"));
+    expect!(service_name(&response[0])).to(be_some().value(".other_calculator.Calculator/calculateOne"));
+
+    let result = configure_protobuf_service(".unknown.Calculator/calculateOne", &config,
+      &calculator, &all_descriptors, "1234");
+    expect!(result.unwrap_err().to_string()).to(be_equal_to(
+      "Did not find a descriptor for service '.unknown.Calculator/calculateOne' in package 'unknown'"));
+  }
+
+  #[test]
+  fn configure_protobuf_service_stores_the_number_ranges_for_each_part() {
+    let message = |name: &str| DescriptorProto {
+      name: Some(name.to_string()),
+      field: vec![
+        FieldDescriptorProto {
+          name: Some("count".to_string()),
+          number: Some(1),
+          r#type: Some(field_descriptor_proto::Type::Int32 as i32),
+          .. FieldDescriptorProto::default()
+        }
+      ],
+      .. DescriptorProto::default()
+    };
+    let calculator = FileDescriptorProto {
+      name: Some("calculator.proto".to_string()),
+      package: Some("area_calculator".to_string()),
+      message_type: vec![ message("Request"), message("Response") ],
+      service: vec![
+        ServiceDescriptorProto {
+          name: Some("Calculator".to_string()),
+          method: vec![
+            MethodDescriptorProto {
+              name: Some("calculateOne".to_string()),
+              input_type: Some(".area_calculator.Request".to_string()),
+              output_type: Some(".area_calculator.Response".to_string()),
+              .. MethodDescriptorProto::default()
+            }
+          ],
+          .. ServiceDescriptorProto::default()
+        }
+      ],
+      .. FileDescriptorProto::default()
+    };
+    let all_descriptors = hashmap! { "calculator.proto".to_string() => &calculator };
+    let count_struct = |count: &str| prost_types::Value {
+      kind: Some(prost_types::value::Kind::StructValue(prost_types::Struct {
+        fields: btreemap! { "count".to_string() => prost_string(count) }
+      }))
+    };
+    let config = btreemap! {
+      "request".to_string() => count_struct("range(1, 10)"),
+      "response".to_string() => count_struct("range(100, <200)")
+    };
+
+    let (request, _) = configure_protobuf_service("Calculator/calculateOne", &config,
+      &calculator, &all_descriptors, "1234").unwrap();
+    let interaction_config = request.unwrap().plugin_configuration.unwrap().interaction_configuration.unwrap();
+    let ranges = proto_value_to_json(interaction_config.fields.get("numberRanges").unwrap());
+    expect!(ranges).to(be_equal_to(json!({
+      "request": {
+        "$.count": { "min": 1.0, "minExclusive": false, "max": 10.0, "maxExclusive": false }
+      },
+      "response": {
+        "$.count": { "min": 100.0, "minExclusive": false, "max": 200.0, "maxExclusive": true }
+      }
+    })));
+  }
+
+  #[rstest::rstest]
+  #[case::enabled(json!({ "strictMessageType": true }), Some(json!(true)))]
+  #[case::not_set(json!({}), None)]
+  fn configure_protobuf_service_stores_the_strict_message_type_option(
+    #[case] protobuf_config: serde_json::Value,
+    #[case] expected: Option<serde_json::Value>
+  ) {
+    let message = |name: &str| DescriptorProto {
+      name: Some(name.to_string()),
+      field: vec![
+        FieldDescriptorProto {
+          name: Some("name".to_string()),
+          number: Some(1),
+          r#type: Some(field_descriptor_proto::Type::String as i32),
+          .. FieldDescriptorProto::default()
+        }
+      ],
+      .. DescriptorProto::default()
+    };
+    let calculator = FileDescriptorProto {
+      name: Some("calculator.proto".to_string()),
+      package: Some("area_calculator".to_string()),
+      message_type: vec![ message("Request"), message("Response") ],
+      service: vec![
+        ServiceDescriptorProto {
+          name: Some("Calculator".to_string()),
+          method: vec![
+            MethodDescriptorProto {
+              name: Some("calculateOne".to_string()),
+              input_type: Some(".area_calculator.Request".to_string()),
+              output_type: Some(".area_calculator.Response".to_string()),
+              .. MethodDescriptorProto::default()
+            }
+          ],
+          .. ServiceDescriptorProto::default()
+        }
+      ],
+      .. FileDescriptorProto::default()
+    };
+    let all_descriptors = hashmap! { "calculator.proto".to_string() => &calculator };
+    let name_struct = prost_types::Value {
+      kind: Some(prost_types::value::Kind::StructValue(prost_types::Struct {
+        fields: btreemap! { "name".to_string() => prost_string("test") }
+      }))
+    };
+    let config = btreemap! {
+      "request".to_string() => name_struct.clone(),
+      "response".to_string() => name_struct,
+      "pact:protobuf-config".to_string() => prost_types::Value {
+        kind: Some(StructValue(pact_plugin_driver::utils::to_proto_struct(&protobuf_config.as_object().unwrap().clone().into_iter().collect())))
+      }
+    };
+
+    let (request, response) = configure_protobuf_service("Calculator/calculateOne", &config,
+      &calculator, &all_descriptors, "1234").unwrap();
+    for result in [request.unwrap(), response[0].clone()] {
+      let interaction_config = result.plugin_configuration.unwrap().interaction_configuration.unwrap();
+      expect!(interaction_config.fields.get("strictMessageType").map(proto_value_to_json)).to(be_equal_to(expected.clone()));
+    }
+  }
+
+  #[test]
+  fn configure_protobuf_message_stores_the_ignored_fields() {
+    let order = FileDescriptorProto {
+      name: Some("order.proto".to_string()),
+      package: Some("orders".to_string()),
+      message_type: vec![
+        DescriptorProto {
+          name: Some("Order".to_string()),
+          field: vec![
+            FieldDescriptorProto {
+              name: Some("id".to_string()),
+              number: Some(1),
+              r#type: Some(field_descriptor_proto::Type::String as i32),
+              .. FieldDescriptorProto::default()
+            }
+          ],
+          .. DescriptorProto::default()
+        }
+      ],
+      .. FileDescriptorProto::default()
+    };
+    let all_descriptors = hashmap! { "order.proto".to_string() => &order };
+    let protobuf_config = json!({ "ignoreFields": [ "$.created_at", "$.items[*].updated_at" ] });
+    let config = btreemap! {
+      "pact:message-type".to_string() => prost_string("Order"),
+      "id".to_string() => prost_string("100"),
+      "pact:protobuf-config".to_string() => prost_types::Value {
+        kind: Some(StructValue(pact_plugin_driver::utils::to_proto_struct(&protobuf_config.as_object().unwrap().clone().into_iter().collect())))
+      }
+    };
+
+    let result = configure_protobuf_message("Order", &config, &order, "1234", &all_descriptors).unwrap();
+    let interaction_config = result.plugin_configuration.unwrap().interaction_configuration.unwrap();
+    let ignore_fields = proto_value_to_json(interaction_config.fields.get("ignoreFields").unwrap());
+    expect!(ignore_fields).to(be_equal_to(json!([ "$.created_at", "$.items[*].updated_at" ])));
+  }
+
+  #[rstest::rstest]
+  #[case::enabled(json!({ "failFast": true }), Some(json!(true)))]
+  #[case::not_set(json!({}), None)]
+  fn configure_protobuf_message_stores_the_fail_fast_option(#[case] protobuf_config: serde_json::Value, #[case] expected: Option<serde_json::Value>) {
+    let order = FileDescriptorProto {
+      name: Some("order.proto".to_string()),
+      package: Some("orders".to_string()),
+      message_type: vec![
+        DescriptorProto {
+          name: Some("Order".to_string()),
+          field: vec![
+            FieldDescriptorProto {
+              name: Some("id".to_string()),
+              number: Some(1),
+              r#type: Some(field_descriptor_proto::Type::String as i32),
+              .. FieldDescriptorProto::default()
+            }
+          ],
+          .. DescriptorProto::default()
+        }
+      ],
+      .. FileDescriptorProto::default()
+    };
+    let all_descriptors = hashmap! { "order.proto".to_string() => &order };
+    let config = btreemap! {
+      "pact:message-type".to_string() => prost_string("Order"),
+      "id".to_string() => prost_string("100"),
+      "pact:protobuf-config".to_string() => prost_types::Value {
+        kind: Some(StructValue(pact_plugin_driver::utils::to_proto_struct(&protobuf_config.as_object().unwrap().clone().into_iter().collect())))
+      }
+    };
+
+    let result = configure_protobuf_message("Order", &config, &order, "1234", &all_descriptors).unwrap();
+    let interaction_config = result.plugin_configuration.unwrap().interaction_configuration.unwrap();
+    expect!(interaction_config.fields.get("failFast").map(proto_value_to_json)).to(be_equal_to(expected));
+  }
+
+  #[test_log::test]
+  fn build_map_field_with_message_values_and_an_each_value_matcher() {
+    let field_descriptor = FieldDescriptorProto {
+      name: Some("areas".to_string()),
+      number: Some(1),
+      label: Some(Label::Repeated as i32),
+      r#type: Some(Type::Message as i32),
+      type_name: Some(".area_calculator.AreaMap.AreasEntry".to_string()),
+      .. FieldDescriptorProto::default()
+    };
+    let message_descriptor = DescriptorProto {
+      name: Some("AreaMap".to_string()),
+      field: vec![ field_descriptor.clone() ],
+      nested_type: vec![
+        DescriptorProto {
+          name: Some("AreasEntry".to_string()),
+          field: vec![
+            FieldDescriptorProto {
+              name: Some("key".to_string()),
+              number: Some(1),
+              label: Some(Label::Optional as i32),
+              r#type: Some(Type::String as i32),
+              .. FieldDescriptorProto::default()
+            },
+            FieldDescriptorProto {
+              name: Some("value".to_string()),
+              number: Some(2),
+              label: Some(Label::Optional as i32),
+              r#type: Some(Type::Message as i32),
+              type_name: Some(".area_calculator.Area".to_string()),
+              .. FieldDescriptorProto::default()
+            }
+          ],
+          options: Some(MessageOptions {
+            map_entry: Some(true),
+            .. MessageOptions::default()
+          }),
+          .. DescriptorProto::default()
+        }
+      ],
+      .. DescriptorProto::default()
+    };
+
+    let mut message_builder = MessageBuilder::new(&message_descriptor, "AreaMap", &FILE_DESCRIPTOR);
+    let path = DocPath::new("$.areas").unwrap();
+    let mut matching_rules = MatchingRuleCategory::empty("body");
+    let mut generators = hashmap!{};
+    let config = json!({
+      "pact:match": "eachValue(matching(type, 'area'))",
+      "a": {
+        "id": "matching(regex, '\\d+', '1234')",
+        "shape": "matching(type, 'rectangle')",
+        "value": "matching(number, 12)"
+      },
+      "b": {
+        "id": "matching(regex, '\\d+', '5678')",
+        "shape": "matching(type, 'square')",
+        "value": "matching(number, 9)"
+      }
+    });
+    let all_descriptors = hashmap!{
+      "area_calculator.proto".to_string() => &FILE_DESCRIPTOR as &FileDescriptorProto
+    };
+
+    let result = build_map_field(&path, &mut message_builder, &field_descriptor, "areas", &config,
+      &mut matching_rules, &mut generators, &all_descriptors);
+    expect!(result).to(be_ok());
+
+    let expected_rules = matchingrules! {
+      "body" => {
+        "$.areas" => [
+          pact_models::matchingrules::MatchingRule::EachValue(MatchingRuleDefinition::new("area".to_string(),
+            ValueType::String, pact_models::matchingrules::MatchingRule::Type, None))
+        ],
+        "$.areas.*.id" => [ pact_models::matchingrules::MatchingRule::Regex("\\d+".to_string()) ],
+        "$.areas.*.shape" => [ pact_models::matchingrules::MatchingRule::Type ],
+        "$.areas.*.value" => [ pact_models::matchingrules::MatchingRule::Number ]
+      }
+    }.rules_for_category("body").unwrap();
+    expect!(matching_rules).to(be_equal_to(expected_rules));
+    expect!(message_builder.fields.contains_key("areas")).to(be_true());
+  }
+
+  #[test_log::test]
+  fn build_map_field_with_message_values_and_a_regex_key_matcher() {
+    let field_descriptor = FieldDescriptorProto {
+      name: Some("areas".to_string()),
+      number: Some(1),
+      label: Some(Label::Repeated as i32),
+      r#type: Some(Type::Message as i32),
+      type_name: Some(".area_calculator.AreaMap.AreasEntry".to_string()),
+      .. FieldDescriptorProto::default()
+    };
+    let message_descriptor = DescriptorProto {
+      name: Some("AreaMap".to_string()),
+      field: vec![ field_descriptor.clone() ],
+      nested_type: vec![
+        DescriptorProto {
+          name: Some("AreasEntry".to_string()),
+          field: vec![
+            FieldDescriptorProto {
+              name: Some("key".to_string()),
+              number: Some(1),
+              label: Some(Label::Optional as i32),
+              r#type: Some(Type::String as i32),
+              .. FieldDescriptorProto::default()
+            },
+            FieldDescriptorProto {
+              name: Some("value".to_string()),
+              number: Some(2),
+              label: Some(Label::Optional as i32),
+              r#type: Some(Type::Message as i32),
+              type_name: Some(".area_calculator.Area".to_string()),
+              .. FieldDescriptorProto::default()
+            }
+          ],
+          options: Some(MessageOptions {
+            map_entry: Some(true),
+            .. MessageOptions::default()
+          }),
+          .. DescriptorProto::default()
+        }
+      ],
+      .. DescriptorProto::default()
+    };
+
+    let mut message_builder = MessageBuilder::new(&message_descriptor, "AreaMap", &FILE_DESCRIPTOR);
+    let path = DocPath::new("$.areas").unwrap();
+    let mut matching_rules = MatchingRuleCategory::empty("body");
+    let mut generators = hashmap!{};
+    let config = json!({
+      "pact:match": "eachKey(matching(regex, '[a-z]+', 'a'))",
+      "a": {
+        "id": "matching(regex, '\\d+', '1234')",
+        "shape": "matching(type, 'rectangle')",
+        "value": "matching(number, 12)"
+      }
+    });
+    let all_descriptors = hashmap!{
+      "area_calculator.proto".to_string() => &FILE_DESCRIPTOR as &FileDescriptorProto
+    };
+
+    let result = build_map_field(&path, &mut message_builder, &field_descriptor, "areas", &config,
+      &mut matching_rules, &mut generators, &all_descriptors);
+    expect!(result).to(be_ok());
+
+    let expected_rules = matchingrules! {
+      "body" => {
+        "$.areas" => [
+          pact_models::matchingrules::MatchingRule::EachKey(MatchingRuleDefinition::new("a".to_string(),
+            ValueType::String, pact_models::matchingrules::MatchingRule::Regex("[a-z]+".to_string()), None))
+        ],
+        "$.areas.*.id" => [ pact_models::matchingrules::MatchingRule::Regex("\\d+".to_string()) ],
+        "$.areas.*.shape" => [ pact_models::matchingrules::MatchingRule::Type ],
+        "$.areas.*.value" => [ pact_models::matchingrules::MatchingRule::Number ]
+      }
+    }.rules_for_category("body").unwrap();
+    expect!(matching_rules).to(be_equal_to(expected_rules));
+    expect!(message_builder.fields.contains_key("areas")).to(be_true());
+  }
 }
@@ -1,32 +1,52 @@
-use std::collections::HashMap;
+use std::collections::{HashMap, HashSet};
+use std::env;
 use std::env::consts::{ARCH, OS};
 use std::fs;
 use std::fs::File;
 use std::io;
 use std::io::Write;
 use std::ops::Add;
-use std::path::{Path, PathBuf};
+use std::path::{Component, Path, PathBuf};
 use std::str::from_utf8;
 
 use anyhow::anyhow;
 use futures::TryFutureExt;
-use md5::Digest;
 use os_info::{Bitness, Info, Type};
 use pact_models::json_utils::json_to_string;
 use prost::Message;
-use prost_types::FileDescriptorSet;
+use prost_types::{FileDescriptorProto, FileDescriptorSet};
+use regex::Regex;
 use reqwest::Url;
 use serde_json::Value;
-use tempfile::NamedTempFile;
+use tempfile::{NamedTempFile, TempDir};
 use tokio::process::Command;
-use tracing::{debug, error, trace};
+use tracing::{debug, error, info, trace, warn};
 use zip::ZipArchive;
 
+use crate::editions::decode_file_descriptor_set;
+use crate::metrics::{self, Counter};
+use crate::utils::{fds_to_map, hash_descriptors};
+
+/// Standard Protobuf well-known types bundled with the plugin, so that proto files importing them
+/// can be compiled without the user needing to supply them on the include path.
+const WELL_KNOWN_TYPES: [(&str, &str); 7] = [
+  ("google/protobuf/any.proto", include_str!("../proto/google/protobuf/any.proto")),
+  ("google/protobuf/duration.proto", include_str!("../proto/google/protobuf/duration.proto")),
+  ("google/protobuf/empty.proto", include_str!("../proto/google/protobuf/empty.proto")),
+  ("google/protobuf/field_mask.proto", include_str!("../proto/google/protobuf/field_mask.proto")),
+  ("google/protobuf/struct.proto", include_str!("../proto/google/protobuf/struct.proto")),
+  ("google/protobuf/timestamp.proto", include_str!("../proto/google/protobuf/timestamp.proto")),
+  ("google/protobuf/wrappers.proto", include_str!("../proto/google/protobuf/wrappers.proto"))
+];
+
 /// Encapsulation over the Protocol Buffers compiler.
 pub(crate) struct Protoc {
   protoc_path: String,
   local_install: bool,
-  additional_includes: Vec<String>
+  additional_includes: Vec<String>,
+  additional_args: Vec<String>,
+  include_source_info: bool,
+  keep_temp_files: bool
 }
 
 impl Protoc {
@@ -35,7 +55,10 @@ impl Protoc {
     Protoc {
       protoc_path: path,
       local_install,
-      additional_includes
+      additional_includes,
+      additional_args: vec![],
+      include_source_info: false,
+      keep_temp_files: keep_temp_files(env::var(KEEP_TEMP_ENV_VAR).ok())
     }
   }
 
@@ -58,49 +81,47 @@ impl Protoc {
     }
   }
 
-  /// Get protoc to compile the proto file, and the load the file descriptors
-  pub(crate) async fn parse_proto_file(&self, proto_file: &Path) -> anyhow::Result<(FileDescriptorSet, Digest, Vec<u8>)> {
+  /// Get protoc to compile the proto file, and the load the file descriptors. The proto file and
+  /// any files it imports are located and read using the given source.
+  pub(crate) async fn parse_proto_file(
+    &self,
+    proto_file: &Path,
+    source: &dyn ProtoSource
+  ) -> anyhow::Result<(FileDescriptorSet, String, Vec<u8>)> {
     trace!(proto_file = ?proto_file, additional_includes = ?self.additional_includes, "Parsing proto file");
     let tmp_dir = Path::new("tmp");
     fs::create_dir_all(tmp_dir)?;
     let file = NamedTempFile::new_in(tmp_dir)?;
     let output = format!("-o{}", file.path().to_string_lossy());
 
+    // Proto files that are not on the real file system are written out to a temporary directory
+    // for protoc to read. The directory will be deleted when this value is dropped.
+    let (proto_file, _sources_dir) = match source.file_system_path(proto_file) {
+      Some(path) => (path, None),
+      None => {
+        let (path, dir) = write_proto_sources(source, proto_file, tmp_dir)?;
+        (path, Some(dir))
+      }
+    };
+
     // Protoc does not work with Windows \\?\ paths
     let path_str = proto_file.to_string_lossy();
     let path_str = path_str.strip_prefix(r"\\?\").unwrap_or(&*path_str);
     let proto_file = PathBuf::from(path_str);
 
-    let mut parent_dir = proto_file.to_path_buf();
-    parent_dir.pop();
-    let include = format!("-I{}", parent_dir.to_string_lossy());
-
     // Create the protoc command line to invoke
+    let well_known_types = write_well_known_types(tmp_dir)?;
     let mut cmd = Command::new(&self.protoc_path);
-    cmd.arg(output.as_str())
-      .arg(include.as_str())
-      .arg("--include_imports")
-      .arg(proto_file.clone());
-
-    // Add any additional includes defined by the user
-    for inc in &self.additional_includes {
-      cmd.arg(format!("-I{}", inc));
-    }
-
-    // If it is a local install, the default Protobuf well-defined types will be available
-    if self.local_install {
-      let include_path = PathBuf::from("protoc").join("include");
-      let include2 = format!("-I{}", include_path.to_string_lossy());
-      cmd.arg(include2);
-    }
+    cmd.args(self.command_args(output.as_str(), &proto_file, well_known_types.path()));
 
     debug!("Invoking protoc: {:?}", cmd);
-    match cmd.output().await {
+    metrics::increment(Counter::ProtocInvocations);
+    let result = match cmd.output().await {
       Ok(out) => {
         if out.status.success() {
           let data = fs::read(file.path())?;
-          FileDescriptorSet::decode(data.as_slice())
-            .map(|descriptor| (descriptor, md5::compute(data.as_slice()), data))
+          decode_file_descriptor_set(data.as_slice())
+            .map(|descriptor| (descriptor, hash_descriptors(data.as_slice()), data))
             .map_err(|err| anyhow!("Failed to load file descriptor set - {}", err))
         } else {
           error!("Protoc output: {}", from_utf8(out.stdout.as_slice()).unwrap_or_default());
@@ -109,40 +130,359 @@ impl Protoc {
         }
       }
       Err(err) => Err(anyhow!("Failed to invoke protoc binary: {}", err))
+    };
+
+    if result.is_err() {
+      metrics::increment(Counter::ProtocFailures);
     }
+    // A failure to clean up should not hide the result of parsing the proto file
+    if let Err(err) = cleanup_descriptor_file(file, self.keep_temp_files) {
+      warn!("Failed to clean up the descriptor file generated by protoc - {}", err);
+    }
+    result
+  }
+
+  /// Arguments to invoke protoc with to compile the proto file
+  fn command_args(&self, output: &str, proto_file: &Path, well_known_types: &Path) -> Vec<String> {
+    // The directory of the proto file is always on the include path, so any files it imports
+    // relative to itself can be found. A bare file name is relative to the current directory.
+    let parent_dir = proto_file.parent()
+      .filter(|dir| !dir.as_os_str().is_empty())
+      .unwrap_or_else(|| Path::new("."));
+    let mut args = vec![
+      output.to_string(),
+      format!("-I{}", parent_dir.to_string_lossy()),
+      "--include_imports".to_string(),
+      proto_file.to_string_lossy().to_string()
+    ];
+
+    // Source code info is required to include the comments from the proto file in the markup
+    if self.include_source_info {
+      args.push("--include_source_info".to_string());
+    }
+
+    // Add any additional includes defined by the user
+    for inc in &self.additional_includes {
+      args.push(format!("-I{}", inc));
+    }
+
+    // If it is a local install, the default Protobuf well-defined types will be available
+    if self.local_install {
+      let include_path = PathBuf::from("protoc").join("include");
+      args.push(format!("-I{}", include_path.to_string_lossy()));
+    }
+
+    // Add the bundled well-known types last, so any user supplied versions take precedence
+    args.push(format!("-I{}", well_known_types.to_string_lossy()));
+
+    // Any additional arguments from the configuration are passed after our own
+    args.extend(self.additional_args.iter().cloned());
+    args
+  }
+}
+
+/// Locates and reads proto files and the files they import. This allows the proto files to be
+/// provided from somewhere other than the real file system, i.e. from memory or a virtual file
+/// system. Files that are not on the real file system are written to a temporary directory before
+/// protoc is invoked.
+pub trait ProtoSource: Send + Sync {
+  /// Reads the contents of the proto file at the given path
+  fn read_proto_file(&self, path: &Path) -> anyhow::Result<String>;
+
+  /// Resolves a file imported by another proto file (using the path from the import statement) to
+  /// the path it can be read from. Returns `None` if the source does not have the file, in which
+  /// case protoc will look for it on its include paths (i.e. the well-known types).
+  fn resolve_import(&self, import: &str, importing_file: &Path) -> Option<PathBuf>;
+
+  /// If the file is on the real file system, the path protoc can read it from. Defaults to `None`.
+  fn file_system_path(&self, _path: &Path) -> Option<PathBuf> {
+    None
   }
 }
 
-// This function first checks for an unpacked protoc binary, and tries to run that
+/// Proto source that uses the real file system. Protoc reads the files directly, and resolves any
+/// imports using the include paths.
+#[derive(Clone, Copy, Debug, Default)]
+pub struct FileSystemProtoSource;
+
+impl ProtoSource for FileSystemProtoSource {
+  fn read_proto_file(&self, path: &Path) -> anyhow::Result<String> {
+    fs::read_to_string(path)
+      .map_err(|err| anyhow!("Failed to read proto file '{}' - {}", path.display(), err))
+  }
+
+  fn resolve_import(&self, import: &str, importing_file: &Path) -> Option<PathBuf> {
+    importing_file.parent()
+      .map(|dir| dir.join(import))
+      .filter(|path| path.is_file())
+  }
+
+  fn file_system_path(&self, path: &Path) -> Option<PathBuf> {
+    Some(path.to_path_buf())
+  }
+}
+
+/// Returns the additional arguments to pass to protoc from the `protocArgs` configuration value,
+/// which can be a single string or a list of strings. Arguments that would change where the
+/// descriptors are written to are rejected, as the plugin needs to read them back.
+fn protoc_args(config: &HashMap<String, Value>) -> anyhow::Result<Vec<String>> {
+  let args: Vec<String> = match config.get("protocArgs") {
+    Some(Value::Array(list)) => list.iter().map(json_to_string).collect(),
+    Some(Value::Null) | None => vec![],
+    Some(value) => vec![json_to_string(value)]
+  };
+  if let Some(arg) = args.iter().find(|arg| arg.starts_with("-o") || arg.starts_with("--descriptor_set_out")) {
+    return Err(anyhow!("protocArgs can not include '{}', as the plugin sets the descriptor output file", arg));
+  }
+  Ok(args)
+}
+
+/// If the `includeComments` configuration value is set, in which case protoc is invoked with
+/// `--include_source_info` so the comments from the proto file can be included in the markup
+fn include_comments(config: &HashMap<String, Value>) -> bool {
+  match config.get("includeComments") {
+    Some(Value::Bool(b)) => *b,
+    Some(Value::String(s)) => s.to_lowercase() == "true",
+    _ => false
+  }
+}
+
+/// Environment variable that can be set to keep the descriptor files generated by protoc
+const KEEP_TEMP_ENV_VAR: &str = "PACT_PROTOBUF_KEEP_TEMP";
+
+/// If the value of the PACT_PROTOBUF_KEEP_TEMP environment variable enables keeping temp files
+fn keep_temp_files(value: Option<String>) -> bool {
+  value
+    .map(|v| matches!(v.trim().to_lowercase().as_str(), "1" | "true"))
+    .unwrap_or(false)
+}
+
+/// Deletes the descriptor file generated by protoc, unless it should be kept (in which case the
+/// path to the file is returned).
+fn cleanup_descriptor_file(file: NamedTempFile, keep: bool) -> anyhow::Result<Option<PathBuf>> {
+  if keep {
+    let (_, path) = file.keep()?;
+    info!("Keeping the descriptor file generated by protoc: {}", path.display());
+    Ok(Some(path))
+  } else {
+    file.close()?;
+    Ok(None)
+  }
+}
+
+/// Writes the bundled well-known type proto files out to a temporary directory that can be used
+/// as an include path. The directory will be deleted when the returned value is dropped.
+fn write_well_known_types(tmp_dir: &Path) -> anyhow::Result<TempDir> {
+  let dir = TempDir::new_in(tmp_dir)?;
+  for (name, contents) in WELL_KNOWN_TYPES {
+    let path = dir.path().join(name);
+    if let Some(parent) = path.parent() {
+      fs::create_dir_all(parent)?;
+    }
+    fs::write(&path, contents)?;
+  }
+  trace!("Wrote the bundled well-known types to {}", dir.path().display());
+  Ok(dir)
+}
+
+/// Writes the proto file and any files it imports that the source can resolve out to a temporary
+/// directory, so they can be compiled by protoc. The imported files are written to the path used
+/// in the import statement, relative to the directory. Returns the path of the written proto file
+/// and the directory, which will be deleted when it is dropped.
+fn write_proto_sources(source: &dyn ProtoSource, proto_file: &Path, tmp_dir: &Path) -> anyhow::Result<(PathBuf, TempDir)> {
+  let import_regex = Regex::new(r#"(?m)^\s*import\s+(?:public\s+|weak\s+)?"([^"]+)"\s*;"#)?;
+  let dir = TempDir::new_in(tmp_dir)?;
+  let file_name = proto_file.file_name()
+    .ok_or_else(|| anyhow!("'{}' is not a valid proto file path", proto_file.display()))?;
+  let written_file = dir.path().join(file_name);
+  let main_file_name = file_name.to_string_lossy().to_string();
+
+  // The main file is already written, so an import with the same name can only refer to it
+  let mut written = HashSet::from([main_file_name.clone()]);
+  let mut pending = vec![(proto_file.to_path_buf(), written_file.clone())];
+  while let Some((path, target)) = pending.pop() {
+    let contents = source.read_proto_file(&path)?;
+    for import in import_regex.captures_iter(&contents).filter_map(|captures| captures.get(1)) {
+      let import = import.as_str();
+      check_import_path(import)?;
+      if written.insert(import.to_string()) {
+        match source.resolve_import(import, &path) {
+          Some(import_path) => pending.push((import_path, dir.path().join(import))),
+          None => trace!("Import '{}' was not resolved by the proto source", import)
+        }
+      } else if import == main_file_name
+        && source.resolve_import(import, &path).is_some_and(|import_path| import_path != proto_file) {
+        return Err(anyhow!("Import '{}' in '{}' has the same name as the proto file '{}'", import,
+          path.display(), proto_file.display()));
+      }
+    }
+    if let Some(parent) = target.parent() {
+      fs::create_dir_all(parent)?;
+    }
+    fs::write(&target, contents)?;
+  }
+
+  trace!("Wrote the proto files for '{}' to {}", proto_file.display(), dir.path().display());
+  Ok((written_file, dir))
+}
+
+/// Checks that the import is a relative path that stays within the directory the proto files are
+/// written to
+fn check_import_path(import: &str) -> anyhow::Result<()> {
+  if Path::new(import).components().all(|component| matches!(component, Component::Normal(_) | Component::CurDir)) {
+    Ok(())
+  } else {
+    Err(anyhow!("Import '{}' must be a relative path that does not contain '..'", import))
+  }
+}
+
+/// Environment variable that can be used to set the path to the protoc binary
+const PROTOC_ENV_VAR: &str = "PROTOC";
+
+// This function first checks for the protoc binary set with the PROTOC environment variable
+// otherwise it checks for an unpacked protoc binary, and tries to run that
 // otherwise it will try unpack the version for the current OS
 // otherwise it will try download and unpack the version for the current OS
 // otherwise then fallback to any version on the system path
 // will error if unable to do that
+// Any additional arguments configured with `protocArgs` are passed to protoc when it is invoked.
 pub(crate) async fn setup_protoc(config: &HashMap<String, Value>, additional_includes: &Vec<String>) -> anyhow::Result<Protoc> {
-  trace!(config = ?config, additional_includes = ?additional_includes, "Setting up protoc");
+  let additional_args = protoc_args(config)?;
+  let protoc = find_protoc(config, additional_includes, env::var(PROTOC_ENV_VAR).ok(), Path::new(".")).await?;
+  Ok(Protoc { additional_args, include_source_info: include_comments(config), .. protoc })
+}
+
+/// Parses the given proto file with protoc, using any additional include paths, and returns the
+/// resulting file descriptors. This is used by the `validate` command of the plugin binary.
+pub async fn validate_proto_file(proto_file: &Path, additional_includes: &[String]) -> anyhow::Result<FileDescriptorSet> {
+  let (descriptors, _, _) = parse_with_includes(proto_file, additional_includes, &FileSystemProtoSource).await?;
+  Ok(descriptors)
+}
+
+/// Sets up protoc with the additional include paths, and parses the proto file read from the source
+async fn parse_with_includes(
+  proto_file: &Path,
+  additional_includes: &[String],
+  source: &dyn ProtoSource
+) -> anyhow::Result<(FileDescriptorSet, String, Vec<u8>)> {
+  let protoc = setup_protoc(&HashMap::default(), &additional_includes.to_vec()).await?;
+  protoc.parse_proto_file(proto_file, source).await
+}
+
+/// Descriptors parsed from a proto file, which can be reused across many operations without
+/// having to invoke protoc again.
+#[derive(Clone, Debug)]
+pub struct DescriptorCache {
+  descriptors: FileDescriptorSet,
+  descriptor_hash: String,
+  descriptor_bytes: Vec<u8>
+}
+
+impl DescriptorCache {
+  /// Creates a cache for descriptors that have already been parsed (i.e. loaded from a Pact file)
+  pub fn new(descriptors: FileDescriptorSet) -> DescriptorCache {
+    let descriptor_bytes = descriptors.encode_to_vec();
+    DescriptorCache {
+      descriptor_hash: hash_descriptors(&descriptor_bytes),
+      descriptors,
+      descriptor_bytes
+    }
+  }
+
+  /// All the file descriptors, including those for any imported files
+  pub fn descriptors(&self) -> &FileDescriptorSet {
+    &self.descriptors
+  }
+
+  /// Hash of the encoded descriptors. This is the key used to store the descriptors in the Pact file.
+  pub fn descriptor_hash(&self) -> &str {
+    self.descriptor_hash.as_str()
+  }
+
+  /// The encoded file descriptor set
+  pub fn descriptor_bytes(&self) -> &[u8] {
+    self.descriptor_bytes.as_slice()
+  }
+
+  /// Map of the file descriptors, keyed by file name
+  pub fn file_descriptors(&self) -> HashMap<String, &FileDescriptorProto> {
+    fds_to_map(&self.descriptors)
+  }
+}
+
+/// Parses the given proto file with protoc, using any additional include paths, and returns a
+/// cache of the resulting descriptors.
+pub async fn parse_to_cache(proto: &Path, includes: &[PathBuf]) -> anyhow::Result<DescriptorCache> {
+  parse_to_cache_from_source(proto, includes, &FileSystemProtoSource).await
+}
+
+/// Parses the given proto file with protoc, reading it and any files it imports from the source,
+/// and returns a cache of the resulting descriptors.
+pub async fn parse_to_cache_from_source(
+  proto: &Path,
+  includes: &[PathBuf],
+  source: &dyn ProtoSource
+) -> anyhow::Result<DescriptorCache> {
+  let includes = includes.iter()
+    .map(|include| include.to_string_lossy().to_string())
+    .collect::<Vec<_>>();
+  let (descriptors, descriptor_hash, descriptor_bytes) = parse_with_includes(proto, &includes, source).await?;
+  Ok(DescriptorCache {
+    descriptors,
+    descriptor_hash,
+    descriptor_bytes
+  })
+}
+
+/// Finds the protoc binary to use. `base_dir` is the plugin installation directory, where any
+/// unpacked or downloaded protoc binary is located.
+async fn find_protoc(
+  config: &HashMap<String, Value>,
+  additional_includes: &Vec<String>,
+  protoc_env: Option<String>,
+  base_dir: &Path
+) -> anyhow::Result<Protoc> {
+  trace!(config = ?config, additional_includes = ?additional_includes, ?protoc_env, "Setting up protoc");
+  if let Some(protoc_path) = protoc_env.filter(|path| !path.trim().is_empty()) {
+    debug!("Using protoc binary from the {} environment variable: '{}'", PROTOC_ENV_VAR, protoc_path);
+    let protoc = Protoc::new(protoc_path.clone(), false, additional_includes.to_owned());
+    return match protoc.invoke().await {
+      Ok(_) => Ok(protoc),
+      Err(err) => Err(anyhow!("The protoc binary '{}' set with the {} environment variable could not be executed ({}). \
+        Make sure it points to a valid Protocol Buffers compiler (protoc) binary.", protoc_path, PROTOC_ENV_VAR, err))
+    };
+  }
+
   let os_info = os_info::get();
   debug!("Detected OS: {}", os_info);
 
-  local_protoc(&os_info, additional_includes)
+  local_protoc(&os_info, additional_includes, base_dir)
     .or_else(|err| {
       trace!("local_protoc: {}", err);
-      unpack_protoc(config, &os_info, additional_includes)
+      unpack_protoc(config, &os_info, additional_includes, base_dir)
     })
     .or_else(|err| {
       trace!("unpack_protoc: {}", err);
-      download_protoc(config, &os_info, additional_includes)
+      download_protoc(config, &os_info, additional_includes, base_dir)
     })
     .or_else(|err| {
       trace!("download_protoc: {}", err);
       system_protoc(additional_includes)
     })
     .await
+    .map_err(|err| {
+      error!("Could not find a protoc binary to use: {}", err);
+      anyhow!("Could not find or execute the Protocol Buffers compiler (protoc): {}. Install protoc and make sure \
+        it is on the system path, or set the {} environment variable to the path of the protoc binary. \
+        See https://grpc.io/docs/protoc-installation/ for installation instructions.", err, PROTOC_ENV_VAR)
+    })
 }
 
 async fn download_protoc(
   config: &HashMap<String, Value>,
   os_info: &Info,
-  additional_includes: &Vec<String>
+  additional_includes: &Vec<String>,
+  base_dir: &Path
 ) -> anyhow::Result<Protoc> {
   trace!("download_protoc: config = {:?}", config);
   let protoc_version = config.get("protocVersion")
@@ -166,14 +506,14 @@ async fn download_protoc(
   let mut response = reqwest::get(url).await?;
 
   if response.status().is_success() {
-    let mut protoc_file = File::create(format!("./protoc-{}-{}.zip", protoc_version, os_type))?;
+    let mut protoc_file = File::create(base_dir.join(format!("protoc-{}-{}.zip", protoc_version, os_type)))?;
     let mut count: usize = 0;
     while let Some(chunk) = response.chunk().await? {
       count += chunk.len();
       protoc_file.write_all(chunk.as_ref())?;
     }
     debug!("Downloaded {} bytes", count);
-    unpack_protoc(config, os_info, additional_includes).await
+    unpack_protoc(config, os_info, additional_includes, base_dir).await
   } else {
     Err(anyhow!("Failed to download protoc - {}", response.status()))
   }
@@ -200,8 +540,8 @@ async fn system_protoc(additional_includes: &[String]) -> anyhow::Result<Protoc>
   }
 }
 
-async fn local_protoc(os_info: &Info, additional_includes: &[String]) -> anyhow::Result<Protoc> {
-  let path = PathBuf::from(".")
+async fn local_protoc(os_info: &Info, additional_includes: &[String], base_dir: &Path) -> anyhow::Result<Protoc> {
+  let path = base_dir
       .join("protoc")
       .join("bin");
   let local_path = if os_info.os_type() == Type::Windows {
@@ -225,28 +565,28 @@ async fn local_protoc(os_info: &Info, additional_includes: &[String]) -> anyhow:
 async fn unpack_protoc(
   config: &HashMap<String, Value>,
   os_info: &Info,
-  additional_includes: &[String]
+  additional_includes: &[String],
+  base_dir: &Path
 ) -> anyhow::Result<Protoc> {
   let protoc_version = config.get("protocVersion")
     .map(json_to_string)
     .ok_or_else(|| anyhow!("Could not get the protoc version from the manifest"))?;
-  let protoc_file = format!("./protoc-{}-{}.zip", protoc_version, os_type(os_info.bitness(), ARCH, OS));
-  trace!("Looking for protoc zip archive '{}'", protoc_file);
-  let protoc_zip_path = Path::new(protoc_file.as_str());
+  let protoc_zip_path = base_dir.join(format!("protoc-{}-{}.zip", protoc_version, os_type(os_info.bitness(), ARCH, OS)));
+  trace!("Looking for protoc zip archive '{}'", protoc_zip_path.to_string_lossy());
   if protoc_zip_path.exists() {
     debug!("Found protoc zip archive: {}", protoc_zip_path.to_string_lossy());
-    unzip_proto_archive(protoc_zip_path)?;
-    local_protoc(os_info, additional_includes).await
+    unzip_proto_archive(&protoc_zip_path, base_dir)?;
+    local_protoc(os_info, additional_includes, base_dir).await
   } else {
     trace!("protoc zip archive not found");
     Err(anyhow!("No local protoc zip archive"))
   }
 }
 
-fn unzip_proto_archive(archive_path: &Path) -> anyhow::Result<()> {
+fn unzip_proto_archive(archive_path: &Path, base_dir: &Path) -> anyhow::Result<()> {
   let file = File::open(archive_path)?;
   let mut archive = ZipArchive::new(file)?;
-  let base_path = PathBuf::from("protoc");
+  let base_path = base_dir.join("protoc");
   for i in 0..archive.len() {
     let mut file = archive.by_index(i)?;
     let outpath = match file.enclosed_name() {
@@ -308,10 +648,39 @@ fn os_type(os_info: Bitness, arch: &str, os: &str) -> String {
 
 #[cfg(test)]
 mod tests {
+  use std::collections::HashMap;
+  use std::env::consts::{ARCH, OS};
+  use std::fs;
+  use std::fs::File;
+  use std::io::Write;
+  use std::path::{Path, PathBuf};
+
   use expectest::prelude::*;
+  use maplit::hashmap;
   use os_info::Bitness;
+  use serde_json::json;
+  use tempfile::NamedTempFile;
+  use zip::write::SimpleFileOptions;
+
+  use crate::message_builder::{MessageBuilder, MessageFieldValue, RType};
+  use crate::utils::hash_descriptors;
 
-  use super::os_type;
+  use super::{
+    cleanup_descriptor_file,
+    FileSystemProtoSource,
+    find_protoc,
+    keep_temp_files,
+    os_type,
+    parse_to_cache,
+    parse_to_cache_from_source,
+    Protoc,
+    ProtoSource,
+    protoc_args,
+    setup_protoc,
+    write_proto_sources,
+    write_well_known_types,
+    WELL_KNOWN_TYPES
+  };
 
   #[test]
   fn os_type_test() {
@@ -323,4 +692,382 @@ mod tests {
     expect!(os_type(Bitness::X32, "", "windows").as_str()).to(be_equal_to("win32"));
     expect!(os_type(Bitness::X64, "", "windows").as_str()).to(be_equal_to("win64"));
   }
+
+  #[test]
+  fn write_well_known_types_test() {
+    let tmp_dir = Path::new("tmp");
+    fs::create_dir_all(tmp_dir).unwrap();
+    let dir = write_well_known_types(tmp_dir).unwrap();
+    for (name, _) in WELL_KNOWN_TYPES {
+      expect!(dir.path().join(name).exists()).to(be_true());
+    }
+  }
+
+  #[test]
+  fn command_args_includes_the_additional_arguments_after_the_plugin_arguments() {
+    let protoc = Protoc {
+      additional_args: vec!["--experimental_allow_proto3_optional".to_string(), "--fatal_warnings".to_string()],
+      .. Protoc::new("protoc".to_string(), false, vec!["/includes".to_string()])
+    };
+    let args = protoc.command_args("-otmp/out", Path::new("/protos/test.proto"), Path::new("/wkt"));
+    expect!(args).to(be_equal_to(vec![
+      "-otmp/out".to_string(),
+      "-I/protos".to_string(),
+      "--include_imports".to_string(),
+      "/protos/test.proto".to_string(),
+      "-I/includes".to_string(),
+      "-I/wkt".to_string(),
+      "--experimental_allow_proto3_optional".to_string(),
+      "--fatal_warnings".to_string()
+    ]));
+  }
+
+  #[test]
+  fn command_args_uses_the_current_directory_for_a_proto_file_without_a_directory() {
+    let protoc = Protoc::new("protoc".to_string(), false, vec![]);
+    let args = protoc.command_args("-otmp/out", Path::new("test.proto"), Path::new("/wkt"));
+    expect!(args).to(be_equal_to(vec![
+      "-otmp/out".to_string(),
+      "-I.".to_string(),
+      "--include_imports".to_string(),
+      "test.proto".to_string(),
+      "-I/wkt".to_string()
+    ]));
+  }
+
+  #[test]
+  fn protoc_args_test() {
+    expect!(protoc_args(&hashmap!{}).unwrap()).to(be_equal_to(Vec::<String>::new()));
+    expect!(protoc_args(&hashmap!{ "protocArgs".to_string() => json!("--fatal_warnings") }).unwrap())
+      .to(be_equal_to(vec!["--fatal_warnings".to_string()]));
+    expect!(protoc_args(&hashmap!{ "protocArgs".to_string() => json!(["--fatal_warnings", "--experimental_editions"]) }).unwrap())
+      .to(be_equal_to(vec!["--fatal_warnings".to_string(), "--experimental_editions".to_string()]));
+
+    let result = protoc_args(&hashmap!{ "protocArgs".to_string() => json!(["--fatal_warnings", "-oother.pb"]) });
+    expect!(result.unwrap_err().to_string()).to(be_equal_to(
+      "protocArgs can not include '-oother.pb', as the plugin sets the descriptor output file"));
+    let result = protoc_args(&hashmap!{ "protocArgs".to_string() => json!("--descriptor_set_out=other.pb") });
+    expect!(result).to(be_err());
+  }
+
+  #[test]
+  fn keep_temp_files_test() {
+    expect!(keep_temp_files(None)).to(be_false());
+    expect!(keep_temp_files(Some("".to_string()))).to(be_false());
+    expect!(keep_temp_files(Some("0".to_string()))).to(be_false());
+    expect!(keep_temp_files(Some("1".to_string()))).to(be_true());
+    expect!(keep_temp_files(Some("true".to_string()))).to(be_true());
+    expect!(keep_temp_files(Some("TRUE".to_string()))).to(be_true());
+  }
+
+  #[test]
+  fn cleanup_descriptor_file_deletes_the_file_by_default() {
+    let tmp_dir = Path::new("tmp");
+    fs::create_dir_all(tmp_dir).unwrap();
+    let file = NamedTempFile::new_in(tmp_dir).unwrap();
+    let path = file.path().to_path_buf();
+
+    expect!(cleanup_descriptor_file(file, false).unwrap()).to(be_none());
+    expect!(path.exists()).to(be_false());
+  }
+
+  #[test]
+  fn cleanup_descriptor_file_keeps_the_file_when_the_flag_is_set() {
+    let tmp_dir = Path::new("tmp");
+    fs::create_dir_all(tmp_dir).unwrap();
+    let file = NamedTempFile::new_in(tmp_dir).unwrap();
+    let path = file.path().to_path_buf();
+
+    let kept = cleanup_descriptor_file(file, true).unwrap();
+    expect!(kept.as_ref()).to(be_some().value(&path));
+    expect!(path.exists()).to(be_true());
+    fs::remove_file(path).unwrap();
+  }
+
+  #[test_log::test(tokio::test)]
+  async fn setup_protoc_with_a_missing_protoc_binary_returns_an_actionable_error() {
+    let result = find_protoc(&hashmap!{}, &vec![], Some("/does/not/exist/protoc".to_string()), Path::new(".")).await;
+    let message = result.err().unwrap().to_string();
+    expect!(message.starts_with("The protoc binary '/does/not/exist/protoc' set with the PROTOC environment variable could not be executed")).to(be_true());
+    expect!(message.contains("Make sure it points to a valid Protocol Buffers compiler (protoc) binary")).to(be_true());
+  }
+
+  /// Script that behaves like protoc when invoked with `--version`
+  const FAKE_PROTOC: &str = "#!/bin/sh\necho 'libprotoc 25.1'\n";
+
+  #[cfg(unix)]
+  fn write_fake_protoc(path: &Path) {
+    use std::os::unix::fs::PermissionsExt;
+    fs::create_dir_all(path.parent().unwrap()).unwrap();
+    fs::write(path, FAKE_PROTOC).unwrap();
+    fs::set_permissions(path, fs::Permissions::from_mode(0o755)).unwrap();
+  }
+
+  #[cfg(unix)]
+  #[test_log::test(tokio::test)]
+  async fn find_protoc_uses_the_protoc_env_var_before_a_local_protoc() {
+    let base_dir = tempfile::tempdir().unwrap();
+    write_fake_protoc(&base_dir.path().join("protoc").join("bin").join("protoc"));
+    let env_protoc = base_dir.path().join("env").join("protoc");
+    write_fake_protoc(&env_protoc);
+
+    let protoc = find_protoc(&hashmap!{}, &vec![], Some(env_protoc.to_string_lossy().to_string()),
+      base_dir.path()).await.unwrap();
+    expect!(protoc.protoc_path).to(be_equal_to(env_protoc.to_string_lossy().to_string()));
+    expect!(protoc.local_install).to(be_false());
+  }
+
+  #[cfg(unix)]
+  #[test_log::test(tokio::test)]
+  async fn find_protoc_uses_a_local_protoc_when_the_env_var_is_not_set() {
+    let base_dir = tempfile::tempdir().unwrap();
+    let local_protoc = base_dir.path().join("protoc").join("bin").join("protoc");
+    write_fake_protoc(&local_protoc);
+
+    for protoc_env in [None, Some(" ".to_string())] {
+      let protoc = find_protoc(&hashmap!{}, &vec![], protoc_env, base_dir.path()).await.unwrap();
+      expect!(protoc.protoc_path).to(be_equal_to(local_protoc.to_string_lossy().to_string()));
+      expect!(protoc.local_install).to(be_true());
+    }
+  }
+
+  #[cfg(unix)]
+  #[test_log::test(tokio::test)]
+  async fn find_protoc_unpacks_the_archive_for_the_configured_protoc_version() {
+    let base_dir = tempfile::tempdir().unwrap();
+    let archive_path = base_dir.path()
+      .join(format!("protoc-25.1-{}.zip", os_type(os_info::get().bitness(), ARCH, OS)));
+    let mut archive = zip::ZipWriter::new(File::create(&archive_path).unwrap());
+    archive.start_file("bin/protoc", SimpleFileOptions::default().unix_permissions(0o755)).unwrap();
+    archive.write_all(FAKE_PROTOC.as_bytes()).unwrap();
+    archive.finish().unwrap();
+    let config = hashmap!{ "protocVersion".to_string() => json!("25.1") };
+
+    let protoc = find_protoc(&config, &vec![], None, base_dir.path()).await.unwrap();
+    let local_protoc = base_dir.path().join("protoc").join("bin").join("protoc");
+    expect!(protoc.protoc_path).to(be_equal_to(local_protoc.to_string_lossy().to_string()));
+    expect!(protoc.local_install).to(be_true());
+  }
+
+  #[test_log::test(tokio::test)]
+  async fn find_protoc_falls_back_to_the_system_protoc() {
+    let base_dir = tempfile::tempdir().unwrap();
+    // Nothing has been unpacked in the plugin directory, and protoc can not be downloaded
+    let config = hashmap!{
+      "protocVersion".to_string() => json!("25.1"),
+      "downloadUrl".to_string() => json!("http://127.0.0.1:1/")
+    };
+
+    let protoc = find_protoc(&config, &vec![], None, base_dir.path()).await.unwrap();
+    expect!(protoc.local_install).to(be_false());
+    expect!(protoc.invoke().await).to(be_ok());
+  }
+
+  #[test_log::test(tokio::test)]
+  async fn parse_proto_file_with_an_import_of_a_sibling_file_and_no_additional_includes() {
+    let proto_dir = tempfile::tempdir().unwrap();
+    fs::write(proto_dir.path().join("common.proto"), r#"syntax = "proto3";
+
+      message Address {
+        string street = 1;
+      }
+    "#).unwrap();
+    let proto_file = proto_dir.path().join("person.proto");
+    fs::write(&proto_file, r#"syntax = "proto3";
+
+      import "common.proto";
+
+      message Person {
+        string name = 1;
+        Address address = 2;
+      }
+    "#).unwrap();
+
+    let protoc = setup_protoc(&hashmap!{}, &vec![]).await.unwrap();
+    let (fds, _, _) = protoc.parse_proto_file(&proto_file, &FileSystemProtoSource).await.unwrap();
+    let files = fds.file.iter().map(|f| f.name.clone().unwrap_or_default()).collect::<Vec<_>>();
+    expect!(files).to(be_equal_to(vec!["common.proto".to_string(), "person.proto".to_string()]));
+  }
+
+  #[test_log::test(tokio::test)]
+  async fn parse_proto_file_with_well_known_type_import_and_no_additional_includes() {
+    let proto_dir = tempfile::tempdir().unwrap();
+    let proto_file = proto_dir.path().join("wkt.proto");
+    fs::write(&proto_file, r#"syntax = "proto3";
+
+      import "google/protobuf/timestamp.proto";
+
+      message Event {
+        string name = 1;
+        google.protobuf.Timestamp created = 2;
+      }
+    "#).unwrap();
+
+    let protoc = setup_protoc(&hashmap!{}, &vec![]).await.unwrap();
+    let (fds, _, _) = protoc.parse_proto_file(&proto_file, &FileSystemProtoSource).await.unwrap();
+    let files = fds.file.iter().map(|f| f.name.clone().unwrap_or_default()).collect::<Vec<_>>();
+    expect!(files).to(be_equal_to(vec!["google/protobuf/timestamp.proto".to_string(), "wkt.proto".to_string()]));
+  }
+
+  #[test_log::test(tokio::test)]
+  async fn parse_to_cache_test() {
+    let proto_file = Path::new("tests/area_calculator.proto");
+    let cache = parse_to_cache(proto_file, &[]).await.unwrap();
+
+    let file_descriptors = cache.file_descriptors();
+    expect!(file_descriptors.keys().cloned().collect::<Vec<_>>()).to(be_equal_to(vec!["area_calculator.proto".to_string()]));
+    let descriptor = file_descriptors.get("area_calculator.proto").unwrap();
+    expect!(descriptor.package()).to(be_equal_to("area_calculator"));
+    let service = descriptor.service.first().unwrap();
+    expect!(service.name()).to(be_equal_to("Calculator"));
+    expect!(service.method.iter().map(|method| method.name()).collect::<Vec<_>>())
+      .to(be_equal_to(vec!["calculateOne", "calculateMulti"]));
+    expect!(cache.descriptor_hash()).to(be_equal_to(hash_descriptors(cache.descriptor_bytes()).as_str()));
+  }
+
+  #[test_log::test(tokio::test)]
+  async fn parse_proto_file_with_comments_includes_them_in_the_markup() {
+    let proto_dir = tempfile::tempdir().unwrap();
+    let proto_file = proto_dir.path().join("comments.proto");
+    fs::write(&proto_file, r#"syntax = "proto3";
+
+      message Person {
+        // Full name of the person
+        string name = 1;
+      }
+    "#).unwrap();
+
+    let protoc = setup_protoc(&hashmap!{ "includeComments".to_string() => json!(true) }, &vec![]).await.unwrap();
+    let (fds, _, _) = protoc.parse_proto_file(&proto_file, &FileSystemProtoSource).await.unwrap();
+    let file_descriptor = fds.file.iter().find(|f| f.name() == "comments.proto").unwrap();
+    let message_descriptor = file_descriptor.message_type.first().unwrap();
+    let field_descriptor = message_descriptor.field.first().unwrap();
+    let mut message = MessageBuilder::new(message_descriptor, "Person", file_descriptor);
+    message.set_field_value(field_descriptor, "name", MessageFieldValue {
+      name: "name".to_string(),
+      raw_value: Some("Fred".to_string()),
+      rtype: RType::String("Fred".to_string())
+    });
+    expect!(message.generate_markup("").unwrap()).to(be_equal_to(
+      "```protobuf\nmessage Person {\n    // Full name of the person\n    string name = 1;\n}\n```\n"));
+
+    let protoc = setup_protoc(&hashmap!{}, &vec![]).await.unwrap();
+    let (fds, _, _) = protoc.parse_proto_file(&proto_file, &FileSystemProtoSource).await.unwrap();
+    let file_descriptor = fds.file.iter().find(|f| f.name() == "comments.proto").unwrap();
+    expect!(file_descriptor.source_code_info.as_ref()).to(be_none());
+  }
+
+  #[test]
+  fn command_args_includes_the_source_info_when_comments_are_included() {
+    let protoc = Protoc {
+      include_source_info: true,
+      .. Protoc::new("protoc".to_string(), false, vec![])
+    };
+    let args = protoc.command_args("-otmp/out", Path::new("/protos/test.proto"), Path::new("/wkt"));
+    expect!(args).to(be_equal_to(vec![
+      "-otmp/out".to_string(),
+      "-I/protos".to_string(),
+      "--include_imports".to_string(),
+      "/protos/test.proto".to_string(),
+      "--include_source_info".to_string(),
+      "-I/wkt".to_string()
+    ]));
+  }
+
+  struct InMemoryProtoSource {
+    files: HashMap<PathBuf, String>
+  }
+
+  impl ProtoSource for InMemoryProtoSource {
+    fn read_proto_file(&self, path: &Path) -> anyhow::Result<String> {
+      self.files.get(path).cloned()
+        .ok_or_else(|| anyhow::anyhow!("No such file '{}'", path.display()))
+    }
+
+    fn resolve_import(&self, import: &str, _importing_file: &Path) -> Option<PathBuf> {
+      let path = Path::new("/virtual").join(import);
+      self.files.contains_key(&path).then_some(path)
+    }
+  }
+
+  #[test_log::test(tokio::test)]
+  async fn parse_to_cache_from_an_in_memory_source() {
+    let source = InMemoryProtoSource {
+      files: hashmap!{
+        PathBuf::from("/virtual/common/address.proto") => r#"syntax = "proto3";
+
+          message Address {
+            string street = 1;
+          }
+        "#.to_string(),
+        PathBuf::from("/virtual/person.proto") => r#"syntax = "proto3";
+
+          import "common/address.proto";
+          import "google/protobuf/timestamp.proto";
+
+          message Person {
+            string name = 1;
+            Address address = 2;
+            google.protobuf.Timestamp created = 3;
+          }
+        "#.to_string()
+      }
+    };
+
+    let cache = parse_to_cache_from_source(Path::new("/virtual/person.proto"), &[], &source).await.unwrap();
+    let files = cache.descriptors().file.iter().map(|f| f.name.clone().unwrap_or_default()).collect::<Vec<_>>();
+    expect!(files).to(be_equal_to(vec![
+      "common/address.proto".to_string(),
+      "google/protobuf/timestamp.proto".to_string(),
+      "person.proto".to_string()
+    ]));
+
+    let result = parse_to_cache_from_source(Path::new("/virtual/missing.proto"), &[], &source).await;
+    expect!(result.unwrap_err().to_string()).to(be_equal_to("No such file '/virtual/missing.proto'"));
+  }
+
+  #[rstest::rstest]
+  #[case::absolute_import("/etc/common.proto", "Import '/etc/common.proto' must be a relative path that does not contain '..'")]
+  #[case::parent_import("../common.proto", "Import '../common.proto' must be a relative path that does not contain '..'")]
+  #[case::nested_parent_import("common/../../common.proto",
+    "Import 'common/../../common.proto' must be a relative path that does not contain '..'")]
+  #[case::same_name_as_the_proto_file("person.proto",
+    "Import 'person.proto' in '/virtual/v1/person.proto' has the same name as the proto file '/virtual/v1/person.proto'")]
+  fn write_proto_sources_rejects_imports_outside_the_directory(#[case] import: &str, #[case] error: &str) {
+    let source = InMemoryProtoSource {
+      files: hashmap!{
+        PathBuf::from("/virtual/person.proto") => "syntax = \"proto3\";".to_string(),
+        PathBuf::from("/virtual/v1/person.proto") => format!("syntax = \"proto3\";\nimport \"{}\";", import)
+      }
+    };
+    let tmp_dir = tempfile::tempdir().unwrap();
+
+    let result = write_proto_sources(&source, Path::new("/virtual/v1/person.proto"), tmp_dir.path());
+    expect!(result.unwrap_err().to_string()).to(be_equal_to(error));
+  }
+
+  #[test]
+  fn write_proto_sources_does_not_overwrite_the_proto_file_with_an_import() {
+    let source = InMemoryProtoSource {
+      files: hashmap!{
+        PathBuf::from("/virtual/person.proto") => "syntax = \"proto3\";\nimport \"person.proto\";".to_string()
+      }
+    };
+    let tmp_dir = tempfile::tempdir().unwrap();
+
+    let (path, _dir) = write_proto_sources(&source, Path::new("/virtual/person.proto"), tmp_dir.path()).unwrap();
+    expect!(fs::read_to_string(path).unwrap()).to(be_equal_to("syntax = \"proto3\";\nimport \"person.proto\";"));
+  }
+
+  #[test]
+  fn file_system_proto_source_resolves_imports_relative_to_the_importing_file() {
+    let proto_dir = tempfile::tempdir().unwrap();
+    fs::write(proto_dir.path().join("common.proto"), "syntax = \"proto3\";").unwrap();
+    let importing_file = proto_dir.path().join("person.proto");
+
+    expect!(FileSystemProtoSource.resolve_import("common.proto", &importing_file))
+      .to(be_some().value(proto_dir.path().join("common.proto")));
+    expect!(FileSystemProtoSource.resolve_import("missing.proto", &importing_file)).to(be_none());
+    expect!(FileSystemProtoSource.file_system_path(&importing_file)).to(be_some().value(importing_file.clone()));
+  }
 }
@@ -4,6 +4,7 @@ use std::collections::{BTreeMap, HashMap};
 use std::collections::hash_map::Entry;
 use std::fs::File;
 use std::io::BufReader;
+use std::time::Duration;
 
 use anyhow::{anyhow, bail};
 use bytes::{Bytes, BytesMut};
@@ -56,7 +57,7 @@ use tonic::metadata::KeyAndValueRef;
 use tracing::{debug, error, info, instrument, trace, warn};
 
 use crate::dynamic_message::DynamicMessage;
-use crate::matching::{match_message, match_service};
+use crate::matching::{fail_fast, match_content_type_message, match_message, match_service};
 use crate::message_decoder::{decode_message, ProtobufField};
 use crate::metadata::{MessageMetadataValue, MetadataMatchResult};
 use crate::mock_server::{GrpcMockServer, MOCK_SERVER_STATE};
@@ -71,7 +72,7 @@ use crate::utils::{
   parse_pact_from_request_json,
   to_fully_qualified_name
 };
-use crate::verification::verify_interaction;
+use crate::verification::{verify_interaction, verify_message_interaction};
 
 /// Plugin gRPC server implementation
 #[derive(Debug, Default)]
@@ -110,6 +111,41 @@ impl ProtobufPactPlugin {
       .map(json_to_string)
   }
 
+  /// Returns the configured maximum size in bytes of a length-delimited field that will be
+  /// decoded, from the configuration in the manifest.
+  pub fn max_field_size(&self) -> Option<usize> {
+    self.manifest.plugin_config
+      .get("maxFieldSize")
+      .and_then(|value| match value {
+        Value::Number(n) => n.as_u64().map(|n| n as usize),
+        _ => json_to_string(value).parse().ok()
+      })
+  }
+
+  /// Returns the configured maximum depth that embedded message fields can be nested when
+  /// building a message from the configuration in the manifest.
+  pub fn max_nesting_depth(&self) -> Option<usize> {
+    self.manifest.plugin_config
+      .get("maxNestingDepth")
+      .and_then(|value| match value {
+        Value::Number(n) => n.as_u64().map(|n| n as usize),
+        _ => json_to_string(value).parse().ok()
+      })
+      .filter(|depth| *depth > 0)
+  }
+
+  /// Returns the configured timeout (in seconds) to use when downloading proto files from a
+  /// remote URL.
+  pub fn proto_download_timeout(&self, config: &HashMap<String, Value>) -> Option<Duration> {
+    config
+      .get("protoDownloadTimeout")
+      .and_then(|value| match value {
+        Value::Number(n) => n.as_u64(),
+        _ => json_to_string(value).parse().ok()
+      })
+      .map(Duration::from_secs)
+  }
+
   /// Returns any additional include paths from the configuration in the manifest to add to the
   /// Protocol Buffers compiler call.
   pub fn additional_includes(&self, config: &HashMap<String, Value>) -> Vec<String> {
@@ -254,6 +290,9 @@ impl ProtobufPactPlugin {
     let (message, service) = Self::lookup_message_and_service(&interaction_config, &expected_message_type)?;
 
     let descriptors = Self::lookup_descriptors(plugin_configuration, message_key)?;
+    let interaction_config_json: HashMap<String, Value> = interaction_config.iter()
+      .map(|(key, value)| (key.clone(), proto_value_to_json(value)))
+      .collect();
 
     let mut expected_body = request.expected.as_ref()
       .and_then(|body| body.content.clone().map(Bytes::from))
@@ -277,7 +316,21 @@ impl ProtobufPactPlugin {
       }
     }
 
-    let result = if let Some(message_name) = message {
+    let expected_content_type = request.expected.as_ref()
+      .and_then(|body| ContentType::parse(body.content_type.as_str()).ok());
+    let actual_content_type = request.actual.as_ref()
+      .and_then(|body| ContentType::parse(body.content_type.as_str()).ok());
+    let strict_message_type = interaction_config_json.get("strictMessageType")
+      .and_then(|value| value.as_bool())
+      .unwrap_or_default();
+    let content_type_result = expected_content_type.as_ref()
+      .map(|ct| match_content_type_message(ct, actual_content_type.as_ref(), strict_message_type))
+      .unwrap_or(BodyMatchResult::Ok);
+
+    let result = if content_type_result != BodyMatchResult::Ok {
+      debug!("compare_contents: content type message parameter did not match - {:?}", content_type_result);
+      Ok(content_type_result)
+    } else if let Some(message_name) = message {
       debug!("Received compare_contents request for message {}", message_name);
       match_message(
         message_name.as_str(),
@@ -285,7 +338,9 @@ impl ProtobufPactPlugin {
         &mut expected_body,
         &mut actual_body,
         &matching_rules,
-        request.allow_unexpected_keys
+        request.allow_unexpected_keys,
+        fail_fast(&interaction_config_json),
+        &interaction_config_json
       )
     } else if let Some(service_name) = service {
       debug!("Received compareContents request for service {}", service_name);
@@ -302,7 +357,9 @@ impl ProtobufPactPlugin {
         &mut actual_body,
         &matching_rules,
         request.allow_unexpected_keys,
-        &expected_content_type
+        fail_fast(&interaction_config_json),
+        &expected_content_type,
+        &interaction_config_json
       )
     } else {
       Err(anyhow!("Did not get a message or service to match"))
@@ -555,22 +612,27 @@ impl ProtobufPactPlugin {
     request_metadata
   }
 
+  // Applies any generators for the request metadata (i.e. `authorization` configured with
+  // `matching(equalTo, fromProviderState('Bearer ${token}', 'Bearer 1234'))`) to the values sent to the provider
   fn apply_generators_to_metadata(
-    interaction: SynchronousMessage,
+    metadata_generators: &HashMap<DocPath, Generator>,
     test_context: &HashMap<&str, Value>,
     request_metadata: &mut HashMap<String, MetadataValue>
   ) {
-    let metadata_generators = interaction.request.generators.categories
-      .get(&GeneratorCategory::METADATA)
-      .cloned()
-      .unwrap_or_default();
     if !metadata_generators.is_empty() {
       debug!(?metadata_generators, ?test_context, "Applying metadata generators...");
       let vm = DefaultVariantMatcher.boxed();
-      for (key, generator) in &metadata_generators {
+      for (key, generator) in metadata_generators {
         if generator.corresponds_to_mode(&GeneratorTestMode::Provider) {
           if let Some(header) = key.first_field() {
-            match generator.generate_value(&MessageMetadataValue::default(), &test_context, &vm) {
+            let example = request_metadata.get(header)
+              .and_then(|value| match &value.value {
+                Some(proto::metadata_value::Value::NonBinaryValue(value)) => proto_value_to_string(value),
+                _ => None
+              })
+              .map(MessageMetadataValue::new)
+              .unwrap_or_default();
+            match generator.generate_value(&example, test_context, &vm) {
               Ok(v) => {
                 request_metadata.insert(header.to_string(), proto::MetadataValue {
                   value: Some(proto::metadata_value::Value::NonBinaryValue(prost_types::Value {
@@ -758,7 +820,8 @@ impl PactPlugin for ProtobufPactPlugin {
     };
 
     // Process the proto file and configure the interaction
-    match process_proto(proto_file, &protoc, &fields).await {
+    let download_timeout = self.proto_download_timeout(&plugin_config);
+    match process_proto(proto_file, &protoc, &fields, download_timeout).await {
       Ok((interactions, plugin_config)) => {
         Ok(Response::new(proto::ConfigureInteractionResponse {
           interaction: interactions,
@@ -972,7 +1035,11 @@ impl PactPlugin for ProtobufPactPlugin {
 
     let mut request_metadata = Self::setup_metadata(&interaction, &service_desc,
       &method_desc, &file_desc, request);
-    Self::apply_generators_to_metadata(interaction, &test_context, &mut request_metadata);
+    let metadata_generators = interaction.request.generators.categories
+      .get(&GeneratorCategory::METADATA)
+      .cloned()
+      .unwrap_or_default();
+    Self::apply_generators_to_metadata(&metadata_generators, &test_context, &mut request_metadata);
 
     let mut buffer = BytesMut::new();
     if let Err(err) = decoded_body.write_to(&mut buffer) {
@@ -998,7 +1065,9 @@ impl PactPlugin for ProtobufPactPlugin {
   /// after `prepare_interaction_for_verification` to verify the interaction
   /// After `prepare_interaction_for_verification` has built the request body and metadata,
   /// this function will use that data to actually make the gRPC call to the provider and verify response.
-  /// Most of the work is done in `verification::verify_interaction` function.
+  /// Most of the work is done in `verification::verify_interaction` function. Asynchronous message
+  /// interactions are verified against the message in the interaction data with
+  /// `verification::verify_message_interaction`.
   async fn verify_interaction(
     &self,
     request: Request<proto::VerifyInteractionRequest>
@@ -1016,14 +1085,34 @@ impl PactPlugin for ProtobufPactPlugin {
       }))
     };
 
+    let body = match &request.interaction_data {
+      Some(data) => match &data.body {
+        Some(b) => match &b.content {
+          Some(data) => OptionalBody::Present(Bytes::from(data.clone()), Some(ContentType::from(b.content_type.clone())), None),
+          None => OptionalBody::Missing
+        }
+        None => OptionalBody::Missing
+      }
+      None => OptionalBody::Missing
+    };
+    let metadata = match &request.interaction_data {
+      Some(data) => data.metadata.clone(),
+      None => HashMap::default()
+    };
+
+    let config = request.config.as_ref().map(proto_struct_to_map).unwrap_or_default();
+
     let key = request.interaction_key.as_str();
     let interaction_by_id = lookup_interaction_by_id(key, &pact);
     // TODO: this lookup of interactions by id is duplicate with at least one other function
-    let interaction = match interaction_by_id {
-      Some(interaction) => match interaction.as_v4_sync_message() {
-        Some(interaction) => interaction,
-        None => return Ok(Response::new(proto::VerifyInteractionResponse {
-          response: Some(proto::verify_interaction_response::Response::Error(format!("gRPC interactions must be of type V4 synchronous message, got {}", interaction.type_of()))),
+    let result = match interaction_by_id {
+      Some(interaction) => if let Some(interaction) = interaction.as_v4_sync_message() {
+        verify_interaction(&pact, &interaction, &body, &metadata, &config).await
+      } else if let Some(interaction) = interaction.as_v4_async_message() {
+        verify_message_interaction(&pact, &interaction, &body)
+      } else {
+        return Ok(Response::new(proto::VerifyInteractionResponse {
+          response: Some(proto::verify_interaction_response::Response::Error(format!("Protobuf interactions must be of type V4 synchronous or asynchronous message, got {}", interaction.type_of()))),
           .. proto::VerifyInteractionResponse::default()
         }))
       }
@@ -1038,23 +1127,7 @@ impl PactPlugin for ProtobufPactPlugin {
       }
     };
 
-    let body = match &request.interaction_data {
-      Some(data) => match &data.body {
-        Some(b) => match &b.content {
-          Some(data) => OptionalBody::Present(Bytes::from(data.clone()), Some(ContentType::from(b.content_type.clone())), None),
-          None => OptionalBody::Missing
-        }
-        None => OptionalBody::Missing
-      }
-      None => OptionalBody::Missing
-    };
-    let metadata = match &request.interaction_data {
-      Some(data) => data.metadata.clone(),
-      None => HashMap::default()
-    };
-
-    let config = request.config.as_ref().map(proto_struct_to_map).unwrap_or_default();
-    match verify_interaction(&pact, &interaction, &body, &metadata, &config).await {
+    match result {
       Ok((result, output)) => {
         let results = result.iter()
           .flat_map(|result| match result {
@@ -1237,6 +1310,8 @@ fn mismatch_to_proto_mismatch(mismatch: &Mismatch) -> proto::ContentMismatch {
 #[cfg(test)]
 #[allow(non_snake_case)]
 mod tests {
+  use std::time::Duration;
+
   use expectest::prelude::*;
   use maplit::{btreemap, hashmap};
   use pact_matching::{BodyMatchResult, Mismatch};
@@ -1245,6 +1320,8 @@ mod tests {
   use pact_plugin_driver::proto::catalogue_entry::EntryType;
   use pact_plugin_driver::proto::pact_plugin_server::PactPlugin;
   use pact_plugin_driver::proto::start_mock_server_response;
+  use pact_models::generators::Generator;
+  use pact_models::path_exp::DocPath;
   use prost_types::value::Kind;
   use serde_json::{json, Map, Value};
   use tonic::Request;
@@ -1344,6 +1421,61 @@ mod tests {
     expect!(plugin.host_to_bind_to()).to(be_some().value("127".to_string()));
   }
 
+  #[test]
+  fn ProtobufPactPlugin__max_field_size__default() {
+    let plugin = ProtobufPactPlugin { manifest: Default::default() };
+    expect!(plugin.max_field_size()).to(be_none());
+  }
+
+  #[test]
+  fn ProtobufPactPlugin__max_field_size__with_value() {
+    let manifest = PactPluginManifest {
+      plugin_config: hashmap! {
+        "maxFieldSize".to_string() => json!(1024)
+      },
+      .. PactPluginManifest::default()
+    };
+    let plugin = ProtobufPactPlugin { manifest };
+    expect!(plugin.max_field_size()).to(be_some().value(1024));
+
+    let manifest = PactPluginManifest {
+      plugin_config: hashmap! {
+        "maxFieldSize".to_string() => json!("2048")
+      },
+      .. PactPluginManifest::default()
+    };
+    let plugin = ProtobufPactPlugin { manifest };
+    expect!(plugin.max_field_size()).to(be_some().value(2048));
+  }
+
+  #[test]
+  fn ProtobufPactPlugin__max_nesting_depth() {
+    let plugin = ProtobufPactPlugin { manifest: Default::default() };
+    expect!(plugin.max_nesting_depth()).to(be_none());
+
+    let plugin_with_depth = |depth: Value| ProtobufPactPlugin {
+      manifest: PactPluginManifest {
+        plugin_config: hashmap! { "maxNestingDepth".to_string() => depth },
+        .. PactPluginManifest::default()
+      }
+    };
+    expect!(plugin_with_depth(json!(64)).max_nesting_depth()).to(be_some().value(64));
+    expect!(plugin_with_depth(json!("16")).max_nesting_depth()).to(be_some().value(16));
+    expect!(plugin_with_depth(json!(0)).max_nesting_depth()).to(be_none());
+  }
+
+  #[test]
+  fn ProtobufPactPlugin__proto_download_timeout() {
+    let plugin = ProtobufPactPlugin { manifest: Default::default() };
+    expect!(plugin.proto_download_timeout(&hashmap!{})).to(be_none());
+    expect!(plugin.proto_download_timeout(&hashmap!{
+      "protoDownloadTimeout".to_string() => json!(10)
+    })).to(be_some().value(Duration::from_secs(10)));
+    expect!(plugin.proto_download_timeout(&hashmap!{
+      "protoDownloadTimeout".to_string() => json!("20")
+    })).to(be_some().value(Duration::from_secs(20)));
+  }
+
   #[test]
   fn ProtobufPactPlugin__additional_includes__default() {
     let plugin = ProtobufPactPlugin { manifest: Default::default() };
@@ -1577,6 +1709,32 @@ mod tests {
     expect!(&error_response.error).to(be_equal_to("Did not find any mock server results for a server with ID 1234abcd"));
   }
 
+  #[test_log::test]
+  fn apply_generators_to_metadata_generates_the_request_metadata_values() {
+    let generators = hashmap!{
+      DocPath::new_unwrap("authorization") => Generator::ProviderStateGenerator("Bearer ${token}".to_string(), None),
+      DocPath::new_unwrap("x-request-id") => Generator::RandomInt(1, 1)
+    };
+    let test_context = hashmap!{ "token" => json!("abc123") };
+    let metadata_value = |value: &str| proto::MetadataValue {
+      value: Some(proto::metadata_value::Value::NonBinaryValue(prost_types::Value {
+        kind: Some(Kind::StringValue(value.to_string()))
+      }))
+    };
+    let mut request_metadata = hashmap!{
+      "authorization".to_string() => metadata_value("Bearer 1234"),
+      "x-other".to_string() => metadata_value("other")
+    };
+
+    ProtobufPactPlugin::apply_generators_to_metadata(&generators, &test_context, &mut request_metadata);
+
+    expect!(request_metadata).to(be_equal_to(hashmap!{
+      "authorization".to_string() => metadata_value("Bearer abc123"),
+      "x-request-id".to_string() => metadata_value("1"),
+      "x-other".to_string() => metadata_value("other")
+    }));
+  }
+
   #[test_log::test]
   fn merge_value_test() {
     expect!(merge_value(&Value::Null, &Value::Null).unwrap()).to(be_equal_to(Value::Null));
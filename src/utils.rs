@@ -2,6 +2,7 @@
 
 use std::collections::{BTreeMap, HashMap};
 use std::fmt::Write;
+use std::iter::once;
 use std::panic::RefUnwindSafe;
 
 use anyhow::anyhow;
@@ -9,11 +10,11 @@ use base64::Engine;
 use base64::engine::general_purpose::STANDARD as BASE64;
 use bytes::{Bytes, BytesMut};
 use field_descriptor_proto::Type;
+use itertools::Itertools;
 use pact_models::json_utils::json_to_string;
 use pact_models::pact::load_pact_from_json;
 use pact_models::prelude::v4::V4Pact;
 use pact_models::v4::interaction::V4Interaction;
-use prost::Message;
 use prost_types::{
   DescriptorProto,
   EnumDescriptorProto,
@@ -28,16 +29,20 @@ use prost_types::{
 use prost_types::field_descriptor_proto::Label;
 use prost_types::value::Kind;
 use serde_json::{json, Map};
+use sha2::{Digest, Sha256};
 use tracing::{debug, error, instrument, trace, warn};
 
+use crate::editions::decode_file_descriptor_set;
+use crate::error::ProtobufError;
 use crate::message_decoder::{decode_message, ProtobufField, ProtobufFieldData};
+use crate::protoc::DescriptorCache;
 
 pub fn fds_to_map(fds: &FileDescriptorSet) -> HashMap<String, &FileDescriptorProto> {
   fds.file.iter().map(
     |des| (des.name.clone().unwrap_or_default(), des)).collect()
 }
 
-fn fds_map_to_vec(descriptors: &HashMap<String, &FileDescriptorProto>) -> Vec<FileDescriptorProto> {
+pub(crate) fn fds_map_to_vec(descriptors: &HashMap<String, &FileDescriptorProto>) -> Vec<FileDescriptorProto> {
   descriptors.values().map(|d| *d).cloned().collect()
 }
 
@@ -80,6 +85,54 @@ pub fn to_fully_qualified_name(name: &str, package: &str) -> anyhow::Result<Stri
   }
 }
 
+/// Method name, input message type and output message type of a service method
+pub type ServiceMethod = (String, String, String);
+
+/// Lists all the services in the descriptors. Returns the fully-qualified service names, with the
+/// method name, input message type and output message type for each method of the service.
+pub fn list_services(descriptors: &FileDescriptorSet) -> Vec<(String, Vec<ServiceMethod>)> {
+  descriptors.file.iter()
+    .flat_map(|file_descriptor| {
+      file_descriptor.service.iter()
+        .filter_map(|service| {
+          to_fully_qualified_name(service.name(), file_descriptor.package())
+            .ok()
+            .map(|service_name| {
+              let methods = service.method.iter()
+                .map(|method| (method.name().to_string(), method.input_type().to_string(),
+                  method.output_type().to_string()))
+                .collect();
+              (service_name, methods)
+            })
+        })
+    })
+    .collect()
+}
+
+/// Lists all the message types in the descriptors, including any nested message types. Returns
+/// the fully-qualified message type names.
+pub fn list_message_types(descriptors: &FileDescriptorSet) -> Vec<String> {
+  fn nested_types(prefix: &str, message: &DescriptorProto) -> Vec<String> {
+    let name = format!("{}.{}", prefix, message.name());
+    once(name.clone())
+      .chain(message.nested_type.iter().flat_map(|nested| nested_types(name.as_str(), nested)))
+      .collect()
+  }
+
+  descriptors.file.iter()
+    .flat_map(|file_descriptor| {
+      let prefix = if file_descriptor.package().is_empty() {
+        String::default()
+      } else {
+        format!(".{}", file_descriptor.package())
+      };
+      file_descriptor.message_type.iter()
+        .flat_map(move |message| nested_types(prefix.as_str(), message))
+        .collect::<Vec<_>>()
+    })
+    .collect()
+}
+
 /// Split a service/method definition into two seprate parts.
 /// E.g. MyService/MyMethod becomes ("MyService", "MyMethod")
 pub fn split_service_and_method(service_name: &str) -> anyhow::Result<(&str, &str)> {
@@ -89,6 +142,20 @@ pub fn split_service_and_method(service_name: &str) -> anyhow::Result<(&str, &st
   }
 }
 
+/// Finds the method descriptor for a `Service/Method` string (the service name can also be fully
+/// qualified, i.e. `.package.Service/Method`), along with the descriptors of its input and output messages
+pub fn resolve_method(
+  cache: &DescriptorCache,
+  service_method: &str
+) -> anyhow::Result<(MethodDescriptorProto, DescriptorProto, DescriptorProto)> {
+  let (service_name, method_name) = split_service_and_method(service_method)?;
+  let descriptors = cache.descriptors();
+  let (_, service_descriptor) = find_service_descriptor_for_type(service_name, descriptors)?;
+  let method_descriptor = find_method_descriptor_for_service(method_name, &service_descriptor)?;
+  let (input_descriptor, _) = find_message_descriptor_for_type(method_descriptor.input_type(), descriptors)?;
+  let (output_descriptor, _) = find_message_descriptor_for_type(method_descriptor.output_type(), descriptors)?;
+  Ok((method_descriptor, input_descriptor, output_descriptor))
+}
 
 /// Converts from `.package.Service` (fully-qualified name) and `Method` to `/package.Service/Method`
 pub fn build_grpc_route(service_full_name: &str, method_name: &str) -> anyhow::Result<String> {
@@ -123,31 +190,23 @@ pub fn parse_grpc_route(route_key: &str) -> Option<(String, String)> {
 pub fn find_message_type_in_file_descriptor(
   message_name: &str,
   descriptor: &FileDescriptorProto
-) -> anyhow::Result<DescriptorProto> {
+) -> Result<DescriptorProto, ProtobufError> {
   descriptor.message_type.iter()
     .find(|message| message.name() == message_name)
     .cloned()
-    .ok_or_else(|| anyhow!("Did not find a message type '{}' in the file descriptor '{}'",
-      message_name, descriptor.name.as_deref().unwrap_or("unknown")))
+    .ok_or_else(|| ProtobufError::DescriptorNotFound(format!("Did not find a message type '{}' in the file descriptor '{}'",
+      message_name, descriptor.name.as_deref().unwrap_or("unknown"))))
 }
 
-// TODO: handle nested types properly
-// current name resolution is dumb - just splits package and message name by a dot
-// but if you have .package.Message.NestedMessage.NestedMessageDeeperLevel this whole structure breaks down
-// because we'll be looking for packages with the name `.package.Message.NestedMessage`
-// while here the package is `.package` and then we need to find message called `Message` and go over it's nested types.
-// To be fair, I don't think this ever worked properly - it was looking across all file descriptors instead of narrowing them down by packages,
-// but it still wasn't looking for nested types.
-
 /// Helper to select a method descriptor by name from a service descriptor.
 pub fn find_method_descriptor_for_service(
   method_name: &str,
   service_descriptor: &ServiceDescriptorProto
-) -> anyhow::Result<MethodDescriptorProto> {
+) -> Result<MethodDescriptorProto, ProtobufError> {
   let method_descriptor = service_descriptor.method.iter().find(|method_desc| {
     method_desc.name() == method_name
-  }).cloned().ok_or_else(|| anyhow!("Did not find the method {} in the Protobuf descriptor for service '{}'", 
-    method_name, service_descriptor.name()))?;
+  }).cloned().ok_or_else(|| ProtobufError::DescriptorNotFound(format!("Did not find the method {} in the Protobuf descriptor for service '{}'", 
+    method_name, service_descriptor.name())))?;
   trace!("Found method descriptor {:?} for method {}", method_descriptor, method_name);
   Ok(method_descriptor)
 }
@@ -162,12 +221,41 @@ pub fn find_method_descriptor_for_service(
 /// If type name does not contain a dot, it is a relative type. We'll search all file descriptors then.
 /// This isn't techically correct, since we're supposed to start from the current file, and then search
 /// level by level, but it's good enough for now (and this is how the plugin used to work for all messages anyway)
+///
+/// Fully qualified names of types nested in other messages (i.e. `.package.Outer.Inner`) are resolved
+/// by finding the outer message in the package, and then walking into its nested types.
 pub fn find_message_descriptor_for_type_in_vec(
   type_name: &str,
   all_descriptors: &Vec<FileDescriptorProto>
-) -> anyhow::Result<(DescriptorProto, FileDescriptorProto)> {
+) -> Result<(DescriptorProto, FileDescriptorProto), ProtobufError> {
   let (message_name, package) = parse_name(type_name);
   find_message_descriptor(message_name, package, &all_descriptors)
+    .or_else(|err| find_nested_message_descriptor(type_name, all_descriptors).ok_or(err))
+}
+
+/// Resolves a fully qualified type name that refers to a type nested in another message
+/// (i.e. `.package.Outer.Inner`). The package of each file descriptor is stripped from the type
+/// name, and then the remaining names are used to walk from the top level message into the
+/// nested types.
+fn find_nested_message_descriptor(
+  type_name: &str,
+  all_descriptors: &[FileDescriptorProto]
+) -> Option<(DescriptorProto, FileDescriptorProto)> {
+  let type_name = type_name.strip_prefix('.')?;
+  all_descriptors.iter()
+    .find_map(|fd| {
+      let names = if fd.package().is_empty() {
+        Some(type_name)
+      } else {
+        type_name.strip_prefix(fd.package()).and_then(|name| name.strip_prefix('.'))
+      }?;
+      let mut names = names.split('.');
+      let outer_name = names.next()?;
+      let outer = fd.message_type.iter().find(|message| message.name() == outer_name)?;
+      names.try_fold(outer, |message, name| {
+        message.nested_type.iter().find(|nested| nested.name() == name)
+      }).map(|message| (message.clone(), fd.clone()))
+    })
 }
 
 /// Find a descriptor for a given type name, fully qualified or relative.
@@ -183,7 +271,7 @@ pub fn find_message_descriptor_for_type_in_vec(
 pub fn find_message_descriptor_for_type_in_map(
   type_name: &str,
   descriptors: &HashMap<String, &FileDescriptorProto>,
-) -> anyhow::Result<(DescriptorProto, FileDescriptorProto)> {
+) -> Result<(DescriptorProto, FileDescriptorProto), ProtobufError> {
   let values = fds_map_to_vec(descriptors);
   find_message_descriptor_for_type_in_vec(type_name, &values)
 }
@@ -201,7 +289,7 @@ pub fn find_message_descriptor_for_type_in_map(
 pub fn find_message_descriptor_for_type(
   type_name: &str,
   descriptors: &FileDescriptorSet,
-) -> anyhow::Result<(DescriptorProto, FileDescriptorProto)> {
+) -> Result<(DescriptorProto, FileDescriptorProto), ProtobufError> {
   find_message_descriptor_for_type_in_vec(type_name, &descriptors.file)
 }
 
@@ -214,23 +302,40 @@ pub(crate) fn find_message_descriptor(
   message_name: &str,
   package: Option<&str>,
   all_descriptors: &Vec<FileDescriptorProto>,
-) -> anyhow::Result<(DescriptorProto, FileDescriptorProto)> {
+) -> Result<(DescriptorProto, FileDescriptorProto), ProtobufError> {
   if package.is_some() {
     trace!("Looking for message descriptor for message '{}' in package '{:?}'", message_name, package);
   } else {
     trace!("Looking for message descriptor for message '{}'", message_name);
   }
   let descriptors = find_file_descriptors(package, &all_descriptors)?;
-  descriptors.iter()
-    .find_map(|fd| find_message_type_in_file_descriptor(message_name, fd).ok().map(|msg| (msg, fd.clone())))
+  let candidates = find_all_message_descriptors(message_name, &descriptors);
+  if candidates.len() > 1 {
+    warn!("Message type '{}' is ambiguous, it is defined in multiple files {:?}. Will use the one from '{}'",
+      message_name, candidates.iter().map(|(_, fd)| fd.name()).collect::<Vec<_>>(),
+      candidates[0].1.name());
+  }
+  candidates.first()
+    .cloned()
     .ok_or_else(|| {
-        anyhow!(
+        ProtobufError::DescriptorNotFound(format!(
             "Did not find a message type '{}' in any of the file descriptors '{:?}'", 
             message_name, 
-            descriptors.iter().map(|d| d.name()).collect::<Vec<_>>())
+            descriptors.iter().map(|d| d.name()).collect::<Vec<_>>()))
     })
 }
 
+/// Finds all the message descriptors with the given name in the file descriptors, along with the
+/// file descriptor that each was defined in
+pub(crate) fn find_all_message_descriptors(
+  message_name: &str,
+  descriptors: &[FileDescriptorProto]
+) -> Vec<(DescriptorProto, FileDescriptorProto)> {
+  descriptors.iter()
+    .filter_map(|fd| find_message_type_in_file_descriptor(message_name, fd).ok().map(|msg| (msg, fd.clone())))
+    .collect()
+}
+
 /// Find a service descriptor for a given service type name, fully qualified or relative.
 /// 
 /// If type name starts with a dot ('.') it's a fully qualified name, so it is split into package and message names; 
@@ -242,7 +347,7 @@ pub(crate) fn find_message_descriptor(
 pub(crate) fn find_service_descriptor_for_type(
   type_name: &str,
   all_descriptors: &FileDescriptorSet
-) -> anyhow::Result<(FileDescriptorProto, ServiceDescriptorProto)> {
+) -> Result<(FileDescriptorProto, ServiceDescriptorProto), ProtobufError> {
   let (message_name, package) = parse_name(type_name);
   find_service_descriptor(message_name, package, all_descriptors)
 }
@@ -251,14 +356,14 @@ pub(crate) fn find_service_descriptor(
   service_name: &str,
   package: Option<&str>,
   descriptors: &FileDescriptorSet
-) -> anyhow::Result<(FileDescriptorProto, ServiceDescriptorProto)> {
+) -> Result<(FileDescriptorProto, ServiceDescriptorProto), ProtobufError> {
   if package.is_some() {
     debug!("Looking for service '{}' with package '{:?}'", service_name, package);
   } else {
     debug!("Looking for service '{}'", service_name);
   }
   let file_descriptors = find_file_descriptors(package, &descriptors.file)?;
-  file_descriptors.iter().filter_map(|descriptor| {
+  let candidates = file_descriptors.iter().filter_map(|descriptor| {
     descriptor.service.iter()
       .find(|p| p.name() == service_name)
       .map(|p| {
@@ -266,14 +371,21 @@ pub(crate) fn find_service_descriptor(
         (descriptor.clone(), p.clone())
       })
   })
-    .next()
-    .ok_or_else(|| anyhow!("Did not find a descriptor for service '{}'", service_name))
+    .collect::<Vec<_>>();
+  if candidates.len() > 1 {
+    warn!("Service '{}' is ambiguous, it is defined in multiple files {:?}. Will use the one from '{}'",
+      service_name, candidates.iter().map(|(fd, _)| fd.name()).collect::<Vec<_>>(),
+      candidates[0].0.name());
+  }
+  candidates.first()
+    .cloned()
+    .ok_or_else(|| ProtobufError::DescriptorNotFound(format!("Did not find a descriptor for service '{}'", service_name)))
 }
 
 pub fn find_file_descriptors(
   package: Option<&str>,
   all_descriptors: &Vec<FileDescriptorProto>,
-) -> anyhow::Result<Vec<FileDescriptorProto>> {
+) -> Result<Vec<FileDescriptorProto>, ProtobufError> {
   match package {
     Some(pkg) if pkg.is_empty() => {
       debug!("Looking for file descriptors with no package");
@@ -290,7 +402,7 @@ pub fn find_file_descriptors(
 fn find_all_file_descriptors_for_package(
   package: &str,
   all_descriptors: &Vec<FileDescriptorProto>,
-) -> anyhow::Result<Vec<FileDescriptorProto>> {
+) -> Result<Vec<FileDescriptorProto>, ProtobufError> {
   let package = if package.starts_with('.') {
       &package[1..]
   } else {
@@ -307,7 +419,7 @@ fn find_all_file_descriptors_for_package(
       }
   }).cloned().collect();
   if found.is_empty() {
-      Err(anyhow!("Did not find any file descriptors for a package '{}'", package))
+      Err(ProtobufError::DescriptorNotFound(format!("Did not find any file descriptors for a package '{}'", package)))
   } else {
       debug!("Found {} file descriptors for package '{}'", found.len(), package);
       Ok(found)
@@ -316,10 +428,10 @@ fn find_all_file_descriptors_for_package(
 
 fn find_all_file_descriptors_with_no_package(
   all_descriptors: &Vec<FileDescriptorProto>
-  ) -> anyhow::Result<Vec<FileDescriptorProto>> {
+  ) -> Result<Vec<FileDescriptorProto>, ProtobufError> {
   let found: Vec<_> = all_descriptors.iter().filter(|d| d.package.is_none()).cloned().collect();
   if found.is_empty() {
-      Err(anyhow!("Did not find any file descriptors with no package specified"))
+      Err(ProtobufError::DescriptorNotFound("Did not find any file descriptors with no package specified".to_string()))
   } else {
       debug!("Found {} file descriptors with no package", found.len());
       Ok(found)
@@ -368,6 +480,106 @@ pub(crate) fn as_hex(data: &[u8]) -> String {
   buffer
 }
 
+/// The maximum number of seconds a `google.protobuf.Duration` can have (about 10,000 years)
+const MAX_DURATION_SECONDS: i64 = 315_576_000_000;
+
+const NANOS_PER_SECOND: i128 = 1_000_000_000;
+
+/// If the seconds and nanos are a valid `google.protobuf.Duration`. The seconds must be within the
+/// range of about +-10,000 years, and the nanos must be less than a second and have the same sign as
+/// the seconds.
+pub(crate) fn is_valid_duration(seconds: i64, nanos: i32) -> bool {
+  seconds.unsigned_abs() <= MAX_DURATION_SECONDS as u64 && nanos.unsigned_abs() < NANOS_PER_SECOND as u32
+    && !(seconds > 0 && nanos < 0) && !(seconds < 0 && nanos > 0)
+}
+
+/// Parses an ISO-8601 duration (i.e. `PT1H30M` or `-P1DT0.5S`) into the seconds and nanos of a
+/// `google.protobuf.Duration`. Years and months are not supported, as they do not have a fixed
+/// length, and only the seconds can have a fractional part.
+pub(crate) fn parse_iso8601_duration(duration: &str) -> anyhow::Result<(i64, i32)> {
+  let invalid = || anyhow!("'{}' is not a valid ISO-8601 duration", duration);
+  let (negative, value) = match duration.trim().strip_prefix('-') {
+    Some(value) => (true, value),
+    None => (false, duration.trim())
+  };
+  let value = value.strip_prefix('P').ok_or_else(invalid)?;
+  let (date_part, time_part) = match value.split_once('T') {
+    Some((date_part, time_part)) if !time_part.is_empty() => (date_part, time_part),
+    Some(_) => return Err(invalid()),
+    None => (value, "")
+  };
+  if date_part.is_empty() && time_part.is_empty() {
+    return Err(invalid());
+  }
+
+  let mut total_nanos: i128 = 0;
+  for (part, units) in [(date_part, "WD"), (time_part, "HMS")] {
+    let mut last_unit = None;
+    let mut number = String::new();
+    for ch in part.chars() {
+      if ch.is_ascii_digit() || ch == '.' || ch == ',' {
+        number.push(if ch == ',' { '.' } else { ch });
+      } else {
+        if units == "WD" && (ch == 'Y' || ch == 'M') {
+          return Err(anyhow!("'{}' is not supported, as years and months do not have a fixed duration", duration));
+        }
+        let unit = units.find(ch).ok_or_else(invalid)?;
+        if number.is_empty() || last_unit.map(|last| unit <= last).unwrap_or(false) ||
+          (number.contains('.') && ch != 'S') {
+          return Err(invalid());
+        }
+        let unit_seconds: i128 = match ch {
+          'W' => 604_800,
+          'D' => 86_400,
+          'H' => 3_600,
+          'M' => 60,
+          _ => 1
+        };
+        let (whole, fraction) = number.split_once('.').unwrap_or((number.as_str(), ""));
+        if whole.is_empty() || fraction.len() > 9 || fraction.contains('.') {
+          return Err(invalid());
+        }
+        let whole: i128 = whole.parse().map_err(|_| invalid())?;
+        let fraction: i128 = format!("{:0<9}", fraction).parse().map_err(|_| invalid())?;
+        total_nanos += whole * unit_seconds * NANOS_PER_SECOND + fraction;
+        if total_nanos > MAX_DURATION_SECONDS as i128 * NANOS_PER_SECOND {
+          return Err(anyhow!("'{}' is larger than the maximum duration of {} seconds", duration, MAX_DURATION_SECONDS));
+        }
+        last_unit = Some(unit);
+        number.clear();
+      }
+    }
+    if !number.is_empty() {
+      return Err(invalid());
+    }
+  }
+
+  let total_nanos = if negative { -total_nanos } else { total_nanos };
+  Ok(((total_nanos / NANOS_PER_SECOND) as i64, (total_nanos % NANOS_PER_SECOND) as i32))
+}
+
+/// Formats the seconds and nanos of a `google.protobuf.Duration` as an ISO-8601 duration, using
+/// hours, minutes and seconds (i.e. `PT1H30M` or `-PT0.5S`)
+pub(crate) fn format_iso8601_duration(seconds: i64, nanos: i32) -> String {
+  let mut result = if seconds < 0 || nanos < 0 { "-PT".to_string() } else { "PT".to_string() };
+  let (seconds, nanos) = (seconds.unsigned_abs(), nanos.unsigned_abs());
+  let (hours, minutes, seconds) = (seconds / 3_600, (seconds % 3_600) / 60, seconds % 60);
+  if hours > 0 {
+    let _ = write!(result, "{}H", hours);
+  }
+  if minutes > 0 {
+    let _ = write!(result, "{}M", minutes);
+  }
+  if seconds > 0 || nanos > 0 || (hours == 0 && minutes == 0) {
+    let _ = write!(result, "{}", seconds);
+    if nanos > 0 {
+      let _ = write!(result, ".{}", format!("{:09}", nanos).trim_end_matches('0'));
+    }
+    result.push('S');
+  }
+  result
+}
+
 /// Create a string from the byte array for rendering/displaying
 pub(crate) fn display_bytes(data: &[u8]) -> String {
   if data.len() <= 16 {
@@ -394,6 +606,12 @@ pub fn is_repeated_field(descriptor: &FieldDescriptorProto) -> bool {
   descriptor.label() == Label::Repeated
 }
 
+/// If the field is a member of a oneof. Proto3 optional fields are implemented with a synthetic
+/// oneof, so are not treated as oneof members.
+pub fn is_oneof_field(descriptor: &FieldDescriptorProto) -> bool {
+  descriptor.oneof_index.is_some() && !descriptor.proto3_optional.unwrap_or_default()
+}
+
 /// Get the name of the enum value
 pub fn enum_name(enum_value: i32, descriptor: &EnumDescriptorProto) -> String {
   descriptor.value.iter().find(|v| v.number.unwrap_or(-1) == enum_value)
@@ -540,6 +758,25 @@ pub fn find_enum_by_name(
   }
 }
 
+/// Find the given enum value by number, first in the message descriptor and then in all the
+/// descriptors. Will only return a value if the enum declares a value with the given number.
+#[tracing::instrument(ret, skip_all, fields(%enum_name, %enum_number))]
+pub fn find_enum_value_by_number(
+  message_descriptor: &DescriptorProto,
+  descriptors: &HashMap<String, &FileDescriptorProto>,
+  enum_name: &str,
+  enum_number: i32
+) -> Option<(i32, EnumDescriptorProto)> {
+  trace!(">> find_enum_value_by_number({}, {})", enum_name, enum_number);
+  find_enum_by_name_in_message(&message_descriptor.enum_type, enum_name)
+    .or_else(|| {
+      let fds = FileDescriptorSet { file: fds_map_to_vec(descriptors) };
+      find_enum_by_name(&fds, enum_name)
+    })
+    .filter(|enum_descriptor| enum_descriptor.value.iter().any(|v| v.number == Some(enum_number)))
+    .map(|enum_descriptor| (enum_number, enum_descriptor))
+}
+
 /// Convert the Google Struct field data into a JSON value
 #[instrument(level = "trace", skip(descriptors))]
 pub fn struct_field_data_to_json(
@@ -675,6 +912,26 @@ pub fn lookup_interaction_by_id<'a>(
     .map(|i| i.as_ref())
 }
 
+/// Returns the field number from the field descriptor as an unsigned value, which is how field
+/// numbers are decoded from the wire. The maximum legal field number (536,870,911) always fits in
+/// both types, but negative (invalid) field numbers must not wrap around to a valid one.
+pub fn field_number(descriptor: &FieldDescriptorProto) -> Option<u32> {
+  descriptor.number.and_then(|number| u32::try_from(number).ok())
+}
+
+/// Returns the name of the message type for the response of the method. This will be the output
+/// type of the method, unless it has been overridden with the `responseMessageType` option in the
+/// interaction configuration.
+pub fn response_message_type<'a>(
+  method_descriptor: &'a MethodDescriptorProto,
+  interaction_config: &'a HashMap<String, serde_json::Value>
+) -> &'a str {
+  interaction_config.get("responseMessageType")
+    .and_then(|value| value.as_str())
+    .filter(|message_type| !message_type.is_empty())
+    .unwrap_or_else(|| method_descriptor.output_type())
+}
+
 pub fn lookup_interaction_config(interaction: &dyn V4Interaction) -> Option<HashMap<String, serde_json::Value>> {
   interaction.plugin_config().iter()
     .find_map(|(key, value)| {
@@ -731,7 +988,8 @@ pub(crate) fn lookup_service_descriptors_for_interaction(
   trace!("gRPC service for interaction: {}", service_with_package);
   
   let plugin_config = lookup_plugin_config(pact)?;
-  let descriptors = get_descriptors_for_interaction(descriptor_key.as_str(), &plugin_config)?;
+  let descriptors = get_descriptors_for_type(descriptor_key.as_str(), &plugin_config,
+    |descriptors| find_service_descriptor_for_type(service_with_package, descriptors).is_ok())?;
   trace!("file descriptors for interaction {:?}", descriptors);
   
   let (file_descriptor, service_descriptor) = find_service_descriptor_for_type(service_with_package, &descriptors)?;
@@ -749,6 +1007,15 @@ fn get_descriptor_config<'a>(
     .ok_or_else(|| anyhow!("Plugin configuration item with key '{}' has an invalid format", message_key))
 }
 
+/// Length of the hex encoded MD5 hashes used as descriptor keys by older versions of the plugin
+const MD5_HEX_LENGTH: usize = 32;
+
+/// Calculates the key the encoded file descriptor set is stored under in the Pact file. This is
+/// the hex encoded SHA-256 hash of the descriptor bytes.
+pub fn hash_descriptors(descriptor_bytes: &[u8]) -> String {
+  format!("{:x}", Sha256::digest(descriptor_bytes))
+}
+
 /// Get the encoded Protobuf descriptors from the Pact level configuration for the message key
 pub fn get_descriptors_for_interaction(
   message_key: &str,
@@ -771,18 +1038,105 @@ pub fn get_descriptors_for_interaction(
   };
   debug!("Protobuf file descriptor set is {} bytes", descriptor_bytes.len());
 
-  // Get an MD5 hash of the bytes to check that it matches the descriptor key
-  let digest = md5::compute(&descriptor_bytes);
-  let descriptor_hash = format!("{:x}", digest);
+  // Get a hash of the bytes to check that it matches the descriptor key. Pacts written by older
+  // versions of the plugin will have an MD5 hash as the key.
+  let descriptor_hash = if message_key.len() == MD5_HEX_LENGTH {
+    format!("{:x}", md5::compute(&descriptor_bytes))
+  } else {
+    hash_descriptors(&descriptor_bytes)
+  };
   if descriptor_hash != message_key {
     return Err(anyhow!("Protobuf descriptors checksum failed. Expected {} but got {}", message_key, descriptor_hash));
   }
 
   // Decode the Protobuf descriptors
-  FileDescriptorSet::decode(descriptor_bytes)
+  decode_file_descriptor_set(descriptor_bytes)
     .map_err(|err| anyhow!(err))
 }
 
+/// Get the Protobuf descriptors for the interaction that define a type (checked with the
+/// `defines_type` function). The descriptors stored under the descriptor key are used if they
+/// define the type, otherwise the other descriptors in the Pact file are searched for the type by
+/// name. This allows interactions to be verified when the descriptors they were recorded with are
+/// not available, but a compatible version of the proto files is (i.e. one with added fields).
+/// The other descriptors are searched in order of the version of the plugin that recorded them,
+/// newest first, and then by key.
+pub fn get_descriptors_for_type(
+  message_key: &str,
+  plugin_config: &BTreeMap<String, serde_json::Value>,
+  defines_type: impl Fn(&FileDescriptorSet) -> bool
+) -> anyhow::Result<FileDescriptorSet> {
+  let descriptors = get_descriptors_for_interaction(message_key, plugin_config);
+  if let Ok(descriptors) = &descriptors {
+    if defines_type(descriptors) {
+      return Ok(descriptors.clone());
+    }
+  }
+
+  let other_descriptors = plugin_config.iter()
+    .filter(|(key, _)| key.as_str() != message_key)
+    .sorted_by(|(key_a, config_a), (key_b, config_b)| {
+      plugin_version(config_b).cmp(&plugin_version(config_a)).then_with(|| key_a.cmp(key_b))
+    })
+    .filter_map(|(key, _)| get_descriptors_for_interaction(key, plugin_config).ok())
+    .find(|descriptors| defines_type(descriptors));
+  match other_descriptors {
+    Some(other_descriptors) => {
+      warn!("The Protobuf descriptors with key '{}' are not available or do not define the type, \
+        using other descriptors from the Pact file that do", message_key);
+      Ok(other_descriptors)
+    }
+    None => descriptors
+  }
+}
+
+/// Returns the version of the plugin that recorded the descriptors (the `pluginVersion` value) as
+/// its numeric components. Descriptors recorded by older versions of the plugin will not have a
+/// version, and will return an empty list.
+fn plugin_version(descriptor_config: &serde_json::Value) -> Vec<u64> {
+  descriptor_config.get("pluginVersion")
+    .and_then(|version| version.as_str())
+    .map(|version| version.split(['.', '-', '+'])
+      .map_while(|part| part.parse().ok())
+      .collect())
+    .unwrap_or_default()
+}
+
+/// If the file descriptor is for a Protobuf file that uses editions (i.e. `edition = "2023"`)
+/// instead of the proto2 or proto3 syntax. Protoc sets the syntax to `editions` for these files.
+pub fn is_editions_file(file_descriptor: &FileDescriptorProto) -> bool {
+  file_descriptor.syntax() == "editions"
+}
+
+/// Finds the file descriptor that the message descriptor was defined in
+pub fn find_file_descriptor_for_message<'a>(
+  message_descriptor: &DescriptorProto,
+  descriptors: &'a FileDescriptorSet
+) -> Option<&'a FileDescriptorProto> {
+  fn contains_message(messages: &[DescriptorProto], message_descriptor: &DescriptorProto) -> bool {
+    messages.iter().any(|message| message == message_descriptor ||
+      contains_message(&message.nested_type, message_descriptor))
+  }
+  descriptors.file.iter()
+    .find(|file| contains_message(&file.message_type, message_descriptor))
+}
+
+/// Returns the package of the Protobuf file with the given name, or `None` if the file is not in
+/// the descriptors. Files with no package will return an empty string.
+pub fn proto_file_package<'a>(file_name: &str, descriptors: &'a FileDescriptorSet) -> Option<&'a str> {
+  descriptors.file.iter()
+    .find(|file| file.name() == file_name)
+    .map(|file| file.package())
+}
+
+/// Returns the imports (dependencies) of the Protobuf file with the given name, or `None` if the
+/// file is not in the descriptors
+pub fn proto_file_imports<'a>(file_name: &str, descriptors: &'a FileDescriptorSet) -> Option<&'a [String]> {
+  descriptors.file.iter()
+    .find(|file| file.name() == file_name)
+    .map(|file| file.dependency.as_slice())
+}
+
 /// If a field type should be packed. These are repeated fields of primitive numeric types
 /// (types which use the varint, 32-bit, or 64-bit wire types)
 pub fn should_be_packed_type(field_type: Type) -> bool {
@@ -823,14 +1177,14 @@ pub(crate) fn prost_string<S: Into<String>>(s: S) -> Value {
 
 #[cfg(test)]
 pub(crate) mod tests {
-  use std::collections::HashSet;
+  use std::collections::{BTreeMap, HashSet};
   use std::vec;
 
   use base64::Engine;
   use base64::engine::general_purpose::STANDARD as BASE64;
   use bytes::{BufMut, Bytes, BytesMut};
   use expectest::prelude::*;
-  use maplit::{hashmap, hashset};
+  use maplit::{btreemap, hashmap, hashset};
   use pretty_assertions::assert_eq;
   use prost::encoding::WireType::LengthDelimited;
   use prost::Message;
@@ -848,15 +1202,25 @@ pub(crate) mod tests {
   use prost_types::field_descriptor_proto::{Label, Type};
   use prost_types::field_descriptor_proto::Label::Optional;
   use serde_json::json;
+  use crate::error::ProtobufError;
   use crate::message_decoder::{ProtobufField, ProtobufFieldData};
-  use crate::utils::{as_hex, struct_field_data_to_json, find_enum_value_by_name, find_nested_type, is_map_field, last_name, parse_name, to_fully_qualified_name};
+  use crate::protoc::DescriptorCache;
+  use crate::utils::{as_hex, format_iso8601_duration, is_valid_duration, parse_iso8601_duration, struct_field_data_to_json, find_enum_value_by_name, find_nested_type, is_map_field, last_name, parse_name, proto_file_imports, proto_file_package, to_fully_qualified_name};
   use super::{
     build_grpc_route,
+    find_all_message_descriptors,
+    find_file_descriptor_for_message,
     find_file_descriptors,
     find_message_descriptor_for_type,
     find_method_descriptor_for_service,
     find_service_descriptor_for_type,
+    get_descriptors_for_interaction,
+    get_descriptors_for_type,
+    hash_descriptors,
+    list_message_types,
+    list_services,
     parse_grpc_route,
+    resolve_method,
     split_service_and_method
   };
 
@@ -1230,6 +1594,30 @@ pub(crate) mod tests {
     expect!(result4).to(be_some().value((1, enum1.clone())));
   }
 
+  #[test]
+  fn descriptor_lookups_return_a_descriptor_not_found_error() {
+    let service = ServiceDescriptorProto {
+      name: Some("Service".to_string()),
+      .. ServiceDescriptorProto::default()
+    };
+    let file = FileDescriptorProto {
+      name: Some("service.proto".to_string()),
+      package: Some("service".to_string()),
+      service: vec![ service.clone() ],
+      .. FileDescriptorProto::default()
+    };
+    let fds = FileDescriptorSet { file: vec![ file ] };
+
+    let result = find_message_descriptor_for_type(".service.Request", &fds);
+    expect!(matches!(result, Err(ProtobufError::DescriptorNotFound(_)))).to(be_true());
+    let result = find_message_descriptor_for_type(".other.Request", &fds);
+    expect!(matches!(result, Err(ProtobufError::DescriptorNotFound(_)))).to(be_true());
+    let result = find_service_descriptor_for_type(".service.Other", &fds);
+    expect!(matches!(result, Err(ProtobufError::DescriptorNotFound(_)))).to(be_true());
+    let result = find_method_descriptor_for_service("Method", &service);
+    expect!(matches!(result, Err(ProtobufError::DescriptorNotFound(_)))).to(be_true());
+  }
+
   #[test]
   fn find_message_descriptor_for_type_test() {
     let request_msg = DescriptorProto {
@@ -1281,6 +1669,52 @@ pub(crate) mod tests {
       "Did not find a message type 'MissingType' in any of the file descriptors")).to(be_true());
   }
 
+  #[test]
+  fn find_message_descriptor_for_type_with_nested_types() {
+    let deepest_msg = DescriptorProto {
+      name: Some("Deepest".to_string()),
+      .. DescriptorProto::default()
+    };
+    let inner_msg = DescriptorProto {
+      name: Some("Inner".to_string()),
+      nested_type: vec![ deepest_msg.clone() ],
+      .. DescriptorProto::default()
+    };
+    let outer_msg = DescriptorProto {
+      name: Some("Outer".to_string()),
+      nested_type: vec![ inner_msg.clone() ],
+      .. DescriptorProto::default()
+    };
+    let outer_file = FileDescriptorProto {
+      name: Some("outer.proto".to_string()),
+      package: Some("pkg.v1".to_string()),
+      message_type: vec![ outer_msg.clone() ],
+      .. FileDescriptorProto::default()
+    };
+    let no_package_file = FileDescriptorProto {
+      name: Some("no_package.proto".to_string()),
+      message_type: vec![ outer_msg.clone() ],
+      .. FileDescriptorProto::default()
+    };
+    let all_descriptors = FileDescriptorSet { file: vec![ outer_file.clone(), no_package_file.clone() ] };
+
+    let (md, fd) = find_message_descriptor_for_type(".pkg.v1.Outer.Inner", &all_descriptors).unwrap();
+    expect!(&md).to(be_equal_to(&inner_msg));
+    expect!(&fd).to(be_equal_to(&outer_file));
+
+    let (md, fd) = find_message_descriptor_for_type(".pkg.v1.Outer.Inner.Deepest", &all_descriptors).unwrap();
+    expect!(&md).to(be_equal_to(&deepest_msg));
+    expect!(&fd).to(be_equal_to(&outer_file));
+
+    let (md, fd) = find_message_descriptor_for_type(".Outer.Inner", &all_descriptors).unwrap();
+    expect!(&md).to(be_equal_to(&inner_msg));
+    expect!(&fd).to(be_equal_to(&no_package_file));
+
+    let result = find_message_descriptor_for_type(".pkg.v1.Outer.Missing", &all_descriptors);
+    expect!(result.unwrap_err().to_string()).to(be_equal_to(
+      "Did not find any file descriptors for a package 'pkg.v1.Outer'"));
+  }
+
   #[test]
   fn find_service_descriptor_for_type_test() {
     let service_desc = ServiceDescriptorProto {
@@ -1610,4 +2044,306 @@ pub(crate) mod tests {
       "kind": "general"
     }));
   }
+
+  #[test]
+  fn find_file_descriptor_for_message_test() {
+    let nested = DescriptorProto {
+      name: Some("Nested".to_string()),
+      .. DescriptorProto::default()
+    };
+    let message = DescriptorProto {
+      name: Some("Test".to_string()),
+      nested_type: vec![ nested.clone() ],
+      .. DescriptorProto::default()
+    };
+    let other = DescriptorProto {
+      name: Some("Other".to_string()),
+      .. DescriptorProto::default()
+    };
+    let fds = FileDescriptorSet {
+      file: vec![
+        FileDescriptorProto {
+          name: Some("proto3.proto".to_string()),
+          message_type: vec![ other.clone() ],
+          syntax: Some("proto3".to_string()),
+          .. FileDescriptorProto::default()
+        },
+        FileDescriptorProto {
+          name: Some("editions.proto".to_string()),
+          message_type: vec![ message.clone() ],
+          syntax: Some("editions".to_string()),
+          .. FileDescriptorProto::default()
+        }
+      ]
+    };
+
+    expect!(find_file_descriptor_for_message(&message, &fds).map(|fd| fd.name()))
+      .to(be_some().value("editions.proto"));
+    expect!(find_file_descriptor_for_message(&nested, &fds).map(|fd| fd.name()))
+      .to(be_some().value("editions.proto"));
+    expect!(find_file_descriptor_for_message(&other, &fds).map(|fd| fd.name()))
+      .to(be_some().value("proto3.proto"));
+  }
+
+  #[test]
+  fn list_services_test() {
+    let bytes = BASE64.decode(crate::protobuf::tests::DESCRIPTOR_BYTES).unwrap();
+    let fds = FileDescriptorSet::decode(bytes.as_slice()).unwrap();
+
+    let services = list_services(&fds);
+    expect!(services.len()).to(be_equal_to(1));
+    let (service_name, methods) = &services[0];
+    expect!(service_name.as_str()).to(be_equal_to(".area_calculator.Calculator"));
+    let method_names = methods.iter().map(|(name, _, _)| name.as_str()).collect::<Vec<_>>();
+    expect!(method_names).to(be_equal_to(vec!["calculateOne", "calculateMulti"]));
+    let (_, input_type, output_type) = &methods[0];
+    expect!(input_type.as_str()).to(be_equal_to(".area_calculator.ShapeMessage"));
+    expect!(output_type.as_str()).to(be_equal_to(".area_calculator.AreaResponse"));
+  }
+
+  #[test]
+  fn resolve_method_test() {
+    let bytes = BASE64.decode(crate::protobuf::tests::DESCRIPTOR_BYTES).unwrap();
+    let cache = DescriptorCache::new(FileDescriptorSet::decode(bytes.as_slice()).unwrap());
+
+    let (method, input, output) = resolve_method(&cache, "Calculator/calculateOne").unwrap();
+    expect!(method.name()).to(be_equal_to("calculateOne"));
+    expect!(input.name()).to(be_equal_to("ShapeMessage"));
+    expect!(output.name()).to(be_equal_to("AreaResponse"));
+
+    let (method, _, _) = resolve_method(&cache, ".area_calculator.Calculator/calculateMulti").unwrap();
+    expect!(method.name()).to(be_equal_to("calculateMulti"));
+
+    expect!(resolve_method(&cache, "Calculator/calculateNone")).to(be_err());
+    expect!(resolve_method(&cache, "Other/calculateOne")).to(be_err());
+    expect!(resolve_method(&cache, "Calculator").unwrap_err().to_string()).to(be_equal_to(
+      "Service name 'Calculator' is not valid, it should be of the form <SERVICE>/<METHOD>"));
+  }
+
+  #[rstest::rstest]
+  #[case("PT0S", (0, 0))]
+  #[case("PT1.5S", (1, 500_000_000))]
+  #[case("PT0,25S", (0, 250_000_000))]
+  #[case("PT1H30M", (5_400, 0))]
+  #[case("P1DT2H", (93_600, 0))]
+  #[case("P2W", (1_209_600, 0))]
+  #[case("-PT1.000000001S", (-1, -1))]
+  #[case(" PT10M ", (600, 0))]
+  fn parse_iso8601_duration_with_valid_durations(#[case] duration: &str, #[case] expected: (i64, i32)) {
+    expect!(parse_iso8601_duration(duration).unwrap()).to(be_equal_to(expected));
+  }
+
+  #[rstest::rstest]
+  #[case("")]
+  #[case("P")]
+  #[case("PT")]
+  #[case("1S")]
+  #[case("PT1")]
+  #[case("PTS")]
+  #[case("PT1X")]
+  #[case("PT1M1H")]
+  #[case("PT1S1S")]
+  #[case("P1H")]
+  #[case("PT1.5M")]
+  #[case("PT1.0000000001S")]
+  #[case("P1Y")]
+  #[case("P1M")]
+  #[case("P100000000W")]
+  fn parse_iso8601_duration_with_invalid_durations(#[case] duration: &str) {
+    expect!(parse_iso8601_duration(duration)).to(be_err());
+  }
+
+  #[test]
+  fn format_iso8601_duration_test() {
+    expect!(format_iso8601_duration(0, 0)).to(be_equal_to("PT0S"));
+    expect!(format_iso8601_duration(1, 500_000_000)).to(be_equal_to("PT1.5S"));
+    expect!(format_iso8601_duration(5_400, 0)).to(be_equal_to("PT1H30M"));
+    expect!(format_iso8601_duration(93_601, 1)).to(be_equal_to("PT26H1.000000001S"));
+    expect!(format_iso8601_duration(-1, -250_000_000)).to(be_equal_to("-PT1.25S"));
+    expect!(format_iso8601_duration(0, -5)).to(be_equal_to("-PT0.000000005S"));
+  }
+
+  #[test]
+  fn is_valid_duration_test() {
+    expect!(is_valid_duration(0, 0)).to(be_true());
+    expect!(is_valid_duration(1, 999_999_999)).to(be_true());
+    expect!(is_valid_duration(-1, -999_999_999)).to(be_true());
+    expect!(is_valid_duration(315_576_000_000, 0)).to(be_true());
+    expect!(is_valid_duration(315_576_000_001, 0)).to(be_false());
+    expect!(is_valid_duration(1, 1_000_000_000)).to(be_false());
+    expect!(is_valid_duration(1, -1)).to(be_false());
+    expect!(is_valid_duration(-1, 1)).to(be_false());
+    expect!(is_valid_duration(i64::MIN, 0)).to(be_false());
+    expect!(is_valid_duration(0, i32::MIN)).to(be_false());
+  }
+
+  #[test]
+  fn list_message_types_test() {
+    let fds = FileDescriptorSet {
+      file: vec![
+        FileDescriptorProto {
+          name: Some("a.proto".to_string()),
+          package: Some("pkg".to_string()),
+          message_type: vec![
+            DescriptorProto {
+              name: Some("Outer".to_string()),
+              nested_type: vec![
+                DescriptorProto {
+                  name: Some("Inner".to_string()),
+                  .. DescriptorProto::default()
+                }
+              ],
+              .. DescriptorProto::default()
+            }
+          ],
+          .. FileDescriptorProto::default()
+        },
+        FileDescriptorProto {
+          name: Some("b.proto".to_string()),
+          message_type: vec![
+            DescriptorProto {
+              name: Some("NoPackage".to_string()),
+              .. DescriptorProto::default()
+            }
+          ],
+          .. FileDescriptorProto::default()
+        }
+      ]
+    };
+
+    expect!(list_message_types(&fds)).to(be_equal_to(vec![
+      ".pkg.Outer".to_string(),
+      ".pkg.Outer.Inner".to_string(),
+      ".NoPackage".to_string()
+    ]));
+  }
+
+  #[test_log::test]
+  fn find_message_descriptor_for_type_with_a_message_type_defined_in_multiple_files() {
+    let message = DescriptorProto {
+      name: Some("Request".to_string()),
+      .. DescriptorProto::default()
+    };
+    let fds = FileDescriptorSet {
+      file: vec![
+        FileDescriptorProto {
+          name: Some("a.proto".to_string()),
+          message_type: vec![ message.clone() ],
+          .. FileDescriptorProto::default()
+        },
+        FileDescriptorProto {
+          name: Some("b.proto".to_string()),
+          message_type: vec![ message.clone() ],
+          .. FileDescriptorProto::default()
+        }
+      ]
+    };
+
+    let candidates = find_all_message_descriptors("Request", &fds.file);
+    expect!(candidates.iter().map(|(_, fd)| fd.name()).collect::<Vec<_>>())
+      .to(be_equal_to(vec!["a.proto", "b.proto"]));
+
+    let (_, file_descriptor) = find_message_descriptor_for_type("Request", &fds).unwrap();
+    expect!(file_descriptor.name()).to(be_equal_to("a.proto"));
+  }
+
+  #[test]
+  fn hash_descriptors_returns_a_sha256_hash() {
+    let bytes = BASE64.decode(crate::protobuf::tests::DESCRIPTOR_BYTES).unwrap();
+    let hash = hash_descriptors(&bytes);
+    expect!(hash.len()).to(be_equal_to(64));
+    expect!(hash.chars().all(|ch| ch.is_ascii_hexdigit())).to(be_true());
+    expect!(hash_descriptors(b"")).to(be_equal_to("e3b0c44298fc1c149afbf4c8996fb92427ae41e4649b934ca495991b7852b855"));
+  }
+
+  #[test]
+  fn get_descriptors_for_interaction_supports_sha256_and_md5_keys() {
+    let bytes = BASE64.decode(crate::protobuf::tests::DESCRIPTOR_BYTES).unwrap();
+    let sha256_key = hash_descriptors(&bytes);
+    let md5_key = format!("{:x}", md5::compute(&bytes));
+    let descriptor_config = json!({
+      "protoDescriptors": crate::protobuf::tests::DESCRIPTOR_BYTES
+    });
+
+    let plugin_config = btreemap! {
+      sha256_key.clone() => descriptor_config.clone()
+    };
+    let result = get_descriptors_for_interaction(sha256_key.as_str(), &plugin_config);
+    expect!(result.unwrap().file.len()).to(be_equal_to(2));
+
+    // Pacts written by older versions of the plugin use an MD5 hash for the key
+    let plugin_config = btreemap! {
+      md5_key.clone() => descriptor_config.clone()
+    };
+    let result = get_descriptors_for_interaction(md5_key.as_str(), &plugin_config);
+    expect!(result.unwrap().file.len()).to(be_equal_to(2));
+
+    let invalid_key = "0".repeat(64);
+    let plugin_config = btreemap! {
+      invalid_key.clone() => descriptor_config
+    };
+    let result = get_descriptors_for_interaction(invalid_key.as_str(), &plugin_config);
+    expect!(result.unwrap_err().to_string()).to(be_equal_to(
+      format!("Protobuf descriptors checksum failed. Expected {} but got {}", invalid_key, sha256_key)));
+  }
+
+  #[test]
+  fn get_descriptors_for_type_prefers_the_descriptors_from_the_newest_plugin_version() {
+    let descriptors = |field_count: i32| FileDescriptorSet {
+      file: vec![
+        FileDescriptorProto {
+          name: Some("test.proto".to_string()),
+          package: Some("test".to_string()),
+          message_type: vec![
+            DescriptorProto {
+              name: Some("Test".to_string()),
+              field: (1..=field_count).map(|number| FieldDescriptorProto {
+                name: Some(format!("field{}", number)),
+                number: Some(number),
+                r#type: Some(Type::String as i32),
+                .. FieldDescriptorProto::default()
+              }).collect(),
+              .. DescriptorProto::default()
+            }
+          ],
+          .. FileDescriptorProto::default()
+        }
+      ]
+    }.encode_to_vec();
+    let descriptor_config = |field_count: i32, version: Option<&str>| {
+      let bytes = descriptors(field_count);
+      let mut config = json!({ "protoDescriptors": BASE64.encode(&bytes) });
+      if let Some(version) = version {
+        config["pluginVersion"] = json!(version);
+      }
+      (hash_descriptors(&bytes), config)
+    };
+    let plugin_config: BTreeMap<String, serde_json::Value> = [
+      descriptor_config(1, None),
+      descriptor_config(2, Some("0.5.9")),
+      descriptor_config(3, Some("0.5.10")),
+      descriptor_config(4, Some("0.4.0"))
+    ].into_iter().collect();
+    let defines_type = |fds: &FileDescriptorSet| find_message_descriptor_for_type(".test.Test", fds).is_ok();
+
+    let result = get_descriptors_for_type("0".repeat(64).as_str(), &plugin_config, defines_type).unwrap();
+    expect!(result.file[0].message_type[0].field.len()).to(be_equal_to(3));
+
+    let (recorded_key, _) = descriptor_config(1, None);
+    let result = get_descriptors_for_type(recorded_key.as_str(), &plugin_config, defines_type).unwrap();
+    expect!(result.file[0].message_type[0].field.len()).to(be_equal_to(1));
+  }
+
+  #[test]
+  fn proto_file_package_and_imports_test() {
+    let bytes = BASE64.decode(crate::protobuf::tests::DESCRIPTOR_BYTES).unwrap();
+    let fds = FileDescriptorSet::decode(bytes.as_slice()).unwrap();
+
+    expect!(proto_file_package("area_calculator.proto", &fds)).to(be_some().value("area_calculator"));
+    expect!(proto_file_imports("area_calculator.proto", &fds))
+      .to(be_some().value(&["common.proto".to_string()][..]));
+    expect!(proto_file_imports("common.proto", &fds).unwrap().iter()).to(be_empty());
+    expect!(proto_file_package("missing.proto", &fds)).to(be_none());
+    expect!(proto_file_imports("missing.proto", &fds)).to(be_none());
+  }
 }
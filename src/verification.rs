@@ -1,4 +1,4 @@
-//! Module with all the functions to verify a gRPC interaction
+//! Module with all the functions to verify a gRPC or Protobuf message interaction
 
 use std::collections::HashMap;
 use std::fmt::{Debug, Display, Formatter};
@@ -7,28 +7,45 @@ use ansi_term::Colour::{Green, Red};
 use ansi_term::Style;
 use anyhow::anyhow;
 use bytes::BytesMut;
+use itertools::Itertools;
 use maplit::hashmap;
 use pact_matching::{BodyMatchResult, CoreMatchingContext, DiffConfig, Mismatch};
 use pact_models::json_utils::{json_to_num, json_to_string};
 use pact_models::prelude::OptionalBody;
 use pact_models::prelude::v4::V4Pact;
+use pact_models::v4::async_message::AsynchronousMessage;
 use pact_models::v4::message_parts::MessageContents;
 use pact_models::v4::sync_message::SynchronousMessage;
 use pact_plugin_driver::proto;
 use pact_plugin_driver::utils::proto_value_to_string;
 use pact_verifier::verification_result::VerificationMismatchResult;
 use prost_types::{DescriptorProto, FileDescriptorSet, MethodDescriptorProto};
-use serde_json::Value;
-use tonic::{Request, Response, Status};
+use serde_json::{json, Value};
+use tonic::{Code, Request, Response, Status};
 use tonic::metadata::{Ascii, Binary, MetadataKey, MetadataMap, MetadataValue};
 use tower::ServiceExt;
 use tracing::{debug, error, instrument, trace, warn};
 
 use crate::dynamic_message::{DynamicMessage, PactCodec};
-use crate::matching::match_message;
-use crate::message_decoder::decode_message;
-use crate::metadata::{compare_metadata, grpc_status, MetadataMatchResult};
-use crate::utils::{find_message_descriptor_for_type, lookup_service_descriptors_for_interaction};
+use crate::matching::{fail_fast, interaction_config_for_part, match_message};
+use crate::message_decoder::{decode_message, render_message};
+use crate::metadata::{
+  compare_metadata,
+  compare_status_details,
+  compare_trailers,
+  expected_trailers,
+  grpc_status,
+  MetadataMatchResult,
+  STATUS_DETAILS_KEY
+};
+use crate::utils::{
+  find_message_descriptor_for_type,
+  get_descriptors_for_type,
+  lookup_interaction_config,
+  lookup_plugin_config,
+  lookup_service_descriptors_for_interaction,
+  response_message_type
+};
 
 #[derive(Debug)]
 struct GrpcError {
@@ -75,7 +92,8 @@ pub async fn verify_interaction(
   let (input_message_desc, _) = find_message_descriptor_for_type(
     input_message_name.as_str(), &all_file_descriptors)?;
   
-  let output_message_name = method_desc.output_type.clone().unwrap_or_default();
+  let interaction_config = lookup_interaction_config(interaction).unwrap_or_default();
+  let output_message_name = response_message_type(&method_desc, &interaction_config).to_string();
   // uses type name from method_descriptor, which always contains the doc; 3-way logic is safe here
   let (output_message_desc, _) = find_message_descriptor_for_type(
     output_message_name.as_str(), &all_file_descriptors)?;
@@ -83,15 +101,23 @@ pub async fn verify_interaction(
 
   match build_grpc_request(request_body, metadata, &all_file_descriptors, &input_message_desc) {
     Ok(request) => match make_grpc_request(
-      request, config, metadata, &all_file_descriptors, &input_message_desc, &output_message_desc, interaction).await {
-      Ok(response) => {
+      request, config, metadata, &all_file_descriptors, &input_message_desc, &output_message_desc, interaction,
+      method_desc.server_streaming()).await {
+      Ok((response, streamed_messages, response_trailers)) => {
         debug!("Received response from gRPC server - {:?}", response);
         let response_metadata = response.metadata();
         let body = response.get_ref();
         trace!("gRPC metadata: {:?}", response_metadata);
+        trace!("gRPC trailers: {:?}", response_trailers);
         trace!("gRPC body: {:?}", body);
-        let (result, verification_output) = verify_response(body, response_metadata, interaction,
-          &all_file_descriptors, &method_desc)?;
+        let (mut result, mut verification_output) = verify_response(body, response_metadata,
+          &response_trailers, interaction, &all_file_descriptors, &method_desc)?;
+        if method_desc.server_streaming() {
+          let (stream_result, stream_output) = verify_streamed_responses(&streamed_messages,
+            interaction, &all_file_descriptors, &method_desc);
+          result.extend(stream_result);
+          verification_output.extend(stream_output);
+        }
 
         let status_result = if !result.is_empty() {
           Red.paint("FAILED")
@@ -119,7 +145,7 @@ pub async fn verify_interaction(
             .unwrap_or(&default_contents);
           if let Some(expected_status) = grpc_status(expected_response) {
             let (result, verification_output) = verify_error_response(expected_response,
-                                                                      &received_status.status, &interaction.id);
+              &received_status.status, &interaction.id, &all_file_descriptors);
             let status_result = if !result.is_empty() {
               Red.paint("FAILED")
             } else {
@@ -150,11 +176,177 @@ pub async fn verify_interaction(
   }
 }
 
+/// Verify an asynchronous Protobuf message interaction.
+///
+/// The actual message bytes received from the provider are decoded with the descriptors stored in
+/// the Pact file and compared against the expected message in the interaction.
+///
+/// # Arguments
+/// * `pact` - Pact to verify against, which contains the Protobuf descriptors
+/// * `interaction` - Message interaction to verify
+/// * `actual_body` - Encoded message received from the provider
+///
+/// # Returns
+/// A tuple with a vector of verification results and a vector of strings
+/// with the human-readable output of the verification
+pub fn verify_message_interaction(
+  pact: &V4Pact,
+  interaction: &AsynchronousMessage,
+  actual_body: &OptionalBody
+) -> anyhow::Result<(Vec<VerificationMismatchResult>, Vec<String>)> {
+  debug!("Verifying message interaction {}", interaction);
+  trace!(?interaction, ?actual_body, ?pact);
+
+  let interaction_config = lookup_interaction_config(interaction)
+    .ok_or_else(|| anyhow!("Interaction does not have any Protobuf configuration"))?;
+  let descriptor_key = interaction_config.get("descriptorKey")
+    .map(json_to_string)
+    .ok_or_else(|| anyhow!("Interaction descriptorKey was missing in Pact file"))?;
+  let message_name = interaction_config.get("message")
+    .map(json_to_string)
+    .ok_or_else(|| anyhow!("Interaction Protobuf message type was missing in Pact file"))?;
+  let plugin_config = lookup_plugin_config(pact)?;
+  let all_file_descriptors = get_descriptors_for_type(descriptor_key.as_str(), &plugin_config,
+    |descriptors| find_message_descriptor_for_type(message_name.as_str(), descriptors).is_ok())?;
+  let (message_descriptor, _) = find_message_descriptor_for_type(message_name.as_str(), &all_file_descriptors)?;
+
+  let mut results = vec![];
+  let mut verification_output = vec![];
+  if let Some(mut expected_body) = interaction.contents.contents.value() {
+    let mut actual_bytes = actual_body.value().unwrap_or_default();
+    match match_message(
+      message_name.as_str(),
+      &all_file_descriptors,
+      &mut expected_body,
+      &mut actual_bytes.clone(),
+      &interaction.contents.matching_rules.rules_for_category("body").unwrap_or_default(),
+      true,
+      fail_fast(&interaction_config),
+      &interaction_config
+    ) {
+      Ok(result) => {
+        debug!("Match message result: {:?}", result);
+        if result != BodyMatchResult::Ok {
+          if let Ok(actual_fields) = decode_message(&mut actual_bytes, &message_descriptor, &all_file_descriptors) {
+            verification_output.push("      actual message:".to_string());
+            verification_output.extend(render_message(&actual_fields, &all_file_descriptors)
+              .iter()
+              .map(|line| format!("        {}", line)));
+          }
+        }
+        match result {
+          BodyMatchResult::Ok => {}
+          BodyMatchResult::BodyTypeMismatch { message, .. } => {
+            results.push(VerificationMismatchResult::Error { error: message, interaction_id: interaction.id.clone() });
+          }
+          BodyMatchResult::BodyMismatches(mismatches) => {
+            for (_, mismatches) in mismatches {
+              results.push(VerificationMismatchResult::Mismatches { mismatches, interaction_id: interaction.id.clone() });
+            }
+          }
+        }
+      }
+      Err(err) => {
+        error!("Verifying the message failed with an error - {}", err);
+        results.push(VerificationMismatchResult::Error { error: err.to_string(), interaction_id: interaction.id.clone() })
+      }
+    }
+  }
+
+  let status_result = if !results.is_empty() {
+    Red.paint("FAILED")
+  } else {
+    Green.paint("OK")
+  };
+  let mut output = vec![
+    format!("Given a {} message [{}]", Style::new().bold().paint(message_name), status_result)
+  ];
+  output.extend(verification_output);
+
+  Ok((results, output))
+}
+
+/// Converts the results of verifying an interaction into JSON, so they can be processed by other
+/// tools (i.e. in a CI pipeline). `field_paths` are the paths of the fields that were compared
+/// (i.e. the fields of the expected message), and any of them without a mismatch are listed as
+/// matched. The `grpc_status` is the status code received from the provider, if there was one.
+/// The JSON document has the form:
+/// ```json
+/// {
+///   "result": "failed",
+///   "grpcStatus": 0,
+///   "body": {
+///     "matched": [ "$.name" ],
+///     "mismatched": [ { "type": "BodyMismatch", "path": "$.value", "expected": "1", "actual": "2", "mismatch": "...", "interactionId": null } ]
+///   },
+///   "metadata": [ { "type": "MetadataMismatch", "key": "x-id", "expected": "a", "actual": "b", "mismatch": "...", "interactionId": null } ],
+///   "errors": [ { "error": "...", "interactionId": null } ]
+/// }
+/// ```
+pub fn verification_results_to_json(
+  field_paths: &[String],
+  results: &[VerificationMismatchResult],
+  grpc_status: Option<Code>
+) -> Value {
+  let mut mismatched = vec![];
+  let mut mismatched_paths = vec![];
+  let mut metadata = vec![];
+  let mut errors = vec![];
+
+  for result in results {
+    match result {
+      VerificationMismatchResult::Mismatches { mismatches, interaction_id } => {
+        for mismatch in mismatches {
+          let mut json = mismatch.to_json();
+          if let Value::Object(map) = &mut json {
+            map.insert("interactionId".to_string(), json!(interaction_id));
+          }
+          match mismatch {
+            Mismatch::MetadataMismatch { .. } => metadata.push(json),
+            Mismatch::BodyMismatch { path, .. } => {
+              mismatched_paths.push(path.as_str());
+              mismatched.push(json)
+            }
+            _ => mismatched.push(json)
+          }
+        }
+      }
+      VerificationMismatchResult::Error { error, interaction_id } => {
+        errors.push(json!({
+          "error": error,
+          "interactionId": interaction_id
+        }));
+      }
+    }
+  }
+
+  // A field has matched if there are no mismatches for it or any of its child fields
+  let matched = field_paths.iter()
+    .filter(|field_path| !mismatched_paths.iter().any(|path| {
+      path.strip_prefix(field_path.as_str())
+        .map(|rest| rest.is_empty() || rest.starts_with('.') || rest.starts_with('['))
+        .unwrap_or(false)
+    }))
+    .collect::<Vec<_>>();
+
+  json!({
+    "result": if results.is_empty() { "passed" } else { "failed" },
+    "grpcStatus": grpc_status.map(i32::from),
+    "body": {
+      "matched": matched,
+      "mismatched": mismatched
+    },
+    "metadata": metadata,
+    "errors": errors
+  })
+}
+
 #[instrument]
 fn verify_error_response(
   response: &MessageContents,
   actual_status: &Status,
-  interaction_id: &Option<String>
+  interaction_id: &Option<String>,
+  descriptors: &FileDescriptorSet
 ) -> (Vec<VerificationMismatchResult>, Vec<String>) {
   let mut output = vec![];
   let mut results = vec![];
@@ -192,41 +384,72 @@ fn verify_error_response(
       }
     }
   }
+  match compare_status_details(&response.metadata, actual_status, descriptors) {
+    Ok((result, details_output)) => {
+      if !result.result {
+        results.push(VerificationMismatchResult::Mismatches {
+          mismatches: result.mismatches,
+          interaction_id: interaction_id.clone()
+        });
+      }
+      output.extend(details_output);
+    }
+    Err(err) => {
+      results.push(VerificationMismatchResult::Mismatches {
+        mismatches: vec![ Mismatch::MetadataMismatch {
+          key: STATUS_DETAILS_KEY.to_string(),
+          expected: "".to_string(),
+          actual: "".to_string(),
+          mismatch: format!("Failed to verify the gRPC status details: {}", err)
+        } ],
+        interaction_id: interaction_id.clone()
+      });
+    }
+  }
+  if !expected_trailers(&response.metadata).is_empty() {
+    // For error responses, the trailers are returned as the status metadata
+    output.push("      with trailers".to_string());
+    results.extend(verify_trailers(actual_status.metadata(), response, interaction_id, &mut output));
+  }
   (results, output)
 }
 
-/// Verify response from the gRPC server against expected response in the interaction
-fn verify_response(
+/// Verify the response message body from the gRPC server against the expected response message
+fn verify_response_body(
   response_body: &DynamicMessage,
-  response_metadata: &MetadataMap,
+  response: &MessageContents,
   interaction: &SynchronousMessage,
   all_file_descriptors: &FileDescriptorSet,
-  method_descriptor: &MethodDescriptorProto
-) -> anyhow::Result<(Vec<VerificationMismatchResult>, Vec<String>)> {
-  let response = interaction.response.first().cloned()
-    .unwrap_or_default();
-  if interaction.response.len() > 1 {
-    warn!("Interaction has more than one response, only comparing the first one");
-  }
-  let expected_body = response.contents.value();
-
+  method_descriptor: &MethodDescriptorProto,
+  output: &mut Vec<String>
+) -> anyhow::Result<Vec<VerificationMismatchResult>> {
   let mut results = vec![];
-  let mut output = vec![];
+  let interaction_config = lookup_interaction_config(interaction)
+    .map(|config| interaction_config_for_part(&config, "response"))
+    .unwrap_or_default();
 
-  if let Some(mut expected_body) = expected_body {
+  if let Some(mut expected_body) = response.contents.value() {
     let mut actual_body = BytesMut::new();
     response_body.write_to(&mut actual_body)?;
 
     match match_message(
-      method_descriptor.output_type(), 
+      response_message_type(method_descriptor, &interaction_config),
       all_file_descriptors,
       &mut expected_body,
       &mut actual_body.freeze(),
       &response.matching_rules.rules_for_category("body").unwrap_or_default(),
-      true
+      true,
+      fail_fast(&interaction_config),
+      &interaction_config
     ) {
       Ok(result) => {
         debug!("Match service result: {:?}", result);
+        if result != BodyMatchResult::Ok {
+          output.push("      actual message:".to_string());
+          output.extend(render_message(&response_body.proto_fields(), all_file_descriptors)
+            .iter()
+            .map(|line| format!("        {}", line)));
+        }
         match result {
           BodyMatchResult::Ok => {}
           BodyMatchResult::BodyTypeMismatch { message, .. } => {
@@ -246,9 +469,115 @@ fn verify_response(
     }
   }
 
+  let mismatches = verify_packed_encoding(response_body, &interaction_config);
+  if !mismatches.is_empty() {
+    results.push(VerificationMismatchResult::Mismatches { mismatches, interaction_id: interaction.id.clone() });
+  }
+
+  Ok(results)
+}
+
+/// Verify the wire encoding (packed or unpacked) of the repeated scalar fields in the response
+/// message, if the interaction has been configured with the expected encoding (`packedEncoding`)
+fn verify_packed_encoding(
+  response_body: &DynamicMessage,
+  interaction_config: &HashMap<String, Value>
+) -> Vec<Mismatch> {
+  let expected_packed = match interaction_config.get("packedEncoding").and_then(|v| v.as_str()) {
+    Some("packed") => true,
+    Some("unpacked") => false,
+    _ => return vec![]
+  };
+  let encoding = |packed: bool| if packed { "packed" } else { "unpacked" };
+
+  response_body.proto_fields().iter()
+    .sorted_by_key(|field| field.field_num)
+    .filter_map(|field| {
+      response_body.packed_encoding(field.field_num)
+        .filter(|packed| *packed != expected_packed)
+        .map(|packed| Mismatch::BodyMismatch {
+          path: format!("$.{}", field.field_name),
+          expected: Some(encoding(expected_packed).into()),
+          actual: Some(encoding(packed).into()),
+          mismatch: format!("Expected repeated field '{}' to be {}, but it was received {}",
+            field.field_name, encoding(expected_packed), encoding(packed))
+        })
+    })
+    .collect()
+}
+
+/// Verify the remaining messages received from a server streaming method (the first message is
+/// verified by `verify_response`). The total number of messages received must be the same as the
+/// number of responses configured for the interaction, and each message is compared to the
+/// response at the same position.
+fn verify_streamed_responses(
+  streamed_messages: &[DynamicMessage],
+  interaction: &SynchronousMessage,
+  all_file_descriptors: &FileDescriptorSet,
+  method_descriptor: &MethodDescriptorProto
+) -> (Vec<VerificationMismatchResult>, Vec<String>) {
+  let mut results = vec![];
+  let mut output = vec![];
+
+  let expected_count = interaction.response.len().max(1);
+  let actual_count = streamed_messages.len() + 1;
+  if expected_count != actual_count {
+    results.push(VerificationMismatchResult::Mismatches {
+      mismatches: vec![
+        Mismatch::BodyMismatch {
+          path: "$".to_string(),
+          expected: Some(expected_count.to_string().into()),
+          actual: Some(actual_count.to_string().into()),
+          mismatch: format!("Expected the response stream to have {} message(s), but received {}",
+            expected_count, actual_count)
+        }
+      ],
+      interaction_id: interaction.id.clone()
+    });
+  }
+
+  for (index, (message, response)) in streamed_messages.iter()
+    .zip(interaction.response.iter().skip(1))
+    .enumerate() {
+    output.push(format!("      with streamed message {}", index + 2));
+    match verify_response_body(message, response, interaction, all_file_descriptors, method_descriptor, &mut output) {
+      Ok(message_results) => results.extend(message_results),
+      Err(err) => results.push(VerificationMismatchResult::Error {
+        error: err.to_string(),
+        interaction_id: interaction.id.clone()
+      })
+    }
+  }
+
+  (results, output)
+}
+
+/// Verify response from the gRPC server against expected response in the interaction
+fn verify_response(
+  response_body: &DynamicMessage,
+  response_metadata: &MetadataMap,
+  response_trailers: &MetadataMap,
+  interaction: &SynchronousMessage,
+  all_file_descriptors: &FileDescriptorSet,
+  method_descriptor: &MethodDescriptorProto
+) -> anyhow::Result<(Vec<VerificationMismatchResult>, Vec<String>)> {
+  let response = interaction.response.first().cloned()
+    .unwrap_or_default();
+  if interaction.response.len() > 1 && !method_descriptor.server_streaming() {
+    warn!("Interaction has more than one response, only comparing the first one");
+  }
+
+  let mut output = vec![];
+  let mut results = verify_response_body(response_body, &response, interaction, all_file_descriptors,
+    method_descriptor, &mut output)?;
+
   if !response.metadata.is_empty() {
     output.push("      with metadata".to_string());
-    match verify_metadata(response_metadata, &response) {
+    // Previous versions merged the trailers into the response metadata, so fall back to the
+    // trailers for any keys not sent as headers
+    let mut metadata = response_trailers.clone().into_headers();
+    metadata.extend(response_metadata.clone().into_headers());
+    match verify_metadata(&MetadataMap::from_headers(metadata), &response) {
       Ok((result, md_output)) => {
         if !result.result {
           results.push(VerificationMismatchResult::Mismatches {
@@ -272,9 +601,49 @@ fn verify_response(
     }
   }
 
+  if !expected_trailers(&response.metadata).is_empty() {
+    output.push("      with trailers".to_string());
+    results.extend(verify_trailers(response_trailers, &response, &interaction.id, &mut output));
+  }
+
   Ok((results, output))
 }
 
+/// Verify the trailing metadata from the gRPC server against the expected trailers
+fn verify_trailers(
+  trailers: &MetadataMap,
+  response: &MessageContents,
+  interaction_id: &Option<String>,
+  output: &mut Vec<String>
+) -> Option<VerificationMismatchResult> {
+  let rules = response.matching_rules.rules_for_category("metadata").unwrap_or_default();
+  let plugin_config = hashmap!{};
+  let context = CoreMatchingContext::new(DiffConfig::AllowUnexpectedKeys,
+    &rules, &plugin_config);
+  match compare_trailers(&response.metadata, trailers, &context) {
+    Ok((result, md_output)) => {
+      output.extend(md_output);
+      if result.result {
+        None
+      } else {
+        Some(VerificationMismatchResult::Mismatches {
+          mismatches: result.mismatches,
+          interaction_id: interaction_id.clone()
+        })
+      }
+    }
+    Err(err) => Some(VerificationMismatchResult::Mismatches {
+      mismatches: vec![ Mismatch::MetadataMismatch {
+        key: "".to_string(),
+        expected: "".to_string(),
+        actual: "".to_string(),
+        mismatch: format!("Failed to verify the message trailers: {}", err)
+      } ],
+      interaction_id: interaction_id.clone()
+    })
+  }
+}
+
 #[instrument(level = "trace")]
 fn verify_metadata(
   metadata: &MetadataMap,
@@ -287,6 +656,7 @@ fn verify_metadata(
   compare_metadata(&response.metadata, metadata, &context)
 }
 
+#[allow(clippy::too_many_arguments)]
 async fn make_grpc_request(
   request: Request<DynamicMessage>,
   config: &HashMap<String, Value>,
@@ -294,8 +664,9 @@ async fn make_grpc_request(
   file_desc: &FileDescriptorSet,
   input_desc: &DescriptorProto,
   output_desc: &DescriptorProto,
-  interaction: &SynchronousMessage
-) -> anyhow::Result<Response<DynamicMessage>> {
+  interaction: &SynchronousMessage,
+  server_streaming: bool
+) -> anyhow::Result<(Response<DynamicMessage>, Vec<DynamicMessage>, MetadataMap)> {
   let host = config.get("host")
     .map(json_to_string)
     .unwrap_or_else(|| "[::1]".to_string());
@@ -321,11 +692,29 @@ async fn make_grpc_request(
   debug!("Making gRPC request to {}", path);
   let codec = PactCodec::new(file_desc, output_desc, input_desc, interaction);
   let mut grpc = tonic::client::Grpc::new(conn);
-  grpc.unary(request, path, codec).await
-    .map_err(|err| {
-      error!("gRPC request failed {:?}", err);
-      anyhow!(GrpcError { status: err })
-    })
+  // Use a streaming call so the trailers are not merged into the response metadata
+  let (metadata, mut stream, extensions) = grpc.server_streaming(request, path, codec).await
+    .map_err(grpc_error)?
+    .into_parts();
+  let message = stream.message().await
+    .map_err(grpc_error)?
+    .ok_or_else(|| grpc_error(Status::internal("Missing response message.")))?;
+  // For server streaming methods, collect all the remaining messages from the stream
+  let mut streamed_messages = vec![];
+  if server_streaming {
+    while let Some(message) = stream.message().await.map_err(grpc_error)? {
+      streamed_messages.push(message);
+    }
+  }
+  let trailers = stream.trailers().await
+    .map_err(grpc_error)?
+    .unwrap_or_default();
+  Ok((Response::from_parts(metadata, message, extensions), streamed_messages, trailers))
+}
+
+fn grpc_error(status: Status) -> anyhow::Error {
+  error!("gRPC request failed {:?}", status);
+  anyhow!(GrpcError { status })
 }
 
 fn build_grpc_request(
@@ -373,3 +762,607 @@ fn build_grpc_request(
   }
   Ok(request)
 }
+
+#[cfg(test)]
+mod tests {
+  use std::collections::HashMap;
+
+  use base64::Engine;
+  use base64::engine::general_purpose::STANDARD as BASE64;
+  use bytes::Bytes;
+  use expectest::prelude::*;
+  use maplit::hashmap;
+  use pact_matching::Mismatch;
+  use pact_models::content_types::ContentType;
+  use pact_models::prelude::OptionalBody;
+  use pact_models::prelude::v4::V4Pact;
+  use pact_models::v4::message_parts::MessageContents;
+  use pact_models::v4::sync_message::SynchronousMessage;
+  use pact_verifier::verification_result::VerificationMismatchResult;
+  use serde_json::json;
+  use tonic::{Code, Status};
+  use prost::Message;
+  use prost_types::{DescriptorProto, FieldDescriptorProto, FileDescriptorProto, FileDescriptorSet, MethodDescriptorProto};
+  use prost_types::field_descriptor_proto::{Label, Type};
+  use tonic::metadata::MetadataMap;
+
+  use crate::dynamic_message::DynamicMessage;
+  use crate::message_decoder::{decode_message, repeated_field_encodings};
+  use crate::metadata::{RpcStatus, STATUS_DETAILS_KEY};
+  use crate::utils::hash_descriptors;
+
+  use super::{
+    verification_results_to_json,
+    verify_message_interaction,
+    verify_metadata,
+    verify_error_response,
+    verify_packed_encoding,
+    verify_response,
+    verify_streamed_responses
+  };
+
+  #[test]
+  fn verification_results_to_json_with_no_mismatches() {
+    let field_paths = vec![ "$.name".to_string(), "$.value".to_string() ];
+    expect!(verification_results_to_json(&field_paths, &[], Some(Code::Ok))).to(be_equal_to(json!({
+      "result": "passed",
+      "grpcStatus": 0,
+      "body": {
+        "matched": [ "$.name", "$.value" ],
+        "mismatched": []
+      },
+      "metadata": [],
+      "errors": []
+    })));
+  }
+
+  #[test]
+  fn verification_results_to_json_with_matched_and_mismatched_fields() {
+    let field_paths = vec![
+      "$.name".to_string(),
+      "$.value".to_string(),
+      "$.values".to_string(),
+      "$.shape".to_string()
+    ];
+    let results = vec![
+      VerificationMismatchResult::Mismatches {
+        mismatches: vec![
+          Mismatch::BodyMismatch {
+            path: "$.value".to_string(),
+            expected: Some("12".into()),
+            actual: Some("13".into()),
+            mismatch: "Expected 12 (Float) to be equal to 13 (Float)".to_string()
+          },
+          Mismatch::BodyMismatch {
+            path: "$.shape.edge_length".to_string(),
+            expected: Some("3".into()),
+            actual: Some("4".into()),
+            mismatch: "Expected 3 (Float) to be equal to 4 (Float)".to_string()
+          },
+          Mismatch::MetadataMismatch {
+            key: "x-request-id".to_string(),
+            expected: "1234".to_string(),
+            actual: "".to_string(),
+            mismatch: "Expected message metadata 'x-request-id' but was missing".to_string()
+          }
+        ],
+        interaction_id: Some("abc123".to_string())
+      },
+      VerificationMismatchResult::Error {
+        error: "Failed to decode the response".to_string(),
+        interaction_id: None
+      }
+    ];
+    expect!(verification_results_to_json(&field_paths, &results, Some(Code::NotFound))).to(be_equal_to(json!({
+      "result": "failed",
+      "grpcStatus": 5,
+      "body": {
+        "matched": [ "$.name", "$.values" ],
+        "mismatched": [
+          {
+            "type": "BodyMismatch",
+            "path": "$.value",
+            "expected": "12",
+            "actual": "13",
+            "mismatch": "Expected 12 (Float) to be equal to 13 (Float)",
+            "interactionId": "abc123"
+          },
+          {
+            "type": "BodyMismatch",
+            "path": "$.shape.edge_length",
+            "expected": "3",
+            "actual": "4",
+            "mismatch": "Expected 3 (Float) to be equal to 4 (Float)",
+            "interactionId": "abc123"
+          }
+        ]
+      },
+      "metadata": [
+        {
+          "type": "MetadataMismatch",
+          "key": "x-request-id",
+          "expected": "1234",
+          "actual": "",
+          "mismatch": "Expected message metadata 'x-request-id' but was missing",
+          "interactionId": "abc123"
+        }
+      ],
+      "errors": [
+        {
+          "error": "Failed to decode the response",
+          "interactionId": null
+        }
+      ]
+    })));
+  }
+
+  #[test]
+  fn verify_metadata_with_v4_metadata_matching_rules() {
+    let response = MessageContents::from_json(&json!({
+      "contents": null,
+      "metadata": {
+        "x-request-id": "a1b2c3",
+        "x-timestamp": "2024-01-02T10:11:12"
+      },
+      "matchingRules": {
+        "metadata": {
+          "x-request-id": {
+            "combine": "AND",
+            "matchers": [ { "match": "regex", "regex": "^[a-f0-9]{6}$" } ]
+          },
+          "x-timestamp": {
+            "combine": "AND",
+            "matchers": [ { "match": "datetime", "format": "yyyy-MM-dd'T'HH:mm:ss" } ]
+          }
+        }
+      }
+    })).unwrap();
+
+    let mut metadata = MetadataMap::new();
+    metadata.insert("x-request-id", "ffee00".parse().unwrap());
+    metadata.insert("x-timestamp", "2025-12-31T23:59:59".parse().unwrap());
+    let (result, _) = verify_metadata(&metadata, &response).unwrap();
+    expect!(result.mismatches.iter()).to(be_empty());
+
+    let mut metadata = MetadataMap::new();
+    metadata.insert("x-request-id", "not-hex".parse().unwrap());
+    metadata.insert("x-timestamp", "2025-12-31".parse().unwrap());
+    let (result, _) = verify_metadata(&metadata, &response).unwrap();
+    expect!(result.mismatches.len()).to(be_equal_to(2));
+  }
+
+  fn string_field(name: &str, number: i32) -> FieldDescriptorProto {
+    FieldDescriptorProto {
+      name: Some(name.to_string()),
+      number: Some(number),
+      label: Some(Label::Optional as i32),
+      r#type: Some(Type::String as i32),
+      .. FieldDescriptorProto::default()
+    }
+  }
+
+  /// Descriptors for a `Test` message with the given fields, and a `Call` method that takes and
+  /// returns the message
+  fn test_descriptors(fields: Vec<FieldDescriptorProto>) -> (DescriptorProto, FileDescriptorSet, MethodDescriptorProto) {
+    let message_descriptor = DescriptorProto {
+      name: Some("Test".to_string()),
+      field: fields,
+      .. DescriptorProto::default()
+    };
+    let descriptors = FileDescriptorSet {
+      file: vec![
+        FileDescriptorProto {
+          name: Some("test.proto".to_string()),
+          message_type: vec![ message_descriptor.clone() ],
+          .. FileDescriptorProto::default()
+        }
+      ]
+    };
+    let method_descriptor = MethodDescriptorProto {
+      name: Some("Call".to_string()),
+      input_type: Some(".Test".to_string()),
+      output_type: Some(".Test".to_string()),
+      .. MethodDescriptorProto::default()
+    };
+    (message_descriptor, descriptors, method_descriptor)
+  }
+
+  /// Descriptions of all the mismatches and errors in the verification results
+  fn mismatch_descriptions(results: &[VerificationMismatchResult]) -> Vec<String> {
+    results.iter()
+      .flat_map(|result| match result {
+        VerificationMismatchResult::Mismatches { mismatches, .. } => mismatches.iter()
+          .map(|mismatch| mismatch.description())
+          .collect(),
+        VerificationMismatchResult::Error { error, .. } => vec![ error.clone() ]
+      })
+      .collect()
+  }
+
+  #[test]
+  fn verify_response_includes_the_actual_message_when_the_body_does_not_match() {
+    let (message_descriptor, descriptors, method_descriptor) = test_descriptors(vec![
+      string_field("id", 1),
+      string_field("note", 2)
+    ]);
+    let interaction = SynchronousMessage {
+      response: vec![
+        MessageContents {
+          // Test { id: "1" }
+          contents: OptionalBody::Present(Bytes::from_static(&[10, 1, b'1']),
+            Some(ContentType::parse("application/protobuf").unwrap()), None),
+          .. MessageContents::default()
+        }
+      ],
+      .. SynchronousMessage::default()
+    };
+
+    // Test { id: "2", note: "x" }
+    let mut actual = Bytes::from_static(&[10, 1, b'2', 18, 1, b'x']);
+    let fields = decode_message(&mut actual, &message_descriptor, &descriptors).unwrap();
+    let response_body = DynamicMessage::new(&fields, &descriptors);
+
+    let (results, output) = verify_response(&response_body, &MetadataMap::new(), &MetadataMap::new(),
+      &interaction, &descriptors, &method_descriptor).unwrap();
+    expect!(results.len()).to(be_equal_to(1));
+    expect!(output).to(be_equal_to(vec![
+      "      actual message:".to_string(),
+      "        id: \"2\"".to_string(),
+      "        note: \"x\"".to_string()
+    ]));
+
+    // Test { id: "1", note: "x" }
+    let mut actual = Bytes::from_static(&[10, 1, b'1', 18, 1, b'x']);
+    let fields = decode_message(&mut actual, &message_descriptor, &descriptors).unwrap();
+    let response_body = DynamicMessage::new(&fields, &descriptors);
+    let (results, output) = verify_response(&response_body, &MetadataMap::new(), &MetadataMap::new(),
+      &interaction, &descriptors, &method_descriptor).unwrap();
+    expect!(results.is_empty()).to(be_true());
+    expect!(output.is_empty()).to(be_true());
+  }
+
+  #[rstest::rstest]
+  #[case::collect_all_mismatches(false, 2)]
+  #[case::fail_fast(true, 1)]
+  fn verify_response_with_the_fail_fast_option(#[case] fail_fast: bool, #[case] expected_mismatches: usize) {
+    let (message_descriptor, descriptors, method_descriptor) = test_descriptors(vec![
+      string_field("id", 1),
+      string_field("note", 2)
+    ]);
+    let interaction = SynchronousMessage {
+      response: vec![
+        MessageContents {
+          // Test { id: "1", note: "a" }
+          contents: OptionalBody::Present(Bytes::from_static(&[10, 1, b'1', 18, 1, b'a']),
+            Some(ContentType::parse("application/protobuf").unwrap()), None),
+          .. MessageContents::default()
+        }
+      ],
+      plugin_config: hashmap!{
+        "protobuf".to_string() => hashmap!{ "failFast".to_string() => json!(fail_fast) }
+      },
+      .. SynchronousMessage::default()
+    };
+
+    // Test { id: "2", note: "b" }
+    let mut actual = Bytes::from_static(&[10, 1, b'2', 18, 1, b'b']);
+    let fields = decode_message(&mut actual, &message_descriptor, &descriptors).unwrap();
+    let response_body = DynamicMessage::new(&fields, &descriptors);
+
+    let (results, _) = verify_response(&response_body, &MetadataMap::new(), &MetadataMap::new(),
+      &interaction, &descriptors, &method_descriptor).unwrap();
+    expect!(mismatch_descriptions(&results).len()).to(be_equal_to(expected_mismatches));
+  }
+
+  #[test]
+  fn verify_error_response_with_no_expected_response_message() {
+    let response = MessageContents {
+      contents: OptionalBody::Missing,
+      metadata: hashmap!{
+        "grpc-status".to_string() => json!("NOT_FOUND"),
+        "grpc-message".to_string() => json!("No such entity")
+      },
+      .. MessageContents::default()
+    };
+    let descriptors = FileDescriptorSet { file: vec![] };
+
+    let (results, _) = verify_error_response(&response, &Status::not_found("No such entity"),
+      &None, &descriptors);
+    expect!(results.is_empty()).to(be_true());
+
+    let (results, _) = verify_error_response(&response, &Status::permission_denied("Not allowed"),
+      &None, &descriptors);
+    expect!(results.is_empty()).to(be_false());
+  }
+
+  #[test]
+  fn verify_error_response_compares_status_details_with_known_types_as_messages() {
+    let error_info = |reason: &str| {
+      let mut value = vec![];
+      prost::encoding::string::encode(1, &reason.to_string(), &mut value);
+      prost::encoding::string::encode(2, &"example.com".to_string(), &mut value);
+      RpcStatus {
+        code: tonic::Code::NotFound as i32,
+        message: "No such entity".to_string(),
+        details: vec![
+          prost_types::Any {
+            type_url: "type.googleapis.com/google.rpc.ErrorInfo".to_string(),
+            value
+          }
+        ]
+      }
+    };
+    let response = MessageContents {
+      contents: OptionalBody::Missing,
+      metadata: hashmap!{
+        "grpc-status".to_string() => json!("NOT_FOUND"),
+        "grpc-message".to_string() => json!("No such entity"),
+        STATUS_DETAILS_KEY.to_string() => json!(BASE64.encode(error_info("ENTITY_NOT_FOUND").encode_to_vec()))
+      },
+      .. MessageContents::default()
+    };
+    let string_field = |name: &str, number: i32| FieldDescriptorProto {
+      name: Some(name.to_string()),
+      number: Some(number),
+      label: Some(Label::Optional as i32),
+      r#type: Some(Type::String as i32),
+      .. FieldDescriptorProto::default()
+    };
+    let descriptors = FileDescriptorSet {
+      file: vec![
+        FileDescriptorProto {
+          name: Some("google/rpc/error_details.proto".to_string()),
+          package: Some("google.rpc".to_string()),
+          message_type: vec![
+            DescriptorProto {
+              name: Some("ErrorInfo".to_string()),
+              field: vec![ string_field("reason", 1), string_field("domain", 2) ],
+              .. DescriptorProto::default()
+            }
+          ],
+          syntax: Some("proto3".to_string()),
+          .. FileDescriptorProto::default()
+        }
+      ]
+    };
+    let status = |reason: &str| Status::with_details(tonic::Code::NotFound, "No such entity",
+      error_info(reason).encode_to_vec().into());
+
+    let (results, _) = verify_error_response(&response, &status("ENTITY_NOT_FOUND"), &None, &descriptors);
+    expect!(results.is_empty()).to(be_true());
+
+    let (results, _) = verify_error_response(&response, &status("ENTITY_DELETED"), &None, &descriptors);
+    let mismatches = results.iter()
+      .flat_map(|result| match result {
+        VerificationMismatchResult::Mismatches { mismatches, .. } => mismatches.iter()
+          .map(|mismatch| mismatch.description())
+          .collect::<Vec<_>>(),
+        VerificationMismatchResult::Error { error, .. } => vec![ error.clone() ]
+      })
+      .collect::<Vec<_>>();
+    expect!(mismatches).to(be_equal_to(vec![
+      "Status detail 0 (google.rpc.ErrorInfo) $.reason: Expected 'ENTITY_DELETED' to be equal to 'ENTITY_NOT_FOUND'".to_string()
+    ]));
+  }
+
+  #[test]
+  fn verify_streamed_responses_with_fewer_messages_than_expected() {
+    let (message_descriptor, descriptors, method_descriptor) = test_descriptors(vec![ string_field("id", 1) ]);
+    let method_descriptor = MethodDescriptorProto {
+      name: Some("Watch".to_string()),
+      server_streaming: Some(true),
+      .. method_descriptor
+    };
+    let response = |id: &'static [u8]| MessageContents {
+      contents: OptionalBody::Present(Bytes::from_static(id),
+        Some(ContentType::parse("application/protobuf").unwrap()), None),
+      .. MessageContents::default()
+    };
+    let interaction = SynchronousMessage {
+      response: vec![
+        response(&[10, 1, b'1']),
+        response(&[10, 1, b'2']),
+        response(&[10, 1, b'3'])
+      ],
+      .. SynchronousMessage::default()
+    };
+    let message = |bytes: &'static [u8]| {
+      let fields = decode_message(&mut Bytes::from_static(bytes), &message_descriptor, &descriptors).unwrap();
+      DynamicMessage::new(&fields, &descriptors)
+    };
+
+    let (results, _) = verify_streamed_responses(&[ message(&[10, 1, b'2']) ], &interaction, &descriptors,
+      &method_descriptor);
+    expect!(mismatch_descriptions(&results)).to(be_equal_to(vec![
+      "$ -> Expected the response stream to have 3 message(s), but received 2".to_string()
+    ]));
+
+    let (results, _) = verify_streamed_responses(&[ message(&[10, 1, b'2']), message(&[10, 1, b'3']) ],
+      &interaction, &descriptors, &method_descriptor);
+    expect!(results.is_empty()).to(be_true());
+  }
+
+  #[test]
+  fn verify_packed_encoding_of_repeated_fields() {
+    let (message_descriptor, descriptors, _) = test_descriptors(vec![
+      FieldDescriptorProto {
+        name: Some("values".to_string()),
+        number: Some(1),
+        label: Some(Label::Repeated as i32),
+        r#type: Some(Type::Int32 as i32),
+        .. FieldDescriptorProto::default()
+      }
+    ]);
+    let message = |bytes: &'static [u8]| {
+      let encodings = repeated_field_encodings(&mut Bytes::from_static(bytes), &message_descriptor).unwrap();
+      let fields = decode_message(&mut Bytes::from_static(bytes), &message_descriptor, &descriptors).unwrap();
+      DynamicMessage::new(&fields, &descriptors).with_repeated_field_encodings(encodings)
+    };
+    // Test { values: [1, 2] }
+    let packed = message(&[10, 2, 1, 2]);
+    let unpacked = message(&[8, 1, 8, 2]);
+    let config = |encoding: &str| hashmap!{ "packedEncoding".to_string() => json!(encoding) };
+
+    expect!(verify_packed_encoding(&packed, &HashMap::default()).is_empty()).to(be_true());
+    expect!(verify_packed_encoding(&unpacked, &HashMap::default()).is_empty()).to(be_true());
+    expect!(verify_packed_encoding(&packed, &config("packed")).is_empty()).to(be_true());
+    expect!(verify_packed_encoding(&unpacked, &config("unpacked")).is_empty()).to(be_true());
+
+    let mismatches = verify_packed_encoding(&unpacked, &config("packed"));
+    expect!(mismatches.iter().map(|m| m.description()).collect::<Vec<_>>()).to(be_equal_to(vec![
+      "$.values -> Expected repeated field 'values' to be packed, but it was received unpacked".to_string()
+    ]));
+    let mismatches = verify_packed_encoding(&packed, &config("unpacked"));
+    expect!(mismatches.iter().map(|m| m.description()).collect::<Vec<_>>()).to(be_equal_to(vec![
+      "$.values -> Expected repeated field 'values' to be unpacked, but it was received packed".to_string()
+    ]));
+  }
+
+  /// V4 Pact JSON with a single `.test.Test` message interaction. The interaction refers to the
+  /// descriptors with `descriptor_key`, and the plugin configuration contains `descriptor_bytes`
+  /// stored with the hash of them as the key.
+  fn message_pact(descriptor_key: &str, descriptor_bytes: &[u8], content: &[u8]) -> serde_json::Value {
+    json!({
+      "interactions": [
+        {
+          "type": "Asynchronous/Messages",
+          "description": "test message",
+          "key": "1234",
+          "pluginConfiguration": {
+            "protobuf": {
+              "descriptorKey": descriptor_key,
+              "message": ".test.Test"
+            }
+          },
+          "contents": {
+            "content": BASE64.encode(content),
+            "contentType": "application/protobuf;message=.test.Test",
+            "contentTypeHint": "BINARY",
+            "encoded": "base64"
+          }
+        }
+      ],
+      "metadata": {
+        "pactSpecification": { "version": "4.0" },
+        "plugins": [
+          {
+            "name": "protobuf",
+            "version": "0.5.5",
+            "configuration": {
+              hash_descriptors(descriptor_bytes): {
+                "protoDescriptors": BASE64.encode(descriptor_bytes),
+                "protoFile": ""
+              }
+            }
+          }
+        ]
+      }
+    })
+  }
+
+  #[test]
+  fn verify_message_interaction_compares_the_message_against_the_expected_one() {
+    let descriptors = FileDescriptorSet {
+      file: vec![
+        FileDescriptorProto {
+          name: Some("test.proto".to_string()),
+          package: Some("test".to_string()),
+          message_type: vec![
+            DescriptorProto {
+              name: Some("Test".to_string()),
+              field: vec![
+                FieldDescriptorProto {
+                  name: Some("name".to_string()),
+                  number: Some(1),
+                  r#type: Some(Type::String as i32),
+                  .. FieldDescriptorProto::default()
+                },
+                FieldDescriptorProto {
+                  name: Some("count".to_string()),
+                  number: Some(2),
+                  r#type: Some(Type::Int32 as i32),
+                  .. FieldDescriptorProto::default()
+                }
+              ],
+              .. DescriptorProto::default()
+            }
+          ],
+          syntax: Some("proto3".to_string()),
+          .. FileDescriptorProto::default()
+        }
+      ]
+    };
+    let descriptor_bytes = descriptors.encode_to_vec();
+    // Test { name: "test", count: 1 }
+    let mut pact_json = message_pact(&hash_descriptors(&descriptor_bytes), &descriptor_bytes,
+      &[10, 4, b't', b'e', b's', b't', 16, 1]);
+    pact_json["interactions"][0]["matchingRules"] = json!({
+      "body": {
+        "$.count": { "combine": "AND", "matchers": [ { "match": "integer" } ] }
+      }
+    });
+    let pact = V4Pact::pact_from_json(&pact_json, "<>").unwrap();
+    let interaction = pact.interactions.first().unwrap().as_v4_async_message().unwrap();
+    let body = |bytes: &'static [u8]| OptionalBody::Present(Bytes::from_static(bytes), None, None);
+
+    // Test { name: "test", count: 100 }
+    let (results, output) = verify_message_interaction(&pact, &interaction,
+      &body(&[10, 4, b't', b'e', b's', b't', 16, 100])).unwrap();
+    expect!(results.is_empty()).to(be_true());
+    expect!(output.len()).to(be_equal_to(1));
+
+    // Test { name: "other", count: 100 }
+    let (results, output) = verify_message_interaction(&pact, &interaction,
+      &body(&[10, 5, b'o', b't', b'h', b'e', b'r', 16, 100])).unwrap();
+    expect!(mismatch_descriptions(&results)).to(be_equal_to(vec![
+      "$.name -> Expected 'other' to be equal to 'test'".to_string()
+    ]));
+    expect!(output.iter().any(|line| line.contains("actual message:"))).to(be_true());
+  }
+
+  #[test]
+  fn verify_message_interaction_with_a_newer_descriptor_with_an_extra_field() {
+    let field = |name: &str, number: i32, field_type: Type| FieldDescriptorProto {
+      name: Some(name.to_string()),
+      number: Some(number),
+      r#type: Some(field_type as i32),
+      .. FieldDescriptorProto::default()
+    };
+    let descriptors = |fields: Vec<FieldDescriptorProto>| FileDescriptorSet {
+      file: vec![
+        FileDescriptorProto {
+          name: Some("test.proto".to_string()),
+          package: Some("test".to_string()),
+          message_type: vec![
+            DescriptorProto {
+              name: Some("Test".to_string()),
+              field: fields,
+              .. DescriptorProto::default()
+            }
+          ],
+          syntax: Some("proto3".to_string()),
+          .. FileDescriptorProto::default()
+        }
+      ]
+    };
+    // The interaction was recorded with the descriptors for the original version of the proto file,
+    // but only the descriptors for the newer version (with an additional field) are available
+    let recorded_descriptors = descriptors(vec![
+      field("name", 1, Type::String)
+    ]).encode_to_vec();
+    let newer_descriptors = descriptors(vec![
+      field("name", 1, Type::String),
+      field("count", 2, Type::Int32)
+    ]).encode_to_vec();
+    // Test { name: "test" }
+    let pact_json = message_pact(&hash_descriptors(&recorded_descriptors), &newer_descriptors,
+      &[10, 4, b't', b'e', b's', b't']);
+    let pact = V4Pact::pact_from_json(&pact_json, "<>").unwrap();
+    let interaction = pact.interactions.first().unwrap().as_v4_async_message().unwrap();
+
+    // Test { name: "test", count: 100 }
+    let actual = OptionalBody::Present(Bytes::from_static(&[10, 4, b't', b'e', b's', b't', 16, 100]), None, None);
+    let (results, _) = verify_message_interaction(&pact, &interaction, &actual).unwrap();
+    expect!(results.is_empty()).to(be_true());
+  }
+}
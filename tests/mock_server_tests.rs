@@ -1,45 +1,145 @@
+use std::fs;
 use std::panic::catch_unwind;
 use std::path::Path;
-use base64::Engine;
+use std::time::Duration;
 
 use expectest::prelude::*;
 use pact_consumer::mock_server::StartMockServerAsync;
-use pact_consumer::prelude::PactBuilderAsync;
+use pact_consumer::prelude::{PactBuilderAsync, ValidatingMockServer};
+use pact_models::v4::sync_message::SynchronousMessage;
 use prost::encoding::WireType;
 use prost::Message;
 use prost_types::FileDescriptorSet;
-use serde_json::json;
+use serde_json::{json, Value};
 use tonic::Request;
+use tonic::client::Grpc;
+use tonic::codec::CompressionEncoding;
+use tonic::transport::{Channel, Endpoint};
 use tower::ServiceExt;
 use pact_protobuf_plugin::dynamic_message::{DynamicMessage, PactCodec};
 use pact_protobuf_plugin::message_decoder::{ProtobufField, ProtobufFieldData};
 use pact_protobuf_plugin::utils::{find_message_descriptor_for_type};
 
-async fn mock_server_block() {
-  let mut pact_builder = PactBuilderAsync::new_v4("null-and-void", "protobuf-plugin");
-  let _mock_server = pact_builder
-    .using_plugin("protobuf", None).await
-    .synchronous_message_interaction("doesn't matter, won't be called", |mut i| async move {
-      let proto_file = Path::new("tests/simple.proto")
-        .canonicalize().unwrap().to_string_lossy().to_string();
-      i.contents_from(json!({
-        "pact:proto": proto_file,
-        "pact:content-type": "application/protobuf",
-        "pact:proto-service": "Test/GetTest",
-
-        "request": {
-          "in": "matching(boolean, true)"
-        },
-
-        "response": {
-          "out": "matching(boolean, true)"
+/// Returns the descriptors for tests/simple.proto. To update the descriptor file:
+/// protoc --descriptor_set_out=tests/simple.pb tests/simple.proto
+fn simple_proto_descriptors() -> FileDescriptorSet {
+  let descriptors = fs::read("tests/simple.pb").unwrap();
+  FileDescriptorSet::decode(descriptors.as_slice()).unwrap()
+}
+
+/// gRPC mock server started for interactions on the services from tests/simple.proto
+struct MockServerFixture {
+  mock_server: Box<dyn ValidatingMockServer>,
+  _pact_builder: PactBuilderAsync,
+  interaction: SynchronousMessage,
+  fds: FileDescriptorSet
+}
+
+impl MockServerFixture {
+  /// Connects a gRPC client to the mock server
+  async fn client(&self) -> Grpc<Channel> {
+    let mut conn = Endpoint::from_shared(self.mock_server.url().to_string())
+      .unwrap()
+      .connect()
+      .await
+      .unwrap();
+    conn.ready().await.unwrap();
+    Grpc::new(conn)
+  }
+
+  /// Creates a codec for the first interaction with the given request and response message types
+  fn codec(&self, input_type: &str, output_type: &str) -> PactCodec {
+    let (input_message, _) = find_message_descriptor_for_type(input_type, &self.fds).unwrap();
+    let (output_message, _) = find_message_descriptor_for_type(output_type, &self.fds).unwrap();
+    PactCodec::new(&self.fds, &input_message, &output_message, &self.interaction)
+  }
+
+  /// Creates a message of the given type with the fields set to the values
+  fn message(&self, message_type: &str, fields: &[(&str, ProtobufFieldData)]) -> DynamicMessage {
+    let (descriptor, _) = find_message_descriptor_for_type(message_type, &self.fds).unwrap();
+    let fields = fields.iter()
+      .map(|(name, data)| {
+        let field_descriptor = descriptor.field.iter()
+          .find(|field| field.name() == *name)
+          .unwrap();
+        let wire_type = match data {
+          ProtobufFieldData::String(_) => WireType::LengthDelimited,
+          _ => WireType::Varint
+        };
+        ProtobufField {
+          field_num: field_descriptor.number() as u32,
+          field_name: name.to_string(),
+          wire_type,
+          data: data.clone(),
+          additional_data: vec![],
+          descriptor: field_descriptor.clone()
         }
-      })).await;
-      i
-    })
-    .await
+      })
+      .collect::<Vec<_>>();
+    DynamicMessage::new(fields.as_slice(), &self.fds)
+  }
+
+  /// Creates a MessageIn message with the `in` field set to the value
+  fn message_in(&self, value: bool) -> DynamicMessage {
+    self.message(".com.pact.protobuf.example.MessageIn", &[("in", ProtobufFieldData::Boolean(value))])
+  }
+}
+
+/// Returns the gRPC path for a method of the Test service
+fn test_service_path(method: &str) -> http::uri::PathAndQuery {
+  http::uri::PathAndQuery::try_from(format!("/com.pact.protobuf.example.Test/{}", method)).unwrap()
+}
+
+/// Starts a gRPC mock server for the interactions, given as the description and the contents to
+/// configure each interaction with. The `pact:proto` and `pact:content-type` values are added to
+/// the contents.
+async fn start_mock_server_for(consumer: &str, interactions: &[(&str, Value)]) -> MockServerFixture {
+  let proto_file = Path::new("tests/simple.proto")
+    .canonicalize().unwrap().to_string_lossy().to_string();
+  let mut pact_builder = PactBuilderAsync::new_v4(consumer, "protobuf-plugin");
+  pact_builder.using_plugin("protobuf", None).await;
+  for (description, contents) in interactions {
+    let mut contents = contents.clone();
+    if let Some(contents) = contents.as_object_mut() {
+      contents.insert("pact:proto".to_string(), json!(proto_file));
+      contents.insert("pact:content-type".to_string(), json!("application/protobuf"));
+    }
+    pact_builder
+      .synchronous_message_interaction(*description, |mut i| async move {
+        i.contents_from(contents).await;
+        i
+      })
+      .await;
+  }
+  let mock_server = pact_builder
     .start_mock_server_async(Some("protobuf/transport/grpc"), None)
     .await;
+  let interaction = pact_builder.build()
+    .interactions().first().unwrap()
+    .as_v4_sync_message().unwrap();
+
+  MockServerFixture {
+    mock_server,
+    _pact_builder: pact_builder,
+    interaction,
+    fds: simple_proto_descriptors()
+  }
+}
+
+async fn mock_server_block() {
+  let _fixture = start_mock_server_for("null-and-void", &[
+    ("doesn't matter, won't be called", json!({
+      "pact:proto-service": "Test/GetTest",
+
+      "request": {
+        "in": "matching(boolean, true)"
+      },
+
+      "response": {
+        "out": "matching(boolean, true)"
+      }
+    }))
+  ]).await;
 
   // Should fail as we have not made a request to the mock server when the mock server is dropped
   // at the end of this function
@@ -62,86 +162,298 @@ fn mock_server_with_no_requests() {
 
 #[test_log::test(tokio::test(flavor = "multi_thread"))]
 async fn each_value_matcher() {
-  let mut pact_builder = PactBuilderAsync::new_v4("each-value", "protobuf-plugin");
-  pact_builder
-    .using_plugin("protobuf", None).await
-    .synchronous_message_interaction("get a list of values", |mut i| async move {
-      let proto_file = Path::new("tests/simple.proto")
-        .canonicalize().unwrap().to_string_lossy().to_string();
-      i.contents_from(json!({
-        "pact:proto": proto_file,
-        "pact:content-type": "application/protobuf",
-        "pact:proto-service": "Test/GetValues",
-
-        "request": {
-          "value": "eachValue(matching(type, '00000000000000000000000000000000'))"
-        },
-
-        "response": {
-          "value": "eachValue(matching(type, '00000000000000000000000000000000'))"
-        }
-      })).await;
-      i
-    })
-    .await;
-  let mock_server = pact_builder
-    .start_mock_server_async(Some("protobuf/transport/grpc"), None)
-    .await;
+  let fixture = start_mock_server_for("each-value", &[
+    ("get a list of values", json!({
+      "pact:proto-service": "Test/GetValues",
+
+      "request": {
+        "value": "eachValue(matching(type, '00000000000000000000000000000000'))"
+      },
 
-  let url = mock_server.url();
-  // encoded descriptor of a simple.proto. 
-  // To update:
-  // protoc --descriptor_set_out=/dev/stdout tests/simple.proto | base64
-  let descriptors = base64::engine::general_purpose::STANDARD.decode(
-    "CpIDChJ0ZXN0cy9zaW1wbGUucHJvdG8SGWNvbS5wYWN0LnByb3RvYnVmLmV4YW1wbGUiGwoJTWVz\
-    c2FnZUluEg4KAmluGAEgASgIUgJpbiIeCgpNZXNzYWdlT3V0EhAKA291dBgBIAEoCFIDb3V0IicK\
-    D1ZhbHVlc01lc3NhZ2VJbhIUCgV2YWx1ZRgBIAMoCVIFdmFsdWUiKAoQVmFsdWVzTWVzc2FnZU91\
-    dBIUCgV2YWx1ZRgBIAMoCVIFdmFsdWUyyAEKBFRlc3QSWAoHR2V0VGVzdBIkLmNvbS5wYWN0LnBy\
-    b3RvYnVmLmV4YW1wbGUuTWVzc2FnZUluGiUuY29tLnBhY3QucHJvdG9idWYuZXhhbXBsZS5NZXNz\
-    YWdlT3V0IgASZgoJR2V0VmFsdWVzEiouY29tLnBhY3QucHJvdG9idWYuZXhhbXBsZS5WYWx1ZXNN\
-    ZXNzYWdlSW4aKy5jb20ucGFjdC5wcm90b2J1Zi5leGFtcGxlLlZhbHVlc01lc3NhZ2VPdXQiAGIG\
-    cHJvdG8z").unwrap();
-  let fds = FileDescriptorSet::decode(descriptors.as_slice()).unwrap();
-
-  let mut conn = tonic::transport::Endpoint::from_shared(url.to_string())
-    .unwrap()
-    .connect()
-    .await
-    .unwrap();
-  conn.ready().await.unwrap();
-
-  let (input_message, _) = find_message_descriptor_for_type(".com.pact.protobuf.example.ValuesMessageIn", &fds).unwrap();
-  // searching by name without package next, to confirm we're backwards compatible 
+      "response": {
+        "value": "eachValue(matching(type, '00000000000000000000000000000000'))"
+      }
+    }))
+  ]).await;
+
+  // searching by name without package for the response, to confirm we're backwards compatible
   // (it's verified by unit tests too, but wouldn't hurt to check here as well)
-  let (output_message, _) = find_message_descriptor_for_type("ValuesMessageOut", &fds).unwrap();
-  let interaction = pact_builder.build()
-    .interactions().first().unwrap()
-    .as_v4_sync_message().unwrap();
+  let codec = fixture.codec(".com.pact.protobuf.example.ValuesMessageIn", "ValuesMessageOut");
+  let mut grpc = fixture.client().await;
+
+  let message = fixture.message(".com.pact.protobuf.example.ValuesMessageIn", &[
+    ("value", ProtobufFieldData::String("value1".to_string())),
+    ("value", ProtobufFieldData::String("value2".to_string()))
+  ]);
+  grpc.unary(Request::new(message), test_service_path("GetValues"), codec).await.unwrap();
+}
+
+#[test_log::test(tokio::test(flavor = "multi_thread"))]
+async fn mock_server_compresses_responses_for_clients_that_accept_gzip() {
+  let fixture = start_mock_server_for("compressed-responses", &[
+    ("get a compressed response", json!({
+      "pact:proto-service": "Test/GetTest",
+      "pact:protobuf-config": {
+        "compressResponses": true
+      },
+
+      "request": {
+        "in": "matching(boolean, true)"
+      },
+
+      "response": {
+        "out": "matching(boolean, true)"
+      }
+    }))
+  ]).await;
+
+  let codec = fixture.codec(".com.pact.protobuf.example.MessageIn", ".com.pact.protobuf.example.MessageOut");
+  let mut grpc = fixture.client().await
+    .accept_compressed(CompressionEncoding::Gzip);
+  let response = grpc.unary(Request::new(fixture.message_in(true)), test_service_path("GetTest"), codec)
+    .await.unwrap();
+
+  let encoding = response.metadata().get("grpc-encoding").map(|v| v.to_str().unwrap().to_string());
+  expect!(encoding).to(be_some().value("gzip"));
+}
+
+#[test_log::test(tokio::test(flavor = "multi_thread"))]
+async fn mock_server_returns_deadline_exceeded_if_the_response_delay_exceeds_the_client_deadline() {
+  let fixture = start_mock_server_for("delayed-responses", &[
+    ("get a delayed response", json!({
+      "pact:proto-service": "Test/GetTest",
+      "pact:protobuf-config": {
+        "responseDelay": 1000
+      },
+
+      "request": {
+        "in": "matching(boolean, true)"
+      },
+
+      "response": {
+        "out": "matching(boolean, true)"
+      }
+    }))
+  ]).await;
+
+  let codec = fixture.codec(".com.pact.protobuf.example.MessageIn", ".com.pact.protobuf.example.MessageOut");
+  let mut grpc = fixture.client().await;
+  let mut request = Request::new(fixture.message_in(true));
+  request.set_timeout(Duration::from_millis(100));
+  let response = grpc.unary(request, test_service_path("GetTest"), codec).await;
+
+  expect!(response.unwrap_err().code()).to(be_equal_to(tonic::Code::DeadlineExceeded));
+}
+
+#[test_log::test(tokio::test(flavor = "multi_thread"))]
+async fn mock_server_returns_the_configured_grpc_status() {
+  let fixture = start_mock_server_for("grpc-status", &[
+    ("get an unauthenticated response", json!({
+      "pact:proto-service": "Test/GetTest",
+      "pact:grpc-status": {
+        "code": "UNAUTHENTICATED",
+        "message": "Invalid token"
+      },
+
+      "request": {
+        "in": "matching(boolean, true)"
+      }
+    }))
+  ]).await;
+
+  let codec = fixture.codec(".com.pact.protobuf.example.MessageIn", ".com.pact.protobuf.example.MessageOut");
+  let mut grpc = fixture.client().await;
+  let response = grpc.unary(Request::new(fixture.message_in(true)), test_service_path("GetTest"), codec).await;
+
+  let status = response.unwrap_err();
+  expect!(status.code()).to(be_equal_to(tonic::Code::Unauthenticated));
+  expect!(status.message()).to(be_equal_to("Invalid token"));
+}
+
+#[test_log::test(tokio::test(flavor = "multi_thread"))]
+async fn mock_server_returns_the_grpc_status_for_an_interaction_with_no_response_message() {
+  let fixture = start_mock_server_for("grpc-status-only", &[
+    ("get a not found response with no message", json!({
+      "pact:proto-service": "Test/GetTest",
+      "request": {
+        "in": "matching(boolean, true)"
+      },
+      "responseMetadata": {
+        "grpc-status": "NOT_FOUND",
+        "grpc-message": "No such entity"
+      }
+    }))
+  ]).await;
+  expect!(fixture.interaction.response.first().unwrap().contents.is_present()).to(be_false());
+
+  let codec = fixture.codec(".com.pact.protobuf.example.MessageIn", ".com.pact.protobuf.example.MessageOut");
+  let mut grpc = fixture.client().await;
+  let response = grpc.unary(Request::new(fixture.message_in(true)), test_service_path("GetTest"), codec).await;
+
+  let status = response.unwrap_err();
+  expect!(status.code()).to(be_equal_to(tonic::Code::NotFound));
+  expect!(status.message()).to(be_equal_to("No such entity"));
+}
+
+#[test_log::test(tokio::test(flavor = "multi_thread"))]
+async fn mock_server_sends_the_response_trailers() {
+  let fixture = start_mock_server_for("response-trailers", &[
+    ("get a response with trailers", json!({
+      "pact:proto-service": "Test/GetTest",
+      "request": {
+        "in": "matching(boolean, true)"
+      },
+      "response": {
+        "out": "matching(boolean, true)"
+      },
+      "responseMetadata": {
+        "x-header": "header"
+      },
+      "responseTrailers": {
+        "x-trailer": "trailer"
+      }
+    }))
+  ]).await;
+
+  let codec = fixture.codec(".com.pact.protobuf.example.MessageIn", ".com.pact.protobuf.example.MessageOut");
+  let mut grpc = fixture.client().await;
+  let response = grpc.unary(Request::new(fixture.message_in(true)), test_service_path("GetTest"), codec).await;
+
+  // the tonic client merges the trailers into the response metadata
+  let response = response.unwrap();
+  expect!(response.metadata().get("x-header").map(|v| v.to_str().unwrap())).to(be_some().value("header"));
+  expect!(response.metadata().get("x-trailer").map(|v| v.to_str().unwrap())).to(be_some().value("trailer"));
+}
+
+#[test_log::test(tokio::test(flavor = "multi_thread"))]
+async fn mock_server_sends_the_response_trailers_with_a_grpc_status() {
+  let fixture = start_mock_server_for("response-trailers-with-status", &[
+    ("get an unauthenticated response with trailers", json!({
+      "pact:proto-service": "Test/GetTest",
+      "pact:grpc-status": "UNAUTHENTICATED",
+      "request": {
+        "in": "matching(boolean, true)"
+      },
+      "responseTrailers": {
+        "x-trailer": "trailer"
+      }
+    }))
+  ]).await;
+
+  let codec = fixture.codec(".com.pact.protobuf.example.MessageIn", ".com.pact.protobuf.example.MessageOut");
+  let mut grpc = fixture.client().await;
+  let response = grpc.unary(Request::new(fixture.message_in(true)), test_service_path("GetTest"), codec).await;
+
+  let status = response.unwrap_err();
+  expect!(status.code()).to(be_equal_to(tonic::Code::Unauthenticated));
+  expect!(status.metadata().get("x-trailer").map(|v| v.to_str().unwrap())).to(be_some().value("trailer"));
+}
+
+#[test_log::test(tokio::test(flavor = "multi_thread", worker_threads = 1))]
+async fn mock_server_echoes_the_request_when_configured() {
+  let fixture = start_mock_server_for("echo-request", &[
+    ("echo the request back", json!({
+      "pact:proto-service": "Test/Echo",
+      "pact:protobuf-config": {
+        "echoRequest": true
+      },
+
+      "request": {
+        "in": "matching(boolean, true)"
+      }
+    }))
+  ]).await;
+
+  let codec = fixture.codec(".com.pact.protobuf.example.MessageIn", ".com.pact.protobuf.example.MessageIn");
+  let mut grpc = fixture.client().await;
+  let response = grpc.unary(Request::new(fixture.message_in(true)), test_service_path("Echo"), codec)
+    .await.unwrap();
+
+  let fields = response.into_inner().proto_fields();
+  expect!(fields.len()).to(be_equal_to(1));
+  expect!(fields[0].data.clone()).to(be_equal_to(ProtobufFieldData::Boolean(true)));
+}
+
+async fn request_with_mismatched_metadata_block() {
+  let fixture = start_mock_server_for("request-metadata", &[
+    ("a request with an API key", json!({
+      "pact:proto-service": "Test/GetTest",
+
+      "requestMetadata": {
+        "x-api-key": "matching(regex, '^key-\\d+$', 'key-100')"
+      },
+      "request": {
+        "in": "matching(boolean, true)"
+      },
+      "response": {
+        "out": "matching(boolean, true)"
+      }
+    }))
+  ]).await;
+
+  let codec = fixture.codec(".com.pact.protobuf.example.MessageIn", ".com.pact.protobuf.example.MessageOut");
+  let mut grpc = fixture.client().await;
+  let mut request = Request::new(fixture.message_in(true));
+  request.metadata_mut().insert("x-api-key", "wrong-key".parse().unwrap());
+  let response = grpc.unary(request, test_service_path("GetTest"), codec).await;
+
+  let status = response.unwrap_err();
+  expect!(status.code()).to(be_equal_to(tonic::Code::InvalidArgument));
+  expect!(status.message().contains("x-api-key")).to(be_true());
+
+  // Should fail as the request did not match when the mock server is dropped at the end of this
+  // function
+}
+
+#[test_log::test]
+fn mock_server_returns_invalid_argument_if_the_request_metadata_does_not_match() {
+  let result = catch_unwind(|| {
+    let runtime = tokio::runtime::Builder::new_multi_thread()
+      .enable_all()
+      .build()
+      .expect("new runtime");
+    runtime.block_on(request_with_mismatched_metadata_block())
+  });
+
+  let error = result.unwrap_err();
+  let error_message = panic_message::panic_message(&error);
+  expect!(error_message.contains("Comparison of metadata key 'x-api-key' failed")).to(be_true());
+}
+
+#[test_log::test(tokio::test(flavor = "multi_thread", worker_threads = 1))]
+async fn mock_server_selects_the_interaction_that_matches_the_request() {
+  let fixture = start_mock_server_for("multiple-interactions", &[
+    ("get test with a true value", json!({
+      "pact:proto-service": "Test/GetTest",
+      "request": {
+        "in": "true"
+      },
+      "response": {
+        "out": "true"
+      }
+    })),
+    ("get test with a false value", json!({
+      "pact:proto-service": "Test/GetTest",
+      "request": {
+        "in": "false"
+      },
+      "response": {
+        "out": "false"
+      }
+    }))
+  ]).await;
+
+  let mut grpc = fixture.client().await;
+  for value in [true, false] {
+    grpc.ready().await.unwrap();
+    let codec = fixture.codec(".com.pact.protobuf.example.MessageIn", ".com.pact.protobuf.example.MessageOut");
+    let response = grpc.unary(Request::new(fixture.message_in(value)), test_service_path("GetTest"), codec)
+      .await.unwrap();
 
-  let codec = PactCodec::new(&fds, &input_message, &output_message, &interaction);
-  let mut grpc = tonic::client::Grpc::new(conn);
-  let path = http::uri::PathAndQuery::try_from("/com.pact.protobuf.example.Test/GetValues").unwrap();
-
-  let field_descriptor = input_message.field.iter()
-    .find(|field| field.number == Some(1))
-    .unwrap();
-  let field = ProtobufField {
-    field_num: 1,
-    field_name: "value".to_string(),
-    wire_type: WireType::LengthDelimited,
-    data: ProtobufFieldData::String("value1".to_string()),
-    additional_data: vec![],
-    descriptor: field_descriptor.clone()
-  };
-  let field2 = ProtobufField {
-    field_num: 1,
-    field_name: "value".to_string(),
-    wire_type: WireType::LengthDelimited,
-    data: ProtobufFieldData::String("value2".to_string()),
-    additional_data: vec![],
-    descriptor: field_descriptor.clone()
-  };
-  let fields = vec![ field, field2 ];
-  let message = DynamicMessage::new(fields.as_slice(), &fds);
-  grpc.unary(Request::new(message), path, codec).await.unwrap();
+    let out = response.into_inner().proto_fields().iter()
+      .find(|field| field.field_num == 1)
+      .map(|field| field.data.clone())
+      .unwrap_or(ProtobufFieldData::Boolean(false));
+    expect!(out).to(be_equal_to(ProtobufFieldData::Boolean(value)));
+  }
 }